@@ -1,5 +1,136 @@
+use std::io::Read;
+use std::sync::OnceLock;
+use std::time::{SystemTime, UNIX_EPOCH};
 use serde::{Serialize, Deserialize};
 
+use crate::record::clock_advance;
+
+/// Batch format version, in the high nibble of the direction byte of a batch
+/// sent between consensus and a runtime. Version 0 is
+/// `[u64 number][u8 direction][u64 len][data]`; version 1 appends a CRC32 of
+/// `data` as a little-endian u32; version 2 adds a u64 `clock` after the
+/// direction byte, the runtimes' `GlobalClock` before the batch is applied, so
+/// a runtime that joins late or missed a batch can set its clock from it.
+/// Version 3 has the layout of version 2, and gives the two bits above the
+/// direction to `WIRE_ACCEPTS_ZSTD` and `WIRE_ZSTD`. Readers take any of them,
+/// so older peers still interoperate. Writers send version 3 only to a peer
+/// that has sent it, and version 2 otherwise.
+pub const WIRE_VERSION: u8 = 3;
+
+/// The version a writer sends until it has read a batch from its peer.
+pub const BASE_WIRE_VERSION: u8 = 2;
+
+/// The `clock` of a batch whose sender does not know the clock, e.g. one
+/// loaded from a history file that predates logical times.
+pub const UNKNOWN_CLOCK: u64 = u64::MAX;
+
+/// Flag in the low nibble of the direction byte of a version 3 batch, set by
+/// a peer that reads zstd-compressed batches. Every version 3 batch this tree
+/// sends carries it, so each side learns from what it reads whether it may
+/// compress what it sends.
+pub const WIRE_ACCEPTS_ZSTD: u8 = 0x04;
+
+/// Flag in the low nibble of the direction byte of a version 3 batch: `data`
+/// is zstd-compressed.
+/// `len` and the CRC32 are of the compressed bytes.
+pub const WIRE_ZSTD: u8 = 0x08;
+
+/// Batches smaller than this are never compressed; zstd's frame overhead
+/// outweighs what it could save on them.
+const MIN_COMPRESS_LEN: usize = 256;
+
+/// Largest batch, or record of a consensus file, that a reader allocates
+/// room for when REPLICODE_MAX_PAYLOAD_BYTES is not set. Only a corrupt or
+/// hostile peer sends a length past it.
+pub const DEFAULT_MAX_PAYLOAD_BYTES: u64 = 64 << 20;
+
+/// The length past which readers reject a batch or record instead of
+/// allocating room for it: REPLICODE_MAX_PAYLOAD_BYTES, or
+/// `DEFAULT_MAX_PAYLOAD_BYTES`.
+pub fn max_payload_bytes() -> u64 {
+    static MAX: OnceLock<u64> = OnceLock::new();
+    *MAX.get_or_init(|| {
+        std::env::var("REPLICODE_MAX_PAYLOAD_BYTES").ok()
+            .and_then(|bytes| bytes.parse::<u64>().ok())
+            .filter(|&bytes| bytes > 0)
+            .unwrap_or(DEFAULT_MAX_PAYLOAD_BYTES)
+    })
+}
+
+/// Reads and drops the next `len` bytes of `reader`, so that reading resumes
+/// after a batch or record too large to hold.
+pub fn skip_bytes<R: Read>(reader: &mut R, len: u64) -> std::io::Result<()> {
+    let skipped = std::io::copy(&mut reader.take(len), &mut std::io::sink())?;
+    if skipped < len {
+        return Err(std::io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(())
+}
+
+/// Serializes a batch for the wire, for a peer that last sent a batch in
+/// format `peer_version`: in version 3 if it did, with its flags, and in
+/// version 2 otherwise. `direction` is 0 for incoming and 1 for outgoing.
+/// With `compress` (the peer sent `WIRE_ACCEPTS_ZSTD`), `data` is sent
+/// zstd-compressed, but only if that makes it smaller.
+pub fn encode_wire(number: u64, direction: u8, clock: u64, data: &[u8], peer_version: u8, compress: bool) -> Vec<u8> {
+    let version = peer_version.clamp(BASE_WIRE_VERSION, WIRE_VERSION);
+    let compressed = if version >= 3 && compress && data.len() >= MIN_COMPRESS_LEN {
+        zstd::bulk::compress(data, 0).ok().filter(|compressed| compressed.len() < data.len())
+    } else {
+        None
+    };
+    let (flags, data) = match &compressed {
+        Some(compressed) => (WIRE_ACCEPTS_ZSTD | WIRE_ZSTD, compressed.as_slice()),
+        None if version >= 3 => (WIRE_ACCEPTS_ZSTD, data),
+        None => (0, data),
+    };
+    let mut serialized = Vec::with_capacity(29 + data.len());
+    serialized.extend_from_slice(&number.to_le_bytes());
+    serialized.push(version << 4 | flags | direction);
+    serialized.extend_from_slice(&clock.to_le_bytes());
+    serialized.extend_from_slice(&(data.len() as u64).to_le_bytes());
+    serialized.extend_from_slice(data);
+    serialized.extend_from_slice(&wire_checksum(data).to_le_bytes());
+    serialized
+}
+
+/// Splits the direction byte of a batch on the wire into its format version,
+/// its direction and its flags (`WIRE_ACCEPTS_ZSTD`, `WIRE_ZSTD`). Batches
+/// before version 3 have no flags.
+pub fn split_wire_direction(byte: u8) -> (u8, u8, u8) {
+    let version = byte >> 4;
+    if version < 3 {
+        return (version, byte & 0x0f, 0);
+    }
+    (version, byte & 0x03, byte & 0x0c)
+}
+
+/// The data of a batch as read off the wire, decompressed if its flags say
+/// it was compressed. Decompressed data is held to `max_payload_bytes()` like
+/// the batch itself, so a small batch cannot expand past it.
+pub fn decode_wire_data(flags: u8, data: Vec<u8>) -> std::io::Result<Vec<u8>> {
+    if flags & WIRE_ZSTD == 0 {
+        return Ok(data);
+    }
+    let max_len = max_payload_bytes();
+    let mut decoded = Vec::new();
+    zstd::stream::Decoder::new(data.as_slice())?
+        .take(max_len.saturating_add(1))
+        .read_to_end(&mut decoded)?;
+    if decoded.len() as u64 > max_len {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("decompresses to over the {}-byte limit", max_len),
+        ));
+    }
+    Ok(decoded)
+}
+
+/// The CRC32 that follows `data` in a batch of version 1 or later.
+pub fn wire_checksum(data: &[u8]) -> u32 {
+    crc32fast::hash(data)
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub enum BatchDirection {
     Incoming, // Consensus -> Runtime
@@ -11,4 +142,27 @@ pub struct Batch {
     pub number: u64,
     pub direction: BatchDirection,
     pub data: Vec<u8>,
-} 
\ No newline at end of file
+    /// Wall-clock creation time in milliseconds since the Unix epoch; 0 for
+    /// batches loaded from a history file that predates timestamps.
+    pub created_at_ms: u64,
+    /// The runtimes' `GlobalClock` (in nanoseconds) once this batch has been
+    /// applied. Only known for incoming batches, which carry the clock records.
+    pub logical_time: Option<u64>,
+}
+
+impl Batch {
+    /// A batch stamped with the current wall-clock time.
+    pub fn new(number: u64, direction: BatchDirection, data: Vec<u8>) -> Self {
+        let created_at_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0);
+        Batch { number, direction, data, created_at_ms, logical_time: None }
+    }
+
+    /// The `GlobalClock` before this batch is applied, i.e. its logical time
+    /// less the clock records it carries.
+    pub fn clock_before(&self) -> Option<u64> {
+        self.logical_time.map(|time| time.saturating_sub(clock_advance(&self.data)))
+    }
+}
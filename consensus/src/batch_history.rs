@@ -3,25 +3,45 @@ use std::io::{self, Write, Read, Seek, SeekFrom};
 use std::path::Path;
 use std::sync::{Arc, Mutex};
 use log::{error, debug};
-use crate::batch::{Batch, BatchDirection};
+use crate::batch::{max_payload_bytes, Batch, BatchDirection};
+
+/// Start of every history file written since batches gained timestamps,
+/// followed by a one-byte format version. Older files begin directly with
+/// the first batch and are read as version 1.
+const HISTORY_MAGIC: &[u8; 4] = b"RCBH";
+/// Version 1: `[u64 number][u8 direction][u64 len][data]`.
+/// Version 2: `[u64 number][u8 direction][u64 created_at_ms][u64 logical_time][u64 len][data]`,
+/// with a logical time of `u64::MAX` meaning unknown.
+const HISTORY_VERSION: u8 = 2;
+const UNKNOWN_LOGICAL_TIME: u64 = u64::MAX;
 
 pub struct BatchHistory {
     file: Arc<Mutex<File>>,
     current_batch: u64,
+    version: u8,
 }
 
 impl BatchHistory {
     pub fn new(history_path: &Path) -> io::Result<Self> {
-        let file = OpenOptions::new()
+        let mut file = OpenOptions::new()
             .read(true)
-            .write(true)
             .create(true)
             .append(true)
             .open(history_path)?;
         
+        // Keep appending in the format the file was started with
+        let version = if file.metadata()?.len() == 0 {
+            file.write_all(HISTORY_MAGIC)?;
+            file.write_all(&[HISTORY_VERSION])?;
+            HISTORY_VERSION
+        } else {
+            read_version(&mut file)?.0
+        };
+        
         Ok(Self {
             file: Arc::new(Mutex::new(file)),
             current_batch: 0,
+            version,
         })
     }
 
@@ -37,6 +57,11 @@ impl BatchHistory {
             BatchDirection::Outgoing => 1,
         }])?;
         
+        if self.version >= 2 {
+            file.write_all(&batch.created_at_ms.to_le_bytes())?;
+            file.write_all(&batch.logical_time.unwrap_or(UNKNOWN_LOGICAL_TIME).to_le_bytes())?;
+        }
+        
         // Write data length (8 bytes)
         file.write_all(&(batch.data.len() as u64).to_le_bytes())?;
         
@@ -46,80 +71,225 @@ impl BatchHistory {
         // Flush to ensure data is written to disk
         file.flush()?;
         
-        self.current_batch = batch.number;
+        // Outgoing batches are numbered independently by each runtime
+        if batch.direction == BatchDirection::Incoming {
+            self.current_batch = batch.number;
+        }
         debug!("Saved batch {} to history file", batch.number);
         Ok(())
     }
 
     pub fn get_batches_since(&self, batch_number: u64) -> io::Result<Vec<Batch>> {
         let mut file = self.file.lock().unwrap();
-        let mut batches = Vec::new();
         
         // Seek to start of file
         file.seek(SeekFrom::Start(0))?;
+        let batches = read_batches(&mut *file, batch_number)?;
         
-        loop {
-            // Read batch number (8 bytes)
-            let mut batch_num_buf = [0u8; 8];
-            match file.read_exact(&mut batch_num_buf) {
-                Ok(_) => {
-                    let batch_num = u64::from_le_bytes(batch_num_buf);
-                    
-                    // Read direction (1 byte)
-                    let mut direction_buf = [0u8; 1];
-                    if file.read_exact(&mut direction_buf).is_err() {
-                        error!("Failed to read batch direction, file may be corrupted");
-                        break;
-                    }
-                    let direction = match direction_buf[0] {
-                        0 => BatchDirection::Incoming,
-                        1 => BatchDirection::Outgoing,
-                        _ => {
-                            error!("Invalid batch direction in history file");
-                            break;
-                        }
-                    };
-                    
-                    // Read data length (8 bytes)
-                    let mut len_buf = [0u8; 8];
-                    if file.read_exact(&mut len_buf).is_err() {
-                        error!("Failed to read batch data length, file may be corrupted");
+        debug!("Retrieved {} batches since batch {}", batches.len(), batch_number);
+        Ok(batches)
+    }
+
+    /// Reads every batch from a saved session file, in the order it was recorded.
+    pub fn load(history_path: &Path) -> io::Result<Vec<Batch>> {
+        let mut file = File::open(history_path)?;
+        let mut batches = Vec::new();
+        read_all_batches(&mut file, |batch| batches.push(batch))?;
+        Ok(batches)
+    }
+
+    pub fn get_current_batch(&self) -> u64 {
+        self.current_batch
+    }
+}
+
+fn read_batches<R: Read>(reader: &mut R, batch_number: u64) -> io::Result<Vec<Batch>> {
+    let mut batches = Vec::new();
+    read_all_batches(reader, |batch| {
+        // Only add batches after the requested number
+        if batch.number > batch_number {
+            batches.push(batch);
+        }
+    })?;
+    Ok(batches)
+}
+
+/// Reads the version header. Returns the format version and whatever bytes
+/// were consumed that turned out to belong to a version 1 file's first batch.
+fn read_version<R: Read>(reader: &mut R) -> io::Result<(u8, Vec<u8>)> {
+    let mut header = Vec::with_capacity(HISTORY_MAGIC.len() + 1);
+    reader.take(HISTORY_MAGIC.len() as u64 + 1).read_to_end(&mut header)?;
+    if header.len() == HISTORY_MAGIC.len() + 1 && header.starts_with(HISTORY_MAGIC) {
+        Ok((header[HISTORY_MAGIC.len()], Vec::new()))
+    } else {
+        Ok((1, header))
+    }
+}
+
+/// Reads batch entries (in either history format) until EOF. A truncated
+/// trailing entry is logged and ignored.
+fn read_all_batches<R: Read>(reader: &mut R, mut on_batch: impl FnMut(Batch)) -> io::Result<()> {
+    let (version, consumed) = read_version(reader)?;
+    if version > HISTORY_VERSION {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Batch history version {} is newer than supported version {}", version, HISTORY_VERSION),
+        ));
+    }
+    let reader = &mut io::Cursor::new(consumed).chain(reader);
+    loop {
+        // Read batch number (8 bytes)
+        let mut batch_num_buf = [0u8; 8];
+        match reader.read_exact(&mut batch_num_buf) {
+            Ok(_) => {
+                let batch_num = u64::from_le_bytes(batch_num_buf);
+                
+                // Read direction (1 byte)
+                let mut direction_buf = [0u8; 1];
+                if reader.read_exact(&mut direction_buf).is_err() {
+                    error!("Failed to read batch direction, file may be corrupted");
+                    break;
+                }
+                let direction = match direction_buf[0] {
+                    0 => BatchDirection::Incoming,
+                    1 => BatchDirection::Outgoing,
+                    _ => {
+                        error!("Invalid batch direction in history file");
                         break;
                     }
-                    let data_len = u64::from_le_bytes(len_buf) as usize;
-                    
-                    // Read the data
-                    let mut data = vec![0u8; data_len];
-                    if file.read_exact(&mut data).is_err() {
-                        error!("Failed to read batch data, file may be corrupted");
+                };
+                
+                // Version 2 adds the wall-clock and logical timestamps
+                let (created_at_ms, logical_time) = if version >= 2 {
+                    let mut times_buf = [0u8; 16];
+                    if reader.read_exact(&mut times_buf).is_err() {
+                        error!("Failed to read batch timestamps, file may be corrupted");
                         break;
                     }
-                    
-                    // Only add batches after the requested number
-                    if batch_num > batch_number {
-                        batches.push(Batch {
-                            number: batch_num,
-                            direction,
-                            data,
-                        });
-                    }
+                    let created_at_ms = u64::from_le_bytes(times_buf[0..8].try_into().unwrap());
+                    let logical_time = u64::from_le_bytes(times_buf[8..16].try_into().unwrap());
+                    (created_at_ms, Some(logical_time).filter(|&t| t != UNKNOWN_LOGICAL_TIME))
+                } else {
+                    (0, None)
+                };
+                
+                // Read data length (8 bytes)
+                let mut len_buf = [0u8; 8];
+                if reader.read_exact(&mut len_buf).is_err() {
+                    error!("Failed to read batch data length, file may be corrupted");
+                    break;
                 }
-                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
-                    // Normal EOF, we're done
+                let data_len = u64::from_le_bytes(len_buf);
+                if data_len > max_payload_bytes() {
+                    error!("Batch {} claims {} bytes, over the {}-byte limit; file may be corrupted",
+                        batch_num, data_len, max_payload_bytes());
                     break;
                 }
-                Err(e) => {
-                    error!("Error reading batch history: {}", e);
-                    return Err(e);
+                let data_len = data_len as usize;
+                
+                // Read the data
+                let mut data = vec![0u8; data_len];
+                if reader.read_exact(&mut data).is_err() {
+                    error!("Failed to read batch data, file may be corrupted");
+                    break;
                 }
+                
+                on_batch(Batch {
+                    number: batch_num,
+                    direction,
+                    data,
+                    created_at_ms,
+                    logical_time,
+                });
+            }
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                // Normal EOF, we're done
+                break;
+            }
+            Err(e) => {
+                error!("Error reading batch history: {}", e);
+                return Err(e);
             }
         }
-        
-        debug!("Retrieved {} batches since batch {}", batches.len(), batch_number);
-        Ok(batches)
     }
+    Ok(())
+}
 
-    pub fn get_current_batch(&self) -> u64 {
-        self.current_batch
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// A history file path of its own for each test, removed when dropped.
+    struct TempHistory(PathBuf);
+
+    impl TempHistory {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!("replicode-history-{}-{}.bin", std::process::id(), name));
+            let _ = std::fs::remove_file(&path);
+            TempHistory(path)
+        }
     }
-} 
\ No newline at end of file
+
+    impl Drop for TempHistory {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    fn batch(number: u64, direction: BatchDirection, created_at_ms: u64, logical_time: Option<u64>) -> Batch {
+        Batch { number, direction, data: format!("batch {}", number).into_bytes(), created_at_ms, logical_time }
+    }
+
+    /// Number, direction, data, creation time and logical time of a batch.
+    type Summary = (u64, BatchDirection, Vec<u8>, u64, Option<u64>);
+
+    fn summary(batches: &[Batch]) -> Vec<Summary> {
+        batches.iter()
+            .map(|b| (b.number, b.direction.clone(), b.data.clone(), b.created_at_ms, b.logical_time))
+            .collect()
+    }
+
+    #[test]
+    fn timestamps_round_trip() {
+        let file = TempHistory::new("timestamps");
+        let saved = [
+            batch(1, BatchDirection::Incoming, 1_700_000_000_123, Some(5_000_000)),
+            batch(1, BatchDirection::Outgoing, 1_700_000_000_456, None),
+            batch(2, BatchDirection::Incoming, 1_700_000_001_000, Some(0)),
+        ];
+        let mut history = BatchHistory::new(&file.0).unwrap();
+        for batch in &saved {
+            history.save_batch(batch).unwrap();
+        }
+        assert_eq!(history.get_current_batch(), 2);
+        assert_eq!(summary(&BatchHistory::load(&file.0).unwrap()), summary(&saved));
+        assert_eq!(summary(&history.get_batches_since(1).unwrap()), summary(&saved[2..]));
+        // Reopening keeps appending in the same format
+        drop(history);
+        let extra = batch(3, BatchDirection::Incoming, 1_700_000_002_000, Some(7));
+        BatchHistory::new(&file.0).unwrap().save_batch(&extra).unwrap();
+        assert_eq!(summary(&BatchHistory::load(&file.0).unwrap()[3..]), summary(&[extra]));
+    }
+
+    #[test]
+    fn version_1_files_load_without_timestamps() {
+        let file = TempHistory::new("version1");
+        let mut bytes = Vec::new();
+        for (number, direction, data) in [(1u64, 0u8, &b"first"[..]), (1, 1, b"out")] {
+            bytes.extend_from_slice(&number.to_le_bytes());
+            bytes.push(direction);
+            bytes.extend_from_slice(&(data.len() as u64).to_le_bytes());
+            bytes.extend_from_slice(data);
+        }
+        std::fs::write(&file.0, &bytes).unwrap();
+        // A version 1 file stays version 1, so timestamps of new batches are dropped
+        BatchHistory::new(&file.0).unwrap()
+            .save_batch(&batch(2, BatchDirection::Incoming, 1_700_000_000_000, Some(9))).unwrap();
+        assert_eq!(summary(&BatchHistory::load(&file.0).unwrap()), vec![
+            (1, BatchDirection::Incoming, b"first".to_vec(), 0, None),
+            (1, BatchDirection::Outgoing, b"out".to_vec(), 0, None),
+            (2, BatchDirection::Incoming, b"batch 2".to_vec(), 0, None),
+        ]);
+    }
+}
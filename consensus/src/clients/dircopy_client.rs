@@ -1,30 +1,81 @@
 use std::env;
-use std::io::{self, Read, Write};
+use std::fs::File;
+use std::io::{self, BufReader, Read, Write};
 use std::net::TcpStream;
+use std::path::Path;
+use super::dircopy_protocol::{build_manifest, decode_have, decode_result, encode_file_header, encode_manifest, files_to_send, ManifestEntry};
+use super::timeout::{describe_timeout, ClientTimeouts};
 
-fn usage() {
-    eprintln!("Usage: dircopy_client <host> <port> <src> <dst>");
+fn usage() -> ! {
+    eprintln!("Usage: consensus dircopy-client <host> <port> <src> <dst> [--timeout <secs>] [--retries <n>]");
     std::process::exit(1);
 }
 
+/// Copies the files of `manifest`, found below `src`, into `dst` on the
+/// server, skipping those it already has. Returns how many were sent.
+fn sync(stream: &mut TcpStream, src: &Path, dst: &str, manifest: &[ManifestEntry]) -> io::Result<usize> {
+    let sending = |e| describe_timeout(e, "sending files");
+    stream.write_all(&encode_manifest(dst, manifest)?).map_err(sending)?;
+    stream.flush().map_err(sending)?;
+
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let have = decode_have(&mut reader).map_err(|e| describe_timeout(e, "waiting for the files the server has"))?;
+    let to_send = files_to_send(manifest, &have);
+    println!("[CLIENT] The server has {} of {} files, sending {}", manifest.len() - to_send.len(), manifest.len(), to_send.len());
+
+    for entry in &to_send {
+        println!("[CLIENT] Sending '{}' ({} bytes)", entry.path, entry.size);
+        stream.write_all(&encode_file_header(&entry.path, entry.size)?).map_err(sending)?;
+        // Exactly the announced size, even if the file changed since
+        let copied = io::copy(&mut File::open(src.join(&entry.path))?.take(entry.size), stream).map_err(sending)?;
+        if copied != entry.size {
+            return Err(io::Error::other(format!("{} shrank while it was being sent", entry.path)));
+        }
+    }
+    stream.write_all(b"DONE\n").map_err(sending)?;
+    stream.flush().map_err(sending)?;
+
+    match decode_result(&mut reader).map_err(|e| describe_timeout(e, "waiting for server response"))? {
+        Ok(received) if received == to_send.len() => Ok(received),
+        Ok(received) => Err(io::Error::other(format!("sent {} files but the server received {}", to_send.len(), received))),
+        Err(message) => Err(io::Error::other(format!("server failed the copy: {}", message))),
+    }
+}
+
 pub fn start_dircopy_client() -> io::Result<()> {
     let args: Vec<String> = env::args().collect();
-    if args.len() != 5 {
+    // We expect: binary_name dircopy-client host port src dst [options]
+    if args.len() < 6 {
         usage();
     }
-    let host = &args[1];
-    let port = &args[2];
-    let src = &args[3];
-    let dst = &args[4];
-    let addr = format!("{}:{}", host, port);
-
-    let mut stream = TcpStream::connect(&addr)?;
-    let cmd = format!("COPY {} {}\n", src, dst);
-    stream.write_all(cmd.as_bytes())?;
-    stream.flush()?;
+    let addr = format!("{}:{}", args[2], args[3]);
+    let src = Path::new(&args[4]);
+    let dst = &args[5];
+    let timeouts = ClientTimeouts::from_args(&args[6..]).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        usage()
+    });
 
-    let mut response = String::new();
-    stream.read_to_string(&mut response)?;
-    print!("{}", response);
-    Ok(())
-} 
\ No newline at end of file
+    let manifest = build_manifest(src)?;
+    println!("[CLIENT] Copying {} files from {} to {} on {}", manifest.len(), src.display(), dst, addr);
+    let mut attempt = 0;
+    loop {
+        // A retry starts over with a fresh manifest exchange, so the files
+        // that arrived before the timeout are not sent again
+        let result = timeouts.connect(&addr).and_then(|mut stream| sync(&mut stream, src, dst, &manifest));
+        match result {
+            Ok(sent) => {
+                println!("Server response: OK {}", sent);
+                return Ok(());
+            }
+            Err(e) if e.kind() == io::ErrorKind::TimedOut && attempt < timeouts.retries => {
+                attempt += 1;
+                eprintln!("Timeout: {} (retry {}/{})", e, attempt, timeouts.retries);
+            }
+            Err(e) => {
+                eprintln!("Error copying {}: {}", src.display(), e);
+                return Err(e);
+            }
+        }
+    }
+}
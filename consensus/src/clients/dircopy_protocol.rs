@@ -0,0 +1,222 @@
+//! Framing of the directory copy protocol spoken by `dircopy-client` and
+//! `wasm_programs/dircopy_server.c`.
+//!
+//! The client copies a directory tree into the server's sandbox, sending
+//! only the files the server does not already have. An interrupted copy is
+//! resumed by running it again: the files that arrived whole match and are
+//! skipped.
+//!
+//! The client opens with the manifest of its source directory, one line per
+//! file. Paths are relative to the source directory, use `/` separators and
+//! come last on the line, so they may hold spaces but not newlines:
+//!
+//! ```text
+//! SYNC <dst> <count>\n             copy into <dst>; <count> lines follow
+//! <size> <crc32> <path>\n          a file: its length, CRC-32 as 8 hex digits
+//! ```
+//!
+//! The server answers with the files it already has under `<dst>` with the
+//! same size and CRC-32:
+//!
+//! ```text
+//! HAVE <count>\n                   <count> lines follow
+//! <path>\n
+//! ```
+//!
+//! The client then sends each other file as `image-client` sends one, and
+//! ends the session:
+//!
+//! ```text
+//! SEND <path>\n<size><bytes>       <size> is 4 bytes, big-endian
+//! DONE\n
+//! ```
+//!
+//! The server's last word is `OK <files received>\n`, or `ERR <message>\n`
+//! as soon as something fails.
+
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, BufRead};
+use std::path::Path;
+
+/// Most files a manifest may list, as the server keeps one path per file.
+pub const MAX_MANIFEST_ENTRIES: usize = 1024;
+
+/// Longest relative path the server accepts, in bytes.
+pub const MAX_PATH_LEN: usize = 255;
+
+/// One file of a source directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManifestEntry {
+    pub path: String,
+    pub size: u64,
+    pub crc32: u32,
+}
+
+impl ManifestEntry {
+    /// The entry for `contents`, found at `path` below the source directory.
+    pub fn new(path: String, contents: &[u8]) -> Self {
+        ManifestEntry { path, size: contents.len() as u64, crc32: crc32fast::hash(contents) }
+    }
+}
+
+/// The files below `root`, sorted by path. Directories are not listed; the
+/// server creates them for the files in them.
+pub fn build_manifest(root: &Path) -> io::Result<Vec<ManifestEntry>> {
+    let mut entries = Vec::new();
+    add_dir(root, "", &mut entries)?;
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(entries)
+}
+
+fn add_dir(dir: &Path, prefix: &str, entries: &mut Vec<ManifestEntry>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().into_string()
+            .map_err(|name| invalid(format!("file name {:?} is not UTF-8", name)))?;
+        let path = format!("{}{}", prefix, name);
+        if entry.file_type()?.is_dir() {
+            add_dir(&entry.path(), &format!("{}/", path), entries)?;
+        } else {
+            entries.push(ManifestEntry::new(path, &fs::read(entry.path())?));
+        }
+    }
+    Ok(())
+}
+
+/// The bytes that open a copy of `manifest` into `dst`. Fails if `dst` is
+/// not a single word, or a path or the manifest is more than the server
+/// takes.
+pub fn encode_manifest(dst: &str, manifest: &[ManifestEntry]) -> io::Result<Vec<u8>> {
+    if dst.is_empty() || dst.contains(char::is_whitespace) {
+        return Err(invalid(format!("invalid destination {:?}: it must be a single word", dst)));
+    }
+    if manifest.len() > MAX_MANIFEST_ENTRIES {
+        return Err(invalid(format!("{} files is more than the {} a copy may hold", manifest.len(), MAX_MANIFEST_ENTRIES)));
+    }
+    let mut bytes = format!("SYNC {} {}\n", dst, manifest.len()).into_bytes();
+    for entry in manifest {
+        check_path(&entry.path)?;
+        bytes.extend(format!("{} {:08x} {}\n", entry.size, entry.crc32, entry.path).into_bytes());
+    }
+    Ok(bytes)
+}
+
+/// Reads the server's answer to the manifest: the paths it already has.
+pub fn decode_have(reader: &mut impl BufRead) -> io::Result<HashSet<String>> {
+    let header = read_line(reader)?;
+    if let Some(message) = header.strip_prefix("ERR ") {
+        return Err(io::Error::other(format!("server refused the copy: {}", message)));
+    }
+    let count: usize = header.strip_prefix("HAVE ").and_then(|count| count.parse().ok())
+        .ok_or_else(|| invalid(format!("expected HAVE, got {:?}", header)))?;
+    if count > MAX_MANIFEST_ENTRIES {
+        return Err(invalid(format!("server has {} files, more than a manifest holds", count)));
+    }
+    (0..count).map(|_| read_line(reader)).collect()
+}
+
+/// The files of `manifest` the server does not have, in manifest order.
+pub fn files_to_send<'a>(manifest: &'a [ManifestEntry], have: &HashSet<String>) -> Vec<&'a ManifestEntry> {
+    manifest.iter().filter(|entry| !have.contains(&entry.path)).collect()
+}
+
+/// The header of a file sent to the server, up to its bytes.
+pub fn encode_file_header(path: &str, size: u64) -> io::Result<Vec<u8>> {
+    check_path(path)?;
+    let size = u32::try_from(size).map_err(|_| invalid(format!("{} is over the 4 GiB a file may hold", path)))?;
+    let mut bytes = format!("SEND {}\n", path).into_bytes();
+    bytes.extend_from_slice(&size.to_be_bytes());
+    Ok(bytes)
+}
+
+/// Reads the server's last word: the number of files it received, or its
+/// error message.
+pub fn decode_result(reader: &mut impl BufRead) -> io::Result<Result<usize, String>> {
+    let line = read_line(reader)?;
+    if let Some(message) = line.strip_prefix("ERR ") {
+        return Ok(Err(message.to_string()));
+    }
+    line.strip_prefix("OK ").and_then(|files| files.parse().ok()).map(Ok)
+        .ok_or_else(|| invalid(format!("expected OK, got {:?}", line)))
+}
+
+fn check_path(path: &str) -> io::Result<()> {
+    if path.is_empty() || path.len() > MAX_PATH_LEN || path.contains('\n') {
+        return Err(invalid(format!("path {:?} cannot be copied: paths are 1 to {} bytes on one line", path, MAX_PATH_LEN)));
+    }
+    Ok(())
+}
+
+/// One line, without its `\n`.
+fn read_line(reader: &mut impl BufRead) -> io::Result<String> {
+    let mut line = Vec::new();
+    reader.read_until(b'\n', &mut line)?;
+    if line.pop() != Some(b'\n') {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed before a full line"));
+    }
+    String::from_utf8(line).map_err(|_| invalid("server sent a line that is not UTF-8".to_string()))
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(path: &str, contents: &[u8]) -> ManifestEntry {
+        ManifestEntry::new(path.to_string(), contents)
+    }
+
+    #[test]
+    fn manifest_lists_size_crc_and_path() {
+        let manifest = [entry("a.txt", b"hello"), entry("sub dir/b.bin", b"")];
+        let bytes = encode_manifest("out", &manifest).unwrap();
+        assert_eq!(
+            String::from_utf8(bytes).unwrap(),
+            "SYNC out 2\n5 3610a686 a.txt\n0 00000000 sub dir/b.bin\n"
+        );
+    }
+
+    #[test]
+    fn manifest_rejects_what_the_server_would() {
+        assert!(encode_manifest("", &[]).is_err());
+        assert!(encode_manifest("two words", &[]).is_err());
+        assert!(encode_manifest("out", &[entry("", b"x")]).is_err());
+        assert!(encode_manifest("out", &[entry("a\nb", b"x")]).is_err());
+        assert!(encode_manifest("out", &[entry(&"p".repeat(MAX_PATH_LEN + 1), b"x")]).is_err());
+        assert!(encode_manifest("out", &[entry(&"p".repeat(MAX_PATH_LEN), b"x")]).is_ok());
+        let too_many = vec![entry("f", b""); MAX_MANIFEST_ENTRIES + 1];
+        assert!(encode_manifest("out", &too_many).is_err());
+    }
+
+    #[test]
+    fn have_lists_paths_with_spaces() {
+        let have = decode_have(&mut &b"HAVE 2\na.txt\nsub dir/b.bin\n"[..]).unwrap();
+        assert_eq!(have, HashSet::from(["a.txt".to_string(), "sub dir/b.bin".to_string()]));
+        assert!(decode_have(&mut &b"HAVE 0\n"[..]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn have_errors() {
+        let refused = decode_have(&mut &b"ERR Invalid destination\n"[..]).unwrap_err();
+        assert!(refused.to_string().contains("Invalid destination"));
+        assert!(decode_have(&mut &b"HAVE x\n"[..]).is_err());
+        assert!(decode_have(&mut &b"OK 1\n"[..]).is_err());
+        assert!(decode_have(&mut format!("HAVE {}\n", MAX_MANIFEST_ENTRIES + 1).as_bytes()).is_err());
+        let truncated = decode_have(&mut &b"HAVE 2\na.txt\n"[..]).unwrap_err();
+        assert_eq!(truncated.kind(), io::ErrorKind::UnexpectedEof);
+        assert!(decode_have(&mut &b"HAVE 1\na.txt"[..]).is_err());
+    }
+
+    #[test]
+    fn only_missing_files_are_sent_in_manifest_order() {
+        let manifest = [entry("a", b"1"), entry("b", b"2"), entry("c", b"3")];
+        let have = HashSet::from(["b".to_string(), "gone".to_string()]);
+        let paths: Vec<_> = files_to_send(&manifest, &have).iter().map(|entry| entry.path.as_str()).collect();
+        assert_eq!(paths, ["a", "c"]);
+        assert!(files_to_send(&manifest, &HashSet::from(["a".into(), "b".into(), "c".into()])).is_empty());
+    }
+}
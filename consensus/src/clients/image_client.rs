@@ -3,11 +3,12 @@ use std::env;
 use std::fs::File;
 use std::io::{self, Read, Write, BufRead, BufReader};
 use std::net::TcpStream;
+use super::timeout::{describe_timeout, ClientTimeouts};
 
 const BUF_SIZE: usize = 4096;
 
-fn usage() {
-    eprintln!("Usage: consensus image-client <host> <port>");
+fn usage() -> ! {
+    eprintln!("Usage: consensus image-client <host> <port> [--timeout <secs>] [--retries <n>]");
     std::process::exit(1);
 }
 
@@ -16,13 +17,14 @@ fn send_file(stream: &mut TcpStream, filename: &str) -> io::Result<()> {
     let mut file = File::open(filename)?;
     let file_size = file.metadata()?.len() as u32;
     println!("[CLIENT] Sending file '{}' ({} bytes)", filename, file_size);
+    let sending = |e| describe_timeout(e, "sending file");
     
     // Send command
-    writeln!(stream, "SEND {}", filename)?;
+    writeln!(stream, "SEND {}", filename).map_err(sending)?;
     
     // Send file size
-    stream.write_all(&file_size.to_be_bytes())?;
-    stream.flush()?;
+    stream.write_all(&file_size.to_be_bytes()).map_err(sending)?;
+    stream.flush().map_err(sending)?;
     
     // Send file data
     let mut buffer = [0u8; BUF_SIZE];
@@ -32,7 +34,7 @@ fn send_file(stream: &mut TcpStream, filename: &str) -> io::Result<()> {
         if n == 0 {
             break;
         }
-        stream.write_all(&buffer[..n])?;
+        stream.write_all(&buffer[..n]).map_err(sending)?;
         total_sent += n as u32;
         println!("[CLIENT] Sent {} bytes, {} bytes remaining", n, file_size.saturating_sub(total_sent));
     }
@@ -40,7 +42,7 @@ fn send_file(stream: &mut TcpStream, filename: &str) -> io::Result<()> {
     
     // Read response
     let mut response = String::new();
-    stream.read_to_string(&mut response)?;
+    stream.read_to_string(&mut response).map_err(|e| describe_timeout(e, "waiting for server response"))?;
     println!("Server response: {}", response);
     
     Ok(())
@@ -48,12 +50,12 @@ fn send_file(stream: &mut TcpStream, filename: &str) -> io::Result<()> {
 
 fn get_file(stream: &mut TcpStream, filename: &str) -> io::Result<()> {
     // Send command
-    writeln!(stream, "GET {}", filename)?;
-    stream.flush()?;
+    writeln!(stream, "GET {}", filename).map_err(|e| describe_timeout(e, "sending GET"))?;
+    stream.flush().map_err(|e| describe_timeout(e, "sending GET"))?;
     
     // Read file size
     let mut size_buf = [0u8; 4];
-    stream.read_exact(&mut size_buf)?;
+    stream.read_exact(&mut size_buf).map_err(|e| describe_timeout(e, "waiting for file size"))?;
     println!("[CLIENT] Raw size bytes: {:02x} {:02x} {:02x} {:02x}", 
              size_buf[0], size_buf[1], size_buf[2], size_buf[3]);
     let file_size = u32::from_be_bytes(size_buf);
@@ -75,7 +77,7 @@ fn get_file(stream: &mut TcpStream, filename: &str) -> io::Result<()> {
     
     while remaining > 0 {
         let to_read = std::cmp::min(remaining, BUF_SIZE);
-        let n = stream.read(&mut buffer[..to_read])?;
+        let n = stream.read(&mut buffer[..to_read]).map_err(|e| describe_timeout(e, "receiving file"))?;
         if n == 0 {
             break;
         }
@@ -90,8 +92,8 @@ fn get_file(stream: &mut TcpStream, filename: &str) -> io::Result<()> {
     println!("[CLIENT] Finished receiving file '{}'. Total bytes received: {}", filename, total_received);
     
     // Send acknowledgment to server
-    stream.write_all(b"OK\n")?;
-    stream.flush()?;
+    stream.write_all(b"OK\n").map_err(|e| describe_timeout(e, "sending acknowledgment"))?;
+    stream.flush().map_err(|e| describe_timeout(e, "sending acknowledgment"))?;
     
     // Wait for server to shutdown write side
     let mut shutdown_buf = [0u8; 1];
@@ -104,19 +106,52 @@ fn get_file(stream: &mut TcpStream, filename: &str) -> io::Result<()> {
     Ok(())
 }
 
+/// Runs `op`, reconnecting and retrying up to `timeouts.retries` times if it times out.
+fn with_retries<F>(stream: &mut TcpStream, addr: &str, timeouts: &ClientTimeouts, mut op: F) -> io::Result<()>
+where
+    F: FnMut(&mut TcpStream) -> io::Result<()>,
+{
+    let mut attempt = 0;
+    loop {
+        match op(stream) {
+            Err(e) if e.kind() == io::ErrorKind::TimedOut && attempt < timeouts.retries => {
+                attempt += 1;
+                eprintln!("Timeout: {} (retry {}/{})", e, attempt, timeouts.retries);
+                *stream = timeouts.connect(addr)?;
+            }
+            other => return other,
+        }
+    }
+}
+
+/// Reports a failed command. A timeout is returned, to end the client with
+/// a nonzero exit; the client carries on after other errors.
+fn report_error(action: &str, e: io::Error) -> io::Result<()> {
+    if e.kind() == io::ErrorKind::TimedOut {
+        eprintln!("Timeout {}: {}", action, e);
+        return Err(e);
+    }
+    eprintln!("Error {}: {}", action, e);
+    Ok(())
+}
+
 pub fn start_image_client() -> io::Result<()> {
     let args: Vec<String> = env::args().collect();
     // Skip the first two arguments (binary name and "image-client")
-    if args.len() != 4 {
+    if args.len() < 4 {
         usage();
     }
     
     let host = &args[2];
     let port = &args[3];
     let addr = format!("{}:{}", host, port);
+    let timeouts = ClientTimeouts::from_args(&args[4..]).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        usage()
+    });
     
     info!("Connecting to {}", addr);
-    let mut stream = TcpStream::connect(&addr)?;
+    let mut stream = timeouts.connect(&addr)?;
     info!("Connected to server");
     
     // Create a buffered reader for stdin
@@ -140,7 +175,7 @@ pub fn start_image_client() -> io::Result<()> {
         match reader.read_line(&mut input) {
             Ok(_) => {
                 // Trim the newline and split the command
-                let parts: Vec<&str> = input.trim().split_whitespace().collect();
+                let parts: Vec<&str> = input.split_whitespace().collect();
                 
                 if parts.is_empty() {
                     continue;
@@ -153,8 +188,8 @@ pub fn start_image_client() -> io::Result<()> {
                             println!("Usage: send <filename>");
                             continue;
                         }
-                        if let Err(e) = send_file(&mut stream, parts[1]) {
-                            eprintln!("Error sending file: {}", e);
+                        if let Err(e) = with_retries(&mut stream, &addr, &timeouts, |s| send_file(s, parts[1])) {
+                            report_error("sending file", e)?;
                         }
                     }
                     "get" => {
@@ -162,8 +197,8 @@ pub fn start_image_client() -> io::Result<()> {
                             println!("Usage: get <filename>");
                             continue;
                         }
-                        if let Err(e) = get_file(&mut stream, parts[1]) {
-                            eprintln!("Error getting file: {}", e);
+                        if let Err(e) = with_retries(&mut stream, &addr, &timeouts, |s| get_file(s, parts[1])) {
+                            report_error("getting file", e)?;
                         }
                     }
                     _ => println!("Unknown command. Available commands: send, get, quit"),
@@ -1,96 +1,95 @@
 use std::env;
-use std::io::{self, BufRead, Write, Read, BufReader};
+use std::io::{self, BufRead, Write, BufReader};
 use std::net::TcpStream;
-use std::time::Duration;
+use super::kv_protocol::{decode_response, encode_command, KvCommand, KvResponse};
+use super::timeout::{describe_timeout, ClientTimeouts};
 
-fn usage() {
-    eprintln!("Usage: consensus kv-client <host> <port>");
+fn usage() -> ! {
+    eprintln!("Usage: consensus kv-client <host> <port> [--timeout <secs>] [--retries <n>]");
     std::process::exit(1);
 }
 
-fn send_command(stream: &mut TcpStream, command: &str) -> io::Result<String> {
-    println!("[CLIENT] Sending command: {}", command);
-    
-    // Ensure command is properly formatted
-    let formatted_command = if command.to_lowercase().starts_with("set ") {
-        // For SET commands, ensure space between key and value
-        let parts: Vec<&str> = command.splitn(3, ' ').collect();
-        if parts.len() >= 3 {
-            format!("SET {} {}", parts[1], parts[2])
-        } else {
-            command.to_string()
-        }
-    } else if command.to_lowercase().starts_with("get ") {
-        // Parse GET command (needs at least 2 parts)
-        let parts: Vec<&str> = command.splitn(2, ' ').collect();
-        if parts.len() >= 2 {
-            format!("GET {}", parts[1])
-        } else {
-            command.to_string()
-        }
-    } else if command.to_lowercase().starts_with("del ") {
-        // Parse DEL command (needs at least 2 parts)
-        let parts: Vec<&str> = command.splitn(2, ' ').collect();
-        if parts.len() >= 2 {
-            format!("DEL {}", parts[1])
-        } else {
-            command.to_string()
-        }
-    } else {
-        command.to_string()
+/// Parses a command as typed at the prompt. The value of a `set` is the rest
+/// of the line, in which `\n` stands for a newline and `\\` for a backslash.
+fn parse_input(line: &str) -> Result<KvCommand, String> {
+    let (word, rest) = line.split_once(' ').unwrap_or((line, ""));
+    let rest = rest.trim_start();
+    let key = || match rest {
+        "" => Err(format!("{} requires a key", word)),
+        key => Ok(key.to_string()),
     };
-    
-    println!("[CLIENT] Formatted command: {}", formatted_command);
-    
-    // Send command with newline
-    writeln!(stream, "{}", formatted_command)?;
-    stream.flush()?;
-    
-    // Read response into a buffer, one byte at a time until newline
-    let mut response = Vec::new();
-    let mut buf = [0u8; 1];
-    
-    println!("[CLIENT] Waiting for response...");
-    
-    loop {
-        match stream.read_exact(&mut buf) {
-            Ok(_) => {
-                response.push(buf[0]);
-                if buf[0] == b'\n' {
-                    break;
-                }
-            },
-            Err(e) => {
-                println!("[CLIENT] Error reading response: {}", e);
-                return Err(e);
+    match word.to_lowercase().as_str() {
+        "set" => match rest.split_once(' ') {
+            Some((key, value)) => Ok(KvCommand::Set { key: key.to_string(), value: unescape(value) }),
+            None => Err("set requires a key and a value".to_string()),
+        },
+        "get" => Ok(KvCommand::Get { key: key()? }),
+        "del" => Ok(KvCommand::Del { key: key()? }),
+        "quit" => Ok(KvCommand::Quit),
+        _ => Err(format!("unknown command: {}", word)),
+    }
+}
+
+fn unescape(value: &str) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('\\', Some('n')) => {
+                bytes.push(b'\n');
+                chars.next();
+            }
+            ('\\', Some('\\')) => {
+                bytes.push(b'\\');
+                chars.next();
             }
+            _ => bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
         }
     }
-    
-    // Convert to string
-    let response_str = String::from_utf8_lossy(&response).to_string();
-    println!("[CLIENT] Received response: {}", response_str.trim());
-    
-    Ok(response_str)
+    bytes
+}
+
+fn send_command(stream: &mut TcpStream, command: &KvCommand) -> io::Result<KvResponse> {
+    let request = encode_command(command)?;
+    println!("[CLIENT] Sending command: {}", String::from_utf8_lossy(&request).trim_end());
+    stream.write_all(&request).map_err(|e| describe_timeout(e, "sending command"))?;
+    stream.flush().map_err(|e| describe_timeout(e, "sending command"))?;
+
+    println!("[CLIENT] Waiting for response...");
+    let response = decode_response(&mut BufReader::new(&*stream)).map_err(|e| {
+        let e = describe_timeout(e, "waiting for response");
+        println!("[CLIENT] Error reading response: {}", e);
+        e
+    })?;
+    println!("[CLIENT] Received response: {}", response);
+    Ok(response)
+}
+
+fn reconnect(addr: &str, timeouts: &ClientTimeouts) -> io::Result<TcpStream> {
+    let stream = timeouts.connect(addr)?;
+    stream.set_nodelay(true)?;
+    Ok(stream)
 }
 
 pub fn start_kv_client() -> io::Result<()> {
     let args: Vec<String> = env::args().collect();
-    // We expect: binary_name kv-client host port
-    if args.len() != 4 {
+    // We expect: binary_name kv-client host port [options]
+    if args.len() < 4 {
         usage();
     }
     let host = &args[2];
     let port = &args[3];
     let addr = format!("{}:{}", host, port);
+    let timeouts = ClientTimeouts::from_args(&args[4..]).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        usage()
+    });
 
     println!("[CLIENT] Connecting to {}...", addr);
-    let mut stream = TcpStream::connect(&addr)?;
+    let mut stream = timeouts.connect(&addr)?;
     
     // Set socket options
     stream.set_nodelay(true)?; // Disable Nagle's algorithm
-    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
-    stream.set_write_timeout(Some(Duration::from_secs(5)))?;
     
     println!("[CLIENT] Connected to server.");
     
@@ -100,7 +99,7 @@ pub fn start_kv_client() -> io::Result<()> {
     let mut input = String::new();
     
     println!("Available commands:");
-    println!("  set <key> <value> - Store a key-value pair");
+    println!("  set <key> <value> - Store a key-value pair (\\n in the value is a newline)");
     println!("  get <key>        - Retrieve a value by key");
     println!("  del <key>        - Delete a key-value pair");
     println!("  quit             - Exit the client");
@@ -121,19 +120,43 @@ pub fn start_kv_client() -> io::Result<()> {
                     continue;
                 }
                 
+                let command = match parse_input(trimmed) {
+                    Ok(command) => command,
+                    Err(e) => {
+                        eprintln!("Error: {}", e);
+                        continue;
+                    }
+                };
+                
                 // If the command is "quit", exit the loop
-                if trimmed.eq_ignore_ascii_case("quit") {
+                if command == KvCommand::Quit {
                     println!("[CLIENT] Sending QUIT command");
-                    match send_command(&mut stream, "QUIT") {
-                        Ok(response) => print!("Server response: {}", response),
+                    match send_command(&mut stream, &command) {
+                        Ok(response) => println!("Server response: {}", response),
                         Err(e) => eprintln!("Error: {}", e),
                     }
                     break;
                 }
                 
-                // Send the command and get response
-                match send_command(&mut stream, trimmed) {
-                    Ok(response) => print!("Server response: {}", response),
+                // Send the command and get response, retrying on a fresh connection after a timeout
+                let mut attempt = 0;
+                let result = loop {
+                    match send_command(&mut stream, &command) {
+                        Err(e) if e.kind() == io::ErrorKind::TimedOut && attempt < timeouts.retries => {
+                            attempt += 1;
+                            eprintln!("Timeout: {} (retry {}/{})", e, attempt, timeouts.retries);
+                            stream = reconnect(&addr, &timeouts)?;
+                        }
+                        other => break other,
+                    }
+                };
+                match result {
+                    Ok(response) => println!("Server response: {}", response),
+                    Err(e) if e.kind() == io::ErrorKind::TimedOut => {
+                        // A stalled server is an error, so exit nonzero
+                        eprintln!("Timeout: {}", e);
+                        return Err(e);
+                    }
                     Err(e) => {
                         eprintln!("Error: {}", e);
                         break;
@@ -141,10 +164,7 @@ pub fn start_kv_client() -> io::Result<()> {
                 }
                 
                 // After each command, reconnect for the next one
-                stream = TcpStream::connect(&addr)?;
-                stream.set_nodelay(true)?;
-                stream.set_read_timeout(Some(Duration::from_secs(5)))?;
-                stream.set_write_timeout(Some(Duration::from_secs(5)))?;
+                stream = reconnect(&addr, &timeouts)?;
                 println!("[CLIENT] Reconnected for next command");
             },
             Err(e) => {
@@ -0,0 +1,231 @@
+//! Framing of the key-value protocol spoken by `kv-client` and
+//! `wasm_programs/kv_server.c`.
+//!
+//! Every message starts with a header line of space-separated words ending
+//! in `\n`. Keys are single words. A value is never part of a line: its
+//! header gives its length in bytes, and exactly that many bytes follow the
+//! header, so values may hold spaces, newlines or any other bytes.
+//!
+//! Requests, one per connection:
+//!
+//! ```text
+//! SET <key> <len>\n<len bytes>    store a value
+//! GET <key>\n                     fetch it
+//! DEL <key>\n                     delete it
+//! QUIT\n                          end the session
+//! ```
+//!
+//! Responses:
+//!
+//! ```text
+//! OK\n                            SET or DEL succeeded
+//! VALUE <len>\n<len bytes>        the value GET asked for
+//! ERR <message>\n                 the request failed; the message is one line
+//! BYE\n                           answer to QUIT
+//! ```
+
+use std::fmt;
+use std::io::{self, BufRead};
+
+/// Longest value `decode_command` and `decode_response` accept, so a corrupt
+/// length cannot make them allocate without bound.
+const MAX_VALUE_LEN: usize = 1 << 20;
+
+/// A request from the client.
+#[derive(Debug, Clone, PartialEq)]
+pub enum KvCommand {
+    Set { key: String, value: Vec<u8> },
+    Get { key: String },
+    Del { key: String },
+    Quit,
+}
+
+/// The server's answer to a request.
+#[derive(Debug, Clone, PartialEq)]
+pub enum KvResponse {
+    Ok,
+    Value(Vec<u8>),
+    Err(String),
+    Bye,
+}
+
+impl fmt::Display for KvResponse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KvResponse::Ok => write!(f, "OK"),
+            KvResponse::Value(value) => write!(f, "VALUE {}", String::from_utf8_lossy(value)),
+            KvResponse::Err(message) => write!(f, "ERR {}", message),
+            KvResponse::Bye => write!(f, "BYE"),
+        }
+    }
+}
+
+/// The bytes that send `command` to the server. Fails if a key is empty or
+/// not a single word, which the header line could not carry.
+pub fn encode_command(command: &KvCommand) -> io::Result<Vec<u8>> {
+    let check = |key: &str| {
+        if key.is_empty() || key.contains(char::is_whitespace) {
+            Err(invalid(format!("invalid key {:?}: keys are single words", key)))
+        } else {
+            Ok(())
+        }
+    };
+    Ok(match command {
+        KvCommand::Set { key, value } => {
+            check(key)?;
+            let mut bytes = format!("SET {} {}\n", key, value.len()).into_bytes();
+            bytes.extend_from_slice(value);
+            bytes
+        }
+        KvCommand::Get { key } => {
+            check(key)?;
+            format!("GET {}\n", key).into_bytes()
+        }
+        KvCommand::Del { key } => {
+            check(key)?;
+            format!("DEL {}\n", key).into_bytes()
+        }
+        KvCommand::Quit => b"QUIT\n".to_vec(),
+    })
+}
+
+/// Reads one request from `reader`, including a SET's value, as the server
+/// does. The consensus binary only speaks the client side.
+#[allow(dead_code)]
+pub fn decode_command(reader: &mut impl BufRead) -> io::Result<KvCommand> {
+    let header = read_header(reader, "request")?;
+    let words: Vec<&str> = header.split(' ').collect();
+    match words.as_slice() {
+        ["SET", key, len] if !key.is_empty() => {
+            let value = read_value(reader, len)?;
+            Ok(KvCommand::Set { key: key.to_string(), value })
+        }
+        ["GET", key] if !key.is_empty() => Ok(KvCommand::Get { key: key.to_string() }),
+        ["DEL", key] if !key.is_empty() => Ok(KvCommand::Del { key: key.to_string() }),
+        ["QUIT"] => Ok(KvCommand::Quit),
+        _ => Err(invalid(format!("unknown request {:?}", header))),
+    }
+}
+
+/// Reads one response from `reader`, including a value's bytes.
+pub fn decode_response(reader: &mut impl BufRead) -> io::Result<KvResponse> {
+    let header = read_header(reader, "response")?;
+    let (word, rest) = header.split_once(' ').unwrap_or((&header, ""));
+    match word {
+        "OK" => Ok(KvResponse::Ok),
+        "BYE" => Ok(KvResponse::Bye),
+        "ERR" => Ok(KvResponse::Err(rest.to_string())),
+        "VALUE" => Ok(KvResponse::Value(read_value(reader, rest)?)),
+        _ => Err(invalid(format!("unknown response {:?}", header))),
+    }
+}
+
+/// Reads a header line, without its `\n`.
+fn read_header(reader: &mut impl BufRead, what: &str) -> io::Result<String> {
+    let mut header = Vec::new();
+    reader.read_until(b'\n', &mut header)?;
+    if header.pop() != Some(b'\n') {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, format!("connection closed before a full {}", what)));
+    }
+    String::from_utf8(header).map_err(|_| invalid(format!("{} header is not UTF-8", what)))
+}
+
+/// Reads the value that follows a header giving its length as `len`.
+fn read_value(reader: &mut impl BufRead, len: &str) -> io::Result<Vec<u8>> {
+    let len: usize = len.parse().map_err(|_| invalid(format!("invalid value length {:?}", len)))?;
+    if len > MAX_VALUE_LEN {
+        return Err(invalid(format!("value of {} bytes is over the {}-byte limit", len, MAX_VALUE_LEN)));
+    }
+    let mut value = vec![0; len];
+    reader.read_exact(&mut value)?;
+    Ok(value)
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(command: KvCommand) -> KvCommand {
+        let bytes = encode_command(&command).unwrap();
+        decode_command(&mut bytes.as_slice()).unwrap()
+    }
+
+    #[test]
+    fn commands_round_trip() {
+        let commands = [
+            KvCommand::Set { key: "k".into(), value: b"two words\nand a line\0".to_vec() },
+            KvCommand::Set { key: "empty".into(), value: Vec::new() },
+            KvCommand::Get { key: "k".into() },
+            KvCommand::Del { key: "k".into() },
+            KvCommand::Quit,
+        ];
+        for command in commands {
+            assert_eq!(round_trip(command.clone()), command);
+        }
+    }
+
+    #[test]
+    fn keys_must_be_single_words() {
+        for key in ["", "two words", "line\nbreak"] {
+            assert!(encode_command(&KvCommand::Get { key: key.into() }).is_err(), "{:?}", key);
+        }
+    }
+
+    #[test]
+    fn responses_decode() {
+        let mut input: &[u8] = b"OK\nVALUE 5\na b\n\nERR no such key\nBYE\n";
+        assert_eq!(decode_response(&mut input).unwrap(), KvResponse::Ok);
+        assert_eq!(decode_response(&mut input).unwrap(), KvResponse::Value(b"a b\n\n".to_vec()));
+        assert_eq!(decode_response(&mut input).unwrap(), KvResponse::Err("no such key".into()));
+        assert_eq!(decode_response(&mut input).unwrap(), KvResponse::Bye);
+        assert!(input.is_empty());
+    }
+
+    #[test]
+    fn values_up_to_the_limit_are_accepted() {
+        let value = vec![b'x'; MAX_VALUE_LEN];
+        let mut response = format!("VALUE {}\n", MAX_VALUE_LEN).into_bytes();
+        response.extend_from_slice(&value);
+        assert_eq!(decode_response(&mut response.as_slice()).unwrap(), KvResponse::Value(value.clone()));
+        let set = KvCommand::Set { key: "k".into(), value };
+        assert_eq!(round_trip(set.clone()), set);
+    }
+
+    #[test]
+    fn values_over_the_limit_are_rejected() {
+        let over = MAX_VALUE_LEN + 1;
+        let response = format!("VALUE {}\n", over);
+        let err = decode_response(&mut response.as_bytes()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        let request = format!("SET k {}\n", over);
+        let err = decode_command(&mut request.as_bytes()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn truncated_frames_are_errors() {
+        let set = encode_command(&KvCommand::Set { key: "k".into(), value: b"value".to_vec() }).unwrap();
+        for len in 0..set.len() {
+            let err = decode_command(&mut &set[..len]).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof, "{} of {} bytes", len, set.len());
+        }
+        for truncated in [&b"OK"[..], b"VALUE 5\nabc", b"ERR no newline"] {
+            let err = decode_response(&mut &truncated[..]).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof, "{:?}", truncated);
+        }
+    }
+
+    #[test]
+    fn malformed_headers_are_rejected() {
+        for request in [&b"PUT k\n"[..], b"SET k\n", b"SET k five\n", b"GET\n", b"GET a b\n"] {
+            let err = decode_command(&mut &request[..]).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData, "{:?}", request);
+        }
+        let err = decode_response(&mut &b"MAYBE\n"[..]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
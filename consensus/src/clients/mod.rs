@@ -1,9 +1,12 @@
 pub mod dircopy_client;
+pub mod dircopy_protocol;
 pub mod image_client;
 pub mod kv_client;
+pub mod kv_protocol;
 pub mod netcat_client;
 pub mod test_client;
 pub mod test_server;
+pub mod timeout;
 
 pub use dircopy_client::start_dircopy_client;
 pub use image_client::start_image_client;
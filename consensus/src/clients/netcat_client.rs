@@ -1,55 +1,110 @@
 use std::env;
 use std::io::{self, Read, Write};
-use std::net::TcpStream;
+use std::net::{Shutdown, TcpStream};
+use std::thread;
 
-fn usage() {
-    eprintln!("Usage: netcat_client <host> <port>");
+fn usage() -> ! {
+    eprintln!("Usage: consensus netcat-client --host <host> --port <port>");
+    eprintln!("       consensus netcat-client <host> <port>");
     std::process::exit(1);
 }
 
+/// Parses `--host <host> --port <port>` (in any order) or the positional `<host> <port>` form.
+fn parse_addr(args: &[String]) -> Option<String> {
+    let mut host = None;
+    let mut port = None;
+    let mut positional = Vec::new();
+    let mut i = 0;
+    while i < args.len() {
+        match args[i].as_str() {
+            "--host" => {
+                host = Some(args.get(i + 1)?.clone());
+                i += 2;
+            }
+            "--port" => {
+                port = Some(args.get(i + 1)?.parse::<u16>().ok()?);
+                i += 2;
+            }
+            other => {
+                positional.push(other.to_string());
+                i += 1;
+            }
+        }
+    }
+    let mut positional = positional.into_iter();
+    let host = host.or_else(|| positional.next())?;
+    let port = match port {
+        Some(p) => p,
+        None => positional.next()?.parse::<u16>().ok()?,
+    };
+    if positional.next().is_some() {
+        return None;
+    }
+    Some(format!("{}:{}", host, port))
+}
+
+/// Full-duplex netcat: one thread forwards stdin to the socket while the main
+/// thread forwards the socket to stdout.
+///
+/// EOF on stdin shuts down the write half so the server sees end-of-stream but
+/// can keep replying; EOF from the server ends the session.
 pub fn start_netcat_client() -> io::Result<()> {
     let args: Vec<String> = env::args().collect();
-    if args.len() != 3 {
-        usage();
-    }
-    let host = &args[1];
-    let port = &args[2];
-    let addr = format!("{}:{}", host, port);
+    // Skip the binary name and "netcat-client"
+    let addr = match parse_addr(args.get(2..).unwrap_or(&[])) {
+        Some(addr) => addr,
+        None => usage(),
+    };
 
-    let mut stream = TcpStream::connect(&addr)?;
-    let mut stream_clone = stream.try_clone()?;
+    let stream = TcpStream::connect(&addr)?;
+    eprintln!("[netcat] Connected to {}", addr);
+    let mut writer = stream.try_clone()?;
+    let mut reader = stream;
 
     // Spawn a thread to read from stdin and send to the socket
-    let stdin_handle = std::thread::spawn(move || {
+    thread::spawn(move || {
         let mut stdin = io::stdin();
         let mut buffer = [0u8; 4096];
         loop {
             let n = match stdin.read(&mut buffer) {
                 Ok(0) => break, // EOF
                 Ok(n) => n,
-                Err(_) => break,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    eprintln!("[netcat] Error reading stdin: {}", e);
+                    break;
+                }
             };
-            if stream.write_all(&buffer[..n]).is_err() {
-                break;
+            if let Err(e) = writer.write_all(&buffer[..n]) {
+                eprintln!("[netcat] Error writing to socket: {}", e);
+                return;
             }
         }
+        // Half-close: tell the server we are done sending
+        if let Err(e) = writer.shutdown(Shutdown::Write) {
+            eprintln!("[netcat] Failed to shut down write half: {}", e);
+        }
     });
 
     // Main thread: read from socket and write to stdout
     let mut stdout = io::stdout();
     let mut buffer = [0u8; 4096];
     loop {
-        let n = match stream_clone.read(&mut buffer) {
+        let n = match reader.read(&mut buffer) {
             Ok(0) => break, // Connection closed
             Ok(n) => n,
-            Err(_) => break,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => {
+                eprintln!("[netcat] Error reading from socket: {}", e);
+                break;
+            }
         };
-        if stdout.write_all(&buffer[..n]).is_err() {
-            break;
-        }
-        stdout.flush().ok();
+        stdout.write_all(&buffer[..n])?;
+        stdout.flush()?;
     }
 
-    stdin_handle.join().ok();
+    // The stdin thread may still be blocked on a read; it is not joined so that
+    // server EOF ends the session immediately.
+    eprintln!("[netcat] Connection closed by server");
     Ok(())
-} 
\ No newline at end of file
+}
@@ -0,0 +1,87 @@
+use std::io;
+use std::net::TcpStream;
+use std::time::Duration;
+
+/// Default read/write timeout applied to client connections.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Environment variable giving the timeout in seconds when there is no
+/// `--timeout` argument.
+pub const TIMEOUT_ENV: &str = "REPLICODE_CLIENT_TIMEOUT";
+
+/// Timeout and retry settings shared by the interactive clients.
+///
+/// Parsed from the optional trailing arguments `--timeout <secs>` and
+/// `--retries <n>`, the timeout falling back to `REPLICODE_CLIENT_TIMEOUT`;
+/// a timeout of 0 disables it (block forever).
+#[derive(Debug, Clone, Copy)]
+pub struct ClientTimeouts {
+    pub io_timeout: Option<Duration>,
+    pub retries: u32,
+}
+
+impl Default for ClientTimeouts {
+    fn default() -> Self {
+        ClientTimeouts {
+            io_timeout: Some(DEFAULT_TIMEOUT),
+            retries: 0,
+        }
+    }
+}
+
+impl ClientTimeouts {
+    pub fn from_args(args: &[String]) -> Result<Self, String> {
+        let mut timeouts = ClientTimeouts::default();
+        if let Ok(value) = std::env::var(TIMEOUT_ENV) {
+            timeouts.io_timeout = parse_timeout(&value).map_err(|e| format!("{}: {}", TIMEOUT_ENV, e))?;
+        }
+        let mut i = 0;
+        while i < args.len() {
+            let value = args.get(i + 1).ok_or_else(|| format!("{} requires a value", args[i]))?;
+            match args[i].as_str() {
+                "--timeout" => timeouts.io_timeout = parse_timeout(value)?,
+                "--retries" => {
+                    timeouts.retries = value.parse().map_err(|_| format!("invalid retry count: {}", value))?;
+                }
+                other => return Err(format!("unknown option: {}", other)),
+            }
+            i += 2;
+        }
+        Ok(timeouts)
+    }
+
+    /// Applies the read and write timeouts to `stream`.
+    pub fn apply(&self, stream: &TcpStream) -> io::Result<()> {
+        stream.set_read_timeout(self.io_timeout)?;
+        stream.set_write_timeout(self.io_timeout)
+    }
+
+    /// Connects to `addr` with the configured timeouts applied.
+    pub fn connect(&self, addr: &str) -> io::Result<TcpStream> {
+        let stream = TcpStream::connect(addr)?;
+        self.apply(&stream)?;
+        Ok(stream)
+    }
+}
+
+/// A timeout in whole seconds, where 0 means none.
+fn parse_timeout(value: &str) -> Result<Option<Duration>, String> {
+    let secs: u64 = value.parse().map_err(|_| format!("invalid timeout: {}", value))?;
+    Ok(if secs == 0 { None } else { Some(Duration::from_secs(secs)) })
+}
+
+/// Whether `e` came from a socket read/write timing out. Depending on the
+/// platform an expired timeout surfaces as either `WouldBlock` or `TimedOut`.
+pub fn is_timeout(e: &io::Error) -> bool {
+    matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut)
+}
+
+/// Rewrites timeout errors as `TimedOut` naming the operation that stalled,
+/// so callers can tell a stalled server apart from other I/O failures.
+pub fn describe_timeout(e: io::Error, operation: &str) -> io::Error {
+    if is_timeout(&e) {
+        io::Error::new(io::ErrorKind::TimedOut, format!("timed out while {}", operation))
+    } else {
+        e
+    }
+}
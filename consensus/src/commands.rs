@@ -1,7 +1,9 @@
+use std::fmt;
 use log::error;
 use serde::{Serialize, Deserialize};
+use crate::redact;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub enum NetworkOperation {
     Connect {
         dest_addr: String,
@@ -17,6 +19,9 @@ pub enum NetworkOperation {
     },
     Listen {
         src_port: u16,
+        bind_addr: Option<String>, // interface requested by the guest (default: loopback)
+        bind_port: Option<u16>,    // port requested by the guest (default: consensus-chosen)
+        backlog: u32,              // connections the host may queue before the guest accepts them
     },
     Accept {
         src_port: u16,
@@ -25,8 +30,146 @@ pub enum NetworkOperation {
     Recv {
         src_port: u16,
     },
+    // Datagram (SOCK_DGRAM) sockets only. Bind is optional: a socket that
+    // was not bound gets an ephemeral port on its first SendTo or RecvFrom.
+    Bind {
+        src_port: u16,
+        bind_addr: String,
+        bind_port: u16, // 0 lets the host choose
+    },
+    SendTo {
+        src_port: u16,
+        dest_addr: String,
+        dest_port: u16,
+        data: Vec<u8>,
+    },
+    RecvFrom {
+        src_port: u16,
+    },
+}
+
+// Written by hand so that `Send` payloads go through the log redaction policy.
+impl fmt::Debug for NetworkOperation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NetworkOperation::Connect { dest_addr, dest_port, src_port } => f
+                .debug_struct("Connect")
+                .field("dest_addr", dest_addr)
+                .field("dest_port", dest_port)
+                .field("src_port", src_port)
+                .finish(),
+            NetworkOperation::Send { src_port, data } => f
+                .debug_struct("Send")
+                .field("src_port", src_port)
+                .field("data", &redact::payload(data))
+                .finish(),
+            NetworkOperation::Close { src_port } => f.debug_struct("Close").field("src_port", src_port).finish(),
+            NetworkOperation::Listen { src_port, bind_addr, bind_port, backlog } => f
+                .debug_struct("Listen")
+                .field("src_port", src_port)
+                .field("bind_addr", bind_addr)
+                .field("bind_port", bind_port)
+                .field("backlog", backlog)
+                .finish(),
+            NetworkOperation::Accept { src_port, new_port } => f
+                .debug_struct("Accept")
+                .field("src_port", src_port)
+                .field("new_port", new_port)
+                .finish(),
+            NetworkOperation::Recv { src_port } => f.debug_struct("Recv").field("src_port", src_port).finish(),
+            NetworkOperation::Bind { src_port, bind_addr, bind_port } => f
+                .debug_struct("Bind")
+                .field("src_port", src_port)
+                .field("bind_addr", bind_addr)
+                .field("bind_port", bind_port)
+                .finish(),
+            NetworkOperation::SendTo { src_port, dest_addr, dest_port, data } => f
+                .debug_struct("SendTo")
+                .field("src_port", src_port)
+                .field("dest_addr", dest_addr)
+                .field("dest_port", dest_port)
+                .field("data", &redact::payload(data))
+                .finish(),
+            NetworkOperation::RecvFrom { src_port } => f.debug_struct("RecvFrom").field("src_port", src_port).finish(),
+        }
+    }
+}
+
+/// Result code of a guest network operation.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NetworkStatusCode {
+    Failed,
+    Success,
+    /// Accept/Recv/RecvFrom is still waiting for a connection or data.
+    Waiting,
+}
+
+/// Outcome of a guest network operation, sent to the runtime as a NetworkIn
+/// record on port 0.
+///
+/// Wire format: `[code: u8][src_port: u16 LE][new_port: u16 LE][peer]`, where
+/// `code` is 0 (failed), 1 (success) or 2 (waiting), `new_port` is the port of
+/// a newly accepted connection (0 otherwise) and `peer` is the remote
+/// `ip:port` of a new connection, or the source of a datagram delivered to a
+/// datagram socket, as UTF-8 text, or empty.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NetworkStatus {
+    pub code: NetworkStatusCode,
+    pub src_port: u16,
+    pub new_port: u16,
+    pub peer: Option<String>,
+}
+
+impl NetworkStatus {
+    /// Length of the fixed part of the payload, before `peer`.
+    pub const HEADER_LEN: usize = 5;
+
+    pub fn new(code: NetworkStatusCode, src_port: u16) -> Self {
+        NetworkStatus { code, src_port, new_port: 0, peer: None }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let code = match self.code {
+            NetworkStatusCode::Failed => 0u8,
+            NetworkStatusCode::Success => 1,
+            NetworkStatusCode::Waiting => 2,
+        };
+        let mut out = Vec::with_capacity(Self::HEADER_LEN);
+        out.push(code);
+        out.extend_from_slice(&self.src_port.to_le_bytes());
+        out.extend_from_slice(&self.new_port.to_le_bytes());
+        if let Some(peer) = &self.peer {
+            out.extend_from_slice(peer.as_bytes());
+        }
+        out
+    }
+
+    /// Returns `None` if `data` is shorter than the fixed header. Codes other
+    /// than 1 and 2 are treated as failures.
+    #[allow(dead_code)] // used by the runtime
+    pub fn decode(data: &[u8]) -> Option<Self> {
+        if data.len() < Self::HEADER_LEN {
+            return None;
+        }
+        let code = match data[0] {
+            1 => NetworkStatusCode::Success,
+            2 => NetworkStatusCode::Waiting,
+            _ => NetworkStatusCode::Failed,
+        };
+        let peer = &data[Self::HEADER_LEN..];
+        Some(NetworkStatus {
+            code,
+            src_port: u16::from_le_bytes([data[1], data[2]]),
+            new_port: u16::from_le_bytes([data[3], data[4]]),
+            peer: if peer.is_empty() { None } else { Some(String::from_utf8_lossy(peer).into_owned()) },
+        })
+    }
 }
 
+/// Most processes one InitN record may start. Runtimes reject a record
+/// asking for more, so a corrupt or hostile count cannot exhaust a replica.
+pub const MAX_INIT_N: u32 = 1024;
+
 /// High-level command variants.
 #[derive(Clone, Debug)]
 pub enum Command {
@@ -34,14 +177,40 @@ pub enum Command {
     Init {
         wasm_bytes: Vec<u8>,
         dir_path: Option<String>,
-        args: Vec<String>
+        args: Vec<String>,
+        file_cache: Option<u64>, // byte budget for the per-process file cache
+        max_memory: Option<u64>, // linear memory cap in bytes (runtime default if unset)
+        limits: Option<String>, // table/instance/memory count overrides, e.g. "table=5000,tables=1"
+        read_only: bool, // run with a read-only sandbox
+        tmpfs: bool, // keep the sandbox's files in memory
+        first_port: Option<u16>, // first guest-visible socket port (runtime default if unset)
+        cwd: Option<String>, // initial working directory, relative to the sandbox root
+        disk_quota: Option<u64>, // disk quota in bytes (runtime default if unset)
+        seed: u64, // seeds random_get, so every replica sees the same bytes
+        env: Vec<String>, // KEY=VALUE strings for environ_get
+        deadline: Option<u64>, // logical ns after Init at which the runtime ends the process
     },
+    InitN(u32, Box<Command>),  // count (at most MAX_INIT_N), the Init every process is started from
     FDMsg(u64, Vec<u8>),
     NetworkIn(u64, u16, Vec<u8>),  // pid, dest_port, data
+    Pause(u64),  // stop scheduling pid until a matching Resume
+    Resume(u64),
+    CancelNetwork(u64),  // fail pid's pending network operations
+    SetQuota(u64, Option<u64>),  // pid, new disk quota in bytes (None only reports usage)
+    Shutdown(u64),  // end pid, or every process for 0
     #[allow(dead_code)]
     NetworkOut(u64, NetworkOperation), // pid, operation
 }
 
+/// Seed for an Init without `-s`. It only has to differ between runs; the
+/// record carries it, so replicas never pick their own.
+fn fresh_seed() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_nanos() as u64)
+        .unwrap_or_default()
+}
+
 /// Reads a WASM file from disk.
 pub fn read_wasm_file(file_path: &str) -> std::io::Result<Vec<u8>> {
     std::fs::read(file_path).map_err(|e| {
@@ -52,10 +221,14 @@ pub fn read_wasm_file(file_path: &str) -> std::io::Result<Vec<u8>> {
 
 /// Parse a text command into a high-level Command.
 /// Supported commands:
-///   - init <wasm_file> [-d directory] [-a 'arg1 arg2 ...']
+///   - init <wasm_file> [-d directory] [-c cache_bytes] [-m memory_bytes] [-l key=value,...] [-r] [-t] [-p first_port] [-w cwd] [-q quota_bytes] [-s seed] [-e KEY=VALUE]... [--deadline ns] [-a 'arg1 arg2 ...']
 ///   - msg <pid> <message>
 ///   - ftp <pid> <ftp_command>
 ///   - clock <nanoseconds>
+///   - pause <pid> / resume <pid>
+///   - cancelnetwork <pid>
+///   - quota <pid> [bytes]
+///   - shutdown <pid> (0 for every process)
 pub fn parse_command(line: &str) -> Option<Command> {
     let trimmed = line.trim();
     if trimmed.eq_ignore_ascii_case("exit") {
@@ -68,7 +241,7 @@ pub fn parse_command(line: &str) -> Option<Command> {
     match tokens[0].to_lowercase().as_str() {
         "init" => {
             if tokens.len() < 2 {
-                error!("Usage: init <wasm_file> [-d directory] [-a 'arg1 arg2 ...']");
+                error!("Usage: init <wasm_file> [-d directory] [-c cache_bytes] [-m memory_bytes] [-l key=value,...] [-r] [-t] [-p first_port] [-w cwd] [-q quota_bytes] [-s seed] [-e KEY=VALUE]... [--deadline ns] [-a 'arg1 arg2 ...']");
                 return None;
             }
             
@@ -80,6 +253,17 @@ pub fn parse_command(line: &str) -> Option<Command> {
             
             let mut dir_path = None;
             let mut args = Vec::new();
+            let mut file_cache = None;
+            let mut max_memory = None;
+            let mut limits = None;
+            let mut read_only = false;
+            let mut tmpfs = false;
+            let mut first_port = None;
+            let mut cwd = None;
+            let mut disk_quota = None;
+            let mut seed = None;
+            let mut env = Vec::new();
+            let mut deadline = None;
             let mut i = 2;
             
             while i < tokens.len() {
@@ -93,6 +277,128 @@ pub fn parse_command(line: &str) -> Option<Command> {
                             return None;
                         }
                     },
+                    "-c" => {
+                        match tokens.get(i + 1).and_then(|t| t.parse::<u64>().ok()) {
+                            Some(bytes) => {
+                                file_cache = Some(bytes);
+                                i += 2;
+                            }
+                            None => {
+                                error!("-c flag requires a cache size in bytes");
+                                return None;
+                            }
+                        }
+                    },
+                    "-m" => {
+                        match tokens.get(i + 1).and_then(|t| t.parse::<u64>().ok()) {
+                            Some(bytes) => {
+                                max_memory = Some(bytes);
+                                i += 2;
+                            }
+                            None => {
+                                error!("-m flag requires a memory limit in bytes");
+                                return None;
+                            }
+                        }
+                    },
+                    "-l" => {
+                        // e.g. -l table=5000,instances=1; keys are checked by the runtime
+                        let valid = |spec: &str| spec.split(',').all(|item| {
+                            matches!(item.split_once('='), Some((key, value)) if !key.is_empty() && value.parse::<u64>().is_ok())
+                        });
+                        match tokens.get(i + 1) {
+                            Some(spec) if valid(spec) => {
+                                limits = Some(spec.to_string());
+                                i += 2;
+                            }
+                            _ => {
+                                error!("-l flag requires limits as key=value pairs, e.g. table=5000,tables=1");
+                                return None;
+                            }
+                        }
+                    },
+                    "-r" => {
+                        read_only = true;
+                        i += 1;
+                    },
+                    "-t" => {
+                        tmpfs = true;
+                        i += 1;
+                    },
+                    "-p" => {
+                        match tokens.get(i + 1).and_then(|t| t.parse::<u16>().ok()) {
+                            Some(port) if port > 0 => {
+                                first_port = Some(port);
+                                i += 2;
+                            }
+                            _ => {
+                                error!("-p flag requires a port between 1 and 65535");
+                                return None;
+                            }
+                        }
+                    },
+                    "-w" => {
+                        // Relative to the sandbox root; the runtime rejects paths leaving it
+                        match tokens.get(i + 1) {
+                            Some(dir) if !dir.starts_with('/') => {
+                                cwd = Some(dir.to_string());
+                                i += 2;
+                            }
+                            _ => {
+                                error!("-w flag requires a directory relative to the sandbox root");
+                                return None;
+                            }
+                        }
+                    },
+                    "-q" | "--quota" => {
+                        match tokens.get(i + 1).and_then(|t| t.parse::<u64>().ok()) {
+                            Some(bytes) => {
+                                disk_quota = Some(bytes);
+                                i += 2;
+                            }
+                            None => {
+                                error!("{} flag requires a disk quota in bytes", tokens[i]);
+                                return None;
+                            }
+                        }
+                    },
+                    "-s" => {
+                        match tokens.get(i + 1).and_then(|t| t.parse::<u64>().ok()) {
+                            Some(value) => {
+                                seed = Some(value);
+                                i += 2;
+                            }
+                            None => {
+                                error!("-s flag requires a random seed (an unsigned integer)");
+                                return None;
+                            }
+                        }
+                    },
+                    "-e" => {
+                        // One variable per flag; the record separates them with \x1F
+                        match tokens.get(i + 1) {
+                            Some(var) if var.split_once('=').is_some_and(|(key, _)| !key.is_empty()) && !var.contains(['\0', '\x1F']) => {
+                                env.push(var.to_string());
+                                i += 2;
+                            }
+                            _ => {
+                                error!("-e flag requires an environment variable as KEY=VALUE");
+                                return None;
+                            }
+                        }
+                    },
+                    "--deadline" => {
+                        match tokens.get(i + 1).and_then(|t| t.parse::<u64>().ok()) {
+                            Some(nanos) => {
+                                deadline = Some(nanos);
+                                i += 2;
+                            }
+                            None => {
+                                error!("--deadline flag requires a logical time in nanoseconds");
+                                return None;
+                            }
+                        }
+                    },
                     "-a" => {
                         if i + 1 < tokens.len() {
                             // Collect all remaining tokens as arguments
@@ -124,7 +430,19 @@ pub fn parse_command(line: &str) -> Option<Command> {
                 }
             }
             
-            Some(Command::Init { wasm_bytes, dir_path, args })
+            Some(Command::Init { wasm_bytes, dir_path, args, file_cache, max_memory, limits, read_only, tmpfs, first_port, cwd, disk_quota, seed: seed.unwrap_or_else(fresh_seed), env, deadline })
+        },
+        "initn" => {
+            // "initn <count> <wasm_file> [init flags...]"
+            let count = match tokens.get(1).and_then(|t| t.parse::<u32>().ok()) {
+                Some(count) if (1..=MAX_INIT_N).contains(&count) => count,
+                _ => {
+                    error!("Usage: initn <count> <wasm_file> [init flags...], with 1 <= count <= {}", MAX_INIT_N);
+                    return None;
+                }
+            };
+            let init = parse_command(&format!("init {}", tokens[2..].join(" ")))?;
+            Some(Command::InitN(count, Box::new(init)))
         },
         "msg" => {
             // "msg <pid> <message>"
@@ -145,9 +463,98 @@ pub fn parse_command(line: &str) -> Option<Command> {
             let delta = tokens[1].parse::<u64>().unwrap_or(0);
             Some(Command::Clock(delta))
         },
+        "pause" | "resume" => {
+            // "pause <pid>" / "resume <pid>"
+            let pid = match tokens.get(1).and_then(|t| t.parse::<u64>().ok()) {
+                Some(pid) => pid,
+                None => {
+                    error!("Usage: {} <pid>", tokens[0].to_lowercase());
+                    return None;
+                }
+            };
+            if tokens[0].eq_ignore_ascii_case("pause") {
+                Some(Command::Pause(pid))
+            } else {
+                Some(Command::Resume(pid))
+            }
+        },
+        "cancelnetwork" => {
+            // "cancelnetwork <pid>"
+            match tokens.get(1).and_then(|t| t.parse::<u64>().ok()) {
+                Some(pid) => Some(Command::CancelNetwork(pid)),
+                None => {
+                    error!("Usage: cancelnetwork <pid>");
+                    None
+                }
+            }
+        },
+        "shutdown" => {
+            // "shutdown <pid>"; pid 0 ends every process
+            match tokens.get(1).and_then(|t| t.parse::<u64>().ok()) {
+                Some(pid) => Some(Command::Shutdown(pid)),
+                None => {
+                    error!("Usage: shutdown <pid>");
+                    None
+                }
+            }
+        },
+        "quota" => {
+            // "quota <pid> [bytes]"; without bytes every replica reports usage and limit
+            let pid = tokens.get(1).and_then(|t| t.parse::<u64>().ok());
+            let bytes = tokens.get(2).map(|t| t.parse::<u64>());
+            match (pid, bytes) {
+                (Some(pid), None) => Some(Command::SetQuota(pid, None)),
+                (Some(pid), Some(Ok(bytes))) => Some(Command::SetQuota(pid, Some(bytes))),
+                _ => {
+                    error!("Usage: quota <pid> [bytes]");
+                    None
+                }
+            }
+        },
         _ => {
-            error!("Unknown command. Use 'init', 'msg', 'ftp', or 'clock'.");
+            error!("Unknown command. Use 'init', 'msg', 'ftp', 'clock', 'pause', 'resume', 'cancelnetwork', 'quota' or 'shutdown'.");
             None
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn network_status_round_trips() {
+        let statuses = [
+            NetworkStatus::new(NetworkStatusCode::Success, 5000),
+            NetworkStatus::new(NetworkStatusCode::Waiting, 5000),
+            NetworkStatus::new(NetworkStatusCode::Failed, 65535),
+            // An accept names the new connection's port and its peer
+            NetworkStatus {
+                code: NetworkStatusCode::Success,
+                src_port: 5000,
+                new_port: 5001,
+                peer: Some("127.0.0.1:40000".to_string()),
+            },
+        ];
+        for status in statuses {
+            assert_eq!(NetworkStatus::decode(&status.encode()), Some(status));
+        }
+    }
+
+    #[test]
+    fn network_status_layout() {
+        let accept = NetworkStatus { new_port: 0x1389, ..NetworkStatus::new(NetworkStatusCode::Success, 0x1388) };
+        assert_eq!(accept.encode(), [1, 0x88, 0x13, 0x89, 0x13]);
+        let peer = NetworkStatus { peer: Some("[::1]:80".to_string()), ..NetworkStatus::new(NetworkStatusCode::Waiting, 1) };
+        assert_eq!(peer.encode(), b"\x02\x01\x00\x00\x00[::1]:80");
+    }
+
+    #[test]
+    fn network_status_decode_rejects_short_payloads() {
+        assert_eq!(NetworkStatus::decode(&[1, 0x88, 0x13, 0x89]), None);
+        assert_eq!(NetworkStatus::decode(&[]), None);
+        // Unknown codes read as failures
+        let decoded = NetworkStatus::decode(&[7, 1, 0, 0, 0]).unwrap();
+        assert_eq!(decoded, NetworkStatus::new(NetworkStatusCode::Failed, 1));
+    }
+}
@@ -1,30 +1,42 @@
-use std::net::{TcpListener, TcpStream};
+use std::net::{SocketAddr, TcpListener, TcpStream};
 use std::io::{Read, Write};
 use std::sync::{Arc, Mutex};
+use std::collections::HashMap;
 use std::thread;
 use log::{info, error};
-use serde_json::json;
+use serde_json::{json, Value};
+use crate::batch::BatchDirection;
+use crate::batch_history::BatchHistory;
 use crate::nat::NatTable;
 
 pub struct HttpServer {
     nat_table: Arc<Mutex<NatTable>>,
+    progress_reports: Arc<Mutex<HashMap<u64, Vec<u8>>>>,
+    batch_history: Arc<Mutex<BatchHistory>>,
 }
 
 impl HttpServer {
-    pub fn new(nat_table: Arc<Mutex<NatTable>>) -> Self {
-        HttpServer { nat_table }
+    pub fn new(
+        nat_table: Arc<Mutex<NatTable>>,
+        progress_reports: Arc<Mutex<HashMap<u64, Vec<u8>>>>,
+        batch_history: Arc<Mutex<BatchHistory>>,
+    ) -> Self {
+        HttpServer { nat_table, progress_reports, batch_history }
     }
 
-    pub fn start(&self, port: u16) -> std::io::Result<()> {
-        let listener = TcpListener::bind(format!("127.0.0.1:{}", port))?;
-        info!("HTTP server listening on 127.0.0.1:{}", port);
+    pub fn start(&self, addr: SocketAddr) -> std::io::Result<()> {
+        let listener = TcpListener::bind(addr)?;
+        // Port 0 picks a free one, so log the one actually bound
+        info!("HTTP server listening on {}", listener.local_addr()?);
 
         for stream in listener.incoming() {
             match stream {
                 Ok(stream) => {
                     let nat_table = Arc::clone(&self.nat_table);
+                    let progress_reports = Arc::clone(&self.progress_reports);
+                    let batch_history = Arc::clone(&self.batch_history);
                     thread::spawn(move || {
-                        if let Err(e) = Self::handle_client(stream, nat_table) {
+                        if let Err(e) = Self::handle_client(stream, nat_table, progress_reports, batch_history) {
                             error!("Error handling client: {}", e);
                         }
                     });
@@ -37,37 +49,75 @@ impl HttpServer {
         Ok(())
     }
 
-    fn handle_client(mut stream: TcpStream, nat_table: Arc<Mutex<NatTable>>) -> std::io::Result<()> {
+    fn handle_client(
+        mut stream: TcpStream,
+        nat_table: Arc<Mutex<NatTable>>,
+        progress_reports: Arc<Mutex<HashMap<u64, Vec<u8>>>>,
+        batch_history: Arc<Mutex<BatchHistory>>,
+    ) -> std::io::Result<()> {
         let mut buffer = [0; 1024];
         let n = stream.read(&mut buffer)?;
         let request = String::from_utf8_lossy(&buffer[..n]);
         
-        // Parse the request path
-        let path = request.lines().next().unwrap_or("").split_whitespace().nth(1).unwrap_or("/");
+        // Parse the request method and path
+        let mut request_line = request.lines().next().unwrap_or("").split_whitespace();
+        let method = request_line.next().unwrap_or("GET");
+        let path = request_line.next().unwrap_or("/");
+        
+        let (path, query) = path.split_once('?').unwrap_or((path, ""));
+        
+        // Every endpoint only reads state
+        if method != "GET" {
+            let response = json_response("405 Method Not Allowed", &json!({ "error": format!("{} is not allowed", method) }));
+            stream.write_all(response.as_bytes())?;
+            stream.flush()?;
+            return Ok(());
+        }
         
         // Generate response based on path
         let response = match path {
+            "/healthz" => json_response("200 OK", &json!({ "status": "ok" })),
+            // Each /api endpoint returns one section of /status, with the same layout
+            "/api/processes" => json_response("200 OK", &nat_table.lock().unwrap().get_process_info()),
+            "/api/connections" => json_response("200 OK", &nat_table.lock().unwrap().get_connection_info()),
+            "/api/listeners" => json_response("200 OK", &nat_table.lock().unwrap().get_listener_info()),
+            "/api/mappings" => json_response("200 OK", &mappings_json(&nat_table.lock().unwrap())),
             "/status" => {
                 let nat_table = nat_table.lock().unwrap();
+                let progress: HashMap<String, String> = progress_reports.lock().unwrap().iter()
+                    .map(|(pid, report)| (pid.to_string(), String::from_utf8_lossy(report).into_owned()))
+                    .collect();
                 let status = json!({
+                    "progress": progress,
                     "processes": nat_table.get_process_info(),
+                    "sockets": nat_table.get_socket_usage(),
                     "connections": nat_table.get_connection_info(),
                     "listeners": nat_table.get_listener_info(),
-                    "mappings": nat_table.get_port_mappings().iter().map(|(pid, process_port, consensus_port, mapping_type)| {
-                        json!({
-                            "pid": pid,
-                            "process_port": process_port,
-                            "consensus_port": consensus_port,
-                            "type": mapping_type
-                        })
-                    }).collect::<Vec<_>>()
+                    "pending": nat_table.get_pending_info(),
+                    "mappings": mappings_json(&nat_table)
                 });
-                format!(
-                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
-                    status.to_string().len(),
-                    status
-                )
+                json_response("200 OK", &status)
             }
+            "/batches" => {
+                // Metadata only, e.g. /batches?since=100; payloads can be large
+                let since = query.split('&')
+                    .find_map(|param| param.strip_prefix("since="))
+                    .and_then(|n| n.parse::<u64>().ok())
+                    .unwrap_or(0);
+                let batches = batch_history.lock().unwrap().get_batches_since(since)?;
+                let body = json!(batches.iter().map(|batch| json!({
+                    "number": batch.number,
+                    "direction": match batch.direction {
+                        BatchDirection::Incoming => "incoming",
+                        BatchDirection::Outgoing => "outgoing",
+                    },
+                    "len": batch.data.len(),
+                    "created_at_ms": batch.created_at_ms,
+                    "logical_time": batch.logical_time,
+                })).collect::<Vec<_>>());
+                json_response("200 OK", &body)
+            }
+            _ if path.starts_with("/api/") => json_response("404 Not Found", &json!({ "error": format!("no endpoint {}", path) })),
             _ => {
                 "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n".to_string()
             }
@@ -77,4 +127,27 @@ impl HttpServer {
         stream.flush()?;
         Ok(())
     }
-} 
\ No newline at end of file
+}
+
+/// An HTTP response with `body` as JSON and the given status line, e.g. `"200 OK"`.
+fn json_response(status: &str, body: &Value) -> String {
+    let body = body.to_string();
+    format!(
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )
+}
+
+/// The NAT table's port mappings, one object per mapping.
+fn mappings_json(nat_table: &NatTable) -> Value {
+    json!(nat_table.get_port_mappings().iter().map(|(pid, process_port, consensus_port, mapping_type)| {
+        json!({
+            "pid": pid,
+            "process_port": process_port,
+            "consensus_port": consensus_port,
+            "type": mapping_type
+        })
+    }).collect::<Vec<_>>())
+}
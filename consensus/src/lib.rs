@@ -7,6 +7,7 @@ pub mod clients;
 pub mod runtime_manager;
 pub mod batch;
 pub mod batch_history;
+pub mod redact;
 
 pub use http_server::HttpServer;
 pub use modes::run_tcp_mode;
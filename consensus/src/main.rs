@@ -2,8 +2,10 @@ mod commands;
 mod record;
 mod modes {
     pub mod benchmark;
+    pub mod diff;
     pub mod tcp;
     pub use benchmark::run_benchmark_mode;
+    pub use diff::run_diff_mode;
     pub use tcp::run_tcp_mode;
 }
 mod nat;
@@ -12,7 +14,9 @@ mod http_server;
 mod batch;
 mod runtime_manager;
 mod batch_history;
+mod redact;
 use std::env;
+use std::net::SocketAddr;
 use std::io;
 use log::{info, error};
 use std::process;
@@ -28,9 +32,11 @@ fn main() -> io::Result<()> {
     eprintln!("TCP mode: enter commands interactively; every 10 seconds a batch is sent over TCP with an automatic clock record appended.");
     eprintln!("Test server: starts a local echo server on 127.0.0.1:8000 for testing network connections.");
     eprintln!("Test client: starts a test client for testing network connections.");
+    eprintln!("Diff: compares two recorded session files and reports the first batch and record where they diverge.");
+    eprintln!("Pass --redact to hide all payload contents in logs, or --no-redact to log them in full.");
     eprintln!("Type 'exit' to quit.\n");
     
-    let args: Vec<String> = env::args().collect();
+    let args: Vec<String> = env::args().filter(|arg| !redact::apply_flag(arg)).collect();
     if args.len() < 2 {
         error!("Usage: {} <mode>", args[0]);
         process::exit(1);
@@ -47,7 +53,43 @@ fn main() -> io::Result<()> {
         //     let input_file_path = &args[2];
         //     modes::run_hybrid_mode(input_file_path)
         // },
-        "tcp" => modes::run_tcp_mode(),
+        "tcp" => {
+            // e.g. `consensus tcp 0.0.0.0:9100`, or REPLICODE_LISTEN_ADDR; port 0 picks a free one
+            let addr = args.get(2).cloned()
+                .or_else(|| env::var("REPLICODE_LISTEN_ADDR").ok())
+                .unwrap_or_else(|| runtime_manager::DEFAULT_RUNTIME_ADDR.to_string());
+            let listen_addr = runtime_manager::resolve_addr(&addr).unwrap_or_else(|e| {
+                eprintln!("Cannot listen for runtimes: {}", e);
+                process::exit(1);
+            });
+            // REPLICODE_HTTP_PORT=0 picks a free port, logged once bound
+            let http_port = match env::var("REPLICODE_HTTP_PORT") {
+                Ok(port) => port.parse().unwrap_or_else(|_| {
+                    eprintln!("Invalid REPLICODE_HTTP_PORT '{}': expected a port number", port);
+                    process::exit(1);
+                }),
+                Err(_) => modes::tcp::DEFAULT_HTTP_PORT,
+            };
+            // e.g. REPLICODE_HTTP_ADDR=0.0.0.0 serves the status to other hosts
+            let http_ip = match env::var("REPLICODE_HTTP_ADDR") {
+                Ok(ip) => ip.parse().unwrap_or_else(|_| {
+                    eprintln!("Invalid REPLICODE_HTTP_ADDR '{}': expected an IP address", ip);
+                    process::exit(1);
+                }),
+                Err(_) => modes::tcp::DEFAULT_HTTP_IP,
+            };
+            modes::run_tcp_mode(listen_addr, SocketAddr::new(http_ip, http_port))
+        },
+        "diff" => {
+            if args.len() < 4 {
+                eprintln!("Usage: {} diff <session-a.bin> <session-b.bin>", args[0]);
+                process::exit(1);
+            }
+            if !modes::run_diff_mode(&args[2], &args[3])? {
+                process::exit(1);
+            }
+            Ok(())
+        },
         "test-server" => clients::start_test_server(),
         "test-client" => {
             clients::run_test_client();
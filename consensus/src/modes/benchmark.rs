@@ -13,7 +13,7 @@ pub fn run_benchmark_mode() -> io::Result<()> {
         .open(file_path)?;
 
     loop {
-        eprint!("Command (init <wasm_file> | msg <pid> <message> | ftp <pid> <ftp_command> | clock <nanoseconds>): ");
+        eprint!("Command (init <wasm_file> | initn <count> <wasm_file> | msg <pid> <message> | ftp <pid> <ftp_command> | clock <nanoseconds> | pause <pid> | resume <pid> | cancelnetwork <pid> | quota <pid> [bytes] | shutdown <pid>): ");
         io::stderr().flush()?;
         let mut input = String::new();
         io::stdin().read_line(&mut input)?;
@@ -27,10 +27,16 @@ pub fn run_benchmark_mode() -> io::Result<()> {
             output.flush()?;
             match &cmd {
                 Command::Init { .. } => info!("Initialization record written."),
+                Command::InitN(count, _) => info!("Initialization record for {} processes written.", count),
                 Command::FDMsg(pid, _) => info!("Message record for process {} written.", pid),
                 Command::Clock(delta) => info!("Clock record ({} ns) written.", delta),
                 Command::NetworkIn(pid, port, _) => info!("Network input record for process {} port {} written.", pid, port),
                 Command::NetworkOut(pid, _) => info!("Network output record for process {} written.", pid),
+                Command::Pause(pid) => info!("Pause record for process {} written.", pid),
+                Command::Resume(pid) => info!("Resume record for process {} written.", pid),
+                Command::CancelNetwork(pid) => info!("CancelNetwork record for process {} written.", pid),
+                Command::SetQuota(pid, _) => info!("SetQuota record for process {} written.", pid),
+                Command::Shutdown(pid) => info!("Shutdown record for process {} written.", pid),
             }
         }
     }
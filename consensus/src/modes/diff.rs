@@ -0,0 +1,254 @@
+use std::io;
+use std::path::Path;
+use log::info;
+use crate::batch::{Batch, BatchDirection};
+use crate::batch_history::BatchHistory;
+use crate::record::{canonical_order, read_records, RawRecord};
+use crate::redact;
+
+/// Where two sessions first disagree within one direction of traffic.
+struct Divergence {
+    index: usize,
+    record: Option<usize>,
+}
+
+fn record_type_name(msg_type: u8) -> &'static str {
+    match msg_type {
+        0 => "Clock",
+        1 => "FDMsg",
+        2 => "Init",
+        3 => "NetworkIn",
+        4 | 5 => "NetworkOut",
+        6 => "ProgressReport",
+        7 => "Pause",
+        8 => "Resume",
+        9 => "CancelNetwork",
+        10 => "SetQuota",
+        11 => "InitN",
+        12 => "BatchRejected",
+        13 => "Shutdown",
+        14 => "ProcessExit",
+        15 => "BatchAck",
+        _ => "Unknown",
+    }
+}
+
+fn describe_record(record: Option<&RawRecord<'_>>) -> String {
+    match record {
+        Some((msg_type, pid, payload)) => format!(
+            "{} (type {}) pid {} len {}: {}",
+            record_type_name(*msg_type), msg_type, pid, payload.len(), redact::payload(payload)
+        ),
+        None => "<no record>".to_string(),
+    }
+}
+
+/// When a batch was created, for history files that record it.
+fn describe_time(batch: &Batch) -> String {
+    let mut parts = Vec::new();
+    if batch.created_at_ms != 0 {
+        parts.push(format!("created at {} ms", batch.created_at_ms));
+    }
+    if let Some(logical_time) = batch.logical_time {
+        parts.push(format!("logical time {} ns", logical_time));
+    }
+    if parts.is_empty() {
+        String::new()
+    } else {
+        format!(", {}", parts.join(", "))
+    }
+}
+
+/// CRC32 of every batch in `batches`, standing in for the state a runtime has
+/// reached after applying them. It depends only on the batches' numbers and
+/// bytes, so it is the same for every build and every run.
+fn state_hash(batches: &[&Batch]) -> u32 {
+    let mut hasher = crc32fast::Hasher::new();
+    for batch in batches {
+        hasher.update(&batch.number.to_le_bytes());
+        hasher.update(&(batch.data.len() as u64).to_le_bytes());
+        hasher.update(&batch.data);
+    }
+    hasher.finalize()
+}
+
+/// The records of `batch` in the order they take effect. Runtimes apply an
+/// incoming batch in `canonical_order`, so two that differ only in the order
+/// of their records agree, and a record's index is the `record=` the
+/// runtime's applied_record log gives it.
+fn batch_records(batch: &Batch) -> io::Result<Vec<RawRecord<'_>>> {
+    let mut records = read_records(&batch.data)?;
+    if batch.direction == BatchDirection::Incoming {
+        canonical_order(&mut records);
+    }
+    Ok(records)
+}
+
+/// Finds the first batch (and record within it) where `a` and `b` differ.
+fn first_divergence(a: &[&Batch], b: &[&Batch]) -> Option<Divergence> {
+    for (index, (batch_a, batch_b)) in a.iter().zip(b.iter()).enumerate() {
+        if batch_a.number == batch_b.number && batch_a.data == batch_b.data {
+            continue;
+        }
+        // Pinpoint the record; batches that no longer parse are reported as a whole
+        let record = match (batch_records(batch_a), batch_records(batch_b)) {
+            (Ok(records_a), Ok(records_b)) => {
+                if batch_a.number == batch_b.number && records_a == records_b {
+                    continue;
+                }
+                let common = records_a.iter().zip(records_b.iter()).take_while(|(ra, rb)| ra == rb).count();
+                Some(common)
+            }
+            _ => None,
+        };
+        return Some(Divergence { index, record });
+    }
+    None
+}
+
+/// Compares one direction of traffic and prints the result. Returns false if
+/// the sessions diverge.
+fn compare_stream(label: &str, a: &[&Batch], b: &[&Batch]) -> bool {
+    match first_divergence(a, b) {
+        Some(Divergence { index, record }) => {
+            let (batch_a, batch_b) = (a[index], b[index]);
+            println!("{} batches diverge at position {}", label, index);
+            println!("  state hash before divergence: {:08x}", state_hash(&a[..index]));
+            for (name, batch, prefix) in [("A", batch_a, a), ("B", batch_b, b)] {
+                println!(
+                    "  session {}: batch #{} ({} bytes{}), state hash after {:08x}",
+                    name, batch.number, batch.data.len(), describe_time(batch), state_hash(&prefix[..=index])
+                );
+            }
+            match record {
+                Some(record) => {
+                    let records_a = batch_records(batch_a).unwrap_or_default();
+                    let records_b = batch_records(batch_b).unwrap_or_default();
+                    println!(
+                        "  first differing record: {} (A has {} records, B has {})",
+                        record, records_a.len(), records_b.len()
+                    );
+                    println!("    A: {}", describe_record(records_a.get(record)));
+                    println!("    B: {}", describe_record(records_b.get(record)));
+                }
+                None => println!("  batch contents could not be split into records"),
+            }
+            false
+        }
+        None => {
+            let common = a.len().min(b.len());
+            println!("{} batches: first {} identical", label, common);
+            if a.len() != b.len() {
+                let (longer, extra) = if a.len() > b.len() { ("A", a) } else { ("B", b) };
+                println!(
+                    "  session {} continues with {} more batches, starting at batch #{}",
+                    longer, extra.len() - common, extra[common].number
+                );
+            }
+            true
+        }
+    }
+}
+
+/// Compares two recorded sessions and reports where they first diverge.
+///
+/// Incoming (consensus -> runtime) and outgoing (runtime -> consensus) batches
+/// are compared separately, since their interleaving depends on timing;
+/// incoming traffic is checked first and comparison stops at the first
+/// divergence. A session that is a prefix of the other is not treated as a
+/// divergence. Outgoing batches are compared in recorded order, so both sessions should
+/// have been recorded with the same number of runtimes.
+///
+/// Returns true if the sessions agree.
+pub fn run_diff_mode(path_a: &str, path_b: &str) -> io::Result<bool> {
+    info!("Comparing sessions {} and {}", path_a, path_b);
+    let session_a = BatchHistory::load(Path::new(path_a))?;
+    let session_b = BatchHistory::load(Path::new(path_b))?;
+    println!("A: {} ({} batches)", path_a, session_a.len());
+    println!("B: {} ({} batches)", path_b, session_b.len());
+
+    for (label, direction) in [("Incoming", BatchDirection::Incoming), ("Outgoing", BatchDirection::Outgoing)] {
+        let a: Vec<&Batch> = session_a.iter().filter(|batch| batch.direction == direction).collect();
+        let b: Vec<&Batch> = session_b.iter().filter(|batch| batch.direction == direction).collect();
+        // Later batches follow from the first difference, so stop there
+        if !compare_stream(label, &a, &b) {
+            return Ok(false);
+        }
+    }
+
+    println!("No divergence found");
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::Command;
+    use crate::record::write_record;
+
+    fn incoming(number: u64, data: Vec<u8>) -> Batch {
+        Batch::new(number, BatchDirection::Incoming, data)
+    }
+
+    fn records(clocks: &[u64]) -> Vec<u8> {
+        clocks.iter().flat_map(|delta| write_record(&Command::Clock(*delta)).unwrap()).collect()
+    }
+
+    #[test]
+    fn state_hash_is_fixed_for_the_same_batches() {
+        let batches = [incoming(1, b"abc".to_vec()), incoming(2, Vec::new())];
+        let refs: Vec<&Batch> = batches.iter().collect();
+        assert_eq!(state_hash(&refs), state_hash(&refs));
+        assert_eq!(state_hash(&[]), 0);
+        // Depends only on the numbers and bytes, not on when the batch was made
+        let mut later = incoming(1, b"abc".to_vec());
+        later.created_at_ms += 1000;
+        assert_eq!(state_hash(&[&later]), state_hash(&refs[..1]));
+    }
+
+    #[test]
+    fn state_hash_changes_with_numbers_and_bytes() {
+        let base = incoming(1, b"abc".to_vec());
+        let hash = state_hash(&[&base]);
+        assert_ne!(state_hash(&[&incoming(2, b"abc".to_vec())]), hash);
+        assert_ne!(state_hash(&[&incoming(1, b"abd".to_vec())]), hash);
+        // Moving bytes from one batch to the next is a different history
+        let (a, b) = (incoming(1, b"ab".to_vec()), incoming(1, b"c".to_vec()));
+        let (c, d) = (incoming(1, b"a".to_vec()), incoming(1, b"bc".to_vec()));
+        assert_ne!(state_hash(&[&a, &b]), state_hash(&[&c, &d]));
+    }
+
+    #[test]
+    fn first_divergence_names_the_batch_and_record() {
+        let a = [incoming(1, records(&[1])), incoming(2, records(&[1, 2, 3]))];
+        let b = [incoming(1, records(&[1])), incoming(2, records(&[1, 5, 3]))];
+        let (a, b): (Vec<&Batch>, Vec<&Batch>) = (a.iter().collect(), b.iter().collect());
+        let divergence = first_divergence(&a, &b).unwrap();
+        assert_eq!((divergence.index, divergence.record), (1, Some(1)));
+        assert!(first_divergence(&a[..1], &b).is_none());
+    }
+
+    #[test]
+    fn incoming_records_are_compared_in_the_order_runtimes_apply_them() {
+        let pause = write_record(&Command::Pause(1)).unwrap();
+        let input = write_record(&Command::FDMsg(1, b"fd:0,body:a".to_vec())).unwrap();
+        let a = [incoming(1, [pause.clone(), input.clone(), records(&[1])].concat())];
+        let b = [incoming(1, [input.clone(), pause.clone(), records(&[1])].concat())];
+        let (a, b): (Vec<&Batch>, Vec<&Batch>) = (a.iter().collect(), b.iter().collect());
+        assert!(first_divergence(&a, &b).is_none());
+        // Consensus carries out outgoing records in the order they came in
+        let c = [Batch::new(1, BatchDirection::Outgoing, [pause.clone(), input.clone()].concat())];
+        let d = [Batch::new(1, BatchDirection::Outgoing, [input, pause].concat())];
+        let (c, d): (Vec<&Batch>, Vec<&Batch>) = (c.iter().collect(), d.iter().collect());
+        assert_eq!(first_divergence(&c, &d).unwrap().record, Some(0));
+    }
+
+    #[test]
+    fn unparsable_batches_diverge_as_a_whole() {
+        let a = [incoming(1, vec![0xff])];
+        let b = [incoming(1, vec![0xfe])];
+        let (a, b): (Vec<&Batch>, Vec<&Batch>) = (a.iter().collect(), b.iter().collect());
+        let divergence = first_divergence(&a, &b).unwrap();
+        assert_eq!((divergence.index, divergence.record), (0, None));
+    }
+}
@@ -1,5 +1,7 @@
 pub mod benchmark;
+pub mod diff;
 pub mod tcp;
 
 pub use benchmark::run_benchmark_mode;
+pub use diff::run_diff_mode;
 pub use tcp::run_tcp_mode;
@@ -1,31 +1,119 @@
-use std::io::{self, Write, Read, BufReader};
-use std::sync::{Arc, Mutex};
+use std::io::{self, Write, Read};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use std::path::PathBuf;
-use std::collections::HashSet;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::collections::{HashMap, HashSet};
 use log::{error, info, debug, warn};
-use bincode;
 use chrono::Local;
 
-use crate::record::write_record;
-use crate::commands::{parse_command, Command, NetworkOperation};
-use crate::nat::NatTable;
+use crate::record::{clock_advance, read_records, write_record};
+use crate::commands::{parse_command, Command, NetworkOperation, NetworkStatus, NetworkStatusCode};
+use crate::nat::{self, BindRule, NatTable, DEFAULT_POOL_IDLE_TIMEOUT};
 use crate::http_server::HttpServer;
 use crate::runtime_manager::RuntimeManager;
-use crate::batch::{Batch, BatchDirection};
+use crate::batch::{decode_wire_data, max_payload_bytes, skip_bytes, split_wire_direction, wire_checksum, Batch, BatchDirection, WIRE_ACCEPTS_ZSTD, WIRE_VERSION};
 use crate::batch_history::BatchHistory;
+use crate::redact;
+
+/// Port of the HTTP status server when REPLICODE_HTTP_PORT is not set.
+pub const DEFAULT_HTTP_PORT: u16 = 8080;
+
+/// Interface of the HTTP status server when REPLICODE_HTTP_ADDR is not set.
+/// It is loopback whatever interface runtimes connect on, since the status
+/// shows every process's connections and mappings.
+pub const DEFAULT_HTTP_IP: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+/// How often a batch is cut when REPLICODE_BATCH_INTERVAL_MS is not set.
+pub const DEFAULT_BATCH_INTERVAL: Duration = Duration::from_millis(15);
+
+/// Size at which records waiting for the next batch have it cut early, when
+/// REPLICODE_BATCH_MAX_BYTES is not set.
+pub const DEFAULT_BATCH_MAX_BYTES: usize = 4 << 20;
+
+/// Records waiting for the next batch. Once they reach `cap` bytes the batch
+/// sender cuts a batch without waiting for the interval to end, and the NAT
+/// checker waits for it before adding more, so a flood of inbound traffic
+/// cannot grow them without bound.
+struct BatchBuffer {
+    records: Mutex<Vec<u8>>,
+    /// Signalled when the records reach the cap, and when a batch takes them
+    changed: Condvar,
+    cap: usize,
+}
+
+impl BatchBuffer {
+    fn new(cap: usize) -> Self {
+        BatchBuffer { records: Mutex::new(Vec::new()), changed: Condvar::new(), cap }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<u8>> {
+        self.records.lock().unwrap()
+    }
+
+    /// Appends `records`, which go into one batch together, first waiting
+    /// for a batch to take what is waiting if it is at the cap.
+    fn push(&self, records: &[u8]) {
+        let mut buf = self.changed.wait_while(self.lock(), |buf| buf.len() >= self.cap).unwrap();
+        buf.extend_from_slice(records);
+        if buf.len() >= self.cap {
+            self.changed.notify_all();
+        }
+    }
+
+    /// Waits until `deadline`, or until the records reach the cap. Returns
+    /// them locked, and whether the cap was reached first.
+    fn wait_for_batch(&self, deadline: Instant) -> (MutexGuard<'_, Vec<u8>>, bool) {
+        let mut buf = self.lock();
+        loop {
+            if buf.len() >= self.cap {
+                return (buf, true);
+            }
+            match deadline.checked_duration_since(Instant::now()) {
+                Some(wait) if !wait.is_zero() => buf = self.changed.wait_timeout(buf, wait).unwrap().0,
+                _ => return (buf, false),
+            }
+        }
+    }
+
+    /// Empties `buf` once its records are in a batch, and wakes a NAT checker
+    /// waiting for room.
+    fn take(&self, mut buf: MutexGuard<'_, Vec<u8>>) {
+        buf.clear();
+        drop(buf);
+        self.changed.notify_all();
+    }
+}
 
 pub struct TcpMode {
+    /// Time between batches; each batch's clock record advances the
+    /// runtimes' `GlobalClock` by exactly this much
+    batch_interval: Duration,
+    /// Where the HTTP status server listens
+    http_addr: SocketAddr,
     runtime_manager: RuntimeManager,
     nat_table: Arc<Mutex<NatTable>>,
-    shared_buffer: Arc<Mutex<Vec<u8>>>,
+    shared_buffer: Arc<BatchBuffer>,
     batch_history: Arc<Mutex<BatchHistory>>,
     executed_outgoing: Arc<Mutex<HashSet<u64>>>,
+    progress_reports: Arc<Mutex<HashMap<u64, Vec<u8>>>>,
+}
+
+/// Applies the BatchAck (15) records in a batch from `runtime_id`: the pid
+/// field of each holds the number of a batch it has applied. Returns whether
+/// the batch held nothing else, as a runtime sends one after every batch.
+fn apply_batch_acks(runtime_manager: &RuntimeManager, runtime_id: u64, batch_data: &[u8]) -> bool {
+    let records = read_records(batch_data).unwrap_or_default();
+    for (_, batch_number, _) in records.iter().filter(|(msg_type, _, _)| *msg_type == 15) {
+        runtime_manager.acknowledge(runtime_id, *batch_number);
+    }
+    records.iter().all(|(msg_type, _, _)| *msg_type == 15)
 }
 
 impl TcpMode {
-    pub fn new() -> io::Result<Self> {
+    /// Listens for runtimes on `listen_addr`, and serves HTTP on `http_addr`.
+    pub fn new(listen_addr: SocketAddr, http_addr: SocketAddr) -> io::Result<Self> {
         info!("Initializing TcpMode");
         
         // Initialize batch history first
@@ -36,18 +124,66 @@ impl TcpMode {
         let history_path = sessions_dir.join(format!("session-{}.bin", date));
         let batch_history: Arc<Mutex<BatchHistory>> = Arc::new(Mutex::new(BatchHistory::new(&history_path)?));
         
-        let runtime_manager = RuntimeManager::new("127.0.0.1:9000", Arc::clone(&batch_history))?;
-        let nat_table = Arc::new(Mutex::new(NatTable::new()));
-        let shared_buffer = Arc::new(Mutex::new(Vec::new()));
+        let runtime_manager = RuntimeManager::new(listen_addr, Arc::clone(&batch_history))?;
+        // Consensus ports never reach the runtime, so the base only matters for host
+        // firewalling; e.g. REPLICODE_NAT_PORT_BASE=20000
+        let mut nat = match std::env::var("REPLICODE_NAT_PORT_BASE").ok().and_then(|b| b.parse::<u16>().ok()) {
+            Some(base) => NatTable::with_port_base(base),
+            None => NatTable::new(),
+        };
+        // e.g. REPLICODE_BIND_ALLOW="0.0.0.0:8000,127.0.0.1:*" lets guests listen on those interfaces/ports
+        if let Ok(rules) = std::env::var("REPLICODE_BIND_ALLOW") {
+            nat.set_bind_allow_list(BindRule::parse_list(&rules));
+        }
+        // A `*` rule must not hand a guest the runtime or HTTP port; a free HTTP
+        // port (0) is only known once bound, and binding it fails anyway
+        let mut reserved = vec![runtime_manager.listener.local_addr()?.port()];
+        if http_addr.port() != 0 {
+            reserved.push(http_addr.port());
+        }
+        nat.set_reserved_ports(reserved);
+        // REPLICODE_MAX_SOCKETS_PER_PROCESS=0 removes the per-process listener/connection cap
+        if let Some(limit) = std::env::var("REPLICODE_MAX_SOCKETS_PER_PROCESS").ok().and_then(|l| l.parse::<usize>().ok()) {
+            nat.set_max_sockets_per_process(if limit == 0 { None } else { Some(limit) });
+        }
+        // REPLICODE_MAX_SOCKETS_TOTAL=0 removes the node-wide cap across all processes
+        if let Some(limit) = std::env::var("REPLICODE_MAX_SOCKETS_TOTAL").ok().and_then(|l| l.parse::<usize>().ok()) {
+            nat.set_max_sockets_total(if limit == 0 { None } else { Some(limit) });
+        }
+        // e.g. REPLICODE_NAT_POOL_SIZE=4 keeps up to 4 closed connections per destination
+        // for reuse, each for REPLICODE_NAT_POOL_IDLE_MS (default 30000)
+        if let Some(size) = std::env::var("REPLICODE_NAT_POOL_SIZE").ok().and_then(|s| s.parse::<usize>().ok()) {
+            let idle_timeout = std::env::var("REPLICODE_NAT_POOL_IDLE_MS").ok()
+                .and_then(|ms| ms.parse::<u64>().ok())
+                .map_or(DEFAULT_POOL_IDLE_TIMEOUT, Duration::from_millis);
+            nat.set_connection_pool(size, idle_timeout);
+        }
+        let nat_table = Arc::new(Mutex::new(nat));
+        // e.g. REPLICODE_BATCH_MAX_BYTES=65536 cuts a batch early once 64 KiB of records are waiting
+        let batch_max_bytes = std::env::var("REPLICODE_BATCH_MAX_BYTES").ok()
+            .and_then(|bytes| bytes.parse::<usize>().ok())
+            .filter(|&bytes| bytes > 0)
+            .unwrap_or(DEFAULT_BATCH_MAX_BYTES);
+        let shared_buffer = Arc::new(BatchBuffer::new(batch_max_bytes));
         let executed_outgoing = Arc::new(Mutex::new(HashSet::new()));
+        let progress_reports = Arc::new(Mutex::new(HashMap::new()));
+        // e.g. REPLICODE_BATCH_INTERVAL_MS=1000 cuts a batch, and advances the clock, once a second
+        let batch_interval = std::env::var("REPLICODE_BATCH_INTERVAL_MS").ok()
+            .and_then(|ms| ms.parse::<u64>().ok())
+            .filter(|&ms| ms > 0)
+            .map_or(DEFAULT_BATCH_INTERVAL, Duration::from_millis);
+        info!("Cutting a batch every {:?}, or once {} bytes of records are waiting", batch_interval, batch_max_bytes);
         
         info!("TcpMode initialized successfully");
         Ok(Self {
+            batch_interval,
+            http_addr,
             runtime_manager,
             nat_table,
             shared_buffer,
             batch_history,
             executed_outgoing,
+            progress_reports,
         })
     }
 
@@ -87,28 +223,40 @@ impl TcpMode {
         let buffer = Arc::clone(&self.shared_buffer);
         let runtime_manager = self.runtime_manager.clone();
         let batch_history: Arc<Mutex<BatchHistory>> = Arc::clone(&self.batch_history);
+        let interval = self.batch_interval;
         thread::spawn(move || {
             let mut batch_number = 0u64;
+            // Mirrors the runtimes' GlobalClock, which only moves on clock records
+            let mut logical_time = 0u64;
+            // Batches are cut on a fixed schedule rather than after a fixed
+            // sleep, so the clock records, one interval each, keep pace with
+            // real time however long cutting and broadcasting a batch takes.
+            // A batch cut early, at the size cap, advances the clock by the
+            // time since the last one, and the schedule starts again from it
+            let mut last_batch = Instant::now();
             info!("Batch sender thread started");
             loop {
-                thread::sleep(Duration::from_micros(15000));
-                let mut buf = buffer.lock().unwrap();
+                let (mut buf, early) = buffer.wait_for_batch(last_batch + interval);
+                let cut = if early { Instant::now() } else { last_batch + interval };
+                let advance = cut.saturating_duration_since(last_batch);
+                last_batch = cut;
                 batch_number += 1;
+                if early {
+                    info!("Cutting batch {} early: {} bytes of records are waiting", batch_number, buf.len());
+                }
                 debug!("Creating new batch {} with {} bytes", batch_number, buf.len());
                 
-                // Append clock record for 10 seconds
-                if let Ok(clock_record) = write_record(&Command::Clock(15_000_000)) {
+                // Append one clock record for the time it covers, even to an empty batch
+                if let Ok(clock_record) = write_record(&Command::Clock(advance.as_nanos() as u64)) {
                     buf.extend(clock_record);
-                    debug!("Added clock record for 10 seconds");
+                    debug!("Added clock record for {:?}", advance);
                 } else {
                     error!("Failed to create clock record");
                 }
 
-                let batch = Batch {
-                    number: batch_number,
-                    direction: BatchDirection::Incoming,
-                    data: buf.clone(),
-                };
+                let mut batch = Batch::new(batch_number, BatchDirection::Incoming, buf.clone());
+                logical_time += clock_advance(&buf);
+                batch.logical_time = Some(logical_time);
                 
                 // Save batch to history
                 if let Err(e) = batch_history.lock().unwrap().save_batch(&batch) {
@@ -117,7 +265,7 @@ impl TcpMode {
                 
                 info!("Broadcasting batch {} to all runtimes", batch.number);
                 runtime_manager.broadcast_batch(&batch);
-                buf.clear();
+                buffer.take(buf);
                 debug!("Batch {} broadcast complete, buffer cleared", batch_number);
             }
         });
@@ -131,10 +279,12 @@ impl TcpMode {
         let nat_table = Arc::clone(&self.nat_table);
         let shared_buffer = Arc::clone(&self.shared_buffer);
         let executed_outgoing = Arc::clone(&self.executed_outgoing);
+        let progress_reports = Arc::clone(&self.progress_reports);
+        let batch_history = Arc::clone(&self.batch_history);
         
         thread::spawn(move || {
             info!("Runtime reader thread started");
-            let mut last_processed_batch = 0u64;
+            let mut last_read_batch = 0u64;
             loop {
                 // Get list of runtime IDs
                 let runtime_ids: Vec<u64> = {
@@ -155,7 +305,9 @@ impl TcpMode {
 
                     if let Some(stream) = conn {
                         debug!("Reading from runtime {}", runtime_id);
-                        let mut reader = BufReader::new(stream);
+                        // Unbuffered: a BufReader dropped at the end of this pass would
+                        // take whatever of the next batch it had read ahead with it
+                        let mut reader = stream;
                         
                         // Read batch header (8 bytes for batch number, 1 byte for direction)
                         let mut batch_header = [0u8; 9];
@@ -167,23 +319,22 @@ impl TcpMode {
                             continue;
                         }
                         let batch_number = u64::from_le_bytes(batch_header[0..8].try_into().unwrap());
-                        let direction = batch_header[8];
-                        debug!("Received batch {} with direction {} from runtime {}", batch_number, direction, runtime_id);
-                        
-                        // Skip processing if batch number is less than or equal to last processed batch
-                        if batch_number <= last_processed_batch {
-                            debug!("Skipping batch {} (already processed up to {})", batch_number, last_processed_batch);
+                        let (version, direction, flags) = split_wire_direction(batch_header[8]);
+                        debug!("Received batch {} with direction {} (format {}) from runtime {}", batch_number, direction, version, runtime_id);
+                        if version > WIRE_VERSION {
+                            // Its framing is unknown, so nothing after it can be read
+                            error!("Runtime {} sent batch {} in unknown format {}; dropping it", runtime_id, batch_number, version);
+                            runtime_manager.runtimes.lock().unwrap().remove(&runtime_id);
                             continue;
                         }
-                        last_processed_batch = batch_number;
-
-                        // For outgoing batches, check if we've already executed this batch number
-                        if direction == 1 {  // Outgoing batch
-                            let mut done = executed_outgoing.lock().unwrap();
-                            if !done.insert(batch_number) {
-                                debug!("Duplicate outgoing batch {} – skipping", batch_number);
+                        // From format 2 on, the runtime's clock follows; consensus keeps its own
+                        if version >= 2 {
+                            let mut clock_buf = [0u8; 8];
+                            if reader.read_exact(&mut clock_buf).is_err() {
+                                error!("Failed to read batch clock from runtime {}", runtime_id);
                                 continue;
                             }
+                            debug!("Runtime {} sent batch {} at clock {}", runtime_id, batch_number, u64::from_le_bytes(clock_buf));
                         }
 
                         // Read batch data length (8 bytes)
@@ -192,9 +343,23 @@ impl TcpMode {
                             error!("Failed to read batch data length from runtime {}", runtime_id);
                             continue;
                         }
-                        let data_len = u64::from_le_bytes(data_len_buf) as usize;
+                        let data_len = u64::from_le_bytes(data_len_buf);
                         debug!("Reading {} bytes of batch data from runtime {}", data_len, runtime_id);
 
+                        // A length past the limit is never allocated; the batch is skipped,
+                        // so the next one is read from where it starts
+                        let max_len = max_payload_bytes();
+                        if data_len > max_len {
+                            error!("Batch {} from runtime {} is {} bytes, over the {}-byte limit; skipping it",
+                                batch_number, runtime_id, data_len, max_len);
+                            let crc_len = if version >= 1 { 4 } else { 0 };
+                            if skip_bytes(&mut reader, data_len.saturating_add(crc_len)).is_err() {
+                                error!("Failed to skip batch {} from runtime {}", batch_number, runtime_id);
+                            }
+                            continue;
+                        }
+                        let data_len = data_len as usize;
+
                         // Read the batch data
                         let mut batch_data = vec![0u8; data_len];
                         if reader.read_exact(&mut batch_data).is_err() {
@@ -202,6 +367,62 @@ impl TcpMode {
                             continue;
                         }
 
+                        // From format 1 on, the data is followed by its CRC32
+                        if version >= 1 {
+                            let mut crc_buf = [0u8; 4];
+                            if reader.read_exact(&mut crc_buf).is_err() {
+                                error!("Failed to read batch checksum from runtime {}", runtime_id);
+                                continue;
+                            }
+                            if u32::from_le_bytes(crc_buf) != wire_checksum(&batch_data) {
+                                error!("Batch {} from runtime {} failed its checksum; skipping it", batch_number, runtime_id);
+                                continue;
+                            }
+                        }
+
+                        let batch_data = match decode_wire_data(flags, batch_data) {
+                            Ok(batch_data) => batch_data,
+                            Err(e) => {
+                                error!("Failed to decompress batch {} from runtime {}: {}; skipping it", batch_number, runtime_id, e);
+                                continue;
+                            }
+                        };
+                        // Batches to the runtime follow the format it last sent in
+                        if let Some(conn) = runtime_manager.runtimes.lock().unwrap().get_mut(&runtime_id) {
+                            conn.wire_version = version;
+                            conn.compress = flags & WIRE_ACCEPTS_ZSTD != 0;
+                        }
+
+                        // Acks are for this runtime alone, so they are taken before the
+                        // batch is skipped as one another runtime already sent
+                        let acks_only = apply_batch_acks(&runtime_manager, runtime_id, &batch_data);
+
+                        // Skip processing if batch number is less than or equal to last processed batch.
+                        // The whole batch has been read first, so the next one starts where expected
+                        if batch_number <= last_read_batch {
+                            debug!("Skipping batch {} (already processed up to {})", batch_number, last_read_batch);
+                            continue;
+                        }
+                        last_read_batch = batch_number;
+
+                        // For outgoing batches, check if we've already executed this batch number
+                        if direction == 1 {  // Outgoing batch
+                            let mut done = executed_outgoing.lock().unwrap();
+                            if !done.insert(batch_number) {
+                                debug!("Duplicate outgoing batch {} – skipping", batch_number);
+                                continue;
+                            }
+                        }
+
+                        // Keep outgoing batches in the session history alongside incoming
+                        // ones, except those that only acknowledge batches
+                        if direction == 1 && !acks_only {
+                            let batch = Batch::new(batch_number, BatchDirection::Outgoing, batch_data.clone());
+                            if let Err(e) = batch_history.lock().unwrap().save_batch(&batch) {
+                                error!("Failed to save outgoing batch {} to history: {}", batch_number, e);
+                            }
+                        }
+
                         // Process the batch data as a series of records
                         let mut data_reader = std::io::Cursor::new(batch_data);
                         loop {
@@ -214,124 +435,193 @@ impl TcpMode {
                             let msg_type = msg_type_buf[0];
                             debug!("Processing record type {} in batch {} from runtime {}", msg_type, batch_number, runtime_id);
                             
-                            // If it's a NetworkOut message (type 5)
-                            if msg_type == 5 {
-                                debug!("Processing NetworkOut message from runtime {}", runtime_id);
-                                // Read process ID (8 bytes)
-                                let mut pid_buf = [0u8; 8];
-                                if data_reader.read_exact(&mut pid_buf).is_err() {
-                                    error!("Failed to read process ID from runtime {}", runtime_id);
-                                    break;
-                                }
-                                let pid = u64::from_le_bytes(pid_buf);
-                                debug!("NetworkOut message for process {}", pid);
-                                
-                                // Read payload length (4 bytes)
-                                let mut len_buf = [0u8; 4];
-                                if data_reader.read_exact(&mut len_buf).is_err() {
-                                    error!("Failed to read payload length from runtime {}", runtime_id);
-                                    break;
-                                }
-                                let payload_len = u32::from_le_bytes(len_buf) as usize;
-                                debug!("Reading {} bytes of payload", payload_len);
-                                
-                                // Read payload
-                                let mut payload = vec![0u8; payload_len];
-                                if data_reader.read_exact(&mut payload).is_err() {
-                                    error!("Failed to read payload from runtime {}", runtime_id);
-                                    break;
-                                }
-                                
-                                // Handle network operation
-                                if let Ok(op) = bincode::deserialize::<NetworkOperation>(&payload) {
-                                    info!("Processing network operation from runtime {}: {:?}", runtime_id, op);
-                                    let (src_port, new_port, is_accept, _is_recv) = match &op {
-                                        NetworkOperation::Connect { src_port, .. } => (*src_port, 0, false, false),
-                                        NetworkOperation::Send { src_port, .. } => (*src_port, 0, false, false),
-                                        NetworkOperation::Listen { src_port } => (*src_port, 0, false, false),
-                                        NetworkOperation::Accept { src_port, new_port, .. } => (*src_port, *new_port, true, false),
-                                        NetworkOperation::Close { src_port } => (*src_port, 0, false, false),
-                                        NetworkOperation::Recv { src_port } => (*src_port, 0, false, true),
-                                    };
-
-                                    // Process the network operation
-                                    let mut nat_table = nat_table.lock().unwrap();
-                                    let mut messages = Vec::new();
-                                    let status: u8 = match nat_table.handle_network_operation(pid, op.clone(), &mut messages) {
-                                        Ok(success) => {
-                                            if !success {
-                                                0  // Return status 0 for failure
-                                            } else {
-                                                // Check if operation is waiting
-                                                let is_waiting = match &op {
-                                                    NetworkOperation::Accept { src_port, .. } => nat_table.is_waiting_for_accept(pid, *src_port),
-                                                    NetworkOperation::Recv { src_port } => nat_table.is_waiting_for_recv(pid, *src_port),
-                                                    _ => false
-                                                };
-                                                
-                                                if is_waiting {
-                                                    debug!("Operation is waiting for process {}:{}", pid, src_port);
-                                                    2 // Return status 2 for waiting
-                                                } else {
-                                                    1 // Return status 1 for success
-                                                }
+                            // Read process ID (8 bytes)
+                            let mut pid_buf = [0u8; 8];
+                            if data_reader.read_exact(&mut pid_buf).is_err() {
+                                error!("Failed to read process ID from runtime {}", runtime_id);
+                                break;
+                            }
+                            let pid = u64::from_le_bytes(pid_buf);
+                            debug!("Record type {} for process {}", msg_type, pid);
+                            
+                            // Read payload length (4 bytes)
+                            let mut len_buf = [0u8; 4];
+                            if data_reader.read_exact(&mut len_buf).is_err() {
+                                error!("Failed to read payload length from runtime {}", runtime_id);
+                                break;
+                            }
+                            let payload_len = u32::from_le_bytes(len_buf) as usize;
+                            debug!("Reading {} bytes of payload", payload_len);
+
+                            // A length past the end of the batch is not allocated
+                            let remaining = data_reader.get_ref().len() as u64 - data_reader.position();
+                            if payload_len as u64 > remaining {
+                                error!("Record in batch {} from runtime {} claims {} bytes, but only {} are left; dropping the rest of the batch",
+                                    batch_number, runtime_id, payload_len, remaining);
+                                break;
+                            }
+                            
+                            // Read payload
+                            let mut payload = vec![0u8; payload_len];
+                            if data_reader.read_exact(&mut payload).is_err() {
+                                error!("Failed to read payload from runtime {}", runtime_id);
+                                break;
+                            }
+                            
+                            match msg_type {
+                                5 => { // NetworkOut
+                                    debug!("Processing NetworkOut message from runtime {}", runtime_id);
+                                    // Handle network operation
+                                    if let Ok(op) = bincode::deserialize::<NetworkOperation>(&payload) {
+                                        info!("Processing network operation from runtime {}: {:?}", runtime_id, op);
+                                        let (src_port, new_port, is_accept, _is_recv) = match &op {
+                                            NetworkOperation::Connect { src_port, .. } => (*src_port, 0, false, false),
+                                            NetworkOperation::Send { src_port, .. } => (*src_port, 0, false, false),
+                                            NetworkOperation::Listen { src_port, .. } => (*src_port, 0, false, false),
+                                            NetworkOperation::Accept { src_port, new_port, .. } => (*src_port, *new_port, true, false),
+                                            NetworkOperation::Close { src_port } => (*src_port, 0, false, false),
+                                            NetworkOperation::Recv { src_port } => (*src_port, 0, false, true),
+                                            NetworkOperation::Bind { src_port, .. } => (*src_port, 0, false, false),
+                                            NetworkOperation::SendTo { src_port, .. } => (*src_port, 0, false, false),
+                                            NetworkOperation::RecvFrom { src_port } => (*src_port, 0, false, true),
+                                        };
+
+                                        // Process the network operation. A connect dials with the table
+                                        // unlocked, and one cancelled meanwhile gets no status (see `nat::dial`)
+                                        let mut messages = Vec::new();
+                                        let (outcome, mut nat_table) = match &op {
+                                            NetworkOperation::Connect { dest_addr, dest_port, src_port } => {
+                                                let outcome = nat::dial(&nat_table, pid, *src_port, dest_addr, *dest_port);
+                                                (outcome, nat_table.lock().unwrap())
                                             }
-                                        },
-                                        Err(e) => {
-                                            error!("Failed to handle network operation: {}", e);
-                                            0
-                                        }
-                                    };
-
-                                    // Process any messages returned from the operation
-                                    let mut buf = shared_buffer.lock().unwrap();
-                                    for (msg_pid, msg_port, msg_data, is_connection) in messages {
-                                        if is_connection {
-                                            // Get the new port from the NAT table
-                                            let new_port = nat_table.get_waiting_port(msg_pid, msg_port)
-                                                .unwrap_or_else(|| {
-                                                    error!("1, No waiting accept entry found for {}:{}", msg_pid, msg_port);
-                                                    msg_port + 1  // Fallback to old behavior if entry not found
-                                                });
-
-                                            if let Ok(record) = write_record(&Command::NetworkIn(msg_pid, 0, vec![
-                                                1,  // Success status
-                                                msg_port as u8, (msg_port >> 8) as u8,  // Listening port
-                                                new_port as u8, (new_port >> 8) as u8  // New port from NAT table
-                                            ])) {
-                                                buf.extend(record);
-                                                info!("Added connection notification for process {}:{} -> {}", msg_pid, msg_port, new_port);
-                                                // Clear the waiting state after successfully processing the notification
-                                                nat_table.clear_waiting_accept(msg_pid, msg_port);
+                                            _ => {
+                                                let mut table = nat_table.lock().unwrap();
+                                                let outcome = table.handle_network_operation(pid, op.clone(), &mut messages);
+                                                (Some(outcome), table)
                                             }
-                                        } else if !msg_data.is_empty() {
-                                            debug!("Adding {} bytes of data for process {}:{}", msg_data.len(), msg_pid, msg_port);
-                                            if let Ok(record) = write_record(&Command::NetworkIn(msg_pid, msg_port, msg_data)) {
-                                                buf.extend(record);
+                                        };
+                                        let Some(outcome) = outcome else {
+                                            continue;
+                                        };
+                                        let code = match outcome {
+                                            Ok(success) => {
+                                                if !success {
+                                                    NetworkStatusCode::Failed
+                                                } else {
+                                                    // Check if operation is waiting
+                                                    let is_waiting = match &op {
+                                                        NetworkOperation::Accept { src_port, .. } => nat_table.is_waiting_for_accept(pid, *src_port),
+                                                        NetworkOperation::Recv { src_port } | NetworkOperation::RecvFrom { src_port } => nat_table.is_waiting_for_recv(pid, *src_port),
+                                                        _ => false
+                                                    };
+                                                    
+                                                    if is_waiting {
+                                                        debug!("Operation is waiting for process {}:{}", pid, src_port);
+                                                        NetworkStatusCode::Waiting
+                                                    } else {
+                                                        NetworkStatusCode::Success
+                                                    }
+                                                }
+                                            },
+                                            Err(e) => {
+                                                error!("Failed to handle network operation: {}", e);
+                                                NetworkStatusCode::Failed
                                             }
-                                            if let Ok(record) = write_record(&Command::NetworkIn(msg_pid, 0, vec![
-                                                1,  // Success status
-                                                msg_port as u8, (msg_port >> 8) as u8,  // Source port
-                                                0, 0  // No new port for recv
-                                            ])) {
-                                                buf.extend(record);
+                                        };
+
+                                        // Process any messages returned from the operation; the records
+                                        // go into the next batch together, through the capped buffer
+                                        let mut buf = Vec::new();
+                                        for (msg_pid, msg_port, msg_data, is_connection) in messages {
+                                            if is_connection {
+                                                // Get the new port from the NAT table
+                                                let new_port = nat_table.get_waiting_port(msg_pid, msg_port)
+                                                    .unwrap_or_else(|| {
+                                                        error!("1, No waiting accept entry found for {}:{}", msg_pid, msg_port);
+                                                        msg_port + 1  // Fallback to old behavior if entry not found
+                                                    });
+
+                                                let status = NetworkStatus {
+                                                    code: NetworkStatusCode::Success,
+                                                    src_port: msg_port, // Listening port
+                                                    new_port,
+                                                    peer: nat_table.peer_addr(msg_pid, new_port).map(|p| p.to_string()),
+                                                };
+                                                if let Ok(record) = write_record(&Command::NetworkIn(msg_pid, 0, status.encode())) {
+                                                    buf.extend(record);
+                                                    info!("Added connection notification for process {}:{} -> {}", msg_pid, msg_port, new_port);
+                                                    // Clear the waiting state after successfully processing the notification
+                                                    nat_table.clear_waiting_accept(msg_pid, msg_port);
+                                                }
+                                            } else if !msg_data.is_empty() {
+                                                debug!("Adding {} bytes of data for process {}:{}", msg_data.len(), msg_pid, msg_port);
+                                                if let Ok(record) = write_record(&Command::NetworkIn(msg_pid, msg_port, msg_data)) {
+                                                    buf.extend(record);
+                                                }
+                                                // A datagram's status carries its sender, for sock_recv_from
+                                                let status = NetworkStatus {
+                                                    peer: nat_table.datagram_source(msg_pid, msg_port).map(|p| p.to_string()),
+                                                    ..NetworkStatus::new(NetworkStatusCode::Success, msg_port)
+                                                };
+                                                if let Ok(record) = write_record(&Command::NetworkIn(msg_pid, 0, status.encode())) {
+                                                    buf.extend(record);
+                                                }
                                             }
                                         }
-                                    }
 
-                                    // Add success/failure message to batch
-                                    if let Ok(record) = write_record(&Command::NetworkIn(pid, 0, vec![
-                                        status,  // Use the computed status code
-                                        src_port as u8, (src_port >> 8) as u8,  // Source port
-                                        if is_accept { new_port as u8 } else { 0 },  // New port for accept
-                                        if is_accept { (new_port >> 8) as u8 } else { 0 }  // New port high byte
-                                    ])) {
-                                        buf.extend(record);
-                                        info!("Added network operation result for process {}:{} (status: {})", 
-                                            pid, src_port, status);
+                                        // Add success/failure message to batch, with the remote address of a new connection
+                                        let succeeded = code == NetworkStatusCode::Success;
+                                        let peer = match &op {
+                                            NetworkOperation::Connect { .. } if succeeded => nat_table.peer_addr(pid, src_port),
+                                            NetworkOperation::Accept { .. } if succeeded => nat_table.peer_addr(pid, new_port),
+                                            _ => None,
+                                        };
+                                        let status = NetworkStatus {
+                                            code,
+                                            src_port,
+                                            new_port: if is_accept { new_port } else { 0 },
+                                            peer: peer.map(|p| p.to_string()),
+                                        };
+                                        if let Ok(record) = write_record(&Command::NetworkIn(pid, 0, status.encode())) {
+                                            buf.extend(record);
+                                            info!("Added network operation result for process {}:{} (status: {:?})", 
+                                                pid, src_port, code);
+                                        }
+                                        // Waiting for room must not hold up the NAT checker
+                                        drop(nat_table);
+                                        shared_buffer.push(&buf);
+                                    } else {
+                                        error!("Failed to deserialize network operation from runtime {}", runtime_id);
                                     }
-                                } else {
-                                    error!("Failed to deserialize network operation from runtime {}", runtime_id);
+                                }
+                                6 => { // ProgressReport
+                                    info!("Progress report from process {}: {}", pid, redact::payload(&payload));
+                                    progress_reports.lock().unwrap().insert(pid, payload);
+                                }
+                                1 => { // An FD update the runtime refused
+                                    error!("Runtime {} refused FD update for process {}: {}", runtime_id, pid, String::from_utf8_lossy(&payload));
+                                }
+                                2 => { // An Init whose process could not be started
+                                    error!("Runtime {} could not start process {}: {}", runtime_id, pid, String::from_utf8_lossy(&payload));
+                                }
+                                14 => { // ProcessExit: "code:<n>" or "error:<reason>"
+                                    info!("Process {} on runtime {} ended: {}", pid, runtime_id, String::from_utf8_lossy(&payload));
+                                }
+                                15 => {} // BatchAck, applied by apply_batch_acks
+                                12 => { // BatchRejected: the pid field holds the batch number
+                                    error!("Runtime {} rejected batch {}: {}", runtime_id, pid, String::from_utf8_lossy(&payload));
+                                }
+                                11 => { // InitN: the first pid and how many were assigned
+                                    match payload.as_slice().try_into() {
+                                        Ok(count) => {
+                                            let count = u32::from_le_bytes(count) as u64;
+                                            info!("Runtime {} assigned pids {}..={} for InitN", runtime_id, pid, pid + count - 1);
+                                        }
+                                        Err(_) => error!("Invalid InitN report of {} bytes from runtime {}", payload.len(), runtime_id),
+                                    }
+                                }
+                                _ => {
+                                    warn!("Unknown record type {} in batch {} from runtime {}", msg_type, batch_number, runtime_id);
                                 }
                             }
                         }
@@ -357,10 +647,11 @@ impl TcpMode {
                 let messages = nat_table.lock().unwrap().check_for_incoming_data();
                 if !messages.is_empty() {
                     debug!("Processing {} NAT messages", messages.len());
-                    let mut buf = shared_buffer.lock().unwrap();
                     for (pid, port, data, is_connection) in messages {
                         debug!("Processing NAT message for process {}:{} (connection: {})", 
                             pid, port, is_connection);
+                        // The records of one message go into the same batch
+                        let mut records = Vec::new();
                         if is_connection {
                             // Get the new port from the NAT table
                             let new_port = nat_table.lock().unwrap().get_waiting_port(pid, port)
@@ -369,12 +660,14 @@ impl TcpMode {
                                     port + 1  // Fallback to old behavior if entry not found
                                 });
 
-                            if let Ok(record) = write_record(&Command::NetworkIn(pid, 0, vec![
-                                1,  // Success status
-                                port as u8, (port >> 8) as u8,  // Listening port
-                                new_port as u8, (new_port >> 8) as u8  // New port from NAT table
-                            ])) {
-                                buf.extend(record);
+                            let status = NetworkStatus {
+                                code: NetworkStatusCode::Success,
+                                src_port: port, // Listening port
+                                new_port,
+                                peer: nat_table.lock().unwrap().peer_addr(pid, new_port).map(|p| p.to_string()),
+                            };
+                            if let Ok(record) = write_record(&Command::NetworkIn(pid, 0, status.encode())) {
+                                records.extend(record);
                                 info!("Added connection notification for process {}:{} -> {}", pid, port, new_port);
                                 // Clear the waiting state after successfully processing the notification
                                 nat_table.lock().unwrap().clear_waiting_accept(pid, port);
@@ -382,16 +675,18 @@ impl TcpMode {
                         } else if !data.is_empty() {
                             debug!("Adding {} bytes of data for process {}:{}", data.len(), pid, port);
                             if let Ok(record) = write_record(&Command::NetworkIn(pid, port, data)) {
-                                buf.extend(record);
+                                records.extend(record);
                             }
-                            if let Ok(record) = write_record(&Command::NetworkIn(pid, 0, vec![
-                                1,  // Success status
-                                port as u8, (port >> 8) as u8,  // Source port
-                                0, 0  // No new port for recv
-                            ])) {
-                                buf.extend(record);
+                            // A datagram's status carries its sender, for sock_recv_from
+                            let status = NetworkStatus {
+                                peer: nat_table.lock().unwrap().datagram_source(pid, port).map(|p| p.to_string()),
+                                ..NetworkStatus::new(NetworkStatusCode::Success, port)
+                            };
+                            if let Ok(record) = write_record(&Command::NetworkIn(pid, 0, status.encode())) {
+                                records.extend(record);
                             }
                         }
+                        shared_buffer.push(&records);
                     }
                 }
             }
@@ -403,22 +698,27 @@ impl TcpMode {
 
     fn start_http_server(&self) -> io::Result<()> {
         debug!("Initializing HTTP server");
-        let http_server = HttpServer::new(Arc::clone(&self.nat_table));
+        let http_server = HttpServer::new(
+            Arc::clone(&self.nat_table),
+            Arc::clone(&self.progress_reports),
+            Arc::clone(&self.batch_history),
+        );
+        let addr = self.http_addr;
         thread::spawn(move || {
             info!("HTTP server thread started");
-            if let Err(e) = http_server.start(8080) {
+            if let Err(e) = http_server.start(addr) {
                 error!("HTTP server error: {}", e);
             }
             warn!("HTTP server thread ended unexpectedly");
         });
-        info!("HTTP status server started on port 8080");
+        info!("HTTP status server started on {}", addr);
         Ok(())
     }
 
     fn run_command_loop(&self) -> io::Result<()> {
         info!("Starting command loop");
         loop {
-            eprint!("Command (init <wasm_file> | msg <pid> <message>): ");
+            eprint!("Command (init <wasm_file> | initn <count> <wasm_file> | msg <pid> <message>): ");
             io::stderr().flush()?;
             let mut input = String::new();
             io::stdin().read_line(&mut input)?;
@@ -426,16 +726,21 @@ impl TcpMode {
             
             if input.eq_ignore_ascii_case("exit") {
                 info!("Received exit command");
+                self.runtime_manager.disconnect_all();
                 break;
             }
             
             debug!("Processing command: {}", input);
             if let Some(cmd) = parse_command(input) {
                 //info!("Parsed command: {:?}", cmd);
+                if let Command::CancelNetwork(pid) = cmd {
+                    // Stop waiting on the host side too, so no late result is sent
+                    let cancelled = self.nat_table.lock().unwrap().cancel_pending(pid);
+                    info!("Cancelled {} pending network operations of process {}", cancelled, pid);
+                }
                 if let Ok(record) = write_record(&cmd) {
                     debug!("Writing command record ({} bytes)", record.len());
-                    let mut buf = self.shared_buffer.lock().unwrap();
-                    buf.extend(record);
+                    self.shared_buffer.push(&record);
                     info!("Command added to shared buffer");
                 } else {
                     error!("Failed to write command record");
@@ -450,8 +755,8 @@ impl TcpMode {
     }
 }
 
-pub fn run_tcp_mode() -> io::Result<()> {
+pub fn run_tcp_mode(listen_addr: SocketAddr, http_addr: SocketAddr) -> io::Result<()> {
     info!("Starting TCP mode");
-    let tcp_mode = TcpMode::new()?;
+    let tcp_mode = TcpMode::new(listen_addr, http_addr)?;
     tcp_mode.run()
 } 
\ No newline at end of file
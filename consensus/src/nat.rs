@@ -1,8 +1,12 @@
-use std::collections::HashMap;
-use std::net::{TcpStream, TcpListener};
-use std::io::{Write, Read};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpStream, TcpListener, UdpSocket};
+use std::io::{self, Write, Read};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
 use log::{info, error, debug};
 use crate::commands::NetworkOperation;
+use crate::redact;
 use serde_json::json;
 
 #[allow(dead_code)]
@@ -12,8 +16,70 @@ pub struct NatEntry {
     pub consensus_port: u16,
     pub connection: TcpStream,
     pub buffer: Vec<u8>,  // Add buffer for received data
+    pub pending_send: Vec<u8>, // Bytes accepted from the process but not yet written to the socket
+    pub destination: Option<String>, // "addr:port" a Connect went to; only these may be pooled
 }
 
+impl NatEntry {
+    /// Queues `data` behind any unsent bytes and writes as much as the
+    /// non-blocking socket accepts. The remainder is retried from
+    /// `NatTable::check_for_incoming_data` once the peer drains its side.
+    fn queue_send(&mut self, data: &[u8]) -> io::Result<()> {
+        self.pending_send.extend_from_slice(data);
+        self.flush_pending()
+    }
+
+    /// Writes buffered bytes until the socket would block or the buffer is empty.
+    fn flush_pending(&mut self) -> io::Result<()> {
+        while !self.pending_send.is_empty() {
+            match self.connection.write(&self.pending_send) {
+                Ok(0) => return Err(io::Error::new(io::ErrorKind::WriteZero, "peer stopped accepting data")),
+                Ok(n) => {
+                    self.pending_send.drain(..n);
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    debug!("Socket for {}:{} is backpressured, {} bytes pending",
+                        self.process_id, self.process_port, self.pending_send.len());
+                    break;
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    /// Best-effort blocking drain of unsent bytes, then shuts the socket down.
+    /// With bytes pending this can block for up to a second, so it runs on its
+    /// own thread instead of under the NAT table's lock.
+    fn close(mut self) {
+        if self.pending_send.is_empty() {
+            self.shutdown();
+            return;
+        }
+        thread::spawn(move || {
+            let pending = std::mem::take(&mut self.pending_send);
+            let result = self.connection.set_nonblocking(false)
+                .and_then(|_| self.connection.set_write_timeout(Some(Duration::from_secs(1))))
+                .and_then(|_| self.connection.write_all(&pending));
+            if let Err(e) = result {
+                error!("Dropped {} unsent bytes for {}:{} on close: {}",
+                    pending.len(), self.process_id, self.process_port, e);
+            }
+            self.shutdown();
+        });
+    }
+
+    fn shutdown(&self) {
+        if let Err(e) = self.connection.shutdown(std::net::Shutdown::Both) {
+            error!("Failed to shutdown socket: {}", e);
+        }
+    }
+}
+
+/// A host listener for a guest's listening socket. Connections are only
+/// accepted from it while the process waits in accept, one per accept, so
+/// the rest stay in the kernel's queue, which holds at most `backlog`.
 #[allow(dead_code)]
 pub struct NatListener {
     pub process_id: u64,
@@ -21,30 +87,409 @@ pub struct NatListener {
     pub consensus_port: u16,
     pub listener: TcpListener,
     pub pending_accepts: Vec<TcpStream>,
+    pub backlog: u32,
+}
+
+/// Largest backlog a guest may ask for; also what the standard library
+/// listens with.
+pub const MAX_BACKLOG: u32 = 128;
+
+/// Sets how many connections the kernel queues for `listener` until they are
+/// accepted, within 1..=`MAX_BACKLOG`. Calling listen() again on a listening
+/// socket only changes its backlog.
+#[cfg(unix)]
+fn set_backlog(listener: &TcpListener, backlog: u32) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+    let backlog = backlog.clamp(1, MAX_BACKLOG) as libc::c_int;
+    // SAFETY: the descriptor is owned by `listener`, which outlives the call
+    if unsafe { libc::listen(listener.as_raw_fd(), backlog) } == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+#[cfg(not(unix))]
+fn set_backlog(_listener: &TcpListener, _backlog: u32) -> io::Result<()> {
+    Ok(())
+}
+
+/// `ip:port` as `TcpStream::connect` parses it, with an IPv6 address in
+/// brackets (`[::1]:8080`).
+pub fn host_port(ip: &str, port: u16) -> String {
+    if ip.contains(':') {
+        format!("[{}]:{}", ip, port)
+    } else {
+        format!("{}:{}", ip, port)
+    }
+}
+
+/// The host socket behind a guest datagram socket. Datagrams are read from
+/// it as they arrive and handed to the process one per RecvFrom.
+pub struct NatDatagramEntry {
+    pub process_id: u64,
+    pub process_port: u16,
+    pub socket: UdpSocket,
+    pub received: VecDeque<(SocketAddr, Vec<u8>)>, // arrived, not yet delivered
+    pub last_source: Option<SocketAddr>, // sender of the datagram delivered last
+}
+
+/// Datagrams kept per socket until the process receives them; later ones
+/// are dropped, as a full socket buffer would.
+pub const MAX_QUEUED_DATAGRAMS: usize = 64;
+
+/// Largest datagram read from a host socket (the UDP maximum payload).
+const MAX_DATAGRAM_LEN: usize = 65507;
+
+impl NatDatagramEntry {
+    /// Queues every datagram waiting on the socket.
+    fn read_available(&mut self) -> io::Result<()> {
+        let mut buf = vec![0u8; MAX_DATAGRAM_LEN];
+        loop {
+            match self.socket.recv_from(&mut buf) {
+                // An empty datagram would be an empty NetworkIn record, which carries nothing
+                Ok((0, source)) => debug!("Dropped empty datagram from {} for {}:{}", source, self.process_id, self.process_port),
+                Ok((n, source)) if self.received.len() < MAX_QUEUED_DATAGRAMS => {
+                    self.received.push_back((source, buf[..n].to_vec()));
+                }
+                Ok((n, source)) => debug!("Dropped {}-byte datagram from {} for {}:{}, queue full",
+                    n, source, self.process_id, self.process_port),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+/// What `NatTable::begin_connect` left to do for a Connect.
+pub enum ConnectStep {
+    /// Answered already: refused at a socket limit, or served from the pool.
+    Done(bool),
+    /// Connect to this "addr:port", then hand the result to `finish_connect`.
+    Dial(String),
+}
+
+/// Handles a Connect like `NatTable::handle_network_operation`, but dials
+/// with the table unlocked, so a host that does not answer holds up neither
+/// other processes' operations nor a cancelnetwork. Returns None if the
+/// process's network operations were cancelled meanwhile: the guest has
+/// already failed the call, so no status may be sent for it.
+pub fn dial(
+    nat: &Mutex<NatTable>,
+    pid: u64,
+    src_port: u16,
+    dest_addr: &str,
+    dest_port: u16,
+) -> Option<Result<bool, Box<dyn std::error::Error>>> {
+    let addr = match nat.lock().unwrap().begin_connect(pid, src_port, dest_addr, dest_port) {
+        ConnectStep::Done(connected) => return Some(Ok(connected)),
+        ConnectStep::Dial(addr) => addr,
+    };
+    let connected = TcpStream::connect(&addr);
+    match nat.lock().unwrap().finish_connect(pid, src_port, &addr, connected)? {
+        Ok(()) => Some(Ok(true)),
+        Err(e) => Some(Err(Box::new(e))),
+    }
+}
+
+/// A connection a process closed, kept open for the next Connect to the
+/// same destination.
+struct PooledConnection {
+    connection: TcpStream,
+    idle_since: Instant,
+}
+
+/// How long a pooled connection may sit idle when none is configured.
+pub const DEFAULT_POOL_IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// An interface/port a guest listener is allowed to bind, e.g. `0.0.0.0:8080`
+/// or `127.0.0.1:*` (any port).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BindRule {
+    pub ip: IpAddr,
+    pub port: Option<u16>, // None = any port
+}
+
+impl BindRule {
+    pub fn parse(rule: &str) -> Option<BindRule> {
+        let (ip, port) = rule.trim().rsplit_once(':')?;
+        let ip = ip.trim_start_matches('[').trim_end_matches(']').parse().ok()?;
+        let port = if port == "*" { None } else { Some(port.parse().ok()?) };
+        Some(BindRule { ip, port })
+    }
+
+    /// Parses a comma-separated list of rules, skipping (and logging) invalid entries.
+    pub fn parse_list(rules: &str) -> Vec<BindRule> {
+        rules.split(',')
+            .filter(|r| !r.trim().is_empty())
+            .filter_map(|r| {
+                let rule = BindRule::parse(r);
+                if rule.is_none() {
+                    error!("Ignoring invalid bind rule: {}", r);
+                }
+                rule
+            })
+            .collect()
+    }
+
+    fn matches(&self, ip: IpAddr, port: u16) -> bool {
+        self.ip == ip && self.port.is_none_or(|p| p == port)
+    }
 }
 
+/// First consensus port handed out when none is configured.
+pub const DEFAULT_PORT_BASE: u16 = 10000;
+
+/// Default cap on simultaneous listeners plus connections per process.
+pub const DEFAULT_MAX_SOCKETS_PER_PROCESS: usize = 64;
+
+/// Default cap on simultaneous listeners plus connections across all processes.
+pub const DEFAULT_MAX_SOCKETS_TOTAL: usize = 1024;
+
+/// Maps guest-visible `(pid, port)` pairs onto real host sockets.
+///
+/// Consensus ports are a host-side detail: they depend on allocation order and
+/// on `port_base`, so nothing returned to the runtime may contain one. Messages
+/// carry only the guest's own `src_port`/`new_port`, and are ordered by
+/// `(pid, port)` rather than by consensus port, so two tables with different
+/// allocation sequences produce the same records.
 pub struct NatTable {
     port_mappings: HashMap<u16, NatEntry>, // consensus_port -> entry
     process_ports: HashMap<(u64, u16), u16>, // (pid, process_port) -> consensus_port
     listeners: HashMap<(u64, u16), NatListener>, // (pid, process_port) -> listener
     connections: HashMap<(u64, u16), u16>, // (pid, process_port) -> connection_consensus_port
+    datagram_sockets: HashMap<(u64, u16), NatDatagramEntry>, // (pid, process_port) -> UDP socket
     next_port: u16,
     waiting_accepts: HashMap<(u64, u16), u16>, // (pid, src_port) -> requested new_port
     waiting_recvs: HashMap<(u64, u16), bool>, // (pid, src_port) -> is_waiting
+    connecting: HashSet<(u64, u16)>, // (pid, src_port) of connects being dialed
+    bind_allow_list: Vec<BindRule>, // where guests may explicitly bind listeners
+    reserved_ports: Vec<u16>, // consensus's own ports, which `*` rules never grant
+    max_sockets_per_process: Option<usize>, // None = unlimited
+    max_sockets_total: Option<usize>, // across all processes; None = unlimited
+    pool: HashMap<String, Vec<PooledConnection>>, // "addr:port" -> idle connections, oldest first
+    pool_size: usize, // idle connections kept per destination; 0 = no pooling
+    pool_idle_timeout: Duration,
+}
+
+impl Default for NatTable {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl NatTable {
     pub fn new() -> Self {
-        info!("Creating new NAT table");
+        Self::with_port_base(DEFAULT_PORT_BASE)
+    }
+
+    /// Creates a table whose consensus ports are allocated upwards from `port_base`.
+    pub fn with_port_base(port_base: u16) -> Self {
+        info!("Creating new NAT table (consensus ports from {})", port_base);
         NatTable {
             port_mappings: HashMap::new(),
             process_ports: HashMap::new(),
             listeners: HashMap::new(),
             connections: HashMap::new(),
-            next_port: 10000, // Start from a high port number
+            datagram_sockets: HashMap::new(),
+            next_port: port_base,
             waiting_accepts: HashMap::new(),
             waiting_recvs: HashMap::new(),
+            connecting: HashSet::new(),
+            bind_allow_list: vec![BindRule { ip: IpAddr::V4(Ipv4Addr::LOCALHOST), port: None }],
+            reserved_ports: Vec::new(),
+            max_sockets_per_process: Some(DEFAULT_MAX_SOCKETS_PER_PROCESS),
+            max_sockets_total: Some(DEFAULT_MAX_SOCKETS_TOTAL),
+            pool: HashMap::new(),
+            pool_size: 0,
+            pool_idle_timeout: DEFAULT_POOL_IDLE_TIMEOUT,
+        }
+    }
+
+    /// Caps how many listeners and connections one process may hold at once.
+    pub fn set_max_sockets_per_process(&mut self, limit: Option<usize>) {
+        info!("NAT per-process socket limit: {:?}", limit);
+        self.max_sockets_per_process = limit;
+    }
+
+    /// Listeners, connections and datagram sockets currently held by `pid`.
+    /// Counted from the tables themselves so Close and remote teardown free
+    /// slots automatically.
+    pub fn open_sockets(&self, pid: u64) -> usize {
+        self.listeners.keys().filter(|(p, _)| *p == pid).count()
+            + self.connections.keys().filter(|(p, _)| *p == pid).count()
+            + self.datagram_sockets.keys().filter(|(p, _)| *p == pid).count()
+    }
+
+    /// Caps how many listeners and connections the node holds at once, over
+    /// all processes, whatever their own limits.
+    pub fn set_max_sockets_total(&mut self, limit: Option<usize>) {
+        info!("NAT node-wide socket limit: {:?}", limit);
+        self.max_sockets_total = limit;
+    }
+
+    /// Keeps up to `size` closed connections per destination open for reuse
+    /// by later Connects, each for at most `idle_timeout`. A size of 0 turns
+    /// pooling off.
+    pub fn set_connection_pool(&mut self, size: usize, idle_timeout: Duration) {
+        info!("NAT connection pool: {} per destination, idle timeout {:?}", size, idle_timeout);
+        self.pool_size = size;
+        self.pool_idle_timeout = idle_timeout;
+        if size == 0 {
+            self.pool.clear();
+        }
+    }
+
+    /// Listeners, connections and datagram sockets currently held by all
+    /// processes, plus idle pooled connections, which still hold a host socket.
+    pub fn total_open_sockets(&self) -> usize {
+        self.listeners.len() + self.connections.len() + self.datagram_sockets.len() + self.pooled_connections()
+    }
+
+    fn pooled_connections(&self) -> usize {
+        self.pool.values().map(Vec::len).sum()
+    }
+
+    fn at_process_limit(&self, pid: u64) -> bool {
+        self.max_sockets_per_process.is_some_and(|limit| self.open_sockets(pid) >= limit)
+    }
+
+    fn at_node_limit(&self) -> bool {
+        self.max_sockets_total.is_some_and(|limit| self.total_open_sockets() >= limit)
+    }
+
+    /// Whether `pid` may not open another socket, because of its own limit or
+    /// because the node as a whole is at capacity.
+    fn at_socket_limit(&self, pid: u64) -> bool {
+        self.at_process_limit(pid) || self.at_node_limit()
+    }
+
+    /// Takes the most recently pooled connection to `destination` that is
+    /// still usable. Connections the peer closed, or that received data while
+    /// idle (a reply nobody will read), are dropped instead.
+    fn take_pooled(&mut self, destination: &str) -> Option<TcpStream> {
+        let idle = self.pool.get_mut(destination)?;
+        while let Some(pooled) = idle.pop() {
+            match pooled.connection.peek(&mut [0u8; 1]) {
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Some(pooled.connection),
+                Ok(0) => debug!("Pooled connection to {} was closed by the peer", destination),
+                Ok(_) => debug!("Pooled connection to {} received data while idle, dropping it", destination),
+                Err(e) => debug!("Pooled connection to {} failed: {}", destination, e),
+            }
+        }
+        None
+    }
+
+    /// Keeps a closed connection for reuse if it went to a pooled destination,
+    /// was fully flushed and there is room. Returns it back if not.
+    fn return_to_pool(&mut self, entry: NatEntry) -> Option<NatEntry> {
+        let Some(destination) = entry.destination.clone() else {
+            return Some(entry);
+        };
+        let idle = self.pool.get(&destination).map_or(0, Vec::len);
+        if idle >= self.pool_size || !entry.pending_send.is_empty() || entry.connection.set_nonblocking(true).is_err() {
+            return Some(entry);
+        }
+        debug!("Returning connection of {}:{} to the pool for {}", entry.process_id, entry.process_port, destination);
+        self.pool.entry(destination).or_default().push(PooledConnection {
+            connection: entry.connection,
+            idle_since: Instant::now(),
+        });
+        None
+    }
+
+    /// Closes pooled connections that have been idle too long.
+    fn expire_pooled(&mut self) {
+        let timeout = self.pool_idle_timeout;
+        self.pool.retain(|destination, idle| {
+            idle.retain(|pooled| {
+                let keep = pooled.idle_since.elapsed() < timeout;
+                if !keep {
+                    debug!("Closing idle pooled connection to {}", destination);
+                    let _ = pooled.connection.shutdown(std::net::Shutdown::Both);
+                }
+                keep
+            });
+            !idle.is_empty()
+        });
+    }
+
+    /// Replaces the set of interfaces/ports guests may request in Listen.
+    pub fn set_bind_allow_list(&mut self, rules: Vec<BindRule>) {
+        info!("NAT bind allow-list: {:?}", rules);
+        self.bind_allow_list = rules;
+    }
+
+    /// Keeps `*` rules from granting `ports`, consensus's own runtime and HTTP
+    /// ports; only a rule naming one of them explicitly lets a guest bind it.
+    pub fn set_reserved_ports(&mut self, ports: Vec<u16>) {
+        info!("NAT ports reserved for consensus: {:?}", ports);
+        self.reserved_ports = ports;
+    }
+
+    fn bind_allowed(&self, ip: IpAddr, port: u16) -> bool {
+        self.bind_allow_list.iter().any(|rule| {
+            rule.matches(ip, port) && (rule.port.is_some() || !self.reserved_ports.contains(&port))
+        })
+    }
+
+    /// The interface a guest's bind to `ip` gets. A guest binding the
+    /// wildcard address (INADDR_ANY) is given loopback, unless the allow-list
+    /// lets it bind the wildcard address on `port` itself.
+    fn bind_ip(&self, ip: IpAddr, port: u16) -> IpAddr {
+        if ip.is_unspecified() && !self.bind_allowed(ip, port) {
+            debug!("Binding {} to loopback, as {}:{} is not in the allow-list", ip, ip, port);
+            return match ip {
+                IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::LOCALHOST),
+                IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::LOCALHOST),
+            };
+        }
+        ip
+    }
+
+    /// Creates the host socket for a datagram socket of `pid`, bound to
+    /// `addr`, which must be on the bind allow-list, as for Listen.
+    fn bind_datagram(&mut self, pid: u64, src_port: u16, addr: SocketAddr) -> io::Result<()> {
+        let addr = SocketAddr::new(self.bind_ip(addr.ip(), addr.port()), addr.port());
+        if !self.bind_allowed(addr.ip(), addr.port()) {
+            return Err(io::Error::new(io::ErrorKind::PermissionDenied, format!("{} is not in the bind allow-list", addr)));
+        }
+        self.open_datagram(pid, src_port, addr)
+    }
+
+    fn open_datagram(&mut self, pid: u64, src_port: u16, addr: SocketAddr) -> io::Result<()> {
+        if self.at_socket_limit(pid) {
+            return Err(io::Error::other(format!("process {} or the node reached its socket limit", pid)));
+        }
+        let socket = UdpSocket::bind(addr)?;
+        socket.set_nonblocking(true)?;
+        info!("Created NAT datagram socket: {}:{} -> {}", pid, src_port, socket.local_addr()?);
+        self.datagram_sockets.insert((pid, src_port), NatDatagramEntry {
+            process_id: pid,
+            process_port: src_port,
+            socket,
+            received: VecDeque::new(),
+            last_source: None,
+        });
+        Ok(())
+    }
+
+    /// The datagram socket of `pid` known as `src_port`. One the guest never
+    /// bound gets an ephemeral loopback port on first use, of the family
+    /// `ipv6` names, since a socket can only send to addresses of its own.
+    fn datagram_socket(&mut self, pid: u64, src_port: u16, ipv6: bool) -> io::Result<&mut NatDatagramEntry> {
+        if !self.datagram_sockets.contains_key(&(pid, src_port)) {
+            let loopback = if ipv6 { IpAddr::V6(Ipv6Addr::LOCALHOST) } else { IpAddr::V4(Ipv4Addr::LOCALHOST) };
+            self.open_datagram(pid, src_port, SocketAddr::new(loopback, 0))?;
         }
+        Ok(self.datagram_sockets.get_mut(&(pid, src_port)).unwrap())
+    }
+
+    /// Where the datagram delivered last to the socket `pid` knows as
+    /// `process_port` came from; `None` for stream sockets.
+    pub fn datagram_source(&self, pid: u64, process_port: u16) -> Option<SocketAddr> {
+        self.datagram_sockets.get(&(pid, process_port))?.last_source
     }
 
     fn allocate_port(&mut self) -> u16 {
@@ -54,6 +499,73 @@ impl NatTable {
         port
     }
 
+    /// First half of a Connect: checks the socket limits and the pool. A
+    /// connect that needs a new host connection is noted as in flight and
+    /// returned as `Dial`; `finish_connect` takes the result.
+    pub fn begin_connect(&mut self, pid: u64, src_port: u16, dest_addr: &str, dest_port: u16) -> ConnectStep {
+        let addr = host_port(dest_addr, dest_port);
+        if self.at_process_limit(pid) {
+            error!("Process {} or the node reached its socket limit, refusing connect from {}", pid, src_port);
+            return ConnectStep::Done(false);
+        }
+        // A pooled connection already holds its host socket, so it is
+        // reused even when the node is at its limit
+        if self.pool_size > 0 {
+            if let Some(stream) = self.take_pooled(&addr) {
+                info!("Reusing pooled connection to {} for {}:{}", addr, pid, src_port);
+                self.install_connection(pid, src_port, &addr, stream);
+                return ConnectStep::Done(true);
+            }
+        }
+        if self.at_node_limit() {
+            error!("Process {} or the node reached its socket limit, refusing connect from {}", pid, src_port);
+            return ConnectStep::Done(false);
+        }
+        debug!("Attempting to connect to {}", addr);
+        self.connecting.insert((pid, src_port));
+        ConnectStep::Dial(addr)
+    }
+
+    /// Second half of a Connect: installs the connection `begin_connect`
+    /// asked for. Returns None, and drops the connection, if the process's
+    /// network operations were cancelled while it was dialed.
+    pub fn finish_connect(&mut self, pid: u64, src_port: u16, addr: &str, connected: io::Result<TcpStream>) -> Option<io::Result<()>> {
+        if !self.connecting.remove(&(pid, src_port)) {
+            info!("Connect from {}:{} to {} was cancelled; dropping it", pid, src_port, addr);
+            return None;
+        }
+        match connected {
+            Ok(stream) => {
+                self.install_connection(pid, src_port, addr, stream);
+                Some(Ok(()))
+            }
+            Err(e) => {
+                error!("Failed to connect to {}: {}", addr, e);
+                Some(Err(e))
+            }
+        }
+    }
+
+    fn install_connection(&mut self, pid: u64, src_port: u16, addr: &str, stream: TcpStream) {
+        if let Err(e) = stream.set_nonblocking(true) {
+            error!("Failed to set non-blocking mode: {}", e);
+        }
+        let consensus_port = self.allocate_port();
+        let entry = NatEntry {
+            process_id: pid,
+            process_port: src_port,
+            consensus_port,
+            connection: stream,
+            buffer: Vec::new(),
+            pending_send: Vec::new(),
+            destination: Some(addr.to_string()),
+        };
+        self.port_mappings.insert(consensus_port, entry);
+        self.process_ports.insert((pid, src_port), consensus_port);
+        self.connections.insert((pid, src_port), consensus_port);
+        info!("Created NAT entry: {}:{} -> consensus:{} -> {}", pid, src_port, consensus_port, addr);
+    }
+
     pub fn handle_network_operation(
         &mut self,
         pid: u64,
@@ -63,17 +575,41 @@ impl NatTable {
         let _start_time = std::time::Instant::now();
         debug!("Handling network operation for process {}: {:?}", pid, op);
         match op {
-            NetworkOperation::Listen { src_port } => {
-                let consensus_port = self.allocate_port();
-                let addr = format!("127.0.0.1:{}", consensus_port);
+            NetworkOperation::Listen { src_port, bind_addr, bind_port, backlog } => {
+                if self.at_socket_limit(pid) {
+                    error!("Process {} or the node reached its socket limit, refusing listen on {}", pid, src_port);
+                    return Ok(false);
+                }
+                let ip = match bind_addr.as_deref().map(str::parse::<IpAddr>) {
+                    None => IpAddr::V4(Ipv4Addr::LOCALHOST),
+                    Some(Ok(ip)) => ip,
+                    Some(Err(_)) => {
+                        error!("Invalid bind address {:?} from process {}:{}", bind_addr, pid, src_port);
+                        return Ok(false);
+                    }
+                };
+                let consensus_port = match bind_port.filter(|&p| p != 0) {
+                    Some(port) => port,
+                    None => self.allocate_port(),
+                };
+                let ip = self.bind_ip(ip, consensus_port);
+                // Explicit requests from the guest must be on the allow-list
+                if (bind_addr.is_some() || bind_port.is_some()) && !self.bind_allowed(ip, consensus_port) {
+                    error!("Process {}:{} may not bind {}:{} (not in allow-list)", pid, src_port, ip, consensus_port);
+                    return Ok(false);
+                }
+                let addr = std::net::SocketAddr::new(ip, consensus_port);
                 
                 debug!("Attempting to listen on {}", addr);
-                match TcpListener::bind(&addr) {
+                match TcpListener::bind(addr) {
                     Ok(listener) => {
                         // Set to non-blocking mode
                         if let Err(e) = listener.set_nonblocking(true) {
                             error!("Failed to set non-blocking mode: {}", e);
                         }
+                        if let Err(e) = set_backlog(&listener, backlog) {
+                            error!("Failed to set backlog {} for {}:{}: {}", backlog, pid, src_port, e);
+                        }
                         
                         let entry = NatListener {
                             process_id: pid,
@@ -81,6 +617,7 @@ impl NatTable {
                             consensus_port,
                             listener,
                             pending_accepts: Vec::new(),
+                            backlog,
                         };
                         
                         self.listeners.insert((pid, src_port), entry);
@@ -101,6 +638,12 @@ impl NatTable {
                     error!("No NAT mapping found for process {}:{}", pid, src_port);
                     return Ok(false);
                 }
+                if self.at_socket_limit(pid) {
+                    // Wait like an empty backlog; check_for_incoming_data accepts once a slot frees up
+                    debug!("Process {} at socket limit, deferring accept on {}", pid, src_port);
+                    self.set_waiting_accept(pid, src_port, new_port);
+                    return Ok(true);
+                }
 
                 // Try to accept any pending connections
                 let accept_result = {
@@ -126,6 +669,8 @@ impl NatTable {
                             consensus_port,
                             connection: stream,
                             buffer: Vec::new(),
+                            pending_send: Vec::new(),
+                            destination: None,
                         };
                         
                         // Add the new connection to our tables
@@ -154,56 +699,32 @@ impl NatTable {
                 }
             }
             NetworkOperation::Connect { dest_addr, dest_port, src_port } => {
-                let consensus_port = self.allocate_port();
-                let addr = format!("{}:{}", dest_addr, dest_port);
-                
-                debug!("Attempting to connect to {}", addr);
-                match TcpStream::connect(&addr) {
-                    Ok(stream) => {
-                        // Set to non-blocking mode
-                        if let Err(e) = stream.set_nonblocking(true) {
-                            error!("Failed to set non-blocking mode: {}", e);
+                match self.begin_connect(pid, src_port, &dest_addr, dest_port) {
+                    ConnectStep::Done(connected) => Ok(connected),
+                    ConnectStep::Dial(addr) => {
+                        let connected = TcpStream::connect(&addr);
+                        match self.finish_connect(pid, src_port, &addr, connected) {
+                            Some(Ok(())) => Ok(true),
+                            Some(Err(e)) => Err(Box::new(e)),
+                            None => Ok(false),
                         }
-                        
-                        let entry = NatEntry {
-                            process_id: pid,
-                            process_port: src_port,
-                            consensus_port,
-                            connection: stream,
-                            buffer: Vec::new(),
-                        };
-                        
-                        self.port_mappings.insert(consensus_port, entry);
-                        self.process_ports.insert((pid, src_port), consensus_port);
-                        self.connections.insert((pid, src_port), consensus_port);  // Add to connections map
-                        info!("Created NAT entry: {}:{} -> consensus:{} -> {}:{}", 
-                            pid, src_port, consensus_port, dest_addr, dest_port);
-                        Ok(true)
-                    }
-                    Err(e) => {
-                        error!("Failed to connect to {}: {}", addr, e);
-                        Err(Box::new(e))
                     }
                 }
             }
             NetworkOperation::Send { src_port, data } => {
                 let start_time = std::time::Instant::now();
                 info!("Processing send operation for process {}:{} ({} bytes): {:?}", 
-                     pid, src_port, data.len(), String::from_utf8_lossy(&data));
+                     pid, src_port, data.len(), redact::payload(&data));
                 
                 // First check for an active connection
                 if let Some(&consensus_port) = self.connections.get(&(pid, src_port)) {
                     debug!("Found connection mapping: process {}:{} -> consensus:{}", pid, src_port, consensus_port);
                     if let Some(entry) = self.port_mappings.get_mut(&consensus_port) {
                         debug!("Found connection entry, attempting to write {} bytes", data.len());
-                        match entry.connection.write_all(&data) {
+                        match entry.queue_send(&data) {
                             Ok(_) => {
-                                if let Err(e) = entry.connection.flush() {
-                                    error!("Failed to flush data to connection: {}", e);
-                                    return Err(Box::new(e));
-                                }
-                                info!("Send operation completed in {:?} with {} bytes", 
-                                     start_time.elapsed(), data.len());
+                                info!("Send operation completed in {:?} with {} bytes ({} still pending)", 
+                                     start_time.elapsed(), data.len(), entry.pending_send.len());
                                 Ok(true)
                             }
                             Err(e) => {
@@ -221,13 +742,9 @@ impl NatTable {
                     debug!("Found listener mapping: process {}:{} -> consensus:{}", pid, src_port, consensus_port);
                     if let Some(entry) = self.port_mappings.get_mut(&consensus_port) {
                         debug!("Found listener entry, attempting to write {} bytes", data.len());
-                        match entry.connection.write_all(&data) {
+                        match entry.queue_send(&data) {
                             Ok(_) => {
-                                if let Err(e) = entry.connection.flush() {
-                                    error!("Failed to flush data to listener: {}", e);
-                                    return Err(Box::new(e));
-                                }
-                                info!("Successfully sent and flushed {} bytes to listener", data.len());
+                                info!("Queued {} bytes to listener ({} still pending)", data.len(), entry.pending_send.len());
                                 Ok(true)
                             }
                             Err(e) => {
@@ -273,31 +790,70 @@ impl NatTable {
                     Ok(false)
                 }
             }
+            NetworkOperation::Bind { src_port, bind_addr, bind_port } => {
+                let addr = match bind_addr.parse::<IpAddr>() {
+                    Ok(ip) => SocketAddr::new(ip, bind_port),
+                    Err(_) => {
+                        error!("Invalid bind address {:?} from process {}:{}", bind_addr, pid, src_port);
+                        return Ok(false);
+                    }
+                };
+                if self.datagram_sockets.contains_key(&(pid, src_port)) {
+                    error!("Datagram socket {}:{} is already bound", pid, src_port);
+                    return Ok(false);
+                }
+                self.bind_datagram(pid, src_port, addr)?;
+                Ok(true)
+            }
+            NetworkOperation::SendTo { src_port, dest_addr, dest_port, data } => {
+                let dest = host_port(&dest_addr, dest_port);
+                let entry = self.datagram_socket(pid, src_port, dest_addr.contains(':'))?;
+                let sent = entry.socket.send_to(&data, &dest)?;
+                info!("Sent {}-byte datagram from {}:{} to {}", sent, pid, src_port, dest);
+                Ok(true)
+            }
+            NetworkOperation::RecvFrom { src_port } => {
+                let entry = self.datagram_socket(pid, src_port, false)?;
+                entry.read_available()?;
+                match entry.received.pop_front() {
+                    Some((source, data)) => {
+                        entry.last_source = Some(source);
+                        info!("Delivered {}-byte datagram from {} to {}:{}", data.len(), source, pid, src_port);
+                        self.waiting_recvs.remove(&(pid, src_port));
+                        messages.push((pid, src_port, data, false));
+                    }
+                    None => {
+                        self.waiting_recvs.insert((pid, src_port), true);
+                        debug!("No datagram for {}:{}, process will wait", pid, src_port);
+                    }
+                }
+                Ok(true)
+            }
+            NetworkOperation::Close { src_port } if self.datagram_sockets.remove(&(pid, src_port)).is_some() => {
+                info!("Closed datagram socket for {}:{}", pid, src_port);
+                Ok(true)
+            }
             NetworkOperation::Close { src_port } => {
                 debug!("Processing close operation for process {}:{}", pid, src_port);
                 
                 // First check if this is a connection
                 if let Some(&consensus_port) = self.connections.get(&(pid, src_port)) {
-                    if let Some(entry) = self.port_mappings.get_mut(&consensus_port) {
-                        // Shutdown the socket
-                        if let Err(e) = entry.connection.shutdown(std::net::Shutdown::Both) {
-                            error!("Failed to shutdown socket: {}", e);
+                    if let Some(entry) = self.port_mappings.remove(&consensus_port) {
+                        // A connection with unsent bytes is never pooled
+                        let unpooled = if self.pool_size > 0 { self.return_to_pool(entry) } else { Some(entry) };
+                        if let Some(entry) = unpooled {
+                            entry.close();
                         }
                     }
-                    self.port_mappings.remove(&consensus_port);
                     self.connections.remove(&(pid, src_port));
                     info!("Closed connection for {}:{}", pid, src_port);
                     Ok(true)
                 }
                 // If not a connection, check if it's a listener
                 else if let Some(&consensus_port) = self.process_ports.get(&(pid, src_port)) {
-                    if let Some(entry) = self.port_mappings.get_mut(&consensus_port) {
-                        // Shutdown the socket
-                        if let Err(e) = entry.connection.shutdown(std::net::Shutdown::Both) {
-                            error!("Failed to shutdown socket: {}", e);
-                        }
+                    if let Some(entry) = self.port_mappings.remove(&consensus_port) {
+                        entry.close();
                     }
-                    self.port_mappings.remove(&consensus_port);
                     self.process_ports.remove(&(pid, src_port));
                     self.listeners.remove(&(pid, src_port));
                     info!("Closed listener for {}:{}", pid, src_port);
@@ -329,6 +885,18 @@ impl NatTable {
         debug!("Process {}:{} is now waiting for recv", pid, src_port);
     }
 
+    /// Stops waiting on every accept, recv and connect of `pid`, for a
+    /// CancelNetwork command. A connect still being dialed is dropped once
+    /// the dial returns (see `finish_connect`). Returns how many operations
+    /// were pending.
+    pub fn cancel_pending(&mut self, pid: u64) -> usize {
+        let before = self.waiting_accepts.len() + self.waiting_recvs.len() + self.connecting.len();
+        self.waiting_accepts.retain(|&(p, _), _| p != pid);
+        self.waiting_recvs.retain(|&(p, _), _| p != pid);
+        self.connecting.retain(|&(p, _)| p != pid);
+        before - self.waiting_accepts.len() - self.waiting_recvs.len() - self.connecting.len()
+    }
+
     pub fn clear_waiting_accept(&mut self, pid: u64, src_port: u16) {
         self.waiting_accepts.remove(&(pid, src_port));
         debug!("Process {}:{} is no longer waiting for accept", pid, src_port);
@@ -362,6 +930,8 @@ impl NatTable {
                 consensus_port,
                 connection: stream,
                 buffer: Vec::new(),
+                pending_send: Vec::new(),
+                destination: None,
             };
             
             self.port_mappings.insert(consensus_port, entry);
@@ -390,6 +960,12 @@ impl NatTable {
         }
     }
 
+    /// Remote address of the connection the process knows as `process_port`.
+    pub fn peer_addr(&self, pid: u64, process_port: u16) -> Option<SocketAddr> {
+        let consensus_port = self.connections.get(&(pid, process_port))?;
+        self.port_mappings.get(consensus_port)?.connection.peer_addr().ok()
+    }
+
     #[allow(dead_code)]
     pub fn has_port_mapping(&self, pid: u64, src_port: u16) -> bool {
         self.process_ports.contains_key(&(pid, src_port))
@@ -397,8 +973,7 @@ impl NatTable {
 
     #[allow(dead_code)]
     pub fn add_port_mapping(&mut self, pid: u64, src_port: u16) {
-        let consensus_port = self.next_port;
-        self.next_port += 1;
+        let consensus_port = self.allocate_port();
         self.process_ports.insert((pid, src_port), consensus_port);
         debug!("Added port mapping: {}:{} -> consensus:{}", pid, src_port, consensus_port);
     }
@@ -407,6 +982,7 @@ impl NatTable {
         let mut messages = Vec::new();
         let mut to_remove = Vec::new();
         let start_time = std::time::Instant::now();
+        self.expire_pooled();
 
         // First check all listeners for new connections
         let waiting_listeners: Vec<(u64, u16)> = self.listeners.keys()
@@ -439,6 +1015,11 @@ impl NatTable {
         }
 
         for (pid, src_port) in waiting_listeners {
+            if self.at_socket_limit(pid) {
+                // Leave the connection in the OS backlog until the process frees a slot
+                debug!("Process {} at socket limit, deferring accept on {}", pid, src_port);
+                continue;
+            }
             if let Some(listener) = self.listeners.get_mut(&(pid, src_port)) {
                 debug!("Attempting to accept connection on listener {}:{} (consensus port: {})", 
                     pid, src_port, listener.consensus_port);
@@ -469,6 +1050,8 @@ impl NatTable {
                             consensus_port,
                             connection: stream,
                             buffer: Vec::new(),
+                            pending_send: Vec::new(),
+                            destination: None,
                         };
                         
                         // Add the new connection to our tables
@@ -497,6 +1080,12 @@ impl NatTable {
 
         // Then check all connections for incoming data
         for (consensus_port, entry) in &mut self.port_mappings {
+            // Retry sends that were cut short by a full socket buffer
+            if let Err(e) = entry.flush_pending() {
+                error!("Error writing pending data to {}:{}: {}", entry.process_id, entry.process_port, e);
+                to_remove.push(*consensus_port);
+                continue;
+            }
             let mut buf = [0u8; 1024];
             match entry.connection.read(&mut buf) {
                 Ok(0) => {
@@ -532,6 +1121,22 @@ impl NatTable {
             }
         }
 
+        // Hand queued datagrams to the processes waiting on their sockets, one per RecvFrom
+        for ((pid, port), entry) in &mut self.datagram_sockets {
+            if let Err(e) = entry.read_available() {
+                error!("Error reading from datagram socket {}:{}: {}", pid, port, e);
+            }
+            if !self.waiting_recvs.contains_key(&(*pid, *port)) {
+                continue;
+            }
+            if let Some((source, data)) = entry.received.pop_front() {
+                info!("Delivered {}-byte datagram from {} to {}:{}", data.len(), source, pid, port);
+                entry.last_source = Some(source);
+                messages.push((*pid, *port, data, false));
+                self.waiting_recvs.remove(&(*pid, *port));
+            }
+        }
+
         // Clean up closed connections
         for port in to_remove {
             if let Some(entry) = self.port_mappings.remove(&port) {
@@ -560,9 +1165,14 @@ impl NatTable {
             }
         }
 
+        // The maps above iterate in consensus-port (hash) order; put the messages in
+        // guest-visible order so the record stream does not depend on it. The sort is
+        // stable, so messages for the same socket keep their relative order.
+        messages.sort_by_key(|(pid, port, _, _)| (*pid, *port));
         messages
     }
 
+    #[allow(dead_code)]
     pub fn has_connection(&self, pid: u64, port: u16) -> bool {
         self.connections.contains_key(&(pid, port))
     }
@@ -572,7 +1182,7 @@ impl NatTable {
         
         // Collect all unique process IDs
         for &(pid, _) in self.process_ports.keys() {
-            if !processes.contains_key(&pid) {
+            if let std::collections::hash_map::Entry::Vacant(slot) = processes.entry(pid) {
                 let mut ports = Vec::new();
                 let mut listeners = Vec::new();
                 let mut connections = Vec::new();
@@ -594,10 +1204,12 @@ impl NatTable {
                     }
                 }
                 
-                processes.insert(pid, json!({
+                slot.insert(json!({
                     "ports": ports,
                     "listeners": listeners,
-                    "connections": connections
+                    "connections": connections,
+                    "open_sockets": self.open_sockets(pid),
+                    "socket_limit": self.max_sockets_per_process
                 }));
             }
         }
@@ -605,6 +1217,14 @@ impl NatTable {
         json!(processes)
     }
 
+    pub fn get_socket_usage(&self) -> serde_json::Value {
+        json!({
+            "open": self.total_open_sockets(),
+            "limit": self.max_sockets_total,
+            "pooled": self.pooled_connections()
+        })
+    }
+
     pub fn get_connection_info(&self) -> serde_json::Value {
         let mut connections = Vec::new();
         
@@ -630,13 +1250,44 @@ impl NatTable {
                 "process_id": pid,
                 "process_port": port,
                 "consensus_port": listener.consensus_port,
-                "pending_accepts": listener.pending_accepts.len()
+                "pending_accepts": listener.pending_accepts.len(),
+                "backlog": listener.backlog
             }));
         }
         
         json!(listeners)
     }
 
+    /// Accepts and recvs that are waiting on the host, per process.
+    pub fn get_pending_info(&self) -> serde_json::Value {
+        let mut pending = Vec::new();
+
+        for (&(pid, port), &new_port) in &self.waiting_accepts {
+            pending.push(json!({
+                "process_id": pid,
+                "process_port": port,
+                "operation": "accept",
+                "new_port": new_port
+            }));
+        }
+        for (&(pid, port), _) in self.waiting_recvs.iter().filter(|(_, &waiting)| waiting) {
+            pending.push(json!({
+                "process_id": pid,
+                "process_port": port,
+                "operation": "recv"
+            }));
+        }
+        for &(pid, port) in &self.connecting {
+            pending.push(json!({
+                "process_id": pid,
+                "process_port": port,
+                "operation": "connect"
+            }));
+        }
+
+        json!(pending)
+    }
+
     pub fn get_port_mappings(&self) -> Vec<(u64, u16, u16, &'static str)> {
         let mut mappings = Vec::new();
         
@@ -662,4 +1313,70 @@ impl NatTable {
     pub fn peek_waiting_port(&self, pid: u64, src_port: u16) -> Option<u16> {
         self.waiting_accepts.get(&(pid, src_port)).copied()
     }
-} 
\ No newline at end of file
+} 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Message = (u64, u16, Vec<u8>, bool);
+
+    /// A loopback port nothing is listening on.
+    fn free_port() -> u16 {
+        TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port()
+    }
+
+    fn handle(nat: &mut NatTable, pid: u64, op: NetworkOperation, messages: &mut Vec<Message>) {
+        assert!(nat.handle_network_operation(pid, op, messages).unwrap());
+    }
+
+    /// Process 1 accepts a client from here and process 2 connects to
+    /// `server`, and each is sent a message. Returns everything the table
+    /// handed back for the runtime, sorted, since its order depends on when
+    /// the bytes arrived.
+    fn session(port_base: u16, server: &TcpListener) -> Vec<Message> {
+        let mut nat = NatTable::with_port_base(port_base);
+        let mut messages = Vec::new();
+        handle(&mut nat, 1, NetworkOperation::Listen { src_port: 5000, bind_addr: None, bind_port: None, backlog: 16 }, &mut messages);
+        handle(&mut nat, 1, NetworkOperation::Accept { src_port: 5000, new_port: 5001 }, &mut messages);
+        let listener_port = nat.listeners[&(1, 5000)].consensus_port;
+        let mut client = TcpStream::connect(("127.0.0.1", listener_port)).unwrap();
+        client.write_all(b"to pid 1").unwrap();
+        let connect = NetworkOperation::Connect {
+            dest_addr: "127.0.0.1".to_string(),
+            dest_port: server.local_addr().unwrap().port(),
+            src_port: 6000,
+        };
+        handle(&mut nat, 2, connect, &mut messages);
+        let (mut peer, _) = server.accept().unwrap();
+        peer.write_all(b"to pid 2").unwrap();
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let mut accepted = false;
+        let received = |messages: &Vec<Message>| messages.iter().filter(|m| !m.2.is_empty()).count();
+        while received(&messages) < 2 && Instant::now() < deadline {
+            messages.extend(nat.check_for_incoming_data());
+            if !accepted && messages.iter().any(|m| m.3) {
+                accepted = true;
+                handle(&mut nat, 1, NetworkOperation::Recv { src_port: 5001 }, &mut messages);
+                handle(&mut nat, 2, NetworkOperation::Recv { src_port: 6000 }, &mut messages);
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        messages.sort();
+        messages
+    }
+
+    #[test]
+    fn consensus_ports_never_reach_the_runtime() {
+        let server = TcpListener::bind("127.0.0.1:0").unwrap();
+        let (base_a, base_b) = (free_port(), free_port());
+        let (a, b) = (session(base_a, &server), session(base_b, &server));
+        assert_eq!(a, vec![
+            (1, 5000, Vec::new(), true),
+            (1, 5001, b"to pid 1".to_vec(), false),
+            (2, 6000, b"to pid 2".to_vec(), false),
+        ]);
+        assert_eq!(a, b);
+    }
+}
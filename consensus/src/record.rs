@@ -2,7 +2,6 @@ use std::io;
 use byteorder::{LittleEndian, WriteBytesExt};
 use std::io::Write;
 use crate::commands::Command;
-use bincode;
 
 /// Write a binary record for a given command.
 /// New record layout:
@@ -13,25 +12,12 @@ pub fn write_record(cmd: &Command) -> io::Result<Vec<u8>> {
             // Type 0; payload is "clock:<delta>"
             (0u8, 0u64, format!("clock:{}", delta).as_bytes().to_vec())
         },
-        Command::Init { wasm_bytes, dir_path, args } => {
-            let mut payload = Vec::new();
-            
-            // Add directory if present
-            if let Some(dir) = dir_path {
-                payload.extend(format!("dir:{}", dir).as_bytes());
-                payload.push(0); // Null terminator between dir and args
-            }
-            
-            // Add arguments if present, using a safe format
-            if !args.is_empty() {
-                // Split the arguments more sensibly
-                let args_str = args.join("\x1F"); // Use Unit Separator as delimiter
-                payload.extend(format!("args:{}", args_str).as_bytes());
-                payload.push(0); // Null terminator between args and wasm
-            }
-            
-            payload.extend(wasm_bytes);
-            (2u8, u64::MAX, payload)
+        Command::Init { .. } => (2u8, u64::MAX, init_payload(cmd)),
+        Command::InitN(count, init) => {
+            // Type 11; the count, then the same payload as the Init
+            let mut payload = count.to_le_bytes().to_vec();
+            payload.extend(init_payload(init));
+            (11u8, u64::MAX, payload)
         },
         Command::FDMsg(pid, data) => (1u8, *pid, data.clone()),
         Command::NetworkIn(pid, port, data) => (3u8, *pid, {
@@ -41,6 +27,13 @@ pub fn write_record(cmd: &Command) -> io::Result<Vec<u8>> {
             payload
         }),
         Command::NetworkOut(pid, op) => (4u8, *pid, bincode::serialize(op).unwrap()),
+        Command::Pause(pid) => (7u8, *pid, Vec::new()),
+        Command::Resume(pid) => (8u8, *pid, Vec::new()),
+        Command::CancelNetwork(pid) => (9u8, *pid, Vec::new()),
+        // Type 10; payload is the new quota as a u64, or empty for a query
+        Command::SetQuota(pid, bytes) => (10u8, *pid, bytes.map(|b| b.to_le_bytes().to_vec()).unwrap_or_default()),
+        // Type 13; 6 is taken by ProgressReport in outgoing batches
+        Command::Shutdown(pid) => (13u8, *pid, Vec::new()),
     };
 
     if payload.len() > (u32::MAX as usize) {
@@ -52,4 +45,195 @@ pub fn write_record(cmd: &Command) -> io::Result<Vec<u8>> {
     record.write_u32::<LittleEndian>(payload.len() as u32)?;
     record.write_all(&payload)?;
     Ok(record)
-}
\ No newline at end of file
+}
+
+/// A record split out of a batch: `(msg_type, process_id, payload)`.
+pub type RawRecord<'a> = (u8, u64, &'a [u8]);
+
+/// Splits a batch back into records using the layout written by `write_record`.
+/// Fails if the batch ends partway through a record.
+pub fn read_records(mut data: &[u8]) -> io::Result<Vec<RawRecord<'_>>> {
+    let mut records = Vec::new();
+    while !data.is_empty() {
+        if data.len() < 13 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Truncated record header"));
+        }
+        let msg_type = data[0];
+        let pid = u64::from_le_bytes(data[1..9].try_into().unwrap());
+        let len = u32::from_le_bytes(data[9..13].try_into().unwrap()) as usize;
+        let payload = data[13..].get(..len)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "Truncated record payload"))?;
+        records.push((msg_type, pid, payload));
+        data = &data[13 + len..];
+    }
+    Ok(records)
+}
+
+/// Total nanoseconds the Clock records in a batch advance the runtimes'
+/// `GlobalClock` by. Malformed records are skipped, as the runtime does.
+pub fn clock_advance(data: &[u8]) -> u64 {
+    read_records(data)
+        .unwrap_or_default()
+        .iter()
+        .filter(|(msg_type, _, _)| *msg_type == 0)
+        .filter_map(|(_, _, payload)| {
+            std::str::from_utf8(payload).ok()?.strip_prefix("clock:")?.trim().parse::<u64>().ok()
+        })
+        .sum()
+}
+
+/// Puts the records of an incoming batch in the order runtimes apply them:
+/// sorted stably by `(msg_type, process_id)`, with Clock records last since
+/// a clock marks the end of a batch, so the order they came in does not
+/// matter. Records for one process of one type keep their order. Repeats of
+/// a Pause, Resume, CancelNetwork, SetQuota or Shutdown record, which change
+/// nothing the second time, are dropped.
+pub fn canonical_order<P: PartialEq>(records: &mut Vec<(u8, u64, P)>) {
+    records.sort_by_key(|(msg_type, pid, _)| (*msg_type == 0, *msg_type, *pid));
+    records.dedup_by(|b, a| matches!(a.0, 7 | 8 | 9 | 10 | 13) && a == b);
+}
+
+/// Payload of an Init record: the options, each as `key:value\0`, then the wasm binary.
+fn init_payload(cmd: &Command) -> Vec<u8> {
+    let Command::Init { wasm_bytes, dir_path, args, file_cache, max_memory, limits, read_only, tmpfs, first_port, cwd, disk_quota, seed, env, deadline } = cmd else {
+        unreachable!("only called for Init commands");
+    };
+    let mut payload = Vec::new();
+    
+    // Add directory if present
+    if let Some(dir) = dir_path {
+        payload.extend(format!("dir:{}", dir).as_bytes());
+        payload.push(0); // Null terminator between dir and args
+    }
+    
+    // Opt into the per-process file cache
+    if let Some(bytes) = file_cache {
+        payload.extend(format!("cache:{}", bytes).as_bytes());
+        payload.push(0);
+    }
+    
+    // Override the runtime's default linear memory cap
+    if let Some(bytes) = max_memory {
+        payload.extend(format!("mem:{}", bytes).as_bytes());
+        payload.push(0);
+    }
+    
+    // Override table size and instance/table/memory counts
+    if let Some(spec) = limits {
+        payload.extend(format!("limits:{}", spec).as_bytes());
+        payload.push(0);
+    }
+    
+    // Make the whole sandbox read-only
+    if *read_only {
+        payload.extend(b"readonly:1");
+        payload.push(0);
+    }
+    
+    // Keep the sandbox's files in memory instead of on disk
+    if *tmpfs {
+        payload.extend(b"tmpfs:1");
+        payload.push(0);
+    }
+    
+    // Start the guest-visible socket ports somewhere other than 1
+    if let Some(port) = first_port {
+        payload.extend(format!("ports:{}", port).as_bytes());
+        payload.push(0);
+    }
+    
+    // Resolve the guest's relative paths somewhere below the sandbox root
+    if let Some(dir) = cwd {
+        payload.extend(format!("cwd:{}", dir).as_bytes());
+        payload.push(0);
+    }
+    
+    // Give the process a disk quota other than the runtime's 10 MB default
+    if let Some(bytes) = disk_quota {
+        payload.extend(format!("quota:{}", bytes).as_bytes());
+        payload.push(0);
+    }
+    
+    // Seed random_get; always sent, so the runtime never picks one itself
+    payload.extend(format!("seed:{}", seed).as_bytes());
+    payload.push(0);
+    
+    // End the process this many logical nanoseconds after its Init is applied
+    if let Some(nanos) = deadline {
+        payload.extend(format!("deadline:{}", nanos).as_bytes());
+        payload.push(0);
+    }
+    
+    // Environment variables for environ_get, delimited like the arguments
+    if !env.is_empty() {
+        payload.extend(format!("env:{}", env.join("\x1F")).as_bytes());
+        payload.push(0);
+    }
+    
+    // Add arguments if present, using a safe format
+    if !args.is_empty() {
+        // Split the arguments more sensibly
+        let args_str = args.join("\x1F"); // Use Unit Separator as delimiter
+        payload.extend(format!("args:{}", args_str).as_bytes());
+        payload.push(0); // Null terminator between args and wasm
+    }
+    
+    payload.extend(wasm_bytes);
+    payload
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn canonical_order_ignores_arrival_order() {
+        let records: Vec<(u8, u64, &[u8])> = vec![
+            (1, 2, b"fd:0,body:b"),
+            (0, 0, b"clock:1"),
+            (7, 1, b""),
+            (1, 1, b"fd:0,body:a"),
+            (2, 0, b"guest"),
+            (8, 1, b""),
+        ];
+        let mut in_order = records.clone();
+        let mut reversed: Vec<_> = records.into_iter().rev().collect();
+        canonical_order(&mut in_order);
+        canonical_order(&mut reversed);
+        assert_eq!(in_order, reversed);
+        assert_eq!(in_order.iter().map(|(msg_type, pid, _)| (*msg_type, *pid)).collect::<Vec<_>>(),
+                   [(1, 1), (1, 2), (2, 0), (7, 1), (8, 1), (0, 0)]);
+    }
+
+    #[test]
+    fn canonical_order_keeps_records_of_one_type_for_one_process_in_order() {
+        let mut records: Vec<(u8, u64, &[u8])> = vec![
+            (1, 1, b"fd:0,body:b"),
+            (0, 0, b"clock:1"),
+            (1, 1, b"fd:0,body:a"),
+        ];
+        canonical_order(&mut records);
+        assert_eq!(records, [(1, 1, &b"fd:0,body:b"[..]), (1, 1, b"fd:0,body:a"), (0, 0, b"clock:1")]);
+    }
+
+    #[test]
+    fn canonical_order_drops_repeated_controls() {
+        let mut records: Vec<(u8, u64, &[u8])> = vec![
+            (7, 1, b""),
+            (1, 1, b"fd:0,body:a"),
+            (7, 1, b""),
+            (1, 1, b"fd:0,body:a"),
+            (10, 1, b"1024"),
+            (10, 1, b"2048"),
+        ];
+        canonical_order(&mut records);
+        // Repeated input is not a repeated control, and a new quota is not a repeat
+        assert_eq!(records, [
+            (1, 1, &b"fd:0,body:a"[..]),
+            (1, 1, b"fd:0,body:a"),
+            (7, 1, b""),
+            (10, 1, b"1024"),
+            (10, 1, b"2048"),
+        ]);
+    }
+}
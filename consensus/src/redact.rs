@@ -0,0 +1,188 @@
+use std::fmt;
+use std::sync::atomic::{AtomicU8, AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+/// Payloads up to this many bytes are logged verbatim unless `--redact` is set.
+pub const DEFAULT_REDACT_THRESHOLD: usize = 64;
+
+const MODE_THRESHOLD: u8 = 0;
+const MODE_ALWAYS: u8 = 1;
+const MODE_OFF: u8 = 2;
+
+static MODE: AtomicU8 = AtomicU8::new(MODE_THRESHOLD);
+static THRESHOLD: AtomicUsize = AtomicUsize::new(DEFAULT_REDACT_THRESHOLD);
+// Values that must never be logged, whatever the mode
+static SECRETS: Mutex<Vec<Vec<u8>>> = Mutex::new(Vec::new());
+
+/// How guest payloads are rendered in log lines.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RedactMode {
+    /// Log contents of small payloads, redact anything larger than the threshold.
+    Threshold(usize),
+    /// Never log payload contents.
+    Always,
+    /// Log full contents (local debugging only).
+    Off,
+}
+
+pub fn set_mode(mode: RedactMode) {
+    match mode {
+        RedactMode::Threshold(limit) => {
+            THRESHOLD.store(limit, Ordering::SeqCst);
+            MODE.store(MODE_THRESHOLD, Ordering::SeqCst);
+        }
+        RedactMode::Always => MODE.store(MODE_ALWAYS, Ordering::SeqCst),
+        RedactMode::Off => MODE.store(MODE_OFF, Ordering::SeqCst),
+    }
+}
+
+pub fn mode() -> RedactMode {
+    match MODE.load(Ordering::SeqCst) {
+        MODE_ALWAYS => RedactMode::Always,
+        MODE_OFF => RedactMode::Off,
+        _ => RedactMode::Threshold(THRESHOLD.load(Ordering::SeqCst)),
+    }
+}
+
+/// Marks `value` as secret: payloads that contain it are redacted in every
+/// mode, even `--no-redact`, and without a hash that could be used to guess it.
+#[allow(dead_code)] // used by the runtime
+pub fn add_secret(value: &[u8]) {
+    let mut secrets = SECRETS.lock().unwrap();
+    if !value.is_empty() && !secrets.iter().any(|s| s == value) {
+        secrets.push(value.to_vec());
+    }
+}
+
+fn contains_secret(data: &[u8]) -> bool {
+    SECRETS.lock().unwrap().iter().any(|secret| data.windows(secret.len()).any(|w| w == secret.as_slice()))
+}
+
+/// Applies a redaction command-line flag. Returns true if `arg` was one, so
+/// callers can strip it before positional argument handling.
+///
+/// - `--redact`: never log payload contents
+/// - `--no-redact`: always log full payload contents
+/// - `--redact-threshold=<bytes>`: redact payloads larger than `<bytes>`
+pub fn apply_flag(arg: &str) -> bool {
+    if arg == "--redact" {
+        set_mode(RedactMode::Always);
+    } else if arg == "--no-redact" {
+        set_mode(RedactMode::Off);
+    } else if let Some(limit) = arg.strip_prefix("--redact-threshold=") {
+        match limit.parse() {
+            Ok(limit) => set_mode(RedactMode::Threshold(limit)),
+            Err(_) => log::warn!("Ignoring invalid redaction threshold: {}", limit),
+        }
+    } else {
+        return false;
+    }
+    true
+}
+
+/// Wraps a payload for logging. Depending on the current mode this renders either
+/// the (lossy UTF-8) contents or only the length and a hash of the bytes. The
+/// hash is a CRC32, which does not depend on the build, so the same payload
+/// logged by consensus and by every replica can be matched up.
+pub fn payload(data: &[u8]) -> Payload<'_> {
+    Payload(data)
+}
+
+pub struct Payload<'a>(&'a [u8]);
+
+impl Payload<'_> {
+    fn redacted(&self) -> bool {
+        match mode() {
+            RedactMode::Always => true,
+            RedactMode::Off => false,
+            RedactMode::Threshold(limit) => self.0.len() > limit,
+        }
+    }
+}
+
+impl fmt::Display for Payload<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if contains_secret(self.0) {
+            write!(f, "<redacted {} bytes containing a secret>", self.0.len())
+        } else if self.redacted() {
+            write!(f, "<redacted {} bytes, hash {:08x}>", self.0.len(), crc32fast::hash(self.0))
+        } else {
+            write!(f, "{}", String::from_utf8_lossy(self.0))
+        }
+    }
+}
+
+impl fmt::Debug for Payload<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.redacted() || contains_secret(self.0) {
+            fmt::Display::fmt(self, f)
+        } else {
+            write!(f, "{:?}", String::from_utf8_lossy(self.0))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The mode is global, so tests that set it take turns
+    static MODE_LOCK: Mutex<()> = Mutex::new(());
+
+    fn render(mode: RedactMode, data: &[u8]) -> String {
+        let _guard = MODE_LOCK.lock().unwrap();
+        set_mode(mode);
+        let rendered = payload(data).to_string();
+        set_mode(RedactMode::Threshold(DEFAULT_REDACT_THRESHOLD));
+        rendered
+    }
+
+    #[test]
+    fn redacted_payloads_carry_their_crc32() {
+        assert_eq!(render(RedactMode::Always, b"hello world"), "<redacted 11 bytes, hash 0d4a1185>");
+    }
+
+    #[test]
+    fn hash_depends_only_on_the_bytes() {
+        let first = render(RedactMode::Always, b"same payload");
+        assert_eq!(render(RedactMode::Always, b"same payload"), first);
+        assert_ne!(render(RedactMode::Always, b"same payloae"), first);
+    }
+
+    #[test]
+    fn threshold_redacts_only_larger_payloads() {
+        assert_eq!(render(RedactMode::Threshold(5), b"hello"), "hello");
+        assert_eq!(render(RedactMode::Threshold(5), b"hello!"), "<redacted 6 bytes, hash 9a86c960>");
+    }
+
+    #[test]
+    fn off_logs_everything() {
+        let long = vec![b'x'; 1000];
+        assert_eq!(render(RedactMode::Off, &long), String::from_utf8(long).unwrap());
+    }
+
+    #[test]
+    fn secrets_are_redacted_in_every_mode_without_a_hash() {
+        add_secret(b"redact-test-secret");
+        let data = b"token=redact-test-secret";
+        for mode in [RedactMode::Off, RedactMode::Threshold(1000), RedactMode::Always] {
+            assert_eq!(render(mode, data), "<redacted 24 bytes containing a secret>");
+        }
+    }
+
+    #[test]
+    fn apply_flag_sets_the_mode() {
+        let _guard = MODE_LOCK.lock().unwrap();
+        assert!(apply_flag("--redact"));
+        assert_eq!(mode(), RedactMode::Always);
+        assert!(apply_flag("--no-redact"));
+        assert_eq!(mode(), RedactMode::Off);
+        assert!(apply_flag("--redact-threshold=10"));
+        assert_eq!(mode(), RedactMode::Threshold(10));
+        // An invalid threshold is ignored
+        assert!(apply_flag("--redact-threshold=many"));
+        assert_eq!(mode(), RedactMode::Threshold(10));
+        assert!(!apply_flag("--redacted"));
+        set_mode(RedactMode::Threshold(DEFAULT_REDACT_THRESHOLD));
+    }
+}
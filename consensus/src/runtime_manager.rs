@@ -1,17 +1,214 @@
-use std::io::{self, Write};
-use std::net::{TcpStream, TcpListener};
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
 use std::sync::{Arc, Mutex};
 use std::thread;
+use std::time::{Duration, Instant};
 use std::collections::HashMap;
 use log::{error, info, debug, warn};
 pub use crate::batch::{Batch, BatchDirection};
+use crate::batch::{decode_wire_data, encode_wire, split_wire_direction, wire_checksum, BASE_WIRE_VERSION, UNKNOWN_CLOCK, WIRE_ACCEPTS_ZSTD, WIRE_ZSTD};
 use crate::batch_history::BatchHistory;
+use crate::record::read_records;
+
+/// Where `consensus tcp` listens for runtimes, and where runtimes connect,
+/// unless told otherwise.
+pub const DEFAULT_RUNTIME_ADDR: &str = "127.0.0.1:9000";
+
+/// Resolves `addr`, given as `host:port`, to the first socket address it
+/// names. The error says what was wrong with it, for the command line.
+pub fn resolve_addr(addr: &str) -> io::Result<SocketAddr> {
+    let invalid = |reason: String| io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("invalid address '{}': {} (expected host:port, e.g. {})", addr, reason, DEFAULT_RUNTIME_ADDR),
+    );
+    addr.to_socket_addrs()
+        .map_err(|e| invalid(e.to_string()))?
+        .next()
+        .ok_or_else(|| invalid("it names no address".to_string()))
+}
+
+/// How long consensus waits for a new runtime's hello before sending it the
+/// history. Runtimes from this tree send it as soon as they connect.
+const HELLO_TIMEOUT: Duration = Duration::from_millis(200);
+
+/// How long `exit` waits for the runtimes to close their connections.
+const DISCONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The largest hello consensus reads; one carries at most a BatchAck record.
+const MAX_HELLO_LEN: u64 = 1024;
+
+/// What a new runtime said in its hello.
+struct Hello {
+    /// The newest wire format the runtime reads.
+    wire_version: u8,
+    /// Whether batches to it may be compressed.
+    compress: bool,
+    /// The last batch it acknowledged on an earlier connection, 0 if none.
+    acked: u64,
+}
+
+/// Waits for a new runtime's hello, the batch 0 it opens the connection
+/// with, in the newest format it reads, and reads it. A runtime that sends
+/// nothing in time, or starts with another batch, is taken to read version 2
+/// without compression and to have acknowledged nothing. Fails if the hello
+/// cannot be read whole.
+fn read_hello(stream: &mut TcpStream, runtime_id: u64) -> io::Result<Hello> {
+    let mut hello = Hello { wire_version: BASE_WIRE_VERSION, compress: false, acked: 0 };
+    let deadline = Instant::now() + HELLO_TIMEOUT;
+    let mut header = [0u8; 9];
+    let answered = loop {
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() || stream.set_read_timeout(Some(left)).is_err() {
+            break false;
+        }
+        match stream.peek(&mut header) {
+            Ok(9) => break header[..8] == [0; 8],
+            // Closed, or only part of the header is in
+            Ok(0) | Err(_) => break false,
+            Ok(_) => thread::sleep(Duration::from_millis(1)),
+        }
+    };
+    if answered {
+        // The rest follows the header at once; a runtime that stalls on it is dropped
+        stream.set_read_timeout(Some(HELLO_TIMEOUT))?;
+        let (version, _, flags) = split_wire_direction(header[8]);
+        let mut fixed = vec![0u8; if version >= 2 { 25 } else { 17 }];
+        stream.read_exact(&mut fixed)?;
+        let len = u64::from_le_bytes(fixed[fixed.len() - 8..].try_into().unwrap());
+        if len > MAX_HELLO_LEN {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("hello of {} bytes", len)));
+        }
+        let mut data = vec![0u8; len as usize];
+        stream.read_exact(&mut data)?;
+        if version >= 1 {
+            let mut crc = [0u8; 4];
+            stream.read_exact(&mut crc)?;
+            if u32::from_le_bytes(crc) != wire_checksum(&data) {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "hello failed its checksum"));
+            }
+        }
+        let data = decode_wire_data(flags, data)?;
+        let records = read_records(&data)?;
+        hello.wire_version = version;
+        hello.compress = flags & WIRE_ACCEPTS_ZSTD != 0;
+        hello.acked = records.iter()
+            .filter(|(msg_type, _, _)| *msg_type == 15)
+            .map(|(_, batch_number, _)| *batch_number)
+            .max()
+            .unwrap_or(0);
+    }
+    stream.set_read_timeout(None)?;
+    info!("Runtime {} reads wire format {} and {} compressed batches", runtime_id, hello.wire_version,
+        if hello.compress { "accepts" } else { "does not accept" });
+    Ok(hello)
+}
+
+/// Brings a newly accepted runtime up to date: reads its hello, sends it the
+/// incoming batches of the session history it has not acknowledged, and adds
+/// it to `runtimes`.
+fn admit_runtime(
+    mut stream: TcpStream,
+    runtime_id: u64,
+    runtimes: &Mutex<HashMap<u64, RuntimeConnection>>,
+    batch_history: &Mutex<BatchHistory>,
+) {
+    let Hello { wire_version, compress, acked } = match read_hello(&mut stream, runtime_id) {
+        Ok(hello) => hello,
+        Err(e) => {
+            error!("Failed to read the hello of runtime {}: {}; dropping it", runtime_id, e);
+            return;
+        }
+    };
+
+    // Send historical batches to new runtime, after any it acknowledged
+    // before it reconnected
+    if acked > 0 {
+        info!("Runtime {} resumes after batch {}, which it acknowledged", runtime_id, acked);
+    }
+    let mut sent = acked;
+    loop {
+        let before = sent;
+        if !send_history(&mut stream, runtime_id, batch_history, &mut sent, wire_version, compress) {
+            // It connects again, and resumes after what it acknowledged
+            error!("Dropping runtime {}", runtime_id);
+            return;
+        }
+        // A batch saved while the history was being sent was broadcast
+        // before this runtime joined, so it is sent here. Holding the map
+        // keeps the next one from being broadcast until it has joined.
+        let mut conns = runtimes.lock().unwrap();
+        let current = batch_history.lock().unwrap().get_current_batch();
+        if current > sent && sent > before {
+            continue;
+        }
+        conns.insert(runtime_id, RuntimeConnection {
+            stream: Arc::new(Mutex::new(stream)),
+            last_sent_batch: current,
+            last_processed_batch: acked,
+            wire_version,
+            compress,
+        });
+        break;
+    }
+    info!("Runtime {} added to connection pool", runtime_id);
+}
+
+/// Sends `stream` the incoming batches of the history after `sent`, and
+/// moves `sent` past them. Returns false if the runtime could not be written to.
+fn send_history(
+    stream: &mut TcpStream,
+    runtime_id: u64,
+    batch_history: &Mutex<BatchHistory>,
+    sent: &mut u64,
+    wire_version: u8,
+    compress: bool,
+) -> bool {
+    let batches = match batch_history.lock().unwrap().get_batches_since(*sent) {
+        Ok(batches) => batches,
+        Err(e) => {
+            error!("Failed to read the history for runtime {}: {}", runtime_id, e);
+            return false;
+        }
+    };
+    // Filter to only include incoming batches
+    let incoming_batches: Vec<_> = batches.into_iter()
+        .filter(|batch| matches!(batch.direction, BatchDirection::Incoming))
+        .collect();
+
+    info!("Sending {} historical incoming batches to new runtime {}",
+        incoming_batches.len(), runtime_id);
+
+    for batch in incoming_batches {
+        // Always Incoming (0) since we filtered
+        let clock = batch.clock_before().unwrap_or(UNKNOWN_CLOCK);
+        let serialized = encode_wire(batch.number, 0, clock, &batch.data, wire_version, compress);
+
+        // Write the entire batch at once
+        if let Err(e) = stream.write_all(&serialized).and_then(|_| stream.flush()) {
+            error!("Failed to send historical batch {} to runtime {}: {}", batch.number, runtime_id, e);
+            return false;
+        }
+        debug!("Successfully sent historical batch {} to runtime {} ({} bytes)",
+            batch.number, runtime_id, serialized.len());
+        *sent = batch.number;
+    }
+    true
+}
 
 /// Represents a connected runtime.
 #[derive(Clone)]
 pub struct RuntimeConnection {
     pub stream: Arc<Mutex<TcpStream>>,
+    /// The last batch written to the runtime.
+    pub last_sent_batch: u64,
+    /// The last batch the runtime acknowledged with a BatchAck record, once
+    /// it had applied all of it; 0 until its first.
     pub last_processed_batch: u64,
+    /// The wire format of the runtime's last batch, which batches to it are
+    /// sent in (see `crate::batch::encode_wire`).
+    pub wire_version: u8,
+    /// Whether the runtime reads zstd-compressed batches.
+    pub compress: bool,
 }
 
 /// Manages multiple runtime connections and session batches.
@@ -24,12 +221,13 @@ pub struct RuntimeManager {
 }
 
 impl RuntimeManager {
-    pub fn new(addr: &str, batch_history: Arc<Mutex<BatchHistory>>) -> io::Result<Self> {
+    pub fn new(addr: SocketAddr, batch_history: Arc<Mutex<BatchHistory>>) -> io::Result<Self> {
         info!("Initializing RuntimeManager on {}", addr);
         let listener = Arc::new(TcpListener::bind(addr)?);
         let runtimes = Arc::new(Mutex::new(HashMap::new()));
         let next_runtime_id = Arc::new(Mutex::new(0));
-        info!("RuntimeManager: Listening for runtimes on {}...", addr);
+        // Port 0 picks a free one, so log the one actually bound
+        info!("RuntimeManager: Listening for runtimes on {}...", listener.local_addr()?);
         Ok(Self {
             listener,
             runtimes,
@@ -49,59 +247,17 @@ impl RuntimeManager {
             info!("Runtime acceptor thread started");
             for stream in listener.incoming() {
                 match stream {
-                    Ok(mut stream) => {
+                    Ok(stream) => {
                         let mut id_lock = next_runtime_id.lock().unwrap();
                         let runtime_id = *id_lock;
                         *id_lock += 1;
                         drop(id_lock);
                         info!("Accepted runtime {} from {}", runtime_id, stream.peer_addr().unwrap());
-                        
-                        // Send historical batches to new runtime
-                        if let Ok(batches) = batch_history.lock().unwrap().get_batches_since(0) {
-                            // Filter to only include incoming batches
-                            let incoming_batches: Vec<_> = batches.into_iter()
-                                .filter(|batch| matches!(batch.direction, BatchDirection::Incoming))
-                                .collect();
-                            
-                            info!("Sending {} historical incoming batches to new runtime {}", 
-                                incoming_batches.len(), runtime_id);
-                            
-                            for batch in incoming_batches {
-                                // Create a new buffer for each batch to ensure clean state
-                                let mut serialized = Vec::new();
-                                // Write batch number (8 bytes)
-                                serialized.extend_from_slice(&batch.number.to_le_bytes());
-                                // Write direction (1 byte)
-                                serialized.push(0); // Always Incoming (0) since we filtered
-                                // Write data length (8 bytes)
-                                serialized.extend_from_slice(&(batch.data.len() as u64).to_le_bytes());
-                                // Write the actual data
-                                serialized.extend_from_slice(&batch.data);
-                                
-                                // Write the entire batch at once
-                                match stream.write_all(&serialized) {
-                                    Ok(_) => {
-                                        if let Err(e) = stream.flush() {
-                                            error!("Failed to flush historical batch {} to runtime {}: {}", batch.number, runtime_id, e);
-                                            break;
-                                        }
-                                        debug!("Successfully sent historical batch {} to runtime {} ({} bytes)", 
-                                            batch.number, runtime_id, serialized.len());
-                                    }
-                                    Err(e) => {
-                                        error!("Failed to send historical batch {} to runtime {}: {}", batch.number, runtime_id, e);
-                                        break;
-                                    }
-                                }
-                            }
-                        }
-                        
-                        let conn = RuntimeConnection {
-                            stream: Arc::new(Mutex::new(stream)),
-                            last_processed_batch: batch_history.lock().unwrap().get_current_batch(),
-                        };
-                        runtimes.lock().unwrap().insert(runtime_id, conn);
-                        info!("Runtime {} added to connection pool", runtime_id);
+                        // Its own thread, so waiting for its hello and sending it the
+                        // history never holds up the runtimes connecting after it
+                        let runtimes = Arc::clone(&runtimes);
+                        let batch_history = Arc::clone(&batch_history);
+                        thread::spawn(move || admit_runtime(stream, runtime_id, &runtimes, &batch_history));
                     }
                     Err(e) => {
                         error!("Failed to accept runtime: {}", e);
@@ -130,44 +286,47 @@ impl RuntimeManager {
 
         info!("Found {} connected runtimes", conns.len());
         for (runtime_id, conn) in conns.iter() {
-            debug!("Runtime {} last processed batch: {}", runtime_id, conn.last_processed_batch);
+            debug!("Runtime {} last sent batch: {}, last processed: {}", runtime_id, conn.last_sent_batch, conn.last_processed_batch);
         }
 
-        // Serialize the batch header and data
-        let mut serialized = Vec::new();
-        // Write batch number (8 bytes)
-        serialized.extend_from_slice(&batch.number.to_le_bytes());
-        // Write direction (1 byte)
-        serialized.push(match batch.direction {
+        // Serialize the batch header, data and checksum
+        let direction = match batch.direction {
             BatchDirection::Incoming => 0,
             BatchDirection::Outgoing => 1,
-        });
-        // Write data length (8 bytes)
-        serialized.extend_from_slice(&(batch.data.len() as u64).to_le_bytes());
-        // Write the actual data
-        serialized.extend_from_slice(&batch.data);
-
+        };
+        let clock = batch.clock_before().unwrap_or(UNKNOWN_CLOCK);
         // Get list of runtimes to process
-        let runtimes_to_process: Vec<(u64, Arc<Mutex<TcpStream>>)> = conns.iter()
-            .filter(|(_, conn)| conn.last_processed_batch <= batch.number)
-            .map(|(id, conn)| (*id, conn.stream.clone()))
+        let runtimes_to_process: Vec<(u64, Arc<Mutex<TcpStream>>, u8, bool)> = conns.iter()
+            .filter(|(_, conn)| conn.last_sent_batch <= batch.number)
+            .map(|(id, conn)| (*id, conn.stream.clone(), conn.wire_version, conn.compress))
             .collect();
 
         // Release the lock before sending
         drop(conns);
 
+        // Each encoding the runtimes need is made once
+        let mut encoded: HashMap<(u8, bool), Vec<u8>> = HashMap::new();
+
         // Process each runtime
-        for (runtime_id, stream) in runtimes_to_process {
+        for (runtime_id, stream, wire_version, compress) in runtimes_to_process {
+            let serialized = encoded.entry((wire_version, compress)).or_insert_with(|| {
+                let serialized = encode_wire(batch.number, direction, clock, &batch.data, wire_version, compress);
+                if split_wire_direction(serialized[8]).2 & WIRE_ZSTD != 0 {
+                    info!("Compressed batch {} from {} to {} bytes", batch.number, batch.data.len(), serialized.len());
+                }
+                serialized
+            });
             debug!("Sending batch {} to runtime {} (last processed: {})", 
                 batch.number, runtime_id, batch.number - 1);
             
             let mut stream_guard = stream.lock().unwrap();
-            match stream_guard.write_all(&serialized) {
+            match stream_guard.write_all(serialized) {
                 Ok(_) => {
                     debug!("Batch {} sent to runtime {}", batch.number, runtime_id);
                     if let Err(e) = stream_guard.flush() {
                         error!("Failed to flush batch {} to runtime {}: {}", batch.number, runtime_id, e);
                         error_count += 1;
+                        drop(stream_guard);
                         // Remove runtime if we get a broken pipe error
                         if e.kind() == io::ErrorKind::BrokenPipe {
                             let mut conns = self.runtimes.lock().unwrap();
@@ -177,10 +336,12 @@ impl RuntimeManager {
                         }
                         continue;
                     }
-                    // Update last processed batch
+                    // The reader thread takes the map lock before a stream's,
+                    // so never hold a stream while waiting for the map
+                    drop(stream_guard);
                     let mut conns = self.runtimes.lock().unwrap();
                     if let Some(conn) = conns.get_mut(&runtime_id) {
-                        conn.last_processed_batch = batch.number;
+                        conn.last_sent_batch = batch.number;
                     }
                     sent_count += 1;
                     info!("Successfully sent batch {} to runtime {} ({} bytes)", 
@@ -189,6 +350,7 @@ impl RuntimeManager {
                 Err(e) => {
                     error!("Failed to send batch {} to runtime {}: {}", batch.number, runtime_id, e);
                     error_count += 1;
+                    drop(stream_guard);
                     // Remove runtime if we get a broken pipe error
                     if e.kind() == io::ErrorKind::BrokenPipe {
                         let mut conns = self.runtimes.lock().unwrap();
@@ -204,7 +366,51 @@ impl RuntimeManager {
             batch.number, sent_count, error_count);
     }
 
+    /// Ends the session for every connected runtime: each connection is shut
+    /// down for writing between two batches, so the runtime reads a clean
+    /// close and stops instead of reconnecting. Called on `exit`. Waits up to
+    /// `DISCONNECT_TIMEOUT` for the reader thread to see each runtime close
+    /// its end: exiting with records from a runtime unread would reset its
+    /// connection, and it would take that for a lost one and reconnect.
+    pub fn disconnect_all(&self) {
+        let conns = self.runtimes.lock().unwrap();
+        for (runtime_id, conn) in conns.iter() {
+            // broadcast_batch holds the lock for a whole batch
+            let stream = conn.stream.lock().unwrap();
+            if let Err(e) = stream.shutdown(Shutdown::Write) {
+                warn!("Failed to close the connection to runtime {}: {}", runtime_id, e);
+            }
+        }
+        drop(conns);
+        let deadline = Instant::now() + DISCONNECT_TIMEOUT;
+        while !self.runtimes.lock().unwrap().is_empty() {
+            if Instant::now() >= deadline {
+                warn!("Runtimes {:?} did not close their connections in time",
+                    self.runtimes.lock().unwrap().keys().collect::<Vec<_>>());
+                return;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        info!("Every runtime closed its connection");
+    }
+
+    /// Records that `runtime_id` has applied every batch up to `batch_number`,
+    /// for a BatchAck record it sent. It never moves back, so an ack that is
+    /// not past the last one changes nothing.
+    pub fn acknowledge(&self, runtime_id: u64, batch_number: u64) {
+        let mut conns = self.runtimes.lock().unwrap();
+        match conns.get_mut(&runtime_id) {
+            Some(conn) if batch_number > conn.last_processed_batch => {
+                conn.last_processed_batch = batch_number;
+                info!("Runtime {} has processed batches up to {}", runtime_id, batch_number);
+            }
+            Some(_) => debug!("Runtime {} acknowledged batch {} again", runtime_id, batch_number),
+            None => debug!("Batch {} acknowledged by runtime {}, which is gone", batch_number, runtime_id),
+        }
+    }
+
     /// Sends the session file (all previous batches) to a specific runtime.
+    #[allow(dead_code)]
     pub fn send_session_file(&self, runtime_id: u64, session_data: &[u8], batch_number: u64) -> io::Result<()> {
         info!("Sending session file to runtime {} ({} bytes, up to batch {})", 
             runtime_id, session_data.len(), batch_number);
@@ -214,7 +420,7 @@ impl RuntimeManager {
                 error!("Failed to send session file to runtime {}: {}", runtime_id, e);
                 return Err(e);
             }
-            conn.last_processed_batch = batch_number;
+            conn.last_sent_batch = batch_number;
             info!("Successfully sent session file to runtime {}", runtime_id);
             Ok(())
         } else {
@@ -223,26 +429,8 @@ impl RuntimeManager {
         }
     }
 
-    /// Handles an outgoing batch from a runtime. Returns true if the batch was processed, false if it was ignored.
-    pub fn handle_outgoing_batch(&self, runtime_id: u64, batch: &Batch) -> bool {
-        debug!("Handling outgoing batch {} from runtime {}", batch.number, runtime_id);
-        let mut conns = self.runtimes.lock().unwrap();
-        if let Some(conn) = conns.get_mut(&runtime_id) {
-            if conn.last_processed_batch < batch.number {
-                info!("Processing outgoing batch {} from runtime {}", batch.number, runtime_id);
-                conn.last_processed_batch = batch.number;
-                true
-            } else {
-                debug!("Ignoring outgoing batch {} from runtime {} (already processed)", batch.number, runtime_id);
-                false
-            }
-        } else {
-            error!("Runtime {} not found for outgoing batch", runtime_id);
-            false
-        }
-    }
-
     /// Returns a clone of the TcpStream for the first runtime in the runtimes map.
+    #[allow(dead_code)]
     pub fn get_runtime_stream(&self) -> io::Result<TcpStream> {
         debug!("Attempting to get stream for first runtime");
         let conns = self.runtimes.lock().unwrap();
@@ -250,7 +438,7 @@ impl RuntimeManager {
             debug!("Found runtime {} for stream clone", runtime_id);
             conn.stream.lock().unwrap().try_clone().map_err(|e| {
                 error!("Failed to clone stream for runtime {}: {}", runtime_id, e);
-                io::Error::new(io::ErrorKind::Other, e)
+                io::Error::other(e)
             })
         } else {
             warn!("No runtimes available for stream clone");
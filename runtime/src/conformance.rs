@@ -0,0 +1,131 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use anyhow::{Context, Result};
+use log::{error, info};
+use serde_json::{json, Value};
+use crate::runtime::process::TerminationReason;
+use crate::selftest::run_guest;
+
+/// Where `runtime conformance` looks for tests when no directory is given.
+pub const DEFAULT_SUITE_DIR: &str = "wasm_programs/wasi_suite";
+/// Guests get pids from here up, one per test, so each has its own sandbox.
+const FIRST_PID: u64 = 1;
+
+/// One test of the suite: a guest module and what it should report.
+struct SuiteTest {
+    name: String,
+    module: PathBuf,
+    expected: Result<Vec<u32>>,
+    known_failure: Option<String>, // the reason, from <name>.xfail
+}
+
+/// Finds the tests in `dir`: every `<name>.wat` or `<name>.wasm`, with its
+/// expected report in `<name>.expected` (decimal u32s separated by
+/// whitespace) and, for a known gap, the reason in `<name>.xfail`.
+fn load_suite(dir: &Path) -> Result<Vec<SuiteTest>> {
+    let mut modules: Vec<PathBuf> = fs::read_dir(dir)
+        .with_context(|| format!("cannot read the test suite in {}", dir.display()))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| matches!(path.extension().and_then(|e| e.to_str()), Some("wat" | "wasm")))
+        .collect();
+    modules.sort();
+    Ok(modules
+        .into_iter()
+        .map(|module| {
+            let name = module.file_stem().unwrap_or_default().to_string_lossy().into_owned();
+            let expected_path = module.with_extension("expected");
+            let expected = fs::read_to_string(&expected_path)
+                .with_context(|| format!("missing {}", expected_path.display()))
+                .and_then(|text| {
+                    text.split_whitespace()
+                        .map(|word| word.parse::<u32>().with_context(|| format!("bad value {:?} in {}", word, expected_path.display())))
+                        .collect()
+                });
+            let known_failure = fs::read_to_string(module.with_extension("xfail"))
+                .ok()
+                .map(|reason| reason.trim().to_string());
+            SuiteTest { name, module, expected, known_failure }
+        })
+        .collect())
+}
+
+/// Runs one test. Returns its report entry, and whether it counts against
+/// the run (a failure that is not a known one, or a test that could not run).
+fn run_test(test: &SuiteTest, pid: u64) -> (Value, bool) {
+    let expected = match &test.expected {
+        Ok(expected) => expected,
+        Err(e) => return (json!({ "name": test.name, "result": "error", "note": format!("{:#}", e) }), true),
+    };
+    let run = fs::read(&test.module)
+        .with_context(|| format!("cannot read {}", test.module.display()))
+        .and_then(|module| run_guest(module, pid));
+    let run = match run {
+        Ok(run) => run,
+        Err(e) => return (json!({ "name": test.name, "result": "error", "note": format!("{:#}", e) }), true),
+    };
+
+    // The guest reports what it observed as little-endian u32s
+    let observed: Vec<u32> = run.report
+        .chunks_exact(4)
+        .map(|word| u32::from_le_bytes(word.try_into().unwrap()))
+        .collect();
+    let passed = run.scheduled.is_ok()
+        && run.termination == Some(TerminationReason::Exited)
+        && observed == *expected;
+    let (result, counts_against) = match (passed, &test.known_failure) {
+        (true, None) => ("pass", false),
+        (true, Some(_)) => ("xpass", false),
+        (false, Some(_)) => ("xfail", false),
+        (false, None) => ("fail", true),
+    };
+    let mut entry = json!({
+        "name": test.name,
+        "result": result,
+        "expected": expected,
+        "observed": observed,
+        "termination": run.termination.map(|reason| reason.to_string()),
+    });
+    if let Err(e) = &run.scheduled {
+        entry["error"] = json!(e.to_string());
+    }
+    if let Some(reason) = &test.known_failure {
+        entry["known_failure"] = json!(reason);
+    }
+    (entry, counts_against)
+}
+
+/// Runs every test in `dir` and writes a JSON report to `report_path` (stdout
+/// if none; some syscalls print there too): one entry per test with its result
+/// (pass, fail, xfail, xpass or error) and what the guest observed, then a
+/// summary. Returns whether the run passed, i.e. nothing failed except known
+/// failures.
+pub fn run(dir: &Path, report_path: Option<&Path>) -> Result<bool> {
+    let suite = load_suite(dir)?;
+    info!("Running {} conformance tests from {}", suite.len(), dir.display());
+    let mut tests = Vec::new();
+    let mut ok = true;
+    for (index, test) in suite.iter().enumerate() {
+        let (entry, counts_against) = run_test(test, FIRST_PID + index as u64);
+        if counts_against {
+            error!("Conformance test {} failed: {}", test.name, entry);
+            ok = false;
+        }
+        tests.push(entry);
+    }
+    let count = |result: &str| tests.iter().filter(|test| test["result"] == result).count();
+    let summary = json!({
+        "pass": count("pass"),
+        "fail": count("fail"),
+        "xfail": count("xfail"),
+        "xpass": count("xpass"),
+        "error": count("error"),
+    });
+    let report = json!({ "suite": dir.display().to_string(), "tests": tests, "summary": summary });
+    let report = serde_json::to_string_pretty(&report)?;
+    match report_path {
+        Some(path) => fs::write(path, report + "\n").with_context(|| format!("cannot write {}", path.display()))?,
+        None => println!("{}", report),
+    }
+    info!("Conformance run {}", if ok { "passed" } else { "failed" });
+    Ok(ok)
+}
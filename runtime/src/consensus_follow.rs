@@ -0,0 +1,71 @@
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
+use std::time::Duration;
+use log::{debug, info, warn};
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+
+/// How often the file is re-read when filesystem notifications are unavailable.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Waits for records to be appended to a consensus file, `tail -f` style.
+///
+/// Uses the platform's file notifications (inotify, kqueue, ...) through the
+/// `notify` crate, and falls back to polling where they are not available.
+/// The parent directory is watched rather than the file itself, so a file
+/// that does not exist yet (or is recreated) is still picked up.
+pub struct FileFollower {
+    // Kept alive for as long as we follow; dropping it stops the notifications
+    watcher: Option<(RecommendedWatcher, Receiver<()>)>,
+}
+
+impl FileFollower {
+    pub fn new(path: &Path) -> Self {
+        let watcher = match watch(path) {
+            Ok(watcher) => {
+                info!("Following {} using filesystem notifications", path.display());
+                Some(watcher)
+            }
+            Err(e) => {
+                warn!("Cannot watch {} ({}); polling every {:?} instead", path.display(), e, POLL_INTERVAL);
+                None
+            }
+        };
+        FileFollower { watcher }
+    }
+
+    /// Blocks until the file may have changed or `timeout` passes. A spurious
+    /// wake-up is harmless: the caller simply finds nothing new to read.
+    pub fn wait_for_change(&self, timeout: Duration) {
+        match &self.watcher {
+            Some((_, changes)) => {
+                if changes.recv_timeout(timeout).is_ok() {
+                    // One read picks up everything appended so far
+                    while changes.try_recv().is_ok() {}
+                }
+            }
+            None => thread::sleep(POLL_INTERVAL.min(timeout)),
+        }
+    }
+}
+
+fn watch(path: &Path) -> notify::Result<(RecommendedWatcher, Receiver<()>)> {
+    let file_name = path.file_name().map(|name| name.to_os_string());
+    let dir: PathBuf = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+
+    let (tx, rx) = channel();
+    let mut watcher = notify::recommended_watcher(move |res: notify::Result<Event>| match res {
+        Ok(event) => {
+            let ours = event.paths.iter().any(|p| p.file_name() == file_name.as_deref());
+            if ours {
+                let _ = tx.send(());
+            }
+        }
+        Err(e) => debug!("File watch error: {}", e),
+    })?;
+    watcher.watch(&dir, RecursiveMode::NonRecursive)?;
+    Ok((watcher, rx))
+}
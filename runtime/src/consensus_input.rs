@@ -1,25 +1,187 @@
 use anyhow::Result;
 use std::io::{BufReader, Read, Seek, SeekFrom, Write};
+use std::collections::VecDeque;
 use std::fs::File;
 use byteorder::{LittleEndian, ReadBytesExt};
-use log::{info, error, debug};
-use std::sync::atomic::{AtomicU64, Ordering};
+use log::{info, warn, error, debug};
+use std::sync::atomic::{AtomicBool, AtomicU8, AtomicU64, Ordering};
+use std::sync::Mutex;
 use crate::runtime::clock::GlobalClock;
 use crate::runtime::process;
 use crate::wasi_syscalls::net::OutgoingNetworkMessage;
+use crate::wasi_syscalls::builtin_report::take_pending_reports;
+use consensus::redact;
+use consensus::batch::{decode_wire_data, encode_wire, max_payload_bytes, skip_bytes, split_wire_direction, wire_checksum, BASE_WIRE_VERSION, UNKNOWN_CLOCK, WIRE_ACCEPTS_ZSTD, WIRE_VERSION};
+use consensus::record::{canonical_order, read_records, RawRecord};
+use consensus::commands::{NetworkStatus, NetworkStatusCode, MAX_INIT_N};
 use crate::runtime::fd_table::FDEntry;
-use bincode;
+use crate::runtime::process::{BlockReason, TerminationReason};
+use crate::runtime::compile::{self, PendingStart};
 
 // Use an AtomicU64 for generating unique process IDs.
 static NEXT_PID: AtomicU64 = AtomicU64::new(1);
 // Track file position for consensus file
 static FILE_POSITION: AtomicU64 = AtomicU64::new(0);
 static OUTGOING_BATCH_NUMBER: AtomicU64 = AtomicU64::new(1);
+// (number, data) of outgoing batches a failed write kept from consensus, sent
+// first on the next connection. Consensus skips any it had already received
+static UNSENT_BATCHES: Mutex<Vec<(u64, Vec<u8>)>> = Mutex::new(Vec::new());
+// Number of the incoming batch applied last, which __builtin_rt_batch returns
+static CURRENT_BATCH: AtomicU64 = AtomicU64::new(0);
+// Records applied so far, over all batches: the cursor `log_applied_record` logs
+static APPLIED_CURSOR: AtomicU64 = AtomicU64::new(0);
+// (msg_type, pid, payload) of status records for consensus, sent with the next outgoing batch
+static PENDING_STATUS: Mutex<Vec<(u8, u64, Vec<u8>)>> = Mutex::new(Vec::new());
+// Processes of Init and InitN records whose modules are still compiling, in pid order
+static PENDING_STARTS: Mutex<Vec<PendingStart>> = Mutex::new(Vec::new());
+// The rest of a batch whose application stopped early so a guest could read its stdin
+static UNAPPLIED_BATCH: Mutex<Option<UnappliedBatch>> = Mutex::new(None);
+// Number of the last batch read off a consensus connection. A reconnected
+// runtime may be sent batches it already had, and skips them
+static LAST_READ_BATCH: Mutex<Option<u64>> = Mutex::new(None);
+// Highest batch acknowledged with a BatchAck record, 0 before the first. The
+// hello on a new connection repeats it, so consensus resumes after it
+static LAST_ACKED_BATCH: AtomicU64 = AtomicU64::new(0);
+// The wire format of the last batch from consensus, which outgoing batches are sent in
+static CONSENSUS_WIRE_VERSION: AtomicU8 = AtomicU8::new(BASE_WIRE_VERSION);
+// Whether consensus reads compressed batches, as the last batch from it said
+static CONSENSUS_ACCEPTS_ZSTD: AtomicBool = AtomicBool::new(false);
+
+struct UnappliedBatch {
+    number: u64,
+    /// The `GlobalClock` consensus stamped on the batch, if it did
+    clock: Option<u64>,
+    records: VecDeque<(u8, u64, Vec<u8>)>,
+    applied: usize,
+}
+
+/// Outcome of reading one batch from a live consensus connection.
+#[derive(Debug)]
+pub enum PipeStatus {
+    /// A batch was read and applied; keep reading.
+    Batch,
+    /// Consensus closed the connection between batches; no more input will come.
+    Closed,
+    /// The connection failed, or closed in the middle of a batch.
+    Lost(std::io::Error),
+}
+
+/// Queues a record telling consensus how an incoming record was applied.
+fn queue_status(msg_type: u8, pid: u64, payload: Vec<u8>) {
+    PENDING_STATUS.lock().unwrap().push((msg_type, pid, payload));
+}
+
+/// Queues a BatchAck (15) record for a batch whose records have all been
+/// applied, or that was applied before.
+fn queue_ack(batch_number: u64) {
+    LAST_ACKED_BATCH.fetch_max(batch_number, Ordering::SeqCst);
+    queue_status(15, batch_number, Vec::new());
+}
+
+/// Queues a ProcessExit (14) record for a process the scheduler has joined:
+/// `"code:<n>"` with what it passed to proc_exit, or 0 if `_start` returned,
+/// and `"error:<reason>"` if it was ended any other way. A trap's guest
+/// backtrace follows the reason on the next lines.
+pub fn queue_process_exit(pid: u64, exit_code: Option<i32>, termination: Option<TerminationReason>, backtrace: Option<String>) {
+    let mut status = match (exit_code, termination) {
+        (Some(code), _) => format!("code:{}", code),
+        (None, Some(TerminationReason::Exited)) => "code:0".to_string(),
+        (None, Some(reason)) => format!("error:{}", reason),
+        (None, None) => "error:unknown".to_string(),
+    };
+    if let (None, Some(backtrace)) = (exit_code, backtrace) {
+        status.push('\n');
+        status.push_str(&backtrace);
+    }
+    queue_status(14, pid, status.into_bytes());
+}
+
+/// Records that the records being applied come from batch `number`, and
+/// tells the processes, for `__builtin_rt_batch`.
+fn set_current_batch(processes: &[process::Process], number: u64) {
+    CURRENT_BATCH.store(number, Ordering::SeqCst);
+    for process in processes {
+        process.data.batch.store(number, Ordering::SeqCst);
+    }
+}
+
+/// Logs a record about to be applied, as the `applied_record` log target at
+/// info level: the applied cursor, which counts every record applied so far
+/// from 1, then the batch number, the record's index in the batch (from 0),
+/// its type, pid and payload length. Replicas that apply the same records
+/// log the same lines, so diffing two replicas' `applied_record` lines
+/// finds the first record where their input differs.
+fn log_applied_record(batch_number: u64, index: usize, msg_type: u8, process_id: u64, payload: &[u8]) {
+    let cursor = APPLIED_CURSOR.fetch_add(1, Ordering::SeqCst) + 1;
+    info!(target: "applied_record", "cursor={} batch={} record={} type={} pid={} payload_len={}",
+        cursor, batch_number, index, msg_type, process_id, payload.len());
+}
 
 fn get_next_pid() -> u64 {
     NEXT_PID.fetch_add(1, Ordering::SeqCst)
 }
 
+/// Caps on how much consensus input one run applies, for bounded CI and
+/// fuzzing runs. Unset limits mean the input is read until it ends (or forever).
+#[derive(Debug, Clone, Copy, Default)]
+pub struct InputLimits {
+    pub max_batches: Option<u64>,
+    pub max_records: Option<u64>,
+}
+
+impl InputLimits {
+    /// Applies `--max-batches=<n>` or `--max-records=<n>`. Returns whether
+    /// `arg` was one of them, so callers can strip it from the positional args.
+    pub fn apply_flag(&mut self, arg: &str) -> bool {
+        let (limit, value) = if let Some(value) = arg.strip_prefix("--max-batches=") {
+            (&mut self.max_batches, value)
+        } else if let Some(value) = arg.strip_prefix("--max-records=") {
+            (&mut self.max_records, value)
+        } else {
+            return false;
+        };
+        match value.parse() {
+            Ok(n) => *limit = Some(n),
+            Err(_) => log::warn!("Ignoring invalid input limit: {}", arg),
+        }
+        true
+    }
+}
+
+/// Counts the batches and records applied so far against `InputLimits`.
+/// A batch is one frame read from the consensus pipe, or in a consensus file
+/// the records up to and including a Clock record.
+#[derive(Debug, Default)]
+pub struct InputBudget {
+    limits: InputLimits,
+    pub batches: u64,
+    pub records: u64,
+}
+
+impl InputBudget {
+    pub fn new(limits: InputLimits) -> Self {
+        InputBudget { limits, batches: 0, records: 0 }
+    }
+
+    /// Whether any limit is set at all.
+    pub fn is_bounded(&self) -> bool {
+        self.limits.max_batches.is_some() || self.limits.max_records.is_some()
+    }
+
+    /// Whether another record may be applied.
+    fn record_allowed(&self) -> bool {
+        self.limits.max_records.is_none_or(|max| self.records < max)
+    }
+
+    /// Whether the run has consumed all the input it is allowed to. Processes
+    /// that are still compiling count as input not yet applied.
+    pub fn exhausted(&self) -> bool {
+        (self.limits.max_batches.is_some_and(|max| self.batches >= max)
+            || self.limits.max_records.is_some_and(|max| self.records >= max))
+            && PENDING_STARTS.lock().unwrap().is_empty()
+    }
+}
+
 /// Reads new records from a live consensus pipe/socket for one batch only.
 /// 
 /// Record format (total header: 1 byte msg_type, 8 bytes process_id, 2 bytes payload length):
@@ -28,25 +190,69 @@ fn get_next_pid() -> u64 {
 /// Supported message types:
 /// - **0**: Clock update. The payload must start with `"clock:"` followed by the nanoseconds value.
 /// - **1**: FD update. The payload is expected to be `"fd:<number>,body:<data>"`.
-/// - **2**: Init command. The payload is a WASM binary; a new process is created.
+/// - **2**: Init command. The payload is a WASM binary; a new process is created
+///   once it has compiled (see `finish_pending_starts`).
 /// - **3**: Msg command. The payload is expected to be `"msg:<message>"` (or just a message),
-///        and the message is sent (for example, to FD 0).
+///   and the message is sent (for example, to FD 0).
 /// - **4**: FTP update. (Logic to dispatch the FTP command can be added.)
 /// - **5**: NetworkIn. The payload is expected to be a network message.
+/// - **7** / **8**: Pause / Resume the process. The payload is empty.
+/// - **9**: CancelNetwork. The payload is empty.
+/// - **10**: SetQuota. The payload is the new disk quota as a little-endian
+///   u64, or empty to only report the process's usage and quota.
+/// - **11**: InitN. The payload is a little-endian u32 count followed by an
+///   Init payload; that many processes are started, with consecutive pids.
+/// - **13**: Shutdown. The payload is empty. The process, or with pid 0
+///   every process, is ended and reaped like one that exited.
+///
+/// Outgoing batches carry records of type **5** (NetworkOut, a bincode-serialized
+/// `NetworkOperation`), **6** (ProgressReport, the raw blob passed to
+/// `__builtin_rt_report`), **1** (an FD update that was refused, as
+/// `"fd:<number>,error:<reason>"`), **2** (an Init whose process could not
+/// be started, with the pid it was given and `"error:<reason>"`), **11** (InitN, the first pid assigned
+/// to an InitN record and, as a little-endian u32, how many were), **12**
+/// (BatchRejected, with the batch number as its pid and the reason as text),
+/// **14** (ProcessExit, sent once a process has finished and been joined,
+/// as `"code:<n>"` or `"error:<reason>"`) and **15** (BatchAck, with the
+/// number of a batch whose records have all been applied as its pid, and no
+/// payload).
+///
+/// A batch with a truncated or malformed record anywhere is rejected whole:
+/// none of its records are applied, and consensus is told with a
+/// BatchRejected record. So is a batch whose data does not match its CRC32
+/// (see `consensus::batch::WIRE_VERSION`).
+///
+/// Batches may arrive zstd-compressed, and outgoing ones are compressed once
+/// consensus has said it reads them (see `consensus::batch::WIRE_ZSTD`). They
+/// go out in the wire format of the last batch from consensus. A batch 0 is
+/// not applied.
+///
+/// A batch numbered no higher than one read before is skipped. Consensus
+/// resumes a reconnected runtime after the last batch it acknowledged (see
+/// `send_hello`), so it may see a batch it read but had not finished again.
+///
+/// Each FD update for stdin is a chunk of a stream. If it reaches a process
+/// blocked reading stdin, the rest of the batch is held back until the
+/// scheduler has run that process, so it reads each chunk on its own instead
+/// of everything the batch carried at once.
+///
+/// Returns what was seen on the connection: a batch that was applied, a clean
+/// close between batches, or a failure (including a close mid-batch).
 pub fn process_consensus_pipe<R: Read + Write>(
     reader: &mut BufReader<R>, 
     processes: &mut Vec<process::Process>,
     outgoing_messages: Vec<OutgoingNetworkMessage>,
-) -> Result<bool> {
+    budget: &mut InputBudget,
+) -> Result<PipeStatus> {
     let batch_start_time = std::time::Instant::now();
     debug!("Processing consensus pipe with {} outgoing messages", outgoing_messages.len());
 
-    // First, send any outgoing network messages as a batch
-    if !outgoing_messages.is_empty() {
+    // First, send any outgoing network messages and progress reports as a batch
+    let progress_reports = take_pending_reports();
+    let statuses = std::mem::take(&mut *PENDING_STATUS.lock().unwrap());
+    if !outgoing_messages.is_empty() || !progress_reports.is_empty() || !statuses.is_empty() {
         let batch_number = OUTGOING_BATCH_NUMBER.fetch_add(1, Ordering::SeqCst);
-        let direction = 1u8; // Outgoing
         let mut batch_data = Vec::new();
-        let start_time = std::time::Instant::now();
         
         for msg in outgoing_messages {
             debug!("Sending outgoing network message for process {}: {:?}", msg.pid, msg.operation);
@@ -59,80 +265,92 @@ pub fn process_consensus_pipe<R: Read + Write>(
             batch_data.extend_from_slice(&(op_bytes.len() as u32).to_le_bytes());
             batch_data.extend_from_slice(&op_bytes);
         }
+
+        for (pid, report) in progress_reports {
+            debug!("Sending progress report for process {} ({} bytes)", pid, report.len());
+            // Write message type (ProgressReport = 6)
+            batch_data.push(6);
+            batch_data.extend_from_slice(&pid.to_le_bytes());
+            batch_data.extend_from_slice(&(report.len() as u32).to_le_bytes());
+            batch_data.extend_from_slice(&report);
+        }
+
+        for (msg_type, pid, status) in statuses {
+            debug!("Sending status for a type {} record of process {} ({} bytes)", msg_type, pid, status.len());
+            batch_data.push(msg_type);
+            batch_data.extend_from_slice(&pid.to_le_bytes());
+            batch_data.extend_from_slice(&(status.len() as u32).to_le_bytes());
+            batch_data.extend_from_slice(&status);
+        }
         
-        // Write batch header
-        reader.get_mut().write_all(&batch_number.to_le_bytes())?;
-        reader.get_mut().write_all(&[direction])?;
-        reader.get_mut().write_all(&(batch_data.len() as u64).to_le_bytes())?;
-        // Write batch data
-        reader.get_mut().write_all(&batch_data)?;
+        UNSENT_BATCHES.lock().unwrap().push((batch_number, batch_data));
+    }
+
+    // Batches a lost connection kept back go out first, in order
+    let mut unsent = std::mem::take(&mut *UNSENT_BATCHES.lock().unwrap()).into_iter();
+    while let Some((batch_number, batch_data)) = unsent.next() {
+        let start_time = std::time::Instant::now();
+        let direction = 1u8; // Outgoing
+        // Write batch header, data and checksum
+        let writer = reader.get_mut();
+        if let Err(e) = writer.write_all(&encode_wire(batch_number, direction, GlobalClock::now(), &batch_data,
+            CONSENSUS_WIRE_VERSION.load(Ordering::SeqCst), CONSENSUS_ACCEPTS_ZSTD.load(Ordering::SeqCst))) {
+            error!("Failed to send outgoing batch {}: {}", batch_number, e);
+            let mut kept = UNSENT_BATCHES.lock().unwrap();
+            kept.push((batch_number, batch_data));
+            kept.extend(unsent);
+            if closed_after_last_batch(reader, &e) {
+                info!("Consensus closed the connection");
+                return Ok(PipeStatus::Closed);
+            }
+            return Ok(PipeStatus::Lost(e));
+        }
         
         let duration = start_time.elapsed();
         info!("Consensus sent outgoing batch {} ({} bytes) in {:?}", 
              batch_number, batch_data.len(), duration);
     }
 
-    // Read batch header (8 bytes for batch number, 1 byte for direction)
-    let mut batch_header = [0u8; 9];
-    if reader.read_exact(&mut batch_header).is_err() {
-        debug!("No batch header in consensus pipe");
-        return Ok(false);
+    if finish_pending_starts(processes, true) {
+        return Ok(PipeStatus::Batch);
     }
 
-    let batch_number = u64::from_le_bytes(batch_header[0..8].try_into().unwrap());
-    let direction = batch_header[8];
-    debug!("Received batch {} with direction {}", batch_number, direction);
-
-    // Read batch data length (8 bytes)
-    let mut data_len_buf = [0u8; 8];
-    if reader.read_exact(&mut data_len_buf).is_err() {
-        error!("Failed to read batch data length");
-        return Ok(false);
-    }
-    let data_len = u64::from_le_bytes(data_len_buf) as usize;
-    debug!("Batch {} data length: {} bytes", batch_number, data_len);
-
-    // Read the batch data
-    let mut batch_data = vec![0u8; data_len];
-    if reader.read_exact(&mut batch_data).is_err() {
-        error!("Failed to read batch data");
-        return Ok(false);
+    let mut batch = match UNAPPLIED_BATCH.lock().unwrap().take() {
+        Some(batch) => batch,
+        None => match read_batch(reader, budget) {
+            Ok(batch) => batch,
+            Err(status) => return Ok(status),
+        },
+    };
+    let batch_number = batch.number;
+    set_current_batch(processes, batch_number);
+    if let Some(clock) = batch.clock.take() {
+        // Normally a no-op; it moves the clock of a runtime that missed
+        // batches, or joined after the history it was sent was cut
+        if clock != GlobalClock::now() {
+            info!("Setting clock from {} to {} ns as stamped on batch {}", GlobalClock::now(), clock, batch_number);
+            GlobalClock::set_absolute(clock);
+        }
     }
-
-    // Process the batch data as a series of records
-    let mut data_reader = std::io::Cursor::new(batch_data);
-    let mut processed_records = 0;
-    loop {
-        // Read the message type (1 byte)
-        let mut msg_type_buf = [0u8; 1];
-        if data_reader.read_exact(&mut msg_type_buf).is_err() {
-            debug!("No more records in batch {} (processed {} records)", batch_number, processed_records);
-            break; // No more data.
+    while let Some((msg_type, process_id, payload)) = batch.records.pop_front() {
+        // The rest of the batch waits for a process started earlier in it
+        if waits_for_start(msg_type, process_id) {
+            batch.records.push_front((msg_type, process_id, payload));
+            *UNAPPLIED_BATCH.lock().unwrap() = Some(batch);
+            return Ok(PipeStatus::Batch);
         }
-        let msg_type = msg_type_buf[0];
-        debug!("Processing record type {} in batch {} (record {})", msg_type, batch_number, processed_records + 1);
-
-        // Read process_id (8 bytes)
-        let process_id = match data_reader.read_u64::<LittleEndian>() {
-            Ok(pid) => pid,
-            Err(_) => break,
-        };
-
-        // Read payload length (4 bytes)
-        let payload_len = match data_reader.read_u32::<LittleEndian>() {
-            Ok(sz) => sz as usize,
-            Err(_) => break,
-        };
-
-        debug!("Reading payload of {} bytes for process {} in batch {} (record {})", 
-            payload_len, process_id, batch_number, processed_records + 1);
-
-        // Read the payload.
-        let mut payload = vec![0u8; payload_len];
-        if let Err(e) = data_reader.read_exact(&mut payload) {
-            error!("Failed to read message from batch {}: {}", batch_number, e);
-            break;
+        if !budget.record_allowed() {
+            // Stop before the record, as for a file: the batch is only
+            // counted and acknowledged once all of it has been applied
+            info!("Record limit reached in batch {}", batch_number);
+            batch.records.push_front((msg_type, process_id, payload));
+            *UNAPPLIED_BATCH.lock().unwrap() = Some(batch);
+            return Ok(PipeStatus::Batch);
         }
+        log_applied_record(batch_number, batch.applied, msg_type, process_id, &payload);
+        budget.records += 1;
+        batch.applied += 1;
+        let mut woke_reader = false;
 
         match msg_type {
             0 => { // Clock update.
@@ -152,22 +370,22 @@ pub fn process_consensus_pipe<R: Read + Write>(
             },
             1 => { // FD update.
                 let msg_str = String::from_utf8_lossy(&payload);
-                debug!("Processing FD update for process {}: {}", process_id, msg_str);
+                debug!("Processing FD update for process {}: {}", process_id, redact::payload(&payload));
                 let parts: Vec<&str> = msg_str.split(",body:").collect();
                 if parts.len() != 2 {
-                    error!("Invalid FD update format for process {}: {}", process_id, msg_str);
+                    error!("Invalid FD update format for process {}: {}", process_id, redact::payload(&payload));
                     continue;
                 }
                 let fd: i32 = if let Some(fd_part) = parts[0].strip_prefix("fd:") {
                     match fd_part.trim().parse() {
                         Ok(num) => num,
                         Err(_) => {
-                            error!("Invalid FD in FD update for process {}: {}", process_id, msg_str);
+                            error!("Invalid FD in FD update for process {}: {}", process_id, redact::payload(&payload));
                             continue;
                         }
                     }
                 } else {
-                    error!("Missing FD prefix in FD update for process {}: {}", process_id, msg_str);
+                    error!("Missing FD prefix in FD update for process {}: {}", process_id, redact::payload(&payload));
                     continue;
                 };
                 let body = parts[1].trim();
@@ -176,12 +394,15 @@ pub fn process_consensus_pipe<R: Read + Write>(
                     if process.id == process_id {
                         found = true;
                         let mut table = process.data.fd_table.lock().unwrap();
-                        if let Some(Some(FDEntry::File { buffer, .. })) = table.entries.get_mut(fd as usize) {
-                            buffer.extend_from_slice(body.as_bytes());
-                            buffer.push(b'\n');
-                            info!("Added FD update to process {}'s FD {} ({} bytes)", process_id, fd, body.len());
-                        } else {
-                            error!("Process {} does not have FD {} open for FD update", process_id, fd);
+                        match table.inject_input(fd, &input_line(body), process.data.max_stdin_buffer) {
+                            Ok(()) => {
+                                info!("Added FD update to process {}'s FD {} ({} bytes)", process_id, fd, body.len());
+                                woke_reader = fd == 0 && waiting_for_stdin(process);
+                            }
+                            Err(e) => {
+                                error!("Refused FD update for process {} ({} bytes): FD {} is {}", process_id, body.len(), fd, e);
+                                queue_status(1, process_id, format!("fd:{},error:FD {} is {}", fd, fd, e).into_bytes());
+                            }
                         }
                         process.data.cond.notify_all();
                         break;
@@ -189,19 +410,16 @@ pub fn process_consensus_pipe<R: Read + Write>(
                 }
                 if !found {
                     error!("No process found with ID {} for FD update", process_id);
+                    queue_status(1, process_id, format!("fd:{},error:no process {}", fd, process_id).into_bytes());
                 }
             },
             2 => { // Init command.
                 debug!("Processing init command for new process");
-                let new_pid = get_next_pid();
-                match process::start_process_from_bytes(payload, new_pid) {
-                    Ok(proc) => {
-                        processes.push(proc);
-                        info!("Added new process {} to scheduler", new_pid);
-                    }
-                    Err(e) => {
-                        error!("Failed to create new process {}: {}", new_pid, e);
-                    }
+                start_process(payload, get_next_pid());
+            },
+            11 => {
+                if let Some((first_pid, count)) = apply_init_n_record(&payload) {
+                    queue_status(11, first_pid, count.to_le_bytes().to_vec());
                 }
             },
             3 => { // NetworkIn
@@ -226,12 +444,11 @@ pub fn process_consensus_pipe<R: Read + Write>(
                     if process.id == process_id {
                         found = true;
                         // If this is a success status message (port 0)
-                        if dest_port == 0 && data.len() >= 5 {  // Now we expect at least 5 bytes
-                            let status = data[0];
-                            let src_port = (data[1] as u16) | ((data[2] as u16) << 8);
-                            let new_port = (data[3] as u16) | ((data[4] as u16) << 8);
-                            match status {
-                                1 => { // Success
+                        let status = if dest_port == 0 { NetworkStatus::decode(data) } else { None };
+                        if let Some(NetworkStatus { code, src_port, new_port, peer }) = status {
+                            *process.data.network_pending.lock().unwrap() = false;
+                            match code {
+                                NetworkStatusCode::Success => {
                                     info!("Network operation succeeded for process {}:{}", process_id, src_port);
                                     // Update the runtime's NAT table to match consensus
                                     let mut nat_table = process.data.nat_table.lock().unwrap();
@@ -245,9 +462,10 @@ pub fn process_consensus_pipe<R: Read + Write>(
                                         // Find the socket with matching port
                                         let mut found = false;
                                         for (fd, entry) in table.entries.iter_mut().enumerate() {
-                                            if let Some(FDEntry::Socket { local_port, connected, .. }) = entry {
+                                            if let Some(FDEntry::Socket { local_port, connected, peer_addr, .. }) = entry {
                                                 if *local_port == new_port {
                                                     *connected = true;
+                                                    *peer_addr = peer.clone();
                                                     debug!("Marked socket FD {} as connected for process {}:{}", fd, process_id, new_port);
                                                     found = true;
                                                     break;
@@ -266,18 +484,35 @@ pub fn process_consensus_pipe<R: Read + Write>(
                                     } else {
                                         // Regular operation, just add mapping for src_port
                                         nat_table.add_port_mapping(process_id, src_port);
+                                        if peer.is_some() {
+                                            // A successful connect: remember who we are talking to.
+                                            // For a datagram socket it is the sender of the datagram
+                                            // just delivered, kept for sock_recv_from.
+                                            let mut table = process.data.fd_table.lock().unwrap();
+                                            for entry in table.entries.iter_mut() {
+                                                if let Some(FDEntry::Socket { local_port, connected, peer_addr, is_listener: false, datagram, .. }) = entry {
+                                                    if *local_port == src_port && !*datagram {
+                                                        *connected = true;
+                                                        *peer_addr = peer.clone();
+                                                    } else if *local_port == src_port && !*connected {
+                                                        *peer_addr = peer.clone();
+                                                    }
+                                                }
+                                            }
+                                        }
                                     }
                                     // Clear the waiting state
                                     nat_table.clear_waiting_accept(process_id, src_port);
                                 }
-                                2 => { // Still waiting
+                                NetworkStatusCode::Waiting => {
                                     debug!("Network operation still waiting for process {}:{}", process_id, src_port);
                                     // Keep the process blocked
                                     let mut nat_table = process.data.nat_table.lock().unwrap();
                                     nat_table.set_waiting_accept(process_id, src_port, 0);
                                 }
-                                _ => { // Failure
-                                    error!("Network operation failed for process {}:{}, status {}", process_id, src_port, status);
+                                NetworkStatusCode::Failed => {
+                                    error!("Network operation failed for process {}:{}", process_id, src_port);
+                                    *process.data.network_failed.lock().unwrap() = true;
                                     // Clear both waiting states to ensure process unblocks
                                     let mut nat_table = process.data.nat_table.lock().unwrap();
                                     nat_table.clear_waiting_accept(process_id, src_port);
@@ -353,73 +588,515 @@ pub fn process_consensus_pipe<R: Read + Write>(
                     error!("No process found with ID {} for NetworkIn", process_id);
                 }
             },
+            7 | 8 => apply_pause_record(processes, process_id, msg_type == 7),
+            9 => apply_cancel_network_record(processes, process_id),
+            10 => apply_set_quota_record(processes, process_id, &payload),
+            13 => apply_shutdown_record(processes, process_id),
             _ => {
                 error!("Unknown message type: {} in message", msg_type);
             }
         }
-        processed_records += 1;
+
+        // Stdin is delivered chunk by chunk: a guest blocked reading it gets
+        // to run on this chunk before the rest of the batch is applied
+        if woke_reader && !batch.records.is_empty() {
+            debug!("Pausing batch {} after record {} so process {} can read its input",
+                batch_number, batch.applied, process_id);
+            *UNAPPLIED_BATCH.lock().unwrap() = Some(batch);
+            return Ok(PipeStatus::Batch);
+        }
     }
 
+    budget.batches += 1;
+    // Sent with the next outgoing batch, before the next batch is read
+    queue_ack(batch_number);
     let batch_duration = batch_start_time.elapsed();
     
-    if processed_records > 1 {
+    if batch.applied > 1 {
         info!("Consensus processed batch {} with {} records in {:?}", 
-             batch_number, processed_records, batch_duration);
+             batch_number, batch.applied, batch_duration);
     }
     else {
         debug!("Consensus processed batch {} with {} records in {:?}", 
-             batch_number, processed_records, batch_duration);
+             batch_number, batch.applied, batch_duration);
     }
-    Ok(true) // For pipe mode, we always return true to keep scheduler running
+    Ok(PipeStatus::Batch)
 }
 
-pub fn process_consensus_file(file_path: &str, processes: &mut Vec<process::Process>) -> Result<bool> {
-    debug!("Processing consensus file: {}", file_path);
-    let file = File::open(file_path)?;
-    let mut reader = BufReader::new(file);
-    
-    // Seek to the current position
-    let current_pos = FILE_POSITION.load(Ordering::SeqCst);
-    debug!("Seeking to position {} in consensus file", current_pos);
-    reader.seek(SeekFrom::Start(current_pos))?;
-    
-    let mut processed_something = false;
+/// Reads and checks the next batch. The error is what to report to the
+/// scheduler when there is no batch to apply: the connection closed or
+/// failed, or the batch was rejected.
+fn read_batch<R: Read>(
+    reader: &mut BufReader<R>,
+    budget: &mut InputBudget,
+) -> std::result::Result<UnappliedBatch, PipeStatus> {
+    // Read batch header (8 bytes for batch number, 1 byte for direction)
+    let mut batch_header = [0u8; 9];
+    match read_batch_header(reader, &mut batch_header) {
+        Ok(true) => {}
+        Ok(false) => {
+            info!("Consensus closed the connection");
+            return Err(PipeStatus::Closed);
+        }
+        Err(e) => {
+            error!("Failed to read batch header: {}", e);
+            return Err(PipeStatus::Lost(e));
+        }
+    }
 
-    loop {
-        // Read the message type (1 byte)
-        let mut msg_type_buf = [0u8; 1];
-        if reader.read_exact(&mut msg_type_buf).is_err() {
-            // End of file reached
-            // Return true if we processed at least one command in this batch
-            // Return false if we reached EOF without processing anything
-            return Ok(processed_something);
+    let batch_number = u64::from_le_bytes(batch_header[0..8].try_into().unwrap());
+    let (version, direction, flags) = split_wire_direction(batch_header[8]);
+    debug!("Received batch {} with direction {} (format {})", batch_number, direction, version);
+    if version > WIRE_VERSION {
+        // Its framing is unknown, so nothing after it can be read
+        error!("Batch {} is in unknown format {}", batch_number, version);
+        return Err(PipeStatus::Lost(std::io::Error::new(std::io::ErrorKind::InvalidData, "unknown batch format")));
+    }
+    // Applying a batch past a gap would leave this replica without the
+    // missing ones for good; connecting again has consensus resend them
+    if let Some(last) = skips_batches(batch_number) {
+        error!("Batch {} follows batch {}; batches in between are missing", batch_number, last);
+        return Err(PipeStatus::Lost(std::io::Error::new(std::io::ErrorKind::InvalidData, "batches missing")));
+    }
+
+    // From format 2 on, the clock before the batch follows the header
+    let mut clock = None;
+    if version >= 2 {
+        let mut clock_buf = [0u8; 8];
+        if let Err(e) = reader.read_exact(&mut clock_buf) {
+            error!("Failed to read batch clock: {}", e);
+            return Err(PipeStatus::Lost(e));
         }
-        let msg_type = msg_type_buf[0];
+        clock = Some(u64::from_le_bytes(clock_buf)).filter(|&clock| clock != UNKNOWN_CLOCK);
+    }
 
-        // Read process_id (8 bytes)
-        let process_id = match reader.read_u64::<LittleEndian>() {
-            Ok(pid) => pid,
-            Err(_) => return Ok(processed_something), // End of file
-        };
+    // Read batch data length (8 bytes)
+    let mut data_len_buf = [0u8; 8];
+    if let Err(e) = reader.read_exact(&mut data_len_buf) {
+        error!("Failed to read batch data length: {}", e);
+        return Err(PipeStatus::Lost(e));
+    }
+    let data_len = u64::from_le_bytes(data_len_buf);
+    debug!("Batch {} data length: {} bytes", batch_number, data_len);
 
-        // Read payload length (4 bytes)
-        let payload_len = match reader.read_u32::<LittleEndian>() {
-            Ok(sz) => sz as usize,
-            Err(_) => return Ok(processed_something), // End of file
+    // A length past the limit is never allocated: the batch is skipped, so
+    // reading resumes at the next one, and rejected
+    let max_len = max_payload_bytes();
+    if data_len > max_len {
+        let crc_len = if version >= 1 { 4 } else { 0 };
+        if let Err(e) = skip_bytes(reader, data_len.saturating_add(crc_len)) {
+            error!("Failed to skip batch {} of {} bytes: {}", batch_number, data_len, e);
+            return Err(PipeStatus::Lost(e));
+        }
+        if batch_number == 0 {
+            return Err(PipeStatus::Batch);
+        }
+        if already_read(batch_number) {
+            queue_ack(batch_number);
+            return Err(PipeStatus::Batch);
+        }
+        error!("Rejected batch {}: {} bytes is over the {}-byte limit", batch_number, data_len, max_len);
+        queue_status(12, batch_number, format!("batch of {} bytes is over the {}-byte limit", data_len, max_len).into_bytes());
+        budget.batches += 1;
+        return Err(PipeStatus::Batch);
+    }
+    let data_len = data_len as usize;
+
+    // Read the batch data
+    let mut batch_data = vec![0u8; data_len];
+    if let Err(e) = reader.read_exact(&mut batch_data) {
+        error!("Failed to read batch data: {}", e);
+        return Err(PipeStatus::Lost(e));
+    }
+
+    // From format 1 on, the data is followed by its CRC32
+    let mut crc = None;
+    if version >= 1 {
+        let mut crc_buf = [0u8; 4];
+        if let Err(e) = reader.read_exact(&mut crc_buf) {
+            error!("Failed to read batch checksum: {}", e);
+            return Err(PipeStatus::Lost(e));
+        }
+        crc = Some(u32::from_le_bytes(crc_buf));
+    }
+
+    CONSENSUS_WIRE_VERSION.store(version, Ordering::SeqCst);
+    CONSENSUS_ACCEPTS_ZSTD.store(flags & WIRE_ACCEPTS_ZSTD != 0, Ordering::SeqCst);
+    if batch_number == 0 {
+        // Consensus numbers the batches of a session from 1
+        return Err(PipeStatus::Batch);
+    }
+
+    // A batch sent again after a reconnect was already handled; acknowledged
+    // again, so consensus learns where the runtime is
+    if already_read(batch_number) {
+        queue_ack(batch_number);
+        return Err(PipeStatus::Batch);
+    }
+
+    // A corrupted batch is rejected like a malformed one, before any of it is applied
+    if crc.is_some_and(|crc| crc != wire_checksum(&batch_data)) {
+        error!("Rejected batch {}: checksum mismatch", batch_number);
+        queue_status(12, batch_number, b"checksum mismatch".to_vec());
+        budget.batches += 1;
+        return Err(PipeStatus::Batch);
+    }
+    let wire_len = batch_data.len();
+    let batch_data = match decode_wire_data(flags, batch_data) {
+        Ok(batch_data) if batch_data.len() != wire_len => {
+            info!("Decompressed batch {} from {} to {} bytes", batch_number, wire_len, batch_data.len());
+            batch_data
+        }
+        Ok(batch_data) => batch_data,
+        Err(e) => {
+            error!("Rejected batch {}: cannot decompress it: {}", batch_number, e);
+            queue_status(12, batch_number, format!("cannot decompress: {}", e).into_bytes());
+            budget.batches += 1;
+            return Err(PipeStatus::Batch);
+        }
+    };
+
+    // Check the whole batch before applying any of it: a malformed batch is
+    // rejected as a unit, so no replica is left with part of it applied
+    let records = match read_records(&batch_data)
+        .map_err(|e| e.to_string())
+        .and_then(|records| validate_batch(&records).map(|_| records))
+    {
+        Ok(records) => records,
+        Err(reason) => {
+            error!("Rejected batch {}: {}", batch_number, reason);
+            queue_status(12, batch_number, reason.into_bytes());
+            budget.batches += 1;
+            return Err(PipeStatus::Batch);
+        }
+    };
+
+    let mut records: Vec<_> = records.into_iter().map(|(t, pid, payload)| (t, pid, payload.to_vec())).collect();
+    canonical_order(&mut records);
+    Ok(UnappliedBatch {
+        number: batch_number,
+        clock,
+        records: records.into(),
+        applied: 0,
+    })
+}
+
+/// Whether `process` is blocked in fd_read waiting for stdin.
+fn waiting_for_stdin(process: &process::Process) -> bool {
+    matches!(*process.data.block_reason.lock().unwrap(), Some(BlockReason::StdinRead))
+}
+
+/// Opens a new connection to consensus with a hello: a batch 0 in the newest
+/// wire format, which tells consensus this runtime reads it and compressed
+/// batches, before it sends the history. Once this runtime has acknowledged
+/// a batch, the hello carries a BatchAck (15) record for the last one, and
+/// consensus only sends the batches after it. Until a batch from consensus
+/// says otherwise, outgoing batches go out in version 2.
+pub fn send_hello<W: Write>(writer: &mut W) -> std::io::Result<()> {
+    CONSENSUS_WIRE_VERSION.store(BASE_WIRE_VERSION, Ordering::SeqCst);
+    CONSENSUS_ACCEPTS_ZSTD.store(false, Ordering::SeqCst);
+    let mut data = Vec::new();
+    let acked = LAST_ACKED_BATCH.load(Ordering::SeqCst);
+    if acked > 0 {
+        data.push(15);
+        data.extend_from_slice(&acked.to_le_bytes());
+        data.extend_from_slice(&0u32.to_le_bytes());
+        info!("Asking consensus to resume after batch {}", acked);
+    }
+    writer.write_all(&encode_wire(0, 1, UNKNOWN_CLOCK, &data, WIRE_VERSION, false))
+}
+
+/// Whether batch `number` was read before, on this connection or an earlier
+/// one; if not, it is recorded as read. Consensus numbers its batches in
+/// order, so anything not past the last one read is a batch sent again.
+fn already_read(number: u64) -> bool {
+    let mut last = LAST_READ_BATCH.lock().unwrap();
+    if last.is_some_and(|last| number <= last) {
+        debug!("Skipping batch {}, already read up to {}", number, last.unwrap());
+        return true;
+    }
+    *last = Some(number);
+    false
+}
+
+/// The last batch read, if batch `number` is more than one past it. The first
+/// batch read may be any, since the history sent to a runtime may be cut.
+fn skips_batches(number: u64) -> Option<u64> {
+    LAST_READ_BATCH.lock().unwrap().filter(|&last| number > last.saturating_add(1))
+}
+
+/// Fills `header` from `reader`. Returns false if the stream ended before its
+/// first byte, i.e. cleanly between batches; ending anywhere later is an error.
+fn read_batch_header<R: Read>(reader: &mut R, header: &mut [u8]) -> std::io::Result<bool> {
+    let mut filled = 0;
+    while filled < header.len() {
+        match reader.read(&mut header[filled..]) {
+            Ok(0) if filled == 0 => return Ok(false),
+            Ok(0) => return Err(std::io::ErrorKind::UnexpectedEof.into()),
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(true)
+}
+
+/// Whether a failed write, `error`, came from consensus having closed the
+/// connection cleanly after the last batch it sent: it sent a FIN, which is
+/// all that is left to read, and its end then reset the connection at this
+/// runtime's unread batch. Any other failure is a lost connection.
+fn closed_after_last_batch<R: Read>(reader: &mut BufReader<R>, error: &std::io::Error) -> bool {
+    use std::io::ErrorKind::{BrokenPipe, ConnectionReset};
+    // After a reset, the read returns at once
+    matches!(error.kind(), BrokenPipe | ConnectionReset)
+        && reader.buffer().is_empty()
+        && matches!(reader.get_mut().read(&mut [0u8; 1]), Ok(0))
+}
+
+/// Checks that every record in a batch is one the runtime can apply: a known
+/// type with a well-formed payload. Records that name a process that does not
+/// exist are not an error here; they are skipped when applied, which every
+/// replica does alike since they all have the same processes between batches.
+fn validate_batch(records: &[RawRecord<'_>]) -> std::result::Result<(), String> {
+    for (i, (msg_type, _, payload)) in records.iter().enumerate() {
+        let valid = match msg_type {
+            0 => std::str::from_utf8(payload).ok()
+                .and_then(|s| s.strip_prefix("clock:"))
+                .is_some_and(|delta| delta.trim().parse::<u64>().is_ok()),
+            1 => std::str::from_utf8(payload).ok()
+                .and_then(|s| s.split_once(",body:"))
+                .and_then(|(fd, _)| fd.strip_prefix("fd:"))
+                .is_some_and(|fd| fd.trim().parse::<i32>().is_ok()),
+            2 | 7 | 8 | 9 => true,
+            13 => payload.is_empty(),
+            3 => payload.len() >= 2,
+            10 => payload.is_empty() || payload.len() == 8,
+            11 => payload.len() >= 4 && payload[..4] != [0; 4],
+            _ => false,
         };
+        if !valid {
+            return Err(format!("record {} (type {}, {} bytes) is malformed", i + 1, msg_type, payload.len()));
+        }
+    }
+    Ok(())
+}
 
-        // Read the payload.
-        let mut payload = vec![0u8; payload_len];
-        if let Err(e) = reader.read_exact(&mut payload) {
-            error!("Failed to read message from file: {}", e);
-            return Ok(processed_something);
+/// Starts the processes of an InitN (11) record: `count` processes from the
+/// Init payload that follows the count, with consecutive pids. Returns the
+/// first pid and `count`, or None if the payload is malformed or asks for more
+/// than `MAX_INIT_N` processes.
+fn apply_init_n_record(payload: &[u8]) -> Option<(u64, u32)> {
+    let (count, init) = match payload.split_first_chunk::<4>() {
+        Some((count, init)) if (1..=MAX_INIT_N).contains(&u32::from_le_bytes(*count)) => (u32::from_le_bytes(*count), init),
+        Some((count, _)) => {
+            error!("Rejected InitN for {} processes: the count must be 1..={}", u32::from_le_bytes(*count), MAX_INIT_N);
+            return None;
+        }
+        None => {
+            error!("Invalid InitN payload of {} bytes", payload.len());
+            return None;
+        }
+    };
+    // Reserve the whole range up front so the pids are consecutive even if a start fails
+    let first_pid = NEXT_PID.fetch_add(count as u64, Ordering::SeqCst);
+    for pid in first_pid..first_pid + count as u64 {
+        start_process(init.to_vec(), pid);
+    }
+    info!("Starting processes {}..={} for InitN", first_pid, first_pid + count as u64 - 1);
+    Some((first_pid, count))
+}
+
+/// Starts compiling the module of an Init payload for process `pid`. The
+/// process joins the scheduler in `finish_pending_starts`.
+fn start_process(payload: Vec<u8>, pid: u64) {
+    match compile::start_in_background(payload, pid) {
+        Ok(pending) => PENDING_STARTS.lock().unwrap().push(pending),
+        Err(e) => error!("Failed to create new process {}: {}", pid, e),
+    }
+}
+
+/// Adds the processes of earlier Init records to `processes` once their
+/// modules have compiled, waiting for them if needed. With `report`, Inits
+/// that failed are reported to consensus. Returns whether there were any;
+/// if so, the caller applies no more input until the scheduler has run them.
+///
+/// Waiting here rather than at the Init record lets the processes that are
+/// already running continue while a large module compiles, and every
+/// replica still starts the process at the same point: the first time the
+/// scheduler asks for input after the Init.
+fn finish_pending_starts(processes: &mut Vec<process::Process>, report: bool) -> bool {
+    let pending = std::mem::take(&mut *PENDING_STARTS.lock().unwrap());
+    if pending.is_empty() {
+        return false;
+    }
+    for start in pending {
+        let pid = start.id;
+        match start.finish() {
+            Ok(proc) => {
+                proc.data.batch.store(CURRENT_BATCH.load(Ordering::SeqCst), Ordering::SeqCst);
+                processes.push(proc);
+                info!("Added new process {} to scheduler", pid);
+            }
+            Err(e) => {
+                error!("Failed to create new process {}: {:#}", pid, e);
+                if report {
+                    queue_status(2, pid, format!("error:{:#}", e).into_bytes());
+                }
+            }
+        }
+    }
+    true
+}
+
+/// Whether a record of `msg_type` for `pid` is for a process whose Init was
+/// applied but which has not joined the scheduler yet, as when the Init came
+/// earlier in the same batch. Such a record is held back, with the rest of
+/// the batch, until `finish_pending_starts` has added the process, so it is
+/// applied to the process rather than dropped for want of one.
+fn waits_for_start(msg_type: u8, pid: u64) -> bool {
+    if !matches!(msg_type, 1 | 3 | 7 | 8 | 9 | 10 | 13) {
+        return false;
+    }
+    let pending = PENDING_STARTS.lock().unwrap();
+    // Shutdown 0 ends every process, those still starting included
+    pending.iter().any(|start| start.id == pid || (msg_type == 13 && pid == 0))
+}
+
+/// Injected text is delivered to the guest as one line.
+fn input_line(text: &str) -> Vec<u8> {
+    let mut line = Vec::with_capacity(text.len() + 1);
+    line.extend_from_slice(text.as_bytes());
+    line.push(b'\n');
+    line
+}
+
+/// Pauses or resumes `process_id` for a Pause (7) or Resume (8) record.
+fn apply_pause_record(processes: &[process::Process], process_id: u64, pause: bool) {
+    let process = match processes.iter().find(|p| p.id == process_id) {
+        Some(process) => process,
+        None => {
+            error!("No process found with ID {} to {}", process_id, if pause { "pause" } else { "resume" });
+            return;
+        }
+    };
+    if pause {
+        if process::pause_process(&process.data) {
+            info!("Paused process {}", process_id);
+        } else {
+            info!("Process {} is already paused or finished; ignoring Pause", process_id);
+        }
+    } else if process::resume_process(&process.data) {
+        info!("Resumed process {}", process_id);
+    } else {
+        info!("Process {} is not paused; ignoring Resume", process_id);
+    }
+}
+
+/// Fails the pending network operations of `process_id` for a CancelNetwork (9) record.
+fn apply_cancel_network_record(processes: &[process::Process], process_id: u64) {
+    match processes.iter().find(|p| p.id == process_id) {
+        Some(process) if process::cancel_network(&process.data) => {
+            info!("Cancelled pending network operations of process {}", process_id);
+        }
+        Some(_) => info!("Process {} is not waiting on the network; ignoring CancelNetwork", process_id),
+        None => error!("No process found with ID {} to cancel network operations", process_id),
+    }
+}
+
+/// Ends `process_id`, or every process for pid 0, for a Shutdown (13)
+/// record. The scheduler then joins its thread and removes its sandbox.
+fn apply_shutdown_record(processes: &[process::Process], process_id: u64) {
+    let mut found = false;
+    for process in processes.iter().filter(|p| process_id == 0 || p.id == process_id) {
+        found = true;
+        if process::shut_down(&process.data) {
+            info!("Shut down process {}", process.id);
+        } else {
+            info!("Process {} has already finished; ignoring Shutdown", process.id);
+        }
+    }
+    if !found && process_id != 0 {
+        error!("No process found with ID {} to shut down", process_id);
+    }
+}
+
+/// Changes (or, with an empty payload, reports) the disk quota of
+/// `process_id` for a SetQuota (10) record.
+fn apply_set_quota_record(processes: &[process::Process], process_id: u64, payload: &[u8]) {
+    let process = match processes.iter().find(|p| p.id == process_id) {
+        Some(process) => process,
+        None => {
+            error!("No process found with ID {} for SetQuota", process_id);
+            return;
         }
+    };
+    let bytes = match payload.try_into() {
+        Ok(bytes) => u64::from_le_bytes(bytes),
+        Err(_) => {
+            if !payload.is_empty() {
+                error!("Invalid SetQuota payload of {} bytes for process {}", payload.len(), process_id);
+            }
+            let (usage, quota) = process::disk_usage(&process.data);
+            info!("Process {} uses {} of {} bytes of disk quota", process_id, usage, quota);
+            return;
+        }
+    };
+    match process::set_disk_quota(&process.data, bytes) {
+        Ok(()) => info!("Set disk quota of process {} to {} bytes", process_id, bytes),
+        Err(usage) => error!(
+            "Refusing to set disk quota of process {} to {} bytes; it already uses {}",
+            process_id, bytes, usage
+        ),
+    }
+}
+
+pub fn process_consensus_file(
+    file_path: &str,
+    follow: bool,
+    processes: &mut Vec<process::Process>,
+    budget: &mut InputBudget,
+) -> Result<bool> {
+    debug!("Processing consensus file: {}", file_path);
+    let mut processed_something = false;
+
+    // There is no consensus peer to forward reports to in file mode; just log them.
+    for (pid, report) in take_pending_reports() {
+        info!("Progress report from process {}: {}", pid, redact::payload(&report));
+    }
+    if finish_pending_starts(processes, false) {
+        return Ok(true);
+    }
 
-        // Save the current position after reading this record
-        let current_pos = reader.stream_position()?;
-        FILE_POSITION.store(current_pos, Ordering::SeqCst);
+    // A file has no batch numbers; batches end with a clock record, as for --max-batches
+    let mut batch = match UNAPPLIED_BATCH.lock().unwrap().take() {
+        Some(batch) => batch,
+        None => match read_file_batch(file_path, budget.batches + 1, follow)? {
+            Some(batch) => batch,
+            // End of file reached without a new record
+            None => return Ok(false),
+        },
+    };
+    set_current_batch(processes, batch.number);
 
+    while let Some((msg_type, process_id, payload)) = batch.records.pop_front() {
+        // As for a live connection, the rest of the batch waits for a process
+        // started earlier in it
+        if waits_for_start(msg_type, process_id) {
+            batch.records.push_front((msg_type, process_id, payload));
+            *UNAPPLIED_BATCH.lock().unwrap() = Some(batch);
+            return Ok(true);
+        }
+        if !budget.record_allowed() {
+            // Stop before the record; the next call starts from it
+            batch.records.push_front((msg_type, process_id, payload));
+            *UNAPPLIED_BATCH.lock().unwrap() = Some(batch);
+            return Ok(processed_something);
+        }
         processed_something = true;
+        log_applied_record(batch.number, batch.applied, msg_type, process_id, &payload);
+        budget.records += 1;
+        batch.applied += 1;
+        let mut woke_reader = false;
 
         // Convert payload to a string for text-based messages.
         let msg_str = match msg_type {
@@ -432,7 +1109,7 @@ pub fn process_consensus_file(file_path: &str, processes: &mut Vec<process::Proc
                     }
                 }
             },
-            2 => String::new(), // For Init command, the payload is binary.
+            2 | 7 | 8 | 9 | 10 | 11 | 13 => String::new(), // Init, SetQuota and InitN are binary; Pause/Resume/CancelNetwork/Shutdown have no payload.
             _ => {
                 error!("Unknown message type: {} in file", msg_type);
                 continue; // Try to process next command in batch
@@ -453,25 +1130,26 @@ pub fn process_consensus_file(file_path: &str, processes: &mut Vec<process::Proc
                     error!("Invalid clock message format in file: {}", msg_str);
                 }
                 // Clock command marks the end of a batch, so return
+                budget.batches += 1;
                 return Ok(true);
             },
             1 => { // FD update.
-                debug!("Processing FD update for process {}: {}", process_id, msg_str);
+                debug!("Processing FD update for process {}: {}", process_id, redact::payload(&payload));
                 let parts: Vec<&str> = msg_str.split(",body:").collect();
                 if parts.len() != 2 {
-                    error!("Invalid file message format for FD update: {}", msg_str);
+                    error!("Invalid file message format for FD update: {}", redact::payload(&payload));
                     continue; // Try to process next command in batch
                 }
                 let fd: i32 = if let Some(fd_part) = parts[0].strip_prefix("fd:") {
                     match fd_part.trim().parse() {
                         Ok(num) => num,
                         Err(_) => {
-                            error!("Invalid FD in file message: {}", msg_str);
+                            error!("Invalid FD in file message: {}", redact::payload(&payload));
                             continue; // Try to process next command in batch
                         }
                     }
                 } else {
-                    error!("Missing FD prefix in file message: {}", msg_str);
+                    error!("Missing FD prefix in file message: {}", redact::payload(&payload));
                     continue; // Try to process next command in batch
                 };
                 let body = parts[1].trim();
@@ -480,18 +1158,15 @@ pub fn process_consensus_file(file_path: &str, processes: &mut Vec<process::Proc
                     if process.id == process_id {
                         found = true;
                         let mut table = process.data.fd_table.lock().unwrap();
-                        if let Some(Some(FDEntry::File { buffer, .. })) = table.entries.get_mut(fd as usize) {
-                            buffer.extend_from_slice(body.as_bytes());
-                            buffer.push(b'\n');
-                            info!(
-                                "Added input to process {}'s FD {} (via file)",
-                                process_id, fd
-                            );
-                        } else {
-                            error!(
-                                "Process {} does not have FD {} open (via file)",
-                                process_id, fd
-                            );
+                        match table.inject_input(fd, &input_line(body), process.data.max_stdin_buffer) {
+                            Ok(()) => {
+                                info!("Added input to process {}'s FD {} (via file)", process_id, fd);
+                                woke_reader = fd == 0 && waiting_for_stdin(process);
+                            }
+                            Err(e) => error!(
+                                "Refused input for process {} ({} bytes, via file): FD {} is {}",
+                                process_id, body.len(), fd, e
+                            ),
                         }
                         process.data.cond.notify_all();
                         break;
@@ -503,19 +1178,13 @@ pub fn process_consensus_file(file_path: &str, processes: &mut Vec<process::Proc
             },
             2 => { // Init command.
                 info!("Received init command from consensus file");
-                let new_pid = get_next_pid();
-                match process::start_process_from_bytes(payload, new_pid) {
-                    Ok(proc) => {
-                        processes.push(proc);
-                        info!("Added new process {} to scheduler (via file)", new_pid);
-                    }
-                    Err(e) => {
-                        error!("Failed to create new process {}: {}", new_pid, e);
-                    }
-                }
+                start_process(payload, get_next_pid());
+            },
+            11 => {
+                apply_init_n_record(&payload);
             },
             3 => { // Msg command.
-                debug!("Processing message command for process {}: {}", process_id, msg_str);
+                debug!("Processing message command for process {}: {}", process_id, redact::payload(&payload));
                 let message = if let Some(msg_part) = msg_str.strip_prefix("msg:") {
                     msg_part.trim()
                 } else {
@@ -526,18 +1195,15 @@ pub fn process_consensus_file(file_path: &str, processes: &mut Vec<process::Proc
                     if process.id == process_id {
                         found = true;
                         let mut table = process.data.fd_table.lock().unwrap();
-                        if let Some(Some(FDEntry::File { buffer, .. })) = table.entries.get_mut(0) {
-                            buffer.extend_from_slice(message.as_bytes());
-                            buffer.push(b'\n');
-                            info!(
-                                "Added msg to process {}'s FD 0 (via file)",
-                                process_id
-                            );
-                        } else {
-                            error!(
-                                "Process {} does not have FD 0 open for msg (via file)",
-                                process_id
-                            );
+                        match table.inject_input(0, &input_line(message), process.data.max_stdin_buffer) {
+                            Ok(()) => {
+                                info!("Added msg to process {}'s FD 0 (via file)", process_id);
+                                woke_reader = waiting_for_stdin(process);
+                            }
+                            Err(e) => error!(
+                                "Refused msg for process {} ({} bytes, via file): FD 0 is {}",
+                                process_id, message.len(), e
+                            ),
                         }
                         process.data.cond.notify_all();
                         break;
@@ -548,12 +1214,125 @@ pub fn process_consensus_file(file_path: &str, processes: &mut Vec<process::Proc
                 }
             },
             4 => { // FTP update.
-                info!("Received FTP command for process {}: {} (via file)", process_id, msg_str);
+                info!("Received FTP command for process {}: {} (via file)", process_id, redact::payload(&payload));
                 // Add FTP command dispatch logic here if needed.
             },
+            7 | 8 => apply_pause_record(processes, process_id, msg_type == 7),
+            9 => apply_cancel_network_record(processes, process_id),
+            10 => apply_set_quota_record(processes, process_id, &payload),
+            13 => apply_shutdown_record(processes, process_id),
             _ => {
-                error!("Unknown message type: {} in file message: {}", msg_type, msg_str);
+                error!("Unknown message type: {} in file message: {}", msg_type, redact::payload(&payload));
+            }
+        }
+
+        // As for a live connection, a guest blocked reading stdin runs on
+        // each chunk before the next record
+        if woke_reader {
+            if !batch.records.is_empty() {
+                *UNAPPLIED_BATCH.lock().unwrap() = Some(batch);
+            }
+            return Ok(true);
+        }
+    }
+    // The file ended partway through a batch
+    Ok(processed_something)
+}
+
+/// Reads the next batch of a consensus file: the records from `FILE_POSITION`
+/// up to and including the next Clock record, in `canonical_order`. Returns
+/// None if there is no complete batch to read yet. Where a batch ends must
+/// not depend on how much of the file had been written when it was read, so
+/// with `follow`, records after the last Clock record wait for the clock that
+/// ends their batch. Otherwise the file is complete, and they are its last
+/// batch.
+///
+/// A file shorter than `FILE_POSITION` was truncated or rewritten since it was
+/// last read, so it is read again from the start. A file that is gone is an
+/// error.
+fn read_file_batch(file_path: &str, number: u64, follow: bool) -> Result<Option<UnappliedBatch>> {
+    let file = match File::open(file_path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            match FILE_POSITION.load(Ordering::SeqCst) {
+                0 => anyhow::bail!("Consensus file {} does not exist", file_path),
+                read => anyhow::bail!("Consensus file {} was deleted after {} bytes were read from it", file_path, read),
             }
         }
+        Err(e) => return Err(e.into()),
+    };
+    let len = file.metadata()?.len();
+    let mut current_pos = FILE_POSITION.load(Ordering::SeqCst);
+    if len < current_pos {
+        warn!("Consensus file {} is {} bytes, shorter than the {} already read; it was rewritten, reading it from the start",
+            file_path, len, current_pos);
+        current_pos = 0;
+        FILE_POSITION.store(0, Ordering::SeqCst);
+    }
+    let mut reader = BufReader::new(file);
+    debug!("Seeking to position {} in consensus file", current_pos);
+    reader.seek(SeekFrom::Start(current_pos))?;
+
+    let mut records = Vec::new();
+    let mut ended = false;
+    loop {
+        // Read the message type (1 byte)
+        let mut msg_type_buf = [0u8; 1];
+        if reader.read_exact(&mut msg_type_buf).is_err() {
+            break; // End of file
+        }
+        let msg_type = msg_type_buf[0];
+
+        // Read process_id (8 bytes)
+        let process_id = match reader.read_u64::<LittleEndian>() {
+            Ok(pid) => pid,
+            Err(_) => break, // End of file
+        };
+
+        // Read payload length (4 bytes)
+        let payload_len = match reader.read_u32::<LittleEndian>() {
+            Ok(sz) => sz as usize,
+            Err(_) => break, // End of file
+        };
+
+        // A record over the limit is dropped without allocating it, once the
+        // file holds all of it; until then it is read like a truncated one
+        let max_len = max_payload_bytes();
+        if payload_len as u64 > max_len {
+            let end = reader.stream_position()?.saturating_add(payload_len as u64);
+            if end > len {
+                break;
+            }
+            error!("Dropped a record of {} bytes for process {}: over the {}-byte limit",
+                payload_len, process_id, max_len);
+            reader.seek(SeekFrom::Start(end))?;
+            current_pos = end;
+            continue;
+        }
+
+        // Read the payload.
+        let mut payload = vec![0u8; payload_len];
+        if let Err(e) = reader.read_exact(&mut payload) {
+            error!("Failed to read message from file: {}", e);
+            break;
+        }
+
+        current_pos = reader.stream_position()?;
+        records.push((msg_type, process_id, payload));
+        if msg_type == 0 {
+            ended = true;
+            break; // Clock command marks the end of a batch
+        }
+    }
+    if !ended && follow {
+        // The rest of the batch has not been written yet; it is read again,
+        // whole, once its clock record is there
+        return Ok(None);
+    }
+    FILE_POSITION.store(current_pos, Ordering::SeqCst);
+    if records.is_empty() {
+        return Ok(None);
     }
+    canonical_order(&mut records);
+    Ok(Some(UnappliedBatch { number, clock: None, records: records.into(), applied: 0 }))
 }
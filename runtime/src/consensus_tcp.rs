@@ -0,0 +1,85 @@
+use std::io;
+use std::net::{SocketAddr, TcpStream};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+use log::{info, warn};
+
+/// Wait before the first retry of a failed connect; doubled after each
+/// failure up to `MAX_BACKOFF`.
+const INITIAL_BACKOFF: Duration = Duration::from_millis(100);
+const MAX_BACKOFF: Duration = Duration::from_secs(2);
+
+/// How long the connection may go without a byte from consensus when
+/// `--idle-timeout` is not given. Consensus cuts a batch every 15ms by
+/// default, even an empty one, so a quiet connection is a dead one.
+pub const DEFAULT_IDLE_TIMEOUT_MS: u64 = 30_000;
+
+/// How long `connect` keeps retrying when `--connect-timeout` is not given.
+pub const DEFAULT_CONNECT_TIMEOUT_MS: u64 = 60_000;
+
+// 0 means no idle timeout
+static IDLE_TIMEOUT_MS: AtomicU64 = AtomicU64::new(DEFAULT_IDLE_TIMEOUT_MS);
+// 0 means retry forever
+static CONNECT_TIMEOUT_MS: AtomicU64 = AtomicU64::new(DEFAULT_CONNECT_TIMEOUT_MS);
+
+/// Applies the idle and connect timeout command-line flags. Returns true if
+/// `arg` was one, so callers can strip it before positional argument handling.
+///
+/// - `--idle-timeout=<ms>`: treat the connection as lost after this long
+///   without input from consensus; 0 waits forever
+/// - `--connect-timeout=<ms>`: give up connecting to consensus after this
+///   long; 0 retries forever
+pub fn apply_flag(arg: &str) -> bool {
+    let (setting, name, ms) = if let Some(ms) = arg.strip_prefix("--idle-timeout=") {
+        (&IDLE_TIMEOUT_MS, "idle", ms)
+    } else if let Some(ms) = arg.strip_prefix("--connect-timeout=") {
+        (&CONNECT_TIMEOUT_MS, "connect", ms)
+    } else {
+        return false;
+    };
+    match ms.parse() {
+        Ok(ms) => setting.store(ms, Ordering::SeqCst),
+        Err(_) => warn!("Ignoring invalid {} timeout: {}", name, ms),
+    }
+    true
+}
+
+fn idle_timeout() -> Option<Duration> {
+    match IDLE_TIMEOUT_MS.load(Ordering::SeqCst) {
+        0 => None,
+        ms => Some(Duration::from_millis(ms)),
+    }
+}
+
+/// Connects to consensus at `addr`, retrying with exponential backoff until
+/// it is up, so a runtime can be started before consensus. Fails once the
+/// connect timeout passes. Reads on the returned stream fail once the idle
+/// timeout passes without input.
+pub fn connect(addr: SocketAddr) -> io::Result<TcpStream> {
+    let deadline = match CONNECT_TIMEOUT_MS.load(Ordering::SeqCst) {
+        0 => None,
+        ms => Some(Instant::now() + Duration::from_millis(ms)),
+    };
+    let mut backoff = INITIAL_BACKOFF;
+    loop {
+        match TcpStream::connect(addr) {
+            Ok(stream) => {
+                if let Err(e) = stream.set_read_timeout(idle_timeout()) {
+                    warn!("Cannot set the idle timeout on the consensus connection: {}", e);
+                }
+                info!("Connected to consensus at {}", addr);
+                return Ok(stream);
+            }
+            Err(e) if deadline.is_some_and(|deadline| Instant::now() + backoff >= deadline) => {
+                return Err(io::Error::new(io::ErrorKind::TimedOut,
+                    format!("consensus at {} is still not reachable ({})", addr, e)));
+            }
+            Err(e) => {
+                info!("Consensus at {} is not reachable ({}); retrying in {:?}", addr, e, backoff);
+                thread::sleep(backoff);
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+            }
+        }
+    }
+}
@@ -1,25 +1,48 @@
 use anyhow::Result;
-use log::{info, error, debug};
-use env_logger;
+use log::{info, warn, error, debug};
+mod conformance;
+mod consensus_follow;
 mod consensus_input;
+mod consensus_tcp;
 mod runtime;
+mod selftest;
 mod wasi_syscalls;
-use std::net::TcpStream;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::fs;
 use std::sync::OnceLock;
-use ctrlc;
 
 static SANDBOX_ROOT: OnceLock<PathBuf> = OnceLock::new();
 
-fn pick_unique_sandbox_root() -> PathBuf {
+/// Creates and returns the first `wasi_sandbox_<N>` that does not exist yet.
+/// The directory is created here, so two runtimes started in the same
+/// directory at once cannot both pick it.
+fn pick_unique_sandbox_root() -> std::io::Result<PathBuf> {
     let mut idx = 0;
     loop {
         let candidate = PathBuf::from(format!("wasi_sandbox_{}", idx));
-        if !candidate.exists() {
-            return candidate;
+        match fs::create_dir(&candidate) {
+            Ok(()) => return Ok(candidate),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => idx += 1,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Removes the sandbox root and every process sandbox under it, logging what
+/// could not be removed. A guest thread still writing can make a removal
+/// fail partway, so it is tried a few times before giving up.
+fn remove_sandbox_root(root: &Path) {
+    info!("Cleaning up sandbox root: {}", root.display());
+    for attempt in 1..=3 {
+        match fs::remove_dir_all(root) {
+            Ok(()) => return,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return,
+            Err(e) if attempt < 3 => {
+                warn!("Failed to remove sandbox root {} ({}); retrying", root.display(), e);
+                std::thread::sleep(std::time::Duration::from_millis(50));
+            }
+            Err(e) => error!("Failed to remove sandbox root {}: {}", root.display(), e),
         }
-        idx += 1;
     }
 }
 
@@ -28,49 +51,132 @@ fn main() -> Result<()> {
     env_logger::init();
 
     // Pick a unique sandbox root and store it globally
-    let sandbox_root = pick_unique_sandbox_root();
-    fs::create_dir_all(&sandbox_root)?;
+    let sandbox_root = pick_unique_sandbox_root()?;
     SANDBOX_ROOT.set(sandbox_root.clone()).unwrap();
     info!("Using sandbox root: {}", sandbox_root.display());
 
     // Ensure cleanup on exit
     let sandbox_root_cleanup = sandbox_root.clone();
     ctrlc::set_handler(move || {
-        info!("Cleaning up sandbox root: {}", sandbox_root_cleanup.display());
-        let _ = fs::remove_dir_all(&sandbox_root_cleanup);
+        remove_sandbox_root(&sandbox_root_cleanup);
         std::process::exit(0);
     }).expect("Error setting Ctrl-C handler");
 
-    // Determine execution mode: "benchmark" or "tcp"
-    let args: Vec<String> = std::env::args().collect();
+    // Determine execution mode: "benchmark", "follow", "tcp", "replay",
+    // "selftest" or "conformance"
+    // Redaction flags (--redact, --no-redact, --redact-threshold=N), input
+    // limits (--max-batches=N, --max-records=N), the watchdog
+    // (--watchdog=MS, --watchdog-kill), --metrics-port=PORT, breakpoints
+    // (--break=SPEC), --compile-timeout=MS, --quantum=FUEL, --fuel=FUEL,
+    // --secrets=FILE, --idle-timeout=MS and --connect-timeout=MS may appear anywhere
+    let mut input_limits = consensus_input::InputLimits::default();
+    let args: Vec<String> = std::env::args()
+        .filter(|arg| {
+            !consensus::redact::apply_flag(arg)
+                && !input_limits.apply_flag(arg)
+                && !runtime::watchdog::apply_flag(arg)
+                && !runtime::metrics::apply_flag(arg)
+                && !runtime::breakpoint::apply_flag(arg)
+                && !runtime::compile::apply_flag(arg)
+                && !runtime::quantum::apply_flag(arg)
+                && !runtime::secrets::apply_flag(arg)
+                && !consensus_tcp::apply_flag(arg)
+        })
+        .collect();
     let mode = if args.len() > 1 { &args[1] } else { "benchmark" };
     info!("Runtime: Running in {} mode", mode);
     debug!("Arguments: {:?}", args);
+    let result = run_mode(mode, &args, input_limits);
+
+    info!("Runtime: Exiting.");
+    // Clean up the sandbox root however the mode ended, an error included
+    remove_sandbox_root(SANDBOX_ROOT.get().unwrap());
+    let exit_code = result?;
+    if exit_code != 0 {
+        std::process::exit(exit_code);
+    }
+    Ok(())
+}
+
+/// Runs `mode` with the rest of the command line, and returns the status the
+/// runtime exits with.
+fn run_mode(mode: &str, args: &[String], input_limits: consensus_input::InputLimits) -> Result<i32> {
+    runtime::metrics::start()?;
 
     // Spawn processes from WASM modules.
     let processes = Vec::new();
+    let mut exit_code = 0;
     //let testdir_path = Path::new("runtime/testdir"); // relative path "testdir"
     //let preload_dir = Some(testdir_path);
     match mode {
         "benchmark" => {
-            let consensus_file = "consensus/consensus_input.bin";
+            let consensus_file = args.get(2).map(String::as_str).unwrap_or("consensus/consensus_input.bin");
             info!("Runtime: Running in benchmark mode with file: {}", consensus_file);
-            runtime::scheduler::run_scheduler_with_file(processes, consensus_file)?;
+            runtime::scheduler::run_scheduler_with_file(processes, consensus_file, input_limits)?;
+        },
+        "follow" => {
+            let consensus_file = args.get(2).map(String::as_str).unwrap_or("consensus/consensus_input.bin");
+            info!("Runtime: Following consensus file {} for new records", consensus_file);
+            runtime::scheduler::run_scheduler_following_file(processes, consensus_file, input_limits)?;
         },
         "tcp" => {
-            info!("Runtime: TCP mode: Connecting to consensus server at 127.0.0.1:9000");
-            let mut stream = TcpStream::connect("127.0.0.1:9000")?;
-            debug!("Connected to TCP server");
-            runtime::scheduler::run_scheduler_interactive(processes, &mut stream)?;
+            // e.g. `runtime tcp 10.0.0.5:9000`, or REPLICODE_CONSENSUS_ADDR
+            let addr = args.get(2).cloned()
+                .or_else(|| std::env::var("REPLICODE_CONSENSUS_ADDR").ok())
+                .unwrap_or_else(|| consensus::runtime_manager::DEFAULT_RUNTIME_ADDR.to_string());
+            match consensus::runtime_manager::resolve_addr(&addr) {
+                Ok(addr) => {
+                    info!("Runtime: TCP mode: Connecting to consensus server at {}", addr);
+                    runtime::scheduler::run_scheduler_connected(processes, addr, input_limits)?;
+                }
+                Err(e) => {
+                    error!("Runtime: Cannot connect to consensus: {}", e);
+                    exit_code = 1;
+                }
+            }
+        },
+        "replay" => match args.get(2) {
+            Some(session_file) => {
+                info!("Runtime: Replaying session {}", session_file);
+                runtime::sandbox_digest::enable();
+                runtime::scheduler::run_scheduler_replaying(processes, std::path::Path::new(session_file), input_limits)?;
+                // One line per process, for comparing replays
+                for (pid, digest) in runtime::sandbox_digest::take() {
+                    println!("process {} sandbox sha256 {}", pid, digest);
+                }
+            }
+            None => {
+                error!("Runtime: replay needs a session file, e.g. sessions/session-<time>.bin");
+                exit_code = 1;
+            }
+        },
+        "selftest" => {
+            info!("Runtime: Running the WASI conformance self-test");
+            match selftest::run() {
+                Ok(true) => {}
+                Ok(false) => exit_code = 1,
+                Err(e) => {
+                    error!("Runtime: Self-test could not start: {:?}", e);
+                    exit_code = 1;
+                }
+            }
+        },
+        "conformance" => {
+            let suite_dir = args.get(2).map(String::as_str).unwrap_or(conformance::DEFAULT_SUITE_DIR);
+            info!("Runtime: Running the WASI conformance suite in {}", suite_dir);
+            let report_path = args.get(3).map(std::path::Path::new);
+            match conformance::run(std::path::Path::new(suite_dir), report_path) {
+                Ok(true) => {}
+                Ok(false) => exit_code = 1,
+                Err(e) => {
+                    error!("Runtime: Conformance suite could not run: {:?}", e);
+                    exit_code = 1;
+                }
+            }
         },
         _ => {
-            error!("Runtime: Unknown mode: {}. Use benchmark or tcp.", mode);
+            error!("Runtime: Unknown mode: {}. Use benchmark, follow, tcp, replay, selftest or conformance.", mode);
         }
     }
-
-    info!("Runtime: Exiting.");
-    // Clean up sandbox root on normal exit
-    info!("Cleaning up sandbox root: {}", SANDBOX_ROOT.get().unwrap().display());
-    let _ = fs::remove_dir_all(SANDBOX_ROOT.get().unwrap());
-    Ok(())
+    Ok(exit_code)
 }
@@ -0,0 +1,139 @@
+use std::io::BufRead;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use log::warn;
+use crate::runtime::clock::GlobalClock;
+use crate::runtime::process::{Process, ProcessState};
+
+/// A condition to stop the scheduler at, from `--break=<spec>`.
+#[derive(Debug, Clone, PartialEq)]
+enum Breakpoint {
+    /// `batch:<n>`: the nth consensus batch has been applied.
+    Batch(u64),
+    /// `pid:<pid>=<state>`: the process is in that state.
+    State(u64, ProcessState),
+    /// `syscall:<name>=<count>`: some process has made that many calls to it.
+    Syscall(String, u64),
+}
+
+impl Breakpoint {
+    fn parse(spec: &str) -> Option<Breakpoint> {
+        let (kind, rest) = spec.split_once(':')?;
+        match kind {
+            "batch" => rest.parse().ok().map(Breakpoint::Batch),
+            "pid" => {
+                let (pid, state) = rest.split_once('=')?;
+                let state = match state.to_ascii_lowercase().as_str() {
+                    "ready" => ProcessState::Ready,
+                    "blocked" => ProcessState::Blocked,
+                    "finished" => ProcessState::Finished,
+                    _ => return None,
+                };
+                Some(Breakpoint::State(pid.parse().ok()?, state))
+            }
+            "syscall" => {
+                let (name, count) = rest.split_once('=')?;
+                Some(Breakpoint::Syscall(name.to_string(), count.parse().ok()?))
+            }
+            _ => None,
+        }
+    }
+
+    fn hit<'a>(&self, mut processes: impl Iterator<Item = &'a Process>, batches: u64) -> bool {
+        match self {
+            Breakpoint::Batch(n) => batches >= *n,
+            Breakpoint::State(pid, state) => {
+                processes.any(|p| p.id == *pid && *p.data.state.lock().unwrap() == *state)
+            }
+            Breakpoint::Syscall(name, count) => processes.any(|p| {
+                p.data.syscalls.snapshot().iter().any(|(n, c)| n == name && c >= count)
+            }),
+        }
+    }
+}
+
+// Breakpoints that have not been hit yet; each one stops the scheduler once
+static PENDING: Mutex<Vec<Breakpoint>> = Mutex::new(Vec::new());
+// Set by `step`: stop again at the next check
+static STEPPING: AtomicBool = AtomicBool::new(false);
+
+/// Applies `--break=<spec>`, which may be given more than once. Returns true
+/// if `arg` was one, so callers can strip it before positional argument handling.
+pub fn apply_flag(arg: &str) -> bool {
+    let Some(spec) = arg.strip_prefix("--break=") else {
+        return false;
+    };
+    match Breakpoint::parse(spec) {
+        Some(breakpoint) => PENDING.lock().unwrap().push(breakpoint),
+        None => warn!("Ignoring invalid breakpoint: {} (use batch:<n>, pid:<pid>=<state> or syscall:<name>=<count>)", spec),
+    }
+    true
+}
+
+/// Called by the scheduler whenever it has control: after each batch and
+/// each time a process yields, blocks or finishes. `processes` are all the
+/// processes it holds. If a breakpoint is hit (or the operator is stepping),
+/// dumps the runtime's state and waits for the operator.
+///
+/// The checks only look at replicated state, so a replay of the same input
+/// stops at the same point every time.
+pub fn check<'a>(processes: impl Iterator<Item = &'a Process> + Clone, batches: u64) {
+    let reason = if STEPPING.swap(false, Ordering::SeqCst) {
+        "step".to_string()
+    } else {
+        let mut pending = PENDING.lock().unwrap();
+        match pending.iter().position(|b| b.hit(processes.clone(), batches)) {
+            Some(i) => format!("{:?}", pending.remove(i)),
+            None => return,
+        }
+    };
+    eprintln!("=== Breakpoint: {} ===", reason);
+    dump(processes, batches);
+    prompt();
+}
+
+fn dump<'a>(processes: impl Iterator<Item = &'a Process>, batches: u64) {
+    eprintln!("clock: {}  batches applied: {}", GlobalClock::now(), batches);
+    for p in processes {
+        let state = *p.data.state.lock().unwrap();
+        match &*p.data.block_reason.lock().unwrap() {
+            Some(reason) => eprintln!("process {}: {} ({})", p.id, state, reason),
+            None => eprintln!("process {}: {}", p.id, state),
+        }
+        eprintln!("  syscalls: {}", p.data.syscalls);
+        let table = p.data.fd_table.lock().unwrap();
+        for (fd, entry) in table.entries.iter().enumerate() {
+            if let Some(entry) = entry {
+                eprintln!("  fd {}: {}", fd, entry);
+            }
+        }
+        let nat = p.data.nat_table.lock().unwrap();
+        for (pid, port, consensus_port, kind) in nat.get_port_mappings() {
+            if pid == p.id {
+                eprintln!("  nat: port {} -> {} ({})", port, consensus_port, kind);
+            }
+        }
+    }
+}
+
+/// Reads operator commands from stdin until one resumes the scheduler. End of
+/// input continues to the end, so scripted replays do not hang.
+fn prompt() {
+    let stdin = std::io::stdin();
+    loop {
+        eprint!("(s)tep, (c)ontinue? ");
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line).unwrap_or(0) == 0 {
+            eprintln!();
+            return;
+        }
+        match line.trim() {
+            "s" | "step" => {
+                STEPPING.store(true, Ordering::SeqCst);
+                return;
+            }
+            "c" | "continue" => return,
+            other => eprintln!("Unknown command: {}", other),
+        }
+    }
+}
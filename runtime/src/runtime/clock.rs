@@ -15,4 +15,10 @@ impl GlobalClock {
     pub fn increment(delta: u64) {
         CLOCK.fetch_add(delta, Ordering::SeqCst);
     }
+
+    /// Sets the clock to `now`, e.g. from the clock stamped on a batch, so a
+    /// runtime that joins late lands on the same time as the others.
+    pub fn set_absolute(now: u64) {
+        CLOCK.store(now, Ordering::SeqCst);
+    }
 }
@@ -0,0 +1,86 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+use anyhow::{anyhow, Result};
+use log::{debug, info};
+use wasmtime::{Engine, Module};
+use crate::runtime::process::{self, InitOptions, Process};
+use crate::runtime::quantum;
+
+// 0 means no timeout, the default: a wall-clock limit could accept a module
+// on one replica and reject it on a slower one
+static TIMEOUT_MS: AtomicU64 = AtomicU64::new(0);
+
+/// Applies `--compile-timeout=<ms>` (`0`, the default, waits for any
+/// module). Returns true if `arg` was it, so callers can strip it before
+/// positional argument handling.
+pub fn apply_flag(arg: &str) -> bool {
+    let Some(ms) = arg.strip_prefix("--compile-timeout=") else {
+        return false;
+    };
+    match ms.parse() {
+        Ok(ms) => TIMEOUT_MS.store(ms, Ordering::SeqCst),
+        Err(_) => log::warn!("Ignoring invalid compile timeout: {}", ms),
+    }
+    true
+}
+
+/// A process whose module is being compiled on a thread of its own, so that
+/// a large Init does not hold up the processes that are already running.
+pub struct PendingStart {
+    pub id: u64,
+    started: Instant,
+    compiled: Receiver<Result<(Engine, Module, InitOptions)>>,
+}
+
+/// Starts compiling the module of an Init payload for process `id`. An Init
+/// with invalid options fails when it is finished, like one whose module
+/// does not compile.
+pub fn start_in_background(payload: Vec<u8>, id: u64) -> Result<PendingStart> {
+    let (sender, compiled) = mpsc::channel();
+    match process::parse_init_payload(payload, id) {
+        Ok((options, wasm_bytes)) => {
+            thread::Builder::new()
+                .name(format!("compile-pid{}", id))
+                .spawn(move || {
+                    let compiled = process::compile_module(&wasm_bytes, &options.limits)
+                        .map(|(engine, module)| (engine, module, options));
+                    // The receiver is gone if the compile timed out; nothing to do then
+                    let _ = sender.send(compiled);
+                })?;
+            debug!("Compiling module of process {} in the background", id);
+        }
+        Err(e) => {
+            let _ = sender.send(Err(e));
+        }
+    }
+    Ok(PendingStart { id, started: Instant::now(), compiled })
+}
+
+impl PendingStart {
+    /// Waits for the module, up to what is left of the compile timeout, and
+    /// starts the process.
+    ///
+    /// The timeout is measured in local wall-clock time, so a module close to
+    /// it may be accepted on one replica and rejected on a slower one. Set it
+    /// well above what legitimate modules take.
+    pub fn finish(self) -> Result<Process> {
+        let compiled = match TIMEOUT_MS.load(Ordering::SeqCst) {
+            0 => self.compiled.recv().map_err(|_| anyhow!("the compile thread panicked"))?,
+            ms => {
+                let remaining = Duration::from_millis(ms).saturating_sub(self.started.elapsed());
+                match self.compiled.recv_timeout(remaining) {
+                    Ok(compiled) => compiled,
+                    Err(RecvTimeoutError::Timeout) => {
+                        return Err(anyhow!("compilation did not finish within {} ms", ms));
+                    }
+                    Err(RecvTimeoutError::Disconnected) => return Err(anyhow!("the compile thread panicked")),
+                }
+            }
+        };
+        let (engine, module, options) = compiled?;
+        info!("Compiled module of process {} in {:?}", self.id, self.started.elapsed());
+        process::spawn_process(self.id, engine, module, options, quantum::fuel())
+    }
+}
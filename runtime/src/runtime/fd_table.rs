@@ -3,28 +3,45 @@ use std::path::PathBuf;
 
 use log::debug;
 
+/// A file's access and modification times, in `GlobalClock` nanoseconds
+/// rather than the host's, so every replica reports the same ones.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct FileTimes {
+    pub atime: u64,
+    pub mtime: u64,
+}
+
 #[derive(Debug, Clone)]
 pub enum FDEntry {
     File {
         buffer: Vec<u8>,    // data waiting to be read
         read_ptr: usize,    // how far we've read from buffer
+        write_ptr: usize,   // where the next fd_write goes, unless append is set
         is_directory: bool,
         is_preopen: bool,
         host_path: Option<String>, // the actual host filesystem path
+        flush_threshold: Option<usize>, // set by the guest, else the process's max_write_buffer
+        append: bool, // opened with FDFLAGS_APPEND; fd_write always writes at the end
+        nonblocking: bool, // FDFLAGS_NONBLOCK; fd_read returns EAGAIN rather than block when nothing is buffered
+        times: FileTimes, // logical times, reported by fd_filestat_get
     },
     Socket {
         local_port: u16,
         connected: bool,
         is_listener: bool,  // whether this is a listening socket
         buffer: Vec<u8>,    // data waiting to be read
+        bound_addr: Option<(String, u16)>, // address/port requested via sock_bind
+        peer_addr: Option<String>, // remote "ip:port" reported by consensus once connected; for a datagram socket, the last sender
+        datagram: bool, // opened as SOCK_DGRAM: sends and receives are whole datagrams
+        ipv6: bool, // opened as AF_INET6: its addresses are sockaddr_in6, not sockaddr_in
     },
 }
 
 impl fmt::Display for FDEntry {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            FDEntry::File { buffer, read_ptr, is_directory, is_preopen, host_path } => {
-                let buffer_str = match std::str::from_utf8(&buffer) {
+            FDEntry::File { buffer, read_ptr, is_directory, is_preopen, host_path, .. } => {
+                let buffer_str = match std::str::from_utf8(buffer) {
                     Ok(s) => s.to_string(),
                     Err(_) => format!("{:?}", buffer),
                 };
@@ -34,8 +51,8 @@ impl fmt::Display for FDEntry {
                     buffer_str, read_ptr, is_directory, is_preopen, host_path
                 )
             },
-            FDEntry::Socket { local_port, connected, is_listener, buffer } => {
-                let buffer_str = match std::str::from_utf8(&buffer) {
+            FDEntry::Socket { local_port, connected, is_listener, buffer, .. } => {
+                let buffer_str = match std::str::from_utf8(buffer) {
                     Ok(s) => s.to_string(),
                     Err(_) => format!("{:?}", buffer),
                 };
@@ -47,23 +64,58 @@ impl fmt::Display for FDEntry {
 }
 
 impl FDEntry {
+    #[allow(dead_code)]
     pub fn new_file(host_path: Option<String>) -> Self {
         FDEntry::File {
             buffer: Vec::new(),
             read_ptr: 0,
+            write_ptr: 0,
             is_directory: false,
             is_preopen: false,
             host_path,
+            flush_threshold: None,
+            append: false,
+            nonblocking: false,
+            times: FileTimes::default(),
         }
     }
 
+    #[allow(dead_code)]
     pub fn new_directory(host_path: String) -> Self {
         FDEntry::File {
             buffer: Vec::new(),
             read_ptr: 0,
+            write_ptr: 0,
             is_directory: true,
             is_preopen: true,
             host_path: Some(host_path),
+            flush_threshold: None,
+            append: false,
+            nonblocking: false,
+            times: FileTimes::default(),
+        }
+    }
+}
+
+/// Why `FDTable::inject_input` refused some input.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InjectError {
+    NotOpen,
+    /// Socket input arrives in NetworkIn records, matched to the socket by port.
+    Socket,
+    Directory,
+    BufferFull { unread: usize, limit: usize },
+}
+
+impl fmt::Display for InjectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InjectError::NotOpen => write!(f, "not open"),
+            InjectError::Socket => write!(f, "a socket, cannot inject stdin-style data (use NetworkIn)"),
+            InjectError::Directory => write!(f, "a directory, cannot inject stdin-style data"),
+            InjectError::BufferFull { unread, limit } => {
+                write!(f, "full: {} unread bytes already buffered, limit {}", unread, limit)
+            }
         }
     }
 }
@@ -82,30 +134,50 @@ impl FDTable {
         table.entries.push(Some(FDEntry::File {  // stdin
             buffer: Vec::new(),
             read_ptr: 0,
+            write_ptr: 0,
             is_directory: false,
             is_preopen: false,
             host_path: None,
+            flush_threshold: None,
+            append: false,
+            nonblocking: false,
+            times: FileTimes::default(),
         }));
         table.entries.push(Some(FDEntry::File {  // stdout
             buffer: Vec::new(),
             read_ptr: 0,
+            write_ptr: 0,
             is_directory: false,
             is_preopen: false,
             host_path: None,
+            flush_threshold: None,
+            append: false,
+            nonblocking: false,
+            times: FileTimes::default(),
         }));
         table.entries.push(Some(FDEntry::File {  // stderr
             buffer: Vec::new(),
             read_ptr: 0,
+            write_ptr: 0,
             is_directory: false,
             is_preopen: false,
             host_path: None,
+            flush_threshold: None,
+            append: false,
+            nonblocking: false,
+            times: FileTimes::default(),
         }));
         table.entries.push(Some(FDEntry::File {
             buffer: Vec::new(),
             read_ptr: 0,
+            write_ptr: 0,
             is_directory: true,
             is_preopen: true,
             host_path: Some(process_root.to_string_lossy().into_owned()),
+            flush_threshold: None,
+            append: false,
+            nonblocking: false,
+            times: FileTimes::default(),
         }));
         table
     }
@@ -122,6 +194,31 @@ impl FDTable {
         }
     }
 
+    /// Appends input injected by consensus to a file FD. Nothing is buffered
+    /// if the unread input would grow past `limit` bytes; the guest has to
+    /// read some of it first. Sockets and directories cannot take input.
+    pub fn inject_input(&mut self, fd: i32, data: &[u8], limit: usize) -> Result<(), InjectError> {
+        match self.get_fd_entry_mut(fd) {
+            Some(FDEntry::File { buffer, read_ptr, is_directory: false, host_path, .. }) => {
+                // Streams such as stdin only keep what has not been read yet
+                if host_path.is_none() {
+                    let consumed = (*read_ptr).min(buffer.len());
+                    buffer.drain(..consumed);
+                    *read_ptr = 0;
+                }
+                let unread = buffer.len().saturating_sub(*read_ptr);
+                if unread + data.len() > limit {
+                    return Err(InjectError::BufferFull { unread, limit });
+                }
+                buffer.extend_from_slice(data);
+                Ok(())
+            }
+            Some(FDEntry::File { .. }) => Err(InjectError::Directory),
+            Some(FDEntry::Socket { .. }) => Err(InjectError::Socket),
+            None => Err(InjectError::NotOpen),
+        }
+    }
+
     /// Helper to get a mutable reference to the FD entry or return an error.
     pub fn get_fd_entry_mut(&mut self, fd: i32) -> Option<&mut FDEntry> {
         if fd < 0 {
@@ -130,6 +227,12 @@ impl FDTable {
         self.entries.get_mut(fd as usize).and_then(|e| e.as_mut())
     }
 
+    /// Returns the lowest free FD, growing the table if every slot is in use.
+    ///
+    /// As in POSIX, a closed FD is reused by the next allocation. Replicas run
+    /// the same syscalls in the same order, so they hand out the same numbers,
+    /// and code that scans the table (e.g. to match sockets) sees them in the
+    /// same order. The slot stays free until the caller fills it.
     pub fn allocate_fd(&mut self) -> i32 {
         // First try to find an existing empty slot
         for (i, entry) in self.entries.iter().enumerate() {
@@ -144,7 +247,25 @@ impl FDTable {
         new_fd
     }
 
-    /// Mark an FD slot as closed
+    /// Moves the entry at `from` to `to`, as `dup2` followed by closing
+    /// `from` would. Whatever `to` held is closed first. A file's cursors and
+    /// buffered input, or a socket's port and connection, go with the entry;
+    /// sockets are matched to NAT state by port, not by FD. Returns false,
+    /// changing nothing, if `from` is not open or `to` is not a slot in the
+    /// table.
+    pub fn renumber(&mut self, from: i32, to: i32) -> bool {
+        if self.get_fd_entry_mut(from).is_none() || to < 0 || to as usize >= self.entries.len() {
+            return false;
+        }
+        if from != to {
+            let entry = self.entries[from as usize].take();
+            self.entries[to as usize] = entry;
+        }
+        true
+    }
+
+    /// Mark an FD slot as closed, making it the next to be reused if it is
+    /// the lowest free one
     pub fn deallocate_fd(&mut self, fd: i32) {
         if fd >= 0 && (fd as usize) < self.entries.len() {
             self.entries[fd as usize] = None;
@@ -163,3 +284,79 @@ impl fmt::Display for FDTable {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file() -> FDEntry {
+        FDEntry::File {
+            buffer: Vec::new(),
+            read_ptr: 0,
+            write_ptr: 0,
+            is_directory: false,
+            is_preopen: false,
+            host_path: None,
+            flush_threshold: None,
+            append: false,
+            nonblocking: false,
+            times: FileTimes::default(),
+        }
+    }
+
+    /// Allocates an FD and fills it, as opening a file does.
+    fn open(table: &mut FDTable) -> i32 {
+        let fd = table.allocate_fd();
+        table.entries[fd as usize] = Some(file());
+        fd
+    }
+
+    #[test]
+    fn allocate_fd_starts_after_the_preopen() {
+        let mut table = FDTable::new(PathBuf::from("/"));
+        assert_eq!(open(&mut table), 4);
+        assert_eq!(open(&mut table), 5);
+    }
+
+    #[test]
+    fn allocate_fd_leaves_the_slot_free_until_filled() {
+        let mut table = FDTable::new(PathBuf::from("/"));
+        assert_eq!(table.allocate_fd(), 4);
+        assert_eq!(table.allocate_fd(), 4);
+    }
+
+    #[test]
+    fn deallocate_fd_frees_the_slot_for_reuse() {
+        let mut table = FDTable::new(PathBuf::from("/"));
+        let fds: Vec<i32> = (0..3).map(|_| open(&mut table)).collect();
+        assert_eq!(fds, [4, 5, 6]);
+        table.deallocate_fd(5);
+        assert!(table.get_fd_entry_mut(5).is_none());
+        assert_eq!(open(&mut table), 5);
+        assert_eq!(open(&mut table), 7);
+    }
+
+    #[test]
+    fn allocate_fd_reuses_the_lowest_free_slot() {
+        let mut table = FDTable::new(PathBuf::from("/"));
+        for _ in 0..4 {
+            open(&mut table);
+        }
+        table.deallocate_fd(6);
+        table.deallocate_fd(4);
+        assert_eq!(open(&mut table), 4);
+        assert_eq!(open(&mut table), 6);
+        // Closing stdin frees the lowest slot of all
+        table.deallocate_fd(0);
+        assert_eq!(open(&mut table), 0);
+    }
+
+    #[test]
+    fn deallocate_fd_ignores_fds_outside_the_table() {
+        let mut table = FDTable::new(PathBuf::from("/"));
+        table.deallocate_fd(-1);
+        table.deallocate_fd(100);
+        assert_eq!(table.entries.len(), 4);
+        assert_eq!(open(&mut table), 4);
+    }
+}
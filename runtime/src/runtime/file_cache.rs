@@ -0,0 +1,83 @@
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+
+use log::debug;
+
+/// A per-process LRU cache of file contents read through `path_open`,
+/// keyed by canonical host path and bounded by the total number of cached bytes.
+///
+/// The byte budget is separate from the sandbox disk quota. Any mutation of a
+/// path (write flush, unlink, create) must call `invalidate` so that the next
+/// open goes back to the host filesystem.
+#[derive(Debug)]
+pub struct FileCache {
+    entries: HashMap<PathBuf, Vec<u8>>,
+    order: VecDeque<PathBuf>, // front = least recently used
+    used_bytes: usize,
+    max_bytes: usize,
+}
+
+impl FileCache {
+    pub fn new(max_bytes: usize) -> Self {
+        FileCache {
+            entries: HashMap::new(),
+            order: VecDeque::new(),
+            used_bytes: 0,
+            max_bytes,
+        }
+    }
+
+    /// Returns a copy of the cached contents and marks the entry as most recently used.
+    pub fn get(&mut self, path: &Path) -> Option<Vec<u8>> {
+        let data = self.entries.get(path)?.clone();
+        self.touch(path);
+        debug!("file cache hit for {:?} ({} bytes)", path, data.len());
+        Some(data)
+    }
+
+    /// Caches `data` for `path`, evicting least recently used entries until it fits.
+    /// Files larger than the whole budget are not cached.
+    pub fn insert(&mut self, path: &Path, data: &[u8]) {
+        self.invalidate_canonical(path);
+        if data.len() > self.max_bytes {
+            return;
+        }
+        while self.used_bytes + data.len() > self.max_bytes {
+            match self.order.pop_front() {
+                Some(victim) => {
+                    if let Some(old) = self.entries.remove(&victim) {
+                        self.used_bytes -= old.len();
+                        debug!("file cache evicted {:?} ({} bytes)", victim, old.len());
+                    }
+                }
+                None => break,
+            }
+        }
+        self.used_bytes += data.len();
+        self.entries.insert(path.to_path_buf(), data.to_vec());
+        self.order.push_back(path.to_path_buf());
+    }
+
+    /// Drops any cached contents for `path`. Non-canonical paths (e.g. ones built
+    /// by `file_create`) are canonicalized first so they match the cache key.
+    pub fn invalidate(&mut self, path: &Path) {
+        let canonical = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        self.invalidate_canonical(&canonical);
+    }
+
+    fn invalidate_canonical(&mut self, path: &Path) {
+        if let Some(old) = self.entries.remove(path) {
+            self.used_bytes -= old.len();
+            self.order.retain(|p| p != path);
+            debug!("file cache invalidated {:?}", path);
+        }
+    }
+
+    fn touch(&mut self, path: &Path) {
+        if let Some(pos) = self.order.iter().position(|p| p == path) {
+            if let Some(p) = self.order.remove(pos) {
+                self.order.push_back(p);
+            }
+        }
+    }
+}
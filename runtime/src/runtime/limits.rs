@@ -0,0 +1,214 @@
+use std::fmt;
+use anyhow::{anyhow, bail, Result};
+use log::warn;
+use wasmtime::{Module, ResourceLimiter, StoreLimits, StoreLimitsBuilder};
+use crate::wasi_syscalls::builtin_flush_threshold::MAX_FLUSH_THRESHOLD;
+
+/// Linear memory a guest may grow to when its Init record sets no `mem:` limit.
+pub const DEFAULT_MAX_MEMORY_BYTES: usize = 64 * 1024 * 1024;
+/// Elements any one table may hold (wasi-libc puts function pointers here).
+pub const DEFAULT_MAX_TABLE_ELEMENTS: u32 = 10_000;
+/// Each process instantiates exactly one module.
+pub const DEFAULT_MAX_INSTANCES: usize = 1;
+pub const DEFAULT_MAX_TABLES: usize = 4;
+/// Syscalls only see one memory, so modules with more are rejected at Init anyway.
+pub const DEFAULT_MAX_MEMORIES: usize = 1;
+/// Unread input consensus may inject into one of a guest's FDs (stdin, usually).
+pub const DEFAULT_MAX_STDIN_BUFFER: usize = 1024 * 1024;
+/// Bytes `fd_write` buffers for a file before flushing them, which blocks the
+/// guest until the next batch if more of the write is left.
+pub const DEFAULT_MAX_WRITE_BUFFER: usize = 64 * 1024;
+
+const WASM_PAGE_SIZE: u64 = 64 * 1024;
+
+/// Store limits for one process. The memory cap comes from the Init `mem:`
+/// option and the rest from `limits:` (see `apply_overrides`).
+#[derive(Debug, Clone, Copy)]
+pub struct ResourceLimits {
+    pub max_memory: usize,
+    pub max_table_elements: u32,
+    pub max_instances: usize,
+    pub max_tables: usize,
+    pub max_memories: usize,
+    pub max_stdin_buffer: usize,
+    pub max_write_buffer: usize,
+}
+
+impl Default for ResourceLimits {
+    fn default() -> Self {
+        ResourceLimits {
+            max_memory: DEFAULT_MAX_MEMORY_BYTES,
+            max_table_elements: DEFAULT_MAX_TABLE_ELEMENTS,
+            max_instances: DEFAULT_MAX_INSTANCES,
+            max_tables: DEFAULT_MAX_TABLES,
+            max_memories: DEFAULT_MAX_MEMORIES,
+            max_stdin_buffer: DEFAULT_MAX_STDIN_BUFFER,
+            max_write_buffer: DEFAULT_MAX_WRITE_BUFFER,
+        }
+    }
+}
+
+impl ResourceLimits {
+    /// Applies a comma-separated list of `key=value` overrides, e.g.
+    /// `table=5000,tables=1`. Keys are `table` (elements per table),
+    /// `instances`, `tables`, `memories`, `stdin` (bytes of unread input) and
+    /// `write` (bytes buffered per write, 1 to `MAX_FLUSH_THRESHOLD`).
+    pub fn apply_overrides(&mut self, spec: &str) -> Result<()> {
+        for item in spec.split(',').map(str::trim).filter(|item| !item.is_empty()) {
+            let (key, value) = item.split_once('=').ok_or_else(|| anyhow!("expected key=value, got {:?}", item))?;
+            let value: usize = value.trim().parse().map_err(|e| anyhow!("invalid value for {}: {}", key, e))?;
+            match key.trim() {
+                "table" => self.max_table_elements = u32::try_from(value)?,
+                "instances" => self.max_instances = value,
+                "tables" => self.max_tables = value,
+                "memories" => self.max_memories = value,
+                "stdin" => self.max_stdin_buffer = value,
+                "write" if (1..=MAX_FLUSH_THRESHOLD).contains(&value) => self.max_write_buffer = value,
+                "write" => bail!("write buffer of {} bytes is not between 1 and {}", value, MAX_FLUSH_THRESHOLD),
+                other => bail!("unknown limit {:?}", other),
+            }
+        }
+        Ok(())
+    }
+
+    /// Rejects at Init a module whose definitions alone exceed the limits, so
+    /// it fails to start instead of failing part-way through instantiation.
+    pub fn check_module(&self, module: &Module) -> Result<()> {
+        let required = module.resources_required();
+        if self.max_instances < 1 {
+            bail!("instance limit is 0; the module cannot be instantiated");
+        }
+        if required.num_memories as usize > self.max_memories {
+            bail!("module defines {} memories; the limit is {}", required.num_memories, self.max_memories);
+        }
+        if let Some(pages) = required.max_initial_memory_size {
+            let bytes = pages.saturating_mul(WASM_PAGE_SIZE);
+            if bytes > self.max_memory as u64 {
+                bail!("module starts with {} bytes of memory; the limit is {}", bytes, self.max_memory);
+            }
+        }
+        if required.num_tables as usize > self.max_tables {
+            bail!("module defines {} tables; the limit is {}", required.num_tables, self.max_tables);
+        }
+        if let Some(elements) = required.max_initial_table_size {
+            if elements > self.max_table_elements {
+                bail!("module declares a table of {} elements; the limit is {}", elements, self.max_table_elements);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A store resource a guest can exhaust.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ResourceLimit {
+    Memory,
+    Table,
+}
+
+impl fmt::Display for ResourceLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ResourceLimit::Memory => write!(f, "memory"),
+            ResourceLimit::Table => write!(f, "table"),
+        }
+    }
+}
+
+/// The trap raised when a guest grows a table past its limit, or the memory
+/// grow refused before a guest traps. It is found in the error returned by
+/// `_start`, or kept by the limiter, so the process can be reported as killed
+/// by the limit rather than by an ordinary trap.
+#[derive(Debug, Clone, Copy)]
+pub struct LimitExceeded {
+    pub limit: ResourceLimit,
+    pub desired: usize,
+    pub maximum: usize,
+}
+
+impl fmt::Display for LimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} limit exceeded: requested {} with a limit of {}", self.limit, self.desired, self.maximum)
+    }
+}
+
+impl std::error::Error for LimitExceeded {}
+
+/// Finds the `LimitExceeded` trap, if any, behind an error returned by the guest.
+pub fn limit_exceeded(error: &anyhow::Error) -> Option<&LimitExceeded> {
+    error.chain().find_map(|cause| cause.downcast_ref::<LimitExceeded>())
+}
+
+/// Per-process resource limiter installed on the store.
+///
+/// Limits are enforced by wasmtime's `StoreLimits`. A `memory.grow` past the
+/// cap returns -1, so wasi-libc's malloc returns NULL and the guest can
+/// recover; the refusal is kept, and if the guest then traps anyway it is
+/// reported as killed by the memory limit. A refused `table.grow` becomes a
+/// `LimitExceeded` trap. Instance, table and memory counts are rejected by
+/// `StoreLimits` itself when the module is instantiated.
+#[derive(Clone)]
+pub struct ProcessLimiter {
+    store_limits: StoreLimits,
+    limits: ResourceLimits,
+    refused_memory: Option<LimitExceeded>,
+}
+
+impl ProcessLimiter {
+    pub fn new(limits: ResourceLimits) -> Self {
+        let store_limits = StoreLimitsBuilder::new()
+            .memory_size(limits.max_memory)
+            .table_elements(limits.max_table_elements)
+            .instances(limits.max_instances)
+            .tables(limits.max_tables)
+            .memories(limits.max_memories)
+            .build();
+        ProcessLimiter { store_limits, limits, refused_memory: None }
+    }
+
+    /// The last `memory.grow` refused for going past the memory cap, if any.
+    pub fn refused_memory(&self) -> Option<&LimitExceeded> {
+        self.refused_memory.as_ref()
+    }
+}
+
+impl ResourceLimiter for ProcessLimiter {
+    fn memory_growing(&mut self, current: usize, desired: usize, maximum: Option<usize>) -> Result<bool> {
+        if self.store_limits.memory_growing(current, desired, maximum)? {
+            return Ok(true);
+        }
+        if desired > self.limits.max_memory {
+            let refused = LimitExceeded { limit: ResourceLimit::Memory, desired, maximum: self.limits.max_memory };
+            warn!("memory.grow refused: {}", refused);
+            self.refused_memory = Some(refused);
+        }
+        // Refused for another reason (e.g. the module's own declared maximum)
+        Ok(false)
+    }
+
+    fn table_growing(&mut self, current: u32, desired: u32, maximum: Option<u32>) -> Result<bool> {
+        if self.store_limits.table_growing(current, desired, maximum)? {
+            return Ok(true);
+        }
+        if desired > self.limits.max_table_elements {
+            return Err(LimitExceeded {
+                limit: ResourceLimit::Table,
+                desired: desired as usize,
+                maximum: self.limits.max_table_elements as usize,
+            }.into());
+        }
+        Ok(false)
+    }
+
+    fn instances(&self) -> usize {
+        self.store_limits.instances()
+    }
+
+    fn tables(&self) -> usize {
+        self.store_limits.tables()
+    }
+
+    fn memories(&self) -> usize {
+        self.store_limits.memories()
+    }
+}
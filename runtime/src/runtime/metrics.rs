@@ -0,0 +1,167 @@
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use log::{error, info};
+use crate::consensus_input::InputBudget;
+use crate::runtime::process::{self, Process};
+
+// 0 means the endpoint is off
+static PORT: AtomicU16 = AtomicU16::new(0);
+
+/// What the scheduler last published; the HTTP thread only ever reads this.
+static SNAPSHOT: Mutex<Snapshot> = Mutex::new(Snapshot {
+    ready: 0,
+    blocked: 0,
+    finished: 0,
+    batches: 0,
+    records: 0,
+    processes: Vec::new(),
+});
+
+struct Snapshot {
+    ready: usize,
+    blocked: usize,
+    finished: u64,
+    batches: u64,
+    records: u64,
+    processes: Vec<ProcessMetrics>,
+}
+
+struct ProcessMetrics {
+    pid: u64,
+    disk_usage: u64,
+    disk_quota: u64,
+    syscalls: Vec<(&'static str, u64)>,
+    fuel: u64,
+}
+
+/// Applies `--metrics-port=<port>`. Returns true if `arg` was it, so callers
+/// can strip it before positional argument handling.
+pub fn apply_flag(arg: &str) -> bool {
+    let Some(port) = arg.strip_prefix("--metrics-port=") else {
+        return false;
+    };
+    match port.parse() {
+        Ok(port) => PORT.store(port, Ordering::SeqCst),
+        Err(_) => log::warn!("Ignoring invalid metrics port: {}", port),
+    }
+    true
+}
+
+fn enabled() -> bool {
+    PORT.load(Ordering::SeqCst) != 0
+}
+
+/// Starts serving `/metrics` on 127.0.0.1 if `--metrics-port` was given.
+pub fn start() -> std::io::Result<()> {
+    let port = PORT.load(Ordering::SeqCst);
+    if port == 0 {
+        return Ok(());
+    }
+    let listener = TcpListener::bind(format!("127.0.0.1:{}", port))?;
+    info!("Metrics endpoint listening on 127.0.0.1:{}", port);
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    if let Err(e) = handle_client(stream) {
+                        error!("Error handling metrics client: {}", e);
+                    }
+                }
+                Err(e) => error!("Failed to accept metrics connection: {}", e),
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Publishes the scheduler's queues and input counters for the endpoint.
+/// Does nothing unless the endpoint is enabled.
+pub fn publish(ready: &VecDeque<Process>, blocked: &VecDeque<Process>, finished: u64, budget: &InputBudget) {
+    if !enabled() {
+        return;
+    }
+    let processes = ready.iter().chain(blocked.iter())
+        .map(|proc| {
+            let (disk_usage, disk_quota) = process::disk_usage(&proc.data);
+            let syscalls = proc.data.syscalls.snapshot();
+            let fuel = *proc.data.fuel_used.lock().unwrap();
+            ProcessMetrics { pid: proc.id, disk_usage, disk_quota, syscalls, fuel }
+        })
+        .collect();
+    *SNAPSHOT.lock().unwrap() = Snapshot {
+        ready: ready.len(),
+        blocked: blocked.len(),
+        finished,
+        batches: budget.batches,
+        records: budget.records,
+        processes,
+    };
+}
+
+/// Renders the last snapshot in the Prometheus text format.
+fn render() -> String {
+    let snapshot = SNAPSHOT.lock().unwrap();
+    let mut out = String::new();
+    let mut metric = |name: &str, kind: &str, help: &str, samples: &[(String, u64)]| {
+        let _ = writeln!(out, "# HELP {} {}", name, help);
+        let _ = writeln!(out, "# TYPE {} {}", name, kind);
+        for (labels, value) in samples {
+            let _ = writeln!(out, "{}{} {}", name, labels, value);
+        }
+    };
+    metric("replicode_ready_processes", "gauge", "Processes waiting to be scheduled.",
+        &[(String::new(), snapshot.ready as u64)]);
+    metric("replicode_blocked_processes", "gauge", "Processes waiting on I/O, time or consensus.",
+        &[(String::new(), snapshot.blocked as u64)]);
+    metric("replicode_finished_processes_total", "counter", "Processes that have finished.",
+        &[(String::new(), snapshot.finished)]);
+    metric("replicode_batches_applied_total", "counter", "Consensus batches applied.",
+        &[(String::new(), snapshot.batches)]);
+    metric("replicode_records_applied_total", "counter", "Consensus records applied.",
+        &[(String::new(), snapshot.records)]);
+    let per_process = |value: fn(&ProcessMetrics) -> u64| {
+        snapshot.processes.iter()
+            .map(|p| (format!("{{pid=\"{}\"}}", p.pid), value(p)))
+            .collect::<Vec<_>>()
+    };
+    metric("replicode_process_disk_usage_bytes", "gauge", "Disk space used in the process's sandbox.",
+        &per_process(|p| p.disk_usage));
+    metric("replicode_process_disk_quota_bytes", "gauge", "Disk quota of the process.",
+        &per_process(|p| p.disk_quota));
+    metric("replicode_process_fuel_consumed_total", "counter", "Fuel the process has burned, about one unit per instruction.",
+        &per_process(|p| p.fuel));
+    let syscalls: Vec<(String, u64)> = snapshot.processes.iter()
+        .flat_map(|p| p.syscalls.iter().map(move |(name, count)| {
+            (format!("{{pid=\"{}\",syscall=\"{}\"}}", p.pid, name), *count)
+        }))
+        .collect();
+    metric("replicode_process_syscalls_total", "counter", "Calls the process has made to each syscall.",
+        &syscalls);
+    out
+}
+
+fn handle_client(mut stream: TcpStream) -> std::io::Result<()> {
+    let mut buffer = [0; 1024];
+    let n = stream.read(&mut buffer)?;
+    let request = String::from_utf8_lossy(&buffer[..n]);
+    let path = request.lines().next().unwrap_or("").split_whitespace().nth(1).unwrap_or("/");
+
+    let response = match path {
+        "/metrics" => {
+            let body = render();
+            format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\n\r\n{}",
+                body.len(),
+                body
+            )
+        }
+        _ => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n".to_string(),
+    };
+    stream.write_all(response.as_bytes())?;
+    stream.flush()
+}
@@ -2,3 +2,13 @@ pub mod process;
 pub mod scheduler;
 pub mod fd_table;  
 pub mod clock;
+pub mod file_cache;
+pub mod limits;
+pub mod watchdog;
+pub mod metrics;
+pub mod breakpoint;
+pub mod compile;
+pub mod quantum;
+pub mod secrets;
+pub mod sandbox_digest;
+pub mod tmpfs;
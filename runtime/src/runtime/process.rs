@@ -1,18 +1,31 @@
-use anyhow::Result;
+use anyhow::{bail, Result};
 use log::{debug, error, info};
 use std::{
-    fmt, fs::{self, create_dir_all}, panic::AssertUnwindSafe, path::{Path, PathBuf}, sync::{Arc, Condvar, Mutex}, thread
+    collections::HashMap, fmt, future::Future, fs::{self, create_dir_all}, panic::AssertUnwindSafe, path::{Component, Path, PathBuf}, sync::{atomic::AtomicU64, Arc, Condvar, Mutex}, task::{Context, Poll, Waker}, thread
 };
-use wasmtime::{Engine, Module, Store, Linker};
+use wasmtime::{Engine, Module, Store, Linker, WasmBacktrace};
+use rand_chacha::{rand_core::SeedableRng, ChaCha20Rng};
 use crate::wasi_syscalls::net::OutgoingNetworkMessage;
+use crate::wasi_syscalls::memory::validate_module_memory;
+use crate::runtime::file_cache::FileCache;
+use crate::runtime::tmpfs::Tmpfs;
+use crate::runtime::limits::{limit_exceeded, LimitExceeded, ProcessLimiter, ResourceLimit, ResourceLimits, DEFAULT_MAX_STDIN_BUFFER, DEFAULT_MAX_WRITE_BUFFER};
 use consensus::nat::NatTable;
 use crate::SANDBOX_ROOT;
 
 use crate::{
-    runtime::fd_table::{FDEntry, FDTable},
-    wasi_syscalls::{self, fs::get_dir_size},
+    runtime::{clock::GlobalClock, fd_table::{FDEntry, FDTable, FileTimes}, quantum::{self, FuelSlice}, secrets, watchdog},
+    wasi_syscalls::{self, fs::get_dir_size, syscall_counts::SyscallCounts},
 };
 
+/// Guest-visible port of a process's first socket when its Init record sets
+/// no `ports:` option. Each sock_open/sock_accept takes the next one, so the
+/// numbers only depend on the order of the guest's socket calls.
+pub const DEFAULT_FIRST_PORT: u16 = 1;
+
+/// Disk quota of a process whose Init record sets no `quota:` option.
+pub const DEFAULT_MAX_DISK_USAGE: u64 = 10 * 1024 * 1024;
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum ProcessState {
     Ready,
@@ -27,13 +40,21 @@ impl fmt::Display for ProcessState {
     }
 }
 
+/// The host path, and offset or None to append, that buffered writes go to.
+pub type WriteTarget = (String, Option<u64>);
+
 #[derive(Debug, Clone)]
 pub enum BlockReason {
     StdinRead,
     Timeout { resume_after: u64 },
     FileIO,
-    WriteIO(String),
+    /// The host path the buffered writes go to, and the offset they start
+    /// at; None appends them.
+    WriteIO(String, Option<u64>),
     NetworkIO,
+    /// Set by a Pause record and cleared only by a Resume record; `previous`
+    /// is what the process was blocked on before, if anything.
+    Paused { previous: Option<Box<BlockReason>> },
 }
 
 impl fmt::Display for BlockReason {
@@ -43,7 +64,47 @@ impl fmt::Display for BlockReason {
             BlockReason::Timeout { resume_after } => write!(f, "Timeout until {:?}", resume_after),
             BlockReason::FileIO => write!(f, "FileIO"),
             BlockReason::NetworkIO => write!(f, "NetworkIO"),
-            BlockReason::WriteIO(_) => write!(f, "WriteIO"),
+            BlockReason::WriteIO(..) => write!(f, "WriteIO"),
+            BlockReason::Paused { .. } => write!(f, "Paused"),
+        }
+    }
+}
+
+/// Why a process stopped, recorded when its `_start` returns.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TerminationReason {
+    Exited,
+    Trapped,
+    ResourceLimit(ResourceLimit),
+    Watchdog,
+    Shutdown,
+    Deadline,
+}
+
+impl fmt::Display for TerminationReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TerminationReason::Exited => write!(f, "exited"),
+            TerminationReason::Trapped => write!(f, "trapped"),
+            TerminationReason::ResourceLimit(limit) => write!(f, "killed by {} limit", limit),
+            TerminationReason::Watchdog => write!(f, "killed by the watchdog"),
+            TerminationReason::Shutdown => write!(f, "shut down by consensus"),
+            TerminationReason::Deadline => write!(f, "killed at its deadline"),
+        }
+    }
+}
+
+impl TerminationReason {
+    /// Classifies the outcome of calling `_start`. A trap after a refused
+    /// `memory.grow` (`refused`) counts as a memory limit kill.
+    fn from_result(result: &Result<()>, refused: Option<&LimitExceeded>) -> Self {
+        match result {
+            Ok(()) => TerminationReason::Exited,
+            Err(e) if watchdog::killed_by_watchdog(e) => TerminationReason::Watchdog,
+            Err(e) => match limit_exceeded(e).or(refused) {
+                Some(exceeded) => TerminationReason::ResourceLimit(exceeded.limit),
+                None => TerminationReason::Trapped,
+            },
         }
     }
 }
@@ -56,33 +117,128 @@ pub struct ProcessData {
     pub block_reason: Arc<Mutex<Option<BlockReason>>>,
     pub fd_table: Arc<Mutex<FDTable>>,
     pub root_path: PathBuf,
-    pub max_disk_usage: u64,
+    pub cwd: PathBuf, // where relative guest paths start, from the Init `cwd:` option
+    pub max_disk_usage: Arc<Mutex<u64>>, // changed by SetQuota records
+    pub read_only_root: bool, // from the Init `readonly:` option
+    pub tmpfs: Option<Arc<Mutex<Tmpfs>>>, // the sandbox's files, in memory, with the Init `tmpfs:` option
     pub current_disk_usage: Arc<Mutex<u64>>,
     pub write_buffer: Arc<Mutex<Vec<u8>>>,
-    pub max_write_buffer: usize,
+    pub write_target: Arc<Mutex<Option<WriteTarget>>>, // file and offset of what fd_write left in write_buffer
+    pub max_write_buffer: usize, // bytes fd_write buffers per file, from the Init `limits:` option
+    pub max_stdin_buffer: usize, // unread injected input per FD, from the Init `limits:` option
     pub id: u64,
-    pub next_port: Arc<Mutex<u16>>,
+    pub next_port: Arc<Mutex<u16>>, // last guest-visible port handed out, from the Init `ports:` option
     pub network_queue: Arc<Mutex<Vec<OutgoingNetworkMessage>>>,
+    pub network_cancelled: Arc<Mutex<bool>>, // set by a CancelNetwork record
+    pub network_pending: Arc<Mutex<bool>>, // a connect or datagram bind/send awaits its network status
+    pub network_failed: Arc<Mutex<bool>>, // set by a Failed network status
     pub nat_table: Arc<Mutex<NatTable>>,
     pub args: Vec<String>,
+    pub env: Vec<String>, // KEY=VALUE strings for environ_get, from the --secrets file
+    pub file_cache: Option<Arc<Mutex<FileCache>>>, // opt-in via the Init `cache:` option
+    pub limiter: ProcessLimiter, // from the Init `mem:` and `limits:` options
+    pub termination: Arc<Mutex<Option<TerminationReason>>>,
+    pub exit_code: Arc<Mutex<Option<i32>>>, // what the guest passed to proc_exit
+    pub backtrace: Arc<Mutex<Option<String>>>, // guest call stack when `_start` trapped
+    pub seq: Arc<Mutex<u64>>, // last value handed out by __builtin_rt_seq; a checkpoint, once there is one, must save it
+    pub batch: Arc<AtomicU64>, // consensus batch applied last, for __builtin_rt_batch
+    pub syscalls: Arc<SyscallCounts>,
+    pub turns: Arc<AtomicU64>, // times the scheduler has set it Running
+    pub fuel_slice: Arc<Mutex<FuelSlice>>, // with --quantum, how much of its turn it has used
+    pub fuel_left: Arc<AtomicU64>, // fuel left as of its last syscall, or once `_start` returned
+    pub fuel_used: Arc<Mutex<u64>>, // fuel burned in its turns so far, counted by the scheduler
+    pub rng: Arc<Mutex<ChaCha20Rng>>, // random_get's stream, from the Init `seed:` option and the pid
+    pub symlinks: Arc<Mutex<HashMap<PathBuf, PathBuf>>>, // emulated links: host path of the link -> target as the guest wrote it
+    pub file_times: Arc<Mutex<HashMap<String, FileTimes>>>, // host path -> its logical times, kept after the fd closes
+    pub deadline: Option<u64>, // logical time the scheduler ends it at, from the Init `deadline:` option
 }
 
 pub struct Process {
     pub id: u64, // Unique process ID
     pub thread: thread::JoinHandle<()>,
+    pub engine: Engine, // its own, so the watchdog can interrupt just this process
     pub data: ProcessData,
 }
+/// The random_get stream of process `id`. Every replica gets the seed from
+/// the same Init record, so they all see the same bytes, while processes
+/// started from one record still get different streams.
+fn process_rng(seed: u64, id: u64) -> ChaCha20Rng {
+    let mut key = [0u8; 32];
+    key[..8].copy_from_slice(&seed.to_le_bytes());
+    key[8..16].copy_from_slice(&id.to_le_bytes());
+    ChaCha20Rng::from_seed(key)
+}
+
+/// The environment of process `id`: the variables from its Init record, then
+/// the secrets. A secret replaces an Init variable with the same key, since
+/// the operator provisioned it.
+fn merge_env(id: u64, init_env: Vec<String>, secrets: Vec<String>) -> Vec<String> {
+    let key = |var: &String| var.split_once('=').map_or(var.clone(), |(key, _)| key.to_string());
+    let secret_keys: Vec<String> = secrets.iter().map(key).collect();
+    let mut env: Vec<String> = init_env.into_iter()
+        .filter(|var| {
+            let shadowed = secret_keys.contains(&key(var));
+            if shadowed {
+                info!("Process {}: secret {} replaces the variable from its Init record", id, key(var));
+            }
+            !shadowed
+        })
+        .collect();
+    env.extend(secrets);
+    env
+}
+
+/// Checks an Init `cwd:` option: a path below the sandbox root, which must
+/// not be absolute or contain `..`.
+fn sandbox_relative(dir: &str) -> Option<PathBuf> {
+    let path = Path::new(dir.trim());
+    path.components()
+        .all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+        .then(|| path.to_path_buf())
+}
+
 /// Creates a new process from a WASM binary (passed as a byte vector) and assigns it a unique ID.
-pub fn start_process_from_bytes(wasm_bytes: Vec<u8>, id: u64) -> Result<Process> {
+/// `fuel` is what it may use per turn (see `spawn_process`).
+pub fn start_process_from_bytes(wasm_bytes: Vec<u8>, id: u64, fuel: u64) -> Result<Process> {
     debug!("Starting process {} from WASM bytes", id);
-    let config = wasmtime::Config::new();
-    debug!("WASM config created");
-    let engine = Engine::new(&config)?;
-    debug!("WASM engine created");
+    let (options, wasm_bytes) = parse_init_payload(wasm_bytes, id)?;
+    let (engine, module) = compile_module(&wasm_bytes, &options.limits)?;
+    spawn_process(id, engine, module, options, fuel)
+}
 
+/// The options an Init payload sets before its module bytes.
+pub struct InitOptions {
+    args: Vec<String>,
+    preload_dir: Option<PathBuf>,
+    file_cache_bytes: Option<usize>,
+    pub limits: ResourceLimits,
+    read_only_root: bool,
+    tmpfs: bool,
+    first_port: u16,
+    cwd: PathBuf,
+    max_disk_usage: u64,
+    seed: u64,
+    env: Vec<String>,
+    deadline: Option<u64>,
+}
+
+/// Splits an Init payload into its options and the module bytes. An invalid
+/// option fails the Init, as a module that does not compile does, rather
+/// than start the process with a default it did not ask for.
+pub fn parse_init_payload(wasm_bytes: Vec<u8>, id: u64) -> Result<(InitOptions, Vec<u8>)> {
     let mut args = Vec::new();
     let mut wasm_bytes = wasm_bytes;
     let mut preload_dir = None;
+    let mut file_cache_bytes = None;
+    let mut limits = ResourceLimits::default();
+    let mut read_only_root = false;
+    let mut tmpfs = false;
+    let mut first_port = DEFAULT_FIRST_PORT;
+    let mut cwd = PathBuf::new();
+    let mut max_disk_usage = DEFAULT_MAX_DISK_USAGE;
+    let mut seed = 0;
+    let mut env = Vec::new();
+    let mut deadline = None;
     // Parse args and dir from the start of wasm_bytes
     loop {
         if wasm_bytes.starts_with(b"args:") {
@@ -95,6 +251,131 @@ pub fn start_process_from_bytes(wasm_bytes: Vec<u8>, id: u64) -> Result<Process>
             } else {
                 break;
             }
+        } else if wasm_bytes.starts_with(b"cache:") {
+            if let Some(null_pos) = wasm_bytes.iter().position(|&b| b == 0) {
+                let cache_str = String::from_utf8_lossy(&wasm_bytes[6..null_pos]);
+                match cache_str.trim().parse::<usize>() {
+                    Ok(bytes) => file_cache_bytes = Some(bytes),
+                    Err(e) => bail!("invalid file cache size {:?}: {}", cache_str, e),
+                }
+                wasm_bytes = wasm_bytes[null_pos+1..].to_vec();
+            } else {
+                break;
+            }
+        } else if wasm_bytes.starts_with(b"mem:") {
+            if let Some(null_pos) = wasm_bytes.iter().position(|&b| b == 0) {
+                let mem_str = String::from_utf8_lossy(&wasm_bytes[4..null_pos]);
+                match mem_str.trim().parse::<usize>() {
+                    Ok(bytes) => limits.max_memory = bytes,
+                    Err(e) => bail!("invalid memory limit {:?}: {}", mem_str, e),
+                }
+                wasm_bytes = wasm_bytes[null_pos+1..].to_vec();
+            } else {
+                break;
+            }
+        } else if wasm_bytes.starts_with(b"limits:") {
+            if let Some(null_pos) = wasm_bytes.iter().position(|&b| b == 0) {
+                let limits_str = String::from_utf8_lossy(&wasm_bytes[7..null_pos]);
+                if let Err(e) = limits.apply_overrides(&limits_str) {
+                    bail!("invalid resource limits {:?}: {}", limits_str, e);
+                }
+                wasm_bytes = wasm_bytes[null_pos+1..].to_vec();
+            } else {
+                break;
+            }
+        } else if wasm_bytes.starts_with(b"readonly:") {
+            if let Some(null_pos) = wasm_bytes.iter().position(|&b| b == 0) {
+                read_only_root = match &wasm_bytes[9..null_pos] {
+                    b"0" => false,
+                    b"1" => true,
+                    other => bail!("invalid read-only flag {:?}: expected 0 or 1", String::from_utf8_lossy(other)),
+                };
+                wasm_bytes = wasm_bytes[null_pos+1..].to_vec();
+            } else {
+                break;
+            }
+        } else if wasm_bytes.starts_with(b"tmpfs:") {
+            if let Some(null_pos) = wasm_bytes.iter().position(|&b| b == 0) {
+                tmpfs = match &wasm_bytes[6..null_pos] {
+                    b"0" => false,
+                    b"1" => true,
+                    other => bail!("invalid tmpfs flag {:?}: expected 0 or 1", String::from_utf8_lossy(other)),
+                };
+                wasm_bytes = wasm_bytes[null_pos+1..].to_vec();
+            } else {
+                break;
+            }
+        } else if wasm_bytes.starts_with(b"ports:") {
+            if let Some(null_pos) = wasm_bytes.iter().position(|&b| b == 0) {
+                let port_str = String::from_utf8_lossy(&wasm_bytes[6..null_pos]);
+                match port_str.trim().parse::<u16>() {
+                    Ok(port) if port > 0 => first_port = port,
+                    _ => bail!("invalid first port {:?}: it must be 1 to 65535", port_str),
+                }
+                wasm_bytes = wasm_bytes[null_pos+1..].to_vec();
+            } else {
+                break;
+            }
+        } else if wasm_bytes.starts_with(b"cwd:") {
+            if let Some(null_pos) = wasm_bytes.iter().position(|&b| b == 0) {
+                let cwd_str = String::from_utf8_lossy(&wasm_bytes[4..null_pos]);
+                match sandbox_relative(&cwd_str) {
+                    Some(dir) => cwd = dir,
+                    None => bail!("invalid working directory {:?}: it must be relative and not contain ..", cwd_str),
+                }
+                wasm_bytes = wasm_bytes[null_pos+1..].to_vec();
+            } else {
+                break;
+            }
+        } else if wasm_bytes.starts_with(b"quota:") {
+            if let Some(null_pos) = wasm_bytes.iter().position(|&b| b == 0) {
+                let quota_str = String::from_utf8_lossy(&wasm_bytes[6..null_pos]);
+                match quota_str.trim().parse::<u64>() {
+                    Ok(bytes) => max_disk_usage = bytes,
+                    Err(e) => bail!("invalid disk quota {:?}: {}", quota_str, e),
+                }
+                wasm_bytes = wasm_bytes[null_pos+1..].to_vec();
+            } else {
+                break;
+            }
+        } else if wasm_bytes.starts_with(b"seed:") {
+            if let Some(null_pos) = wasm_bytes.iter().position(|&b| b == 0) {
+                let seed_str = String::from_utf8_lossy(&wasm_bytes[5..null_pos]);
+                match seed_str.trim().parse::<u64>() {
+                    Ok(value) => seed = value,
+                    Err(e) => bail!("invalid random seed {:?}: {}", seed_str, e),
+                }
+                wasm_bytes = wasm_bytes[null_pos+1..].to_vec();
+            } else {
+                break;
+            }
+        } else if wasm_bytes.starts_with(b"deadline:") {
+            if let Some(null_pos) = wasm_bytes.iter().position(|&b| b == 0) {
+                let deadline_str = String::from_utf8_lossy(&wasm_bytes[9..null_pos]);
+                // Counted from when the Init record is applied, which is the
+                // same logical time on every replica
+                match deadline_str.trim().parse::<u64>() {
+                    Ok(nanos) => deadline = Some(GlobalClock::now().saturating_add(nanos)),
+                    Err(e) => bail!("invalid deadline {:?}: {}", deadline_str, e),
+                }
+                wasm_bytes = wasm_bytes[null_pos+1..].to_vec();
+            } else {
+                break;
+            }
+        } else if wasm_bytes.starts_with(b"env:") {
+            if let Some(null_pos) = wasm_bytes.iter().position(|&b| b == 0) {
+                let env_str = String::from_utf8_lossy(&wasm_bytes[4..null_pos]);
+                // KEY=VALUE strings, split by the Unit Separator like args
+                for var in env_str.split('\x1F') {
+                    match var.split_once('=') {
+                        Some((key, _)) if !key.is_empty() => env.push(var.to_string()),
+                        _ => bail!("invalid environment variable {:?}: expected KEY=VALUE", var),
+                    }
+                }
+                wasm_bytes = wasm_bytes[null_pos+1..].to_vec();
+            } else {
+                break;
+            }
         } else if wasm_bytes.starts_with(b"dir:") {
             if let Some(null_pos) = wasm_bytes.iter().position(|&b| b == 0) {
                 let dir_str = String::from_utf8_lossy(&wasm_bytes[4..null_pos]);
@@ -107,28 +388,66 @@ pub fn start_process_from_bytes(wasm_bytes: Vec<u8>, id: u64) -> Result<Process>
             break;
         }
     }
+    let options = InitOptions { args, preload_dir, file_cache_bytes, limits, read_only_root, tmpfs, first_port, cwd, max_disk_usage, seed, env, deadline };
+    Ok((options, wasm_bytes))
+}
+
+/// Compiles a guest module on an engine of its own and checks it against
+/// `limits`. This is the slow part of starting a process.
+pub fn compile_module(wasm_bytes: &[u8], limits: &ResourceLimits) -> Result<(Engine, Module)> {
+    let mut config = wasmtime::Config::new();
+    // Lets the scheduler's watchdog interrupt a guest that never yields
+    config.epoch_interruption(watchdog::kills());
+    // Traps carry the guest call stack, with function names if the module has them
+    config.wasm_backtrace(true);
+    // Metered to count what each process burns, and with --quantum or
+    // --fuel to slice it into scheduler turns
+    config.consume_fuel(true);
+    // Guests are run as futures, so running out of fuel suspends them
+    // instead of trapping (see `run_guest`)
+    config.async_support(true);
+    let engine = Engine::new(&config)?;
+    debug!("WASM engine created");
 
     // Load the module from the in-memory bytes.
-    let module = Module::new(&engine, &wasm_bytes)?;
+    let module = Module::new(&engine, wasm_bytes)?;
     debug!("WASM module loaded from bytes");
+    // Fail at Init rather than at the first syscall if the memory layout is unsupported
+    validate_module_memory(&module)?;
+    limits.check_module(&module)?;
+    Ok((engine, module))
+}
+
+/// Sets up the sandbox of process `id` and its guest thread, which waits for
+/// the scheduler to run it. Each turn it may use `fuel`, after which it goes
+/// back to the ready queue; `quantum::FUEL_TANK` never runs out.
+pub fn spawn_process(id: u64, engine: Engine, module: Module, options: InitOptions, fuel: u64) -> Result<Process> {
+    let InitOptions { args, preload_dir, file_cache_bytes, limits, read_only_root, tmpfs, first_port, cwd, max_disk_usage, seed, env, deadline } = options;
+    let env = merge_env(id, env, secrets::load(id)?);
 
     // Initialize process state and associated resources.
     let state = Arc::new(Mutex::new(ProcessState::Ready));
     let cond = Arc::new(Condvar::new());
     let block_reason = Arc::new(Mutex::new(None));
     let process_root = SANDBOX_ROOT.get().unwrap().join(format!("pid_{}", id));
-    let fd_table = Arc::new(Mutex::new(FDTable::new(process_root.clone())));
+    let cwd = process_root.join(cwd);
+    // The preopen is the guest's ".", so it is the working directory too
+    let fd_table = Arc::new(Mutex::new(FDTable::new(cwd.clone())));
     fs::create_dir_all(&process_root)?;
+    // With tmpfs the sandbox directory stays empty, and its files live here
+    let mut tmpfs = tmpfs.then(|| Tmpfs::new(&process_root));
 
-    let max_disk_usage = 1024 * 1024 * 10;
     // Optionally preload a directory
     let preload_size;
     if let Some(src_dir) = &preload_dir {
         if src_dir.exists() {
-            copy_dir_recursive(src_dir, &process_root)?;
+            let loaded = match &mut tmpfs {
+                Some(tmpfs) => tmpfs.load(src_dir),
+                None => copy_dir_recursive(src_dir, &process_root).and_then(|()| get_dir_size(&process_root)),
+            };
             info!("Preloaded {:?} into sandbox for process {}", src_dir, id);
 
-            preload_size = match get_dir_size(&process_root) {
+            preload_size = match loaded {
                 Ok(sz) => sz,
                 Err(e) => {
                     error!("Cannot compute size of preloaded data: {}", e);
@@ -142,7 +461,9 @@ pub fn start_process_from_bytes(wasm_bytes: Vec<u8>, id: u64) -> Result<Process>
                     preload_size, max_disk_usage, id
                 );
                 // Clean up the partially-created sandbox directory.
-                let _ = fs::remove_dir_all(&process_root);
+                if let Err(e) = fs::remove_dir_all(&process_root) {
+                    error!("Failed to remove sandbox of process {}: {}", id, e);
+                }
                 // Return an error so the caller knows the process wasn't started.
                 return Err(anyhow::anyhow!("Preloaded data exceeds disk quota; process not created."));
             }
@@ -154,6 +475,10 @@ pub fn start_process_from_bytes(wasm_bytes: Vec<u8>, id: u64) -> Result<Process>
     } else {
         preload_size = 0;
     }
+    match &mut tmpfs {
+        Some(tmpfs) => tmpfs.create_dir_all(&cwd)?,
+        None => fs::create_dir_all(&cwd)?,
+    }
 
     let process_data = ProcessData {
         state: state.clone(),
@@ -161,46 +486,83 @@ pub fn start_process_from_bytes(wasm_bytes: Vec<u8>, id: u64) -> Result<Process>
         block_reason,
         fd_table,
         root_path: process_root,
-        max_disk_usage: max_disk_usage, // 10MB default limit
+        cwd,
+        max_disk_usage: Arc::new(Mutex::new(max_disk_usage)),
+        read_only_root,
+        tmpfs: tmpfs.map(|tmpfs| Arc::new(Mutex::new(tmpfs))),
         current_disk_usage: Arc::new(Mutex::new(preload_size)),
         write_buffer: Arc::new(Mutex::new(Vec::new())),
-        max_write_buffer: 1024,
+        write_target: Arc::new(Mutex::new(None)),
+        max_write_buffer: limits.max_write_buffer,
+        max_stdin_buffer: limits.max_stdin_buffer,
         id,
-        next_port: Arc::new(Mutex::new(0)),
+        next_port: Arc::new(Mutex::new(first_port - 1)),
         network_queue: Arc::new(Mutex::new(Vec::new())),
+        network_cancelled: Arc::new(Mutex::new(false)),
+        network_pending: Arc::new(Mutex::new(false)),
+        network_failed: Arc::new(Mutex::new(false)),
         nat_table: Arc::new(Mutex::new(NatTable::new())),
         args,
+        env,
+        file_cache: file_cache_bytes.map(|bytes| Arc::new(Mutex::new(FileCache::new(bytes)))),
+        limiter: ProcessLimiter::new(limits),
+        termination: Arc::new(Mutex::new(None)),
+        exit_code: Arc::new(Mutex::new(None)),
+        backtrace: Arc::new(Mutex::new(None)),
+        seq: Arc::new(Mutex::new(0)),
+        batch: Arc::new(AtomicU64::new(0)),
+        syscalls: Arc::new(SyscallCounts::new()),
+        turns: Arc::new(AtomicU64::new(0)),
+        fuel_slice: Arc::new(Mutex::new(FuelSlice::new())),
+        fuel_left: Arc::new(AtomicU64::new(quantum::FUEL_TANK)),
+        fuel_used: Arc::new(Mutex::new(0)),
+        rng: Arc::new(Mutex::new(process_rng(seed, id))),
+        symlinks: Arc::new(Mutex::new(HashMap::new())),
+        file_times: Arc::new(Mutex::new(HashMap::new())),
+        deadline,
     };
 
     let thread_data = process_data.clone();
+    let thread_engine = engine.clone();
     let thread = thread::Builder::new()
         .name(format!("pid{}", id))
         .spawn(move || {
-            let mut store = Store::new(&engine, thread_data);
-            // Set fuel (or other resource limits) as needed.
-            let _ = store.set_fuel(2_000_000);
-            let mut linker: Linker<ProcessData> = Linker::new(&engine);
+            let data = thread_data.clone();
+            let mut store = Store::new(&thread_engine, thread_data);
+            store.limiter(|data| &mut data.limiter);
+            // Fails harmlessly unless fuel is metered for --quantum or --fuel
+            let _ = store.set_fuel(quantum::FUEL_TANK);
+            if fuel != quantum::FUEL_TANK {
+                let _ = store.fuel_async_yield_interval(Some(fuel));
+            }
+            store.set_epoch_deadline(1);
+            let mut linker: Linker<ProcessData> = Linker::new(&thread_engine);
             if let Err(e) = wasi_syscalls::register(&mut linker) {
                 error!("Failed to register WASI syscalls: {:?}", e);
                 return;
             }
             debug!("WASI syscalls registered");
 
-            let instance = match linker.instantiate(&mut store, &module) {
+            let instance = match run_guest(&data, linker.instantiate_async(&mut store, &module)) {
                 Ok(inst) => inst,
                 Err(e) => {
                     error!("Failed to instantiate module: {:?}", e);
+                    // Finish on the scheduler's first turn so it reaps the process
+                    // instead of waiting on it forever
+                    record_termination(store.data(), &Err(e));
+                    if wait_for_turn(store.data()) {
+                        *store.data().state.lock().unwrap() = ProcessState::Finished;
+                        store.data().cond.notify_all();
+                    }
                     return;
                 }
             };
             debug!("WASM module instantiated");
 
             // Wait until the scheduler sets the process state to Running.
-            {
-                let mut st = store.data().state.lock().unwrap();
-                while *st != ProcessState::Running {
-                    st = store.data().cond.wait(st).unwrap();
-                }
+            if !wait_for_turn(store.data()) {
+                info!("Process {} was shut down before it started", id);
+                return;
             }
 
             // Call the _start function.
@@ -211,8 +573,14 @@ pub fn start_process_from_bytes(wasm_bytes: Vec<u8>, id: u64) -> Result<Process>
                     return;
                 }
             };
-            if let Err(e) = start_func.call(&mut store, ()) {
-                error!("Error executing wasm: {:?}", e);
+            let result = run_guest(&data, start_func.call_async(&mut store, ()));
+            note_final_fuel(&store);
+            record_termination(store.data(), &result);
+            match &result {
+                Err(_) if exited_via_proc_exit(store.data()) => {}
+                // The backtrace, if any, was logged by record_termination
+                Err(e) => error!("Error executing wasm: {}", e.root_cause()),
+                Ok(()) => {}
             }
             // Mark process as Finished.
             {
@@ -224,27 +592,36 @@ pub fn start_process_from_bytes(wasm_bytes: Vec<u8>, id: u64) -> Result<Process>
         })?;
 
     info!("Started process with id {}", id);
-    Ok(Process { id, thread, data: process_data })
+    Ok(Process { id, thread, data: process_data, engine })
 }
 
 /// Spawns a new process from a WASM module and assigns it a unique ID.
 /// Now also optionally copies a preload directory (`preload_dir`) into the
-/// new process sandbox before execution starts.
+/// new process sandbox before execution starts. `fuel` is what it may use
+/// per turn, as in `spawn_process`; it used to get 2_000_000 in all.
+#[allow(dead_code)]
 pub fn start_process(
     wasm_path: PathBuf,
     id: u64,
     preload_dir: Option<&Path>,
     max_disk_bytes: u64,
     args: Vec<String>,
+    fuel: u64,
 ) -> Result<Process> {
     debug!("Starting process with path: {:?} and id: {}", wasm_path, id);
     let mut config = wasmtime::Config::new();
     config.consume_fuel(true);
+    config.async_support(true);
+    config.epoch_interruption(watchdog::kills());
+    config.wasm_backtrace(true);
     let engine = Engine::new(&config)?;
     let module = Module::from_file(&engine, &wasm_path)?;
     debug!("WASM module loaded from path: {:?}", wasm_path);
+    validate_module_memory(&module)?;
+    ResourceLimits::default().check_module(&module)?;
+    let env = secrets::load(id)?;
 
-    // Create the sandbox directory in "wasi_sandbox/pid_<ID>"
+    // Create the sandbox directory in "<sandbox root>/pid_<ID>"
     let sandbox_base = SANDBOX_ROOT.get().unwrap().clone();
     let process_root_rel = sandbox_base.join(format!("pid_{}", id));
     create_dir_all(&process_root_rel)?;
@@ -262,9 +639,14 @@ pub fn start_process(
         table.entries[0] = Some(FDEntry::File {
             buffer: Vec::new(),
             read_ptr: 0,
+            write_ptr: 0,
             is_directory: false,
             is_preopen: false,
             host_path: None,
+            flush_threshold: None,
+            append: false,
+            nonblocking: false,
+            times: FileTimes::default(),
         });
     }
 
@@ -284,9 +666,14 @@ pub fn start_process(
         table.entries[3] = Some(FDEntry::File {
             buffer: Vec::new(),
             read_ptr: 0,
+            write_ptr: 0,
             is_directory: true,
             is_preopen: true,
             host_path: Some(process_root.to_string_lossy().into_owned()),
+            flush_threshold: None,
+            append: false,
+            nonblocking: false,
+            times: FileTimes::default(),
         });
     }
 
@@ -296,18 +683,44 @@ pub fn start_process(
         block_reason: reason,
         fd_table,
         root_path: process_root.clone(),
-        max_disk_usage: max_disk_bytes,
+        cwd: process_root.clone(),
+        max_disk_usage: Arc::new(Mutex::new(max_disk_bytes)),
+        read_only_root: false,
+        tmpfs: None,
         current_disk_usage: Arc::new(Mutex::new(0)),
         write_buffer: Arc::new(Mutex::new(Vec::new())),
-        max_write_buffer: 1024,
+        write_target: Arc::new(Mutex::new(None)),
+        max_write_buffer: DEFAULT_MAX_WRITE_BUFFER,
+        max_stdin_buffer: DEFAULT_MAX_STDIN_BUFFER,
         id,
-        next_port: Arc::new(Mutex::new(0)),
+        next_port: Arc::new(Mutex::new(DEFAULT_FIRST_PORT - 1)),
         network_queue: Arc::new(Mutex::new(Vec::new())),
+        network_cancelled: Arc::new(Mutex::new(false)),
+        network_pending: Arc::new(Mutex::new(false)),
+        network_failed: Arc::new(Mutex::new(false)),
         nat_table: Arc::new(Mutex::new(NatTable::new())),
         args,
+        env,
+        file_cache: None,
+        limiter: ProcessLimiter::new(ResourceLimits::default()),
+        termination: Arc::new(Mutex::new(None)),
+        exit_code: Arc::new(Mutex::new(None)),
+        backtrace: Arc::new(Mutex::new(None)),
+        seq: Arc::new(Mutex::new(0)),
+        batch: Arc::new(AtomicU64::new(0)),
+        syscalls: Arc::new(SyscallCounts::new()),
+        turns: Arc::new(AtomicU64::new(0)),
+        fuel_slice: Arc::new(Mutex::new(FuelSlice::new())),
+        fuel_left: Arc::new(AtomicU64::new(quantum::FUEL_TANK)),
+        fuel_used: Arc::new(Mutex::new(0)),
+        rng: Arc::new(Mutex::new(process_rng(0, id))),
+        symlinks: Arc::new(Mutex::new(HashMap::new())),
+        file_times: Arc::new(Mutex::new(HashMap::new())),
+        deadline: None,
     };
 
     let process_data_clone = process_data.clone();
+    let thread_engine = engine.clone();
     let thread = thread::Builder::new()
         .name(format!("pid{}", id))
         .spawn(move || {
@@ -318,24 +731,26 @@ pub fn start_process(
                     thread::current().name().unwrap_or("unknown"),
                     id
                 );
-                let mut store = Store::new(&engine, process_data_clone.clone());
-                let _ = store.set_fuel(2_000_000);
+                let mut store = Store::new(&thread_engine, process_data_clone.clone());
+                store.limiter(|data| &mut data.limiter);
+                let _ = store.set_fuel(quantum::FUEL_TANK);
+                if fuel != quantum::FUEL_TANK {
+                    let _ = store.fuel_async_yield_interval(Some(fuel));
+                }
+                store.set_epoch_deadline(1);
 
-                let mut linker: Linker<ProcessData> = Linker::new(&engine);
+                let mut linker: Linker<ProcessData> = Linker::new(&thread_engine);
                 wasi_syscalls::register(&mut linker).expect("Failed to register WASI syscalls");
                 debug!("WASI syscalls registered for process {}", id);
 
                 // Instantiate the module
-                let instance = linker
-                    .instantiate(&mut store, &module)
+                let instance = run_guest(&process_data_clone, linker.instantiate_async(&mut store, &module))
                     .expect("Failed to instantiate module");
 
                 debug!("Process {} instantiated; waiting for state=Running", id);
-                {
-                    let mut st = store.data().state.lock().unwrap();
-                    while *st != ProcessState::Running {
-                        st = store.data().cond.wait(st).unwrap();
-                    }
+                if !wait_for_turn(store.data()) {
+                    info!("Process {} was shut down before it started", id);
+                    return;
                 }
 
                 // Call _start
@@ -343,8 +758,13 @@ pub fn start_process(
                     .get_typed_func::<(), ()>(&mut store, "_start")
                     .expect("Missing _start function");
 
-                if let Err(e) = start_func.call(&mut store, ()) {
-                    error!("Process {}: error executing _start: {:?}", id, e);
+                let result = run_guest(&process_data_clone, start_func.call_async(&mut store, ()));
+                note_final_fuel(&store);
+                record_termination(store.data(), &result);
+                match &result {
+                    Err(_) if exited_via_proc_exit(store.data()) => {}
+                    Err(e) => error!("Process {}: error executing _start: {}", id, e.root_cause()),
+                    Ok(()) => {}
                 }
 
                 // Mark finished
@@ -369,7 +789,246 @@ pub fn start_process(
         })?;
 
     info!("Started process with id {}", id);
-    Ok(Process { id, thread, data: process_data })
+    Ok(Process { id, thread, data: process_data, engine })
+}
+
+/// Runs a guest call on the process's own thread. The future is pending only
+/// when the guest has used up its turn's fuel: the process then yields to the
+/// scheduler, which puts it back in the ready queue, and the call carries on
+/// from where it stopped, refueled, once the process runs again. Fuel counts
+/// instructions, so it stops at the same point on every replica.
+fn run_guest<T>(data: &ProcessData, future: impl Future<Output = Result<T>>) -> Result<T> {
+    let mut future = std::pin::pin!(future);
+    let mut context = Context::from_waker(Waker::noop());
+    loop {
+        if let Poll::Ready(result) = future.as_mut().poll(&mut context) {
+            return result;
+        }
+        debug!("Process {} used up its fuel for this turn; yielding", data.id);
+        {
+            let mut state = data.state.lock().unwrap();
+            if *state == ProcessState::Running {
+                *state = ProcessState::Ready;
+            }
+        }
+        data.cond.notify_all();
+        if !wait_for_turn(data) {
+            // Shut down while it waited; dropping the call abandons it
+            let reason = data.termination.lock().unwrap().unwrap_or(TerminationReason::Shutdown);
+            return Err(anyhow::anyhow!("process {}", reason));
+        }
+    }
+}
+
+/// Notes the fuel the guest has left once `_start` has returned, so the
+/// scheduler counts its last turn in full.
+fn note_final_fuel(store: &Store<ProcessData>) {
+    if let Ok(fuel) = store.get_fuel() {
+        quantum::note_fuel_left(store.data(), fuel);
+    }
+}
+
+/// Whether the guest ended itself with proc_exit, whose trap out of `_start`
+/// is how it stops rather than an error.
+fn exited_via_proc_exit(data: &ProcessData) -> bool {
+    data.exit_code.lock().unwrap().is_some()
+}
+
+/// Records why `_start` returned, logging resource-limit kills distinctly
+/// from ordinary traps, and keeps the guest backtrace of a trap.
+fn record_termination(data: &ProcessData, result: &Result<()>) {
+    info!("Process {} syscalls: {}", data.id, data.syscalls);
+    // Already ended by the runtime (see `shut_down`); the trap it left
+    // `_start` with is not the guest's doing
+    if data.termination.lock().unwrap().is_some() {
+        return;
+    }
+    let refused = data.limiter.refused_memory();
+    if let Some(exceeded) = result.as_ref().err().and_then(|e| limit_exceeded(e).or(refused)) {
+        error!("Process {} was terminated: {}", data.id, exceeded);
+    }
+    if let Some(backtrace) = result.as_ref().err().and_then(|e| e.downcast_ref::<WasmBacktrace>()) {
+        let backtrace = backtrace.to_string();
+        error!("Process {} trapped in:\n{}", data.id, backtrace);
+        *data.backtrace.lock().unwrap() = Some(backtrace);
+    }
+    *data.termination.lock().unwrap() = Some(TerminationReason::from_result(result, refused));
+}
+
+/// Blocks the calling guest thread on `reason` until the scheduler sets the
+/// process Running again. Every blocking syscall goes through here, so the
+/// set-reason, set-Blocked, notify, wait sequence is the same for all of them
+/// and the scheduler's unblock check sees each reason it has to handle.
+///
+/// A process paused while it was running keeps its pause: `reason` becomes
+/// what it goes back to waiting on once resumed. Any other process that is
+/// not Running is already parked in a syscall, so blocking again would wait
+/// for a wake-up nobody sends. That is refused and false returned, and the
+/// caller fails the syscall instead.
+pub fn block_until_running(data: &ProcessData, reason: BlockReason) -> bool {
+    // Only a Resume record clears Paused, so a syscall must never block on it
+    debug_assert!(!matches!(reason, BlockReason::Paused { .. }), "syscalls cannot block on Paused");
+    {
+        let mut state = data.state.lock().unwrap();
+        let mut current = data.block_reason.lock().unwrap();
+        match (*state, &mut *current) {
+            (ProcessState::Running, _) => {
+                debug!("Process {}: blocking on {}", data.id, reason);
+                *current = Some(reason);
+                *state = ProcessState::Blocked;
+            }
+            (ProcessState::Blocked, Some(BlockReason::Paused { previous })) if previous.is_none() => {
+                debug!("Process {}: paused, blocking on {} once resumed", data.id, reason);
+                *previous = Some(Box::new(reason));
+            }
+            (state, current) => {
+                error!(
+                    "Process {}: refusing to block on {} while {:?} ({})",
+                    data.id,
+                    reason,
+                    state,
+                    current.as_ref().map_or("no reason".to_string(), |r| r.to_string())
+                );
+                return false;
+            }
+        }
+    }
+    data.cond.notify_all();
+
+    // After a Shutdown record the caller's result is replaced by a trap
+    // (see `Counted`), so the guest never sees it
+    wait_for_turn(data)
+}
+
+/// Waits until the scheduler sets the process Running. Returns false
+/// instead if it is shut down meanwhile, in which case the guest must not
+/// run any further.
+fn wait_for_turn(data: &ProcessData) -> bool {
+    let mut state = data.state.lock().unwrap();
+    while *state != ProcessState::Running {
+        if *state == ProcessState::Finished {
+            return false;
+        }
+        state = data.cond.wait(state).unwrap();
+    }
+    true
+}
+
+/// Ends a process for a Shutdown record. It must be parked waiting for its
+/// turn, as every process is while consensus input is applied: it is set
+/// Finished, so the scheduler reaps it like one that exited, and its thread
+/// wakes up and leaves `_start` with a trap from the syscall it was in (or
+/// from `run_guest`, if it had run out of fuel), or never calls `_start` if
+/// it had not started. Returns false if it had
+/// already finished.
+pub fn shut_down(data: &ProcessData) -> bool {
+    end_process(data, TerminationReason::Shutdown)
+}
+
+/// Ends a process whose Init `deadline:` has passed, the way `shut_down`
+/// does, so it is reaped like one that exited. Returns false if it had
+/// already finished.
+pub fn kill_at_deadline(data: &ProcessData) -> bool {
+    end_process(data, TerminationReason::Deadline)
+}
+
+fn end_process(data: &ProcessData, reason: TerminationReason) -> bool {
+    let mut state = data.state.lock().unwrap();
+    if *state == ProcessState::Finished {
+        return false;
+    }
+    *data.termination.lock().unwrap() = Some(reason);
+    *state = ProcessState::Finished;
+    drop(state);
+    data.cond.notify_all();
+    true
+}
+
+/// Stops scheduling a process until `resume_process`. The process is left
+/// Blocked with `BlockReason::Paused`, which the scheduler never satisfies on
+/// its own. Its thread is parked waiting to be set Running, so it holds no
+/// locks while paused. Returns false if there was nothing to pause.
+pub fn pause_process(data: &ProcessData) -> bool {
+    let mut state = data.state.lock().unwrap();
+    let mut reason = data.block_reason.lock().unwrap();
+    match *state {
+        ProcessState::Finished => return false,
+        ProcessState::Blocked if matches!(*reason, Some(BlockReason::Paused { .. })) => return false,
+        _ => {}
+    }
+    // A Ready process has nothing to go back to; a Blocked one keeps its reason for later
+    let previous = match *state {
+        ProcessState::Blocked => reason.take().map(Box::new),
+        _ => None,
+    };
+    *reason = Some(BlockReason::Paused { previous });
+    *state = ProcessState::Blocked;
+    true
+}
+
+/// Undoes `pause_process`. A process that was Blocked before the pause goes
+/// back to waiting on its original reason; otherwise it becomes Ready.
+/// Returns false if the process was not paused.
+pub fn resume_process(data: &ProcessData) -> bool {
+    let mut state = data.state.lock().unwrap();
+    let mut reason = data.block_reason.lock().unwrap();
+    let previous = match reason.take() {
+        Some(BlockReason::Paused { previous }) => previous,
+        other => {
+            *reason = other;
+            return false;
+        }
+    };
+    match previous {
+        Some(previous) => *reason = Some(*previous),
+        None => *state = ProcessState::Ready,
+    }
+    drop(reason);
+    drop(state);
+    data.cond.notify_all();
+    true
+}
+
+/// Fails the network operations `data`'s process is waiting on, for a
+/// CancelNetwork record: unsent operations are dropped, accept/recv waits are
+/// cleared, and the blocked syscall returns an error once the scheduler next
+/// looks at it. A paused process gets the error after it is resumed. Returns
+/// false if the process was not blocked on the network.
+pub fn cancel_network(data: &ProcessData) -> bool {
+    let reason = data.block_reason.lock().unwrap();
+    let waiting = match &*reason {
+        Some(BlockReason::NetworkIO) => true,
+        Some(BlockReason::Paused { previous }) => matches!(previous.as_deref(), Some(BlockReason::NetworkIO)),
+        _ => false,
+    };
+    if !waiting {
+        return false;
+    }
+    let dropped = data.network_queue.lock().unwrap().drain(..).count();
+    let cleared = data.nat_table.lock().unwrap().cancel_pending(data.id);
+    debug!("Process {}: dropped {} queued and {} waiting network operations", data.id, dropped, cleared);
+    *data.network_cancelled.lock().unwrap() = true;
+    true
+}
+
+/// Returns the disk space `data`'s process uses and its quota, in bytes.
+pub fn disk_usage(data: &ProcessData) -> (u64, u64) {
+    let usage = data.current_disk_usage.lock().unwrap();
+    let quota = data.max_disk_usage.lock().unwrap();
+    (*usage, *quota)
+}
+
+/// Changes the disk quota of `data`'s process for a SetQuota record. A quota
+/// below what the process already uses is refused, and the current usage is
+/// returned as the error.
+pub fn set_disk_quota(data: &ProcessData, bytes: u64) -> Result<(), u64> {
+    // Same lock order as the fs syscalls: usage, then quota
+    let usage = data.current_disk_usage.lock().unwrap();
+    if bytes < *usage {
+        return Err(*usage);
+    }
+    *data.max_disk_usage.lock().unwrap() = bytes;
+    Ok(())
 }
 
 /// Recursively copy all files & subdirectories from `src` into `dst`.
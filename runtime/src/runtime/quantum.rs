@@ -0,0 +1,121 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use log::debug;
+use wasmtime::Caller;
+use crate::runtime::process::ProcessData;
+use crate::wasi_syscalls::builtin_yield::yield_to_scheduler;
+
+// 0 means no quantum: a process runs until it yields, blocks or finishes
+static QUANTUM: AtomicU64 = AtomicU64::new(0);
+// Fuel a process may burn in one turn, set with --fuel; FUEL_TANK for no limit
+static FUEL: AtomicU64 = AtomicU64::new(DEFAULT_FUEL);
+
+/// Fuel a process may burn in one turn without `--fuel`.
+const DEFAULT_FUEL: u64 = 2_000_000;
+
+/// Fuel a store starts with. Fuel is always metered, to count what each
+/// process burns and, with a quantum or `--fuel`, to slice it into turns;
+/// running out is not a limit anyone is meant to reach.
+pub const FUEL_TANK: u64 = u64::MAX;
+
+/// Where the current fuel slice of a process started.
+#[derive(Debug)]
+pub struct FuelSlice {
+    turn: u64,       // scheduler turn the slice belongs to
+    start: u64,      // fuel left when the turn began
+    last_entry: u64, // fuel left at the last syscall the guest made
+}
+
+impl Default for FuelSlice {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FuelSlice {
+    pub fn new() -> Self {
+        FuelSlice { turn: 0, start: FUEL_TANK, last_entry: FUEL_TANK }
+    }
+}
+
+/// Applies `--quantum=<fuel>` or `--fuel=<fuel>`. Returns true if `arg` was
+/// one of them, so callers can strip it before positional argument handling.
+pub fn apply_flag(arg: &str) -> bool {
+    if let Some(fuel) = arg.strip_prefix("--quantum=") {
+        match fuel.parse() {
+            Ok(fuel) => QUANTUM.store(fuel, Ordering::SeqCst),
+            Err(_) => log::warn!("Ignoring invalid quantum: {}", fuel),
+        }
+        return true;
+    }
+    let Some(fuel) = arg.strip_prefix("--fuel=") else {
+        return false;
+    };
+    match fuel.parse() {
+        Ok(0) => FUEL.store(FUEL_TANK, Ordering::SeqCst),
+        Ok(fuel) => FUEL.store(fuel, Ordering::SeqCst),
+        Err(_) => log::warn!("Ignoring invalid fuel: {}", fuel),
+    }
+    true
+}
+
+/// Fuel a process gets for each turn: 2_000_000 unless `--fuel` says
+/// otherwise, and `FUEL_TANK` for `--fuel=0`. Unlike the quantum, it also
+/// stops a guest that computes without making syscalls: once it is used up
+/// the guest yields wherever it is, and carries on refueled on its next turn.
+pub fn fuel() -> u64 {
+    FUEL.load(Ordering::SeqCst)
+}
+
+/// Notes how much fuel process `data` has left, for `count_turn`. Its thread
+/// calls this on entry to every syscall and once `_start` returns, which is
+/// where its turns end, except one `--fuel` cuts short in the middle of the
+/// guest's code; what that turn burned is noted at the next syscall.
+pub fn note_fuel_left(data: &ProcessData, fuel: u64) {
+    data.fuel_left.store(fuel, Ordering::SeqCst);
+}
+
+/// Adds the fuel process `data` burned since it was last counted to its
+/// total, and returns it. The scheduler calls this each time the process
+/// stops running.
+pub fn count_turn(data: &ProcessData) -> u64 {
+    let burned = FUEL_TANK - data.fuel_left.load(Ordering::SeqCst);
+    let mut used = data.fuel_used.lock().unwrap();
+    let turn = burned.saturating_sub(*used);
+    *used += turn;
+    turn
+}
+
+/// Called on entry to every syscall. Once the process has used up a quantum
+/// of fuel in its current turn, it yields to the scheduler before the
+/// syscall runs, and goes to the back of the ready queue.
+///
+/// Fuel counts executed instructions, not time, so every replica (and every
+/// replay) yields at the same syscall and interleaves processes the same way.
+/// A guest that computes without making syscalls cannot be stopped midway;
+/// its quantum ends at its next syscall.
+pub fn check(caller: &Caller<'_, ProcessData>) {
+    let Ok(fuel) = caller.get_fuel() else {
+        return;
+    };
+    let data = caller.data();
+    note_fuel_left(data, fuel);
+    let quantum = QUANTUM.load(Ordering::SeqCst);
+    if quantum == 0 {
+        return;
+    }
+    let used = {
+        let mut slice = data.fuel_slice.lock().unwrap();
+        let turn = data.turns.load(Ordering::SeqCst);
+        if slice.turn != turn {
+            // The process resumed inside the syscall it last entered
+            slice.turn = turn;
+            slice.start = slice.last_entry;
+        }
+        slice.last_entry = fuel;
+        slice.start - fuel
+    };
+    if used >= quantum {
+        debug!("Process {} used {} fuel of a {} quantum; yielding", data.id, used, quantum);
+        yield_to_scheduler(data);
+    }
+}
@@ -0,0 +1,67 @@
+use log::error;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Mutex;
+
+// Digests of the sandboxes removed so far, by pid; None until `enable` is called
+static DIGESTS: Mutex<Option<BTreeMap<u64, String>>> = Mutex::new(None);
+
+/// Makes the scheduler record a digest of each process's sandbox just before
+/// removing it, for `replay` to print.
+pub fn enable() {
+    DIGESTS.lock().unwrap().get_or_insert_with(BTreeMap::new);
+}
+
+/// Records the digest of `root`, the sandbox of process `pid`, if digests are
+/// enabled. Called with the sandbox as the process left it.
+pub fn record(pid: u64, root: &Path) {
+    let mut digests = DIGESTS.lock().unwrap();
+    let Some(digests) = digests.as_mut() else {
+        return;
+    };
+    match digest(root) {
+        Ok(hex) => {
+            digests.insert(pid, hex);
+        }
+        Err(e) => error!("Failed to hash sandbox of process {}: {}", pid, e),
+    }
+}
+
+/// The digests recorded so far, in pid order.
+pub fn take() -> BTreeMap<u64, String> {
+    DIGESTS.lock().unwrap().as_mut().map(std::mem::take).unwrap_or_default()
+}
+
+/// SHA-256 of a sandbox, as hex. It covers the path of every file and
+/// directory below `root`, relative to it and in sorted order, and each
+/// file's contents, so it does not depend on where the sandbox is on the host
+/// or on the order the host lists directories in. A missing sandbox hashes
+/// like an empty one.
+fn digest(root: &Path) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    if root.exists() {
+        hash_dir(&mut hasher, root, Path::new(""))?;
+    }
+    Ok(hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+fn hash_dir(hasher: &mut Sha256, dir: &Path, relative: &Path) -> io::Result<()> {
+    let mut entries = fs::read_dir(dir)?.collect::<io::Result<Vec<_>>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let path = relative.join(entry.file_name());
+        let name = path.to_string_lossy();
+        if entry.file_type()?.is_dir() {
+            hasher.update(format!("d {}\0", name));
+            hash_dir(hasher, &entry.path(), &path)?;
+        } else {
+            let contents = fs::read(entry.path())?;
+            hasher.update(format!("f {} {}\0", name, contents.len()));
+            hasher.update(&contents);
+        }
+    }
+    Ok(())
+}
@@ -1,38 +1,46 @@
 use anyhow::Result;
 use crate::{
-    consensus_input:: {process_consensus_file, process_consensus_pipe},
+    consensus_follow::FileFollower,
+    consensus_tcp,
+    consensus_input:: {process_consensus_file, process_consensus_pipe, queue_process_exit, send_hello, InputBudget, InputLimits, PipeStatus},
     runtime::{
         clock::GlobalClock,
-        process::{BlockReason, Process, ProcessState},
-    }, wasi_syscalls::fs::flush_write_buffer_for_scheduler,
+        process::{self, BlockReason, Process, ProcessState},
+        breakpoint,
+        metrics,
+        quantum,
+        sandbox_digest,
+        watchdog,
+    }, wasi_syscalls::fs::{flush_pending_write, flush_write_buffer_for_scheduler},
 };
-use std::{collections::VecDeque, fs};
-use std::io::{Read, Write};
-use log::{debug, error, info};
+use std::{collections::VecDeque, fs, path::Path, sync::atomic::Ordering, time::{Duration, Instant}};
+use std::io::{self, Read, Write};
+use consensus::batch::{encode_wire, UNKNOWN_CLOCK, WIRE_VERSION};
+use consensus::{BatchDirection, BatchHistory};
+use log::{debug, error, info, warn};
 use std::thread;
 use crate::wasi_syscalls::net::OutgoingNetworkMessage;
 use crate::runtime::fd_table::FDEntry;
 use std::io::BufReader;
+use std::net::{SocketAddr, TcpStream};
 
 struct BatchCollector {
     outgoing_messages: Vec<OutgoingNetworkMessage>,
-    batch_start_time: u64,
 }
 
 impl BatchCollector {
     fn new() -> Self {
         BatchCollector {
             outgoing_messages: Vec::new(),
-            batch_start_time: GlobalClock::now(),
         }
     }
 
     fn collect_network_messages(&mut self, processes: &[Process]) {
         for process in processes {
+            // In the order the process issued them: a Close must not overtake
+            // the Connect or Send before it
             let mut queue = process.data.network_queue.lock().unwrap();
-            while let Some(msg) = queue.pop() {
-                self.outgoing_messages.push(msg);
-            }
+            self.outgoing_messages.extend(queue.drain(..));
         }
     }
 }
@@ -40,14 +48,23 @@ impl BatchCollector {
 /// A dynamic scheduler that runs indefinitely and uses a generic consensus function.
 /// The consensus function receives a mutable vector of processes (which may be new or blocked)
 /// and updates their state based on external input.
-pub fn run_scheduler_dynamic<F>(processes: Vec<Process>, mut consensus_input: F) -> Result<()>
+///
+/// Once the input allowed by `limits` has been applied, no more is read: the
+/// scheduler runs processes until none is ready, then stops and leaves the
+/// rest blocked (see `stop_blocked_processes`).
+pub fn run_scheduler_dynamic<F>(processes: Vec<Process>, limits: InputLimits, mut consensus_input: F) -> Result<()>
 where
-    F: FnMut(&mut Vec<Process>, Vec<OutgoingNetworkMessage>) -> Result<bool>,
+    F: FnMut(&mut Vec<Process>, Vec<OutgoingNetworkMessage>, &mut InputBudget) -> Result<bool>,
 {
     let mut ready_queue: VecDeque<Process> = processes.into();
     let mut blocked_queue: VecDeque<Process> = VecDeque::new();
     let mut has_more_input = true;
     let mut batch_collector = BatchCollector::new();
+    let mut budget = InputBudget::new(limits);
+    let mut input_exhausted = false;
+    let mut finished_count = 0;
+    let mut finished = Vec::new();
+    let mut left_blocked = 0;
 
     debug!(
         "Dynamic scheduler running on thread: {}",
@@ -61,6 +78,7 @@ where
                 // Set process state to Running and notify.
                 let mut st = proc.data.state.lock().unwrap();
                 *st = ProcessState::Running;
+                proc.data.turns.fetch_add(1, Ordering::SeqCst);
                 proc.data.cond.notify_all();
                 info!(
                     "Process {} set to Running on thread: {}",
@@ -70,27 +88,14 @@ where
             }
 
             // Wait until the process is no longer Running.
-            {
-                let mut st = proc.data.state.lock().unwrap();
-                while *st == ProcessState::Running {
-                    debug!(
-                        "Dynamic scheduler waiting for process {} (state: {:?})",
-                        proc.id, *st
-                    );
-                    st = proc.data.cond.wait(st).unwrap();
-                }
-            }
+            wait_while_running(&proc);
+            let fuel = quantum::count_turn(&proc.data);
+            debug!("Process {} used {} fuel in its turn ({} in all)", proc.id, fuel, *proc.data.fuel_used.lock().unwrap());
 
             // Check new state and decide where to enqueue.
             let current_state = { *proc.data.state.lock().unwrap() };
             match current_state {
-                ProcessState::Finished => {
-                    let _ = proc.thread.join();
-                    if let Err(e) = fs::remove_dir_all(&proc.data.root_path) {
-                        error!("Failed to remove dir for process {}: {}", proc.id, e);
-                    }
-                    info!("Process {} finished and joined.", proc.id);
-                }
+                ProcessState::Finished => finished.push(proc),
                 ProcessState::Ready => {
                     info!("Process {} yielded; moving it to Ready queue.", proc.id);
                     ready_queue.push_back(proc);
@@ -103,16 +108,32 @@ where
                     error!("Process {} still Running unexpectedly.", proc.id);
                 }
             }
+            breakpoint::check(ready_queue.iter().chain(&blocked_queue).chain(&finished), budget.batches);
         }
+        finished_count += reap_finished(&mut finished);
+        // Before waiting on consensus input, so the endpoint shows this round
+        metrics::publish(&ready_queue, &blocked_queue, finished_count, &budget);
 
         // When no process is ready, try to update states via the consensus input.
         if ready_queue.is_empty() {
             if blocked_queue.is_empty() {
+                if input_exhausted {
+                    info!("Input limit reached and all processes finished. Exiting scheduler.");
+                    break;
+                }
                 debug!("No processes in queue; waiting for consensus input.");
                 let mut new_processes = Vec::new();
                 batch_collector.collect_network_messages(&new_processes);
-                has_more_input = consensus_input(&mut new_processes, batch_collector.outgoing_messages.drain(..).collect())?;
-                ready_queue.extend(new_processes);
+                has_more_input = consensus_input(&mut new_processes, batch_collector.outgoing_messages.drain(..).collect(), &mut budget)?;
+                input_exhausted = budget.exhausted();
+                kill_past_deadline(&new_processes);
+                // A Shutdown later in the same batch, or a deadline of 0, can end a process before its first turn
+                let (shut_down, started): (Vec<_>, Vec<_>) = new_processes.into_iter()
+                    .partition(|proc| *proc.data.state.lock().unwrap() == ProcessState::Finished);
+                ready_queue.extend(started);
+                finished.extend(shut_down);
+                breakpoint::check(ready_queue.iter().chain(&blocked_queue).chain(&finished), budget.batches);
+                finished_count += reap_finished(&mut finished);
 
                 if ready_queue.is_empty() && !has_more_input {
                     info!("All processes finished and no more consensus input. Exiting scheduler.");
@@ -126,9 +147,13 @@ where
             } else {
                 // Combine blocked processes and update their states.
                 let mut all_processes: Vec<Process> = blocked_queue.drain(..).collect();
-                batch_collector.collect_network_messages(&all_processes);
-                has_more_input = consensus_input(&mut all_processes, batch_collector.outgoing_messages.drain(..).collect())?;
-                info!("All processes blocked; consensus input updated process states.");
+                if !input_exhausted {
+                    batch_collector.collect_network_messages(&all_processes);
+                    has_more_input = consensus_input(&mut all_processes, batch_collector.outgoing_messages.drain(..).collect(), &mut budget)?;
+                    input_exhausted = budget.exhausted();
+                    info!("All processes blocked; consensus input updated process states.");
+                }
+                kill_past_deadline(&all_processes);
 
                 // Re-split processes based on new state.
                 for proc in all_processes.into_iter() {
@@ -136,20 +161,14 @@ where
                     match state {
                         ProcessState::Ready => ready_queue.push_back(proc),
                         ProcessState::Blocked => blocked_queue.push_back(proc),
-                        ProcessState::Finished => {
-                            if let Err(e) = fs::remove_dir_all(&proc.data.root_path) {
-                                if e.kind() != std::io::ErrorKind::NotFound {
-                                    error!("Failed to remove dir for process {}: {}", proc.id, e);
-                                }
-                            }
-                            let _ = proc.thread.join();
-                            info!("Process {} finished and joined.", proc.id);
-                        }
+                        ProcessState::Finished => finished.push(proc),
                         ProcessState::Running => {
                             error!("Process {} still Running unexpectedly after consensus input.", proc.id);
                         }
                     }
                 }
+                breakpoint::check(ready_queue.iter().chain(&blocked_queue).chain(&finished), budget.batches);
+                finished_count += reap_finished(&mut finished);
 
                 // Try to unblock processes based on their block reasons.
                 let mut still_blocked = VecDeque::new();
@@ -164,8 +183,8 @@ where
                                 };
                                 fd_has_input
                             }
-                            Some(BlockReason::WriteIO(ref path)) => {
-                                match flush_write_buffer_for_scheduler(&proc.data, path) {
+                            Some(BlockReason::WriteIO(ref path, at)) => {
+                                match flush_write_buffer_for_scheduler(&proc.data, path, at) {
                                     Ok(_bytes) => true,  // Flushed successfully: unblock the process.
                                     Err(_errno) => false // If flush fails, keep the process blocked.
                                 }
@@ -177,6 +196,12 @@ where
                                 true
                             }
                             Some(BlockReason::Timeout { resume_after }) => GlobalClock::now() >= resume_after,
+                            // Only a Resume record takes a process out of Paused
+                            Some(BlockReason::Paused { .. }) => false,
+                            // A CancelNetwork record releases the process with an error
+                            Some(BlockReason::NetworkIO) if *proc.data.network_cancelled.lock().unwrap() => true,
+                            // A connect or a datagram bind/send stays blocked until its status arrives
+                            Some(BlockReason::NetworkIO) if *proc.data.network_pending.lock().unwrap() => false,
                             Some(BlockReason::NetworkIO) => {
                                 let nat_table = proc.data.nat_table.lock().unwrap();
                                 let fd_table = proc.data.fd_table.lock().unwrap();
@@ -194,7 +219,9 @@ where
                                 }
                                 !should_block
                             },
-                            _ => false,
+                            // No catch-all: a new BlockReason must get a case here, or
+                            // processes blocked on it would never be woken
+                            None => false,
                         }
                     };
 
@@ -216,11 +243,23 @@ where
                 }
                 blocked_queue = still_blocked;
 
+                if ready_queue.is_empty() && input_exhausted {
+                    info!("Input limit reached and no process can run. Exiting scheduler.");
+                    left_blocked = stop_blocked_processes(blocked_queue.drain(..));
+                    break;
+                }
+
                 if ready_queue.is_empty() && blocked_queue.is_empty() && !has_more_input {
                     info!("All processes finished and no more consensus input. Exiting scheduler.");
                     break;
                 }
 
+                if ready_queue.is_empty() && !has_more_input && blocked_queue.iter().all(needs_input) {
+                    info!("No more consensus input and no blocked process can run without it. Exiting scheduler.");
+                    left_blocked = stop_blocked_processes(blocked_queue.drain(..));
+                    break;
+                }
+
                 if ready_queue.is_empty() {
                     debug!("No processes unblocked; scheduler sleeping briefly.");
                     //thread::sleep(Duration::from_millis(10));
@@ -229,24 +268,262 @@ where
         }
     }
 
+    metrics::publish(&ready_queue, &blocked_queue, finished_count, &budget);
     info!("Scheduler exiting: no more processes to run and no more input.");
+    if budget.is_bounded() {
+        println!(
+            "Stopped after {} batches and {} records: {} processes finished, {} left blocked.",
+            budget.batches, budget.records, finished_count, left_blocked
+        );
+    }
     Ok(())
 }
 
+/// Ends the processes whose Init `deadline:` the logical clock has reached,
+/// which the re-split then moves to the finished list like any other. The
+/// clock only advances when consensus input is applied, so this runs right
+/// after, and every replica ends them after the same batch. A guest that
+/// never yields or blocks is not stopped by its deadline, since the scheduler
+/// reads no input while it runs: that is the watchdog's job.
+fn kill_past_deadline(processes: &[Process]) {
+    let now = GlobalClock::now();
+    for proc in processes {
+        match proc.data.deadline {
+            Some(deadline) if now >= deadline && process::kill_at_deadline(&proc.data) => {
+                info!("Process {} reached its deadline of {} ns at logical time {} ns; killing it.", proc.id, deadline, now);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Joins the processes that finished in this scheduler iteration and removes
+/// their sandboxes, in pid order rather than in the order they finished, which
+/// depends on how the queues happened to be drained, and tells consensus how
+/// each one ended with a ProcessExit record. Returns how many there were.
+fn reap_finished(finished: &mut Vec<Process>) -> u64 {
+    finished.sort_by_key(|proc| proc.id);
+    let count = finished.len() as u64;
+    for proc in finished.drain(..) {
+        let _ = proc.thread.join();
+        // A process that returned from _start made no syscall since its last write
+        let _ = flush_pending_write(&proc.data);
+        sandbox_digest::record(proc.id, &proc.data.root_path);
+        if let Err(e) = fs::remove_dir_all(&proc.data.root_path) {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                error!("Failed to remove dir for process {}: {}", proc.id, e);
+            }
+        }
+        let termination = *proc.data.termination.lock().unwrap();
+        match termination {
+            Some(reason) => info!("Process {} finished ({}) and joined.", proc.id, reason),
+            None => info!("Process {} finished and joined.", proc.id),
+        }
+        info!("Process {} used {} fuel", proc.id, *proc.data.fuel_used.lock().unwrap());
+        queue_process_exit(proc.id, *proc.data.exit_code.lock().unwrap(), termination, proc.data.backtrace.lock().unwrap().take());
+    }
+    count
+}
+
+/// Waits for a process the scheduler has set Running to yield, block or
+/// finish. With `--watchdog=<ms>`, a process that runs longer than that is
+/// flagged, and with `--watchdog-kill` also interrupted, which ends it with
+/// `TerminationReason::Watchdog`.
+fn wait_while_running(proc: &Process) {
+    let running_since = Instant::now();
+    let mut flagged = false;
+    let mut st = proc.data.state.lock().unwrap();
+    while *st == ProcessState::Running {
+        debug!(
+            "Dynamic scheduler waiting for process {} (state: {:?})",
+            proc.id, *st
+        );
+        let timeout = match watchdog::timeout() {
+            Some(timeout) if !flagged => timeout,
+            _ => {
+                st = proc.data.cond.wait(st).unwrap();
+                continue;
+            }
+        };
+        let remaining = timeout.saturating_sub(running_since.elapsed());
+        st = proc.data.cond.wait_timeout(st, remaining).unwrap().0;
+        if *st == ProcessState::Running && running_since.elapsed() >= timeout {
+            flagged = true;
+            error!(
+                "Watchdog: process {} has been running for {:?} without yielding or blocking",
+                proc.id, running_since.elapsed()
+            );
+            if watchdog::kills() {
+                error!("Watchdog: killing process {}", proc.id);
+                proc.engine.increment_epoch();
+            }
+        }
+    }
+}
+
+/// Releases what processes that are still blocked when the scheduler stops
+/// hold on the host: buffered writes are flushed and sandboxes removed. Their
+/// threads stay parked on the state condvar, so they are not joined.
+fn stop_blocked_processes(processes: impl Iterator<Item = Process>) -> usize {
+    let mut count = 0;
+    for proc in processes {
+        let reason = proc.data.block_reason.lock().unwrap().clone();
+        if let Some(BlockReason::WriteIO(ref path, at)) = reason {
+            if let Err(errno) = flush_write_buffer_for_scheduler(&proc.data, path, at) {
+                error!("Failed to flush writes of process {} (errno {})", proc.id, errno);
+            }
+        }
+        let _ = flush_pending_write(&proc.data);
+        sandbox_digest::record(proc.id, &proc.data.root_path);
+        if let Err(e) = fs::remove_dir_all(&proc.data.root_path) {
+            error!("Failed to remove dir for process {}: {}", proc.id, e);
+        }
+        match reason {
+            Some(reason) => info!("Process {} left blocked ({}).", proc.id, reason),
+            None => info!("Process {} left blocked.", proc.id),
+        }
+        count += 1;
+    }
+    count
+}
+
 
-pub fn run_scheduler_with_file(processes: Vec<Process>, consensus_file: &str) -> Result<()> {
-    run_scheduler_dynamic(processes, |processes, _| {
+pub fn run_scheduler_with_file(processes: Vec<Process>, consensus_file: &str, limits: InputLimits) -> Result<()> {
+    run_scheduler_dynamic(processes, limits, |processes, _, budget| {
         // Use the existing process_consensus_file function.
-        process_consensus_file(consensus_file, processes)
+        process_consensus_file(consensus_file, false, processes, budget)
     })
 }
 
-// // /// Wrapper for interactive mode using a live consensus pipe/socket.
-pub fn run_scheduler_interactive<R: Read + Write>(processes: Vec<Process>, consensus_pipe: &mut R) -> Result<()> {
+/// Longest a follower sleeps before re-reading the file without a notification.
+const FOLLOW_WAIT: Duration = Duration::from_secs(1);
+
+/// Like `run_scheduler_with_file`, but keeps following the file as records are
+/// appended to it instead of stopping at its end, like `tail -f`. A file that
+/// gets shorter was rewritten and is read again from the start.
+pub fn run_scheduler_following_file(processes: Vec<Process>, consensus_file: &str, limits: InputLimits) -> Result<()> {
+    let path = Path::new(consensus_file);
+    let follower = FileFollower::new(path);
+    let mut created = false;
+    run_scheduler_dynamic(processes, limits, |processes, _, budget| {
+        // The file may not have been created yet. Once it has, deleting it
+        // is an error
+        created = created || path.exists();
+        let processed = created && process_consensus_file(consensus_file, true, processes, budget)?;
+        if !processed && processes.iter().all(needs_input) {
+            follower.wait_for_change(FOLLOW_WAIT);
+        }
+        Ok(true) // Keep following until the runtime is stopped
+    })
+}
+
+/// Whether a blocked process can only make progress once new records arrive.
+/// Processes blocked on file I/O are released by the scheduler itself, so the
+/// follower must not sleep while any of them is waiting, and the scheduler
+/// must not give up on them when input ends.
+fn needs_input(proc: &Process) -> bool {
+    !matches!(
+        *proc.data.block_reason.lock().unwrap(),
+        Some(BlockReason::FileIO) | Some(BlockReason::WriteIO(..))
+    )
+}
+
+/// Wrapper for interactive mode using a live consensus pipe/socket.
+///
+/// Runs until consensus closes the connection between batches, then stops
+/// like at the end of a consensus file. Losing the connection any other way
+/// is an error.
+pub fn run_scheduler_interactive<R: Read + Write>(
+    processes: Vec<Process>,
+    consensus_pipe: &mut R,
+    limits: InputLimits,
+) -> Result<()> {
     let mut reader = BufReader::new(consensus_pipe);
-    run_scheduler_dynamic(processes, |processes, outgoing_messages| {
-        // Process pipe should keep running indefinitely
-        process_consensus_pipe(&mut reader, processes, outgoing_messages)?;
-        Ok(true) // Always return true for pipe mode to keep scheduler running
+    let mut closed = false;
+    run_scheduler_dynamic(processes, limits, |processes, outgoing_messages, budget| {
+        if closed {
+            return Ok(false);
+        }
+        match process_consensus_pipe(&mut reader, processes, outgoing_messages, budget)? {
+            PipeStatus::Batch => Ok(true),
+            PipeStatus::Closed => {
+                closed = true;
+                Ok(false)
+            }
+            PipeStatus::Lost(e) => Err(anyhow::Error::new(e).context("Consensus connection lost")),
+        }
     })
-}
\ No newline at end of file
+}
+/// Interactive mode over TCP: connects to consensus at `addr` and, unlike
+/// `run_scheduler_interactive`, does not give up when the connection fails,
+/// goes idle or closes in the middle of a batch. It connects again and reads
+/// on from the batch after the last one it acknowledged, skipping any it
+/// already read, and first sends any outgoing batch it could not send before.
+/// A close between batches still ends the session, and so does failing to
+/// connect within the connect timeout.
+pub fn run_scheduler_connected(processes: Vec<Process>, addr: SocketAddr, limits: InputLimits) -> Result<()> {
+    let open = || -> Result<BufReader<TcpStream>> {
+        let mut stream = consensus_tcp::connect(addr)
+            .map_err(|e| anyhow::Error::new(e).context("Cannot connect to consensus"))?;
+        // A failed hello shows up as a lost connection on the first read
+        if let Err(e) = send_hello(&mut stream) {
+            warn!("Failed to greet consensus: {}", e);
+        }
+        Ok(BufReader::new(stream))
+    };
+    let mut reader = open()?;
+    let mut closed = false;
+    run_scheduler_dynamic(processes, limits, |processes, outgoing_messages, budget| {
+        if closed {
+            return Ok(false);
+        }
+        match process_consensus_pipe(&mut reader, processes, outgoing_messages, budget)? {
+            PipeStatus::Batch => Ok(true),
+            PipeStatus::Closed => {
+                closed = true;
+                Ok(false)
+            }
+            PipeStatus::Lost(e) => {
+                warn!("Consensus connection lost ({}); reconnecting", e);
+                reader = open()?;
+                Ok(true)
+            }
+        }
+    })
+}
+
+/// Re-runs a session recorded by consensus (`sessions/session-*.bin`): its
+/// incoming batches are sent through `process_consensus_pipe` as if from a
+/// live connection that closes after the last one, and the outgoing batches
+/// the runtime produces are dropped.
+pub fn run_scheduler_replaying(processes: Vec<Process>, session_file: &Path, limits: InputLimits) -> Result<()> {
+    let batches: Vec<_> = BatchHistory::load(session_file)?.into_iter()
+        .filter(|batch| batch.direction == BatchDirection::Incoming)
+        .collect();
+    let mut input = Vec::new();
+    for batch in &batches {
+        input.extend(encode_wire(batch.number, 0, batch.clock_before().unwrap_or(UNKNOWN_CLOCK), &batch.data, WIRE_VERSION, false));
+    }
+    info!("Replaying {} incoming batches from {}", batches.len(), session_file.display());
+    run_scheduler_interactive(processes, &mut ReplayPipe(io::Cursor::new(input)), limits)
+}
+
+/// The consensus side of a replay: reads the recorded batches, and discards
+/// whatever the runtime sends back.
+struct ReplayPipe(io::Cursor<Vec<u8>>);
+
+impl Read for ReplayPipe {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf)
+    }
+}
+
+impl Write for ReplayPipe {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,53 @@
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use anyhow::{anyhow, Context, Result};
+use log::info;
+use consensus::redact;
+
+// Set by --secrets; no secrets without it
+static SECRETS_FILE: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+/// Applies `--secrets=<file>`. Returns true if `arg` was it, so callers can
+/// strip it before positional argument handling.
+pub fn apply_flag(arg: &str) -> bool {
+    let Some(path) = arg.strip_prefix("--secrets=") else {
+        return false;
+    };
+    *SECRETS_FILE.lock().unwrap() = Some(PathBuf::from(path));
+    true
+}
+
+/// Reads the `--secrets` file, if there is one, into `KEY=VALUE` strings for
+/// the environment of process `id`. Blank lines and lines starting with `#`
+/// are skipped. The file is read again for every process, so a changed file
+/// applies to processes started afterwards.
+///
+/// Secrets reach the process without going through consensus, so they are
+/// never in a batch or a session file; every replica must be provisioned
+/// with the same file, or their processes will diverge. Their values are
+/// registered for redaction, so they never appear in logs either.
+pub fn load(id: u64) -> Result<Vec<String>> {
+    let Some(path) = SECRETS_FILE.lock().unwrap().clone() else {
+        return Ok(Vec::new());
+    };
+    let text = fs::read_to_string(&path)
+        .with_context(|| format!("cannot read secrets file {}", path.display()))?;
+    let mut env = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (key, value) = line.split_once('=')
+            .filter(|(key, _)| !key.is_empty())
+            .ok_or_else(|| anyhow!("line {} of secrets file {} is not KEY=VALUE", number + 1, path.display()))?;
+        redact::add_secret(value.as_bytes());
+        env.push(format!("{}={}", key, value));
+    }
+    if !env.is_empty() {
+        let keys: Vec<&str> = env.iter().filter_map(|var| var.split('=').next()).collect();
+        info!("Process {} gets secrets {}", id, keys.join(", "));
+    }
+    Ok(env)
+}
@@ -0,0 +1,221 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use log::debug;
+
+use crate::wasi_syscalls::fs::symlink_key as fold;
+
+/// The files of a process started with the Init `tmpfs:` option. They live in
+/// memory instead of its sandbox directory, which stays empty, and go away
+/// with the process.
+///
+/// Paths are host paths below the sandbox root, as the syscalls resolve them.
+/// With nothing on disk to canonicalize, `.` and `..` are folded lexically,
+/// and a path that leaves the root fails with `PermissionDenied`.
+#[derive(Debug)]
+pub struct Tmpfs {
+    root: PathBuf,
+    files: BTreeMap<PathBuf, Vec<u8>>,
+    dirs: BTreeSet<PathBuf>, // the root included
+}
+
+/// What `Tmpfs::stat` finds at a path.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TmpfsEntry {
+    File { len: u64 },
+    Directory,
+}
+
+impl Tmpfs {
+    pub fn new(root: &Path) -> Self {
+        let root = fold(root);
+        Tmpfs {
+            dirs: BTreeSet::from([root.clone()]),
+            root,
+            files: BTreeMap::new(),
+        }
+    }
+
+    /// `path` with `.` and `..` folded away, as files are keyed. Fails if it
+    /// leaves the root.
+    pub fn resolve(&self, path: &Path) -> io::Result<PathBuf> {
+        let path = fold(path);
+        if !path.starts_with(&self.root) {
+            return Err(io::Error::new(io::ErrorKind::PermissionDenied, "outside the sandbox"));
+        }
+        Ok(path)
+    }
+
+    pub fn stat(&self, path: &Path) -> io::Result<TmpfsEntry> {
+        let path = self.resolve(path)?;
+        if let Some(data) = self.files.get(&path) {
+            Ok(TmpfsEntry::File { len: data.len() as u64 })
+        } else if self.dirs.contains(&path) {
+            Ok(TmpfsEntry::Directory)
+        } else {
+            Err(io::ErrorKind::NotFound.into())
+        }
+    }
+
+    pub fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        let path = self.resolve(path)?;
+        self.files.get(&path).cloned().ok_or_else(|| self.missing_file(&path))
+    }
+
+    /// Creates an empty file at `path` unless there is one. Its directory
+    /// must exist. Returns whether it created one.
+    pub fn create(&mut self, path: &Path) -> io::Result<bool> {
+        let path = self.resolve(path)?;
+        if self.files.contains_key(&path) {
+            return Ok(false);
+        }
+        if self.dirs.contains(&path) {
+            return Err(io::ErrorKind::IsADirectory.into());
+        }
+        self.check_parent(&path)?;
+        self.files.insert(path, Vec::new());
+        Ok(true)
+    }
+
+    /// Writes `data` into the file at `path`, at offset `at` (which may be
+    /// past the end, filling the gap with zeros) or at the end if it is None.
+    pub fn write_at(&mut self, path: &Path, at: Option<u64>, data: &[u8]) -> io::Result<()> {
+        let path = self.resolve(path)?;
+        let error = self.missing_file(&path);
+        let file = self.files.get_mut(&path).ok_or(error)?;
+        let start = at.map_or(file.len(), |at| at as usize);
+        let end = start + data.len();
+        if file.len() < end {
+            file.resize(end, 0);
+        }
+        file[start..end].copy_from_slice(data);
+        Ok(())
+    }
+
+    /// Truncates or extends the file at `path` to `len` bytes.
+    pub fn set_len(&mut self, path: &Path, len: u64) -> io::Result<()> {
+        let path = self.resolve(path)?;
+        let error = self.missing_file(&path);
+        self.files.get_mut(&path).ok_or(error)?.resize(len as usize, 0);
+        Ok(())
+    }
+
+    pub fn create_dir(&mut self, path: &Path) -> io::Result<()> {
+        let path = self.resolve(path)?;
+        if self.files.contains_key(&path) || self.dirs.contains(&path) {
+            return Err(io::ErrorKind::AlreadyExists.into());
+        }
+        self.check_parent(&path)?;
+        self.dirs.insert(path);
+        Ok(())
+    }
+
+    /// Creates the directory at `path` and any missing ones above it.
+    pub fn create_dir_all(&mut self, path: &Path) -> io::Result<()> {
+        let path = self.resolve(path)?;
+        for dir in path.ancestors().take_while(|dir| dir.starts_with(&self.root)) {
+            if self.files.contains_key(dir) {
+                return Err(io::ErrorKind::NotADirectory.into());
+            }
+        }
+        let missing: Vec<PathBuf> = path.ancestors().take_while(|dir| !self.dirs.contains(*dir)).map(Path::to_path_buf).collect();
+        self.dirs.extend(missing);
+        Ok(())
+    }
+
+    /// Removes the file at `path`. Returns its size.
+    pub fn remove_file(&mut self, path: &Path) -> io::Result<u64> {
+        let path = self.resolve(path)?;
+        let error = self.missing_file(&path);
+        self.files.remove(&path).map(|data| data.len() as u64).ok_or(error)
+    }
+
+    /// Removes the empty directory at `path`. The root cannot be removed.
+    pub fn remove_dir(&mut self, path: &Path) -> io::Result<()> {
+        let path = self.resolve(path)?;
+        if path == self.root {
+            return Err(io::ErrorKind::PermissionDenied.into());
+        }
+        if !self.dirs.contains(&path) {
+            return Err(if self.files.contains_key(&path) { io::ErrorKind::NotADirectory } else { io::ErrorKind::NotFound }.into());
+        }
+        if !self.children(&path).is_empty() {
+            return Err(io::ErrorKind::DirectoryNotEmpty.into());
+        }
+        self.dirs.remove(&path);
+        Ok(())
+    }
+
+    /// Moves the file at `from` to `to`, replacing any file there. Returns the
+    /// size of the file it replaced, or 0.
+    pub fn rename(&mut self, from: &Path, to: &Path) -> io::Result<u64> {
+        let (from, to) = (self.resolve(from)?, self.resolve(to)?);
+        if self.dirs.contains(&to) {
+            return Err(io::ErrorKind::IsADirectory.into());
+        }
+        self.check_parent(&to)?;
+        let error = self.missing_file(&from);
+        let data = self.files.remove(&from).ok_or(error)?;
+        Ok(self.files.insert(to, data).map_or(0, |old| old.len() as u64))
+    }
+
+    /// The names in the directory at `path`, sorted, as `fd_readdir` lists them.
+    pub fn list(&self, path: &Path) -> io::Result<Vec<String>> {
+        let path = self.resolve(path)?;
+        if !self.dirs.contains(&path) {
+            return Err(if self.files.contains_key(&path) { io::ErrorKind::NotADirectory } else { io::ErrorKind::NotFound }.into());
+        }
+        Ok(self.children(&path))
+    }
+
+    /// Copies the files and directories below `src`, on the host, into the
+    /// root, as `init -d` preloads a sandbox. Returns the bytes copied.
+    pub fn load(&mut self, src: &Path) -> io::Result<u64> {
+        let root = self.root.clone();
+        self.load_into(src, &root)
+    }
+
+    fn load_into(&mut self, src: &Path, dst: &Path) -> io::Result<u64> {
+        let mut bytes = 0;
+        for entry in fs::read_dir(src)? {
+            let entry = entry?;
+            let path = dst.join(entry.file_name());
+            if entry.file_type()?.is_dir() {
+                self.dirs.insert(path.clone());
+                bytes += self.load_into(&entry.path(), &path)?;
+            } else {
+                let data = fs::read(entry.path())?;
+                bytes += data.len() as u64;
+                debug!("tmpfs: loaded {:?} ({} bytes)", path, data.len());
+                self.files.insert(path, data);
+            }
+        }
+        Ok(bytes)
+    }
+
+    /// Names of the files and directories directly in the directory `dir`.
+    fn children(&self, dir: &Path) -> Vec<String> {
+        let in_dir = |path: &&PathBuf| path.parent() == Some(dir);
+        let name = |path: &PathBuf| path.file_name().map(|name| name.to_string_lossy().into_owned());
+        let mut names: Vec<String> = self.files.keys().filter(in_dir).filter_map(name)
+            .chain(self.dirs.iter().filter(in_dir).filter_map(name))
+            .collect();
+        names.sort();
+        names
+    }
+
+    fn check_parent(&self, path: &Path) -> io::Result<()> {
+        match path.parent() {
+            Some(parent) if self.dirs.contains(parent) => Ok(()),
+            Some(parent) if self.files.contains_key(parent) => Err(io::ErrorKind::NotADirectory.into()),
+            _ => Err(io::ErrorKind::NotFound.into()),
+        }
+    }
+
+    /// The error for a file operation on `path`, which is not a file.
+    fn missing_file(&self, path: &Path) -> io::Error {
+        if self.dirs.contains(path) { io::ErrorKind::IsADirectory } else { io::ErrorKind::NotFound }.into()
+    }
+}
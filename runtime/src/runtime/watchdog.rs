@@ -0,0 +1,48 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::Duration;
+use wasmtime::Trap;
+
+// 0 means the watchdog is off
+static TIMEOUT_MS: AtomicU64 = AtomicU64::new(0);
+static KILL: AtomicBool = AtomicBool::new(false);
+
+/// Applies a watchdog command-line flag. Returns true if `arg` was one, so
+/// callers can strip it before positional argument handling.
+///
+/// - `--watchdog=<ms>`: flag a process that runs this long without yielding or blocking
+/// - `--watchdog-kill`: also kill it
+pub fn apply_flag(arg: &str) -> bool {
+    if arg == "--watchdog-kill" {
+        KILL.store(true, Ordering::SeqCst);
+    } else if let Some(ms) = arg.strip_prefix("--watchdog=") {
+        match ms.parse() {
+            Ok(ms) => TIMEOUT_MS.store(ms, Ordering::SeqCst),
+            Err(_) => log::warn!("Ignoring invalid watchdog timeout: {}", ms),
+        }
+    } else {
+        return false;
+    }
+    true
+}
+
+/// How long a process may stay Running before the scheduler flags it.
+///
+/// This is wall-clock time: logical time only advances between consensus
+/// batches, and no batch is applied while a guest is running.
+pub fn timeout() -> Option<Duration> {
+    match TIMEOUT_MS.load(Ordering::SeqCst) {
+        0 => None,
+        ms => Some(Duration::from_millis(ms)),
+    }
+}
+
+/// Whether flagged processes are killed. Kills need epoch interruption, so
+/// this has to be decided before processes are started.
+pub fn kills() -> bool {
+    KILL.load(Ordering::SeqCst) && timeout().is_some()
+}
+
+/// Whether `_start` failed because the watchdog interrupted the guest.
+pub fn killed_by_watchdog(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| cause.downcast_ref::<Trap>() == Some(&Trap::Interrupt))
+}
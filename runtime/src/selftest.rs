@@ -0,0 +1,183 @@
+use anyhow::{bail, Result};
+use log::{error, info};
+use crate::consensus_input::InputLimits;
+use crate::runtime::{
+    clock::GlobalClock,
+    process::{start_process_from_bytes, TerminationReason},
+    quantum,
+    scheduler::run_scheduler_dynamic,
+};
+use crate::wasi_syscalls::builtin_report::take_pending_reports;
+
+/// The conformance guest; wasmtime compiles the text format directly.
+const CONFORMANCE_MODULE: &[u8] = include_bytes!("../../wasm_programs/conformance.wat");
+const SELFTEST_PID: u64 = 1;
+/// Simulated time that passes each time the guest is blocked, so clock waits resolve.
+const CLOCK_STEP_NANOS: u64 = 10_000_000;
+/// Consensus rounds after which a guest that is still blocked is considered hung.
+const MAX_ROUNDS: usize = 1000;
+
+/// Checks in the order the guest reports them: (syscall, what is asserted).
+const SELFTEST_CHECKS: &[(&str, &str)] = &[
+    ("fd_prestat_get", "fd 3 is a preopened directory"),
+    ("fd_prestat_dir_name", "the preopen is named \".\""),
+    ("fd_fdstat_get", "fd 3 has filetype directory"),
+    ("args_sizes_get", "argc is 0"),
+    ("fd_write", "stdout accepts a full buffer"),
+    ("path_open", "O_CREAT creates a file"),
+    ("fd_write (file)", "all 11 bytes are written"),
+    ("fd_close", "a closed fd rejects writes"),
+    ("path_open (read)", "an existing file reopens"),
+    ("fd_read", "reads back \"hello\""),
+    ("fd_seek", "SEEK_CUR, SEEK_SET past the end, SEEK_END"),
+    ("fd_tell", "matches the last seek"),
+    ("fd_filestat_get", "filetype and st_size"),
+    ("path_filestat_get", "filetype and st_size"),
+    ("path_create_directory", "creates, then EEXIST"),
+    ("fd_readdir", "dirent header and name"),
+    ("path_unlink_file", "removes, then ENOENT"),
+    ("path_remove_directory", "the directory is gone"),
+    ("path_rename", "new name exists, old name does not"),
+    ("clock_res_get", "non-zero resolution"),
+    ("clock_time_get", "monotonic"),
+    ("poll_oneoff", "clock subscription fires; nevents is a u32"),
+    ("random_get", "fills the buffer"),
+    ("sock_open", "AF_INET stream socket"),
+    ("sock_bind", "binds 127.0.0.1:8080"),
+    ("sock_getsockname", "reports the bound address"),
+    ("sock_getpeername", "ENOTCONN before connect"),
+    ("sock_close", "closes the socket"),
+    ("fd_close (bad fd)", "EBADF"),
+    ("sock_open (reuse)", "reuses the lowest free fd"),
+];
+
+/// Checks the runtime is known to fail today. They are reported as XFAIL and
+/// do not fail the run; once one passes (XPASS) it should be removed here.
+const KNOWN_FAILURES: &[(&str, &str)] = &[
+    ("fd_readdir", "returns newline-separated names instead of dirent records"),
+    ("poll_oneoff", "writes nevents as a u64"),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Outcome {
+    NotRun,
+    Pass,
+    Fail,
+}
+
+impl Outcome {
+    fn from_report(byte: Option<&u8>) -> Self {
+        match byte {
+            Some(1) => Outcome::Pass,
+            Some(2) => Outcome::Fail,
+            _ => Outcome::NotRun,
+        }
+    }
+}
+
+/// What a guest left behind after `run_guest`.
+pub struct GuestRun {
+    pub report: Vec<u8>, // the last blob it passed to __builtin_rt_report
+    pub termination: Option<TerminationReason>,
+    pub backtrace: Option<String>,
+    pub scheduled: Result<()>, // Err if the guest hung or the scheduler failed
+}
+
+/// Runs one guest module under the normal scheduler with no consensus input,
+/// advancing the clock while it is blocked, until it finishes or hangs.
+pub fn run_guest(module: Vec<u8>, pid: u64) -> Result<GuestRun> {
+    let process = start_process_from_bytes(module, pid, quantum::fuel())?;
+    let termination = process.data.termination.clone();
+    let backtrace = process.data.backtrace.clone();
+
+    // The guest may report after every check; keep the latest
+    let mut report = Vec::new();
+    let mut rounds = 0;
+    let scheduled = run_scheduler_dynamic(vec![process], InputLimits::default(), |processes, _outgoing, _budget| {
+        for (reporter, blob) in take_pending_reports() {
+            if reporter == pid {
+                report = blob;
+            }
+        }
+        if processes.is_empty() {
+            return Ok(false); // the guest has finished
+        }
+        rounds += 1;
+        if rounds > MAX_ROUNDS {
+            bail!("guest still blocked after {} rounds", MAX_ROUNDS);
+        }
+        GlobalClock::increment(CLOCK_STEP_NANOS);
+        Ok(true)
+    });
+    for (reporter, blob) in take_pending_reports() {
+        if reporter == pid {
+            report = blob;
+        }
+    }
+    let termination = *termination.lock().unwrap();
+    let backtrace = backtrace.lock().unwrap().clone();
+    Ok(GuestRun { report, termination, backtrace, scheduled })
+}
+
+/// Runs the conformance guest under the normal scheduler and prints a
+/// pass/fail matrix. Returns whether every check either passed or is a
+/// known failure, and the guest exited normally.
+pub fn run() -> Result<bool> {
+    let GuestRun { report, termination, backtrace, scheduled } = run_guest(CONFORMANCE_MODULE.to_vec(), SELFTEST_PID)?;
+    if let Err(e) = &scheduled {
+        error!("Selftest: {}", e);
+    }
+
+    let mut passed = 0;
+    let mut expected_failures = 0;
+    let mut failed = 0;
+    let mut not_run = 0;
+    println!();
+    println!("{:<4} {:<24} {:<8} check", "#", "syscall", "result");
+    for (index, (name, check)) in SELFTEST_CHECKS.iter().enumerate() {
+        let known = KNOWN_FAILURES.iter().find(|(known, _)| known == name).map(|(_, reason)| *reason);
+        let (label, note) = match (Outcome::from_report(report.get(index)), known) {
+            (Outcome::Pass, None) => {
+                passed += 1;
+                ("PASS", String::new())
+            }
+            (Outcome::Pass, Some(_)) => {
+                passed += 1;
+                ("XPASS", " (no longer a known failure)".to_string())
+            }
+            (Outcome::Fail, Some(reason)) => {
+                expected_failures += 1;
+                ("XFAIL", format!(" ({})", reason))
+            }
+            (Outcome::Fail, None) => {
+                failed += 1;
+                ("FAIL", String::new())
+            }
+            (Outcome::NotRun, _) => {
+                not_run += 1;
+                ("NOT RUN", String::new())
+            }
+        };
+        println!("{:<4} {:<24} {:<8} {}{}", index, name, label, check, note);
+    }
+    println!();
+    println!(
+        "{} passed, {} known failures, {} failed, {} not run",
+        passed, expected_failures, failed, not_run
+    );
+    match termination {
+        Some(reason) => println!("Conformance guest {}.", reason),
+        None => println!("Conformance guest did not finish."),
+    }
+    // Shows which check the guest was in when it trapped
+    if let Some(backtrace) = &backtrace {
+        println!("{}", backtrace);
+    }
+
+    let ok = scheduled.is_ok()
+        && failed == 0
+        && not_run == 0
+        && termination == Some(TerminationReason::Exited);
+    info!("Selftest {}", if ok { "passed" } else { "failed" });
+    Ok(ok)
+}
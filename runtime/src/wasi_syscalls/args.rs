@@ -1,68 +1,93 @@
 use anyhow::Result;
 use wasmtime::Caller;
 use crate::runtime::process::ProcessData;
+use crate::wasi_syscalls::memory::{guest_memory, write_slice, WASI_EFAULT};
 
 pub fn wasi_args_get(
-    mut caller: Caller<ProcessData>,
+    caller: Caller<ProcessData>,
     argv_ptr: u32,
     argv_buf_ptr: u32,
 ) -> Result<u32> {
     // Clone args to avoid borrow checker issues
     let args = caller.data().args.clone();
-    let memory = match caller.get_export("memory") {
-        Some(wasmtime::Extern::Memory(mem)) => mem,
-        _ => return Ok(1), // WASI_EINVAL
-    };
-    let mem = memory.data_mut(&mut caller);
-    let mut buf_offset = argv_buf_ptr as usize;
-    for (i, arg) in args.iter().enumerate() {
-        let ptr_offset = argv_ptr as usize + i * 4;
-        let arg_bytes = arg.as_bytes();
-        let arg_len = arg_bytes.len();
-        // Write pointer to this arg in argv[i]
-        let ptr = buf_offset as u32;
-        mem[ptr_offset..ptr_offset + 4].copy_from_slice(&ptr.to_le_bytes());
-        // Write arg string to argv_buf
-        mem[buf_offset..buf_offset + arg_len].copy_from_slice(arg_bytes);
-        mem[buf_offset + arg_len] = 0; // null terminator
-        buf_offset += arg_len + 1;
-    }
-    Ok(0)
+    Ok(write_strings(caller, &args, argv_ptr, argv_buf_ptr))
 }
 
 pub fn wasi_args_sizes_get(
-    mut caller: Caller<ProcessData>,
+    caller: Caller<ProcessData>,
     argc_ptr: u32,
     argv_buf_size_ptr: u32,
 ) -> Result<u32> {
-    // Clone args to avoid borrow checker issues
     let args = caller.data().args.clone();
-    let argc = args.len() as u32;
-    let argv_buf_size: u32 = args.iter().map(|a| a.len() as u32 + 1).sum();
-    let memory = match caller.get_export("memory") {
-        Some(wasmtime::Extern::Memory(mem)) => mem,
-        _ => return Ok(1), // WASI_EINVAL
-    };
-    let mem = memory.data_mut(&mut caller);
-    mem[argc_ptr as usize..(argc_ptr as usize + 4)].copy_from_slice(&argc.to_le_bytes());
-    mem[argv_buf_size_ptr as usize..(argv_buf_size_ptr as usize + 4)].copy_from_slice(&argv_buf_size.to_le_bytes());
-    Ok(0)
+    Ok(write_sizes(caller, &args, argc_ptr, argv_buf_size_ptr))
 }
 
+/// Returns the process's environment, `KEY=VALUE` strings laid out like argv.
 pub fn wasi_environ_get(
-    _caller: Caller<ProcessData>,
-    _environ_ptr: u32,
-    _environ_buf_ptr: u32,
+    caller: Caller<ProcessData>,
+    environ_ptr: u32,
+    environ_buf_ptr: u32,
 ) -> Result<u32> {
-    // TODO: Implement actual environ handling
-    Ok(0)
+    let env = caller.data().env.clone();
+    Ok(write_strings(caller, &env, environ_ptr, environ_buf_ptr))
 }
 
 pub fn wasi_environ_sizes_get(
-    _caller: Caller<ProcessData>,
-    _environ_count_ptr: u32,
-    _environ_buf_size_ptr: u32,
+    caller: Caller<ProcessData>,
+    environ_count_ptr: u32,
+    environ_buf_size_ptr: u32,
 ) -> Result<u32> {
-    // TODO: Implement actual environ size calculation
-    Ok(0)
-} 
\ No newline at end of file
+    let env = caller.data().env.clone();
+    Ok(write_sizes(caller, &env, environ_count_ptr, environ_buf_size_ptr))
+}
+
+/// Writes `strings` as NUL-terminated strings packed from `buf_ptr`, and a
+/// pointer to each at `ptrs_ptr`, as args_get and environ_get do. Returns the errno.
+fn write_strings(mut caller: Caller<ProcessData>, strings: &[String], ptrs_ptr: u32, buf_ptr: u32) -> u32 {
+    let memory = match guest_memory(&mut caller) {
+        Ok(mem) => mem,
+        Err(errno) => return errno as u32,
+    };
+    let mem = memory.data_mut(&mut caller);
+    let mut buf_offset = buf_ptr;
+    for (i, string) in strings.iter().enumerate() {
+        let ptr_offset = match (i as u32).checked_mul(4).and_then(|off| ptrs_ptr.checked_add(off)) {
+            Some(off) => off,
+            None => return WASI_EFAULT as u32,
+        };
+        // Write pointer to this string in ptrs[i]
+        if let Err(errno) = write_slice(mem, ptr_offset, &buf_offset.to_le_bytes()) {
+            return errno as u32;
+        }
+        // Write the string to the buffer, followed by its null terminator
+        let mut bytes = string.as_bytes().to_vec();
+        bytes.push(0);
+        if let Err(errno) = write_slice(mem, buf_offset, &bytes) {
+            return errno as u32;
+        }
+        buf_offset = match buf_offset.checked_add(bytes.len() as u32) {
+            Some(off) => off,
+            None => return WASI_EFAULT as u32,
+        };
+    }
+    0
+}
+
+/// Writes how many `strings` there are and the buffer size `write_strings`
+/// needs for them. Returns the errno.
+fn write_sizes(mut caller: Caller<ProcessData>, strings: &[String], count_ptr: u32, buf_size_ptr: u32) -> u32 {
+    let count = strings.len() as u32;
+    let buf_size: u32 = strings.iter().map(|s| s.len() as u32 + 1).sum();
+    let memory = match guest_memory(&mut caller) {
+        Ok(mem) => mem,
+        Err(errno) => return errno as u32,
+    };
+    let mem = memory.data_mut(&mut caller);
+    if let Err(errno) = write_slice(mem, count_ptr, &count.to_le_bytes()) {
+        return errno as u32;
+    }
+    if let Err(errno) = write_slice(mem, buf_size_ptr, &buf_size.to_le_bytes()) {
+        return errno as u32;
+    }
+    0
+}
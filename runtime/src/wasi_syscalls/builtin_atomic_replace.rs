@@ -0,0 +1,74 @@
+use std::fs::File;
+use wasmtime::Caller;
+use crate::runtime::process::ProcessData;
+use log::{debug, error};
+use crate::wasi_syscalls::fs::{confine_rename, deny_if_read_only, io_err_to_wasi_errno, rename_in_sandbox, resolve_guest_path};
+use crate::wasi_syscalls::memory::{guest_memory, read_slice};
+
+/// `env.__builtin_rt_atomic_replace(tmp_ptr, tmp_len, final_ptr, final_len)`:
+/// replaces the file at the final path with the one at the temp path, for
+/// crash-safe write-temp-then-rename updates. The temp file is synced to disk
+/// before the rename and the directory after it, so once this returns 0 the
+/// new content is durable under the final name. `fd_write` leaves nothing
+/// buffered when it returns, so everything the guest wrote is included.
+///
+/// Returns 0, or an errno: 13 (EACCES) for a read-only root or a path outside
+/// the sandbox, 2 (ENOENT) if the temp file or the final directory is missing.
+#[allow(non_snake_case)]
+pub fn wasi__builtin_rt_atomic_replace(
+    mut caller: Caller<'_, ProcessData>,
+    tmp_ptr: i32,
+    tmp_len: i32,
+    final_ptr: i32,
+    final_len: i32,
+) -> i32 {
+    if let Err(errno) = deny_if_read_only(caller.data(), "__builtin_rt_atomic_replace") {
+        return errno;
+    }
+    let (tmp_path, final_path) = {
+        let memory = match guest_memory(&mut caller) {
+            Ok(mem) => mem,
+            Err(errno) => return errno,
+        };
+        let data = memory.data(&caller);
+        let read_path = |ptr: i32, len: i32| -> Result<String, i32> {
+            let bytes = read_slice(data, ptr as u32, len as u32 as usize)?;
+            std::str::from_utf8(bytes).map(str::to_owned).map_err(|_| 1)
+        };
+        match (read_path(tmp_ptr, tmp_len), read_path(final_ptr, final_len)) {
+            (Ok(tmp), Ok(dest)) => (tmp, dest),
+            (Err(errno), _) | (_, Err(errno)) => return errno,
+        }
+    };
+    let from = resolve_guest_path(caller.data(), &tmp_path);
+    let to = resolve_guest_path(caller.data(), &final_path);
+    // A tmpfs has nothing on disk to sync, and its rename cannot be torn.
+    let on_disk = caller.data().tmpfs.is_none();
+
+    // Both paths are confined to the sandbox before either is opened to sync it
+    let (from, to) = if on_disk {
+        match confine_rename(caller.data(), &from, &to) {
+            Ok(paths) => paths,
+            Err(errno) => return errno,
+        }
+    } else {
+        (from, to)
+    };
+    if on_disk {
+        if let Err(e) = File::open(&from).and_then(|file| file.sync_all()) {
+            error!("__builtin_rt_atomic_replace: failed to sync {}: {}", tmp_path, e);
+            return io_err_to_wasi_errno(&e);
+        }
+    }
+    if let Err(errno) = rename_in_sandbox(&mut caller, &from, &to) {
+        return errno;
+    }
+    if let Some(dir) = to.parent().filter(|_| on_disk) {
+        if let Err(e) = File::open(dir).and_then(|dir| dir.sync_all()) {
+            error!("__builtin_rt_atomic_replace: failed to sync the directory of {}: {}", final_path, e);
+            return io_err_to_wasi_errno(&e);
+        }
+    }
+    debug!("__builtin_rt_atomic_replace: process {} replaced {} with {}", caller.data().id, final_path, tmp_path);
+    0
+}
@@ -0,0 +1,16 @@
+use std::sync::atomic::Ordering;
+use wasmtime::Caller;
+use crate::runtime::process::ProcessData;
+use log::debug;
+
+/// `env.__builtin_rt_batch()`: returns the number of the consensus batch
+/// whose records were applied last, i.e. the one that let the guest run. It
+/// is the same on every replica, so guests can tag their output with it, for
+/// example to make an effect happen exactly once per batch. 0 before the
+/// first batch.
+#[allow(non_snake_case)]
+pub fn wasi__builtin_rt_batch(caller: Caller<'_, ProcessData>) -> u64 {
+    let batch = caller.data().batch.load(Ordering::SeqCst);
+    debug!("__builtin_rt_batch: process {} -> {}", caller.data().id, batch);
+    batch
+}
@@ -0,0 +1,37 @@
+use wasmtime::Caller;
+use crate::runtime::fd_table::FDEntry;
+use crate::runtime::process::ProcessData;
+use log::{debug, error};
+
+/// Largest flush threshold a guest may set; writes are buffered in host
+/// memory up to this size.
+pub const MAX_FLUSH_THRESHOLD: usize = 1024 * 1024;
+
+/// `env.__builtin_rt_set_flush_threshold(fd, bytes)`: sets how many bytes
+/// `fd_write` buffers for the file open at `fd` before flushing them to disk,
+/// in place of the process's `max_write_buffer`. A small threshold gets data
+/// to disk sooner, a large one makes big writes block less often. Each
+/// `fd_write` still flushes what is left before returning.
+///
+/// Returns 0, or an errno: 8 (EBADF) if `fd` is not a regular file in the
+/// sandbox, 28 (EINVAL) if `bytes` is not between 1 and `MAX_FLUSH_THRESHOLD`.
+#[allow(non_snake_case)]
+pub fn wasi__builtin_rt_set_flush_threshold(caller: Caller<'_, ProcessData>, fd: i32, bytes: i32) -> i32 {
+    let bytes = bytes as u32 as usize;
+    if bytes == 0 || bytes > MAX_FLUSH_THRESHOLD {
+        error!("__builtin_rt_set_flush_threshold: {} bytes is not between 1 and {}", bytes, MAX_FLUSH_THRESHOLD);
+        return 28; // EINVAL
+    }
+    let mut table = caller.data().fd_table.lock().unwrap();
+    match table.get_fd_entry_mut(fd) {
+        Some(FDEntry::File { is_directory: false, host_path: Some(_), flush_threshold, .. }) => {
+            *flush_threshold = Some(bytes);
+        }
+        _ => {
+            error!("__builtin_rt_set_flush_threshold: fd {} is not a file in the sandbox", fd);
+            return 8; // EBADF
+        }
+    }
+    debug!("__builtin_rt_set_flush_threshold: process {} flushes fd {} every {} bytes", caller.data().id, fd, bytes);
+    0
+}
@@ -0,0 +1,44 @@
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use wasmtime::Caller;
+use crate::runtime::process::ProcessData;
+use log::{debug, error};
+use crate::wasi_syscalls::memory::{guest_memory, read_slice};
+
+/// Largest status blob a guest may report in one call.
+const MAX_REPORT_LEN: usize = 4096;
+
+// Latest pending report per pid, drained when the next outgoing batch is built.
+// Only the most recent report is kept, so a chatty guest cannot flood consensus.
+static PENDING_REPORTS: Mutex<BTreeMap<u64, Vec<u8>>> = Mutex::new(BTreeMap::new());
+
+/// Takes all reports emitted since the last call, ordered by pid.
+pub fn take_pending_reports() -> Vec<(u64, Vec<u8>)> {
+    let mut pending = PENDING_REPORTS.lock().unwrap();
+    std::mem::take(&mut *pending).into_iter().collect()
+}
+
+/// `env.__builtin_rt_report(ptr, len)`: records an opaque status blob for this
+/// process, forwarded to consensus as a ProgressReport record.
+#[allow(non_snake_case)]
+pub fn wasi__builtin_rt_report(mut caller: Caller<'_, ProcessData>, ptr: i32, len: i32) -> i32 {
+    let memory = match guest_memory(&mut caller) {
+        Ok(mem) => mem,
+        Err(errno) => return errno,
+    };
+
+    let len = len as u32 as usize;
+    if len > MAX_REPORT_LEN {
+        error!("__builtin_rt_report: report of {} bytes exceeds limit of {}", len, MAX_REPORT_LEN);
+        return 28; // EINVAL
+    }
+    let report = match read_slice(memory.data(&caller), ptr as u32, len) {
+        Ok(bytes) => bytes.to_vec(),
+        Err(errno) => return errno,
+    };
+
+    let pid = caller.data().id;
+    debug!("__builtin_rt_report: process {} reported {} bytes", pid, report.len());
+    PENDING_REPORTS.lock().unwrap().insert(pid, report);
+    0
+}
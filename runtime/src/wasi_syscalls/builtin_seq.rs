@@ -0,0 +1,15 @@
+use wasmtime::Caller;
+use crate::runtime::process::ProcessData;
+use log::debug;
+
+/// `env.__builtin_rt_seq()`: returns the next value of this process's event
+/// counter, starting at 1. The counter only moves when the guest calls this,
+/// so every replica running the same program sees the same sequence, which
+/// makes it usable for unique ids where time or randomness would diverge.
+#[allow(non_snake_case)]
+pub fn wasi__builtin_rt_seq(caller: Caller<'_, ProcessData>) -> u64 {
+    let mut seq = caller.data().seq.lock().unwrap();
+    *seq += 1;
+    debug!("__builtin_rt_seq: process {} -> {}", caller.data().id, *seq);
+    *seq
+}
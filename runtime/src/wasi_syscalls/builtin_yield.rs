@@ -4,8 +4,13 @@ use log::{info, debug};
 
 #[allow(non_snake_case)]
 pub fn wasi__builtin_rt_yield(caller: Caller<'_, ProcessData>) {
+    yield_to_scheduler(caller.data());
+}
+
+/// Sets the process Ready and waits until the scheduler runs it again.
+pub fn yield_to_scheduler(data: &ProcessData) {
     {
-        let mut st = caller.data().state.lock().unwrap();
+        let mut st = data.state.lock().unwrap();
         if *st == ProcessState::Running {
             info!("wasi__builtin_rt_yield: Setting process state to Ready");
             *st = ProcessState::Ready;
@@ -13,15 +18,15 @@ pub fn wasi__builtin_rt_yield(caller: Caller<'_, ProcessData>) {
             debug!("wasi__builtin_rt_yield: Process state is not Running, current state: {:?}", *st);
         }
         // Notify the scheduler that we’re now waiting.
-        caller.data().cond.notify_all();
+        data.cond.notify_all();
         debug!("wasi__builtin_rt_yield: Notified the scheduler");
     }
 
     // Now wait until the state changes.
-    let mut state = caller.data().state.lock().unwrap();
+    let mut state = data.state.lock().unwrap();
     while *state == ProcessState::Ready {
         debug!("wasi__builtin_rt_yield: Waiting for state to change from Ready");
-        state = caller.data().cond.wait(state).unwrap();
+        state = data.cond.wait(state).unwrap();
     }
     debug!("wasi__builtin_rt_yield: State changed to {:?}", *state);
 }
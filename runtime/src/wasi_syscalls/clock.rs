@@ -2,42 +2,43 @@ use anyhow::Result;
 use wasmtime::Caller;
 use crate::runtime::process::ProcessData;
 use crate::runtime::clock::GlobalClock;
+use crate::wasi_syscalls::memory::{guest_memory, write_slice};
 
 // WASI clock IDs
+#[allow(dead_code)]
 const CLOCK_REALTIME: u32 = 0;
+#[allow(dead_code)]
 const CLOCK_MONOTONIC: u32 = 1;
+#[allow(dead_code)]
 const CLOCK_PROCESS_CPUTIME_ID: u32 = 2;
+#[allow(dead_code)]
 const CLOCK_THREAD_CPUTIME_ID: u32 = 3;
 
 pub fn wasi_clock_res_get(
     mut caller: Caller<ProcessData>,
-    clock_id: u32,
+    _clock_id: u32,
     resolution_ptr: u32,
 ) -> Result<u32> {
     // For deterministic behavior, we'll use a fixed resolution of 1ms
     let resolution: u64 = 1_000_000; // 1ms in nanoseconds
     
     // Write resolution to memory
-    let memory = match caller.get_export("memory") {
-        Some(wasmtime::Extern::Memory(mem)) => mem,
-        _ => return Ok(1), // EINVAL
+    let memory = match guest_memory(&mut caller) {
+        Ok(mem) => mem,
+        Err(errno) => return Ok(errno as u32),
     };
     
-    let mem_mut = memory.data_mut(&mut caller);
-    let out_ptr = resolution_ptr as usize;
-    if out_ptr + 8 > mem_mut.len() {
-        return Ok(1); // EINVAL
-    }
-    
     // Write resolution as u64 in little-endian
-    mem_mut[out_ptr..out_ptr+8].copy_from_slice(&resolution.to_le_bytes());
+    if let Err(errno) = write_slice(memory.data_mut(&mut caller), resolution_ptr, &resolution.to_le_bytes()) {
+        return Ok(errno as u32);
+    }
     
     Ok(0)
 }
 
 pub fn wasi_clock_time_get(
     mut caller: Caller<ProcessData>,
-    clock_id: u32,
+    _clock_id: u32,
     _precision: u64,
     time_ptr: u32,
 ) -> Result<u32> {
@@ -45,19 +46,15 @@ pub fn wasi_clock_time_get(
     let current_time = GlobalClock::now();
     
     // Write time to memory
-    let memory = match caller.get_export("memory") {
-        Some(wasmtime::Extern::Memory(mem)) => mem,
-        _ => return Ok(1), // EINVAL
+    let memory = match guest_memory(&mut caller) {
+        Ok(mem) => mem,
+        Err(errno) => return Ok(errno as u32),
     };
     
-    let mem_mut = memory.data_mut(&mut caller);
-    let out_ptr = time_ptr as usize;
-    if out_ptr + 8 > mem_mut.len() {
-        return Ok(1); // EINVAL
-    }
-    
     // Write time as u64 in little-endian
-    mem_mut[out_ptr..out_ptr+8].copy_from_slice(&current_time.to_le_bytes());
+    if let Err(errno) = write_slice(memory.data_mut(&mut caller), time_ptr, &current_time.to_le_bytes()) {
+        return Ok(errno as u32);
+    }
     
     Ok(0)
 } 
\ No newline at end of file
@@ -1,9 +1,12 @@
-use wasmtime::{Caller, Extern};
+use wasmtime::Caller;
 use std::convert::TryInto;
-use crate::runtime::process::{BlockReason, ProcessData, ProcessState};
+use std::path::Path;
+use crate::runtime::process::{block_until_running, BlockReason, ProcessData, TerminationReason};
 use crate::runtime::clock::GlobalClock;
 use crate::runtime::fd_table::FDEntry;
 use log::{info, error};
+use crate::wasi_syscalls::memory::{guest_memory, read_iovecs, read_slice, slice_mut, write_slice, WASI_EFAULT};
+use crate::wasi_syscalls::fs::{file_len, flush_pending_write};
 
 
 
@@ -12,12 +15,9 @@ pub fn wasi_fd_fdstat_get(mut caller: Caller<'_, ProcessData>, fd: i32, buf: i32
     info!("Called fd_fdstat_get with fd: {}", fd);
     
     // Get memory export
-    let memory = match caller.get_export("memory") {
-        Some(wasmtime::Extern::Memory(mem)) => mem,
-        _ => {
-            error!("fd_fdstat_get: no memory export found");
-            return 1;
-        }
+    let memory = match guest_memory(&mut caller) {
+        Ok(mem) => mem,
+        Err(errno) => return errno,
     };
 
     // Get FD entry
@@ -34,10 +34,13 @@ pub fn wasi_fd_fdstat_get(mut caller: Caller<'_, ProcessData>, fd: i32, buf: i32
     let mut fdstat = [0u8; 24]; // WASI fdstat struct size
     
     // Set file type (0=unknown, 1=block device, 2=character device, 3=directory, 4=regular file)
+    // and flags (only APPEND and NONBLOCK are tracked)
     if let Some(entry) = fd_entry {
         match entry {
-            FDEntry::File { is_directory, .. } => {
+            FDEntry::File { is_directory, append, nonblocking, .. } => {
                 fdstat[0] = if is_directory { 3 } else { 4 };
+                let flags = append as u16 | (nonblocking as u16) << 2;
+                fdstat[2..4].copy_from_slice(&flags.to_le_bytes());
             }
             FDEntry::Socket { .. } => {
                 fdstat[0] = 5; // Socket type
@@ -45,21 +48,14 @@ pub fn wasi_fd_fdstat_get(mut caller: Caller<'_, ProcessData>, fd: i32, buf: i32
         }
     }
 
-    // Set flags (0 for now)
-    fdstat[2..4].copy_from_slice(&0u16.to_le_bytes());
-
     // Set rights (full rights for now)
     fdstat[8..16].copy_from_slice(&u64::MAX.to_le_bytes());  // fs_rights_base
     fdstat[16..24].copy_from_slice(&u64::MAX.to_le_bytes()); // fs_rights_inheriting
 
     // Write fdstat to memory
-    let mem_mut = memory.data_mut(&mut caller);
-    let buf_ptr = buf as usize;
-    if buf_ptr + 24 > mem_mut.len() {
-        error!("fd_fdstat_get: buffer out of bounds");
-        return 1;
+    if let Err(errno) = write_slice(memory.data_mut(&mut caller), buf as u32, &fdstat) {
+        return errno;
     }
-    mem_mut[buf_ptr..buf_ptr + 24].copy_from_slice(&fdstat);
 
     0 // Success
 }
@@ -75,57 +71,74 @@ pub fn wasi_fd_seek(
     info!("Called fd_seek with fd: {}, offset: {}, whence: {}", fd, offset, whence);
     
     // Get memory export
-    let memory = match caller.get_export("memory") {
-        Some(wasmtime::Extern::Memory(mem)) => mem,
-        _ => {
-            error!("fd_seek: no memory export found");
-            return 1;
-        }
+    let memory = match guest_memory(&mut caller) {
+        Ok(mem) => mem,
+        Err(errno) => return errno,
     };
 
-    // Get current position and buffer length
-    let (current_pos, buffer_len) = {
+    // Bytes fd_write left buffered are not in the file yet, and SEEK_END
+    // must count them
+    if let Err(errno) = flush_pending_write(caller.data()) {
+        return errno;
+    }
+
+    // Get the cursor this fd seeks and where its end is. Append-mode writes
+    // always go to the end, so there a seek only moves the read cursor.
+    let (current_pos, end, append) = {
         let process_data = caller.data();
         let mut table = process_data.fd_table.lock().unwrap();
         if fd < 0 || (fd as usize) >= table.entries.len() {
             return 8; // WASI_EBADF
         }
         match &mut table.entries[fd as usize] {
-            Some(FDEntry::File { read_ptr, buffer, .. }) => (*read_ptr as i64, buffer.len() as i64),
+            Some(FDEntry::File { read_ptr, write_ptr, buffer, host_path, is_directory, append, .. }) => {
+                let current = if *append { *read_ptr } else { *write_ptr };
+                // The read buffer is a copy taken at open, which writes
+                // through this fd do not grow: a file's end is on disk
+                let end = match host_path {
+                    Some(path) if !*is_directory => file_len(process_data, Path::new(path))
+                        .map(|len| len as i64)
+                        .unwrap_or(buffer.len() as i64),
+                    _ => buffer.len() as i64,
+                };
+                (current as i64, end, *append)
+            }
             _ => return 8, // WASI_EBADF
         }
     };
 
     // Calculate new position based on whence
     let new_pos = match whence {
-        0 => offset,                    // SEEK_SET
-        1 => current_pos + offset,      // SEEK_CUR
-        2 => buffer_len + offset,       // SEEK_END
-        _ => return 28,                 // WASI_EINVAL
+        0 => Some(offset),                      // SEEK_SET
+        1 => current_pos.checked_add(offset),   // SEEK_CUR
+        2 => end.checked_add(offset),           // SEEK_END
+        _ => return 28,                         // WASI_EINVAL
     };
 
-    // Check bounds
-    if new_pos < 0 || new_pos > buffer_len {
-        return 28; // WASI_EINVAL
-    }
+    // Past the end is fine, as with lseek: the next write there grows the
+    // file. Only a negative (or overflowing) offset is invalid.
+    let new_pos = match new_pos {
+        Some(pos) if pos >= 0 => pos,
+        _ => return 28, // WASI_EINVAL
+    };
 
     // Update position
     {
         let process_data = caller.data();
         let mut table = process_data.fd_table.lock().unwrap();
-        if let Some(FDEntry::File { read_ptr, .. }) = table.get_fd_entry_mut(fd) {
+        if let Some(FDEntry::File { read_ptr, write_ptr, .. }) = table.get_fd_entry_mut(fd) {
             *read_ptr = new_pos as usize;
+            if !append {
+                *write_ptr = new_pos as usize;
+            }
         }
     }
 
     // Write new position to memory if requested
     if newoffset != 0 {
-        let mem_mut = memory.data_mut(&mut caller);
-        let out_ptr = newoffset as usize;
-        if out_ptr + 8 > mem_mut.len() {
-            return 1;
+        if let Err(errno) = write_slice(memory.data_mut(&mut caller), newoffset as u32, &new_pos.to_le_bytes()) {
+            return errno;
         }
-        mem_mut[out_ptr..out_ptr + 8].copy_from_slice(&new_pos.to_le_bytes());
     }
 
     0 // Success
@@ -138,15 +151,53 @@ pub fn wasi_fd_read(
     iovs_len: i32,
     nread: i32,
 ) -> i32 {
+    // Check the pointers before waiting for input, so a bad one fails with
+    // EFAULT now instead of once input arrives (or never, if none does)
+    {
+        let memory = match guest_memory(&mut caller) {
+            Ok(mem) => mem,
+            Err(errno) => return errno,
+        };
+        let data = memory.data(&caller);
+        if let Err(errno) = read_iovecs(data, iovs as u32, iovs_len as u32).and_then(|_| read_slice(data, nread as u32, 4)) {
+            return errno;
+        }
+    }
     loop {
         let (data_to_read, _) = {
             let process_data = caller.data();
             let mut table = process_data.fd_table.lock().unwrap();
             match table.get_fd_entry_mut(fd) {
-                Some(FDEntry::File { buffer, read_ptr, .. }) => {
+                Some(FDEntry::File { buffer, read_ptr, nonblocking, host_path, .. }) => {
+                    // A file on disk at or past its end (after a seek there)
+                    // is at EOF; only stdin waits for more input
+                    if *read_ptr >= buffer.len() && host_path.is_some() {
+                        drop(table);
+                        let memory = match guest_memory(&mut caller) {
+                            Ok(mem) => mem,
+                            Err(errno) => return errno,
+                        };
+                        return match write_slice(memory.data_mut(&mut caller), nread as u32, &0u32.to_le_bytes()) {
+                            Ok(()) => 0,
+                            Err(errno) => errno,
+                        };
+                    }
+                    if *read_ptr >= buffer.len() && *nonblocking {
+                        drop(table);
+                        let memory = match guest_memory(&mut caller) {
+                            Ok(mem) => mem,
+                            Err(errno) => return errno,
+                        };
+                        if let Err(errno) = write_slice(memory.data_mut(&mut caller), nread as u32, &0u32.to_le_bytes()) {
+                            return errno;
+                        }
+                        return 6; // WASI_EAGAIN
+                    }
                     if *read_ptr >= buffer.len() {
                         drop(table);
-                        block_process_for_stdin(&mut caller);
+                        if !block_until_running(caller.data(), BlockReason::StdinRead) {
+                            return 29; // EIO
+                        }
                         continue;
                     }
                     let available_data = &buffer[*read_ptr..];
@@ -160,80 +211,38 @@ pub fn wasi_fd_read(
         };
 
         // At this point, data is available, so proceed to copy it into the WASM memory.
-        let memory = match caller.get_export("memory") {
-            Some(Extern::Memory(mem)) => mem,
-            _ => {
-                error!("fd_read: Failed to find memory export");
-                return 1;
-            }
+        let memory = match guest_memory(&mut caller) {
+            Ok(mem) => mem,
+            Err(errno) => return errno,
         };
 
         let total_read = {
-            // First, determine how many bytes will be read using the iovecs.
-            let mut total = 0;
-            {
-                let data = memory.data(&caller);
-                for i in 0..iovs_len {
-                    let iovec_addr = (iovs as usize) + (i as usize) * 8;
-                    if iovec_addr + 8 > data.len() {
-                        error!("iovec out of bounds");
-                        return 1;
-                    }
-                    let offset_bytes: [u8; 4] = data[iovec_addr..iovec_addr + 4].try_into().unwrap();
-                    let len_bytes: [u8; 4] = data[iovec_addr + 4..iovec_addr + 8].try_into().unwrap();
-                    let offset = u32::from_le_bytes(offset_bytes) as usize;
-                    let len = u32::from_le_bytes(len_bytes) as usize;
-                    if offset + len > data.len() {
-                        error!("data slice out of bounds");
-                        return 1;
-                    }
-                    let to_copy = std::cmp::min(len, data_to_read.len() - total);
-                    if to_copy == 0 {
-                        break;
-                    }
-                    total += to_copy;
-                    if total >= data_to_read.len() {
-                        break;
-                    }
-                }
-            }
-
-            // Now actually write the data into the WASM memory.
             let data_mut = memory.data_mut(&mut caller);
+            // Every iovec is bounds-checked before anything is copied
+            let iovecs = match read_iovecs(data_mut, iovs as u32, iovs_len as u32) {
+                Ok(iovecs) => iovecs,
+                Err(errno) => return errno,
+            };
             let mut total = 0;
-            for i in 0..iovs_len {
-                let iovec_addr = (iovs as usize) + (i as usize) * 8;
-                if iovec_addr + 8 > data_mut.len() {
-                    error!("iovec out of bounds");
-                    return 1;
-                }
-                let offset_bytes: [u8; 4] = data_mut[iovec_addr..iovec_addr + 4].try_into().unwrap();
-                let len_bytes: [u8; 4] = data_mut[iovec_addr + 4..iovec_addr + 8].try_into().unwrap();
-                let offset = u32::from_le_bytes(offset_bytes) as usize;
-                let len = u32::from_le_bytes(len_bytes) as usize;
-                if offset + len > data_mut.len() {
-                    error!("data slice out of bounds");
-                    return 1;
-                }
-                let to_copy = std::cmp::min(len, data_to_read.len() - total);
+            for (buf, buf_len) in iovecs {
+                let to_copy = std::cmp::min(buf_len as usize, data_to_read.len() - total);
                 if to_copy == 0 {
                     break;
                 }
-                data_mut[offset..offset + to_copy]
-                    .copy_from_slice(&data_to_read[total..total + to_copy]);
+                let dest = match slice_mut(data_mut, buf, to_copy) {
+                    Ok(dest) => dest,
+                    Err(errno) => return errno,
+                };
+                dest.copy_from_slice(&data_to_read[total..total + to_copy]);
                 total += to_copy;
                 if total >= data_to_read.len() {
                     break;
                 }
             }
             // Write the total number of bytes read into memory.
-            let total_read_bytes = (total as u32).to_le_bytes();
-            let nread_ptr = nread as usize;
-            if nread_ptr + 4 > data_mut.len() {
-                error!("nread pointer out of bounds");
-                return 1;
+            if let Err(errno) = write_slice(data_mut, nread as u32, &(total as u32).to_le_bytes()) {
+                return errno;
             }
-            data_mut[nread_ptr..nread_ptr + 4].copy_from_slice(&total_read_bytes);
             total
         };
 
@@ -241,45 +250,27 @@ pub fn wasi_fd_read(
         {
             let process_data = caller.data();
             let mut table = process_data.fd_table.lock().unwrap();
-            if let Some(FDEntry::File { read_ptr, .. }) = table.get_fd_entry_mut(fd) {
+            if let Some(FDEntry::File { read_ptr, write_ptr, append, .. }) = table.get_fd_entry_mut(fd) {
                 *read_ptr += total_read;
+                // Outside append mode a write continues where the read stopped
+                if !*append {
+                    *write_ptr = *read_ptr;
+                }
             }
         }
         return 0;
     }
 }
 
-/// Blocks the process, telling the scheduler we're waiting on stdin.
-fn block_process_for_stdin(caller: &mut Caller<'_, ProcessData>) {
-    {
-        let mut st = caller.data().state.lock().unwrap();
-        if *st == ProcessState::Running {
-            info!("fd_read: Setting process state to Blocked");
-            *st = ProcessState::Blocked;
-        }
-        let mut reason = caller.data().block_reason.lock().unwrap();
-        *reason = Some(BlockReason::StdinRead);
-        // Notify the scheduler that we're now waiting.
-        caller.data().cond.notify_all();
-    }
-
-    // Now wait until the state changes.
-    let mut state = caller.data().state.lock().unwrap();
-    while *state != ProcessState::Running {
-        state = caller.data().cond.wait(state).unwrap();
-    }
-}
-
 pub fn wasi_fd_prestat_get(
     mut caller: wasmtime::Caller<'_, ProcessData>,
     fd: i32,
     prestat_ptr: i32,
 ) -> i32 {
-    use wasmtime::Extern;
     // Get memory export.
-    let memory = match caller.get_export("memory") {
-        Some(Extern::Memory(mem)) => mem,
-        _ => return 1,
+    let memory = match guest_memory(&mut caller) {
+        Ok(mem) => mem,
+        Err(errno) => return errno,
     };
 
     // Retrieve the FD entry for fd. We assume that if it's preopen and a directory,
@@ -311,13 +302,10 @@ pub fn wasi_fd_prestat_get(
     buf[4..8].copy_from_slice(&name_len.to_le_bytes());
 
     // Write the prestat struct back to memory.
-    let offset = prestat_ptr as usize;
-    let mem_mut = memory.data_mut(&mut caller);
-    if offset + 8 > mem_mut.len() {
-        return 1;
+    match write_slice(memory.data_mut(&mut caller), prestat_ptr as u32, &buf) {
+        Ok(()) => 0,
+        Err(errno) => errno,
     }
-    mem_mut[offset..offset+8].copy_from_slice(&buf);
-    0
 }
 
 
@@ -327,14 +315,9 @@ pub fn wasi_fd_prestat_dir_name(
     path_ptr: i32,
     path_len: i32,
 ) -> i32 {
-    use wasmtime::Extern;
-    use log::error;
-    let memory = match caller.get_export("memory") {
-        Some(Extern::Memory(mem)) => mem,
-        _ => {
-            error!("fd_prestat_dir_name: Memory not found");
-            return 1;
-        }
+    let memory = match guest_memory(&mut caller) {
+        Ok(mem) => mem,
+        Err(errno) => return errno,
     };
 
     // Return "." so that WASI libc uses FD=3 as the current working directory.
@@ -344,14 +327,10 @@ pub fn wasi_fd_prestat_dir_name(
         return 1;
     }
 
-    let mem_mut = memory.data_mut(&mut caller);
-    let offset = path_ptr as usize;
-    if offset + needed > mem_mut.len() {
-        return 1;
+    match write_slice(memory.data_mut(&mut caller), path_ptr as u32, dir_str.as_bytes()) {
+        Ok(()) => 0,
+        Err(errno) => errno,
     }
-
-    mem_mut[offset..offset+needed].copy_from_slice(dir_str.as_bytes());
-    0
 }
 
 
@@ -366,41 +345,38 @@ pub fn wasi_poll_oneoff(
     nevents_ptr: i32,
 ) -> i32 {
     // Get the memory export.
-    let memory = match caller.get_export("memory") {
-        Some(Extern::Memory(mem)) => mem,
-        _ => {
-            error!("poll_oneoff: Failed to find memory export");
-            return 1;
-        }
+    let memory = match guest_memory(&mut caller) {
+        Ok(mem) => mem,
+        Err(errno) => return errno,
     };
 
-    let mem_data = memory.data(&caller);
     let subscription_size = 48;
-    let nsubs = nsubscriptions as usize;
-    if (subscriptions_ptr as usize) + nsubs * subscription_size > mem_data.len() {
-        error!("poll_oneoff: Subscription array out of bounds");
-        return 1;
-    }
+    let event_size = 32;
+    let nsubs = nsubscriptions as u32 as usize;
+    let subs_data = match nsubs.checked_mul(subscription_size) {
+        Some(len) => match read_slice(memory.data(&caller), subscriptions_ptr as u32, len) {
+            Ok(subs_data) => subs_data,
+            Err(errno) => return errno,
+        },
+        None => return WASI_EFAULT,
+    };
 
     // For each subscription, extract its parameters and compute the wake time.
     let now = GlobalClock::now();
     let mut subscriptions = Vec::with_capacity(nsubs);
     let mut earliest_wake_time = u64::MAX;
-    for i in 0..nsubs {
-        let sub_offset = (subscriptions_ptr as usize) + i * subscription_size;
+    for sub in subs_data.chunks_exact(subscription_size) {
         // Read userdata (u64) from offset 0.
-        let userdata_bytes = &mem_data[sub_offset..sub_offset + 8];
-        let userdata = u64::from_le_bytes(userdata_bytes.try_into().unwrap());
+        let userdata = u64::from_le_bytes(sub[0..8].try_into().unwrap());
         // Read type (u16) from offset 8.
-        let type_bytes = &mem_data[sub_offset + 8..sub_offset + 10];
-        let sub_type = u16::from_le_bytes(type_bytes.try_into().unwrap());
+        let sub_type = u16::from_le_bytes(sub[8..10].try_into().unwrap());
         // Read timeout (u64) from offset 24.
-        let timeout_bytes = &mem_data[sub_offset + 24..sub_offset + 32];
+        let timeout_bytes = &sub[24..32];
         let timeout_nanos = u64::from_le_bytes(timeout_bytes.try_into().unwrap());
 
         // Use a default of 1 second if timeout is 0.
         let sleep_nanos = if timeout_nanos == 0 { 1_000_000_000 } else { timeout_nanos };
-        let wake_time = now + sleep_nanos;
+        let wake_time = now.saturating_add(sleep_nanos);
         if wake_time < earliest_wake_time {
             earliest_wake_time = wake_time;
         }
@@ -413,69 +389,51 @@ pub fn wasi_poll_oneoff(
     );
 
     // Block the process until the earliest wake time.
-    {
-        let process_data = caller.data();
-        let mut state = process_data.state.lock().unwrap();
-        let mut reason = process_data.block_reason.lock().unwrap();
-        *reason = Some(BlockReason::Timeout { resume_after: earliest_wake_time });
-        *state = ProcessState::Blocked;
-        process_data.cond.notify_all();
+    if !block_until_running(caller.data(), BlockReason::Timeout { resume_after: earliest_wake_time }) {
+        return 29; // EIO
     }
 
-    // Wait until the scheduler unblocks the process.
-    {
-        let mut state = caller.data().state.lock().unwrap();
-        while *state != ProcessState::Running {
-            state = caller.data().cond.wait(state).unwrap();
-        }
-    } // Lock on state is dropped here.
-
     // After unblocking, check which subscriptions have reached their wake time.
     let current_time = GlobalClock::now();
     let mut num_events = 0;
-    let event_size = 32;
-    let events_addr = events_ptr as usize;
     {
         let mem_mut = memory.data_mut(&mut caller);
-        if events_addr + nsubs * event_size > mem_mut.len() {
-            error!("poll_oneoff: Events area out of bounds");
-            return 1;
-        }
+        // nsubs * event_size cannot overflow: it is smaller than the subscription array
+        let events = match slice_mut(mem_mut, events_ptr as u32, nsubs * event_size) {
+            Ok(events) => events,
+            Err(errno) => return errno,
+        };
         // For each subscription, if the current time is at or past its wake time, record an event.
         for (userdata, sub_type, wake_time) in subscriptions.iter() {
             if current_time >= *wake_time {
-                let event_offset = events_addr + num_events * event_size;
+                let event = &mut events[num_events * event_size..(num_events + 1) * event_size];
                 // Write userdata (8 bytes).
-                mem_mut[event_offset..event_offset + 8].copy_from_slice(&userdata.to_le_bytes());
+                event[0..8].copy_from_slice(&userdata.to_le_bytes());
                 // Write error code (0 for success) as u16.
-                mem_mut[event_offset + 8..event_offset + 10].copy_from_slice(&0u16.to_le_bytes());
+                event[8..10].copy_from_slice(&0u16.to_le_bytes());
                 // Write the event type.
-                mem_mut[event_offset + 10..event_offset + 12].copy_from_slice(&sub_type.to_le_bytes());
+                event[10..12].copy_from_slice(&sub_type.to_le_bytes());
                 // Zero the remaining bytes.
-                for byte in &mut mem_mut[event_offset + 12..event_offset + event_size] {
-                    *byte = 0;
-                }
+                event[12..].fill(0);
                 num_events += 1;
             }
         }
         // Write the number of events (triggered subscriptions) to nevents_ptr.
-        let nevents_addr = nevents_ptr as usize;
-        if nevents_addr + 8 > mem_mut.len() {
-            error!("poll_oneoff: nevents pointer out of bounds");
-            return 1;
+        if let Err(errno) = write_slice(mem_mut, nevents_ptr as u32, &(num_events as u64).to_le_bytes()) {
+            return errno;
         }
-        mem_mut[nevents_addr..nevents_addr + 8].copy_from_slice(&((num_events as u64).to_le_bytes()));
     }
     0
 }
 
 /// Implementation for proc_exit: logs and terminates the process.
-pub fn wasi_proc_exit(caller: Caller<'_, ProcessData>, code: i32) -> () {
+///
+/// Nothing unwinds the guest thread: with the termination recorded, the
+/// `Counted` wrapper returns a trap instead, which leaves `_start`, and the
+/// thread then marks the process Finished as if `_start` had returned.
+pub fn wasi_proc_exit(caller: Caller<'_, ProcessData>, code: i32) {
     info!("Called proc_exit with code: {}", code);
-    {
-        let mut st = caller.data().state.lock().unwrap();
-        *st = ProcessState::Finished;
-    }
-    caller.data().cond.notify_all();
-    panic!("Process exited with code {}", code)
+    // Kept for the ProcessExit record the scheduler sends once it joins the process
+    *caller.data().exit_code.lock().unwrap() = Some(code);
+    *caller.data().termination.lock().unwrap() = Some(TerminationReason::Exited);
 }   
\ No newline at end of file
@@ -1,55 +1,105 @@
+use std::fs::{File, OpenOptions};
+use std::io::{Seek, SeekFrom, Write};
+use std::path::PathBuf;
 use anyhow::Result;
 use wasmtime::Caller;
 use crate::runtime::process::ProcessData;
 use crate::runtime::fd_table::FDEntry;
-use log::info;
+use log::{error, info};
+use crate::wasi_syscalls::memory::{guest_memory, read_iovecs, read_slice, write_slice};
+use crate::wasi_syscalls::fs::{deny_if_read_only, file_len, flush_pending_write, in_tmpfs, invalidate_cached, io_err_to_wasi_errno, touch, usage_add, usage_sub};
 
+/// Highest `advice` value, `ADVICE_NOREUSE`; the runtime has no use for
+/// any of them.
+const WASI_ADVICE_MAX: u32 = 5;
+
+/// Checks the FD is open and the advice is one WASI defines, then ignores
+/// it: reads and writes already go through buffers the runtime manages.
 pub fn wasi_fd_advise(
-    _caller: Caller<ProcessData>,
+    caller: Caller<ProcessData>,
     fd: u32,
     offset: u64,
     len: u64,
     advice: u32,
 ) -> Result<u32> {
     info!("wasi_fd_advise: fd={}, offset={}, len={}, advice={}", fd, offset, len, advice);
+    if !matches!(caller.data().fd_table.lock().unwrap().entries.get(fd as usize), Some(Some(_))) {
+        return Ok(8); // WASI_EBADF
+    }
+    if advice > WASI_ADVICE_MAX {
+        return Ok(28); // WASI_EINVAL
+    }
     Ok(0)
 }
 
+/// Makes the file at least `offset + len` bytes long, like
+/// `posix_fallocate`, so a guest that preallocates can rely on the space.
+/// Growth is zero-filled and counts against the disk quota; a file that is
+/// already long enough is left alone.
 pub fn wasi_fd_allocate(
-    _caller: Caller<ProcessData>,
+    mut caller: Caller<ProcessData>,
     fd: u32,
     offset: u64,
     len: u64,
 ) -> Result<u32> {
     info!("wasi_fd_allocate: fd={}, offset={}, len={}", fd, offset, len);
-    Ok(0)
+    if let Err(errno) = deny_if_read_only(caller.data(), "fd_allocate") {
+        return Ok(errno as u32);
+    }
+    if len == 0 {
+        return Ok(28); // WASI_EINVAL
+    }
+    let end = match offset.checked_add(len) {
+        Some(end) => end,
+        None => return Ok(22), // WASI_EFBIG
+    };
+    let host_path = match file_host_path(&caller, fd) {
+        Ok(host_path) => host_path,
+        Err(errno) => return Ok(errno),
+    };
+    let size = match &host_path {
+        Some(path) => match file_len(caller.data(), path) {
+            Ok(len) => len,
+            Err(e) => return Ok(io_err_to_wasi_errno(&e) as u32),
+        },
+        None => match caller.data().fd_table.lock().unwrap().entries.get(fd as usize) {
+            Some(Some(FDEntry::File { buffer, .. })) => buffer.len() as u64,
+            _ => 0,
+        },
+    };
+    if end <= size {
+        return Ok(0);
+    }
+    Ok(set_size(&mut caller, fd, host_path, end, "fd_allocate"))
 }
 
+/// Like `fd_sync`, but only the file's data and size need reach the disk.
 pub fn wasi_fd_datasync(
-    _caller: Caller<ProcessData>,
+    caller: Caller<ProcessData>,
     fd: u32,
 ) -> Result<u32> {
     info!("wasi_fd_datasync: fd={}", fd);
-    
-    // Check if fd is valid
-    let process_data = _caller.data();
-    let table = process_data.fd_table.lock().unwrap();
-    if fd as usize >= table.entries.len() {
-        return Ok(8); // WASI_EBADF
-    }
-    match &table.entries[fd as usize] {
-        Some(_) => Ok(0), // Success - no-op since we're working with in-memory files
-        None => Ok(8), // WASI_EBADF
-    }
+    Ok(sync_fd(&caller, fd, true, "fd_datasync"))
 }
 
+/// Only NONBLOCK (4) is applied, to files: fd_read on one then returns
+/// EAGAIN when nothing is buffered instead of blocking the process. Other
+/// flags, and sockets, are accepted and ignored.
 pub fn wasi_fd_fdstat_set_flags(
-    _caller: Caller<ProcessData>,
+    caller: Caller<ProcessData>,
     fd: u32,
     flags: u32,
 ) -> Result<u32> {
     info!("wasi_fd_fdstat_set_flags: fd={}, flags={}", fd, flags);
-    Ok(0)
+    let mut table = caller.data().fd_table.lock().unwrap();
+    match table.entries.get_mut(fd as usize) {
+        Some(Some(FDEntry::File { nonblocking, .. })) => {
+            *nonblocking = flags & 4 != 0;
+            Ok(0)
+        }
+        Some(Some(FDEntry::Socket { .. })) => Ok(0),
+        _ => Ok(8), // WASI_EBADF
+    }
 }
 
 pub fn wasi_fd_fdstat_set_rights(
@@ -187,17 +237,78 @@ pub fn wasi_fd_fdstat_set_rights(
 //     Ok(0)
 // }
 
+/// Truncates or zero-extends the file to `size` bytes, like `ftruncate`.
+/// Growth counts against the disk quota and shrinking releases it. FDs
+/// without a host file (stdio) only have their buffer resized, up to the
+/// process's stdin buffer limit.
 pub fn wasi_fd_filestat_set_size(
-    _caller: Caller<ProcessData>,
+    mut caller: Caller<ProcessData>,
     fd: u32,
     size: u64,
 ) -> Result<u32> {
     info!("wasi_fd_filestat_set_size: fd={}, size={}", fd, size);
-    Ok(0)
+    if let Err(errno) = deny_if_read_only(caller.data(), "fd_filestat_set_size") {
+        return Ok(errno as u32);
+    }
+    match file_host_path(&caller, fd) {
+        Ok(host_path) => Ok(set_size(&mut caller, fd, host_path, size, "fd_filestat_set_size")),
+        Err(errno) => Ok(errno),
+    }
+}
+
+/// The host path of FD `fd`, None for stdio. Fails with EBADF unless it is
+/// a file.
+fn file_host_path(caller: &Caller<ProcessData>, fd: u32) -> Result<Option<PathBuf>, u32> {
+    let process_data = caller.data();
+    let table = process_data.fd_table.lock().unwrap();
+    match table.entries.get(fd as usize) {
+        Some(Some(FDEntry::File { is_directory: false, host_path, .. })) => Ok(host_path.clone().map(PathBuf::from)),
+        _ => Err(8), // WASI_EBADF, also for sockets and directories
+    }
+}
+
+/// Resizes the file FD `fd` has open at `host_path` (None for stdio) to
+/// `size` bytes for `fd_filestat_set_size` and `fd_allocate`. Returns the errno.
+fn set_size(caller: &mut Caller<ProcessData>, fd: u32, host_path: Option<PathBuf>, size: u64, syscall: &str) -> u32 {
+    if let Some(path) = &host_path {
+        let old_size = match file_len(caller.data(), path) {
+            Ok(len) => len,
+            Err(e) => return io_err_to_wasi_errno(&e) as u32,
+        };
+        if let Err(errno) = usage_add(caller, size.saturating_sub(old_size)) {
+            return errno as u32;
+        }
+        invalidate_cached(caller.data(), path);
+        let resized = match in_tmpfs(caller.data(), |tmpfs| tmpfs.set_len(path, size)) {
+            Some(resized) => resized,
+            None => OpenOptions::new().write(true).open(path).and_then(|file| file.set_len(size)),
+        };
+        if let Err(e) = resized {
+            error!("wasi_{}: failed to resize {}: {}", syscall, path.display(), e);
+            usage_sub(caller, size.saturating_sub(old_size));
+            return io_err_to_wasi_errno(&e) as u32;
+        }
+        usage_sub(caller, old_size.saturating_sub(size));
+        touch(caller.data(), &path.to_string_lossy());
+    } else if size > caller.data().max_stdin_buffer as u64 {
+        return 22; // WASI_EFBIG
+    }
+
+    // Keep what reads through this FD see in step with the new size. A file
+    // whose contents were never loaded reports its size from disk instead.
+    let process_data = caller.data();
+    let mut table = process_data.fd_table.lock().unwrap();
+    if let Some(Some(FDEntry::File { buffer, read_ptr, .. })) = table.entries.get_mut(fd as usize) {
+        if host_path.is_none() || !buffer.is_empty() {
+            buffer.resize(size as usize, 0);
+            *read_ptr = (*read_ptr).min(buffer.len());
+        }
+    }
+    0
 }
 
 pub fn wasi_fd_filestat_set_times(
-    _caller: Caller<ProcessData>,
+    caller: Caller<ProcessData>,
     fd: u32,
     atim: u64,
     mtim: u64,
@@ -205,11 +316,16 @@ pub fn wasi_fd_filestat_set_times(
 ) -> Result<u32> {
     info!("wasi_fd_filestat_set_times: fd={}, atim={}, mtim={}, fst_flags={}", 
         fd, atim, mtim, fst_flags);
+    if let Err(errno) = deny_if_read_only(caller.data(), "fd_filestat_set_times") {
+        return Ok(errno as u32);
+    }
     Ok(0)
 }
 
+/// Reads into the iovecs from the file's contents starting at `offset`,
+/// without moving the read pointer. Reads past the end return 0 bytes.
 pub fn wasi_fd_pread(
-    _caller: Caller<ProcessData>,
+    mut caller: Caller<ProcessData>,
     fd: u32,
     iovs_ptr: u32,
     iovs_len: u32,
@@ -218,11 +334,54 @@ pub fn wasi_fd_pread(
 ) -> Result<u32> {
     info!("wasi_fd_pread: fd={}, iovs_ptr={}, iovs_len={}, offset={}, nread_ptr={}", 
         fd, iovs_ptr, iovs_len, offset, nread_ptr);
+
+    let data_to_read = {
+        let process_data = caller.data();
+        let table = process_data.fd_table.lock().unwrap();
+        match table.entries.get(fd as usize) {
+            Some(Some(FDEntry::File { is_directory: true, .. })) => return Ok(31), // WASI_EISDIR
+            Some(Some(FDEntry::File { buffer, host_path: Some(_), .. })) => {
+                let start = usize::try_from(offset).unwrap_or(usize::MAX).min(buffer.len());
+                buffer[start..].to_vec()
+            }
+            _ => return Ok(8), // WASI_EBADF
+        }
+    };
+
+    let memory = match guest_memory(&mut caller) {
+        Ok(mem) => mem,
+        Err(errno) => return Ok(errno as u32),
+    };
+    let data_mut = memory.data_mut(&mut caller);
+    // Every iovec is bounds-checked before anything is copied
+    let iovecs = match read_iovecs(data_mut, iovs_ptr, iovs_len) {
+        Ok(iovecs) => iovecs,
+        Err(errno) => return Ok(errno as u32),
+    };
+    let mut total = 0;
+    for (buf, buf_len) in iovecs {
+        let to_copy = std::cmp::min(buf_len as usize, data_to_read.len() - total);
+        if to_copy == 0 {
+            break;
+        }
+        if let Err(errno) = write_slice(data_mut, buf, &data_to_read[total..total + to_copy]) {
+            return Ok(errno as u32);
+        }
+        total += to_copy;
+    }
+    if let Err(errno) = write_slice(data_mut, nread_ptr, &(total as u32).to_le_bytes()) {
+        return Ok(errno as u32);
+    }
     Ok(0)
 }
 
+/// Writes the iovecs to the file at `offset`, without moving the read pointer
+/// and regardless of what `fd_write` appends. Unlike `fd_write`, the data is
+/// on disk when this returns, and `fd_pread` and `fd_read` on the file see
+/// it at once. Only bytes that grow the file count toward the process's disk
+/// usage.
 pub fn wasi_fd_pwrite(
-    _caller: Caller<ProcessData>,
+    mut caller: Caller<ProcessData>,
     fd: u32,
     iovs_ptr: u32,
     iovs_len: u32,
@@ -231,33 +390,144 @@ pub fn wasi_fd_pwrite(
 ) -> Result<u32> {
     info!("wasi_fd_pwrite: fd={}, iovs_ptr={}, iovs_len={}, offset={}, nwritten_ptr={}", 
         fd, iovs_ptr, iovs_len, offset, nwritten_ptr);
+    if let Err(errno) = deny_if_read_only(caller.data(), "fd_pwrite") {
+        return Ok(errno as u32);
+    }
+
+    let host_path = {
+        let process_data = caller.data();
+        let table = process_data.fd_table.lock().unwrap();
+        match table.entries.get(fd as usize) {
+            Some(Some(FDEntry::File { is_directory: true, .. })) => return Ok(31), // WASI_EISDIR
+            Some(Some(FDEntry::File { host_path: Some(path), .. })) => PathBuf::from(path),
+            _ => return Ok(8), // WASI_EBADF
+        }
+    };
+
+    let memory = match guest_memory(&mut caller) {
+        Ok(mem) => mem,
+        Err(errno) => return Ok(errno as u32),
+    };
+    let data_to_write = {
+        let data = memory.data(&caller);
+        let iovecs = match read_iovecs(data, iovs_ptr, iovs_len) {
+            Ok(iovecs) => iovecs,
+            Err(errno) => return Ok(errno as u32),
+        };
+        let mut buf = Vec::new();
+        for (ptr, len) in iovecs {
+            match read_slice(data, ptr, len as usize) {
+                Ok(bytes) => buf.extend_from_slice(bytes),
+                Err(errno) => return Ok(errno as u32),
+            }
+        }
+        buf
+    };
+
+    let size = match file_len(caller.data(), &host_path) {
+        Ok(len) => len,
+        Err(e) => return Ok(io_err_to_wasi_errno(&e) as u32),
+    };
+    let end = offset.saturating_add(data_to_write.len() as u64);
+    if let Err(errno) = usage_add(&mut caller, end.saturating_sub(size)) {
+        return Ok(errno as u32);
+    }
+    invalidate_cached(caller.data(), &host_path);
+    let written = match in_tmpfs(caller.data(), |tmpfs| tmpfs.write_at(&host_path, Some(offset), &data_to_write)) {
+        Some(written) => written,
+        None => OpenOptions::new().write(true).open(&host_path).and_then(|mut file| {
+            file.seek(SeekFrom::Start(offset))?;
+            file.write_all(&data_to_write)
+        }),
+    };
+    if let Err(e) = written {
+        error!("wasi_fd_pwrite: failed to write to {}: {}", host_path.display(), e);
+        // Only what a partial write grew the file by stays charged
+        let grown = file_len(caller.data(), &host_path).unwrap_or(size).saturating_sub(size);
+        usage_sub(&mut caller, end.saturating_sub(size).saturating_sub(grown));
+        return Ok(io_err_to_wasi_errno(&e) as u32);
+    }
+    touch(caller.data(), &host_path.to_string_lossy());
+    // The FDs open on the file read from what it held when they were
+    // opened, so they get the new bytes too
+    let path = host_path.to_string_lossy();
+    for entry in caller.data().fd_table.lock().unwrap().entries.iter_mut().flatten() {
+        if let FDEntry::File { buffer, host_path: Some(open_path), .. } = entry {
+            if *open_path == path {
+                patch(buffer, offset, &data_to_write);
+            }
+        }
+    }
+
+    if let Err(errno) = write_slice(memory.data_mut(&mut caller), nwritten_ptr, &(data_to_write.len() as u32).to_le_bytes()) {
+        return Ok(errno as u32);
+    }
     Ok(0)
 }
 
+/// Writes `data` over `buffer` at `offset`, growing it with zeros as the
+/// file grows.
+fn patch(buffer: &mut Vec<u8>, offset: u64, data: &[u8]) {
+    let start = offset as usize;
+    let end = start + data.len();
+    if buffer.len() < end {
+        buffer.resize(end, 0);
+    }
+    buffer[start..end].copy_from_slice(data);
+}
+
 pub fn wasi_fd_renumber(
-    _caller: Caller<ProcessData>,
+    caller: Caller<ProcessData>,
     from: u32,
     to: u32,
 ) -> Result<u32> {
     info!("wasi_fd_renumber: from={}, to={}", from, to);
+    let mut table = caller.data().fd_table.lock().unwrap();
+    if !table.renumber(from as i32, to as i32) {
+        return Ok(8); // WASI_EBADF
+    }
     Ok(0)
 }
 
+/// Writes out what the process has buffered for the file and has the host
+/// commit it to disk, so it survives a crash once this returns.
 pub fn wasi_fd_sync(
-    _caller: Caller<ProcessData>,
+    caller: Caller<ProcessData>,
     fd: u32,
 ) -> Result<u32> {
     info!("wasi_fd_sync: fd={}", fd);
-    
-    // Check if fd is valid
-    let process_data = _caller.data();
-    let table = process_data.fd_table.lock().unwrap();
-    if fd as usize >= table.entries.len() {
-        return Ok(8); // WASI_EBADF
+    Ok(sync_fd(&caller, fd, false, "fd_sync"))
+}
+
+/// Writes out what fd_write left buffered, then syncs the file FD `fd` has
+/// open on the host: its data and size only if `data_only`, as `fdatasync` does.
+/// Stdio, sockets and files in a tmpfs have nothing on disk to sync, so they
+/// succeed at once. Returns the errno.
+fn sync_fd(caller: &Caller<ProcessData>, fd: u32, data_only: bool, syscall: &str) -> u32 {
+    let host_path = {
+        let table = caller.data().fd_table.lock().unwrap();
+        match table.entries.get(fd as usize) {
+            Some(Some(FDEntry::File { host_path: Some(path), .. })) => Some(path.clone()),
+            Some(Some(_)) => None,
+            _ => return 8, // WASI_EBADF
+        }
+    };
+    let Some(host_path) = host_path else {
+        return 0;
+    };
+    if let Err(errno) = flush_pending_write(caller.data()) {
+        return errno as u32;
     }
-    match &table.entries[fd as usize] {
-        Some(_) => Ok(0), // Success - no-op since we're working with in-memory files
-        None => Ok(8), // WASI_EBADF
+    if caller.data().tmpfs.is_some() {
+        return 0;
+    }
+    let synced = File::open(&host_path).and_then(|file| if data_only { file.sync_data() } else { file.sync_all() });
+    match synced {
+        Ok(()) => 0,
+        Err(e) => {
+            error!("wasi_{}: failed to sync {}: {}", syscall, host_path, e);
+            io_err_to_wasi_errno(&e) as u32
+        }
     }
 }
 
@@ -276,19 +546,22 @@ pub fn wasi_fd_tell(
             return Ok(8); // WASI_EBADF
         }
         match &table.entries[fd as usize] {
-            Some(FDEntry::File { read_ptr, .. }) => *read_ptr as u64,
+            // The cursor fd_seek moves: writes go to the end in append mode
+            Some(FDEntry::File { read_ptr, write_ptr, append, .. }) => {
+                if *append { *read_ptr as u64 } else { *write_ptr as u64 }
+            }
             _ => return Ok(8), // WASI_EBADF
         }
     };
 
     // Write position to memory
-    let memory = caller.get_export("memory").unwrap().into_memory().unwrap();
-    let mem = memory.data_mut(&mut caller);
-    let ptr = offset_ptr as usize;
-    if ptr + 8 > mem.len() {
-        return Ok(21); // WASI_EFAULT
+    let memory = match guest_memory(&mut caller) {
+        Ok(mem) => mem,
+        Err(errno) => return Ok(errno as u32),
+    };
+    if let Err(errno) = write_slice(memory.data_mut(&mut caller), offset_ptr, &current_pos.to_le_bytes()) {
+        return Ok(errno as u32);
     }
-    mem[ptr..ptr+8].copy_from_slice(&current_pos.to_le_bytes());
     
     Ok(0)
 } 
\ No newline at end of file
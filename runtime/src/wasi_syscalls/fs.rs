@@ -1,45 +1,124 @@
 use std::fs;
 use std::fs::OpenOptions;
 use std::io;
-use std::path::Path;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Mutex;
 use log::{error, debug};
-use wasmtime::{Caller, Extern};
-use std::io::Write;
-
-use crate::runtime::process::{ProcessData, ProcessState, BlockReason};
-use crate::runtime::fd_table::{FDEntry};
+use wasmtime::Caller;
+use std::io::{Seek, SeekFrom, Write};
+
+use crate::runtime::clock::GlobalClock;
+use crate::runtime::process::{block_until_running, ProcessData, BlockReason};
+use crate::runtime::fd_table::{FDEntry, FileTimes};
+use crate::runtime::tmpfs::{Tmpfs, TmpfsEntry};
+use crate::wasi_syscalls::memory::{guest_memory, read_iovecs, read_slice, write_slice};
 const WASI_ERRNO_NOSPC: i32 = 28;  // __WASI_ERRNO_NOSPC
-const WASI_ERRNO_NOSYS: i32 = 52;  // __WASI_ERRNO_NOSYS
-
-
-fn io_err_to_wasi_errno(e: &io::Error) -> i32 {
+const WASI_ERRNO_LOOP: i32 = 32;  // __WASI_ERRNO_LOOP
+const WASI_ERRNO_ACCES: i32 = 13;  // as io_err_to_wasi_errno reports PermissionDenied
+// path_open flags and rights that ask for write access
+const WASI_ERRNO_EXIST: i32 = 20;
+const WASI_ERRNO_NOTDIR: i32 = 54;
+const WASI_OFLAGS_CREAT: i32 = 0x1;
+const WASI_OFLAGS_DIRECTORY: i32 = 0x2;
+const WASI_OFLAGS_EXCL: i32 = 0x4;
+const WASI_OFLAGS_TRUNC: i32 = 0x8;
+const WASI_RIGHTS_FD_READ: i64 = 1 << 1;
+const WASI_RIGHTS_FD_WRITE: i64 = 1 << 6;
+const WASI_FDFLAGS_APPEND: i32 = 0x1;
+const WASI_FDFLAGS_NONBLOCK: i32 = 0x4;
+pub const WASI_LOOKUPFLAGS_SYMLINK_FOLLOW: i32 = 0x1;
+/// Longest chain of emulated symlinks `path_open` follows before it fails
+/// with ELOOP.
+const MAX_SYMLINK_DEPTH: usize = 16;
+
+
+pub fn io_err_to_wasi_errno(e: &io::Error) -> i32 {
     use io::ErrorKind::*;
     match e.kind() {
         NotFound => 2,           // e.g. __WASI_ERRNO_NOENT
         PermissionDenied => 13,  // e.g. __WASI_ERRNO_ACCES
         AlreadyExists => 20,     // __WASI_ERRNO_EXIST
+        CrossesDevices => 75,    // __WASI_ERRNO_XDEV
+        IsADirectory => 31,      // __WASI_ERRNO_ISDIR
+        NotADirectory => 54,     // __WASI_ERRNO_NOTDIR
+        DirectoryNotEmpty => 55, // __WASI_ERRNO_NOTEMPTY
         _ => 1,                  // catch-all or __WASI_ERRNO_IO
     }
 }
 
-/// If you want to block for file I/O
-fn block_process_for_fileio(caller: &mut Caller<'_, ProcessData>) {
-    let process_id = caller.data().id;
-    {
-        let mut state = caller.data().state.lock().unwrap();
-        if *state == ProcessState::Running {
-            println!("Process {}: Setting process state to Blocked (FileIO).", process_id);
-            *state = ProcessState::Blocked;
+/// Fails a syscall that would modify the sandbox of a process started with a
+/// read-only root (`init -r`). Stdout, stderr and sockets stay writable.
+pub fn deny_if_read_only(data: &ProcessData, syscall: &str) -> Result<(), i32> {
+    if data.read_only_root {
+        error!("{}: process {} has a read-only root", syscall, data.id);
+        return Err(WASI_ERRNO_ACCES);
+    }
+    Ok(())
+}
+
+/// Host path for a path the guest passed to a syscall. Paths starting with
+/// `/` start at the sandbox root, all others at the process's working
+/// directory. Callers still have to check the result stays inside the root.
+pub fn resolve_guest_path(data: &ProcessData, path: &str) -> PathBuf {
+    match path.strip_prefix('/') {
+        Some(absolute) => data.root_path.join(absolute.trim_start_matches('/')),
+        None => data.cwd.join(path),
+    }
+}
+
+/// Runs `op` on the process's in-memory files, if it was started with the
+/// Init `tmpfs:` option. None means its files are on the host.
+pub fn in_tmpfs<T>(data: &ProcessData, op: impl FnOnce(&mut Tmpfs) -> io::Result<T>) -> Option<io::Result<T>> {
+    data.tmpfs.as_ref().map(|tmpfs| op(&mut tmpfs.lock().unwrap()))
+}
+
+/// Size of the file at `host_path`, in the process's tmpfs or on the host.
+pub fn file_len(data: &ProcessData, host_path: &Path) -> io::Result<u64> {
+    match in_tmpfs(data, |tmpfs| tmpfs.stat(host_path)) {
+        Some(Ok(TmpfsEntry::File { len })) => Ok(len),
+        Some(Ok(TmpfsEntry::Directory)) => Ok(0),
+        Some(Err(e)) => Err(e),
+        None => fs::metadata(host_path).map(|md| md.len()),
+    }
+}
+
+/// Key of an emulated symlink in `ProcessData::symlinks`: a host path from
+/// `resolve_guest_path` with `.` and `..` folded away, so every spelling of
+/// the link's path finds it.
+pub fn symlink_key(path: &Path) -> PathBuf {
+    let mut key = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir if matches!(key.components().next_back(), Some(Component::Normal(_))) => {
+                key.pop();
+            }
+            other => key.push(other),
         }
-        let mut reason = caller.data().block_reason.lock().unwrap();
-        *reason = Some(BlockReason::FileIO);
-        caller.data().cond.notify_all();
     }
-    let mut state = caller.data().state.lock().unwrap();
-    while *state != ProcessState::Running {
-        state = caller.data().cond.wait(state).unwrap();
+    key
+}
+
+/// Follows the emulated symlinks made by `path_symlink`, starting at `path`
+/// (a host path from `resolve_guest_path`), and returns the path of the file
+/// they lead to, or `path` itself if it is not a link. Targets starting with
+/// `/` start at the sandbox root, all others at the link's directory. Callers
+/// still have to check the result stays inside the root.
+pub fn follow_symlinks(data: &ProcessData, path: &Path) -> Result<PathBuf, i32> {
+    let links = data.symlinks.lock().unwrap();
+    let mut current = path.to_path_buf();
+    for _ in 0..MAX_SYMLINK_DEPTH {
+        let key = symlink_key(&current);
+        let Some(target) = links.get(&key) else {
+            return Ok(current);
+        };
+        current = match target.strip_prefix("/") {
+            Ok(absolute) => data.root_path.join(absolute),
+            Err(_) => key.parent().unwrap_or(&data.root_path).join(target),
+        };
     }
-    println!("Process {}: Resuming after FileIO block.", process_id);
+    error!("follow_symlinks: more than {} links from {:?}", MAX_SYMLINK_DEPTH, path);
+    Err(WASI_ERRNO_LOOP)
 }
 
 // ----------------------------------------------------------------------------
@@ -48,7 +127,7 @@ fn block_process_for_fileio(caller: &mut Caller<'_, ProcessData>) {
 
 /// Increment the process's tracked usage by `bytes`. If the limit is exceeded,
 /// return an error code WASI_ERRNO_NOSPC.
-fn usage_add(caller: &mut Caller<'_, ProcessData>, bytes: u64) -> Result<(), i32> {
+pub fn usage_add(caller: &mut Caller<'_, ProcessData>, bytes: u64) -> Result<(), i32> {
     // 1) Figure out if we exceed the limit
     let over_limit = {
         // Borrow immutably but only within this block
@@ -57,7 +136,7 @@ fn usage_add(caller: &mut Caller<'_, ProcessData>, bytes: u64) -> Result<(), i32
         *usage = usage.saturating_add(bytes);
 
         // Return boolean so we can decide outside
-        *usage > pd.max_disk_usage
+        *usage > *pd.max_disk_usage.lock().unwrap()
     }; // Immutable borrow ends here
 
     // 2) If over the limit, return error code
@@ -71,12 +150,156 @@ fn usage_add(caller: &mut Caller<'_, ProcessData>, bytes: u64) -> Result<(), i32
 
 
 /// Decrement the process's tracked usage by `bytes`. 
-fn usage_sub(caller: &mut Caller<'_, ProcessData>, bytes: u64) {
+pub fn usage_sub(caller: &mut Caller<'_, ProcessData>, bytes: u64) {
     let pd = caller.data();
     let mut usage = pd.current_disk_usage.lock().unwrap();
     *usage = usage.saturating_sub(bytes);
 }
 
+/// Return WASI_ERRNO_NOSPC, without charging anything, if `bytes` more would
+/// exceed the limit. `fd_write` checks this up front, and each flush then
+/// charges what it actually added to the file.
+fn usage_check(data: &ProcessData, bytes: u64) -> Result<(), i32> {
+    let usage = data.current_disk_usage.lock().unwrap();
+    if usage.saturating_add(bytes) > *data.max_disk_usage.lock().unwrap() {
+        eprintln!("Exceeded disk quota! Returning NOSPC error.");
+        return Err(WASI_ERRNO_NOSPC);
+    }
+    Ok(())
+}
+
+// ----------------------------------------------------------------------------
+// Logical file times
+// ----------------------------------------------------------------------------
+
+/// The times `fd_filestat_get` and `path_filestat_get` report for
+/// `host_path`. A file the process has not written or given times to has
+/// both at 0.
+pub fn logical_times(data: &ProcessData, host_path: &str) -> FileTimes {
+    data.file_times.lock().unwrap().get(host_path).copied().unwrap_or_default()
+}
+
+/// Changes the times of `host_path`, and of every FD open on it, with
+/// `update`. Returns the new times. Takes the FD table's lock, so callers
+/// must not hold it.
+pub fn update_times(data: &ProcessData, host_path: &str, update: impl FnOnce(&mut FileTimes)) -> FileTimes {
+    let updated = {
+        let mut file_times = data.file_times.lock().unwrap();
+        let times = file_times.entry(host_path.to_string()).or_default();
+        update(times);
+        *times
+    };
+    let mut table = data.fd_table.lock().unwrap();
+    for entry in table.entries.iter_mut() {
+        if let Some(FDEntry::File { host_path: Some(path), times, .. }) = entry {
+            if path == host_path {
+                *times = updated;
+            }
+        }
+    }
+    updated
+}
+
+/// Stamps `host_path` as written now. A file seen for the first time, such
+/// as a new one, gets its access time set too.
+pub fn touch(data: &ProcessData, host_path: &str) -> FileTimes {
+    let now = GlobalClock::now();
+    update_times(data, host_path, |times| {
+        if *times == FileTimes::default() {
+            times.atime = now;
+        }
+        times.mtime = now;
+    })
+}
+
+/// Renames the file `from` to `to` (host paths from `resolve_guest_path`),
+/// replacing `to` if it exists. Both must stay inside the sandbox. Open FDs
+/// on `from` follow the file, and a replaced file's size is released from
+/// the process's disk usage.
+pub fn rename_in_sandbox(caller: &mut Caller<'_, ProcessData>, from: &Path, to: &Path) -> Result<(), i32> {
+    if let Some(tmpfs) = caller.data().tmpfs.clone() {
+        let mut tmpfs = tmpfs.lock().unwrap();
+        let (from, to) = match (tmpfs.resolve(from), tmpfs.resolve(to)) {
+            (Ok(from), Ok(to)) => (from, to),
+            (Err(e), _) | (_, Err(e)) => return Err(io_err_to_wasi_errno(&e)),
+        };
+        let replaced_size = tmpfs.rename(&from, &to).map_err(|e| io_err_to_wasi_errno(&e))?;
+        drop(tmpfs);
+        if from != to {
+            usage_sub(caller, replaced_size);
+        }
+        moved(caller.data(), &from, &to);
+        return Ok(());
+    }
+    let (from, to) = confine_rename(caller.data(), from, to)?;
+
+    let replaced_size = match fs::metadata(&to) {
+        Ok(md) if md.is_file() => md.len(),
+        _ => 0,
+    };
+    invalidate_cached(caller.data(), &from);
+    invalidate_cached(caller.data(), &to);
+    if let Err(e) = fs::rename(&from, &to) {
+        error!("rename: failed to rename {:?} to {:?}: {}", from, to, e);
+        return Err(io_err_to_wasi_errno(&e));
+    }
+    if from != to {
+        usage_sub(caller, replaced_size);
+    }
+
+    moved(caller.data(), &from, &to);
+    Ok(())
+}
+
+/// Resolves the on-disk paths of a rename, `from` and the parent directory
+/// of `to`, and checks both are inside the process's sandbox, without opening
+/// either. Anything that touches them goes through here first, so a guest
+/// cannot reach a file outside its sandbox, or learn whether one exists.
+pub fn confine_rename(data: &ProcessData, from: &Path, to: &Path) -> Result<(PathBuf, PathBuf), i32> {
+    let canonical_root = data.root_path.canonicalize().map_err(|e| io_err_to_wasi_errno(&e))?;
+    let (parent, name) = match (to.parent(), to.file_name()) {
+        (Some(parent), Some(name)) => (parent, name),
+        _ => return Err(1),
+    };
+    // Checked lexically first, so the errors of the canonicalizations below
+    // say nothing about paths outside the sandbox
+    let absolute = |path: &Path| std::path::absolute(path).map(|path| symlink_key(&path));
+    let root = absolute(&data.root_path).map_err(|e| io_err_to_wasi_errno(&e))?;
+    let outside = |path: &Path| !absolute(path).is_ok_and(|path| path.starts_with(&root));
+    if outside(from) || outside(parent) {
+        error!("rename: attempt to escape sandbox root!");
+        return Err(WASI_ERRNO_ACCES);
+    }
+    let from = from.canonicalize().map_err(|e| io_err_to_wasi_errno(&e))?;
+    let to = parent.canonicalize().map_err(|e| io_err_to_wasi_errno(&e))?.join(name);
+    if !from.starts_with(&canonical_root) || !to.starts_with(&canonical_root) || from == canonical_root {
+        error!("rename: attempt to escape sandbox root!");
+        return Err(WASI_ERRNO_ACCES);
+    }
+    Ok((from, to))
+}
+
+/// Makes the logical times and open FDs of the file renamed from `from`
+/// follow it to `to`.
+fn moved(data: &ProcessData, from: &Path, to: &Path) {
+    let from = from.to_string_lossy();
+    {
+        let mut file_times = data.file_times.lock().unwrap();
+        match file_times.remove(from.as_ref()) {
+            Some(times) => file_times.insert(to.to_string_lossy().into_owned(), times),
+            None => file_times.remove(to.to_string_lossy().as_ref()),
+        };
+    }
+    let mut table = data.fd_table.lock().unwrap();
+    for entry in table.entries.iter_mut() {
+        if let Some(FDEntry::File { host_path: Some(path), .. }) = entry {
+            if *path == from {
+                *path = to.to_string_lossy().into_owned();
+            }
+        }
+    }
+}
+
 /// If you remove a directory, or some other operation, and need to figure out how many
 /// bytes were in that directory, you can do a quick naive walk:
 pub fn get_dir_size(path: &Path) -> io::Result<u64> {
@@ -106,7 +329,7 @@ pub fn wasi_fd_filestat_get(
     debug!("wasi_fd_filestat_get: fd={}, buf_ptr={}", fd, buf_ptr);
     
     // Get FD entry
-    let (size, filetype) = {
+    let (size, filetype, times) = {
         let process_data = caller.data();
         let table = process_data.fd_table.lock().unwrap();
         debug!("wasi_fd_filestat_get: checking fd {} in table with {} entries", fd, table.entries.len());
@@ -117,7 +340,7 @@ pub fn wasi_fd_filestat_get(
         }
         
         match &table.entries[fd as usize] {
-            Some(FDEntry::File { buffer, is_directory, host_path, .. }) => {
+            Some(FDEntry::File { buffer, is_directory, host_path, times, .. }) => {
                 debug!("wasi_fd_filestat_get: found File entry - buffer.len={}, is_dir={}, host_path={:?}", 
                     buffer.len(), is_directory, host_path);
                 
@@ -128,9 +351,8 @@ pub fn wasi_fd_filestat_get(
                     match host_path {
                         Some(path) => {
                             debug!("wasi_fd_filestat_get: buffer empty, trying metadata for {}", path);
-                            match std::fs::metadata(path) {
-                                Ok(metadata) => {
-                                    let size = metadata.len();
+                            match file_len(process_data, Path::new(path)) {
+                                Ok(size) => {
                                     debug!("wasi_fd_filestat_get: got metadata size {}", size);
                                     size
                                 },
@@ -146,11 +368,11 @@ pub fn wasi_fd_filestat_get(
                         }
                     }
                 };
-                (size, if *is_directory { 3u8 } else { 4u8 })
+                (size, if *is_directory { 3u8 } else { 4u8 }, *times)
             }
             Some(FDEntry::Socket { .. }) => {
                 debug!("wasi_fd_filestat_get: found Socket entry");
-                (0, 5u8) // Socket type
+                (0, 5u8, FileTimes::default()) // Socket type
             }
             None => {
                 debug!("wasi_fd_filestat_get: no entry found for fd {}", fd);
@@ -181,25 +403,21 @@ pub fn wasi_fd_filestat_get(
     buf[32..40].copy_from_slice(&size.to_le_bytes());
     debug!("wasi_fd_filestat_get: writing size {} to buffer at offset 32", size);
     
-    // st_atim (8 bytes) - set to 0
-    buf[40..48].copy_from_slice(&0u64.to_le_bytes());
-    
-    // st_mtim (8 bytes) - set to 0
-    buf[48..56].copy_from_slice(&0u64.to_le_bytes());
-    
-    // st_ctim (8 bytes) - set to 0
-    buf[56..64].copy_from_slice(&0u64.to_le_bytes());
+    // st_atim, st_mtim and st_ctim (8 bytes each) - the logical times,
+    // with the mtime standing in for the ctime
+    buf[40..48].copy_from_slice(&times.atime.to_le_bytes());
+    buf[48..56].copy_from_slice(&times.mtime.to_le_bytes());
+    buf[56..64].copy_from_slice(&times.mtime.to_le_bytes());
 
     // Write to memory
-    let memory = caller.get_export("memory").unwrap().into_memory().unwrap();
-    let mem = memory.data_mut(&mut caller);
-    let ptr = buf_ptr as usize;
-    if ptr + 64 > mem.len() {
-        debug!("wasi_fd_filestat_get: buffer out of bounds");
-        return Ok(21); // WASI_EFAULT
+    let memory = match guest_memory(&mut caller) {
+        Ok(mem) => mem,
+        Err(errno) => return Ok(errno as u32),
+    };
+    if let Err(errno) = write_slice(memory.data_mut(&mut caller), buf_ptr, &buf) {
+        return Ok(errno as u32);
     }
-    mem[ptr..ptr+64].copy_from_slice(&buf);
-    debug!("wasi_fd_filestat_get: wrote filestat to memory at offset {}", ptr);
+    debug!("wasi_fd_filestat_get: wrote filestat to memory at offset {}", buf_ptr);
     
     Ok(0)
 }
@@ -210,25 +428,23 @@ pub fn wasi_path_unlink_file(
     path_ptr: i32,
     path_len: i32,
 ) -> i32 {
-    use wasmtime::Extern;
     use log::error;
 
-    let memory = match caller.get_export("memory") {
-        Some(Extern::Memory(mem)) => mem,
-        _ => {
-            error!("path_unlink_file: Memory not found");
-            return 1;
-        }
+    if let Err(errno) = deny_if_read_only(caller.data(), "path_unlink_file") {
+        return errno;
+    }
+
+    let memory = match guest_memory(&mut caller) {
+        Ok(mem) => mem,
+        Err(errno) => return errno,
     };
 
     let data = memory.data(&caller);
-    let start = path_ptr as usize;
-    let end = start + (path_len as usize);
-    if end > data.len() {
-        error!("path_unlink_file: path out of bounds");
-        return 1;
-    }
-    let path_str = match std::str::from_utf8(&data[start..end]) {
+    let path_bytes = match read_slice(data, path_ptr as u32, path_len as u32 as usize) {
+        Ok(bytes) => bytes,
+        Err(errno) => return errno,
+    };
+    let path_str = match std::str::from_utf8(path_bytes) {
         Ok(s) => s,
         Err(_) => {
             error!("path_unlink_file: invalid UTF-8");
@@ -237,7 +453,30 @@ pub fn wasi_path_unlink_file(
     };
 
     let root_path = caller.data().root_path.clone();
-    let joined = root_path.join(path_str.trim_start_matches('/'));
+    let joined = resolve_guest_path(caller.data(), path_str);
+
+    // Emulated symlinks have no file behind them
+    if caller.data().symlinks.lock().unwrap().remove(&symlink_key(&joined)).is_some() {
+        return 0;
+    }
+
+    let removed = in_tmpfs(caller.data(), |tmpfs| {
+        let path = tmpfs.resolve(&joined)?;
+        Ok((tmpfs.remove_file(&path)?, path))
+    });
+    if let Some(removed) = removed {
+        return match removed {
+            Ok((file_size, path)) => {
+                usage_sub(&mut caller, file_size);
+                caller.data().file_times.lock().unwrap().remove(path.to_string_lossy().as_ref());
+                0
+            }
+            Err(e) => {
+                error!("path_unlink_file: failed to unlink: {}", e);
+                io_err_to_wasi_errno(&e)
+            }
+        };
+    }
     
     // Canonicalize paths for security check
     let canonical_root = match root_path.canonicalize() {
@@ -270,11 +509,14 @@ pub fn wasi_path_unlink_file(
         }
     };
 
+    invalidate_cached(caller.data(), &canonical);
+
     // remove the file
     match fs::remove_file(&canonical) {
         Ok(_) => {
             // Decrement usage
             usage_sub(&mut caller, file_size);
+            caller.data().file_times.lock().unwrap().remove(canonical.to_string_lossy().as_ref());
             0
         }
         Err(e) => {
@@ -290,25 +532,23 @@ pub fn wasi_path_remove_directory(
     path_ptr: i32,
     path_len: i32,
 ) -> i32 {
-    use wasmtime::Extern;
     use log::error;
 
-    let memory = match caller.get_export("memory") {
-        Some(Extern::Memory(mem)) => mem,
-        _ => {
-            error!("path_remove_directory: Memory not found");
-            return 1;
-        }
+    if let Err(errno) = deny_if_read_only(caller.data(), "path_remove_directory") {
+        return errno;
+    }
+
+    let memory = match guest_memory(&mut caller) {
+        Ok(mem) => mem,
+        Err(errno) => return errno,
     };
 
     let data = memory.data(&caller);
-    let start = path_ptr as usize;
-    let end = start + (path_len as usize);
-    if end > data.len() {
-        error!("path_remove_directory: path out of bounds");
-        return 1;
-    }
-    let path_str = match std::str::from_utf8(&data[start..end]) {
+    let path_bytes = match read_slice(data, path_ptr as u32, path_len as u32 as usize) {
+        Ok(bytes) => bytes,
+        Err(errno) => return errno,
+    };
+    let path_str = match std::str::from_utf8(path_bytes) {
         Ok(s) => s,
         Err(_) => {
             error!("path_remove_directory: invalid UTF-8");
@@ -317,7 +557,24 @@ pub fn wasi_path_remove_directory(
     };
 
     let root_path = caller.data().root_path.clone();
-    let joined = root_path.join(path_str.trim_start_matches('/'));
+    let joined = resolve_guest_path(caller.data(), path_str);
+
+    // rmdir does not follow a link, and a link is not a directory
+    if caller.data().symlinks.lock().unwrap().contains_key(&symlink_key(&joined)) {
+        error!("path_remove_directory: {} is a symlink", path_str);
+        return 54; // ENOTDIR
+    }
+
+    // An empty directory holds no bytes, so usage stays as it was
+    if let Some(removed) = in_tmpfs(caller.data(), |tmpfs| tmpfs.remove_dir(&joined)) {
+        return match removed {
+            Ok(()) => 0,
+            Err(e) => {
+                error!("path_remove_directory: failed: {}", e);
+                io_err_to_wasi_errno(&e)
+            }
+        };
+    }
     
     // Canonicalize paths for security check
     let canonical_root = match root_path.canonicalize() {
@@ -370,25 +627,23 @@ pub fn wasi_path_create_directory(
     path_ptr: i32,
     path_len: i32,
 ) -> i32 {
-    use wasmtime::Extern;
     use log::error;
 
-    let memory = match caller.get_export("memory") {
-        Some(Extern::Memory(mem)) => mem,
-        _ => {
-            error!("path_create_directory: Memory not found");
-            return 1;
-        }
+    if let Err(errno) = deny_if_read_only(caller.data(), "path_create_directory") {
+        return errno;
+    }
+
+    let memory = match guest_memory(&mut caller) {
+        Ok(mem) => mem,
+        Err(errno) => return errno,
     };
 
     let data = memory.data(&caller);
-    let start = path_ptr as usize;
-    let end = start + (path_len as usize);
-    if end > data.len() {
-        error!("path_create_directory: path out of bounds");
-        return 1;
-    }
-    let path_str = match std::str::from_utf8(&data[start..end]) {
+    let path_bytes = match read_slice(data, path_ptr as u32, path_len as u32 as usize) {
+        Ok(bytes) => bytes,
+        Err(errno) => return errno,
+    };
+    let path_str = match std::str::from_utf8(path_bytes) {
         Ok(s) => s,
         Err(_) => {
             error!("path_create_directory: invalid UTF-8");
@@ -398,8 +653,22 @@ pub fn wasi_path_create_directory(
 
     let root_path = caller.data().root_path.clone();
     
-    // Join the requested path to the root path
-    let joined = root_path.join(path_str.trim_start_matches('/'));
+    // Join the requested path to the working directory
+    let joined = resolve_guest_path(caller.data(), path_str);
+
+    // Charged like a directory on the host, which takes a 4096-byte block
+    if let Some(created) = in_tmpfs(caller.data(), |tmpfs| tmpfs.create_dir(&joined)) {
+        return match created {
+            Ok(()) => match usage_add(&mut caller, 4096) {
+                Ok(()) => 0,
+                Err(errno) => errno,
+            },
+            Err(e) => {
+                error!("path_create_directory: failed: {}", e);
+                io_err_to_wasi_errno(&e)
+            }
+        };
+    }
     
     // For security check, we need to canonicalize existing paths or ensure joined path is valid
     // First, check if the parent of joined exists and can be canonicalized
@@ -460,20 +729,90 @@ pub fn wasi_path_create_directory(
     }
 }
 
-/// Implementation of the symbolic link syscall.
-/// Since symlink is not supported, we simply log an error,
-/// mark the process as Finished, notify the scheduler,
-/// and then loop indefinitely until the scheduler joins the thread.
+/// Creates an emulated symbolic link at `new_path` pointing at `old_path`.
+/// A host symlink could point out of the sandbox, so links only exist in the
+/// process's `symlinks` map: `path_readlink` reads them back, `path_open`
+/// follows them and `path_unlink_file` removes them. The target is kept as
+/// the guest wrote it and only checked against the sandbox root when followed.
+///
+/// Returns 0, or an errno: 13 (EACCES) for a read-only root or a link outside
+/// the sandbox, 20 (EEXIST) if the link's name is taken, 2 (ENOENT) for an
+/// empty target or a missing directory.
 pub fn wasi_path_symlink(
-    caller: Caller<'_, ProcessData>,
-    _old_path_ptr: i32,
-    _old_path_len: i32,
+    mut caller: Caller<'_, ProcessData>,
+    old_path_ptr: i32,
+    old_path_len: i32,
     _new_dirfd: i32,
-    _new_path_ptr: i32,
-    _new_path_len: i32,
+    new_path_ptr: i32,
+    new_path_len: i32,
 ) -> i32 {
-    eprintln!("path_symlink: not yet implemented");
-    return WASI_ERRNO_NOSYS;
+    if let Err(errno) = deny_if_read_only(caller.data(), "path_symlink") {
+        return errno;
+    }
+    let (target, link_path) = {
+        let memory = match guest_memory(&mut caller) {
+            Ok(mem) => mem,
+            Err(errno) => return errno,
+        };
+        let data = memory.data(&caller);
+        let read_path = |ptr: i32, len: i32| -> Result<String, i32> {
+            let bytes = read_slice(data, ptr as u32, len as u32 as usize)?;
+            std::str::from_utf8(bytes).map(str::to_owned).map_err(|_| 1)
+        };
+        match (read_path(old_path_ptr, old_path_len), read_path(new_path_ptr, new_path_len)) {
+            (Ok(target), Ok(link)) => (target, link),
+            (Err(errno), _) | (_, Err(errno)) => return errno,
+        }
+    };
+    if target.is_empty() {
+        return 2;
+    }
+
+    let link = resolve_guest_path(caller.data(), &link_path);
+    let link_dir = in_tmpfs(caller.data(), |tmpfs| {
+        let exists = tmpfs.stat(&link).is_ok();
+        match tmpfs.stat(link.parent().unwrap_or(&link))? {
+            TmpfsEntry::Directory => Ok(exists),
+            TmpfsEntry::File { .. } => Err(io::ErrorKind::NotADirectory.into()),
+        }
+    });
+    if let Some(link_dir) = link_dir {
+        let key = symlink_key(&link);
+        let mut links = caller.data().symlinks.lock().unwrap();
+        return match link_dir {
+            Ok(exists) if exists || links.contains_key(&key) => WASI_ERRNO_EXIST,
+            Ok(_) => {
+                links.insert(key, PathBuf::from(target));
+                0
+            }
+            Err(e) => io_err_to_wasi_errno(&e),
+        };
+    }
+    let canonical_root = match caller.data().root_path.canonicalize() {
+        Ok(c) => c,
+        Err(e) => return io_err_to_wasi_errno(&e),
+    };
+    let parent = match link.parent() {
+        Some(parent) => parent,
+        None => return 1,
+    };
+    match parent.canonicalize() {
+        Ok(parent) if parent.starts_with(&canonical_root) => {}
+        Ok(_) => {
+            error!("path_symlink: attempt to escape sandbox root!");
+            return WASI_ERRNO_ACCES;
+        }
+        Err(e) => return io_err_to_wasi_errno(&e),
+    }
+
+    let key = symlink_key(&link);
+    let mut links = caller.data().symlinks.lock().unwrap();
+    if links.contains_key(&key) || fs::symlink_metadata(&link).is_ok() {
+        return WASI_ERRNO_EXIST;
+    }
+    debug!("path_symlink: process {} linked {} to {}", caller.data().id, link_path, target);
+    links.insert(key, PathBuf::from(target));
+    0
 }
 
 
@@ -494,16 +833,17 @@ pub fn wasi_fd_close(caller: Caller<'_, ProcessData>, fd: i32) -> i32 {
 ///
 /// This version ensures that all file operations are restricted to the
 /// process's `root_path`.
+#[allow(clippy::too_many_arguments)]
 pub fn wasi_path_open(
     mut caller: Caller<'_, ProcessData>,
     _dirfd: i32,      // not used in this simplified implementation
-    _dirflags: i32,   // not used
+    dirflags: i32,    // only SYMLINK_FOLLOW, for emulated symlinks
     path_ptr: i32,
     path_len: i32,
     oflags: i32,
-    _fs_rights_base: i64,
+    fs_rights_base: i64,
     _fs_rights_inheriting: i64,
-    _fdflags: i32,
+    fdflags: i32,
     opened_fd_out: i32,
 ) -> i32 {
     println!(
@@ -512,21 +852,16 @@ pub fn wasi_path_open(
     );
 
     // 1) Extract path string from WASM memory.
-    let memory = match caller.get_export("memory") {
-        Some(wasmtime::Extern::Memory(mem)) => mem,
-        _ => {
-            eprintln!("path_open: no memory export found");
-            return 1;
-        }
+    let memory = match guest_memory(&mut caller) {
+        Ok(mem) => mem,
+        Err(errno) => return errno,
     };
     let mem_data = memory.data(&caller);
-    let start = path_ptr as usize;
-    let end = start + (path_len as usize);
-    if end > mem_data.len() {
-        eprintln!("path_open: path out of bounds");
-        return 1;
-    }
-    let path_str = match std::str::from_utf8(&mem_data[start..end]) {
+    let path_bytes = match read_slice(mem_data, path_ptr as u32, path_len as u32 as usize) {
+        Ok(bytes) => bytes,
+        Err(errno) => return errno,
+    };
+    let path_str = match std::str::from_utf8(path_bytes) {
         Ok(s) => s.trim(),  // Trim whitespace and newlines
         Err(_) => {
             eprintln!("path_open: invalid UTF-8");
@@ -535,11 +870,56 @@ pub fn wasi_path_open(
     };
     println!("path_open: requested path: '{}'", path_str);
 
+    let wants_write = oflags & (WASI_OFLAGS_CREAT | WASI_OFLAGS_TRUNC) != 0
+        || fs_rights_base & WASI_RIGHTS_FD_WRITE != 0
+        || fdflags & WASI_FDFLAGS_APPEND != 0;
+    if wants_write {
+        if let Err(errno) = deny_if_read_only(caller.data(), "path_open") {
+            return errno;
+        }
+    }
+
     // 2) Get sandbox (fake root) from ProcessData.
     let root_path = caller.data().root_path.clone();
 
-    // 3) Join relative path to fake root.
-    let joined_path = root_path.join(path_str.trim_start_matches('/'));
+    // 3) Join relative path to the working directory inside the fake root,
+    // and follow emulated symlinks if the lookup flags ask for it.
+    let joined_path = resolve_guest_path(caller.data(), path_str);
+    let joined_path = if dirflags & WASI_LOOKUPFLAGS_SYMLINK_FOLLOW != 0 {
+        match follow_symlinks(caller.data(), &joined_path) {
+            Ok(path) => path,
+            Err(errno) => return errno,
+        }
+    } else if caller.data().symlinks.lock().unwrap().contains_key(&symlink_key(&joined_path)) {
+        eprintln!("path_open: '{}' is a symlink and SYMLINK_FOLLOW is not set", path_str);
+        return WASI_ERRNO_LOOP;
+    } else {
+        joined_path
+    };
+    let append = fdflags & WASI_FDFLAGS_APPEND != 0;
+    let nonblocking = fdflags & WASI_FDFLAGS_NONBLOCK != 0;
+    if let Some(tmpfs) = caller.data().tmpfs.clone() {
+        let is_readable = fs_rights_base & WASI_RIGHTS_FD_READ != 0;
+        return match open_in_tmpfs(&mut caller, &tmpfs, &joined_path, oflags, is_readable) {
+            Ok((host_path, is_dir, file_data)) => {
+                let times = logical_times(caller.data(), &host_path);
+                let entry = FDEntry::File {
+                    buffer: file_data,
+                    read_ptr: 0,
+                    write_ptr: 0,
+                    is_directory: is_dir,
+                    is_preopen: false,
+                    host_path: Some(host_path),
+                    flush_threshold: None,
+                    append,
+                    nonblocking,
+                    times,
+                };
+                install_fd(&mut caller, entry, opened_fd_out)
+            }
+            Err(errno) => errno,
+        };
+    }
     
     // 4) Security check: ensure the path is inside the fake root.
     // Canonicalize the root path
@@ -600,11 +980,33 @@ pub fn wasi_path_open(
         return 13;
     }
 
-    // 5) Get metadata or create file if it does not exist and O_CREAT is set.
-    // Let's assume that O_CREAT is indicated by bit 0x1.
-    let o_creat = (oflags & 1) != 0;
-    let is_readable = (oflags & 0x1) == 0; // O_RDONLY or O_RDWR
-    let _is_writable = (oflags & 0x2) != 0; // O_WRONLY or O_RDWR
+    // 5) Apply the oflags to an existing file, then get metadata or create
+    // the file if it does not exist and O_CREAT is set. Only FDs with the
+    // read right get the file's contents.
+    let o_creat = oflags & WASI_OFLAGS_CREAT != 0;
+    let is_readable = fs_rights_base & WASI_RIGHTS_FD_READ != 0;
+    if let Ok(md) = fs::metadata(&canonical) {
+        if o_creat && oflags & WASI_OFLAGS_EXCL != 0 {
+            eprintln!("path_open: '{}' exists and O_EXCL is set", path_str);
+            return WASI_ERRNO_EXIST;
+        }
+        if oflags & WASI_OFLAGS_DIRECTORY != 0 && !md.is_dir() {
+            eprintln!("path_open: '{}' is not a directory", path_str);
+            return WASI_ERRNO_NOTDIR;
+        }
+        if oflags & WASI_OFLAGS_TRUNC != 0 && md.is_file() {
+            invalidate_cached(caller.data(), &canonical);
+            if let Err(e) = OpenOptions::new().write(true).truncate(true).open(&canonical) {
+                eprintln!("path_open: failed to truncate '{}': {}", path_str, e);
+                return io_err_to_wasi_errno(&e);
+            }
+            usage_sub(&mut caller, md.len());
+            touch(caller.data(), &canonical.to_string_lossy());
+        }
+    } else if oflags & WASI_OFLAGS_DIRECTORY != 0 {
+        // O_DIRECTORY never creates anything
+        return 2; // ENOENT, as io_err_to_wasi_errno reports NotFound
+    }
 
     let (is_dir, file_data) = match fs::metadata(&canonical) {
         Ok(md) => {
@@ -613,13 +1015,11 @@ pub fn wasi_path_open(
                 let mut buf = Vec::new();
                 match fs::read_dir(&canonical) {
                     Ok(entries) => {
-                        for entry_res in entries {
-                            if let Ok(dirent) = entry_res {
-                                let name = dirent.file_name();
-                                let name_str = name.to_string_lossy().into_owned();
-                                buf.extend_from_slice(name_str.as_bytes());
-                                buf.push(b'\n');
-                            }
+                        for dirent in entries.flatten() {
+                            let name = dirent.file_name();
+                            let name_str = name.to_string_lossy().into_owned();
+                            buf.extend_from_slice(name_str.as_bytes());
+                            buf.push(b'\n');
                         }
                     }
                     Err(e) => {
@@ -630,14 +1030,26 @@ pub fn wasi_path_open(
                 (true, buf)
             } else {
                 // It's a file: read file content if readable
-                let file_data = if is_readable {
+                let cached = if is_readable {
+                    caller.data().file_cache.as_ref().and_then(|c| c.lock().unwrap().get(&canonical))
+                } else {
+                    None
+                };
+                let file_data = if let Some(data) = cached {
+                    data
+                } else if is_readable {
                     match fs::read(&canonical) {
                         Ok(data) => {
+                            if let Some(cache) = &caller.data().file_cache {
+                                cache.lock().unwrap().insert(&canonical, &data);
+                            }
                             debug!("DEBUG: file_data.len() = {}", data.len());
                             debug!("DEBUG: host_path = {:?}", canonical);
                             if data.len() > 1_000_000 {
                                 debug!("path_open: File is large => blocking to simulate I/O wait");
-                                block_process_for_fileio(&mut caller);
+                                if !block_until_running(caller.data(), BlockReason::FileIO) {
+                                    return 29; // EIO
+                                }
                             }
                             data
                         },
@@ -665,10 +1077,13 @@ pub fn wasi_path_open(
                 match OpenOptions::new()
                     .write(true)
                     .create(true)
+                    .truncate(false)
                     .open(&canonical)
                 {
                     Ok(_f) => {
                         // File is now created (empty).
+                        let created = canonical.canonicalize().unwrap_or_else(|_| canonical.clone());
+                        touch(caller.data(), &created.to_string_lossy());
                         let file_data = if is_readable {
                             fs::read(&canonical).unwrap_or_default()
                         } else {
@@ -690,34 +1105,95 @@ pub fn wasi_path_open(
         }
     };
 
-    // 6) Allocate a new FD and store the buffer.
+    // 6) Allocate a new FD and store the buffer. A file created above only
+    // canonicalizes now that it exists.
+    let host_path = canonical.canonicalize().unwrap_or(canonical).to_string_lossy().into_owned();
+    let times = logical_times(caller.data(), &host_path);
+    let entry = FDEntry::File {
+        buffer: file_data,
+        read_ptr: 0,
+        write_ptr: 0,
+        is_directory: is_dir,
+        is_preopen: false,
+        host_path: Some(host_path),
+        flush_threshold: None,
+        append,
+        nonblocking,
+        times,
+    };
+    install_fd(&mut caller, entry, opened_fd_out)
+}
+
+/// Steps 4 and 5 of `path_open` for a process with a tmpfs: applies the
+/// oflags to the file at `path` in memory, creating it for O_CREAT, and
+/// returns its key, whether it is a directory, and what reads through the
+/// new FD see. That is the file's contents if `is_readable`, or for a
+/// directory its names, one per line, as on the host.
+fn open_in_tmpfs(
+    caller: &mut Caller<'_, ProcessData>,
+    tmpfs: &Mutex<Tmpfs>,
+    path: &Path,
+    oflags: i32,
+    is_readable: bool,
+) -> Result<(String, bool, Vec<u8>), i32> {
+    let errno = |e: io::Error| io_err_to_wasi_errno(&e);
+    let path = tmpfs.lock().unwrap().resolve(path).map_err(errno)?;
+    let host_path = path.to_string_lossy().into_owned();
+    let found = tmpfs.lock().unwrap().stat(&path);
+    match found {
+        Ok(_) if oflags & WASI_OFLAGS_CREAT != 0 && oflags & WASI_OFLAGS_EXCL != 0 => return Err(WASI_ERRNO_EXIST),
+        Ok(TmpfsEntry::File { .. }) if oflags & WASI_OFLAGS_DIRECTORY != 0 => return Err(WASI_ERRNO_NOTDIR),
+        Ok(TmpfsEntry::File { len }) if oflags & WASI_OFLAGS_TRUNC != 0 => {
+            tmpfs.lock().unwrap().set_len(&path, 0).map_err(errno)?;
+            usage_sub(caller, len);
+            touch(caller.data(), &host_path);
+        }
+        Ok(_) => {}
+        Err(e) if e.kind() == io::ErrorKind::NotFound && oflags & WASI_OFLAGS_CREAT != 0 && oflags & WASI_OFLAGS_DIRECTORY == 0 => {
+            // Charged like a new file on the host
+            let metadata_size: u64 = 4096;
+            usage_add(caller, metadata_size)?;
+            let created = tmpfs.lock().unwrap().create(&path);
+            if let Err(e) = created {
+                usage_sub(caller, metadata_size);
+                return Err(errno(e));
+            }
+            touch(caller.data(), &host_path);
+        }
+        Err(e) => return Err(errno(e)),
+    }
+    let tmpfs = tmpfs.lock().unwrap();
+    let (is_dir, file_data) = match tmpfs.stat(&path).map_err(errno)? {
+        TmpfsEntry::Directory => {
+            let names = tmpfs.list(&path).map_err(errno)?;
+            (true, names.iter().flat_map(|name| name.bytes().chain([b'\n'])).collect())
+        }
+        TmpfsEntry::File { .. } if is_readable => (false, tmpfs.read(&path).map_err(errno)?),
+        TmpfsEntry::File { .. } => (false, Vec::new()),
+    };
+    Ok((host_path, is_dir, file_data))
+}
+
+/// Puts `entry` in a free FD and writes its number to `opened_fd_out`.
+fn install_fd(caller: &mut Caller<'_, ProcessData>, entry: FDEntry, opened_fd_out: i32) -> i32 {
     let fd = {
-        let pd = caller.data();
-        let mut table = pd.fd_table.lock().unwrap();
+        let mut table = caller.data().fd_table.lock().unwrap();
         let fd = table.allocate_fd();
         if fd < 0 {
             eprintln!("path_open: No free FD available!");
             return 76;
         }
-        table.entries[fd as usize] = Some(FDEntry::File {
-            buffer: file_data,
-            read_ptr: 0,
-            is_directory: is_dir,
-            is_preopen: false,
-            host_path: Some(canonical.to_string_lossy().into_owned()),
-        });
+        table.entries[fd as usize] = Some(entry);
         fd
     };
 
-    // 7) Write the FD back to WASM memory.
-    {
-        let mem_mut = memory.data_mut(&mut caller);
-        let out_ptr = opened_fd_out as usize;
-        if out_ptr + 4 > mem_mut.len() {
-            eprintln!("path_open: opened_fd_out out of bounds");
-            return 1;
-        }
-        mem_mut[out_ptr..out_ptr + 4].copy_from_slice(&(fd as u32).to_le_bytes());
+    // Write the FD back to WASM memory.
+    let memory = match guest_memory(caller) {
+        Ok(mem) => mem,
+        Err(errno) => return errno,
+    };
+    if let Err(errno) = write_slice(memory.data_mut(caller), opened_fd_out as u32, &(fd as u32).to_le_bytes()) {
+        return errno;
     }
 
     println!("path_open: success, new FD = {}", fd);
@@ -772,22 +1248,13 @@ pub fn wasi_fd_readdir(
 
     // 3) Write that many bytes into the Wasm memory
     {
-        let memory = match caller.get_export("memory") {
-            Some(wasmtime::Extern::Memory(mem)) => mem,
-            _ => {
-                eprintln!("fd_readdir: no memory export found");
-                return 1;
-            }
+        let memory = match guest_memory(&mut caller) {
+            Ok(mem) => mem,
+            Err(errno) => return errno,
         };
-        let mem_mut = memory.data_mut(&mut caller);
-
-        let buf_start = buf as usize;
-        let buf_end = buf_start + n_to_copy;
-        if buf_end > mem_mut.len() {
-            eprintln!("fd_readdir: buf out of bounds");
-            return 1;
+        if let Err(errno) = write_slice(memory.data_mut(&mut caller), buf as u32, &data_to_read[..n_to_copy]) {
+            return errno;
         }
-        mem_mut[buf_start..buf_end].copy_from_slice(&data_to_read[..n_to_copy]);
     }
 
     // 4) Update the read_ptr in FD table in a separate scope
@@ -812,36 +1279,26 @@ pub fn wasi_fd_write(
     nwritten: i32,
 ) -> i32 {
     use std::cmp::min;
-    use std::convert::TryInto;
     use std::io::Write;
     
-    let memory = match caller.get_export("memory") {
-        Some(wasmtime::Extern::Memory(mem)) => mem,
-        _ => {
-            error!("fd_write: Failed to find memory export");
-            return 1;
-        }
+    let memory = match guest_memory(&mut caller) {
+        Ok(mem) => mem,
+        Err(errno) => return errno,
     };
     
     // Gather data to write.
     let data_to_write = {
         let data = memory.data(&caller);
+        let iovecs = match read_iovecs(data, iovs as u32, iovs_len as u32) {
+            Ok(iovecs) => iovecs,
+            Err(errno) => return errno,
+        };
         let mut buf = Vec::new();
-        for i in 0..iovs_len {
-            let iovec_addr = (iovs as usize) + (i as usize) * 8;
-            if iovec_addr + 8 > data.len() {
-                error!("fd_write: iovec out of bounds");
-                return 1;
-            }
-            let offset_bytes: [u8; 4] = data[iovec_addr..iovec_addr + 4].try_into().unwrap();
-            let len_bytes: [u8; 4] = data[iovec_addr + 4..iovec_addr + 8].try_into().unwrap();
-            let offset = u32::from_le_bytes(offset_bytes) as usize;
-            let len = u32::from_le_bytes(len_bytes) as usize;
-            if offset + len > data.len() {
-                error!("fd_write: data slice out of bounds");
-                return 1;
+        for (offset, len) in iovecs {
+            match read_slice(data, offset, len as usize) {
+                Ok(bytes) => buf.extend_from_slice(bytes),
+                Err(errno) => return errno,
             }
-            buf.extend_from_slice(&data[offset..offset + len]);
         }
         buf
     };
@@ -859,50 +1316,72 @@ pub fn wasi_fd_write(
             .map(|_| data_to_write.len())
             .map_err(|e| io_err_to_wasi_errno(&e))
     } else {
-        // For sandbox file writes, look up the host path.
+        // For sandbox file writes, look up the host path, how much to
+        // buffer before flushing and where the writes go (None appends).
         let host_path_opt = {
             let pd = caller.data();
             let table = pd.fd_table.lock().unwrap();
             match table.entries.get(fd as usize) {
-                Some(Some(FDEntry::File { host_path, is_directory, .. })) if host_path.is_some() && !is_directory => {
-                    host_path.clone()
+                Some(Some(FDEntry::File { host_path, is_directory, flush_threshold, append, write_ptr, .. })) if host_path.is_some() && !is_directory => {
+                    let at = if *append { None } else { Some(*write_ptr as u64) };
+                    host_path.clone().map(|path| (path, flush_threshold.unwrap_or(pd.max_write_buffer), at))
                 }
                 _ => None,
             }
         };
     
-        if let Some(host_path) = host_path_opt {
-            // Account for the total bytes.
-            if let Err(errno) = usage_add(&mut caller, data_to_write.len() as u64) {
+        if let Some((host_path, max_write_buffer, mut at)) = host_path_opt {
+            if let Err(errno) = deny_if_read_only(caller.data(), "fd_write") {
                 return errno;
             }
+            // An earlier write left bytes buffered: this one adds to them if
+            // it continues where they end, else they are written out first.
+            let buffered = {
+                let target = caller.data().write_target.lock().unwrap();
+                let pending = caller.data().write_buffer.lock().unwrap().len() as u64;
+                match &*target {
+                    Some((path, start)) if *path == host_path && start.map(|start| start + pending) == at => Some((*start, pending)),
+                    _ => None,
+                }
+            };
+            if buffered.is_none() {
+                if let Err(errno) = flush_pending_write(caller.data()) {
+                    return errno;
+                }
+            }
+            // Only what the file grows by counts, and the flushes charge it
+            // as it reaches the file; here it is only checked against the quota.
             let total = data_to_write.len();
+            let size = file_len(caller.data(), Path::new(&host_path)).unwrap_or(0);
+            let appended = match buffered {
+                Some((None, pending)) => pending,
+                _ => 0,
+            };
+            let end = at.unwrap_or(size + appended) + total as u64;
+            if let Err(errno) = usage_check(caller.data(), end.saturating_sub(size)) {
+                return errno;
+            }
+            if let Some((start, _)) = buffered {
+                caller.data().write_target.lock().unwrap().take();
+                at = start;
+            }
             let mut offset = 0;
             while offset < total {
                 // Check free capacity.
                 let available = {
                     let write_buf = caller.data().write_buffer.lock().unwrap();
-                    caller.data().max_write_buffer.saturating_sub(write_buf.len())
+                    max_write_buffer.saturating_sub(write_buf.len())
                 };
     
                 if available == 0 {
-                    // Buffer is full and there is still data to write.
-                    {
-                        let mut state = caller.data().state.lock().unwrap();
-                        *state = ProcessState::Blocked;
-                    }
-                    {
-                        let mut reason = caller.data().block_reason.lock().unwrap();
-                        // Save the host path in the block reason.
-                        *reason = Some(BlockReason::WriteIO(host_path.clone()));
-                    }
-                    caller.data().cond.notify_all();
-                    {
-                        let mut state = caller.data().state.lock().unwrap();
-                        while *state != ProcessState::Running {
-                            state = caller.data().cond.wait(state).unwrap();
-                        }
+                    // Buffer is full and there is still data to write. The
+                    // block reason carries the host path and offset the
+                    // scheduler flushes to.
+                    let pending = caller.data().write_buffer.lock().unwrap().len() as u64;
+                    if !block_until_running(caller.data(), BlockReason::WriteIO(host_path.clone(), at)) {
+                        return 29; // EIO
                     }
+                    at = at.map(|at| at + pending);
                     // Once unblocked (scheduler should flush), continue the loop.
                     continue;
                 } else {
@@ -914,40 +1393,36 @@ pub fn wasi_fd_write(
                     offset += chunk;
                     // After appending, if the buffer is full:
                     let current_size = { caller.data().write_buffer.lock().unwrap().len() };
-                    if current_size == caller.data().max_write_buffer {
+                    if current_size == max_write_buffer {
                         if offset < total {
                             // Buffer full with more data pending: block.
-                            {
-                                let mut state = caller.data().state.lock().unwrap();
-                                *state = ProcessState::Blocked;
-                            }
-                            {
-                                let mut reason = caller.data().block_reason.lock().unwrap();
-                                *reason = Some(BlockReason::WriteIO(host_path.clone()));
-                            }
-                            caller.data().cond.notify_all();
-                            {
-                                let mut state = caller.data().state.lock().unwrap();
-                                while *state != ProcessState::Running {
-                                    state = caller.data().cond.wait(state).unwrap();
-                                }
+                            if !block_until_running(caller.data(), BlockReason::WriteIO(host_path.clone(), at)) {
+                                return 29; // EIO
                             }
+                            at = at.map(|at| at + current_size as u64);
                             continue;
                         } else {
                             // Buffer full but no data remains: flush immediately.
-                            if let Err(errno) = flush_write_buffer(&mut caller, &host_path) {
-                                return errno;
+                            match flush_write_buffer(&mut caller, &host_path, at) {
+                                Ok(bytes) => at = at.map(|at| at + bytes as u64),
+                                Err(errno) => return errno,
                             }
                         }
                     }
                 }
             }
-            // Flush any remaining data.
-            if !caller.data().write_buffer.lock().unwrap().is_empty() {
-                if let Err(errno) = flush_write_buffer(&mut caller, &host_path) {
-                    return errno;
+            // What is left stays buffered, see `flush_pending_write`.
+            let left = caller.data().write_buffer.lock().unwrap().len();
+            if left > 0 {
+                *caller.data().write_target.lock().unwrap() = Some((host_path.clone(), at));
+            }
+            if let Some(at) = at {
+                let mut table = caller.data().fd_table.lock().unwrap();
+                if let Some(FDEntry::File { write_ptr, .. }) = table.get_fd_entry_mut(fd) {
+                    *write_ptr = (at + left as u64) as usize;
                 }
             }
+            touch(caller.data(), &host_path);
             Ok(total)
         } else {
             error!("fd_write: unsupported fd: {}", fd);
@@ -961,100 +1436,130 @@ pub fn wasi_fd_write(
     };
     
     // Write the number of bytes written into WASM memory.
-    {
-        let total_written_bytes = (bytes_written as u32).to_le_bytes();
-        let nwritten_ptr = nwritten as usize;
-        let mem_mut = memory.data_mut(&mut caller);
-        if nwritten_ptr + 4 > mem_mut.len() {
-            error!("fd_write: nwritten pointer out of bounds");
-            return 1;
-        }
-        mem_mut[nwritten_ptr..nwritten_ptr + 4].copy_from_slice(&total_written_bytes);
+    match write_slice(memory.data_mut(&mut caller), nwritten as u32, &(bytes_written as u32).to_le_bytes()) {
+        Ok(()) => 0,
+        Err(errno) => errno,
     }
-    0
 }
 
 
-/// Flush the process write buffer to the file at `host_path`.
-/// This writes out the entire buffer and then clears it.
+/// Drops `path` from the process file cache (if enabled) after it has been mutated.
+pub fn invalidate_cached(data: &ProcessData, path: &Path) {
+    if let Some(cache) = &data.file_cache {
+        cache.lock().unwrap().invalidate(path);
+    }
+}
+
+/// Flush the process write buffer to the file at `host_path`, starting at
+/// offset `at` (which may be past the end, growing the file) or appending
+/// if it is None. This writes out the entire buffer and then clears it.
 fn flush_write_buffer(
     caller: &mut Caller<'_, ProcessData>,
     host_path: &str,
+    at: Option<u64>,
 ) -> Result<usize, i32> {
-    let mut buf = caller.data().write_buffer.lock().unwrap();
-    if buf.is_empty() {
-        return Ok(0);
-    }
-    match OpenOptions::new().append(true).open(host_path) {
-        Ok(mut file) => {
-            if let Err(e) = file.write_all(&buf) {
-                error!("flush_write_buffer: failed to write to file {}: {}", host_path, e);
-                return Err(io_err_to_wasi_errno(&e));
-            }
-            let bytes = buf.len();
-            buf.clear();
-            Ok(bytes)
-        }
-        Err(e) => {
-            error!("flush_write_buffer: failed to open file {}: {}", host_path, e);
-            Err(io_err_to_wasi_errno(&e))
-        }
-    }
+    flush_write_buffer_for_scheduler(caller.data(), host_path, at)
+}
+
+
+/// Writes out what fd_write left in the process's write buffer, where it
+/// was headed. A write that does not fill the buffer stays there after
+/// fd_write returns, so that the next write can add to it, until the process
+/// makes a syscall that could see the file (any but those in
+/// `KEEPS_WRITES_BUFFERED`), syncs a file, or ends. Only the host's view of
+/// the file lags behind; the guest never sees it. If the bytes cannot be
+/// written they are dropped, and the errno returned.
+pub fn flush_pending_write(data: &ProcessData) -> Result<(), i32> {
+    let Some((host_path, at)) = data.write_target.lock().unwrap().take() else {
+        return Ok(());
+    };
+    flush_write_buffer_for_scheduler(data, &host_path, at).map(|_| ()).inspect_err(|_| {
+        data.write_buffer.lock().unwrap().clear();
+    })
 }
 
 
 /// flush_write_buffer_for_scheduler flushes all data currently stored in
 /// the process's write buffer (data is stored in an Arc<Mutex<Vec<u8>>> within ProcessData)
-/// by appending it to the file at the given host_path. It then clears the buffer.
+/// by writing it to the file at the given host_path, at offset `at` or appending
+/// if that is None. It then clears the buffer.
+/// The process's disk usage grows by what the file grew by, even if the
+/// write failed partway, so it always matches the bytes on disk.
 /// Returns the number of bytes flushed, or an errno on failure.
 pub fn flush_write_buffer_for_scheduler(
     data: &ProcessData,
     host_path: &str,
+    at: Option<u64>,
 ) -> Result<usize, i32> {
     let mut buf = data.write_buffer.lock().unwrap();
     if buf.is_empty() {
         return Ok(0);
     }
-    match OpenOptions::new().append(true).open(host_path) {
-        Ok(mut file) => {
-            if let Err(e) = file.write_all(&buf) {
-                error!("flush_write_buffer_for_scheduler: failed to write to file {}: {}", host_path, e);
-                return Err(io_err_to_wasi_errno(&e));
-            }
+    invalidate_cached(data, Path::new(host_path));
+    let size = file_len(data, Path::new(host_path)).unwrap_or(0);
+    let written = write_out(data, host_path, at, &buf);
+    let grown = file_len(data, Path::new(host_path)).unwrap_or(size).saturating_sub(size);
+    {
+        let mut usage = data.current_disk_usage.lock().unwrap();
+        *usage = usage.saturating_add(grown);
+    }
+    match written {
+        Ok(()) => {
             let bytes = buf.len();
             buf.clear();
             Ok(bytes)
         }
         Err(e) => {
-            error!("flush_write_buffer_for_scheduler: failed to open file {}: {}", host_path, e);
+            error!("flush_write_buffer_for_scheduler: failed to write to file {}: {}", host_path, e);
             Err(io_err_to_wasi_errno(&e))
         }
     }
 }
 
 
+/// Writes `buf` to the file at `host_path` as a flush does: at offset `at`,
+/// or at the end if it is None. A process with a tmpfs writes it in memory.
+fn write_out(data: &ProcessData, host_path: &str, at: Option<u64>, buf: &[u8]) -> io::Result<()> {
+    match in_tmpfs(data, |tmpfs| tmpfs.write_at(Path::new(host_path), at, buf)) {
+        Some(written) => written,
+        None => open_for_flush(host_path, at)?.write_all(buf),
+    }
+}
+
+/// Opens `host_path` for a flush: positioned at `at`, or for appending.
+fn open_for_flush(host_path: &str, at: Option<u64>) -> io::Result<fs::File> {
+    match at {
+        Some(at) => {
+            let mut file = OpenOptions::new().write(true).open(host_path)?;
+            file.seek(SeekFrom::Start(at))?;
+            Ok(file)
+        }
+        None => OpenOptions::new().append(true).open(host_path),
+    }
+}
+
+
 pub fn wasi_file_create(
     mut caller: Caller<'_, ProcessData>,
     path_ptr: i32,
     path_len: i32,
     opened_fd_out: i32,
 ) -> i32 {
-    let memory = match caller.get_export("memory") {
-        Some(Extern::Memory(mem)) => mem,
-        _ => {
-            error!("file_create: No memory export found");
-            return 1;
-        }
+    if let Err(errno) = deny_if_read_only(caller.data(), "file_create") {
+        return errno;
+    }
+
+    let memory = match guest_memory(&mut caller) {
+        Ok(mem) => mem,
+        Err(errno) => return errno,
     };
 
     let mem_data = memory.data(&caller);
-    let start = path_ptr as usize;
-    let end = start + (path_len as usize);
-    if end > mem_data.len() {
-        error!("file_create: path out of bounds");
-        return 1;
-    }
-    let path_str = match std::str::from_utf8(&mem_data[start..end]) {
+    let path_bytes = match read_slice(mem_data, path_ptr as u32, path_len as u32 as usize) {
+        Ok(bytes) => bytes,
+        Err(errno) => return errno,
+    };
+    let path_str = match std::str::from_utf8(path_bytes) {
         Ok(s) => s,
         Err(_) => {
             error!("file_create: invalid UTF-8");
@@ -1064,95 +1569,100 @@ pub fn wasi_file_create(
 
     // Build the full path inside the sandbox.
     let root_path = caller.data().root_path.clone();
-    let joined_path = root_path.join(path_str.trim_start_matches('/'));
+    let joined_path = resolve_guest_path(caller.data(), path_str);
 
-    // Security check: ensure the parent directory is inside the sandbox.
-    let parent = joined_path.parent().unwrap_or(&joined_path);
-    let canonical_parent = match parent.canonicalize() {
-        Ok(c) => c,
-        Err(e) => {
-            error!("file_create: failed to canonicalize parent: {}", e);
-            return io_err_to_wasi_errno(&e);
+    // Create the new file, failing if the path exists.
+    let created = match in_tmpfs(caller.data(), |tmpfs| {
+        if tmpfs.stat(&joined_path).is_ok() {
+            return Err(io::ErrorKind::AlreadyExists.into());
         }
-    };
-    let canonical_root = match root_path.canonicalize() {
-        Ok(c) => c,
-        Err(e) => {
-            error!("file_create: failed to canonicalize root: {}", e);
-            return io_err_to_wasi_errno(&e);
-        }
-    };
-    if !canonical_parent.starts_with(&canonical_root) {
-        error!("file_create: attempt to escape sandbox root");
-        return 13;
-    }
-
-    // Create the new file; use create_new(true) to fail if the file exists.
-    match OpenOptions::new().write(true).create_new(true).open(&joined_path) {
-        Ok(_file) => {
-            // Retrieve metadata size (or use a fallback overhead, e.g. 4096 bytes).
-            let metadata_size = match fs::metadata(&joined_path) {
-                Ok(md) => md.len(),
-                Err(_) => 4096,
-            };
-            // Update disk usage with the metadata overhead.
-            if let Err(errno) = usage_add(&mut caller, metadata_size) {
-                return errno;
-            }
-            // Allocate a new FD.
-            let fd = {
-                let pd = caller.data();
-                let mut table = pd.fd_table.lock().unwrap();
-                let fd = table.allocate_fd();
-                if fd < 0 {
-                    error!("file_create: No free FD available!");
-                    return 76;
+        tmpfs.create(&joined_path)?;
+        // A tmpfs file has no metadata on disk; charge the usual 4096 bytes.
+        Ok((tmpfs.resolve(&joined_path)?, 4096))
+    }) {
+        Some(created) => created,
+        None => {
+            // Security check: ensure the parent directory is inside the sandbox.
+            let parent = joined_path.parent().unwrap_or(&joined_path);
+            let canonical_parent = match parent.canonicalize() {
+                Ok(c) => c,
+                Err(e) => {
+                    error!("file_create: failed to canonicalize parent: {}", e);
+                    return io_err_to_wasi_errno(&e);
                 }
-                table.entries[fd as usize] = Some(FDEntry::File {
-                    buffer: Vec::new(),
-                    read_ptr: 0,
-                    is_directory: false,
-                    is_preopen: false,
-                    host_path: Some(joined_path.to_string_lossy().into_owned()),
-                });
-                fd
             };
-
-            // Write the new FD back into WASM memory.
-            {
-                let mem_mut = memory.data_mut(&mut caller);
-                let out_ptr = opened_fd_out as usize;
-                if out_ptr + 4 > mem_mut.len() {
-                    error!("file_create: opened_fd_out pointer out of bounds");
-                    return 1;
+            let canonical_root = match root_path.canonicalize() {
+                Ok(c) => c,
+                Err(e) => {
+                    error!("file_create: failed to canonicalize root: {}", e);
+                    return io_err_to_wasi_errno(&e);
                 }
-                mem_mut[out_ptr..out_ptr + 4].copy_from_slice(&(fd as u32).to_le_bytes());
+            };
+            if !canonical_parent.starts_with(&canonical_root) {
+                error!("file_create: attempt to escape sandbox root");
+                return 13;
             }
-            0
+            OpenOptions::new().write(true).create_new(true).open(&joined_path).map(|_file| {
+                // Retrieve metadata size (or use a fallback overhead, e.g. 4096 bytes).
+                let metadata_size = fs::metadata(&joined_path).map_or(4096, |md| md.len());
+                (joined_path.canonicalize().unwrap_or_else(|_| joined_path.clone()), metadata_size)
+            })
         }
+    };
+    let (created, metadata_size) = match created {
+        Ok(created) => created,
         Err(e) => {
             error!("file_create: Failed to create file: {}", e);
-            io_err_to_wasi_errno(&e)
+            return io_err_to_wasi_errno(&e);
+        }
+    };
+    // Update disk usage with the metadata overhead.
+    if let Err(errno) = usage_add(&mut caller, metadata_size) {
+        return errno;
+    }
+    // Keyed like path_open's FDs, so the logical times are found by path.
+    let host_path = created.to_string_lossy().into_owned();
+    let times = touch(caller.data(), &host_path);
+    // Allocate a new FD.
+    let fd = {
+        let pd = caller.data();
+        let mut table = pd.fd_table.lock().unwrap();
+        let fd = table.allocate_fd();
+        if fd < 0 {
+            error!("file_create: No free FD available!");
+            return 76;
         }
+        table.entries[fd as usize] = Some(FDEntry::File {
+            buffer: Vec::new(),
+            read_ptr: 0,
+            write_ptr: 0,
+            is_directory: false,
+            is_preopen: false,
+            host_path: Some(host_path),
+            flush_threshold: None,
+            append: false,
+            nonblocking: false,
+            times,
+        });
+        fd
+    };
+
+    // Write the new FD back into WASM memory.
+    if let Err(errno) = write_slice(memory.data_mut(&mut caller), opened_fd_out as u32, &(fd as u32).to_le_bytes()) {
+        return errno;
     }
+    0
 }
 
 
 /// Utility to write the "bytes used" result into memory
 fn set_bufused(caller: &mut Caller<'_, ProcessData>, ptr: i32, value: u32) -> i32 {
-    let memory = match caller.get_export("memory") {
-        Some(wasmtime::Extern::Memory(mem)) => mem,
-        _ => {
-            eprintln!("fd_readdir: no memory export found (for bufused_out)");
-            return 1;
-        }
+    let memory = match guest_memory(caller) {
+        Ok(mem) => mem,
+        Err(errno) => return errno,
     };
-    let mem_mut = memory.data_mut(caller);
-    let out_ptr = ptr as usize;
-    if out_ptr + 4 > mem_mut.len() {
-        eprintln!("fd_readdir: bufused_out pointer out of bounds");
-        return 1;
+    if let Err(errno) = write_slice(memory.data_mut(caller), ptr as u32, &value.to_le_bytes()) {
+        return errno;
     }
-    mem_mut[out_ptr..out_ptr + 4].copy_from_slice(&value.to_le_bytes());
     0
 }
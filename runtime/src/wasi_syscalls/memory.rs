@@ -0,0 +1,121 @@
+use std::ops::Range;
+use anyhow::{bail, Result};
+use log::error;
+use wasmtime::{Caller, Extern, ExternType, Memory, Module};
+use crate::runtime::process::ProcessData;
+
+/// WASI errno for a pointer or length outside guest memory.
+pub const WASI_EFAULT: i32 = 21;
+
+/// Name of the linear memory WASI syscalls read and write, per the WASI ABI.
+pub const GUEST_MEMORY_EXPORT: &str = "memory";
+
+/// Checks at Init that syscalls will be able to find the guest's memory.
+///
+/// The module must define and export a non-shared memory named `memory`.
+/// Imported memories are rejected since the runtime never provides one, and
+/// shared memories are rejected because the threads proposal is unsupported:
+/// other threads could change the buffers a syscall is in the middle of
+/// reading. Modules with several memories (multi-memory) are rejected too,
+/// since syscalls only ever see the one exported as `memory` and a guest
+/// passing a pointer into another would read the wrong bytes.
+pub fn validate_module_memory(module: &Module) -> Result<()> {
+    for import in module.imports() {
+        if let ExternType::Memory(_) = import.ty() {
+            bail!(
+                "module imports memory {}::{}; guests must define and export their own \"{}\"",
+                import.module(), import.name(), GUEST_MEMORY_EXPORT
+            );
+        }
+    }
+
+    let mut guest_memory = None;
+    for export in module.exports() {
+        if let ExternType::Memory(ty) = export.ty() {
+            if ty.is_shared() {
+                bail!("module exports shared memory {:?}; the WebAssembly threads proposal is not supported", export.name());
+            }
+            if export.name() == GUEST_MEMORY_EXPORT {
+                guest_memory = Some(ty);
+            }
+        }
+    }
+
+    if guest_memory.is_none() {
+        bail!("module does not export a linear memory named \"{}\"", GUEST_MEMORY_EXPORT);
+    }
+    let memories = module.resources_required().num_memories;
+    if memories > 1 {
+        bail!("module defines {} memories; multi-memory is not supported, so define only \"{}\"", memories, GUEST_MEMORY_EXPORT);
+    }
+    Ok(())
+}
+
+/// Looks up the guest memory syscalls operate on. Modules are validated at
+/// Init, so this only fails for processes started some other way; every
+/// address is then unreachable, so the error is `WASI_EFAULT` like any other
+/// bad pointer.
+pub fn guest_memory(caller: &mut Caller<'_, ProcessData>) -> Result<Memory, i32> {
+    match caller.get_export(GUEST_MEMORY_EXPORT) {
+        Some(Extern::Memory(mem)) => Ok(mem),
+        Some(Extern::SharedMemory(_)) => {
+            error!("Guest memory is shared; the threads proposal is not supported");
+            Err(WASI_EFAULT)
+        }
+        _ => {
+            error!("Guest module has no exported \"{}\"", GUEST_MEMORY_EXPORT);
+            Err(WASI_EFAULT)
+        }
+    }
+}
+
+/// The byte range `[ptr, ptr + len)` if it lies entirely within `mem`.
+/// The end is computed with `checked_add`, so huge lengths cannot wrap around
+/// and pass the bounds check.
+fn guest_range(mem: &[u8], ptr: u32, len: usize) -> Result<Range<usize>, i32> {
+    let start = ptr as usize;
+    match start.checked_add(len) {
+        Some(end) if end <= mem.len() => Ok(start..end),
+        _ => {
+            error!("Guest pointer {:#x} (+{} bytes) is outside linear memory ({} bytes)", ptr, len, mem.len());
+            Err(WASI_EFAULT)
+        }
+    }
+}
+
+/// Borrows `len` bytes of guest memory starting at `ptr`.
+pub fn read_slice(mem: &[u8], ptr: u32, len: usize) -> Result<&[u8], i32> {
+    guest_range(mem, ptr, len).map(|range| &mem[range])
+}
+
+/// Mutably borrows `len` bytes of guest memory starting at `ptr`.
+pub fn slice_mut(mem: &mut [u8], ptr: u32, len: usize) -> Result<&mut [u8], i32> {
+    guest_range(mem, ptr, len).map(move |range| &mut mem[range])
+}
+
+/// Copies `bytes` into guest memory at `ptr`.
+pub fn write_slice(mem: &mut [u8], ptr: u32, bytes: &[u8]) -> Result<(), i32> {
+    slice_mut(mem, ptr, bytes.len())?.copy_from_slice(bytes);
+    Ok(())
+}
+
+/// Reads a little-endian `u32` (e.g. an iovec field or an in/out length).
+pub fn read_u32(mem: &[u8], ptr: u32) -> Result<u32, i32> {
+    Ok(u32::from_le_bytes(read_slice(mem, ptr, 4)?.try_into().unwrap()))
+}
+
+/// Reads the `(buf, buf_len)` pairs of a WASI iovec array. Each buffer is
+/// bounds-checked here so callers can index memory with the results directly.
+pub fn read_iovecs(mem: &[u8], iovs: u32, iovs_len: u32) -> Result<Vec<(u32, u32)>, i32> {
+    let array_len = (iovs_len as usize).checked_mul(8).ok_or(WASI_EFAULT)?;
+    let array = read_slice(mem, iovs, array_len)?;
+    array
+        .chunks_exact(8)
+        .map(|iov| {
+            let buf = u32::from_le_bytes(iov[0..4].try_into().unwrap());
+            let buf_len = u32::from_le_bytes(iov[4..8].try_into().unwrap());
+            guest_range(mem, buf, buf_len as usize)?;
+            Ok((buf, buf_len))
+        })
+        .collect()
+}
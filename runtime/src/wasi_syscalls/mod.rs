@@ -1,85 +1,130 @@
 use anyhow::Result;
 use wasmtime::Linker;
 use crate::runtime::process::ProcessData;
+use syscall_counts::Counted;
 
 pub mod fd;
 pub mod fs;
 pub mod net;
 pub mod builtin_yield;
+pub mod builtin_report;
+pub mod builtin_seq;
+pub mod builtin_batch;
+pub mod builtin_atomic_replace;
+pub mod builtin_flush_threshold;
 pub mod args;
 pub mod clock;
 pub mod process;
 pub mod fd_ops;
 pub mod path_ops;
+pub mod memory;
+pub mod syscall_counts;
 
-pub fn register(linker: &mut Linker<ProcessData>) -> Result<()> {
+/// Syscalls that leave what fd_write buffered alone: fd_write adds to it,
+/// the syncs write it out themselves, and the others cannot see files.
+/// Every other syscall writes it out first, see `fs::flush_pending_write`.
+const KEEPS_WRITES_BUFFERED: &[&str] = &[
+    "fd_write", "fd_sync", "fd_datasync",
+    "args_get", "args_sizes_get", "environ_get", "environ_sizes_get",
+    "clock_res_get", "clock_time_get", "poll_oneoff", "random_get", "sched_yield",
+    "__builtin_rt_yield", "__builtin_rt_seq", "__builtin_rt_batch",
+];
+
+/// Declares every syscall the runtime provides as `module name => function`.
+/// Generates `SYSCALL_NAMES` and `register`, which links each function
+/// wrapped so that calling it bumps the process's counter for that syscall
+/// and, unless it is in `KEEPS_WRITES_BUFFERED`, writes out buffered writes.
+macro_rules! syscalls {
+    ($($module:literal $name:literal => $func:path,)*) => {
+        /// Names of the registered syscalls, indexed like `SyscallCounts`.
+        pub const SYSCALL_NAMES: &[&str] = &[$($name),*];
+
+        pub fn register(linker: &mut Linker<ProcessData>) -> Result<()> {
+            let mut slots = 0..;
+            $(
+                linker.func_wrap($module, $name, Counted::counted($func, slots.next().unwrap(), !KEEPS_WRITES_BUFFERED.contains(&$name)))?;
+            )*
+            Ok(())
+        }
+    };
+}
+
+syscalls! {
     // Arguments and Environment
-    linker.func_wrap("wasi_snapshot_preview1", "args_get", args::wasi_args_get)?;
-    linker.func_wrap("wasi_snapshot_preview1", "args_sizes_get", args::wasi_args_sizes_get)?;
-    linker.func_wrap("wasi_snapshot_preview1", "environ_get", args::wasi_environ_get)?;
-    linker.func_wrap("wasi_snapshot_preview1", "environ_sizes_get", args::wasi_environ_sizes_get)?;
+    "wasi_snapshot_preview1" "args_get" => args::wasi_args_get,
+    "wasi_snapshot_preview1" "args_sizes_get" => args::wasi_args_sizes_get,
+    "wasi_snapshot_preview1" "environ_get" => args::wasi_environ_get,
+    "wasi_snapshot_preview1" "environ_sizes_get" => args::wasi_environ_sizes_get,
 
     // Clock
-    linker.func_wrap("wasi_snapshot_preview1", "clock_res_get", clock::wasi_clock_res_get)?;
-    linker.func_wrap("wasi_snapshot_preview1", "clock_time_get", clock::wasi_clock_time_get)?;
+    "wasi_snapshot_preview1" "clock_res_get" => clock::wasi_clock_res_get,
+    "wasi_snapshot_preview1" "clock_time_get" => clock::wasi_clock_time_get,
 
     // Process and Random
-    linker.func_wrap("wasi_snapshot_preview1", "proc_raise", process::wasi_proc_raise)?;
-    linker.func_wrap("wasi_snapshot_preview1", "sched_yield", process::wasi_sched_yield)?;
-    linker.func_wrap("wasi_snapshot_preview1", "random_get", process::wasi_random_get)?;
+    "wasi_snapshot_preview1" "proc_raise" => process::wasi_proc_raise,
+    "wasi_snapshot_preview1" "sched_yield" => process::wasi_sched_yield,
+    "wasi_snapshot_preview1" "random_get" => process::wasi_random_get,
 
     // File Descriptor Operations
-    linker.func_wrap("wasi_snapshot_preview1", "fd_advise", fd_ops::wasi_fd_advise)?;
-    linker.func_wrap("wasi_snapshot_preview1", "fd_allocate", fd_ops::wasi_fd_allocate)?;
-    linker.func_wrap("wasi_snapshot_preview1", "fd_datasync", fd_ops::wasi_fd_datasync)?;
-    linker.func_wrap("wasi_snapshot_preview1", "fd_fdstat_set_flags", fd_ops::wasi_fd_fdstat_set_flags)?;
-    linker.func_wrap("wasi_snapshot_preview1", "fd_fdstat_set_rights", fd_ops::wasi_fd_fdstat_set_rights)?;
-    linker.func_wrap("wasi_snapshot_preview1", "fd_filestat_get", fs::wasi_fd_filestat_get)?;
-    linker.func_wrap("wasi_snapshot_preview1", "fd_filestat_set_size", fd_ops::wasi_fd_filestat_set_size)?;
-    linker.func_wrap("wasi_snapshot_preview1", "fd_filestat_set_times", fd_ops::wasi_fd_filestat_set_times)?;
-    linker.func_wrap("wasi_snapshot_preview1", "fd_pread", fd_ops::wasi_fd_pread)?;
-    linker.func_wrap("wasi_snapshot_preview1", "fd_pwrite", fd_ops::wasi_fd_pwrite)?;
-    linker.func_wrap("wasi_snapshot_preview1", "fd_renumber", fd_ops::wasi_fd_renumber)?;
-    linker.func_wrap("wasi_snapshot_preview1", "fd_sync", fd_ops::wasi_fd_sync)?;
-    linker.func_wrap("wasi_snapshot_preview1", "fd_tell", fd_ops::wasi_fd_tell)?;
+    "wasi_snapshot_preview1" "fd_advise" => fd_ops::wasi_fd_advise,
+    "wasi_snapshot_preview1" "fd_allocate" => fd_ops::wasi_fd_allocate,
+    "wasi_snapshot_preview1" "fd_datasync" => fd_ops::wasi_fd_datasync,
+    "wasi_snapshot_preview1" "fd_fdstat_set_flags" => fd_ops::wasi_fd_fdstat_set_flags,
+    "wasi_snapshot_preview1" "fd_fdstat_set_rights" => fd_ops::wasi_fd_fdstat_set_rights,
+    "wasi_snapshot_preview1" "fd_filestat_get" => fs::wasi_fd_filestat_get,
+    "wasi_snapshot_preview1" "fd_filestat_set_size" => fd_ops::wasi_fd_filestat_set_size,
+    "wasi_snapshot_preview1" "fd_filestat_set_times" => fd_ops::wasi_fd_filestat_set_times,
+    "wasi_snapshot_preview1" "fd_pread" => fd_ops::wasi_fd_pread,
+    "wasi_snapshot_preview1" "fd_pwrite" => fd_ops::wasi_fd_pwrite,
+    "wasi_snapshot_preview1" "fd_renumber" => fd_ops::wasi_fd_renumber,
+    "wasi_snapshot_preview1" "fd_sync" => fd_ops::wasi_fd_sync,
+    "wasi_snapshot_preview1" "fd_tell" => fd_ops::wasi_fd_tell,
 
     // Path Operations
-    linker.func_wrap("wasi_snapshot_preview1", "path_filestat_get", path_ops::wasi_path_filestat_get)?;
-    linker.func_wrap("wasi_snapshot_preview1", "path_filestat_set_times", path_ops::wasi_path_filestat_set_times)?;
-    linker.func_wrap("wasi_snapshot_preview1", "path_link", path_ops::wasi_path_link)?;
-    linker.func_wrap("wasi_snapshot_preview1", "path_readlink", path_ops::wasi_path_readlink)?;
-    linker.func_wrap("wasi_snapshot_preview1", "path_rename", path_ops::wasi_path_rename)?;
+    "wasi_snapshot_preview1" "path_filestat_get" => path_ops::wasi_path_filestat_get,
+    "wasi_snapshot_preview1" "path_filestat_set_times" => path_ops::wasi_path_filestat_set_times,
+    "wasi_snapshot_preview1" "path_link" => path_ops::wasi_path_link,
+    "wasi_snapshot_preview1" "path_readlink" => path_ops::wasi_path_readlink,
+    "wasi_snapshot_preview1" "path_rename" => path_ops::wasi_path_rename,
 
     // Existing registrations
-    linker.func_wrap("wasi_snapshot_preview1", "fd_fdstat_get", fd::wasi_fd_fdstat_get)?;
-    linker.func_wrap("wasi_snapshot_preview1", "fd_seek", fd::wasi_fd_seek)?;
-    linker.func_wrap("wasi_snapshot_preview1", "fd_read", fd::wasi_fd_read)?;
-    linker.func_wrap("wasi_snapshot_preview1", "poll_oneoff", fd::wasi_poll_oneoff)?;
-    linker.func_wrap("wasi_snapshot_preview1", "proc_exit", fd::wasi_proc_exit)?;
+    "wasi_snapshot_preview1" "fd_fdstat_get" => fd::wasi_fd_fdstat_get,
+    "wasi_snapshot_preview1" "fd_seek" => fd::wasi_fd_seek,
+    "wasi_snapshot_preview1" "fd_read" => fd::wasi_fd_read,
+    "wasi_snapshot_preview1" "poll_oneoff" => fd::wasi_poll_oneoff,
+    "wasi_snapshot_preview1" "proc_exit" => fd::wasi_proc_exit,
 
-    linker.func_wrap("env","__builtin_rt_yield",builtin_yield::wasi__builtin_rt_yield)?;
+    "env" "__builtin_rt_yield" => builtin_yield::wasi__builtin_rt_yield,
+    "env" "__builtin_rt_report" => builtin_report::wasi__builtin_rt_report,
+    "env" "__builtin_rt_seq" => builtin_seq::wasi__builtin_rt_seq,
+    "env" "__builtin_rt_batch" => builtin_batch::wasi__builtin_rt_batch,
+    "env" "__builtin_rt_atomic_replace" => builtin_atomic_replace::wasi__builtin_rt_atomic_replace,
+    "env" "__builtin_rt_set_flush_threshold" => builtin_flush_threshold::wasi__builtin_rt_set_flush_threshold,
 
-    linker.func_wrap("wasi_snapshot_preview1", "path_open", fs::wasi_path_open)?;
-    linker.func_wrap("wasi_snapshot_preview1", "fd_readdir", fs::wasi_fd_readdir)?;
-    linker.func_wrap("wasi_snapshot_preview1", "fd_close", fs::wasi_fd_close)?;
-    linker.func_wrap("wasi_snapshot_preview1", "fd_prestat_get", fd::wasi_fd_prestat_get)?;
-    linker.func_wrap("wasi_snapshot_preview1", "fd_prestat_dir_name", fd::wasi_fd_prestat_dir_name)?;
-    linker.func_wrap("wasi_snapshot_preview1", "path_create_directory", fs::wasi_path_create_directory)?;
-    linker.func_wrap("wasi_snapshot_preview1", "path_remove_directory", fs::wasi_path_remove_directory)?;
-    linker.func_wrap("wasi_snapshot_preview1", "path_unlink_file", fs::wasi_path_unlink_file)?;
-    linker.func_wrap("wasi_snapshot_preview1", "path_symlink", fs::wasi_path_symlink)?;
-    linker.func_wrap("wasi_snapshot_preview1", "fd_write", fs::wasi_fd_write)?;
-    linker.func_wrap("env", "file_create", fs::wasi_file_create)?;
+    "wasi_snapshot_preview1" "path_open" => fs::wasi_path_open,
+    "wasi_snapshot_preview1" "fd_readdir" => fs::wasi_fd_readdir,
+    "wasi_snapshot_preview1" "fd_close" => fs::wasi_fd_close,
+    "wasi_snapshot_preview1" "fd_prestat_get" => fd::wasi_fd_prestat_get,
+    "wasi_snapshot_preview1" "fd_prestat_dir_name" => fd::wasi_fd_prestat_dir_name,
+    "wasi_snapshot_preview1" "path_create_directory" => fs::wasi_path_create_directory,
+    "wasi_snapshot_preview1" "path_remove_directory" => fs::wasi_path_remove_directory,
+    "wasi_snapshot_preview1" "path_unlink_file" => fs::wasi_path_unlink_file,
+    "wasi_snapshot_preview1" "path_symlink" => fs::wasi_path_symlink,
+    "wasi_snapshot_preview1" "fd_write" => fs::wasi_fd_write,
+    "env" "file_create" => fs::wasi_file_create,
 
     // Socket Operations
-    linker.func_wrap("wasi_snapshot_preview1", "sock_open", net::wasi_sock_open)?;
-    linker.func_wrap("wasi_snapshot_preview1", "sock_connect", net::wasi_sock_connect)?;
-    linker.func_wrap("wasi_snapshot_preview1", "sock_listen", net::wasi_sock_listen)?;
-    linker.func_wrap("wasi_snapshot_preview1", "sock_accept", net::wasi_sock_accept)?;
-    linker.func_wrap("wasi_snapshot_preview1", "sock_recv", net::wasi_sock_recv)?;
-    linker.func_wrap("wasi_snapshot_preview1", "sock_send", net::wasi_sock_send)?;
-    linker.func_wrap("wasi_snapshot_preview1", "sock_shutdown", net::wasi_sock_shutdown)?;
-    linker.func_wrap("wasi_snapshot_preview1", "sock_close", net::wasi_sock_close)?;
-
-    Ok(())
-}
\ No newline at end of file
+    "wasi_snapshot_preview1" "sock_open" => net::wasi_sock_open,
+    "wasi_snapshot_preview1" "sock_connect" => net::wasi_sock_connect,
+    "wasi_snapshot_preview1" "sock_bind" => net::wasi_sock_bind,
+    "wasi_snapshot_preview1" "sock_getsockname" => net::wasi_sock_getsockname,
+    "wasi_snapshot_preview1" "sock_getpeername" => net::wasi_sock_getpeername,
+    "wasi_snapshot_preview1" "sock_listen" => net::wasi_sock_listen,
+    "wasi_snapshot_preview1" "sock_accept" => net::wasi_sock_accept,
+    "wasi_snapshot_preview1" "sock_recv" => net::wasi_sock_recv,
+    "wasi_snapshot_preview1" "sock_send" => net::wasi_sock_send,
+    "wasi_snapshot_preview1" "sock_recv_from" => net::wasi_sock_recv_from,
+    "wasi_snapshot_preview1" "sock_send_to" => net::wasi_sock_send_to,
+    "wasi_snapshot_preview1" "sock_shutdown" => net::wasi_sock_shutdown,
+    "wasi_snapshot_preview1" "sock_close" => net::wasi_sock_close,
+}
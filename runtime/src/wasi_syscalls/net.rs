@@ -1,8 +1,10 @@
 use wasmtime::Caller;
-use crate::runtime::process::{BlockReason, ProcessData, ProcessState};
+use crate::runtime::process::{block_until_running, BlockReason, ProcessData};
 use consensus::commands::NetworkOperation;
+use consensus::nat::host_port;
 use anyhow::Result;
 use log::{info, error, debug};
+use crate::wasi_syscalls::memory::{guest_memory, read_slice, read_u32, write_slice};
 
 #[derive(Debug, Clone)]
 pub struct OutgoingNetworkMessage {
@@ -40,10 +42,12 @@ pub fn wasi_sock_open(
     {
         let process_data = caller.data();
         pid = process_data.id;
-        src_port = {
-            let mut port = process_data.next_port.lock().unwrap();
-            *port += 1;
-            *port
+        src_port = match allocate_port(process_data) {
+            Some(port) => port,
+            None => {
+                error!("wasi_sock_open: process {} has used up its socket ports", pid);
+                return 4; // EADDRNOTAVAIL
+            }
         };
         debug!("Allocated port {} for process {}", src_port, pid);
 
@@ -59,26 +63,23 @@ pub fn wasi_sock_open(
             connected: false,
             is_listener: false,  // New sockets start as non-listeners
             buffer: Vec::new(),
+            bound_addr: None,
+            peer_addr: None,
+            datagram: socktype == 2,
+            ipv6: domain == 2,
         });
         info!("Created socket FD {} for process {}:{}", fd, pid, src_port);
     }
     
     // Write FD back to WASM memory
-    let memory = match caller.get_export("memory") {
-        Some(wasmtime::Extern::Memory(mem)) => mem,
-        _ => {
-            error!("sock_open: no memory export found");
-            return 1; // EINVAL
-        }
+    let memory = match guest_memory(&mut caller) {
+        Ok(mem) => mem,
+        Err(errno) => return errno,
     };
-    let mem_mut = memory.data_mut(&mut caller);
-    let out_ptr = sock_fd_out as usize;
-    if out_ptr + 4 > mem_mut.len() {
-        error!("sock_open: sock_fd_out pointer out of bounds");
-        return 1; // EINVAL
+    if let Err(errno) = write_slice(memory.data_mut(&mut caller), sock_fd_out as u32, &(fd as u32).to_le_bytes()) {
+        return errno;
     }
-    mem_mut[out_ptr..out_ptr+4].copy_from_slice(&(fd as u32).to_le_bytes());
-    debug!("Wrote socket FD {} to memory at offset {}", fd, out_ptr);
+    debug!("Wrote socket FD {} to memory at offset {}", fd, sock_fd_out);
     0 // Success
 }
 
@@ -90,24 +91,38 @@ pub fn wasi_sock_send(
     si_flags: i32,
     ret_data_len: i32,
 ) -> i32 {
-    let start_time = std::time::Instant::now();
     debug!("wasi_sock_send called with fd={}, si_data={}, si_data_len={}, si_flags={}, ret_data_len={}", 
         fd, si_data, si_data_len, si_flags, ret_data_len);
+    sock_send(&mut caller, fd, si_data, si_data_len, ret_data_len, None)
+}
+
+/// Sends from guest memory for `sock_send` and `sock_send_to`. A stream
+/// socket sends to its connection; a datagram socket sends one datagram to
+/// `dest`, or without one to the address it was connected to.
+fn sock_send(
+    caller: &mut Caller<'_, ProcessData>,
+    fd: i32,
+    si_data: i32,
+    si_data_len: i32,
+    ret_data_len: i32,
+    dest: Option<(String, u16)>,
+) -> i32 {
+    let start_time = std::time::Instant::now();
     let pid;
     let src_port;
+    let datagram;
     let data;
     
     // First get the memory data
     {
-        let memory = match caller.get_export("memory") {
-            Some(wasmtime::Extern::Memory(mem)) => mem,
-            _ => {
-                error!("sock_send: no memory export found");
-                return 1;
-            }
+        let memory = match guest_memory(caller) {
+            Ok(mem) => mem,
+            Err(errno) => return errno,
+        };
+        data = match read_slice(memory.data(&*caller), si_data as u32, si_data_len as u32 as usize) {
+            Ok(bytes) => bytes.to_vec(),
+            Err(errno) => return errno,
         };
-        let mem = memory.data(&caller);
-        data = mem[si_data as usize..(si_data + si_data_len) as usize].to_vec();
         debug!("Read {} bytes from memory for send operation", data.len());
     }
 
@@ -117,22 +132,49 @@ pub fn wasi_sock_send(
         pid = process_data.id;
         
         // Get socket FD entry
-        src_port = {
+        let datagram_dest;
+        (src_port, datagram_dest) = {
             let table = process_data.fd_table.lock().unwrap();
-            if let Some(Some(crate::runtime::fd_table::FDEntry::Socket { local_port, .. })) = table.entries.get(fd as usize) {
-                *local_port
-            } else {
-                error!("Invalid socket FD {} for process {}", fd, pid);
-                return 1; // Invalid FD
+            match table.entries.get(fd as usize) {
+                Some(Some(crate::runtime::fd_table::FDEntry::Socket { local_port, datagram: false, .. })) => (*local_port, None),
+                Some(Some(crate::runtime::fd_table::FDEntry::Socket { local_port, datagram: true, connected, peer_addr, .. })) => {
+                    let connected_to = peer_addr.as_deref()
+                        .filter(|_| *connected)
+                        .and_then(|p| p.parse::<std::net::SocketAddr>().ok())
+                        .map(|p| (p.ip().to_string(), p.port()));
+                    match dest.or(connected_to) {
+                        Some(dest) => (*local_port, Some(dest)),
+                        None => {
+                            error!("Datagram socket FD {} of process {} has no destination", fd, pid);
+                            return 17; // EDESTADDRREQ
+                        }
+                    }
+                }
+                _ => {
+                    error!("Invalid socket FD {} for process {}", fd, pid);
+                    return 1; // Invalid FD
+                }
             }
         };
         
         // Queue the send operation
-        let op = NetworkOperation::Send {
-            src_port,
-            data: data.clone(),
+        datagram = datagram_dest.is_some();
+        let op = match datagram_dest {
+            None => NetworkOperation::Send {
+                src_port,
+                data: data.clone(),
+            },
+            Some((dest_addr, dest_port)) => NetworkOperation::SendTo {
+                src_port,
+                dest_addr,
+                dest_port,
+                data: data.clone(),
+            },
         };
         
+        if datagram {
+            await_network_status(process_data);
+        }
         process_data.network_queue.lock().unwrap().push(OutgoingNetworkMessage {
             pid,
             operation: op,
@@ -143,20 +185,24 @@ pub fn wasi_sock_send(
     
     // Block until consensus processes this
     debug!("Blocking process {} for network operation", pid);
-    block_process_for_network(&mut caller);
+    if !block_process_for_network(caller) {
+        return 13; // ECONNABORTED
+    }
+    if datagram && network_op_failed(caller) {
+        error!("Consensus could not send the datagram of process {}:{}", pid, src_port);
+        return 29; // EIO
+    }
 
     // Write the number of bytes sent back to memory
     {
-        let memory = match caller.get_export("memory") {
-            Some(wasmtime::Extern::Memory(mem)) => mem,
-            _ => {
-                error!("sock_send: no memory export found for return value");
-                return 1;
-            }
+        let memory = match guest_memory(caller) {
+            Ok(mem) => mem,
+            Err(errno) => return errno,
         };
-        let mem_mut = memory.data_mut(&mut caller);
         let ret_data_len_bytes = (data.len() as u32).to_le_bytes();
-        mem_mut[ret_data_len as usize..(ret_data_len + 4) as usize].copy_from_slice(&ret_data_len_bytes);
+        if let Err(errno) = write_slice(memory.data_mut(&mut *caller), ret_data_len as u32, &ret_data_len_bytes) {
+            return errno;
+        }
         debug!("Wrote return value {} to memory at offset {}", data.len(), ret_data_len);
     }
     0
@@ -197,7 +243,9 @@ pub fn wasi_sock_close(
     
     // Block until consensus processes this
     debug!("Blocking process {} for network operation", pid);
-    block_process_for_network(&mut caller);
+    if !block_process_for_network(&mut caller) {
+        return 13; // ECONNABORTED
+    }
     
     // Return success since we've already deallocated the FD
     0
@@ -211,6 +259,7 @@ pub fn wasi_sock_listen(
     debug!("wasi_sock_listen called with fd={}, backlog={}", fd, backlog);
     let pid;
     let src_port;
+    let bind;
     
     // Get socket FD entry
     {
@@ -218,8 +267,9 @@ pub fn wasi_sock_listen(
         pid = process_data.id;
         debug!("Processing listen request for process {}", pid);
         let mut table = process_data.fd_table.lock().unwrap();
-        if let Some(Some(crate::runtime::fd_table::FDEntry::Socket { local_port, is_listener, .. })) = table.entries.get_mut(fd as usize) {
+        if let Some(Some(crate::runtime::fd_table::FDEntry::Socket { local_port, is_listener, bound_addr, .. })) = table.entries.get_mut(fd as usize) {
             src_port = *local_port;
+            bind = bound_addr.clone();
             *is_listener = true;
             debug!("Found socket FD {} for process {}:{} and marked as listener", fd, pid, src_port);
         } else {
@@ -231,8 +281,17 @@ pub fn wasi_sock_listen(
     // Queue the listen operation
     {
         let process_data = caller.data();
+        // INADDR_ANY with port 0 (or no bind at all) leaves the choice to consensus
+        let (bind_addr, bind_port) = match bind {
+            Some((ip, port)) => (Some(ip), Some(port).filter(|&p| p != 0)),
+            None => (None, None),
+        };
         let op = NetworkOperation::Listen {
             src_port,
+            bind_addr,
+            bind_port,
+            // Consensus keeps it within 1..=MAX_BACKLOG
+            backlog: backlog.max(0) as u32,
         };
         debug!("Creating listen operation for process {}:{}", pid, src_port);
         
@@ -245,7 +304,12 @@ pub fn wasi_sock_listen(
     
     // Block until consensus processes this
     debug!("Blocking process {} for network operation", pid);
-    block_process_for_network(&mut caller);
+    if !block_process_for_network(&mut caller) {
+        if let Some(Some(crate::runtime::fd_table::FDEntry::Socket { is_listener, .. })) = caller.data().fd_table.lock().unwrap().entries.get_mut(fd as usize) {
+            *is_listener = false;
+        }
+        return 13; // ECONNABORTED
+    }
 
     // Check if the listen operation succeeded by verifying the NAT mapping exists
     let listen_succeeded = {
@@ -282,6 +346,7 @@ pub fn wasi_sock_accept(
     debug!("wasi_sock_accept called with fd={}, flags={}, fd_out={}", fd, flags, fd_out);
     let pid;
     let src_port;
+    let ipv6;
     
     // Get socket FD entry
     {
@@ -289,8 +354,9 @@ pub fn wasi_sock_accept(
         pid = process_data.id;
         debug!("Processing accept request for process {}", pid);
         let table = process_data.fd_table.lock().unwrap();
-        if let Some(Some(crate::runtime::fd_table::FDEntry::Socket { local_port, .. })) = table.entries.get(fd as usize) {
+        if let Some(Some(crate::runtime::fd_table::FDEntry::Socket { local_port, ipv6: listener_ipv6, .. })) = table.entries.get(fd as usize) {
             src_port = *local_port;
+            ipv6 = *listener_ipv6;
             debug!("Found socket FD {} for process {}:{}", fd, pid, src_port);
         } else {
             error!("Invalid socket FD {} for process {}", fd, pid);
@@ -308,10 +374,12 @@ pub fn wasi_sock_accept(
             error!("No free file descriptors available for accepted connection");
             return 76; // EMFILE
         }
-        let new_port = {
-            let mut port = process_data.next_port.lock().unwrap();
-            *port += 1;
-            *port
+        let new_port = match allocate_port(process_data) {
+            Some(port) => port,
+            None => {
+                error!("Process {} has used up its socket ports", pid);
+                return 4; // EADDRNOTAVAIL
+            }
         };
         debug!("Allocated new FD {} and port {} for accepted connection", new_fd, new_port);
         table.entries[new_fd as usize] = Some(crate::runtime::fd_table::FDEntry::Socket {
@@ -319,6 +387,10 @@ pub fn wasi_sock_accept(
             connected: false,  // Start as not connected, will be set to true when connection is established
             is_listener: false,  // Accepted connections are never listeners
             buffer: Vec::new(),
+            bound_addr: None,
+            peer_addr: None,
+            datagram: false,
+            ipv6,
         });
         (new_fd, new_port)
     };
@@ -336,12 +408,20 @@ pub fn wasi_sock_accept(
             pid,
             operation: op,
         });
+        // Stay blocked until consensus answers, or the scheduler would wake the
+        // process before the connection it accepts has a socket to land in
+        process_data.nat_table.lock().unwrap().set_waiting_accept(pid, src_port, new_port);
         info!("Queued accept operation for process {}:{} -> new port {}", pid, src_port, new_port);
     }
     
     // Block until consensus processes this
     debug!("Blocking process {} for network operation", pid);
-    block_process_for_network(&mut caller);
+    if !block_process_for_network(&mut caller) {
+        let process_data = caller.data();
+        process_data.fd_table.lock().unwrap().entries[new_fd as usize] = None;
+        *process_data.next_port.lock().unwrap() -= 1;
+        return 13; // ECONNABORTED
+    }
     
     // Check if we got a connection
     let has_connection = {
@@ -364,21 +444,14 @@ pub fn wasi_sock_accept(
 
     if has_connection {
         // Write the new FD back to WASM memory
-        let memory = match caller.get_export("memory") {
-            Some(wasmtime::Extern::Memory(mem)) => mem,
-            _ => {
-                error!("sock_accept: no memory export found");
-                return 1; // EINVAL
-            }
+        let memory = match guest_memory(&mut caller) {
+            Ok(mem) => mem,
+            Err(errno) => return errno,
         };
-        let mem_mut = memory.data_mut(&mut caller);
-        let out_ptr = fd_out as usize;
-        if out_ptr + 4 > mem_mut.len() {
-            error!("sock_accept: fd_out pointer out of bounds");
-            return 1; // EINVAL
+        if let Err(errno) = write_slice(memory.data_mut(&mut caller), fd_out as u32, &(new_fd as u32).to_le_bytes()) {
+            return errno;
         }
-        mem_mut[out_ptr..out_ptr+4].copy_from_slice(&(new_fd as u32).to_le_bytes());
-        debug!("Wrote new FD {} to memory at offset {}", new_fd, out_ptr);
+        debug!("Wrote new FD {} to memory at offset {}", new_fd, fd_out);
 
         // Mark the socket as connected
         {
@@ -424,26 +497,54 @@ pub fn wasi_sock_recv(
     ro_datalen_ptr: u32,
     ro_flags_ptr: u32,
 ) -> i32 {
-    let start_time = std::time::Instant::now();
     debug!("wasi_sock_recv: fd={}, ri_data_ptr={}, ri_data_len={}, ri_flags={}, ro_datalen_ptr={}, ro_flags_ptr={}", 
         fd, ri_data_ptr, ri_data_len, ri_flags, ro_datalen_ptr, ro_flags_ptr);
+    sock_recv(&mut caller, fd, ri_data_ptr, ri_data_len, ro_datalen_ptr, ro_flags_ptr)
+}
+
+/// Takes what a read of up to `max` bytes gets from a socket's buffer. A
+/// datagram socket's buffer holds one datagram, which is taken whole: what
+/// does not fit is discarded, and the returned flag says it was truncated.
+fn take_received(buffer: &mut Vec<u8>, datagram: bool, max: usize) -> (Vec<u8>, bool) {
+    let to_take = buffer.len().min(max);
+    let data = buffer.drain(..to_take).collect::<Vec<u8>>();
+    let truncated = datagram && !buffer.is_empty();
+    if datagram {
+        buffer.clear();
+    }
+    (data, truncated)
+}
+
+/// Receives into guest memory for `sock_recv` and `sock_recv_from`, blocking
+/// on consensus if the socket's buffer is empty.
+fn sock_recv(
+    caller: &mut Caller<'_, ProcessData>,
+    fd: u32,
+    ri_data_ptr: u32,
+    ri_data_len: u32,
+    ro_datalen_ptr: u32,
+    ro_flags_ptr: u32,
+) -> i32 {
+    let start_time = std::time::Instant::now();
     let pid;
     let src_port;
+    let datagram;
     let mut data = Vec::new();
+    let mut truncated = false;
     let mut has_data = false;
     {
         let process_data = caller.data();
         pid = process_data.id;
         let mut table = process_data.fd_table.lock().unwrap();
-        if let Some(Some(crate::runtime::fd_table::FDEntry::Socket { local_port, buffer, .. })) = table.entries.get_mut(fd as usize) {
+        if let Some(Some(crate::runtime::fd_table::FDEntry::Socket { local_port, buffer, datagram: is_datagram, .. })) = table.entries.get_mut(fd as usize) {
             src_port = *local_port;
+            datagram = *is_datagram;
             if !buffer.is_empty() {
                 // Only take what we need from the buffer
-                let to_take = buffer.len().min(ri_data_len as usize);
-                data = buffer.drain(..to_take).collect::<Vec<u8>>();
+                (data, truncated) = take_received(buffer, datagram, ri_data_len as usize);
                 has_data = true;
                 info!("Runtime read {} bytes from buffer for process {}:{} in {:?}", 
-                     to_take, pid, src_port, start_time.elapsed());
+                     data.len(), pid, src_port, start_time.elapsed());
             }
         } else {
             error!("Invalid socket FD {} for process {}", fd, pid);
@@ -456,7 +557,11 @@ pub fn wasi_sock_recv(
         debug!("No data available for socket {}:{}, queuing Recv operation and blocking", pid, src_port);
         {
             let process_data = caller.data();
-            let op = NetworkOperation::Recv { src_port };
+            let op = if datagram {
+                NetworkOperation::RecvFrom { src_port }
+            } else {
+                NetworkOperation::Recv { src_port }
+            };
             process_data.network_queue.lock().unwrap().push(OutgoingNetworkMessage {
                 pid,
                 operation: op,
@@ -467,7 +572,9 @@ pub fn wasi_sock_recv(
                  pid, src_port, start_time.elapsed());
         }
         debug!("Blocking process {} for network recv operation", pid);
-        block_process_for_network(&mut caller);
+        if !block_process_for_network(caller) {
+            return 13; // ECONNABORTED
+        }
         
         // After waking up, check buffer again
         let mut data2 = Vec::new();
@@ -477,11 +584,10 @@ pub fn wasi_sock_recv(
             let mut table = process_data.fd_table.lock().unwrap();
             if let Some(Some(crate::runtime::fd_table::FDEntry::Socket { buffer, .. })) = table.entries.get_mut(fd as usize) {
                 if !buffer.is_empty() {
-                    let to_take = buffer.len().min(ri_data_len as usize);
-                    data2 = buffer.drain(..to_take).collect::<Vec<u8>>();
+                    (data2, truncated) = take_received(buffer, datagram, ri_data_len as usize);
                     has_data2 = true;
                     info!("Runtime received {} bytes after blocking for process {}:{} in {:?}", 
-                         to_take, pid, src_port, start_time.elapsed());
+                         data2.len(), pid, src_port, start_time.elapsed());
                 }
             }
         }
@@ -494,39 +600,28 @@ pub fn wasi_sock_recv(
     }
 
     // Get the memory to write data to
-    let memory = match caller.get_export("memory") {
-        Some(wasmtime::Extern::Memory(mem)) => mem,
-        _ => {
-            error!("sock_recv: no memory export found");
-            return 1; // EINVAL
-        }
+    let memory = match guest_memory(caller) {
+        Ok(mem) => mem,
+        Err(errno) => return errno,
     };
-    let mem_mut = memory.data_mut(&mut caller);
+    let mem_mut = memory.data_mut(&mut *caller);
 
     // Write data to memory
     let data_len = data.len().min(ri_data_len as usize);
-    let out_ptr = ri_data_ptr as usize;
-    if out_ptr + data_len > mem_mut.len() {
-        error!("sock_recv: data pointer out of bounds");
-        return 1; // EINVAL
+    if let Err(errno) = write_slice(mem_mut, ri_data_ptr, &data[..data_len]) {
+        return errno;
     }
-    mem_mut[out_ptr..out_ptr + data_len].copy_from_slice(&data[..data_len]);
 
     // Write data length back to memory
-    let len_ptr = ro_datalen_ptr as usize;
-    if len_ptr + 4 > mem_mut.len() {
-        error!("sock_recv: length pointer out of bounds");
-        return 1; // EINVAL
+    if let Err(errno) = write_slice(mem_mut, ro_datalen_ptr, &(data_len as u32).to_le_bytes()) {
+        return errno;
     }
-    mem_mut[len_ptr..len_ptr + 4].copy_from_slice(&(data_len as u32).to_le_bytes());
 
-    // Write flags back to memory (0 for now)
-    let flags_ptr = ro_flags_ptr as usize;
-    if flags_ptr + 4 > mem_mut.len() {
-        error!("sock_recv: flags pointer out of bounds");
-        return 1; // EINVAL
+    // Write flags back to memory: RECV_DATA_TRUNCATED (1) if part of a datagram was discarded
+    let ro_flags = if truncated { 1u32 } else { 0 };
+    if let Err(errno) = write_slice(mem_mut, ro_flags_ptr, &ro_flags.to_le_bytes()) {
+        return errno;
     }
-    mem_mut[flags_ptr..flags_ptr + 4].copy_from_slice(&0u32.to_le_bytes());
 
     // Clear the waiting state since we successfully read data
     {
@@ -538,6 +633,101 @@ pub fn wasi_sock_recv(
     0 // Success
 }
 
+/// `sock_send` with a destination address, as in POSIX `sendto`.
+///
+/// A datagram socket sends one datagram to the address. A stream socket must
+/// be connected, and (as on Linux) the address is checked but otherwise ignored.
+#[allow(clippy::too_many_arguments)]
+pub fn wasi_sock_send_to(
+    mut caller: Caller<'_, ProcessData>,
+    fd: i32,
+    si_data: i32,
+    si_data_len: i32,
+    si_flags: i32,
+    addr: i32,
+    addr_len: i32,
+    ret_data_len: i32,
+) -> i32 {
+    debug!("wasi_sock_send_to called with fd={}, si_data={}, si_data_len={}, si_flags={}, addr={}, addr_len={}, ret_data_len={}",
+        fd, si_data, si_data_len, si_flags, addr, addr_len, ret_data_len);
+    let dest = {
+        let memory = match guest_memory(&mut caller) {
+            Ok(mem) => mem,
+            Err(errno) => return errno,
+        };
+        let bytes = match read_slice(memory.data(&caller), addr as u32, addr_len as u32 as usize) {
+            Ok(bytes) => bytes,
+            Err(errno) => return errno,
+        };
+        match parse_sockaddr(&caller, fd, bytes) {
+            Ok(dest) => dest,
+            Err(errno) => return errno,
+        }
+    };
+
+    {
+        let process_data = caller.data();
+        let table = process_data.fd_table.lock().unwrap();
+        match table.entries.get(fd as usize) {
+            Some(Some(crate::runtime::fd_table::FDEntry::Socket { datagram: true, .. })) => {
+                drop(table);
+                return sock_send(&mut caller, fd, si_data, si_data_len, ret_data_len, Some(dest));
+            }
+            Some(Some(crate::runtime::fd_table::FDEntry::Socket { connected: true, .. })) => {
+                debug!("sock_send_to: socket FD {} of process {} is connected, ignoring {}:{}", fd, process_data.id, dest.0, dest.1);
+            }
+            Some(Some(crate::runtime::fd_table::FDEntry::Socket { .. })) => {
+                error!("sock_send_to: socket FD {} of process {} is not connected", fd, process_data.id);
+                return 53; // ENOTCONN
+            }
+            _ => {
+                error!("sock_send_to: invalid socket FD {} for process {}", fd, process_data.id);
+                return 8; // EBADF
+            }
+        }
+    }
+
+    wasi_sock_send(caller, fd, si_data, si_data_len, si_flags, ret_data_len)
+}
+
+/// `sock_recv` that also reports the source address, as in POSIX `recvfrom`.
+///
+/// Everything a connected socket receives comes from its peer, so that is the
+/// address written to `addr_ptr`; for an unconnected datagram socket it is
+/// the sender of the datagram just received. If consensus never relayed the
+/// address, the reported length is 0.
+#[allow(clippy::too_many_arguments)]
+pub fn wasi_sock_recv_from(
+    mut caller: Caller<'_, ProcessData>,
+    fd: u32,
+    ri_data_ptr: u32,
+    ri_data_len: u32,
+    ri_flags: u32,
+    addr_ptr: u32,
+    addr_len_ptr: u32,
+    ro_datalen_ptr: u32,
+    ro_flags_ptr: u32,
+) -> i32 {
+    debug!("wasi_sock_recv_from: fd={}, ri_data_ptr={}, ri_data_len={}, ri_flags={}, addr_ptr={}, addr_len_ptr={}, ro_datalen_ptr={}, ro_flags_ptr={}",
+        fd, ri_data_ptr, ri_data_len, ri_flags, addr_ptr, addr_len_ptr, ro_datalen_ptr, ro_flags_ptr);
+    let errno = sock_recv(&mut caller, fd, ri_data_ptr, ri_data_len, ro_datalen_ptr, ro_flags_ptr);
+    if errno != 0 {
+        return errno;
+    }
+
+    let source = {
+        let table = caller.data().fd_table.lock().unwrap();
+        match table.entries.get(fd as usize) {
+            Some(Some(crate::runtime::fd_table::FDEntry::Socket { peer_addr, .. })) => {
+                peer_addr.as_deref().and_then(|p| p.parse::<std::net::SocketAddr>().ok())
+            }
+            _ => None,
+        }
+    };
+    let sockaddr = source.map(encode_sockaddr).unwrap_or_default();
+    write_sockaddr(&mut caller, &sockaddr, addr_ptr as i32, addr_len_ptr as i32)
+}
+
 pub fn wasi_sock_shutdown(
     mut caller: Caller<'_, ProcessData>,
     fd: u32,
@@ -576,7 +766,9 @@ pub fn wasi_sock_shutdown(
     
     // Block until consensus processes this
     debug!("Blocking process {} for network operation", pid);
-    block_process_for_network(&mut caller);
+    if !block_process_for_network(&mut caller) {
+        return Ok(13); // ECONNABORTED
+    }
     
     Ok(0)
 }
@@ -597,39 +789,22 @@ pub fn wasi_sock_connect(
     
     // First get the memory data for address
     {
-        let memory = match caller.get_export("memory") {
-            Some(wasmtime::Extern::Memory(mem)) => mem,
-            _ => {
-                error!("sock_connect: no memory export found");
-                return 1; // EINVAL
-            }
+        let memory = match guest_memory(&mut caller) {
+            Ok(mem) => mem,
+            Err(errno) => return errno,
+        };
+        let bytes = match read_slice(memory.data(&caller), addr as u32, addr_len as u32 as usize) {
+            Ok(bytes) => bytes,
+            Err(errno) => return errno,
         };
-        let mem = memory.data(&caller);
-        if addr as usize + addr_len as usize > mem.len() {
-            error!("sock_connect: address out of bounds");
-            return 1; // EINVAL
-        }
         
-        // Parse sockaddr_in structure (assuming IPv4 for now)
-        // struct sockaddr_in {
-        //     sa_family_t sin_family;  // 2 bytes
-        //     in_port_t sin_port;      // 2 bytes
-        //     struct in_addr sin_addr; // 4 bytes
-        //     char sin_zero[8];        // 8 bytes
-        // }
-        let addr_bytes = &mem[addr as usize..(addr + addr_len) as usize];
-        if addr_bytes.len() < 16 {
-            error!("sock_connect: address too short");
-            return 1; // EINVAL
+        match parse_sockaddr(&caller, fd, bytes) {
+            Ok((ip, port)) => {
+                dest_addr = ip;
+                dest_port = port;
+            }
+            Err(errno) => return errno,
         }
-        
-        // Parse port (network byte order)
-        let port_bytes: [u8; 2] = [addr_bytes[2], addr_bytes[3]];
-        dest_port = u16::from_be_bytes(port_bytes);
-        
-        // Parse address (network byte order)
-        let addr_bytes: [u8; 4] = [addr_bytes[4], addr_bytes[5], addr_bytes[6], addr_bytes[7]];
-        dest_addr = format!("{}.{}.{}.{}", addr_bytes[0], addr_bytes[1], addr_bytes[2], addr_bytes[3]);
     }
 
     // Then handle process data
@@ -639,7 +814,14 @@ pub fn wasi_sock_connect(
         
         // Get socket FD entry
         src_port = {
-            let table = process_data.fd_table.lock().unwrap();
+            let mut table = process_data.fd_table.lock().unwrap();
+            if let Some(Some(crate::runtime::fd_table::FDEntry::Socket { local_port, datagram: true, connected, peer_addr, .. })) = table.entries.get_mut(fd as usize) {
+                // Connecting a datagram socket only sets where its sends go
+                *connected = true;
+                *peer_addr = Some(host_port(&dest_addr, dest_port));
+                info!("Connected datagram socket FD {} of process {}:{} to {}", fd, pid, local_port, host_port(&dest_addr, dest_port));
+                return 0;
+            }
             if let Some(Some(crate::runtime::fd_table::FDEntry::Socket { local_port, .. })) = table.entries.get(fd as usize) {
                 *local_port
             } else {
@@ -655,35 +837,307 @@ pub fn wasi_sock_connect(
             src_port,
         };
         
+        // Consensus may take longer than a batch to connect, so wait for its answer
+        await_network_status(process_data);
         process_data.network_queue.lock().unwrap().push(OutgoingNetworkMessage {
             pid,
             operation: op,
         });
-        info!("Queued connect operation for process {}:{} -> {}:{}", pid, src_port, dest_addr, dest_port);
+        info!("Queued connect operation for process {}:{} -> {}", pid, src_port, host_port(&dest_addr, dest_port));
     }
     
     // Block until consensus processes this
     debug!("Blocking process {} for network operation", pid);
-    block_process_for_network(&mut caller);
-    0 // Success
+    if !block_process_for_network(&mut caller) {
+        return 13; // ECONNABORTED
+    }
+
+    // Consensus marks the socket connected only if the NAT connection was made
+    let table = caller.data().fd_table.lock().unwrap();
+    match table.entries.get(fd as usize) {
+        Some(Some(crate::runtime::fd_table::FDEntry::Socket { connected: true, .. })) => 0, // Success
+        _ => {
+            error!("Connect failed for process {}:{}", pid, src_port);
+            14 // ECONNREFUSED
+        }
+    }
 }
 
-fn block_process_for_network(caller: &mut Caller<'_, ProcessData>) {
-    {
-        let mut state = caller.data().state.lock().unwrap();
-        if *state == ProcessState::Running {
-            debug!("Setting process state to Blocked for network operation");
-            *state = ProcessState::Blocked;
+/// Parses the address a guest passed for socket `fd`: a `sockaddr_in6` if
+/// the socket was opened as AF_INET6, or else a `sockaddr_in`. Fails with
+/// EBADF for an unknown FD, or EINVAL for an address too short for its family.
+fn parse_sockaddr(caller: &Caller<'_, ProcessData>, fd: i32, bytes: &[u8]) -> Result<(String, u16), i32> {
+    let table = caller.data().fd_table.lock().unwrap();
+    let parsed = match table.entries.get(fd as usize) {
+        Some(Some(crate::runtime::fd_table::FDEntry::Socket { ipv6: true, .. })) => parse_sockaddr_in6(bytes),
+        Some(Some(crate::runtime::fd_table::FDEntry::Socket { .. })) => parse_sockaddr_in(bytes),
+        _ => {
+            error!("Invalid socket FD {} for process {}", fd, caller.data().id);
+            return Err(8); // EBADF
         }
-        let mut reason = caller.data().block_reason.lock().unwrap();
-        *reason = Some(BlockReason::NetworkIO);
-        caller.data().cond.notify_all();
+    };
+    parsed.ok_or_else(|| {
+        error!("Address of {} bytes is too short for socket FD {}", bytes.len(), fd);
+        1 // EINVAL
+    })
+}
+
+/// Parses a `sockaddr_in6` into an IPv6 address, without brackets, and
+/// host-order port.
+///
+/// struct sockaddr_in6 {
+///     sa_family_t sin6_family;   // 2 bytes
+///     in_port_t sin6_port;       // 2 bytes (network byte order)
+///     uint32_t sin6_flowinfo;    // 4 bytes
+///     struct in6_addr sin6_addr; // 16 bytes (network byte order)
+///     uint32_t sin6_scope_id;    // 4 bytes
+/// }
+fn parse_sockaddr_in6(bytes: &[u8]) -> Option<(String, u16)> {
+    if bytes.len() < 28 {
+        return None;
     }
+    let port = u16::from_be_bytes([bytes[2], bytes[3]]);
+    let octets: [u8; 16] = bytes[8..24].try_into().ok()?;
+    Some((std::net::Ipv6Addr::from(octets).to_string(), port))
+}
 
-    let mut state = caller.data().state.lock().unwrap();
-    while *state != ProcessState::Running {
-        debug!("Process waiting for network operation to complete");
-        state = caller.data().cond.wait(state).unwrap();
+/// Parses a `sockaddr_in` into a dotted-quad address and host-order port.
+///
+/// struct sockaddr_in {
+///     sa_family_t sin_family;  // 2 bytes
+///     in_port_t sin_port;      // 2 bytes (network byte order)
+///     struct in_addr sin_addr; // 4 bytes (network byte order)
+///     char sin_zero[8];        // 8 bytes
+/// }
+fn parse_sockaddr_in(bytes: &[u8]) -> Option<(String, u16)> {
+    if bytes.len() < 16 {
+        return None;
     }
-    debug!("Process resumed after network operation");
+    let port = u16::from_be_bytes([bytes[2], bytes[3]]);
+    let ip = format!("{}.{}.{}.{}", bytes[4], bytes[5], bytes[6], bytes[7]);
+    Some((ip, port))
+}
+
+pub fn wasi_sock_bind(
+    mut caller: Caller<'_, ProcessData>,
+    fd: i32,
+    addr: i32,
+    addr_len: i32,
+) -> i32 {
+    debug!("wasi_sock_bind called with fd={}, addr={}, addr_len={}", fd, addr, addr_len);
+
+    let requested = {
+        let memory = match guest_memory(&mut caller) {
+            Ok(mem) => mem,
+            Err(errno) => return errno,
+        };
+        let bytes = match read_slice(memory.data(&caller), addr as u32, addr_len as u32 as usize) {
+            Ok(bytes) => bytes,
+            Err(errno) => return errno,
+        };
+        match parse_sockaddr(&caller, fd, bytes) {
+            Ok(requested) => requested,
+            Err(errno) => return errno,
+        }
+    };
+
+    let process_data = caller.data();
+    let pid = process_data.id;
+    let mut table = process_data.fd_table.lock().unwrap();
+    let (src_port, datagram) = match table.entries.get_mut(fd as usize) {
+        Some(Some(crate::runtime::fd_table::FDEntry::Socket { local_port, is_listener, connected, bound_addr, datagram, .. })) => {
+            // Binding only makes sense once, before listen/connect
+            if bound_addr.is_some() || *is_listener || *connected {
+                error!("sock_bind: socket FD {} of process {} is already bound or in use", fd, pid);
+                return 1; // EINVAL
+            }
+            info!("Bound socket FD {} of process {}:{} to {}:{}", fd, pid, local_port, requested.0, requested.1);
+            *bound_addr = Some(requested.clone());
+            (*local_port, *datagram)
+        }
+        _ => {
+            error!("sock_bind: invalid socket FD {} for process {}", fd, pid);
+            return 8; // EBADF
+        }
+    };
+    drop(table);
+
+    // A stream socket's address is used by its Listen; a datagram socket has
+    // none, so consensus binds its host socket now
+    if datagram {
+        let (bind_addr, bind_port) = requested;
+        await_network_status(process_data);
+        process_data.network_queue.lock().unwrap().push(OutgoingNetworkMessage {
+            pid,
+            operation: NetworkOperation::Bind { src_port, bind_addr, bind_port },
+        });
+        info!("Queued bind operation for process {}:{}", pid, src_port);
+        if !block_process_for_network(&mut caller) {
+            return 13; // ECONNABORTED
+        }
+        if network_op_failed(&caller) {
+            error!("Consensus could not bind datagram socket FD {} of process {}:{}", fd, pid, src_port);
+            if let Some(Some(crate::runtime::fd_table::FDEntry::Socket { bound_addr, .. })) = caller.data().fd_table.lock().unwrap().entries.get_mut(fd as usize) {
+                *bound_addr = None;
+            }
+            return 3; // EADDRINUSE
+        }
+    }
+    0
+}
+
+/// Encodes `ip:port` as a `sockaddr_in` (AF_INET = 1, 16 bytes) or
+/// `sockaddr_in6` (AF_INET6 = 2, 28 bytes) using wasi-libc's family numbers.
+fn encode_sockaddr(addr: std::net::SocketAddr) -> Vec<u8> {
+    match addr {
+        std::net::SocketAddr::V4(v4) => {
+            let mut out = Vec::with_capacity(16);
+            out.extend_from_slice(&1u16.to_le_bytes());
+            out.extend_from_slice(&v4.port().to_be_bytes());
+            out.extend_from_slice(&v4.ip().octets());
+            out.extend_from_slice(&[0u8; 8]);
+            out
+        }
+        std::net::SocketAddr::V6(v6) => {
+            let mut out = Vec::with_capacity(28);
+            out.extend_from_slice(&2u16.to_le_bytes());
+            out.extend_from_slice(&v6.port().to_be_bytes());
+            out.extend_from_slice(&v6.flowinfo().to_be_bytes());
+            out.extend_from_slice(&v6.ip().octets());
+            out.extend_from_slice(&v6.scope_id().to_le_bytes());
+            out
+        }
+    }
+}
+
+/// Writes `sockaddr` to `addr_ptr` and its length to `addr_len_ptr`.
+///
+/// `addr_len_ptr` holds the size of the guest buffer on entry; the address is
+/// truncated to fit, as with POSIX getsockname/getpeername.
+fn write_sockaddr(
+    caller: &mut Caller<'_, ProcessData>,
+    sockaddr: &[u8],
+    addr_ptr: i32,
+    addr_len_ptr: i32,
+) -> i32 {
+    let memory = match guest_memory(caller) {
+        Ok(mem) => mem,
+        Err(errno) => return errno,
+    };
+    let mem = memory.data_mut(caller);
+    let capacity = match read_u32(mem, addr_len_ptr as u32) {
+        Ok(capacity) => capacity as usize,
+        Err(errno) => return errno,
+    };
+    let to_copy = sockaddr.len().min(capacity);
+    if let Err(errno) = write_slice(mem, addr_ptr as u32, &sockaddr[..to_copy]) {
+        return errno;
+    }
+    match write_slice(mem, addr_len_ptr as u32, &(sockaddr.len() as u32).to_le_bytes()) {
+        Ok(()) => 0,
+        Err(errno) => errno,
+    }
+}
+
+pub fn wasi_sock_getsockname(
+    mut caller: Caller<'_, ProcessData>,
+    fd: i32,
+    addr_ptr: i32,
+    addr_len_ptr: i32,
+) -> i32 {
+    debug!("wasi_sock_getsockname called with fd={}, addr_ptr={}, addr_len_ptr={}", fd, addr_ptr, addr_len_ptr);
+    let local = {
+        let process_data = caller.data();
+        let table = process_data.fd_table.lock().unwrap();
+        match table.entries.get(fd as usize) {
+            Some(Some(crate::runtime::fd_table::FDEntry::Socket { local_port, bound_addr, .. })) => {
+                // The guest sees the address it bound to, or the wildcard address
+                // with its guest-visible port; the real host port is never exposed.
+                let (ip, port) = match bound_addr {
+                    Some((ip, port)) => (ip.clone(), if *port != 0 { *port } else { *local_port }),
+                    None => ("0.0.0.0".to_string(), *local_port),
+                };
+                match format!("{}:{}", ip, port).parse::<std::net::SocketAddr>() {
+                    Ok(addr) => addr,
+                    Err(_) => {
+                        error!("sock_getsockname: unparseable local address {}:{}", ip, port);
+                        return 1; // EINVAL
+                    }
+                }
+            }
+            _ => {
+                error!("sock_getsockname: invalid socket FD {} for process {}", fd, process_data.id);
+                return 8; // EBADF
+            }
+        }
+    };
+    write_sockaddr(&mut caller, &encode_sockaddr(local), addr_ptr, addr_len_ptr)
+}
+
+pub fn wasi_sock_getpeername(
+    mut caller: Caller<'_, ProcessData>,
+    fd: i32,
+    addr_ptr: i32,
+    addr_len_ptr: i32,
+) -> i32 {
+    debug!("wasi_sock_getpeername called with fd={}, addr_ptr={}, addr_len_ptr={}", fd, addr_ptr, addr_len_ptr);
+    let peer = {
+        let process_data = caller.data();
+        let table = process_data.fd_table.lock().unwrap();
+        match table.entries.get(fd as usize) {
+            Some(Some(crate::runtime::fd_table::FDEntry::Socket { peer_addr, .. })) => {
+                match peer_addr.as_deref().and_then(|p| p.parse::<std::net::SocketAddr>().ok()) {
+                    Some(addr) => addr,
+                    None => {
+                        debug!("sock_getpeername: socket FD {} of process {} has no peer", fd, process_data.id);
+                        return 53; // ENOTCONN
+                    }
+                }
+            }
+            _ => {
+                error!("sock_getpeername: invalid socket FD {} for process {}", fd, process_data.id);
+                return 8; // EBADF
+            }
+        }
+    };
+    write_sockaddr(&mut caller, &encode_sockaddr(peer), addr_ptr, addr_len_ptr)
+}
+
+/// Blocks until consensus has answered the queued operation. Returns false if
+/// the operation was cancelled by a CancelNetwork record instead, or the block
+/// was refused; the caller then undoes its own bookkeeping and fails with
+/// ECONNABORTED.
+fn block_process_for_network(caller: &mut Caller<'_, ProcessData>) -> bool {
+    if !block_until_running(caller.data(), BlockReason::NetworkIO) {
+        return false;
+    }
+    let cancelled = std::mem::take(&mut *caller.data().network_cancelled.lock().unwrap());
+    if cancelled {
+        *caller.data().network_pending.lock().unwrap() = false;
+        info!("Network operation of process {} was cancelled", caller.data().id);
+    } else {
+        debug!("Process resumed after network operation");
+    }
+    !cancelled
+}
+
+/// Hands out the process's next guest-visible port, or None once it has
+/// reached 65535. Ports are not reused, so every replica assigns the same ones.
+fn allocate_port(process_data: &ProcessData) -> Option<u16> {
+    let mut port = process_data.next_port.lock().unwrap();
+    *port = port.checked_add(1)?;
+    Some(*port)
+}
+
+/// Keeps the process blocked on the operation about to be queued until
+/// consensus answers it with a status, which `network_op_failed` then reads.
+fn await_network_status(process_data: &ProcessData) {
+    *process_data.network_pending.lock().unwrap() = true;
+    *process_data.network_failed.lock().unwrap() = false;
+}
+
+/// Whether consensus answered the network operation the process was just
+/// blocked on with a Failed status.
+fn network_op_failed(caller: &Caller<'_, ProcessData>) -> bool {
+    std::mem::take(&mut *caller.data().network_failed.lock().unwrap())
 }
@@ -1,70 +1,114 @@
 use anyhow::Result;
 use wasmtime::Caller;
+use crate::runtime::clock::GlobalClock;
 use crate::runtime::process::ProcessData;
 use crate::runtime::fd_table::FDEntry;
-use log::info;
+use crate::runtime::tmpfs::TmpfsEntry;
+use log::{error, info};
 use std::fs;
 use std::os::unix::fs::MetadataExt;
+use std::path::PathBuf;
+use crate::wasi_syscalls::memory::{guest_memory, read_slice, write_slice};
+use crate::wasi_syscalls::fs::{deny_if_read_only, follow_symlinks, in_tmpfs, io_err_to_wasi_errno, logical_times, rename_in_sandbox, resolve_guest_path, symlink_key, update_times, WASI_LOOKUPFLAGS_SYMLINK_FOLLOW};
 
+// path_filestat_set_times fst_flags
+const WASI_FSTFLAGS_ATIM: u32 = 0x1;
+const WASI_FSTFLAGS_ATIM_NOW: u32 = 0x2;
+const WASI_FSTFLAGS_MTIM: u32 = 0x4;
+const WASI_FSTFLAGS_MTIM_NOW: u32 = 0x8;
+
+/// Resolves the path a `path_filestat_*` call names, as for `path_open`:
+/// from the working directory, or the root if it starts with `/`, through
+/// emulated symlinks if `flags` has SYMLINK_FOLLOW. `fd` must be an open
+/// directory (8, EBADF, otherwise), and the file must exist (2, ENOENT) and
+/// stay inside the sandbox (13, EACCES). Returns its canonical host path.
+fn stat_target(caller: &mut Caller<ProcessData>, fd: u32, flags: u32, path_ptr: u32, path_len: u32) -> Result<PathBuf, i32> {
+    {
+        let process_data = caller.data();
+        let table = process_data.fd_table.lock().unwrap();
+        if !matches!(table.entries.get(fd as usize), Some(Some(FDEntry::File { is_directory: true, .. }))) {
+            return Err(8); // WASI_EBADF
+        }
+    }
+    let memory = guest_memory(caller)?;
+    let path_bytes = read_slice(memory.data(&caller), path_ptr, path_len as usize)?;
+    let path = match std::str::from_utf8(path_bytes) {
+        Ok(s) => resolve_guest_path(caller.data(), s),
+        Err(_) => return Err(28), // WASI_EILSEQ (invalid unicode)
+    };
+    let path = if flags as i32 & WASI_LOOKUPFLAGS_SYMLINK_FOLLOW != 0 {
+        follow_symlinks(caller.data(), &path)?
+    } else {
+        path
+    };
+    if let Some(found) = in_tmpfs(caller.data(), |tmpfs| {
+        let path = tmpfs.resolve(&path)?;
+        tmpfs.stat(&path).map(|_| path)
+    }) {
+        return found.map_err(|e| io_err_to_wasi_errno(&e));
+    }
+    let canonical_root = caller.data().root_path.canonicalize().map_err(|e| io_err_to_wasi_errno(&e))?;
+    let canonical = path.canonicalize().map_err(|e| io_err_to_wasi_errno(&e))?;
+    if !canonical.starts_with(&canonical_root) {
+        error!("path_filestat: attempt to escape sandbox root!");
+        return Err(13); // WASI_EACCES
+    }
+    Ok(canonical)
+}
+
+/// Stats a file by path, resolved by `stat_target`. Fills the 64-byte WASI
+/// filestat like `fd_filestat_get`, with device and inode zeroed and the
+/// logical times: the host's differ between replicas.
 pub fn wasi_path_filestat_get(
     mut caller: Caller<ProcessData>,
     fd: u32,
-    _flags: u32,
+    flags: u32,
     path_ptr: u32,
     path_len: u32,
     buf_ptr: u32,
 ) -> anyhow::Result<u32> {
-    info!("wasi_path_filestat_get: fd={}, path_ptr={}, path_len={}, buf_ptr={}", fd, path_ptr, path_len, buf_ptr);
-    // Get the base directory from fd
-    let dir_path = {
-        let process_data = caller.data();
-        let table = process_data.fd_table.lock().unwrap();
-        if fd as usize >= table.entries.len() {
-            return Ok(8); // WASI_EBADF
-        }
-        match &table.entries[fd as usize] {
-            Some(FDEntry::File { host_path: Some(path), is_directory: true, .. }) => path.clone(),
-            _ => return Ok(8), // WASI_EBADF
-        }
+    info!("wasi_path_filestat_get: fd={}, flags={}, path_ptr={}, path_len={}, buf_ptr={}", fd, flags, path_ptr, path_len, buf_ptr);
+    let canonical = match stat_target(&mut caller, fd, flags, path_ptr, path_len) {
+        Ok(path) => path,
+        Err(errno) => return Ok(errno as u32),
     };
-    // Read the path string from WASM memory
-    let memory = caller.get_export("memory").unwrap().into_memory().unwrap();
-    let mem = memory.data(&caller);
-    let start = path_ptr as usize;
-    let end = start + path_len as usize;
-    if end > mem.len() {
-        return Ok(21); // WASI_EFAULT
-    }
-    let rel_path = match std::str::from_utf8(&mem[start..end]) {
-        Ok(s) => s,
-        Err(_) => return Ok(28), // WASI_EILSEQ (invalid unicode)
+    let found = match in_tmpfs(caller.data(), |tmpfs| tmpfs.stat(&canonical)) {
+        Some(Ok(TmpfsEntry::File { len })) => Ok((false, 1, len)),
+        Some(Ok(TmpfsEntry::Directory)) => Ok((true, 1, 0)),
+        Some(Err(e)) => Err(e),
+        None => fs::metadata(&canonical).map(|meta| (meta.is_dir(), meta.nlink(), meta.len())),
+    };
+    let (is_dir, nlink, size) = match found {
+        Ok(found) => found,
+        Err(e) => return Ok(io_err_to_wasi_errno(&e) as u32),
     };
-    let full_path = std::path::Path::new(&dir_path).join(rel_path.trim_start_matches('/'));
-    let meta = match fs::metadata(&full_path) {
-        Ok(m) => m,
-        Err(_) => return Ok(2), // WASI_ENOENT
+    let times = logical_times(caller.data(), &canonical.to_string_lossy());
+    let mut buf = [0u8; 64];
+    // st_dev and st_ino (0..16) stay zero
+    buf[16] = if is_dir { 3 } else { 4 }; // 3=directory, 4=regular file
+    buf[24..32].copy_from_slice(&nlink.to_le_bytes());
+    buf[32..40].copy_from_slice(&size.to_le_bytes());
+    buf[40..48].copy_from_slice(&times.atime.to_le_bytes());
+    buf[48..56].copy_from_slice(&times.mtime.to_le_bytes());
+    buf[56..64].copy_from_slice(&times.mtime.to_le_bytes()); // the mtime stands in for st_ctim
+    let memory = match guest_memory(&mut caller) {
+        Ok(mem) => mem,
+        Err(errno) => return Ok(errno as u32),
     };
-    let filetype = if meta.is_dir() { 3u8 } else { 4u8 }; // 3=directory, 4=regular file
-    let mut buf = [0u8; 56];
-    buf[0..8].copy_from_slice(&meta.dev().to_le_bytes());
-    buf[8..16].copy_from_slice(&meta.ino().to_le_bytes());
-    buf[16] = filetype;
-    buf[20..24].copy_from_slice(&(meta.nlink() as u32).to_le_bytes());
-    buf[24..32].copy_from_slice(&meta.size().to_le_bytes());
-    buf[32..40].copy_from_slice(&meta.atime().to_le_bytes());
-    buf[40..48].copy_from_slice(&meta.mtime().to_le_bytes());
-    buf[48..56].copy_from_slice(&meta.ctime().to_le_bytes());
-    let mem_mut = memory.data_mut(&mut caller);
-    let ptr = buf_ptr as usize;
-    if ptr + 56 > mem_mut.len() {
-        return Ok(21); // WASI_EFAULT
+    if let Err(errno) = write_slice(memory.data_mut(&mut caller), buf_ptr, &buf) {
+        return Ok(errno as u32);
     }
-    mem_mut[ptr..ptr+56].copy_from_slice(&buf);
     Ok(0)
 }
 
+/// Sets the logical times of a file by path, resolved by `stat_target`.
+/// `fst_flags` picks which change: ATIM and MTIM take `atim` and `mtim`,
+/// while ATIM_NOW and MTIM_NOW take the `GlobalClock`. Asking for both
+/// forms of one time fails with 28 (EINVAL). The host file's own times are
+/// left alone.
+#[allow(clippy::too_many_arguments)]
 pub fn wasi_path_filestat_set_times(
-    _caller: Caller<ProcessData>,
+    mut caller: Caller<ProcessData>,
     fd: u32,
     flags: u32,
     path_ptr: u32,
@@ -75,11 +119,36 @@ pub fn wasi_path_filestat_set_times(
 ) -> Result<u32> {
     info!("wasi_path_filestat_set_times: fd={}, flags={}, path_ptr={}, path_len={}, atim={}, mtim={}, fst_flags={}", 
         fd, flags, path_ptr, path_len, atim, mtim, fst_flags);
+    if let Err(errno) = deny_if_read_only(caller.data(), "path_filestat_set_times") {
+        return Ok(errno as u32);
+    }
+    let both = |set, now| fst_flags & set != 0 && fst_flags & now != 0;
+    if both(WASI_FSTFLAGS_ATIM, WASI_FSTFLAGS_ATIM_NOW) || both(WASI_FSTFLAGS_MTIM, WASI_FSTFLAGS_MTIM_NOW) {
+        return Ok(28); // WASI_EINVAL
+    }
+    let canonical = match stat_target(&mut caller, fd, flags, path_ptr, path_len) {
+        Ok(path) => path,
+        Err(errno) => return Ok(errno as u32),
+    };
+    let now = GlobalClock::now();
+    update_times(caller.data(), &canonical.to_string_lossy(), |times| {
+        if fst_flags & WASI_FSTFLAGS_ATIM != 0 {
+            times.atime = atim;
+        } else if fst_flags & WASI_FSTFLAGS_ATIM_NOW != 0 {
+            times.atime = now;
+        }
+        if fst_flags & WASI_FSTFLAGS_MTIM != 0 {
+            times.mtime = mtim;
+        } else if fst_flags & WASI_FSTFLAGS_MTIM_NOW != 0 {
+            times.mtime = now;
+        }
+    });
     Ok(0)
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn wasi_path_link(
-    _caller: Caller<ProcessData>,
+    caller: Caller<ProcessData>,
     old_fd: u32,
     old_flags: u32,
     old_path_ptr: u32,
@@ -90,11 +159,16 @@ pub fn wasi_path_link(
 ) -> Result<u32> {
     info!("wasi_path_link: old_fd={}, old_flags={}, old_path_ptr={}, old_path_len={}, new_fd={}, new_path_ptr={}, new_path_len={}", 
         old_fd, old_flags, old_path_ptr, old_path_len, new_fd, new_path_ptr, new_path_len);
+    if let Err(errno) = deny_if_read_only(caller.data(), "path_link") {
+        return Ok(errno as u32);
+    }
     Ok(0)
 }
 
+/// Reads back the target of an emulated symlink made by `path_symlink`, cut
+/// to `buf_len` bytes. Returns 28 (EINVAL) if the path is not a link.
 pub fn wasi_path_readlink(
-    _caller: Caller<ProcessData>,
+    mut caller: Caller<ProcessData>,
     fd: u32,
     path_ptr: u32,
     path_len: u32,
@@ -104,11 +178,35 @@ pub fn wasi_path_readlink(
 ) -> Result<u32> {
     info!("wasi_path_readlink: fd={}, path_ptr={}, path_len={}, buf_ptr={}, buf_len={}, nread_ptr={}", 
         fd, path_ptr, path_len, buf_ptr, buf_len, nread_ptr);
+    let memory = match guest_memory(&mut caller) {
+        Ok(mem) => mem,
+        Err(errno) => return Ok(errno as u32),
+    };
+    let path_bytes = match read_slice(memory.data(&caller), path_ptr, path_len as usize) {
+        Ok(bytes) => bytes,
+        Err(errno) => return Ok(errno as u32),
+    };
+    let path = match std::str::from_utf8(path_bytes) {
+        Ok(s) => resolve_guest_path(caller.data(), s),
+        Err(_) => return Ok(28), // WASI_EILSEQ (invalid unicode)
+    };
+    let target = match caller.data().symlinks.lock().unwrap().get(&symlink_key(&path)) {
+        Some(target) => target.to_string_lossy().into_owned(),
+        None => return Ok(28), // WASI_EINVAL: not a symlink
+    };
+    let bytes = &target.as_bytes()[..target.len().min(buf_len as usize)];
+    let data = memory.data_mut(&mut caller);
+    if let Err(errno) = write_slice(data, buf_ptr, bytes) {
+        return Ok(errno as u32);
+    }
+    if let Err(errno) = write_slice(data, nread_ptr, &(bytes.len() as u32).to_le_bytes()) {
+        return Ok(errno as u32);
+    }
     Ok(0)
 }
 
 pub fn wasi_path_rename(
-    _caller: Caller<ProcessData>,
+    mut caller: Caller<ProcessData>,
     old_fd: u32,
     old_path_ptr: u32,
     old_path_len: u32,
@@ -118,5 +216,31 @@ pub fn wasi_path_rename(
 ) -> Result<u32> {
     info!("wasi_path_rename: old_fd={}, old_path_ptr={}, old_path_len={}, new_fd={}, new_path_ptr={}, new_path_len={}", 
         old_fd, old_path_ptr, old_path_len, new_fd, new_path_ptr, new_path_len);
-    Ok(0)
-} 
\ No newline at end of file
+    if let Err(errno) = deny_if_read_only(caller.data(), "path_rename") {
+        return Ok(errno as u32);
+    }
+    // Read both path strings from WASM memory
+    let (old_path, new_path) = {
+        let memory = match guest_memory(&mut caller) {
+            Ok(mem) => mem,
+            Err(errno) => return Ok(errno as u32),
+        };
+        let data = memory.data(&caller);
+        let read_path = |ptr: u32, len: u32| -> Result<String, i32> {
+            let bytes = read_slice(data, ptr, len as usize)?;
+            std::str::from_utf8(bytes).map(str::to_owned).map_err(|_| 28) // WASI_EILSEQ
+        };
+        match (read_path(old_path_ptr, old_path_len), read_path(new_path_ptr, new_path_len)) {
+            (Ok(old), Ok(new)) => (old, new),
+            (Err(errno), _) | (_, Err(errno)) => return Ok(errno as u32),
+        }
+    };
+    let from = resolve_guest_path(caller.data(), &old_path);
+    let to = resolve_guest_path(caller.data(), &new_path);
+    // Same sandbox checks as path_open/path_unlink_file; a rename inside the
+    // sandbox leaves the disk usage total as it was.
+    match rename_in_sandbox(&mut caller, &from, &to) {
+        Ok(()) => Ok(0),
+        Err(errno) => Ok(errno as u32),
+    }
+}
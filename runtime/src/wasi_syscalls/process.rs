@@ -1,6 +1,8 @@
 use anyhow::Result;
 use wasmtime::Caller;
+use rand_chacha::rand_core::RngCore;
 use crate::runtime::process::ProcessData;
+use crate::wasi_syscalls::memory::{guest_memory, slice_mut};
 use log::info;
 
 
@@ -19,11 +21,23 @@ pub fn wasi_sched_yield(
     Ok(0)
 }
 
+/// Fills the buffer from the process's seeded stream rather than host
+/// entropy, so every replica and every replay gets the same bytes.
 pub fn wasi_random_get(
-    _caller: Caller<ProcessData>,
+    mut caller: Caller<ProcessData>,
     buf_ptr: u32,
     buf_len: u32,
 ) -> Result<u32> {
     info!("wasi_random_get: buf_ptr={}, buf_len={}", buf_ptr, buf_len);
+    let memory = match guest_memory(&mut caller) {
+        Ok(mem) => mem,
+        Err(errno) => return Ok(errno as u32),
+    };
+    let rng = caller.data().rng.clone();
+    let buf = match slice_mut(memory.data_mut(&mut caller), buf_ptr, buf_len as usize) {
+        Ok(buf) => buf,
+        Err(errno) => return Ok(errno as u32),
+    };
+    rng.lock().unwrap().fill_bytes(buf);
     Ok(0)
 } 
\ No newline at end of file
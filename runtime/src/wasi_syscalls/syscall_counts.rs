@@ -0,0 +1,102 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use wasmtime::{Caller, IntoFunc, WasmRet, WasmTy};
+use crate::runtime::process::ProcessData;
+use crate::runtime::quantum;
+use crate::wasi_syscalls::SYSCALL_NAMES;
+use crate::wasi_syscalls::fs::flush_pending_write;
+
+/// How often a process has called each syscall, one atomic per entry of
+/// `SYSCALL_NAMES`, so counting adds no lock to the syscall path.
+pub struct SyscallCounts {
+    counts: Box<[AtomicU64]>,
+}
+
+impl Default for SyscallCounts {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SyscallCounts {
+    pub fn new() -> Self {
+        SyscallCounts {
+            counts: SYSCALL_NAMES.iter().map(|_| AtomicU64::new(0)).collect(),
+        }
+    }
+
+    fn record(&self, slot: usize) {
+        self.counts[slot].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// The syscalls called at least once, with their counts, in registration order.
+    pub fn snapshot(&self) -> Vec<(&'static str, u64)> {
+        SYSCALL_NAMES.iter()
+            .zip(self.counts.iter())
+            .map(|(name, count)| (*name, count.load(Ordering::Relaxed)))
+            .filter(|(_, count)| *count > 0)
+            .collect()
+    }
+}
+
+impl std::fmt::Display for SyscallCounts {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let counts: Vec<String> = self.snapshot().iter()
+            .map(|(name, count)| format!("{}={}", name, count))
+            .collect();
+        write!(f, "{}", counts.join(", "))
+    }
+}
+
+/// A syscall implementation that can be wrapped to count its calls in slot
+/// `slot` of the calling process's `SyscallCounts` (and to end its fuel
+/// quantum, see `quantum::check`, and write out what fd_write left buffered
+/// if `flushes_writes`) before running it. The wrapper has the
+/// same signature, so it registers like the original, except that it can
+/// also trap: when a Shutdown record ended the process while the call was
+/// parked, or the call was proc_exit, the guest gets a trap instead of the
+/// call's result.
+pub trait Counted<Params, Results: WasmRet> {
+    fn counted(self, slot: usize, flushes_writes: bool) -> impl IntoFunc<ProcessData, Params, Results::Fallible>;
+}
+
+macro_rules! impl_counted {
+    ($($param:ident),*) => {
+        impl<'a, F, R, $($param),*> Counted<(Caller<'a, ProcessData>, $($param,)*), R> for F
+        where
+            F: Fn(Caller<'_, ProcessData>, $($param),*) -> R + Send + Sync + 'static,
+            $($param: WasmTy,)*
+            R: WasmRet,
+        {
+            #[allow(non_snake_case)]
+            fn counted(self, slot: usize, flushes_writes: bool) -> impl IntoFunc<ProcessData, (Caller<'a, ProcessData>, $($param,)*), R::Fallible> {
+                move |caller: Caller<'_, ProcessData>, $($param: $param),*| {
+                    caller.data().syscalls.record(slot);
+                    if flushes_writes {
+                        // The flush logs a failure; the guest sees none
+                        let _ = flush_pending_write(caller.data());
+                    }
+                    quantum::check(&caller);
+                    let termination = Arc::clone(&caller.data().termination);
+                    let result = self(caller, $($param),*);
+                    let ended = *termination.lock().unwrap();
+                    match ended {
+                        Some(reason) => R::fallible_from_error(anyhow::anyhow!("process {}", reason)),
+                        None => result.into_fallible(),
+                    }
+                }
+            }
+        }
+    };
+}
+
+impl_counted!();
+impl_counted!(A1);
+impl_counted!(A1, A2);
+impl_counted!(A1, A2, A3);
+impl_counted!(A1, A2, A3, A4);
+impl_counted!(A1, A2, A3, A4, A5);
+impl_counted!(A1, A2, A3, A4, A5, A6);
+impl_counted!(A1, A2, A3, A4, A5, A6, A7);
+impl_counted!(A1, A2, A3, A4, A5, A6, A7, A8);
+impl_counted!(A1, A2, A3, A4, A5, A6, A7, A8, A9);
@@ -0,0 +1,59 @@
+#!/usr/bin/env python3
+"""
+applied_record.py
+---------------------------------
+• Replays a consensus file of three batches (an Init, two stdin lines, a
+  quota query, each batch ended by a clock record) with `runtime benchmark`
+  and RUST_LOG=applied_record=info.
+• Checks there is one `applied_record` line per record, with the batch
+  number, index in the batch, type, pid and payload length of that record,
+  and a cursor that goes up by one from 1.
+• Replays the file again and checks the lines are the same, so two runs (or
+  replicas) can be diffed line by line.
+Usage (after `cargo build --bin runtime`, from the repository root):
+    python3 test/applied_record.py
+"""
+import os, re, subprocess, sys, tempfile
+from support import record
+
+GUEST = b'(module (memory (export "memory") 1) (func (export "_start")))'
+BATCHES = [
+    [(2, 0, GUEST), (0, 0, b"clock:1")],
+    [(1, 1, b"fd:0,body:hello"), (1, 1, b"fd:0,body:world"), (0, 0, b"clock:1")],
+    [(10, 1, b""), (0, 0, b"clock:1")],
+]
+TIMEOUT = 30
+LINE = re.compile(r"applied_record\] cursor=(\d+) batch=(\d+) record=(\d+) type=(\d+) pid=(\d+) payload_len=(\d+)$")
+
+
+def replay(path):
+    """The fields of each applied_record line the runtime logs."""
+    result = subprocess.run(["target/debug/runtime", "benchmark", path], capture_output=True, timeout=TIMEOUT,
+                            env={"RUST_LOG": "applied_record=info"})
+    return [tuple(int(field) for field in match.groups())
+            for match in map(LINE.search, result.stderr.decode(errors="replace").splitlines()) if match]
+
+
+def run():
+    with tempfile.NamedTemporaryFile(suffix=".bin", delete=False) as f:
+        f.write(b"".join(record(*r) for batch in BATCHES for r in batch))
+    try:
+        first, second = replay(f.name), replay(f.name)
+    finally:
+        os.unlink(f.name)
+    expected = []
+    for number, batch in enumerate(BATCHES, 1):
+        for index, (msg_type, pid, payload) in enumerate(batch):
+            expected.append((len(expected) + 1, number, index, msg_type, pid, len(payload)))
+    if first != expected:
+        print(f"FAIL: expected the applied records {expected}, got {first}")
+        return 1
+    if second != first:
+        print(f"FAIL: a second replay logged different records: {second}")
+        return 1
+    print(f"PASS: {len(first)} records were logged in order, with the cursor going up by one each time")
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(run())
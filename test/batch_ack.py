@@ -0,0 +1,170 @@
+#!/usr/bin/env python3
+"""
+batch_ack.py
+---------------------------------
+• Stands in for `consensus tcp` and sends a runtime an Init batch, a
+  malformed batch, a clock batch, and the first batch again, as consensus
+  does after a reconnect.
+• Checks the runtime sends a BatchAck (type 15) record for each batch it
+  applied, and again for the batch sent twice, but none for the one it
+  rejected.
+• Then cuts the connection off mid-batch, and checks the runtime opens the
+  next one with a hello carrying a BatchAck for batch 3, its last ack.
+• Runs `consensus tcp` with a runtime, and checks consensus logs the
+  runtime's processed position advancing as the acks arrive.
+• Connects stand-in runtimes to `consensus tcp` with a hello acking no batch
+  and one acking batch 20, and checks consensus sends the first from batch
+  1 and the second from batch 21, instead of the whole history.
+Usage (after `cargo build`, from the repository root; uses port 9000):
+    python3 test/batch_ack.py
+"""
+import glob, os, re, socket, struct, subprocess, sys, threading, time, zlib
+from support import batch, hang_up, read_exact, read_hello, read_outgoing_records, record
+
+GUEST = b"""(module
+  (memory (export "memory") 1)
+  (func (export "_start")))
+"""
+TIMEOUT = 10
+
+
+def answer(conn, number, expected_type):
+    """Returns the records the runtime sends until its ack or rejection of batch `number`."""
+    records = []
+    while not any(msg_type == expected_type and pid == number for msg_type, pid, _ in records):
+        records.extend(read_outgoing_records(conn))
+    return records
+
+
+def stand_in():
+    server = socket.socket()
+    server.setsockopt(socket.SOL_SOCKET, socket.SO_REUSEADDR, 1)
+    server.bind(("127.0.0.1", 9000))
+    server.listen(1)
+    runtime = subprocess.Popen(["target/debug/runtime", "tcp"], stdout=subprocess.DEVNULL, stderr=subprocess.DEVNULL)
+    try:
+        conn, _ = server.accept()
+        conn.settimeout(TIMEOUT)
+        first = batch(1, record(2, 2**64 - 1, GUEST) + record(0, 0, b"clock:1"))
+        records = []
+        conn.sendall(first)
+        records += answer(conn, 1, 15)
+        conn.sendall(batch(2, record(10, 1, b"\x01\x02\x03")))  # a quota is 0 or 8 bytes
+        records += answer(conn, 2, 12)
+        conn.sendall(batch(3, record(0, 0, b"clock:1")))
+        records += answer(conn, 3, 15)
+        conn.sendall(first)
+        records += answer(conn, 1, 15)
+        conn.sendall(struct.pack("<QB", 4, 0) + b"\x10")  # cut off mid-header
+        conn.close()
+        conn, _ = server.accept()
+        conn.settimeout(TIMEOUT)
+        hello = read_hello(conn)
+        hang_up(conn)
+        runtime.wait(timeout=TIMEOUT)
+    except (socket.timeout, EOFError, subprocess.TimeoutExpired) as e:
+        print(f"FAIL: the runtime stopped answering ({e})")
+        return None, None
+    finally:
+        runtime.kill()
+        server.close()
+    return [pid for msg_type, pid, _ in records if msg_type == 15], hello
+
+
+def hello(acked):
+    """A hello in wire format 3 from a runtime whose last ack was for batch `acked`, 0 for none."""
+    data = record(15, acked, b"") if acked else b""
+    return struct.pack("<QBQQ", 0, 0x30 | 0x04 | 1, 2**64 - 1, len(data)) + data + struct.pack("<I", zlib.crc32(data))
+
+
+def first_batch_sent(port, acked):
+    """Connects to consensus as a runtime that acked batch `acked`, and returns
+    the number of the first batch consensus sends it."""
+    with socket.create_connection(("127.0.0.1", port), timeout=TIMEOUT) as conn:
+        conn.sendall(hello(acked))
+        number, _ = struct.unpack("<QB", read_exact(conn, 9, "consensus"))
+        return number
+
+
+def with_consensus():
+    """Returns the processed positions consensus logged for runtime 0."""
+    before = set(glob.glob("sessions/session-*.bin"))
+    consensus = subprocess.Popen(["target/debug/consensus", "tcp"], stdin=subprocess.PIPE,
+                                 stdout=subprocess.DEVNULL, stderr=subprocess.PIPE, env={"RUST_LOG": "info"})
+    log = bytearray()
+
+    def read():
+        for chunk in iter(lambda: consensus.stderr.read1(65536), b""):
+            log.extend(chunk)
+
+    threading.Thread(target=read, daemon=True).start()
+    runtime = None
+    try:
+        time.sleep(0.5)
+        runtime = subprocess.Popen(["target/debug/runtime", "tcp"], stdout=subprocess.DEVNULL,
+                                   stderr=subprocess.DEVNULL)
+        time.sleep(1)
+        consensus.stdin.write(b"exit\n")
+        consensus.stdin.close()
+        consensus.wait(timeout=TIMEOUT)
+        runtime.wait(timeout=TIMEOUT)
+    except subprocess.TimeoutExpired:
+        print("FAIL: consensus or the runtime did not exit")
+        return None
+    finally:
+        consensus.kill()
+        if runtime:
+            runtime.kill()
+        for path in set(glob.glob("sessions/session-*.bin")) - before:
+            os.unlink(path)
+    return [int(n) for n in re.findall(rb"Runtime 0 has processed batches up to (\d+)", log)]
+
+
+def resume():
+    """Returns the first batch consensus sends a runtime that acked nothing,
+    and one that acked batch 20, or None."""
+    before = set(glob.glob("sessions/session-*.bin"))
+    consensus = subprocess.Popen(["target/debug/consensus", "tcp"], stdin=subprocess.PIPE,
+                                 stdout=subprocess.DEVNULL, stderr=subprocess.DEVNULL)
+    try:
+        time.sleep(1)  # consensus cuts a batch every 15ms, so its history is past batch 20
+        return first_batch_sent(9000, 0), first_batch_sent(9000, 20)
+    except (OSError, EOFError) as e:
+        print(f"FAIL: consensus did not send the stand-in runtimes any batch ({e})")
+        return None
+    finally:
+        consensus.kill()
+        consensus.wait()
+        for path in set(glob.glob("sessions/session-*.bin")) - before:
+            os.unlink(path)
+
+
+def run():
+    acks, reconnect_hello = stand_in()
+    if acks is None:
+        return 1
+    if acks != [1, 3, 1]:
+        print(f"FAIL: expected acks for batches 1 and 3 and for batch 1 sent again, got {acks}")
+        return 1
+    if reconnect_hello != (0, [(15, 3, b"")]):
+        print(f"FAIL: expected the runtime to reconnect with a hello acking batch 3, got {reconnect_hello}")
+        return 1
+    positions = with_consensus()
+    if positions is None:
+        return 1
+    if len(positions) < 5 or positions != sorted(set(positions)):
+        print(f"FAIL: expected consensus to track the runtime's position advancing, got {positions}")
+        return 1
+    first = resume()
+    if first is None:
+        return 1
+    if first != (1, 21):
+        print(f"FAIL: expected a new runtime to get batches from 1 and one that acked 20 from 21, got {first}")
+        return 1
+    print(f"PASS: the runtime acked each batch it applied, consensus tracked it up to batch {positions[-1]}, "
+          "and a runtime that reconnects resumes after its last ack")
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(run())
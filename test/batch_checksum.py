@@ -0,0 +1,97 @@
+#!/usr/bin/env python3
+"""
+batch_checksum.py
+---------------------------------
+• Stands in for `consensus tcp` and sends an Init batch in wire format 1,
+  i.e. followed by the CRC32 of its data, with one byte of the data flipped
+  after the checksum was taken. Then it sends the same batch intact.
+• Checks the runtime rejects the corrupted batch (no process started, a
+  BatchRejected record saying "checksum mismatch"), applies the intact one,
+  and sends its own batches in format 2, the newest this stand-in for an
+  older consensus has sent, with a matching checksum.
+Usage (after `cargo build --bin runtime`, from the repository root):
+    python3 test/batch_checksum.py
+"""
+import socket, struct, subprocess, sys, zlib
+from support import read_exact, record
+
+GUEST = b"""(module
+  (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 0) "\\10\\00\\00\\00\\08\\00\\00\\00")
+  (data (i32.const 16) "started\\n")
+  (func (export "_start")
+    (drop (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 32)))))
+"""
+TIMEOUT = 10
+
+
+def batch(number, records, corrupt_at=None):
+    checksum = zlib.crc32(records)
+    if corrupt_at is not None:
+        records = bytearray(records)
+        records[corrupt_at] ^= 0xff
+        records = bytes(records)
+    return struct.pack("<QBQ", number, 0x10, len(records)) + records + struct.pack("<I", checksum)
+
+
+def read_outgoing(conn):
+    """Returns the format of the runtime's next batch after its hello,
+    whether its checksum matched, and its records."""
+    number = 0
+    while number == 0:
+        number, direction = struct.unpack("<QB", read_exact(conn, 9))
+        if direction >> 4 >= 2:
+            read_exact(conn, 8)  # the runtime's clock
+        length, = struct.unpack("<Q", read_exact(conn, 8))
+        data = read_exact(conn, length)
+        checksum_ok = struct.unpack("<I", read_exact(conn, 4))[0] == zlib.crc32(data) if direction >> 4 else False
+    records = []
+    while data:
+        msg_type, pid, size = struct.unpack("<BQI", data[:13])
+        records.append((msg_type, pid, data[13:13 + size]))
+        data = data[13 + size:]
+    return direction >> 4, checksum_ok, records
+
+
+def fail(message, output=b""):
+    print(output.decode(errors="replace"))
+    print(f"FAIL: {message}")
+    return 1
+
+
+def run():
+    init = record(2, 2**64 - 1, GUEST)
+    server = socket.socket()
+    server.setsockopt(socket.SOL_SOCKET, socket.SO_REUSEADDR, 1)
+    server.bind(("127.0.0.1", 9000))
+    server.listen(1)
+    runtime = subprocess.Popen(["target/debug/runtime", "tcp"], env={"RUST_LOG": "info"},
+                               stdout=subprocess.PIPE, stderr=subprocess.STDOUT)
+    conn, _ = server.accept()
+    conn.settimeout(TIMEOUT)
+
+    # flip a byte inside the guest, so the records would still parse
+    conn.sendall(batch(1, init, corrupt_at=len(init) - 10))
+    version, checksum_ok, records = read_outgoing(conn)
+    conn.sendall(batch(2, init))
+    conn.close()
+    server.close()
+
+    try:
+        output, _ = runtime.communicate(timeout=TIMEOUT)
+    except subprocess.TimeoutExpired:
+        runtime.kill()
+        return fail("runtime did not exit")
+    if version != 2 or not checksum_ok:
+        return fail(f"the runtime's batch should be format 2 with a matching CRC32, got format {version}", output)
+    if records != [(12, 1, b"checksum mismatch")]:
+        return fail(f"expected a BatchRejected record for batch 1, got {records}", output)
+    if output.count(b"started\n") != 1:
+        return fail("expected exactly one process to start, from batch 2", output)
+    print("PASS: the corrupted batch was rejected by its checksum and the intact one applied")
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(run())
@@ -0,0 +1,149 @@
+#!/usr/bin/env python3
+"""
+batch_compression.py
+---------------------------------
+• Runs `consensus tcp 127.0.0.1:0` and a runtime, and inits a guest with a
+  1 MiB data segment of repeated text, which it writes to stdout.
+• Checks consensus compresses the Init batch for the runtime, which accepts
+  compressed batches, to a fraction of its size. Checks the runtime
+  decompresses it and the guest prints its segment intact.
+• Opens 25 connections that never send a hello, then starts a second
+  runtime, and checks consensus admits it without waiting out theirs first.
+  Checks the history it is sent is compressed too, and that its guest also
+  prints the segment intact.
+• Connects a stand-in runtime that sends no hello, as one reading only wire
+  format 2 would, and checks it gets the Init batch in format 2 without
+  flags, uncompressed, with the guest's text at the end of the Init record's
+  payload.
+Usage (after `cargo build`, from the repository root):
+    python3 test/batch_compression.py
+"""
+import glob, os, re, socket, struct, subprocess, sys, tempfile, time
+from support import read_exact, wait_for, watch
+
+SEGMENT = b"0123456789abcdef" * 65536 + b"<END>\n"
+GUEST = b"""(module
+  (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 17)
+  (data (i32.const 0) "\\40\\00\\00\\00\\06\\00\\10\\00")
+  (data (i32.const 64) "%s")
+  (func (export "_start")
+    (drop (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 32)))))
+""" % SEGMENT.replace(b"\n", b"\\0a")
+TIMEOUT = 20
+ZSTD = 0x08
+SILENT = 25
+
+
+def start_runtime(port):
+    runtime = subprocess.Popen(["target/debug/runtime", "tcp", f"127.0.0.1:{port}"],
+                               stdout=subprocess.PIPE, stderr=subprocess.PIPE, env={"RUST_LOG": "info"})
+    return runtime, watch(runtime.stdout), watch(runtime.stderr)
+
+
+def read_batch(conn):
+    number, direction = struct.unpack("<QB", read_exact(conn, 9, "consensus"))
+    read_exact(conn, 8, "consensus")  # clock
+    (length,) = struct.unpack("<Q", read_exact(conn, 8, "consensus"))
+    data = read_exact(conn, length, "consensus")
+    read_exact(conn, 4, "consensus")  # checksum
+    return number, direction, data
+
+
+def init_payload(data):
+    """The payload of the first Init record in `data`, if it has one."""
+    while data:
+        msg_type, _pid, length = struct.unpack("<BQI", data[:13])
+        if msg_type == 2:
+            return data[13:13 + length]
+        data = data[13 + length:]
+    return None
+
+
+def stand_in_runtime(port):
+    """Connects without a hello. Returns the direction byte and Init payload
+    of the first batch with an Init record in it, read as uncompressed."""
+    with socket.create_connection(("127.0.0.1", port), timeout=TIMEOUT) as conn:
+        while True:
+            _number, direction, data = read_batch(conn)
+            payload = init_payload(data)
+            if payload is not None:
+                return direction, payload
+
+
+def run():
+    before = set(glob.glob("sessions/session-*.bin"))
+    consensus = subprocess.Popen(["target/debug/consensus", "tcp", "127.0.0.1:0"], stdin=subprocess.PIPE,
+                                 stdout=subprocess.DEVNULL, stderr=subprocess.PIPE,
+                                 env={"REPLICODE_HTTP_PORT": "0", "RUST_LOG": "info"})
+    log = watch(consensus.stderr)
+    runtimes = []
+    with tempfile.NamedTemporaryFile(suffix=".wat", delete=False) as f:
+        f.write(GUEST)
+    try:
+        if not wait_for(lambda: re.search(rb"Listening for runtimes on 127\.0\.0\.1:(\d+)", log), TIMEOUT):
+            print("FAIL: consensus did not log the port it listens for runtimes on")
+            return 1
+        port = int(re.search(rb"Listening for runtimes on 127\.0\.0\.1:(\d+)", log).group(1))
+
+        runtimes.append(start_runtime(port))
+        if not wait_for(lambda: b"Runtime 0 added to connection pool" in log, TIMEOUT):
+            print("FAIL: consensus did not accept the runtime")
+            return 1
+        if b"Runtime 0 reads wire format 3 and accepts compressed batches" not in log:
+            print("FAIL: the runtime did not say it reads wire format 3 and compressed batches")
+            return 1
+        consensus.stdin.write(f"init {f.name}\n".encode())
+        consensus.stdin.flush()
+        if not wait_for(lambda: len(runtimes[0][1]) >= len(SEGMENT), TIMEOUT):
+            print(f"FAIL: the guest printed {len(runtimes[0][1])} of {len(SEGMENT)} bytes")
+            return 1
+        sizes = re.search(rb"Compressed batch \d+ from (\d+) to (\d+) bytes", log)
+        if not sizes or int(sizes.group(2)) * 10 > int(sizes.group(1)):
+            print("FAIL: consensus did not compress the Init batch to under a tenth of its size")
+            return 1
+
+        # Runtimes 1 to SILENT stay quiet, so consensus waits out each one's hello
+        silent = [socket.create_connection(("127.0.0.1", port)) for _ in range(SILENT)]
+        started = time.monotonic()
+        runtimes.append(start_runtime(port))
+        if not wait_for(lambda: f"Runtime {SILENT + 1} added to connection pool".encode() in log, TIMEOUT):
+            print("FAIL: consensus did not accept the late runtime")
+            return 1
+        if time.monotonic() - started > 2:
+            print(f"FAIL: the late runtime waited {time.monotonic() - started:.1f}s behind {SILENT} silent connections")
+            return 1
+        for conn in silent:
+            conn.close()
+        if not wait_for(lambda: len(runtimes[1][1]) >= len(SEGMENT), TIMEOUT):
+            print(f"FAIL: the late runtime's guest printed {len(runtimes[1][1])} of {len(SEGMENT)} bytes")
+            return 1
+        for i, (_, output, runtime_log) in enumerate(runtimes):
+            if bytes(output) != SEGMENT:
+                print(f"FAIL: runtime {i}'s guest did not print its data segment intact")
+                return 1
+            if not re.search(rb"Decompressed batch \d+ from \d+ to \d+ bytes", runtime_log):
+                print(f"FAIL: runtime {i} did not get a compressed batch")
+                return 1
+
+        direction, payload = stand_in_runtime(port)
+        if direction != 0x20:
+            print(f"FAIL: a runtime without a hello was sent direction byte {direction:#04x}, not format 2 without flags")
+            return 1
+        if not payload.endswith(GUEST):
+            print("FAIL: a runtime without a hello was not sent the Init batch uncompressed")
+            return 1
+    finally:
+        os.unlink(f.name)
+        for process in [runtime for runtime, _, _ in runtimes] + [consensus]:
+            process.kill()
+            process.wait()
+        for session in set(glob.glob("sessions/session-*.bin")) - before:
+            os.unlink(session)
+    print(f"PASS: a {int(sizes.group(1))}-byte Init batch went out as {int(sizes.group(2))} bytes, both runtimes "
+          "decoded it intact, and a runtime without compression got it as it was")
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(run())
@@ -0,0 +1,74 @@
+#!/usr/bin/env python3
+"""
+batch_gap.py
+---------------------------------
+• Stands in for `consensus tcp` and sends a runtime batch 1, with the Init of
+  a guest that echoes what it reads from stdin, then batch 3, with a line for
+  it, as if batch 2 had never been sent.
+• Checks the runtime drops the connection rather than apply batch 3, and
+  opens the next one with a hello acking batch 1.
+• Sends batches 2 and 3 on the new connection, each with a line, and checks
+  the guest echoes both in order, so nothing was applied past the gap.
+Usage (after `cargo build --bin runtime`, from the repository root):
+    python3 test/batch_gap.py
+"""
+import socket, subprocess, sys
+from support import batch, hang_up, read_hello, record
+
+GUEST = b"""(module
+  (import "wasi_snapshot_preview1" "fd_read" (func $fd_read (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (func (export "_start")
+    (loop $echo
+      (i32.store (i32.const 0) (i32.const 64))
+      (i32.store (i32.const 4) (i32.const 64))
+      (drop (call $fd_read (i32.const 0) (i32.const 0) (i32.const 1) (i32.const 8)))
+      (i32.store (i32.const 4) (i32.load (i32.const 8)))
+      (drop (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 8)))
+      (br $echo))))
+"""
+TIMEOUT = 10
+
+
+def run():
+    server = socket.socket()
+    server.bind(("127.0.0.1", 0))
+    server.listen(1)
+    server.settimeout(TIMEOUT)
+    port = server.getsockname()[1]
+    runtime = subprocess.Popen(["target/debug/runtime", "tcp", f"127.0.0.1:{port}"],
+                               stdout=subprocess.PIPE, stderr=subprocess.DEVNULL)
+    try:
+        conn, _ = server.accept()
+        conn.settimeout(TIMEOUT)
+        read_hello(conn)
+        conn.sendall(batch(1, record(2, 0, GUEST) + record(0, 0, b"clock:1")))
+        conn.sendall(batch(3, record(1, 1, b"fd:0,body:c\n") + record(0, 0, b"clock:1")))
+        conn, _ = server.accept()
+        conn.settimeout(TIMEOUT)
+        _, records = read_hello(conn)
+        conn.sendall(batch(2, record(1, 1, b"fd:0,body:b\n") + record(0, 0, b"clock:1")))
+        conn.sendall(batch(3, record(1, 1, b"fd:0,body:c\n") + record(0, 0, b"clock:1")))
+        hang_up(conn)
+        output, _ = runtime.communicate(timeout=TIMEOUT)
+    except (socket.timeout, EOFError, subprocess.TimeoutExpired) as e:
+        print(f"FAIL: the runtime did not reconnect after the gap and stop ({e})")
+        return 1
+    finally:
+        runtime.kill()
+        server.close()
+
+    if records != [(15, 1, b"")]:
+        print(f"FAIL: expected the runtime to reconnect with a hello acking batch 1, got {records}")
+        return 1
+    print("PASS: the runtime refused a batch past a gap and reconnected from the last one it applied")
+    if output != b"b\nc\n":
+        print(f"FAIL: expected the guest to echo the lines of batches 2 and 3 in order, got {output}")
+        return 1
+    print("PASS: the missing batch was applied before the one after it")
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(run())
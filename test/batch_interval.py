@@ -0,0 +1,85 @@
+#!/usr/bin/env python3
+"""
+batch_interval.py
+---------------------------------
+• Runs `consensus tcp` with REPLICODE_BATCH_INTERVAL_MS=1000 and connects to
+  it as a runtime, sending no commands, then collects the batches it is sent
+  over 3.5 seconds.
+• Checks three batches arrived, each holding exactly one clock record of
+  1000000000 ns, so the clock advanced as much as the time that passed.
+Usage (after `cargo build --bin consensus`, from the repository root; uses port 9000):
+    python3 test/batch_interval.py
+"""
+import glob, os, socket, struct, subprocess, sys, time
+from support import read_exact
+
+WINDOW = 3.5
+
+
+def read_batch(conn):
+    """Returns the number and the records of the next batch."""
+    number, direction = struct.unpack("<QB", read_exact(conn, 9, "consensus"))
+    if direction >> 4 >= 2:
+        read_exact(conn, 8, "consensus")  # the runtime's clock
+    length, = struct.unpack("<Q", read_exact(conn, 8, "consensus"))
+    data = read_exact(conn, length, "consensus")
+    if direction >> 4:
+        read_exact(conn, 4, "consensus")  # CRC32 of the data
+    records = []
+    while data:
+        msg_type, pid, size = struct.unpack("<BQI", data[:13])
+        records.append((msg_type, pid, data[13:13 + size]))
+        data = data[13 + size:]
+    return number, records
+
+
+def collect_batches(conn, deadline):
+    batches = []
+    while True:
+        remaining = deadline - time.monotonic()
+        if remaining <= 0:
+            return batches
+        conn.settimeout(remaining)
+        try:
+            _number, records = read_batch(conn)
+            batches.append(records)
+        except socket.timeout:
+            return batches
+
+
+def run():
+    before = set(glob.glob("sessions/session-*.bin"))
+    consensus = subprocess.Popen(["target/debug/consensus", "tcp"], stdin=subprocess.PIPE,
+                                 stdout=subprocess.DEVNULL, stderr=subprocess.DEVNULL,
+                                 env={"REPLICODE_BATCH_INTERVAL_MS": "1000"})
+    started = time.monotonic()
+    try:
+        for _ in range(50):
+            try:
+                conn = socket.create_connection(("127.0.0.1", 9000))
+                break
+            except ConnectionRefusedError:
+                time.sleep(0.02)
+        else:
+            print("FAIL: consensus is not listening on port 9000")
+            return 1
+        batches = collect_batches(conn, started + WINDOW)
+        conn.close()
+    finally:
+        consensus.kill()
+        consensus.wait()
+        for path in set(glob.glob("sessions/session-*.bin")) - before:
+            os.unlink(path)
+    if len(batches) != 3:
+        print(f"FAIL: expected 3 batches in {WINDOW}s with a 1s interval, got {len(batches)}")
+        return 1
+    for records in batches:
+        if records != [(0, 0, b"clock:1000000000")]:
+            print(f"FAIL: expected each batch to be one 1s clock record, got {records}")
+            return 1
+    print("PASS: consensus cut a batch with one 1s clock record every second")
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(run())
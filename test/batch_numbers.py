@@ -0,0 +1,73 @@
+#!/usr/bin/env python3
+"""
+batch_numbers.py
+---------------------------------
+• Stands in for `consensus tcp`: starts a process in batch 1 that prints the
+  number `__builtin_rt_batch()` returns, then again after each line it reads
+  from stdin, and sends it a line in each of batches 2, 5 and 7, with empty
+  batches between them, since a runtime refuses a gap in the numbers.
+• Checks the guest saw 1, 2, 5 and 7: the batches that let it run.
+Usage (after `cargo build --bin runtime`, from the repository root):
+    python3 test/batch_numbers.py
+"""
+import socket, subprocess, sys, time
+from support import batch, hang_up, record
+
+GUEST = b"""(module
+  (import "env" "__builtin_rt_batch" (func $batch (result i64)))
+  (import "wasi_snapshot_preview1" "fd_read" (func $fd_read (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 16) "\\20\\00\\00\\00\\02\\00\\00\\00")
+  (data (i32.const 32) "?\\n")
+  (func $print_batch
+    ;; one digit is enough for this test
+    (i32.store8 (i32.const 32) (i32.add (i32.const 48) (i32.wrap_i64 (call $batch))))
+    (drop (call $fd_write (i32.const 1) (i32.const 16) (i32.const 1) (i32.const 12))))
+  (func (export "_start")
+    (call $print_batch)
+    (loop $next
+      (i32.store (i32.const 0) (i32.const 100))
+      (i32.store (i32.const 4) (i32.const 64))
+      (drop (call $fd_read (i32.const 0) (i32.const 0) (i32.const 1) (i32.const 8)))
+      (if (i32.eqz (i32.load (i32.const 8))) (then (return)))
+      (call $print_batch)
+      (br $next))))
+"""
+TIMEOUT = 10
+
+
+def run():
+    server = socket.socket()
+    server.setsockopt(socket.SOL_SOCKET, socket.SO_REUSEADDR, 1)
+    server.bind(("127.0.0.1", 9000))
+    server.listen(1)
+    runtime = subprocess.Popen(["target/debug/runtime", "tcp"],
+                               stdout=subprocess.PIPE, stderr=subprocess.DEVNULL)
+    conn, _ = server.accept()
+    conn.sendall(batch(1, record(2, 0, GUEST)))
+    for number in range(2, 8):
+        if number in (2, 5, 7):
+            time.sleep(0.5)  # let the guest block on stdin again
+            conn.sendall(batch(number, record(1, 1, b"fd:0,body:next")))
+        else:
+            conn.sendall(batch(number, b""))
+    time.sleep(0.5)
+    hang_up(conn)
+    server.close()
+
+    try:
+        output, _ = runtime.communicate(timeout=TIMEOUT)
+    except subprocess.TimeoutExpired:
+        runtime.kill()
+        print("FAIL: runtime did not exit")
+        return 1
+    if output.split() != [b"1", b"2", b"5", b"7"]:
+        print(f"FAIL: expected batches 1, 2, 5 and 7, got {output!r}")
+        return 1
+    print("PASS: the guest saw the number of each batch that let it run")
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(run())
@@ -0,0 +1,67 @@
+#!/usr/bin/env python3
+"""
+batch_rejection.py
+---------------------------------
+• Stands in for `consensus tcp` and sends a batch whose second record is
+  malformed after a valid Init, then a batch with the same Init alone.
+• Checks the runtime rejects the first batch whole (no process started,
+  a BatchRejected record sent back) and applies the second normally.
+Usage (after `cargo build --bin runtime`, from the repository root):
+    python3 test/batch_rejection.py
+"""
+import socket, subprocess, sys
+from support import batch, read_outgoing_records, record
+
+GUEST = b"""(module
+  (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 0) "\\10\\00\\00\\00\\08\\00\\00\\00")
+  (data (i32.const 16) "started\\n")
+  (func (export "_start")
+    (drop (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 32)))))
+"""
+TIMEOUT = 10
+
+
+def fail(message, output=b""):
+    print(output.decode(errors="replace"))
+    print(f"FAIL: {message}")
+    return 1
+
+
+def run():
+    init = record(2, 2**64 - 1, GUEST)
+    bad_quota = record(10, 1, b"\x01\x02\x03")  # a quota is 0 or 8 bytes
+    server = socket.socket()
+    server.setsockopt(socket.SOL_SOCKET, socket.SO_REUSEADDR, 1)
+    server.bind(("127.0.0.1", 9000))
+    server.listen(1)
+    runtime = subprocess.Popen(["target/debug/runtime", "tcp"], env={"RUST_LOG": "info"},
+                               stdout=subprocess.PIPE, stderr=subprocess.STDOUT)
+    conn, _ = server.accept()
+    conn.settimeout(TIMEOUT)
+
+    conn.sendall(batch(1, init + bad_quota))
+    records = read_outgoing_records(conn)
+    conn.sendall(batch(2, init))
+    conn.close()
+    server.close()
+
+    try:
+        output, _ = runtime.communicate(timeout=TIMEOUT)
+    except subprocess.TimeoutExpired:
+        runtime.kill()
+        return fail("runtime did not exit")
+    rejected = [pid for msg_type, pid, _ in records if msg_type == 12]
+    if rejected != [1]:
+        return fail(f"expected a BatchRejected record for batch 1, got {records}", output)
+    if output.count(b"started\n") != 1:
+        return fail("expected exactly one process to start, from batch 2", output)
+    if b"Added new process 1 " not in output:
+        return fail("the rejected batch used up a pid", output)
+    print("PASS: the malformed batch was rejected whole and the next one applied")
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(run())
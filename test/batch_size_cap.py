@@ -0,0 +1,160 @@
+#!/usr/bin/env python3
+"""
+batch_size_cap.py
+---------------------------------
+• Runs `consensus tcp` with a 2-second batch interval and
+  REPLICODE_BATCH_MAX_BYTES=8192, connects to it as a runtime, and floods
+  it with 2000 `msg` commands of 200 bytes each. Commands wait for the next
+  batch in the same buffer as the NAT table's inbound messages, so this is
+  the flood a busy guest socket would cause.
+• Checks every batch stays under the cap plus one record and its clock
+  record, that batches were cut early instead of every 2 seconds, and that
+  all 2000 messages arrived once each and in order.
+• Checks the clock records add up to no more than the time that passed, so
+  early batches do not run the runtimes' clock ahead.
+• Runs it again, and as the runtime sends it 10000 NetworkOut records that
+  close a socket that was never opened. Checks the 10000 status records the
+  NAT table answers with are held to the same cap and cut early too.
+Usage (after `cargo build --bin consensus`, from the repository root; uses port 9000):
+    python3 test/batch_size_cap.py
+"""
+import glob, os, socket, struct, subprocess, sys, time, zlib
+from support import read_exact, record
+
+CAP = 8192
+MESSAGES = 2000
+CLOSES = 10000
+TIMEOUT = 20
+
+
+def body(i):
+    """The 200-byte body of the ith message, numbered so their order shows."""
+    return f"{i:05d}".ljust(200, "x")
+
+
+def read_batch(conn):
+    """Returns the number, the size of the records and the records of the next batch."""
+    number, direction = struct.unpack("<QB", read_exact(conn, 9, "consensus"))
+    if direction >> 4 >= 2:
+        read_exact(conn, 8, "consensus")  # the runtime's clock
+    length, = struct.unpack("<Q", read_exact(conn, 8, "consensus"))
+    data = read_exact(conn, length, "consensus")
+    if direction >> 4:
+        read_exact(conn, 4, "consensus")  # CRC32 of the data
+    records = []
+    rest = data
+    while rest:
+        msg_type, pid, size = struct.unpack("<BQI", rest[:13])
+        records.append((msg_type, pid, rest[13:13 + size]))
+        rest = rest[13 + size:]
+    return number, len(data), records
+
+
+def start_consensus():
+    """Starts consensus with the cap, and connects to it as a runtime."""
+    consensus = subprocess.Popen(["target/debug/consensus", "tcp"], stdin=subprocess.PIPE,
+                                 stdout=subprocess.DEVNULL, stderr=subprocess.DEVNULL,
+                                 env={"REPLICODE_BATCH_INTERVAL_MS": "2000", "REPLICODE_BATCH_MAX_BYTES": str(CAP)})
+    for _ in range(50):
+        try:
+            conn = socket.create_connection(("127.0.0.1", 9000))
+            conn.settimeout(TIMEOUT)
+            return consensus, conn
+        except ConnectionRefusedError:
+            time.sleep(0.02)
+    consensus.kill()
+    consensus.wait()
+    return None, None
+
+
+def flood(send, count, count_type, largest_record):
+    """Calls `send` on a runtime's connection to consensus, and reads batches
+    until `count` records of `count_type` came. Returns an error message, or
+    None and a summary if every batch stayed under the cap."""
+    before = set(glob.glob("sessions/session-*.bin"))
+    consensus, conn = start_consensus()
+    if not consensus:
+        return "consensus is not listening on port 9000", None
+    batches = []
+    try:
+        started = time.monotonic()
+        send(consensus, conn)
+        received = 0
+        while received < count:
+            number, size, records = read_batch(conn)
+            batches.append((number, size, records))
+            received += sum(1 for msg_type, _, _ in records if msg_type == count_type)
+        elapsed = time.monotonic() - started
+        conn.close()
+    except (socket.timeout, EOFError) as e:
+        return f"stopped receiving batches ({e}) after {len(batches)}", None
+    finally:
+        consensus.kill()
+        consensus.wait()
+        for path in set(glob.glob("sessions/session-*.bin")) - before:
+            os.unlink(path)
+    largest = max(size for _, size, _ in batches)
+    if largest >= CAP + largest_record + 64:
+        return f"a batch held {largest} bytes of records, over the {CAP}-byte cap", None
+    numbers = [number for number, _, _ in batches]
+    if numbers != list(range(numbers[0], numbers[0] + len(numbers))):
+        return f"batch numbers are not consecutive: {numbers}", None
+    if len(batches) < 10:
+        return f"expected the flood to be cut into early batches, got {len(batches)} in {elapsed:.1f}s", None
+    clock = sum(int(payload.split(b":")[1]) for _, _, records in batches for msg_type, _, payload in records
+                if msg_type == 0) / 1e9
+    if clock > elapsed + 2.5:
+        return f"the clock records add up to {clock:.2f}s, but only {elapsed:.2f}s passed", None
+    return None, (batches, largest)
+
+
+def commands():
+    def send(consensus, _conn):
+        consensus.stdin.write("".join(f"msg 1 {body(i)}\n" for i in range(MESSAGES)).encode())
+        consensus.stdin.flush()
+
+    error, result = flood(send, MESSAGES, 1, 13 + len(body(0)))
+    if error:
+        return error
+    batches, largest = result
+    bodies = [payload for _, _, records in batches for msg_type, _, payload in records if msg_type == 1]
+    if bodies != [body(i).encode() for i in range(MESSAGES)]:
+        return f"expected {MESSAGES} messages in order, got {len(bodies)}"
+    print(f"{MESSAGES} messages arrived in order in {len(batches)} batches of at most {largest} bytes")
+    return None
+
+
+def outgoing_batch(number, records):
+    """An outgoing batch from a runtime, in wire format 2."""
+    return (struct.pack("<QBQQ", number, 0x21, 2**64 - 1, len(records)) + records
+            + struct.pack("<I", zlib.crc32(records)))
+
+
+def nat_statuses():
+    # NetworkOperation::Close (variant 2) of port 7, which process 1 never opened
+    close = record(5, 1, struct.pack("<IH", 2, 7))
+
+    def send(_consensus, conn):
+        for number in range(1, CLOSES // 100 + 1):
+            conn.sendall(outgoing_batch(number, close * 100))
+
+    error, result = flood(send, CLOSES, 3, 128)
+    if error:
+        return error
+    batches, largest = result
+    print(f"{CLOSES} NAT status records arrived in {len(batches)} batches of at most {largest} bytes")
+    return None
+
+
+def run():
+    for check in (commands, nat_statuses):
+        error = check()
+        if error:
+            print(f"FAIL: {error}")
+            return 1
+    print("PASS: floods of commands and of NAT status records were both cut into batches under the cap")
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(run())
@@ -0,0 +1,128 @@
+#!/usr/bin/env python3
+"""
+cancel_connect.py
+---------------------------------
+• Runs `consensus tcp` and a runtime, and starts a guest that connects to a
+  local listener whose backlog is full, so the NAT's connect hangs, and then
+  prints the errno sock_connect returned.
+• Checks /status lists the connect as pending, types `cancelnetwork 1`, and
+  checks the guest prints 13 (ECONNABORTED) while the connect still hangs.
+• Then makes room in the backlog so the connect goes through, and checks the
+  NAT drops it: the listener sees the connection closed, /status no longer
+  lists it, and the process holds no socket.
+Usage (after `cargo build`, from the repository root; uses ports 9000 and 8080):
+    python3 test/cancel_connect.py
+"""
+import glob, json, os, socket, subprocess, sys, tempfile, time, urllib.request
+from support import wait_for, watch
+
+GUEST = """(module
+  (import "wasi_snapshot_preview1" "sock_open" (func $sock_open (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "sock_connect" (func $sock_connect (param i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  ;; sockaddr_in for 127.0.0.1:PORT
+  (data (i32.const 32) "\\02\\00PORTBYTES\\7f\\00\\00\\01\\00\\00\\00\\00\\00\\00\\00\\00")
+  (func (export "_start")
+    (local $errno i32)
+    (drop (call $sock_open (i32.const 1) (i32.const 1) (i32.const 0) (i32.const 0)))
+    (local.set $errno (call $sock_connect (i32.load (i32.const 0)) (i32.const 32) (i32.const 16)))
+    ;; "errno NN\\n"
+    (i64.store (i32.const 64) (i64.const 0x206f6e727265))
+    (i32.store8 (i32.const 70) (i32.add (i32.const 48) (i32.div_u (local.get $errno) (i32.const 10))))
+    (i32.store8 (i32.const 71) (i32.add (i32.const 48) (i32.rem_u (local.get $errno) (i32.const 10))))
+    (i32.store8 (i32.const 72) (i32.const 10))
+    (i32.store (i32.const 8) (i32.const 64))
+    (i32.store (i32.const 12) (i32.const 9))
+    (drop (call $fd_write (i32.const 1) (i32.const 8) (i32.const 1) (i32.const 16)))))
+"""
+TIMEOUT = 10
+
+
+def status():
+    try:
+        with urllib.request.urlopen("http://127.0.0.1:8080/status", timeout=TIMEOUT) as response:
+            return json.load(response)
+    except OSError:
+        return {}
+
+
+def pending_connects():
+    return [op for op in status().get("pending", []) if op.get("operation") == "connect"]
+
+
+def run():
+    # With a backlog of 0 and one connection queued, the next connect hangs
+    server = socket.socket()
+    server.bind(("127.0.0.1", 0))
+    server.listen(0)
+    port = server.getsockname()[1]
+    queued = socket.create_connection(("127.0.0.1", port))
+    workdir = tempfile.mkdtemp()
+    path = os.path.join(workdir, "connect.wat")
+    with open(path, "w") as f:
+        f.write(GUEST.replace("PORTBYTES", "".join(f"\\{b:02x}" for b in port.to_bytes(2, "big"))))
+    before = set(glob.glob("sessions/session-*.bin"))
+
+    consensus = subprocess.Popen(["target/debug/consensus", "tcp"], stdin=subprocess.PIPE,
+                                 stdout=subprocess.DEVNULL, stderr=subprocess.DEVNULL)
+    time.sleep(0.5)
+    runtime = subprocess.Popen(["target/debug/runtime", "tcp"], stdout=subprocess.PIPE,
+                               stderr=subprocess.DEVNULL)
+    output = watch(runtime.stdout)
+    try:
+        time.sleep(0.5)
+        consensus.stdin.write(f"init {path}\n".encode())
+        consensus.stdin.flush()
+        if not wait_for(lambda: pending_connects(), TIMEOUT):
+            print("FAIL: /status never listed the hanging connect as pending")
+            return 1
+        consensus.stdin.write(b"cancelnetwork 1\n")
+        consensus.stdin.flush()
+        if not wait_for(lambda: b"errno " in output, TIMEOUT):
+            print("FAIL: the guest was not unblocked by cancelnetwork")
+            return 1
+        if b"errno 13\n" not in output:
+            print(f"FAIL: expected sock_connect to fail with ECONNABORTED (13), got {bytes(output)}")
+            return 1
+        print("PASS: cancelnetwork failed the pending connect with ECONNABORTED")
+
+        # Accepting the queued connection lets the NAT's connect through
+        first, _ = server.accept()
+        server.settimeout(TIMEOUT)
+        late, _ = server.accept()
+        late.settimeout(TIMEOUT)
+        dropped = late.recv(1) == b""
+        late.close()
+        first.close()
+        current = status()
+        held = current.get("processes", {}).get("1", {}).get("open_sockets", 0)
+        if not dropped or pending_connects() or held:
+            print(f"FAIL: expected the NAT to drop the late connection, got closed={dropped}, "
+                  f"pending={current.get('pending')}, open sockets={held}")
+            return 1
+        print("PASS: the NAT dropped the connect once it went through")
+        return 0
+    except OSError as e:
+        print(f"FAIL: {e}")
+        return 1
+    finally:
+        consensus.stdin.write(b"exit\n")
+        consensus.stdin.close()
+        try:
+            runtime.wait(timeout=TIMEOUT)
+            consensus.wait(timeout=TIMEOUT)
+        except subprocess.TimeoutExpired:
+            runtime.kill()
+            consensus.kill()
+            print("FAIL: consensus or the runtime did not exit")
+        queued.close()
+        server.close()
+        os.unlink(path)
+        os.rmdir(workdir)
+        for session in set(glob.glob("sessions/session-*.bin")) - before:
+            os.unlink(session)
+
+
+if __name__ == "__main__":
+    sys.exit(run())
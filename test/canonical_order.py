@@ -0,0 +1,117 @@
+#!/usr/bin/env python3
+"""
+canonical_order.py
+---------------------------------
+• Replays two consensus files with `runtime benchmark` that start two guests
+  which each print <N> once consensus sends them a line on stdin, and then
+  send both guests a line in one batch: to process 1 first in one file, and
+  to process 2 first in the other. Checks both replays print <1><2>.
+• Replays two files that send one guest a Pause and a Resume in one batch,
+  in two orders, and its line in the next. Checks both pause and then
+  resume the guest, which prints, since the Pause sorts first either way.
+• Starts `runtime follow` on a file holding a guest's Init, then appends
+  its line without the clock record that ends the batch. Checks the line is
+  applied only once the clock record is appended, so where a batch ends does
+  not depend on how much of it had been written when the runtime read it.
+Usage (after `cargo build --bin runtime`, from the repository root):
+    python3 test/canonical_order.py
+"""
+import os, re, subprocess, sys, tempfile, time
+from support import record, wait_for, watch
+
+GUEST = b"""(module
+  (import "wasi_snapshot_preview1" "fd_read" (func $fd_read (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 64) "<N>")
+  (func (export "_start")
+    (i32.store (i32.const 16) (i32.const 128))
+    (i32.store (i32.const 20) (i32.const 16))
+    (drop (call $fd_read (i32.const 0) (i32.const 16) (i32.const 1) (i32.const 24)))
+    (i32.store (i32.const 0) (i32.const 64))
+    (i32.store (i32.const 4) (i32.const 3))
+    (drop (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 8)))))
+"""
+INIT = record(2, 0, GUEST.replace(b"<N>", b"<1>"))
+CLOCK = record(0, 0, b"clock:1")
+TIMEOUT = 30
+
+
+def line(pid):
+    return record(1, pid, b"fd:0,body:go")
+
+
+def replay(records):
+    """Returns what the guests print, and the runtime's log."""
+    with tempfile.NamedTemporaryFile(suffix=".bin", delete=False) as f:
+        f.write(records)
+    try:
+        result = subprocess.run(["target/debug/runtime", "benchmark", f.name], env={"RUST_LOG": "info"},
+                                capture_output=True, timeout=TIMEOUT)
+    finally:
+        os.unlink(f.name)
+    return result.stdout.decode(errors="replace"), result.stderr.decode(errors="replace")
+
+
+def lines_in_two_orders():
+    start = INIT + record(2, 0, GUEST.replace(b"<N>", b"<2>")) + CLOCK * 2
+    outputs = [replay(start + line(first) + line(second) + CLOCK)[0] for first, second in ((1, 2), (2, 1))]
+    printed = ["".join(re.findall(r"<\d>", output)) for output in outputs]
+    if printed != ["<1><2>", "<1><2>"]:
+        return f"expected both orders to print <1><2>, got {printed}"
+    return None
+
+
+def controls_in_two_orders():
+    pause, resume = record(7, 1, b""), record(8, 1, b"")
+    results = []
+    for batch in (pause + resume, resume + pause):
+        output, log = replay(INIT + CLOCK * 2 + batch + CLOCK + line(1) + CLOCK)
+        results.append((re.findall(r"(Paused|Resumed) process 1", log), "<1>" in output))
+    if results[0] != results[1]:
+        return f"the same batch in two orders paused and printed differently: {results}"
+    if results[0] != (["Paused", "Resumed"], True):
+        return f"expected the guest paused, resumed and printing, got {results[0]}"
+    return None
+
+
+def follow_waits_for_clock():
+    path = os.path.join(tempfile.mkdtemp(), "consensus_input.bin")
+    with open(path, "wb") as f:
+        f.write(INIT + CLOCK)
+    runtime = subprocess.Popen(["target/debug/runtime", "follow", path], stdout=subprocess.DEVNULL,
+                               stderr=subprocess.PIPE, env={"RUST_LOG": "info"})
+    log = watch(runtime.stderr)
+    try:
+        if not wait_for(lambda: b"Process 1 blocked" in log, TIMEOUT):
+            return "the guest never started and blocked on stdin"
+        with open(path, "ab") as f:
+            f.write(line(1))
+        time.sleep(1)
+        if b"Added input to process 1" in log:
+            return "the line was applied before the clock record that ends its batch was written"
+        with open(path, "ab") as f:
+            f.write(CLOCK)
+        if not wait_for(lambda: b"Process 1 finished (exited)" in log, TIMEOUT):
+            return "the line was not applied once its batch was complete"
+    finally:
+        runtime.kill()
+        runtime.wait()
+        os.unlink(path)
+        os.rmdir(os.path.dirname(path))
+    return None
+
+
+def run():
+    for check in (lines_in_two_orders, controls_in_two_orders, follow_waits_for_clock):
+        error = check()
+        if error:
+            print(f"FAIL: {error}")
+            return 1
+    print("PASS: batches were applied the same way whatever order their records came in, "
+          "and a followed batch only once it was complete")
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(run())
@@ -0,0 +1,120 @@
+#!/usr/bin/env python3
+"""
+client_timeout.py
+---------------------------------
+• Starts a server that accepts connections and reads requests but never
+  replies.
+• Runs `consensus kv-client` with `--timeout 1`, and `consensus image-client`
+  with REPLICODE_CLIENT_TIMEOUT=1, each sending one request to it.
+• Checks each client gives up within a few seconds with a timeout error and
+  a nonzero exit, instead of waiting for the reply forever.
+• Runs `consensus kv-client --timeout 1 --retries 1` against a server that
+  never answers its first connection but answers the ones after it, and
+  checks the client retries on a new connection, prints the value and exits
+  with status 0.
+Usage (after `cargo build --bin consensus`, from the repository root):
+    python3 test/client_timeout.py
+"""
+import os, socket, subprocess, sys, threading, time
+
+WINDOW = 5  # seconds a client with a 1s timeout may take to give up
+
+
+def drain(conn):
+    """Reads from `conn` until it closes, never answering."""
+    while conn.recv(4096):
+        pass
+
+
+def silent_server(server):
+    """Accepts connections and reads from them, never answering."""
+    connections = []
+    while True:
+        try:
+            conn, _ = server.accept()
+        except OSError:
+            return
+        connections.append(conn)
+        threading.Thread(target=drain, args=(conn,), daemon=True).start()
+
+
+def stalls_once(server):
+    """Never answers the first connection; answers GET and QUIT on later ones."""
+    first = True
+    while True:
+        try:
+            conn, _ = server.accept()
+        except OSError:
+            return
+        if first:
+            first = False
+            threading.Thread(target=drain, args=(conn,), daemon=True).start()
+            continue
+        with conn:
+            request = conn.makefile("rb").readline()
+            conn.sendall(b"VALUE 5\nhello" if request.startswith(b"GET ") else b"BYE\n")
+
+
+def run_client(args, typed, env=None):
+    """Returns the client's exit code, stderr and how long it ran."""
+    start = time.time()
+    try:
+        result = subprocess.run(["target/debug/consensus"] + args, input=typed, capture_output=True,
+                                timeout=WINDOW * 4, env={**os.environ, **(env or {})})
+    except subprocess.TimeoutExpired:
+        return None, "", time.time() - start
+    return result.returncode, result.stderr.decode(errors="replace"), time.time() - start
+
+
+def run():
+    server = socket.socket()
+    server.bind(("127.0.0.1", 0))
+    server.listen(4)
+    port = str(server.getsockname()[1])
+    threading.Thread(target=silent_server, args=(server,), daemon=True).start()
+    try:
+        clients = {
+            "kv-client": run_client(["kv-client", "127.0.0.1", port, "--timeout", "1"], b"get greeting\n"),
+            "image-client": run_client(["image-client", "127.0.0.1", port], b"get photo.jpg\n",
+                                       env={"REPLICODE_CLIENT_TIMEOUT": "1"}),
+        }
+    finally:
+        server.close()
+    for name, (code, stderr, elapsed) in clients.items():
+        if code is None:
+            print(f"FAIL: {name} was still waiting for the silent server after {elapsed:.0f}s")
+            return 1
+        if code == 0 or "timed out" not in stderr:
+            print(f"FAIL: {name} exited with {code} and no timeout error: {stderr.strip()[-200:]}")
+            return 1
+        if elapsed > WINDOW:
+            print(f"FAIL: {name} took {elapsed:.1f}s to give up with a 1s timeout")
+            return 1
+    print("PASS: " + ", ".join(f"{name} timed out in {elapsed:.1f}s" for name, (_, _, elapsed) in clients.items()))
+
+    server = socket.socket()
+    server.bind(("127.0.0.1", 0))
+    server.listen(4)
+    port = str(server.getsockname()[1])
+    threading.Thread(target=stalls_once, args=(server,), daemon=True).start()
+    start = time.time()
+    try:
+        result = subprocess.run(["target/debug/consensus", "kv-client", "127.0.0.1", port, "--timeout", "1",
+                                 "--retries", "1"], input=b"get greeting\nquit\n", capture_output=True,
+                                timeout=WINDOW * 4)
+    except subprocess.TimeoutExpired:
+        print("FAIL: kv-client with --retries 1 was still waiting after the retry")
+        return 1
+    finally:
+        server.close()
+    stdout, stderr = result.stdout.decode(errors="replace"), result.stderr.decode(errors="replace")
+    if "retry 1/1" not in stderr or "Server response: VALUE hello" not in stdout or result.returncode != 0:
+        print(f"FAIL: expected kv-client to retry once and get the value, exited with {result.returncode}: "
+              f"{stderr.strip()[-200:]} {stdout.strip()[-200:]}")
+        return 1
+    print(f"PASS: kv-client retried on a new connection and got the value in {time.time() - start:.1f}s")
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(run())
@@ -0,0 +1,84 @@
+#!/usr/bin/env python3
+"""
+clock_stamp.py
+---------------------------------
+• Stands in for `consensus tcp` and replays a session of three batches in wire
+  format 2, each stamped with the clock before it: 1000 ns and 2500 ns of
+  clock records, then an Init for a guest that prints clock_time_get and
+  another 500 ns.
+• Checks a runtime sent the whole session sees the sum of the clock records,
+  4000 ns, and so does a runtime that joins late and is sent only the last
+  batch, which it fast-forwards its clock to from the stamp.
+Usage (after `cargo build --bin runtime`, from the repository root; uses port 9000):
+    python3 test/clock_stamp.py
+"""
+import re, socket, struct, subprocess, sys, zlib
+from support import record
+
+GUEST = b"""(module
+  (import "wasi_snapshot_preview1" "clock_time_get" (func $clock_time_get (param i32 i64 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 64) "<clock:")
+  (data (i32.const 79) ">\\n")
+  (func (export "_start")
+    (drop (call $clock_time_get (i32.const 0) (i64.const 1) (i32.const 71)))
+    (i32.store (i32.const 0) (i32.const 64))
+    (i32.store (i32.const 4) (i32.const 17))
+    (drop (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 8)))))
+"""
+DELTAS = [1000, 2500, 500]
+TIMEOUT = 10
+
+
+def session():
+    """Returns the batches of the session, each in wire format 2 with the
+    clock before it."""
+    batches, clock = [], 0
+    for number, delta in enumerate(DELTAS, start=1):
+        records = record(2, 0, GUEST) if number == len(DELTAS) else b""
+        records += record(0, 0, f"clock:{delta}".encode())
+        batches.append(struct.pack("<QBQQ", number, 0x20, clock, len(records)) + records
+                       + struct.pack("<I", zlib.crc32(records)))
+        clock += delta
+    return batches
+
+
+def clock_seen(batches):
+    """Sends `batches` to a fresh runtime and returns the clock its guest printed."""
+    server = socket.socket()
+    server.setsockopt(socket.SOL_SOCKET, socket.SO_REUSEADDR, 1)
+    server.bind(("127.0.0.1", 9000))
+    server.listen(1)
+    runtime = subprocess.Popen(["target/debug/runtime", "tcp"], stdout=subprocess.PIPE,
+                               stderr=subprocess.DEVNULL)
+    try:
+        conn, _ = server.accept()
+        conn.sendall(b"".join(batches))
+        conn.shutdown(socket.SHUT_WR)
+        output, _ = runtime.communicate(timeout=TIMEOUT)
+        conn.close()
+    except subprocess.TimeoutExpired:
+        runtime.kill()
+        return None
+    finally:
+        server.close()
+    clocks = re.findall(rb"<clock:(.{8})>", output, re.DOTALL)
+    return struct.unpack("<Q", clocks[0])[0] if len(clocks) == 1 else None
+
+
+def run():
+    batches = session()
+    full, late = clock_seen(batches), clock_seen(batches[-1:])
+    if full != sum(DELTAS):
+        print(f"FAIL: replaying the whole session should end at {sum(DELTAS)} ns, the guest saw {full}")
+        return 1
+    if late != full:
+        print(f"FAIL: a runtime sent only the last batch should also end at {full} ns, the guest saw {late}")
+        return 1
+    print("PASS: the clock ended at the sum of the clock records, with or without the batches before")
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(run())
@@ -0,0 +1,109 @@
+#!/usr/bin/env python3
+"""
+compile_in_background.py
+---------------------------------
+• Stands in for `consensus tcp`: starts an echo process, then sends a batch
+  with the Init of an artificially large module and a line for the echo
+  process.
+• `overlap` checks the echo process answers while the large module is still
+  compiling, and that the large process starts afterwards.
+• `timeout` runs the runtime with --compile-timeout=500 and checks the Init is
+  rejected and reported back to consensus, while the echo process still runs.
+Usage (after `cargo build --bin runtime`, from the repository root):
+    python3 test/compile_in_background.py [overlap|timeout]
+"""
+import socket, subprocess, sys, threading, time
+from support import batch, read_outgoing_records, record
+
+ECHO = b"""(module
+  (import "wasi_snapshot_preview1" "fd_read" (func $fd_read (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (func (export "_start")
+    (loop $next
+      (i32.store (i32.const 0) (i32.const 100))
+      (i32.store (i32.const 4) (i32.const 64))
+      (drop (call $fd_read (i32.const 0) (i32.const 0) (i32.const 1) (i32.const 8)))
+      (i32.store (i32.const 4) (i32.load (i32.const 8)))
+      (drop (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 12)))
+      (br_if $next (i32.load (i32.const 8))))))
+"""
+# Many large functions, so that compiling takes a while even in a fast build
+LARGE = (b"""(module
+  (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 0) "\\10\\00\\00\\00\\0c\\00\\00\\00")
+  (data (i32.const 16) "large start\\n")
+""" + b"".join(
+    b"(func (param i32) (result i32) "
+    + b"(local.set 0 (i32.add (i32.mul (local.get 0) (i32.const 31)) (i32.const 7)))" * 200
+    + b" (local.get 0))\n"
+    for _ in range(150)
+) + b"""  (func (export "_start")
+    (drop (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 32)))))
+""")
+TIMEOUT = 60
+
+
+def collect_lines(stream, lines):
+    for line in iter(stream.readline, b""):
+        lines.append((time.monotonic(), line))
+
+
+def run(mode):
+    server = socket.socket()
+    server.setsockopt(socket.SOL_SOCKET, socket.SO_REUSEADDR, 1)
+    server.bind(("127.0.0.1", 9000))
+    server.listen(1)
+    command = ["target/debug/runtime", "tcp"]
+    if mode == "timeout":
+        command.append("--compile-timeout=500")
+    runtime = subprocess.Popen(command, stdout=subprocess.PIPE, stderr=subprocess.DEVNULL)
+    lines = []
+    reader = threading.Thread(target=collect_lines, args=(runtime.stdout, lines))
+    reader.start()
+    conn, _ = server.accept()
+    conn.settimeout(TIMEOUT)
+
+    conn.sendall(batch(1, record(2, 0, ECHO)))
+    time.sleep(1)  # let the echo process block on stdin
+    sent = time.monotonic()
+    conn.sendall(batch(2, record(2, 0, LARGE) + record(1, 1, b"fd:0,body:ping")))
+    records = []
+    # Skip the batches that only acknowledge the ones sent so far
+    while mode == "timeout" and all(msg_type == 15 for msg_type, _, _ in records):
+        records = read_outgoing_records(conn)
+    if mode == "overlap":
+        time.sleep(1)
+        while not any(b"large start" in line for _, line in lines) and time.monotonic() - sent < TIMEOUT:
+            time.sleep(0.1)
+    conn.close()
+    server.close()
+    try:
+        runtime.wait(timeout=TIMEOUT)
+    except subprocess.TimeoutExpired:
+        runtime.kill()
+    reader.join()
+
+    echoed = [t - sent for t, line in lines if line == b"ping\n"]
+    started = [t - sent for t, line in lines if line == b"large start\n"]
+    if not echoed:
+        print(f"FAIL: the echo process never answered; output {lines}")
+        return 1
+    if mode == "timeout":
+        failures = [payload for msg_type, pid, payload in records if msg_type == 2 and pid == 2]
+        if started or not failures or b"did not finish within 500 ms" not in failures[0]:
+            print(f"FAIL: expected process 2 to be rejected and reported, got {records} and {lines}")
+            return 1
+        print(f"PASS: the slow Init was rejected and reported: {failures[0].decode()}")
+        return 0
+    if not started or echoed[0] > started[0] / 2:
+        print(f"FAIL: the echo process answered after {echoed[0]:.2f}s, the large one started after "
+              f"{started[0] if started else None}")
+        return 1
+    print(f"PASS: echoed after {echoed[0]:.2f}s while the large module compiled ({started[0]:.2f}s)")
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(run(sys.argv[1] if len(sys.argv) > 1 else "overlap"))
@@ -0,0 +1,67 @@
+#!/usr/bin/env python3
+"""
+consensus_disconnect.py
+---------------------------------
+• Stands in for `consensus tcp`: starts a process that blocks reading stdin,
+  then drops the connection, and checks the runtime exits instead of spinning.
+• `clean` closes between batches (runtime exits 0), `partial` closes in the
+  middle of a batch header (runtime connects again; once that connection is
+  closed between batches, it exits 0).
+Usage (after `cargo build --bin runtime`, from the repository root):
+    python3 test/consensus_disconnect.py [clean|partial]
+"""
+import socket, struct, subprocess, sys, time
+from support import batch, hang_up
+
+GUEST = b"""(module
+  (import "wasi_snapshot_preview1" "fd_read" (func $fd_read (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (func (export "_start")
+    (i32.store (i32.const 0) (i32.const 100))
+    (i32.store (i32.const 4) (i32.const 16))
+    (drop (call $fd_read (i32.const 0) (i32.const 0) (i32.const 1) (i32.const 50)))))
+"""
+TIMEOUT = 10
+
+
+def run(mode):
+    init = bytes([2]) + struct.pack("<QI", 0, len(GUEST)) + GUEST
+    server = socket.socket()
+    server.setsockopt(socket.SOL_SOCKET, socket.SO_REUSEADDR, 1)
+    server.bind(("127.0.0.1", 9000))
+    server.listen(1)
+    runtime = subprocess.Popen(["target/debug/runtime", "tcp"],
+                               stdout=subprocess.PIPE, stderr=subprocess.STDOUT)
+    server.settimeout(TIMEOUT)
+    conn, _ = server.accept()
+    conn.sendall(batch(1, init))
+    time.sleep(1)  # let the guest block on stdin
+    if mode == "partial":
+        conn.sendall(struct.pack("<QB", 2, 0) + b"\x10\x00")
+        time.sleep(0.5)
+        conn.close()
+        try:
+            conn, _ = server.accept()
+        except socket.timeout:
+            runtime.kill()
+            print(f"FAIL: runtime did not reconnect within {TIMEOUT}s of the lost connection")
+            return 1
+    hang_up(conn)
+    server.close()
+
+    try:
+        output, _ = runtime.communicate(timeout=TIMEOUT)
+    except subprocess.TimeoutExpired:
+        runtime.kill()
+        print(f"FAIL: runtime still running {TIMEOUT}s after the disconnect")
+        return 1
+    if runtime.returncode != 0:
+        print(output.decode(errors="replace"))
+        print(f"FAIL: runtime exited with {runtime.returncode}, expected 0")
+        return 1
+    print(f"PASS: runtime exited with 0 after a {mode} disconnect")
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(run(sys.argv[1] if len(sys.argv) > 1 else "clean"))
@@ -0,0 +1,75 @@
+#!/usr/bin/env python3
+"""
+consensus_file_rewrite.py
+---------------------------------
+• Starts `runtime follow` on a consensus file of four clock records and waits
+  until it has applied them, with RUST_LOG=applied_record=info.
+• Rewrites the file with two clock records, shorter than what was already
+  read, and checks the runtime warns that it was rewritten and applies both
+  new records from the start of the file.
+• Deletes the file and checks the runtime stops with a nonzero exit status
+  and an error saying the file was deleted.
+Usage (after `cargo build --bin runtime`, from the repository root):
+    python3 test/consensus_file_rewrite.py
+"""
+import os, re, subprocess, sys, tempfile
+from support import record, wait_for, watch
+
+FIRST = [(0, 0, b"clock:1")] * 4
+SECOND = [(0, 0, b"clock:22")] * 2
+TIMEOUT = 20
+LINE = re.compile(rb"applied_record\] cursor=(\d+) batch=\d+ record=\d+ type=\d+ pid=\d+ payload_len=(\d+)")
+
+
+def write(path, records):
+    with open(path, "wb") as f:
+        f.write(b"".join(record(*r) for r in records))
+
+
+def applied(log):
+    """(cursor, payload length) of each record the runtime applied so far."""
+    return [(int(cursor), int(length)) for cursor, length in LINE.findall(log)]
+
+
+def run():
+    path = os.path.join(tempfile.mkdtemp(), "consensus_input.bin")
+    write(path, FIRST)
+    runtime = subprocess.Popen(["target/debug/runtime", "follow", path],
+                               stdout=subprocess.DEVNULL, stderr=subprocess.PIPE,
+                               env={"RUST_LOG": "applied_record=info,warn"})
+    log = watch(runtime.stderr)
+    try:
+        if not wait_for(lambda: len(applied(log)) == len(FIRST), TIMEOUT):
+            print(f"FAIL: expected {len(FIRST)} records applied from the first file, got {applied(log)}")
+            return 1
+        write(path, SECOND)
+        wanted = len(FIRST) + len(SECOND)
+        if not wait_for(lambda: len(applied(log)) >= wanted and b"rewritten" in log, TIMEOUT):
+            print(f"FAIL: the runtime did not read the rewritten file from the start, applied {applied(log)}")
+            return 1
+        lengths = [length for _, length in applied(log)]
+        expected = [len(p) for _, _, p in FIRST] + [len(p) for _, _, p in SECOND]
+        if lengths != expected:
+            print(f"FAIL: expected records with payloads of {expected} bytes, got {lengths}")
+            return 1
+        os.unlink(path)
+        try:
+            status = runtime.wait(timeout=TIMEOUT)
+        except subprocess.TimeoutExpired:
+            print("FAIL: the runtime kept running after its consensus file was deleted")
+            return 1
+    finally:
+        runtime.kill()
+        runtime.wait()
+        if os.path.exists(path):
+            os.unlink(path)
+        os.rmdir(os.path.dirname(path))
+    if status == 0 or b"was deleted" not in log:
+        print(f"FAIL: expected a nonzero exit and a deleted-file error, got {status}: {bytes(log[-300:])}")
+        return 1
+    print("PASS: the rewritten file was read again from the start, and deleting it stopped the runtime")
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(run())
@@ -0,0 +1,64 @@
+#!/usr/bin/env python3
+"""
+consensus_follow.py
+---------------------------------
+• Starts `runtime follow` on an empty consensus file, with
+  RUST_LOG=applied_record=info, so it is waiting for records to be appended.
+• Appends one clock record at a time, five times, pausing between them, and
+  times how long the runtime takes to apply each.
+• Checks every record is applied, and each well within the second the
+  runtime waits between reads when nothing wakes it, so it was woken by the
+  append rather than finding it on its next scheduled read.
+Usage (after `cargo build --bin runtime`, from the repository root):
+    python3 test/consensus_follow.py
+"""
+import os, re, shutil, subprocess, sys, tempfile, time
+from support import record, wait_for, watch
+
+APPENDS = 5
+PROMPT = 0.3  # seconds; the runtime rereads the file once a second unprompted
+TIMEOUT = 10
+LINE = re.compile(rb"applied_record\] cursor=(\d+)")
+
+
+def run():
+    workdir = tempfile.mkdtemp()
+    path = os.path.join(workdir, "consensus_input.bin")
+    open(path, "wb").close()
+    runtime = subprocess.Popen(["target/debug/runtime", "follow", path],
+                               stdout=subprocess.DEVNULL, stderr=subprocess.PIPE,
+                               env={"RUST_LOG": "applied_record=info,info"})
+    log = watch(runtime.stderr)
+    delays = []
+    try:
+        if not wait_for(lambda: b"Following" in log, TIMEOUT):
+            print(f"FAIL: the runtime did not start following the file, got {bytes(log)}")
+            return 1
+        for count in range(1, APPENDS + 1):
+            # Let the runtime settle into its wait before the next append
+            time.sleep(1.2)
+            with open(path, "ab") as f:
+                f.write(record(0, 0, b"clock:1"))
+            start = time.time()
+            if not wait_for(lambda: len(LINE.findall(log)) >= count, TIMEOUT):
+                print(f"FAIL: record {count} was not applied within {TIMEOUT}s")
+                return 1
+            delays.append(time.time() - start)
+    finally:
+        runtime.kill()
+        runtime.wait()
+        shutil.rmtree(workdir)
+
+    if b"filesystem notifications" not in log:
+        print("FAIL: the runtime fell back to polling instead of using filesystem notifications")
+        return 1
+    slowest = max(delays)
+    if slowest > PROMPT:
+        print(f"FAIL: an appended record took {slowest:.2f}s to be applied, delays {[round(d, 2) for d in delays]}")
+        return 1
+    print(f"PASS: all {APPENDS} appended records were applied within {slowest:.2f}s")
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(run())
@@ -0,0 +1,94 @@
+#!/usr/bin/env python3
+"""
+cwd.py
+---------------------------------
+• Replays a consensus file with `runtime benchmark` that starts a guest with
+  the Init option `cwd:data` (what `init <wasm_file> -w data` sends). The
+  guest creates note.txt with a relative path and writes a line to it, then
+  reads it back as ../data/note.txt and as /data/note.txt, looks for
+  ../note.txt and tries to create ../../outside.txt (the steps of
+  wasm_programs/test_cwd.c).
+• Checks note.txt was created in data/: both paths to it read the line back,
+  and there is no note.txt in the sandbox root. Also checks leaving the
+  sandbox from data/ is still refused.
+Usage (after `cargo build --bin runtime`, from the repository root):
+    python3 test/cwd.py
+"""
+import os, re, subprocess, sys, tempfile
+from support import record
+
+GUEST = b"""(module
+  (import "wasi_snapshot_preview1" "path_open" (func $path_open (param i32 i32 i32 i32 i32 i64 i64 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_read" (func $fd_read (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_close" (func $fd_close (param i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 256) "note.txt")
+  (data (i32.const 272) "../data/note.txt")
+  (data (i32.const 304) "/data/note.txt")
+  (data (i32.const 320) "../note.txt")
+  (data (i32.const 336) "../../outside.txt")
+  (data (i32.const 384) "hello from data/")
+  (data (i32.const 416) "<??>")
+  (func $print (param $ptr i32) (param $len i32)
+    (i32.store (i32.const 16) (local.get $ptr))
+    (i32.store (i32.const 20) (local.get $len))
+    (drop (call $fd_write (i32.const 1) (i32.const 16) (i32.const 1) (i32.const 24))))
+  ;; opens a path relative to the preopen, prints the errno as <NN> and
+  ;; returns the new fd
+  (func $open (param $path i32) (param $len i32) (param $oflags i32) (result i32)
+    (local $errno i32)
+    (local.set $errno (call $path_open (i32.const 3) (i32.const 0) (local.get $path) (local.get $len)
+      (local.get $oflags) (i64.const -1) (i64.const -1) (i32.const 0) (i32.const 64)))
+    (i32.store8 (i32.const 417) (i32.add (i32.const 48) (i32.div_u (local.get $errno) (i32.const 10))))
+    (i32.store8 (i32.const 418) (i32.add (i32.const 48) (i32.rem_u (local.get $errno) (i32.const 10))))
+    (call $print (i32.const 416) (i32.const 4))
+    (i32.load (i32.const 64)))
+  ;; prints what the fd reads between square brackets
+  (func $cat (param $fd i32)
+    (i32.store (i32.const 0) (i32.const 513))
+    (i32.store (i32.const 4) (i32.const 64))
+    (drop (call $fd_read (local.get $fd) (i32.const 0) (i32.const 1) (i32.const 8)))
+    (i32.store8 (i32.const 512) (i32.const 91))
+    (i32.store8 (i32.add (i32.const 513) (i32.load (i32.const 8))) (i32.const 93))
+    (call $print (i32.const 512) (i32.add (i32.load (i32.const 8)) (i32.const 2)))
+    (drop (call $fd_close (local.get $fd))))
+  (func (export "_start")
+    (local $fd i32)
+    (local.set $fd (call $open (i32.const 256) (i32.const 8) (i32.const 1)))
+    (i32.store (i32.const 0) (i32.const 384))
+    (i32.store (i32.const 4) (i32.const 16))
+    (drop (call $fd_write (local.get $fd) (i32.const 0) (i32.const 1) (i32.const 8)))
+    (drop (call $fd_close (local.get $fd)))
+    (call $cat (call $open (i32.const 272) (i32.const 16) (i32.const 0)))
+    (call $cat (call $open (i32.const 304) (i32.const 14) (i32.const 0)))
+    (drop (call $open (i32.const 320) (i32.const 11) (i32.const 0)))
+    (drop (call $open (i32.const 336) (i32.const 17) (i32.const 1)))))
+"""
+# Created, read back through both paths, then ENOENT and EACCES as the
+# runtime numbers them (see io_err_to_wasi_errno)
+EXPECTED = "<00><00>[hello from data/]<00>[hello from data/]<02><13>"
+TIMEOUT = 30
+
+
+def run():
+    with tempfile.NamedTemporaryFile(suffix=".bin", delete=False) as f:
+        f.write(record(2, 0, b"cwd:data\0" + GUEST) + record(0, 0, b"clock:1"))
+    try:
+        result = subprocess.run(["target/debug/runtime", "benchmark", f.name], capture_output=True, timeout=TIMEOUT)
+    except subprocess.TimeoutExpired:
+        print("FAIL: the runtime did not finish the guest")
+        return 1
+    finally:
+        os.unlink(f.name)
+    # The runtime traces some syscalls to stdout too
+    output = "".join(re.findall(r"<\d\d>|\[[^\]]*\]", result.stdout.decode(errors="replace")))
+    if output != EXPECTED:
+        print(f"FAIL: expected {EXPECTED!r}, got {output!r}")
+        return 1
+    print("PASS: relative paths resolved under the working directory, which the guest could not leave")
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(run())
@@ -0,0 +1,127 @@
+#!/usr/bin/env python3
+"""
+dircopy_resume.py
+---------------------------------
+• Stands in for wasm_programs/dircopy_server.c, speaking the protocol
+  documented in consensus/src/clients/dircopy_protocol.rs, and runs
+  `consensus dircopy-client` to copy a tree of three files, one in a
+  subdirectory and one with a space in its name.
+• Checks the manifest lists each file with its size and CRC-32, all three
+  files are sent, and the copy matches the source.
+• Deletes one copied file and runs the client again, and checks only that
+  file is sent and the copy matches the source again.
+Usage (after `cargo build --bin consensus`, from the repository root):
+    python3 test/dircopy_resume.py
+"""
+import os, shutil, socket, subprocess, sys, tempfile, threading, zlib
+from support import read_exact
+
+FILES = {"a.txt": b"hello\n", "sub/big.bin": bytes(range(256)) * 400, "sub/deep/my file.txt": b"with space\n"}
+TIMEOUT = 10
+
+
+def read_line(conn):
+    line = b""
+    while not line.endswith(b"\n"):
+        chunk = conn.recv(1)
+        if not chunk:
+            raise EOFError("the client closed the connection")
+        line += chunk
+    return line[:-1].decode()
+
+
+def sync(conn, root, sessions):
+    """Serves one SYNC, recording its manifest and the paths sent."""
+    _, dst, count = read_line(conn).split(" ")
+    manifest, have = [], []
+    for _ in range(int(count)):
+        size, crc, path = read_line(conn).split(" ", 2)
+        manifest.append((path, int(size), int(crc, 16)))
+        try:
+            with open(os.path.join(root, dst, path), "rb") as f:
+                data = f.read()
+            if (len(data), zlib.crc32(data)) == (int(size), int(crc, 16)):
+                have.append(path)
+        except FileNotFoundError:
+            pass
+    conn.sendall(f"HAVE {len(have)}\n".encode() + b"".join(f"{path}\n".encode() for path in have))
+    sent = []
+    while (line := read_line(conn)) != "DONE":
+        path = line.removeprefix("SEND ")
+        size = int.from_bytes(read_exact(conn, 4, "the client"), "big")
+        target = os.path.join(root, dst, path)
+        os.makedirs(os.path.dirname(target), exist_ok=True)
+        with open(target, "wb") as f:
+            f.write(read_exact(conn, size, "the client"))
+        sent.append(path)
+    conn.sendall(f"OK {len(sent)}\n".encode())
+    sessions.append((manifest, sent))
+
+
+def serve(server, root, sessions):
+    while True:
+        try:
+            conn, _ = server.accept()
+        except OSError:
+            return
+        with conn:
+            try:
+                sync(conn, root, sessions)
+            except EOFError:
+                pass
+
+
+def copy(port, src):
+    return subprocess.run(["target/debug/consensus", "dircopy-client", "127.0.0.1", str(port), src, "out"],
+                          capture_output=True, timeout=TIMEOUT)
+
+
+def tree(root):
+    files = {}
+    for dir, _, names in os.walk(root):
+        for name in names:
+            with open(os.path.join(dir, name), "rb") as f:
+                files[os.path.relpath(os.path.join(dir, name), root)] = f.read()
+    return files
+
+
+def run():
+    workdir = tempfile.mkdtemp()
+    src, server_root = os.path.join(workdir, "src"), os.path.join(workdir, "server")
+    for path, data in FILES.items():
+        os.makedirs(os.path.dirname(os.path.join(src, path)), exist_ok=True)
+        with open(os.path.join(src, path), "wb") as f:
+            f.write(data)
+    server = socket.socket()
+    server.bind(("127.0.0.1", 0))
+    server.listen(4)
+    sessions = []
+    threading.Thread(target=serve, args=(server, server_root, sessions), daemon=True).start()
+    try:
+        first = copy(server.getsockname()[1], src)
+        copied = tree(os.path.join(server_root, "out"))
+        os.unlink(os.path.join(server_root, "out", "sub", "big.bin"))
+        second = copy(server.getsockname()[1], src)
+        recopied = tree(os.path.join(server_root, "out"))
+    finally:
+        server.close()
+        shutil.rmtree(workdir)
+    if first.returncode != 0 or second.returncode != 0 or len(sessions) != 2:
+        print(f"FAIL: expected two successful copies, got {first.returncode}, {second.returncode}: {second.stderr.decode()}")
+        return 1
+    expected = sorted((path, len(data), zlib.crc32(data)) for path, data in FILES.items())
+    if sessions[0][0] != expected:
+        print(f"FAIL: expected the manifest {expected}, got {sessions[0][0]}")
+        return 1
+    if sorted(sessions[0][1]) != sorted(FILES) or copied != FILES:
+        print(f"FAIL: the first copy sent {sessions[0][1]} and left {sorted(copied)}")
+        return 1
+    if sessions[1][1] != ["sub/big.bin"] or recopied != FILES:
+        print(f"FAIL: expected the second copy to send only sub/big.bin, it sent {sessions[1][1]}")
+        return 1
+    print("PASS: the second copy sent only the deleted file, and both copies match the source")
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(run())
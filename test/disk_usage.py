@@ -0,0 +1,107 @@
+#!/usr/bin/env python3
+"""
+disk_usage.py
+---------------------------------
+• Stands in for `consensus tcp` and starts a guest with an 8 KiB quota and a
+  1 KiB write buffer, so its writes block for the scheduler to flush them.
+  It creates a file, writes 3000 bytes, seeks back and writes the same 3000
+  bytes over them, then tries to append 3000 more, prints the three errnos
+  and waits on stdin.
+• Checks the overwrite succeeds, as it does not grow the file, and the
+  append fails with the runtime's NOSPC errno, as it would take the process
+  over its quota.
+• Sends a SetQuota record without a quota (what `quota <pid>` sends) and
+  checks the usage the runtime logs is exactly the file's size on disk plus
+  the 4 KiB charged for creating it.
+Usage (after `cargo build --bin runtime`, from the repository root):
+    python3 test/disk_usage.py
+"""
+import os, re, shutil, socket, subprocess, sys, time
+from support import batch, record, wait_for, watch
+
+GUEST = b"""(module
+  (import "wasi_snapshot_preview1" "path_open" (func $path_open (param i32 i32 i32 i32 i32 i64 i64 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_seek" (func $fd_seek (param i32 i64 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_read" (func $fd_read (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 256) "near.bin")
+  (data (i32.const 512) "errnos ?? ?? ??\\n")
+  (func $report (param $at i32) (param $errno i32)
+    (i32.store8 (local.get $at) (i32.add (i32.const 48) (i32.div_u (local.get $errno) (i32.const 10))))
+    (i32.store8 (i32.add (local.get $at) (i32.const 1)) (i32.add (i32.const 48) (i32.rem_u (local.get $errno) (i32.const 10)))))
+  (func $write (result i32)
+    (i32.store (i32.const 16) (i32.const 1024))
+    (i32.store (i32.const 20) (i32.const 3000))
+    (call $fd_write (i32.load (i32.const 64)) (i32.const 16) (i32.const 1) (i32.const 24)))
+  (func (export "_start")
+    (drop (call $path_open (i32.const 3) (i32.const 0) (i32.const 256) (i32.const 8) (i32.const 1)
+      (i64.const -1) (i64.const -1) (i32.const 0) (i32.const 64)))
+    (call $report (i32.const 519) (call $write))
+    (drop (call $fd_seek (i32.load (i32.const 64)) (i64.const 0) (i32.const 0) (i32.const 32)))
+    (call $report (i32.const 522) (call $write))
+    (call $report (i32.const 525) (call $write))
+    (i32.store (i32.const 16) (i32.const 512))
+    (i32.store (i32.const 20) (i32.const 16))
+    (drop (call $fd_write (i32.const 1) (i32.const 16) (i32.const 1) (i32.const 24)))
+    (drop (call $fd_read (i32.const 0) (i32.const 16) (i32.const 1) (i32.const 24)))))
+"""
+CREATE_CHARGE = 4096
+NOSPC = 28  # the runtime's WASI_ERRNO_NOSPC
+TIMEOUT = 20
+
+
+def run():
+    server = socket.socket()
+    server.bind(("127.0.0.1", 0))
+    server.listen(1)
+    server.settimeout(TIMEOUT)
+    runtime = subprocess.Popen(["target/debug/runtime", "tcp", f"127.0.0.1:{server.getsockname()[1]}"],
+                               stdout=subprocess.PIPE, stderr=subprocess.PIPE, env={"RUST_LOG": "info"})
+    output, log = watch(runtime.stdout), watch(runtime.stderr)
+    root = on_disk = usage = None
+    try:
+        conn, _ = server.accept()
+        conn.sendall(batch(1, record(2, 0, b"quota:8192\0limits:write=1024\0" + GUEST) + record(0, 0, b"clock:1")))
+        number = 2
+        while b"errnos" not in output and number < 100:
+            conn.sendall(batch(number, record(0, 0, b"clock:1")))
+            number += 1
+            time.sleep(0.05)
+        found = re.search(rb"Using sandbox root: (\S+)", log)
+        root = found and found.group(1).decode()
+        if root and os.path.exists(os.path.join(root, "pid_1", "near.bin")):
+            on_disk = os.path.getsize(os.path.join(root, "pid_1", "near.bin"))
+        conn.sendall(batch(number, record(10, 1, b"")))
+        if wait_for(lambda: re.search(rb"Process 1 uses (\d+) of", log), TIMEOUT):
+            usage = int(re.search(rb"Process 1 uses (\d+) of", log).group(1))
+        conn.close()
+    except socket.timeout:
+        print(f"FAIL: the runtime did not connect within {TIMEOUT}s")
+        return 1
+    finally:
+        runtime.kill()
+        runtime.wait()
+        server.close()
+        if root:
+            shutil.rmtree(root, ignore_errors=True)
+    errnos = re.search(rb"errnos (\d+) (\d+) (\d+)", output)
+    if not errnos:
+        print(f"FAIL: the guest did not finish its writes, got {bytes(output)}")
+        return 1
+    errnos = [int(errno) for errno in errnos.groups()]
+    if errnos != [0, 0, NOSPC]:
+        print(f"FAIL: expected the write and overwrite to succeed and the append to fail with {NOSPC}, got {errnos}")
+        return 1
+    if on_disk is None or usage is None:
+        print(f"FAIL: could not compare the usage ({usage}) with the file on disk ({on_disk})")
+        return 1
+    if usage != on_disk + CREATE_CHARGE:
+        print(f"FAIL: the runtime counts {usage} bytes for a {on_disk}-byte file, expected {on_disk + CREATE_CHARGE}")
+        return 1
+    print(f"PASS: the process's disk usage ({usage}) matches its {on_disk}-byte file plus the creation charge")
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(run())
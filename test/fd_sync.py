@@ -0,0 +1,135 @@
+#!/usr/bin/env python3
+"""
+fd_sync.py
+---------------------------------
+• Stands in for `consensus tcp` and starts a guest with `limits:write=64`
+  that creates a file and twice writes 100 bytes to it, then waits for the
+  next batch, so 36 bytes of each write are still buffered, and calls
+  fd_sync after the first and fd_datasync after the second. It waits for
+  the next batch after each sync too, then calls fd_sync on an FD it never
+  opened, prints the three errnos and waits on stdin.
+• Checks, while the guest waits, that only the 64 bytes that filled the
+  buffer are on disk after each write, and that the sync is what writes
+  the rest: all 100 or 200 bytes are on disk after it.
+• Checks both syncs of the file succeed and the unopened FD fails with
+  EBADF (8).
+Usage (after `cargo build --bin runtime`, from the repository root):
+    python3 test/fd_sync.py
+"""
+import os, re, shutil, socket, subprocess, sys
+from support import batch, record, wait_for, watch
+
+GUEST = b"""(module
+  (import "wasi_snapshot_preview1" "path_open" (func $path_open (param i32 i32 i32 i32 i32 i64 i64 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_sync" (func $fd_sync (param i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_datasync" (func $fd_datasync (param i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_read" (func $fd_read (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "poll_oneoff" (func $poll_oneoff (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 256) "synced.txt")
+  (data (i32.const 300) "0123456789abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789abcdefghijklmnopqrstuvwxyz.\\n")
+  (data (i32.const 448) "written\\n")
+  (data (i32.const 464) "synced\\n")
+  (data (i32.const 512) "errnos ?? ?? ??\\n")
+  (func $print (param $at i32) (param $len i32)
+    (i32.store (i32.const 16) (local.get $at))
+    (i32.store (i32.const 20) (local.get $len))
+    (drop (call $fd_write (i32.const 1) (i32.const 16) (i32.const 1) (i32.const 24))))
+  ;; Sleeps 1 ns, until the next batch advances the clock
+  (func $wait
+    (i64.store (i32.const 152) (i64.const 1))
+    (drop (call $poll_oneoff (i32.const 128) (i32.const 192) (i32.const 1) (i32.const 32))))
+  (func $write_line
+    (i32.store (i32.const 16) (i32.const 300))
+    (i32.store (i32.const 20) (i32.const 100))
+    (drop (call $fd_write (i32.load (i32.const 64)) (i32.const 16) (i32.const 1) (i32.const 24)))
+    (call $print (i32.const 448) (i32.const 8))
+    (call $wait))
+  (func $report (param $at i32) (param $errno i32)
+    (i32.store8 (local.get $at) (i32.add (i32.const 48) (i32.div_u (local.get $errno) (i32.const 10))))
+    (i32.store8 (i32.add (local.get $at) (i32.const 1)) (i32.add (i32.const 48) (i32.rem_u (local.get $errno) (i32.const 10)))))
+  (func (export "_start")
+    (drop (call $path_open (i32.const 3) (i32.const 0) (i32.const 256) (i32.const 10) (i32.const 1)
+      (i64.const -1) (i64.const -1) (i32.const 0) (i32.const 64)))
+    (call $write_line)
+    (call $report (i32.const 519) (call $fd_sync (i32.load (i32.const 64))))
+    (call $print (i32.const 464) (i32.const 7))
+    (call $wait)
+    (call $write_line)
+    (call $report (i32.const 522) (call $fd_datasync (i32.load (i32.const 64))))
+    (call $print (i32.const 464) (i32.const 7))
+    (call $wait)
+    (call $report (i32.const 525) (call $fd_sync (i32.const 99)))
+    (call $print (i32.const 512) (i32.const 16))
+    (drop (call $fd_read (i32.const 0) (i32.const 16) (i32.const 1) (i32.const 24)))))
+"""
+LINE = b"0123456789abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789abcdefghijklmnopqrstuvwxyz.\n"
+BUFFER = 64
+TIMEOUT = 20
+
+
+def run():
+    server = socket.socket()
+    server.bind(("127.0.0.1", 0))
+    server.listen(1)
+    server.settimeout(TIMEOUT)
+    runtime = subprocess.Popen(["target/debug/runtime", "tcp", f"127.0.0.1:{server.getsockname()[1]}"],
+                               stdout=subprocess.PIPE, stderr=subprocess.PIPE, env={"RUST_LOG": "info"})
+    output, log = watch(runtime.stdout), watch(runtime.stderr)
+    root = None
+    seen = []  # what is on disk at each step: written, synced, written, datasynced
+    try:
+        conn, _ = server.accept()
+        number = 1
+        conn.sendall(batch(number, record(2, 0, f"limits:write={BUFFER}\0".encode() + GUEST) + record(0, 0, b"clock:1")))
+        for step, marker in enumerate([b"written\n", b"synced\n", b"written\n", b"synced\n"]):
+            if marker == b"written\n":
+                # The write blocks once its first 64 bytes fill the buffer,
+                # until the next batch has them written out
+                number += 1
+                conn.sendall(batch(number, record(0, 0, b"clock:1")))
+            if not wait_for(lambda: output.count(marker) > step // 2, TIMEOUT):
+                break
+            if root is None:
+                found = re.search(rb"Using sandbox root: (\S+)", log)
+                root = found and found.group(1).decode()
+            with open(os.path.join(root, "pid_1", "synced.txt"), "rb") as f:
+                seen.append(f.read())
+            # The guest waits for this one before it goes on
+            number += 1
+            conn.sendall(batch(number, record(0, 0, b"clock:1")))
+        wait_for(lambda: b"errnos" in output, TIMEOUT)
+        conn.close()
+    except socket.timeout:
+        print(f"FAIL: the runtime did not connect within {TIMEOUT}s")
+        return 1
+    finally:
+        runtime.kill()
+        runtime.wait()
+        server.close()
+        if root:
+            shutil.rmtree(root, ignore_errors=True)
+    expected = [LINE[:BUFFER], LINE, LINE + LINE[:BUFFER], LINE + LINE]
+    for step, (name, on_disk, wanted) in enumerate(zip(["write", "fd_sync", "write", "fd_datasync"], seen, expected)):
+        if on_disk != wanted:
+            print(f"FAIL: expected {len(wanted)} bytes on disk after the {name}, found {len(on_disk)}: {on_disk}")
+            return 1
+    if len(seen) < len(expected):
+        print(f"FAIL: the guest stopped after {len(seen)} of its {len(expected)} steps, got {bytes(output)}")
+        return 1
+    print(f"PASS: each write left {len(LINE) - BUFFER} bytes buffered, and fd_sync and fd_datasync wrote them to disk")
+    errnos = re.search(rb"errnos (\d+) (\d+) (\d+)", output)
+    if not errnos:
+        print(f"FAIL: the guest did not finish its syncs, got {bytes(output)}")
+        return 1
+    errnos = [int(errno) for errno in errnos.groups()]
+    if errnos != [0, 0, 8]:
+        print(f"FAIL: expected fd_sync and fd_datasync to succeed and an unopened FD to fail with 8, got {errnos}")
+        return 1
+    print("PASS: fd_sync and fd_datasync succeeded and an unopened FD failed with EBADF")
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(run())
@@ -0,0 +1,88 @@
+#!/usr/bin/env python3
+"""
+file_cache.py
+---------------------------------
+• Replays, with `runtime benchmark`, the Init of a guest started with the
+  file cache (`cache:4096`), the WAT counterpart of
+  wasm_programs/test_file_cache.c. It writes "v1" to a file and reads it
+  back five times, each through a fresh path_open, then appends "v2" and
+  reads it once more, printing every read.
+• Checks every read returned what the file held at the time, so the last
+  one sees "v1v2" rather than a stale cached "v1", and that the log shows
+  the cache served the repeated reads and was invalidated by the write.
+Usage (after `cargo build --bin runtime`, from the repository root):
+    python3 test/file_cache.py
+"""
+import os, subprocess, sys, tempfile
+from support import record
+
+GUEST = b"""(module
+  (import "wasi_snapshot_preview1" "path_open" (func $path_open (param i32 i32 i32 i32 i32 i64 i64 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_read" (func $fd_read (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_close" (func $fd_close (param i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 100) "cache_test.txt")
+  (data (i32.const 120) "v1v2")
+  (func $open (param $oflags i32) (param $fdflags i32) (result i32)
+    (drop (call $path_open (i32.const 3) (i32.const 0) (i32.const 100) (i32.const 14) (local.get $oflags)
+      (i64.const -1) (i64.const -1) (local.get $fdflags) (i32.const 16)))
+    (i32.load (i32.const 16)))
+  (func $write (param $at i32)
+    (local $fd i32)
+    ;; O_CREAT the first time; FDFLAGS_APPEND the second
+    (local.set $fd (call $open (i32.eq (local.get $at) (i32.const 120)) (i32.ne (local.get $at) (i32.const 120))))
+    (i32.store (i32.const 0) (local.get $at))
+    (i32.store (i32.const 4) (i32.const 2))
+    (drop (call $fd_write (local.get $fd) (i32.const 0) (i32.const 1) (i32.const 8)))
+    (drop (call $fd_close (local.get $fd))))
+  (func $read_and_print
+    (local $fd i32)
+    (local.set $fd (call $open (i32.const 0) (i32.const 0)))
+    (i32.store (i32.const 0) (i32.const 200))
+    (i32.store (i32.const 4) (i32.const 64))
+    (drop (call $fd_read (local.get $fd) (i32.const 0) (i32.const 1) (i32.const 8)))
+    (drop (call $fd_close (local.get $fd)))
+    (i32.store8 (i32.add (i32.const 200) (i32.load (i32.const 8))) (i32.const 10))
+    (i32.store (i32.const 4) (i32.add (i32.load (i32.const 8)) (i32.const 1)))
+    (drop (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 8))))
+  (func (export "_start")
+    (local $i i32)
+    (call $write (i32.const 120))
+    (loop $reads
+      (call $read_and_print)
+      (local.set $i (i32.add (local.get $i) (i32.const 1)))
+      (br_if $reads (i32.lt_u (local.get $i) (i32.const 5))))
+    (call $write (i32.const 122))
+    (call $read_and_print)))
+"""
+TIMEOUT = 10
+
+
+def run():
+    with tempfile.NamedTemporaryFile(suffix=".bin", delete=False) as f:
+        f.write(record(2, 0, b"cache:4096\0" + GUEST) + record(0, 0, b"clock:1"))
+    try:
+        result = subprocess.run(["target/debug/runtime", "benchmark", f.name], capture_output=True, timeout=TIMEOUT,
+                                env=dict(os.environ, RUST_LOG="runtime::runtime::file_cache=debug"))
+    except subprocess.TimeoutExpired:
+        print(f"FAIL: the replay did not end within {TIMEOUT}s")
+        return 1
+    finally:
+        os.unlink(f.name)
+    log = result.stderr.decode(errors="replace")
+    # The runtime traces path_open and fd_close on stdout too
+    reads = [line for line in result.stdout.splitlines() if line.startswith(b"v")]
+    if reads != [b"v1"] * 5 + [b"v1v2"]:
+        print(f"FAIL: expected five reads of v1 and then v1v2, got {reads}")
+        return 1
+    if log.count("file cache hit") < 4 or "file cache invalidated" not in log:
+        print(log)
+        print("FAIL: expected the repeated reads to hit the cache and the write to invalidate it")
+        return 1
+    print("PASS: the file cache served repeated reads and the read after a write saw the new content")
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(run())
@@ -0,0 +1,80 @@
+#!/usr/bin/env python3
+"""
+finish_order.py
+---------------------------------
+• Stands in for `consensus tcp` and sends one batch with three Inits of
+  wasm_programs/test_finish_order.wat, which yields once per argument and
+  then exits: process 1 with four arguments, process 2 with none and process
+  3 with two. Process 2 finishes first and process 1 last, all within the
+  scheduler's run of the batch.
+• Checks the runtime logged them finishing out of pid order, and still sent
+  their ProcessExit records in one batch, in pid order.
+Usage (after `cargo build --bin runtime`, from the repository root; uses port 9000):
+    python3 test/finish_order.py
+"""
+import re, socket, struct, subprocess, sys, time, zlib
+from support import read_outgoing_records, record, watch
+
+TIMEOUT = 10
+
+
+def batch(number, records):
+    return struct.pack("<QBQQ", number, 0x20, 0, len(records)) + records + struct.pack("<I", zlib.crc32(records))
+
+
+def init(guest, args):
+    options = b"args:" + "\x1f".join(args).encode() + b"\0" if args else b""
+    return record(2, 0, options + guest)
+
+
+def run():
+    with open("wasm_programs/test_finish_order.wat", "rb") as f:
+        guest = f.read()
+    server = socket.socket()
+    server.setsockopt(socket.SOL_SOCKET, socket.SO_REUSEADDR, 1)
+    server.bind(("127.0.0.1", 9000))
+    server.listen(1)
+    runtime = subprocess.Popen(["target/debug/runtime", "tcp"], env={"RUST_LOG": "debug"},
+                               stdout=subprocess.DEVNULL, stderr=subprocess.PIPE)
+    log = watch(runtime.stderr)
+    exit_batches = []
+    try:
+        conn, _ = server.accept()
+        conn.settimeout(0.2)
+        conn.sendall(batch(1, init(guest, ["a", "b", "c", "d"]) + init(guest, []) + init(guest, ["a", "b"])
+                                + record(0, 0, b"clock:1")))
+        number, deadline = 2, time.time() + TIMEOUT
+        while sum(map(len, exit_batches)) < 3 and time.time() < deadline:
+            # The runtime only sends its batch before reading the next one
+            conn.sendall(batch(number, record(0, 0, b"clock:1")))
+            number += 1
+            try:
+                records = read_outgoing_records(conn)
+            except socket.timeout:
+                continue
+            exits = [pid for msg_type, pid, _ in records if msg_type == 14]
+            if exits:
+                exit_batches.append(exits)
+        conn.close()
+        runtime.wait(timeout=TIMEOUT)
+    except subprocess.TimeoutExpired:
+        runtime.kill()
+        print("FAIL: the runtime did not exit")
+        return 1
+    finally:
+        server.close()
+    finished = [int(pid) for pid in re.findall(r"Process (\d+) marked as Finished",
+                                               log.decode(errors="replace"))]
+    if finished[:1] != [2] or finished[-1:] != [1]:
+        print(f"FAIL: expected process 2 to finish first and process 1 last, got {finished}")
+        return 1
+    if exit_batches != [[1, 2, 3]]:
+        print(f"FAIL: expected the ProcessExit records for 1, 2 and 3 in one batch, in that order, "
+              f"got {exit_batches}")
+        return 1
+    print(f"PASS: processes that finished in the order {finished} were reported in pid order")
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(run())
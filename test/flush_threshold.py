@@ -0,0 +1,74 @@
+#!/usr/bin/env python3
+"""
+flush_threshold.py
+---------------------------------
+• Replays a consensus file with `runtime benchmark` that starts a guest
+  writing 64 bytes to a file in one fd_write, first with the default
+  max_write_buffer and then after `__builtin_rt_set_flush_threshold(fd, 16)`.
+• Checks the first write went through without flushing early, and the second
+  flushed every 16 bytes: it blocked three times, each time until the next
+  batch. Also checks the builtin refuses stdout (EBADF) and 0 bytes (EINVAL).
+Usage (after `cargo build --bin runtime`, from the repository root):
+    python3 test/flush_threshold.py
+"""
+import os, subprocess, sys, tempfile
+from support import record
+
+GUEST = """(module
+  (import "wasi_snapshot_preview1" "path_open" (func $path_open (param i32 i32 i32 i32 i32 i64 i64 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (import "env" "__builtin_rt_batch" (func $batch (result i64)))
+  (import "env" "__builtin_rt_set_flush_threshold" (func $set_flush_threshold (param i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 16) "\\20\\00\\00\\00\\0c\\00\\00\\00")
+  (data (i32.const 32) "?? ?? ?? ??\\n")
+  (data (i32.const 256) "out.txt")
+  ;; writes 64 bytes in one call and returns how many batches that took
+  (func $write64 (param $fd i32) (result i32)
+    (local $before i32)
+    (local.set $before (i32.wrap_i64 (call $batch)))
+    (i32.store (i32.const 80) (i32.const 1024))
+    (i32.store (i32.const 84) (i32.const 64))
+    (drop (call $fd_write (local.get $fd) (i32.const 80) (i32.const 1) (i32.const 88)))
+    (i32.sub (i32.wrap_i64 (call $batch)) (local.get $before)))
+  ;; stores a two-digit number at the given field of the output line
+  (func $put (param $field i32) (param $value i32)
+    (i32.store8 (i32.add (i32.const 32) (i32.mul (local.get $field) (i32.const 3)))
+      (i32.add (i32.const 48) (i32.div_u (local.get $value) (i32.const 10))))
+    (i32.store8 (i32.add (i32.const 33) (i32.mul (local.get $field) (i32.const 3)))
+      (i32.add (i32.const 48) (i32.rem_u (local.get $value) (i32.const 10)))))
+  (func (export "_start")
+    (local $fd i32)
+    (drop (call $path_open (i32.const 3) (i32.const 0) (i32.const 256) (i32.const 7) (i32.const 1)
+      (i64.const -1) (i64.const -1) (i32.const 0) (i32.const 64)))
+    (local.set $fd (i32.load (i32.const 64)))
+    (call $put (i32.const 0) (call $write64 (local.get $fd)))
+    (drop (call $set_flush_threshold (local.get $fd) (i32.const 16)))
+    (call $put (i32.const 1) (call $write64 (local.get $fd)))
+    (call $put (i32.const 2) (call $set_flush_threshold (i32.const 1) (i32.const 16)))
+    (call $put (i32.const 3) (call $set_flush_threshold (local.get $fd) (i32.const 0)))
+    (drop (call $fd_write (i32.const 1) (i32.const 16) (i32.const 1) (i32.const 12)))))
+"""
+TIMEOUT = 30
+
+
+def run():
+    records = record(2, 0, GUEST.encode()) + b"".join(record(0, 0, b"clock:1") for _ in range(10))
+    with tempfile.NamedTemporaryFile(suffix=".bin", delete=False) as f:
+        f.write(records)
+    try:
+        result = subprocess.run(["target/debug/runtime", "benchmark", f.name],
+                                capture_output=True, timeout=TIMEOUT)
+    finally:
+        os.unlink(f.name)
+    lines = result.stdout.decode().splitlines()
+    observed = lines[-1].split() if lines else []
+    if observed != ["00", "03", "08", "28"]:
+        print(f"FAIL: expected batches 0 and 3 for the writes, then errnos 8 and 28, got {observed}")
+        return 1
+    print("PASS: with a 16-byte threshold, the 64-byte write was flushed every 16 bytes")
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(run())
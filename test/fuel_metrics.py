@@ -0,0 +1,98 @@
+#!/usr/bin/env python3
+"""
+fuel_metrics.py
+---------------------------------
+• Stands in for `consensus tcp` and starts a runtime with --metrics-port on a
+  guest that, over and over, runs a compute loop and then reads stdin.
+• Sends it one line of stdin at a time. After each, it scrapes /metrics and
+  checks replicode_process_fuel_consumed_total for the process went up, by
+  at least the instructions of one compute loop.
+Usage (after `cargo build --bin runtime`, from the repository root):
+    python3 test/fuel_metrics.py
+"""
+import re, socket, subprocess, sys, time, urllib.request
+from support import batch, record
+
+GUEST = b"""(module
+  (import "wasi_snapshot_preview1" "fd_read" (func $fd_read (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 0) "\\40\\00\\00\\00\\10\\00\\00\\00")
+  (func (export "_start")
+    (local $i i32)
+    (loop $forever
+      (local.set $i (i32.const 0))
+      (loop $compute
+        (local.set $i (i32.add (local.get $i) (i32.const 1)))
+        (br_if $compute (i32.lt_u (local.get $i) (i32.const 100000))))
+      (drop (call $fd_read (i32.const 0) (i32.const 0) (i32.const 1) (i32.const 8)))
+      (br $forever))))
+"""
+LOOP_FUEL = 100000  # at least one unit per iteration
+STEPS = 4
+TIMEOUT = 10
+
+
+def free_port():
+    with socket.socket() as s:
+        s.bind(("127.0.0.1", 0))
+        return s.getsockname()[1]
+
+
+def fuel(metrics_port):
+    """The fuel process 1 has burned, as /metrics reports it."""
+    with urllib.request.urlopen(f"http://127.0.0.1:{metrics_port}/metrics", timeout=TIMEOUT) as response:
+        body = response.read().decode()
+    match = re.search(r'^replicode_process_fuel_consumed_total\{pid="1"\} (\d+)$', body, re.MULTILINE)
+    return int(match.group(1)) if match else None
+
+
+def wait_for_fuel(metrics_port, above):
+    """Waits for process 1's fuel to pass `above`. Returns the last reading."""
+    deadline = time.time() + TIMEOUT
+    reading = None
+    while time.time() < deadline:
+        reading = fuel(metrics_port)
+        if reading is not None and reading > above:
+            return reading
+        time.sleep(0.1)
+    return reading
+
+
+def run():
+    server = socket.socket()
+    server.bind(("127.0.0.1", 0))
+    server.listen(1)
+    server.settimeout(TIMEOUT)
+    metrics_port = free_port()
+    runtime = subprocess.Popen(["target/debug/runtime", "tcp", f"127.0.0.1:{server.getsockname()[1]}",
+                                f"--metrics-port={metrics_port}"],
+                               stdout=subprocess.DEVNULL, stderr=subprocess.DEVNULL)
+    try:
+        conn, _ = server.accept()
+        conn.sendall(batch(1, record(2, 0, GUEST) + record(0, 0, b"clock:1")))
+        readings = [wait_for_fuel(metrics_port, 0)]
+        for step in range(STEPS):
+            if readings[-1] is None:
+                break
+            conn.sendall(batch(step + 2, record(1, 1, b"fd:0,body:x\n") + record(0, 0, b"clock:1")))
+            readings.append(wait_for_fuel(metrics_port, readings[-1] + LOOP_FUEL))
+        conn.close()
+    except socket.timeout:
+        print(f"FAIL: the runtime did not connect within {TIMEOUT}s")
+        return 1
+    finally:
+        runtime.kill()
+        runtime.wait()
+        server.close()
+    if None in readings:
+        print(f"FAIL: /metrics did not report the process's fuel, got {readings}")
+        return 1
+    if any(later < earlier + LOOP_FUEL for earlier, later in zip(readings, readings[1:])):
+        print(f"FAIL: the fuel did not go up by a compute loop after each line of stdin: {readings}")
+        return 1
+    print(f"PASS: the process's fuel went up after every turn: {readings}")
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(run())
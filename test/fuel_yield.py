@@ -0,0 +1,74 @@
+#!/usr/bin/env python3
+"""
+fuel_yield.py
+---------------------------------
+• Writes a consensus file that starts two processes: one busy-loops without
+  making a single syscall and then prints <A>, the other prints <B> at once.
+• Replays it with `runtime benchmark` and checks that with `--fuel=0` the
+  busy loop runs to the end before the second process starts, and that by
+  default and with `--fuel=1000000` it yields whenever its turn's fuel is
+  used up, lets <B> through first, still finishes, and yields the same
+  number of times on every replay.
+Usage (after `cargo build --bin runtime`, from the repository root):
+    python3 test/fuel_yield.py
+"""
+import os, re, subprocess, sys, tempfile
+from support import record
+
+BUSY = b"""(module
+  (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 0) "\\10\\00\\00\\00\\03\\00\\00\\00")
+  (data (i32.const 16) "<A>")
+  (func (export "_start")
+    (local $i i32)
+    (loop $busy
+      (local.set $i (i32.add (local.get $i) (i32.const 1)))
+      (br_if $busy (i32.lt_u (local.get $i) (i32.const 3000000))))
+    (drop (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 32)))))
+"""
+QUICK = BUSY.replace(b"<A>", b"<B>").replace(b"3000000", b"1")
+TIMEOUT = 60
+
+
+def replay(*flags):
+    """Returns the guests' output and how many times the busy one yielded."""
+    records = record(2, 0, BUSY) + record(2, 0, QUICK) + record(0, 0, b"clock:1")
+    with tempfile.NamedTemporaryFile(suffix=".bin", delete=False) as f:
+        f.write(records)
+    try:
+        result = subprocess.run(["target/debug/runtime", "benchmark", *flags, f.name],
+                                env={"RUST_LOG": "info"}, capture_output=True, timeout=TIMEOUT)
+    finally:
+        os.unlink(f.name)
+    yields = len(re.findall(r"Process 1 yielded; moving it to Ready queue", result.stderr.decode(errors="replace")))
+    return "".join(re.findall(r"<[AB]>", result.stdout.decode(errors="replace"))), yields
+
+
+def run():
+    output, _ = replay("--fuel=0")
+    if output != "<A><B>":
+        print(f"FAIL: with --fuel=0 the busy loop should finish before <B>, got {output!r}")
+        return 1
+    counts = []
+    for flags in ((), ("--fuel=1000000",)):
+        output, yields = replay(*flags)
+        if output != "<B><A>" or yields < 2:
+            print(f"FAIL: with {flags or 'the default fuel'} the busy loop should yield to <B> and still finish, "
+                  f"got {output!r} after {yields} yields")
+            return 1
+        _, again = replay(*flags)
+        if again != yields:
+            print(f"FAIL: the busy loop yielded {yields} times on one replay and {again} on another")
+            return 1
+        counts.append(yields)
+    if counts[0] >= counts[1]:
+        print(f"FAIL: the default slice of 2000000 yielded {counts[0]} times, --fuel=1000000 {counts[1]}")
+        return 1
+    print(f"PASS: the busy loop yielded {counts[0]} times by default and {counts[1]} with --fuel=1000000, "
+          f"and still finished")
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(run())
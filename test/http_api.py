@@ -0,0 +1,97 @@
+#!/usr/bin/env python3
+"""
+http_api.py
+---------------------------------
+• Runs `consensus tcp` with REPLICODE_HTTP_PORT=0, so its HTTP server binds a
+  free port, and reads the port from its log.
+• Checks /healthz answers 200, /api/connections, /api/listeners and
+  /api/mappings answer 200 with a JSON list, and /api/processes with a JSON
+  object, all as application/json; an unknown /api endpoint answers 404 and
+  a POST 405.
+Usage (after `cargo build --bin consensus`, from the repository root; uses port 9000):
+    python3 test/http_api.py
+"""
+import glob, json, os, re, subprocess, sys, threading, urllib.error, urllib.request
+
+TIMEOUT = 10
+ENDPOINTS = {"/api/connections": list, "/api/listeners": list, "/api/mappings": list, "/api/processes": dict}
+
+
+def http_port(consensus):
+    """Returns the port consensus logs its HTTP server is listening on, and
+    keeps draining its log afterwards."""
+    found = threading.Event()
+    port = []
+
+    def read_log():
+        for line in consensus.stderr:
+            match = re.search(rb"HTTP server listening on 127\.0\.0\.1:(\d+)", line)
+            if match and not port:
+                port.append(int(match.group(1)))
+                found.set()
+
+    threading.Thread(target=read_log, daemon=True).start()
+    found.wait(TIMEOUT)
+    return port[0] if port else None
+
+
+def request(port, path, method="GET"):
+    """Returns the status, Content-Type and body of a request to consensus."""
+    req = urllib.request.Request(f"http://127.0.0.1:{port}{path}", method=method)
+    try:
+        with urllib.request.urlopen(req, timeout=TIMEOUT) as response:
+            return response.status, response.headers.get("Content-Type"), response.read()
+    except urllib.error.HTTPError as e:
+        return e.code, e.headers.get("Content-Type"), e.read()
+
+
+def check(port):
+    """Returns why the endpoints are wrong, or None."""
+    status, _, _ = request(port, "/healthz")
+    if status != 200:
+        return f"/healthz answered {status}"
+    for path, kind in ENDPOINTS.items():
+        status, content_type, body = request(port, path)
+        if status != 200 or content_type != "application/json":
+            return f"{path} answered {status} with {content_type}"
+        try:
+            value = json.loads(body)
+        except ValueError:
+            return f"{path} is not JSON: {body!r}"
+        if not isinstance(value, kind):
+            return f"{path} should be a JSON {kind.__name__}, got {value!r}"
+    status, _, _ = request(port, "/api/nope")
+    if status != 404:
+        return f"an unknown endpoint answered {status}"
+    status, _, _ = request(port, "/api/connections", method="POST")
+    if status != 405:
+        return f"a POST answered {status}"
+    return None
+
+
+def run():
+    before = set(glob.glob("sessions/session-*.bin"))
+    consensus = subprocess.Popen(["target/debug/consensus", "tcp"], stdin=subprocess.PIPE,
+                                 stdout=subprocess.DEVNULL, stderr=subprocess.PIPE,
+                                 env={"REPLICODE_HTTP_PORT": "0", "RUST_LOG": "info"})
+    try:
+        port = http_port(consensus)
+        failure = check(port) if port else "consensus did not log its HTTP port"
+        consensus.stdin.write(b"exit\n")
+        consensus.stdin.close()
+        consensus.wait(timeout=TIMEOUT)
+    except subprocess.TimeoutExpired:
+        consensus.kill()
+        failure = "consensus did not exit"
+    finally:
+        for path in set(glob.glob("sessions/session-*.bin")) - before:
+            os.unlink(path)
+    if failure:
+        print(f"FAIL: {failure}")
+        return 1
+    print(f"PASS: the HTTP server on port {port} answered /healthz and each /api endpoint with JSON")
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(run())
@@ -0,0 +1,75 @@
+#!/usr/bin/env python3
+"""
+init_deadline.py
+---------------------------------
+• Replays a consensus file with `runtime benchmark` that starts a guest
+  which sleeps in poll_oneoff and prints <woke> when it wakes, twice: with
+  `deadline:5000` on its Init record (what `init <wasm_file> --deadline
+  5000` sends) and a 1-second sleep, and with `deadline:50000` and a
+  2000 ns sleep. Each batch advances the logical clock by 1000 ns.
+• Checks the first process is killed in the batch that takes the clock to
+  its deadline, 5000 ns after its Init was applied at 0 ns, not before or
+  later, and that the scheduler joins it and removes its sandbox.
+• Checks the second process wakes and exits before its deadline.
+Usage (after `cargo build --bin runtime`, from the repository root):
+    python3 test/init_deadline.py
+"""
+import glob, os, re, subprocess, sys, tempfile
+from support import record
+
+GUEST = """(module
+  (import "wasi_snapshot_preview1" "poll_oneoff" (func $poll_oneoff (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 64) "<woke>")
+  (func (export "_start")
+    ;; one relative clock subscription of SLEEP ns
+    (i64.store (i32.const 152) (i64.const SLEEP))
+    (drop (call $poll_oneoff (i32.const 128) (i32.const 192) (i32.const 1) (i32.const 24)))
+    (i32.store (i32.const 0) (i32.const 64))
+    (i32.store (i32.const 4) (i32.const 6))
+    (drop (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 8)))))
+"""
+TIMEOUT = 30
+
+
+def guest(sleep):
+    return GUEST.replace("SLEEP", str(sleep)).encode()
+
+
+def run():
+    sandboxes = set(glob.glob("wasi_sandbox_*"))
+    records = (record(2, 0, b"deadline:5000\0" + guest(10**9)) + record(2, 0, b"deadline:50000\0" + guest(2000))
+               + record(0, 0, b"clock:1000") * 10)
+    with tempfile.NamedTemporaryFile(suffix=".bin", delete=False) as f:
+        f.write(records)
+    try:
+        result = subprocess.run(["target/debug/runtime", "benchmark", f.name], env={"RUST_LOG": "info"},
+                                capture_output=True, timeout=TIMEOUT)
+    finally:
+        os.unlink(f.name)
+    output, log = result.stdout.decode(errors="replace"), result.stderr.decode(errors="replace")
+    killed = re.findall(r"Process (\d+) reached its deadline of (\d+) ns at logical time (\d+) ns", log)
+    if len(killed) != 1 or killed[0][0] != "1":
+        print(f"FAIL: expected only process 1 to reach its deadline, got {killed}")
+        return 1
+    _, deadline, now = killed[0]
+    if (deadline, now) != ("5000", "5000"):
+        print(f"FAIL: expected process 1 to be killed as the clock reached 5000 ns, "
+              f"it was killed at {now} ns for a deadline of {deadline} ns")
+        return 1
+    if "Process 1 finished (killed at its deadline) and joined." not in log:
+        print("FAIL: the scheduler never joined process 1 after its deadline")
+        return 1
+    if output.count("<woke>") != 1 or "Process 2 finished (exited) and joined." not in log:
+        print(f"FAIL: expected only process 2 to wake and exit, got {output!r}")
+        return 1
+    if set(glob.glob("wasi_sandbox_*")) - sandboxes:
+        print("FAIL: the sandbox of the process killed at its deadline was left behind")
+        return 1
+    print(f"PASS: process 1 was killed at logical time {now} ns, its deadline, and process 2 exited")
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(run())
@@ -0,0 +1,52 @@
+#!/usr/bin/env python3
+"""
+init_env.py
+---------------------------------
+• Replays a consensus file with `runtime benchmark` whose Init record carries
+  `env:FOO=bar\\x1FBAZ=qux`, which `init <wasm_file> -e FOO=bar -e BAZ=qux`
+  sends. The guest reads its environment with environ_sizes_get and
+  environ_get and prints the strings.
+• Checks the guest saw exactly FOO=bar and BAZ=qux, in that order.
+Usage (after `cargo build --bin runtime`, from the repository root):
+    python3 test/init_env.py
+"""
+import os, re, subprocess, sys, tempfile
+from support import record
+
+GUEST = b"""(module
+  (import "wasi_snapshot_preview1" "environ_sizes_get" (func $environ_sizes_get (param i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "environ_get" (func $environ_get (param i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 255) "<")
+  (func (export "_start")
+    (drop (call $environ_sizes_get (i32.const 16) (i32.const 20)))
+    (drop (call $environ_get (i32.const 64) (i32.const 256)))
+    ;; close the strings with '>' and print them, NULs and all
+    (i32.store8 (i32.add (i32.const 256) (i32.load (i32.const 20))) (i32.const 62))
+    (i32.store (i32.const 0) (i32.const 255))
+    (i32.store (i32.const 4) (i32.add (i32.load (i32.const 20)) (i32.const 2)))
+    (drop (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 8)))))
+"""
+TIMEOUT = 30
+
+
+def run():
+    records = record(2, 0, b"env:FOO=bar\x1fBAZ=qux\0" + GUEST) + record(0, 0, b"clock:1")
+    with tempfile.NamedTemporaryFile(suffix=".bin", delete=False) as f:
+        f.write(records)
+    try:
+        result = subprocess.run(["target/debug/runtime", "benchmark", f.name],
+                                capture_output=True, timeout=TIMEOUT)
+    finally:
+        os.unlink(f.name)
+    printed = re.findall(rb"<([^<>]*)>", result.stdout)
+    if printed != [b"FOO=bar\0BAZ=qux\0"]:
+        print(f"FAIL: expected the guest's environment to be FOO=bar and BAZ=qux, got {printed}")
+        return 1
+    print("PASS: the guest read the environment its Init record carried")
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(run())
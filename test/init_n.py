@@ -0,0 +1,52 @@
+#!/usr/bin/env python3
+"""
+init_n.py
+---------------------------------
+• Replays a consensus file with `runtime benchmark` that starts one process
+  with an Init record and, in the next batch, 10 workers from a single InitN
+  record carrying wasm_programs/test_init_n.wat. Each worker prints "worker"
+  and exits.
+• Checks the InitN started processes 2..=11, following on from the earlier
+  process, that "worker" was printed 10 times and that each of the pids 2 to
+  11 finished.
+Usage (after `cargo build --bin runtime`, from the repository root):
+    python3 test/init_n.py
+"""
+import os, re, struct, subprocess, sys, tempfile
+from support import record
+
+WORKERS = 10
+TIMEOUT = 60
+
+
+def run():
+    with open("wasm_programs/test_init_n.wat", "rb") as f:
+        worker = f.read()
+    records = (record(2, 0, worker) + record(0, 0, b"clock:1")
+               + record(11, 0, struct.pack("<I", WORKERS) + worker) + record(0, 0, b"clock:1"))
+    with tempfile.NamedTemporaryFile(suffix=".bin", delete=False) as f:
+        f.write(records)
+    try:
+        result = subprocess.run(["target/debug/runtime", "benchmark", f.name], env={"RUST_LOG": "info"},
+                                capture_output=True, timeout=TIMEOUT)
+    finally:
+        os.unlink(f.name)
+    log = result.stderr.decode(errors="replace")
+    if f"Starting processes 2..={WORKERS + 1} for InitN" not in log:
+        print(f"FAIL: expected the InitN to start processes 2..={WORKERS + 1}")
+        return 1
+    workers = result.stdout.decode(errors="replace").count("worker\n")
+    # The first process prints "worker" too
+    if workers != WORKERS + 1:
+        print(f"FAIL: expected {WORKERS + 1} lines of worker, got {workers}")
+        return 1
+    finished = sorted(int(pid) for pid in re.findall(r"Process (\d+) finished", log))
+    if finished != list(range(1, WORKERS + 2)):
+        print(f"FAIL: expected processes 1 to {WORKERS + 1} to finish, got {finished}")
+        return 1
+    print(f"PASS: one InitN record started {WORKERS} workers with the consecutive pids 2 to {WORKERS + 1}")
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(run())
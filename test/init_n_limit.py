@@ -0,0 +1,49 @@
+#!/usr/bin/env python3
+"""
+init_n_limit.py
+---------------------------------
+• Replays a consensus file with `runtime benchmark` whose first batch has an
+  InitN record for 1025 processes, one more than an InitN may start, and
+  whose second has an InitN for 3, each from a guest that prints "hi".
+• Checks the guest printed "hi" three times: the oversized record was
+  rejected without starting any process, and the other was applied.
+Usage (after `cargo build --bin runtime`, from the repository root):
+    python3 test/init_n_limit.py
+"""
+import os, struct, subprocess, sys, tempfile
+from support import record
+
+GUEST = b"""(module
+  (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 16) "hi\\n")
+  (func (export "_start")
+    (i32.store (i32.const 0) (i32.const 16))
+    (i32.store (i32.const 4) (i32.const 3))
+    (drop (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 8)))))
+"""
+MAX_INIT_N = 1024
+TIMEOUT = 60
+
+
+def init_n(count):
+    return record(11, 0, struct.pack("<I", count) + GUEST)
+
+
+def run():
+    with tempfile.NamedTemporaryFile(suffix=".bin", delete=False) as f:
+        f.write(init_n(MAX_INIT_N + 1) + record(0, 0, b"clock:1") + init_n(3) + record(0, 0, b"clock:1"))
+    try:
+        result = subprocess.run(["target/debug/runtime", "benchmark", f.name], capture_output=True, timeout=TIMEOUT)
+    finally:
+        os.unlink(f.name)
+    output = result.stdout.decode(errors="replace")
+    if output.count("hi\n") != 3:
+        print(f"FAIL: expected 3 processes to print hi, got {output.count('hi')} ({output[:200]!r})")
+        return 1
+    print(f"PASS: an InitN for {MAX_INIT_N + 1} processes was rejected, and one for 3 started 3")
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(run())
@@ -0,0 +1,64 @@
+#!/usr/bin/env python3
+"""
+init_quota.py
+---------------------------------
+• Replays a consensus file with `runtime benchmark` that starts the same guest
+  twice, with Init records carrying `quota:8192` and `quota:32768` (what
+  `init <wasm_file> -q <bytes>` sends). The guest creates a file and appends
+  1 KiB to it until fd_write fails, then prints how many writes succeeded.
+• Checks the process with the smaller quota was stopped first: creating the
+  file takes 4 KiB of either quota, leaving room for 4 and 28 writes.
+Usage (after `cargo build --bin runtime`, from the repository root):
+    python3 test/init_quota.py
+"""
+import os, subprocess, sys, tempfile
+from support import record
+
+GUEST = b"""(module
+  (import "wasi_snapshot_preview1" "path_open" (func $path_open (param i32 i32 i32 i32 i32 i64 i64 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 256) "fill")
+  (data (i32.const 32) "writes ??\\n")
+  (func (export "_start")
+    (local $fd i32) (local $count i32)
+    (drop (call $path_open (i32.const 3) (i32.const 0) (i32.const 256) (i32.const 4) (i32.const 1)
+      (i64.const -1) (i64.const -1) (i32.const 0) (i32.const 64)))
+    (local.set $fd (i32.load (i32.const 64)))
+    (i32.store (i32.const 16) (i32.const 1024))
+    (i32.store (i32.const 20) (i32.const 1024))
+    (block $full
+      (loop $next
+        (br_if $full (call $fd_write (local.get $fd) (i32.const 16) (i32.const 1) (i32.const 24)))
+        (local.set $count (i32.add (local.get $count) (i32.const 1)))
+        (br_if $next (i32.lt_u (local.get $count) (i32.const 99)))))
+    (i32.store8 (i32.const 39) (i32.add (i32.const 48) (i32.div_u (local.get $count) (i32.const 10))))
+    (i32.store8 (i32.const 40) (i32.add (i32.const 48) (i32.rem_u (local.get $count) (i32.const 10))))
+    (i32.store (i32.const 0) (i32.const 32))
+    (i32.store (i32.const 4) (i32.const 10))
+    (drop (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 8)))))
+"""
+TIMEOUT = 30
+
+
+def run():
+    records = (record(2, 0, b"quota:8192\0" + GUEST) + record(2, 0, b"quota:32768\0" + GUEST)
+               + record(0, 0, b"clock:1"))
+    with tempfile.NamedTemporaryFile(suffix=".bin", delete=False) as f:
+        f.write(records)
+    try:
+        result = subprocess.run(["target/debug/runtime", "benchmark", f.name],
+                                capture_output=True, timeout=TIMEOUT)
+    finally:
+        os.unlink(f.name)
+    counts = sorted(int(line.split()[1]) for line in result.stdout.decode().splitlines()
+                    if line.startswith("writes "))
+    if counts != [4, 28]:
+        print(f"FAIL: expected 4 writes under the 8 KiB quota and 28 under the 32 KiB one, got {counts}")
+        return 1
+    print("PASS: each process was held to the quota its Init record set")
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(run())
@@ -0,0 +1,79 @@
+#!/usr/bin/env python3
+"""
+inject_mismatch.py
+---------------------------------
+• Stands in for `consensus tcp` and starts wasm_programs/test_inject_mismatch.wat,
+  which opens a socket (FD 4) and a directory (FD 5) and then waits for a
+  line on stdin. The next batch sends FD updates to its socket, its
+  directory, FD 9, which is not open, and to process 7, which does not
+  exist, then a line on stdin.
+• Checks the runtime sent consensus a status record for each refused update,
+  naming why: a socket, a directory, not open, no such process. Also checks
+  the guest still got the line on stdin.
+Usage (after `cargo build --bin runtime`, from the repository root; uses port 9000):
+    python3 test/inject_mismatch.py
+"""
+import socket, struct, subprocess, sys, time, zlib
+from support import read_outgoing_records, record, watch
+
+EXPECTED = [
+    (1, b"fd:4,error:FD 4 is a socket, cannot inject stdin-style data (use NetworkIn)"),
+    (1, b"fd:5,error:FD 5 is a directory, cannot inject stdin-style data"),
+    (1, b"fd:9,error:FD 9 is not open"),
+    (7, b"fd:0,error:no process 7"),
+]
+TIMEOUT = 10
+
+
+def batch(number, records):
+    return struct.pack("<QBQQ", number, 0x20, 0, len(records)) + records + struct.pack("<I", zlib.crc32(records))
+
+
+def run():
+    with open("wasm_programs/test_inject_mismatch.wat", "rb") as f:
+        guest = f.read()
+    server = socket.socket()
+    server.setsockopt(socket.SOL_SOCKET, socket.SO_REUSEADDR, 1)
+    server.bind(("127.0.0.1", 9000))
+    server.listen(1)
+    runtime = subprocess.Popen(["target/debug/runtime", "tcp"], stdout=subprocess.PIPE,
+                               stderr=subprocess.DEVNULL)
+    output = watch(runtime.stdout)
+    refusals = []
+    try:
+        conn, _ = server.accept()
+        conn.settimeout(0.2)
+        conn.sendall(batch(1, record(2, 0, guest) + record(0, 0, b"clock:1")))
+        updates = (record(1, 1, b"fd:4,body:to-socket") + record(1, 1, b"fd:5,body:to-directory")
+                   + record(1, 1, b"fd:9,body:to-nothing") + record(1, 7, b"fd:0,body:to-nobody")
+                   + record(1, 1, b"fd:0,body:done"))
+        number, deadline = 2, time.time() + TIMEOUT
+        while len(refusals) < len(EXPECTED) and time.time() < deadline:
+            # The runtime only sends its batch before reading the next one
+            conn.sendall(batch(number, (updates if number == 2 else b"") + record(0, 0, b"clock:1")))
+            number += 1
+            try:
+                records = read_outgoing_records(conn)
+            except socket.timeout:
+                continue
+            refusals += [(pid, payload) for msg_type, pid, payload in records if msg_type == 1]
+        conn.close()
+        runtime.wait(timeout=TIMEOUT)
+    except subprocess.TimeoutExpired:
+        runtime.kill()
+        print("FAIL: the runtime did not exit")
+        return 1
+    finally:
+        server.close()
+    if refusals != EXPECTED:
+        print(f"FAIL: expected a refusal for each mismatched FD update:\n  {EXPECTED}\ngot\n  {refusals}")
+        return 1
+    if b"done\n" not in output:
+        print(f"FAIL: expected the guest to echo done, got {bytes(output)!r}")
+        return 1
+    print("PASS: each mismatched FD update was refused with its reason, and stdin still reached the guest")
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(run())
@@ -0,0 +1,88 @@
+#!/usr/bin/env python3
+"""
+invalid_init_options.py
+---------------------------------
+• Stands in for `consensus tcp` and sends a batch of Inits of a guest that
+  prints "started": each of the first ones has one invalid option (`mem:`,
+  `limits:`, `ports:`, `cwd:`, `quota:`, `seed:`, `env:`, `cache:`,
+  `deadline:`, `readonly:`, `tmpfs:`), and the last has valid ones.
+• Checks the runtime reports each invalid Init back to consensus as failed
+  (a type 2 record starting "error:" that names the option), and that only
+  the valid one starts.
+Usage (after `cargo build --bin runtime`, from the repository root):
+    python3 test/invalid_init_options.py
+"""
+import socket, subprocess, sys, time
+from support import batch, read_outgoing_records, record
+
+GUEST = b"""(module
+  (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 16) "started\\n")
+  (func (export "_start")
+    (i32.store (i32.const 0) (i32.const 16))
+    (i32.store (i32.const 4) (i32.const 8))
+    (drop (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 8)))))
+"""
+INVALID = [
+    (b"mem:lots", b"memory limit"),
+    (b"limits:write=0", b"resource limits"),
+    (b"ports:0", b"first port"),
+    (b"cwd:../outside", b"working directory"),
+    (b"quota:-1", b"disk quota"),
+    (b"seed:random", b"random seed"),
+    (b"env:=value", b"environment variable"),
+    (b"cache:big", b"file cache size"),
+    (b"deadline:soon", b"deadline"),
+    (b"readonly:yes", b"read-only flag"),
+    (b"tmpfs:on", b"tmpfs flag"),
+]
+VALID = b"readonly:0\0tmpfs:0\0mem:16777216\0limits:write=1024\0ports:20000\0cwd:work\0quota:1048576\0seed:7\0env:A=1\0"
+TIMEOUT = 10
+
+
+def run():
+    server = socket.socket()
+    server.bind(("127.0.0.1", 0))
+    server.listen(1)
+    server.settimeout(TIMEOUT)
+    runtime = subprocess.Popen(["target/debug/runtime", "tcp", f"127.0.0.1:{server.getsockname()[1]}"],
+                               stdout=subprocess.PIPE, stderr=subprocess.DEVNULL)
+    failures = {}
+    try:
+        conn, _ = server.accept()
+        conn.settimeout(TIMEOUT)
+        inits = b"".join(record(2, 0, option + b"\0" + GUEST) for option, _ in INVALID)
+        conn.sendall(batch(1, inits + record(2, 0, VALID + GUEST) + record(0, 0, b"clock:1")))
+        number, deadline = 2, time.time() + TIMEOUT
+        while len(failures) < len(INVALID) and time.time() < deadline:
+            # The runtime only sends its batch before reading the next one
+            conn.sendall(batch(number, record(0, 0, b"clock:1")))
+            number += 1
+            for msg_type, pid, payload in read_outgoing_records(conn):
+                if msg_type == 2:
+                    failures[pid] = payload
+        conn.close()
+        output, _ = runtime.communicate(timeout=TIMEOUT)
+    except (socket.timeout, subprocess.TimeoutExpired, EOFError) as e:
+        print(f"FAIL: the runtime stopped answering ({e})")
+        return 1
+    finally:
+        runtime.kill()
+        server.close()
+
+    for pid, (option, named) in enumerate(INVALID, start=1):
+        failure = failures.get(pid, b"")
+        if not failure.startswith(b"error:") or named not in failure:
+            print(f"FAIL: expected the Init with {option.decode()} to be reported as failed, got {failure}")
+            return 1
+    print(f"PASS: the {len(INVALID)} Inits with an invalid option were rejected and reported")
+    if len(INVALID) + 1 in failures or output.count(b"started\n") != 1:
+        print(f"FAIL: expected only the valid Init to start, got {failures.get(len(INVALID) + 1)} and {output}")
+        return 1
+    print("PASS: the Init with valid options started")
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(run())
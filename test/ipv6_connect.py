@@ -0,0 +1,145 @@
+#!/usr/bin/env python3
+"""
+ipv6_connect.py
+---------------------------------
+• Runs `consensus tcp` and a runtime, and starts a guest that connects to a
+  server listening on the IPv6 loopback from a socket opened with domain 2
+  (wasi-libc's AF_INET6) and a `sockaddr_in6`, and to one on 127.0.0.1 from
+  a socket opened with domain 1 (AF_INET) and a `sockaddr_in`, sending a
+  line on each. Both addresses carry family 2, so only the socket's domain
+  tells them apart. Then sends a datagram to [::1] with sock_send_to from an
+  unbound SOCK_DGRAM socket of domain 2.
+• Checks each server got its line over one connection, so the NAT table
+  connected to [::1] for the first and 127.0.0.1 for the second, and that
+  the datagram reached a UDP socket on [::1].
+Usage (after `cargo build`, from the repository root; uses port 9000):
+    python3 test/ipv6_connect.py
+"""
+import glob, os, socket, subprocess, sys, tempfile, threading, time
+
+GUEST = """(module
+  (import "wasi_snapshot_preview1" "sock_open" (func $sock_open (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "sock_connect" (func $sock_connect (param i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "sock_send" (func $sock_send (param i32 i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "sock_send_to" (func $sock_send_to (param i32 i32 i32 i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "sock_close" (func $sock_close (param i32) (result i32)))
+  (memory (export "memory") 1)
+  ;; sockaddr_in6 for [::1]:PORT6
+  (data (i32.const 32) "\\02\\00PORT6\\00\\00\\00\\00\\00\\00\\00\\00\\00\\00\\00\\00\\00\\00\\00\\00\\00\\00\\00\\01\\00\\00\\00\\00")
+  ;; sockaddr_in for 127.0.0.1:PORT4
+  (data (i32.const 64) "\\02\\00PORT4\\7f\\00\\00\\01\\00\\00\\00\\00\\00\\00\\00\\00")
+  (data (i32.const 96) "hello over v6\\nhello over v4\\n")
+  ;; sockaddr_in6 for [::1]:PORTU
+  (data (i32.const 128) "\\02\\00PORTU\\00\\00\\00\\00\\00\\00\\00\\00\\00\\00\\00\\00\\00\\00\\00\\00\\00\\00\\00\\01\\00\\00\\00\\00")
+  (data (i32.const 160) "hello via udp\\n")
+  (func $send_line (param $domain i32) (param $addr i32) (param $len i32) (param $line i32)
+    (local $fd i32)
+    (drop (call $sock_open (local.get $domain) (i32.const 1) (i32.const 0) (i32.const 0)))
+    (local.set $fd (i32.load (i32.const 0)))
+    (drop (call $sock_connect (local.get $fd) (local.get $addr) (local.get $len)))
+    ;; the runtime's sock_send takes the data itself, not an iovec array
+    (drop (call $sock_send (local.get $fd) (local.get $line) (i32.const 14) (i32.const 0) (i32.const 24)))
+    (drop (call $sock_close (local.get $fd))))
+  (func (export "_start")
+    (call $send_line (i32.const 2) (i32.const 32) (i32.const 28) (i32.const 96))
+    (call $send_line (i32.const 1) (i32.const 64) (i32.const 16) (i32.const 110))
+    (drop (call $sock_open (i32.const 2) (i32.const 2) (i32.const 0) (i32.const 0)))
+    (drop (call $sock_send_to (i32.load (i32.const 0)) (i32.const 160) (i32.const 14) (i32.const 0)
+      (i32.const 128) (i32.const 28) (i32.const 24)))))
+"""
+TIMEOUT = 10
+
+
+def collect(conn, received):
+    for chunk in iter(lambda: conn.recv(1024), b""):
+        received.append(chunk)
+
+
+def serve(server, connections):
+    """Accepts connections and collects what arrives on each."""
+    while True:
+        try:
+            conn, _ = server.accept()
+        except OSError:
+            return
+        received = []
+        connections.append(received)
+        threading.Thread(target=collect, args=(conn, received), daemon=True).start()
+
+
+def listen(family, host):
+    server = socket.socket(family)
+    server.bind((host, 0))
+    server.listen(4)
+    connections = []
+    threading.Thread(target=serve, args=(server, connections), daemon=True).start()
+    return server, connections
+
+
+def port_bytes(server):
+    return "".join(f"\\{b:02x}" for b in server.getsockname()[1].to_bytes(2, "big"))
+
+
+def run():
+    workdir = tempfile.mkdtemp()
+    before = set(glob.glob("sessions/session-*.bin"))
+    v6, v6_connections = listen(socket.AF_INET6, "::1")
+    v4, v4_connections = listen(socket.AF_INET, "127.0.0.1")
+    udp = socket.socket(socket.AF_INET6, socket.SOCK_DGRAM)
+    udp.bind(("::1", 0))
+    guest = os.path.join(workdir, "connect_ipv6.wat")
+    with open(guest, "w") as f:
+        f.write(GUEST.replace("PORT6", port_bytes(v6)).replace("PORT4", port_bytes(v4))
+                .replace("PORTU", port_bytes(udp)))
+    consensus = subprocess.Popen(["target/debug/consensus", "tcp"], stdin=subprocess.PIPE,
+                                 stdout=subprocess.DEVNULL, stderr=subprocess.DEVNULL)
+    try:
+        time.sleep(0.5)
+        runtime = subprocess.Popen(["target/debug/runtime", "tcp"], stdout=subprocess.DEVNULL,
+                                   stderr=subprocess.DEVNULL)
+        time.sleep(0.5)
+        consensus.stdin.write(f"init {guest}\n".encode())
+        consensus.stdin.flush()
+        time.sleep(2)
+        consensus.stdin.write(b"exit\n")
+        consensus.stdin.close()
+        try:
+            runtime.wait(timeout=TIMEOUT)
+            consensus.wait(timeout=TIMEOUT)
+        except subprocess.TimeoutExpired:
+            runtime.kill()
+            print("FAIL: consensus or the runtime did not exit")
+            return 1
+    finally:
+        consensus.kill()
+        v6.close()
+        v4.close()
+        os.unlink(guest)
+        os.rmdir(workdir)
+        for path in set(glob.glob("sessions/session-*.bin")) - before:
+            os.unlink(path)
+    udp.settimeout(1)
+    try:
+        datagram = udp.recv(1024)
+    except socket.timeout:
+        datagram = None
+    finally:
+        udp.close()
+    v6_lines = [b"".join(received) for received in v6_connections]
+    v4_lines = [b"".join(received) for received in v4_connections]
+    if v6_lines != [b"hello over v6\n"]:
+        print(f"FAIL: expected one connection to [::1] carrying the IPv6 line, got {v6_lines}")
+        return 1
+    if v4_lines != [b"hello over v4\n"]:
+        print(f"FAIL: expected one connection to 127.0.0.1 carrying the IPv4 line, got {v4_lines}")
+        return 1
+    if datagram != b"hello via udp\n":
+        print(f"FAIL: expected the datagram on the [::1] UDP socket, got {datagram}")
+        return 1
+    print("PASS: the guest reached the IPv6 and the IPv4 loopback listeners and sent a datagram "
+          "to [::1] through the NAT table")
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(run())
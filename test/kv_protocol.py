@@ -0,0 +1,90 @@
+#!/usr/bin/env python3
+"""
+kv_protocol.py
+---------------------------------
+• Stands in for wasm_programs/kv_server.c, speaking the framing documented
+  in consensus/src/clients/kv_protocol.rs, and runs `consensus kv-client`
+  against it.
+• Types `set` with a value holding spaces and an escaped newline, and checks
+  the server gets a SET header with the value's length, then exactly its
+  bytes.
+• Types `get` and answers with that value, then with an ERR, and checks the
+  client prints the value intact, newline included, and the error.
+Usage (after `cargo build --bin consensus`, from the repository root):
+    python3 test/kv_protocol.py
+"""
+import socket, subprocess, sys, threading
+
+VALUE = b"hello big\nworld"
+TIMEOUT = 10
+
+
+def read_request(conn):
+    """Reads one request. Returns its header words and value bytes."""
+    header = b""
+    while not header.endswith(b"\n"):
+        chunk = conn.recv(1)
+        if not chunk:
+            return None, None
+        header += chunk
+    words = header.decode().split()
+    value = b""
+    if words[0] == "SET":
+        while len(value) < int(words[2]):
+            value += conn.recv(int(words[2]) - len(value))
+    return words, value
+
+
+def serve(server, requests):
+    """Answers each connection from the client, recording the requests."""
+    gets = 0
+    while True:
+        try:
+            conn, _ = server.accept()
+        except OSError:
+            return
+        with conn:
+            words, value = read_request(conn)
+            if words is None:
+                continue
+            requests.append((words, value))
+            if words[0] == "SET":
+                conn.sendall(b"OK\n")
+            elif words[0] == "GET":
+                gets += 1
+                conn.sendall(b"VALUE %d\n" % len(VALUE) + VALUE if gets == 1 else b"ERR key not found\n")
+            elif words[0] == "QUIT":
+                conn.sendall(b"BYE\n")
+
+
+def run():
+    server = socket.socket()
+    server.bind(("127.0.0.1", 0))
+    server.listen(4)
+    requests = []
+    threading.Thread(target=serve, args=(server, requests), daemon=True).start()
+    typed = "set greeting hello big\\nworld\nget greeting\nget greeting\nquit\n"
+    try:
+        result = subprocess.run(["target/debug/consensus", "kv-client", "127.0.0.1", str(server.getsockname()[1])],
+                                input=typed.encode(), capture_output=True, timeout=TIMEOUT)
+    finally:
+        server.close()
+    output = result.stdout.decode()
+    if not requests or requests[0] != (["SET", "greeting", str(len(VALUE))], VALUE):
+        print(f"FAIL: expected a SET of the {len(VALUE)}-byte value, the server got {requests[:1]}")
+        return 1
+    if [words for words, _ in requests[1:]] != [["GET", "greeting"]] * 2 + [["QUIT"]]:
+        print(f"FAIL: expected two GETs and a QUIT after the SET, the server got {requests[1:]}")
+        return 1
+    if "Server response: VALUE hello big\nworld\n" not in output:
+        print("FAIL: the client did not print the value intact")
+        return 1
+    if "Server response: ERR key not found" not in output:
+        print("FAIL: the client did not print the server's error")
+        return 1
+    print("PASS: a value with spaces and a newline went to the server and back by its length")
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(run())
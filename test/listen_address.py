@@ -0,0 +1,148 @@
+#!/usr/bin/env python3
+"""
+listen_address.py
+---------------------------------
+• Runs `consensus tcp 127.0.0.1:0`, so it listens for runtimes on a free
+  port, with REPLICODE_HTTP_PORT=0, and reads the port from its log.
+• Starts `runtime tcp 127.0.0.1:<port>` and checks consensus accepts it, then
+  inits a guest that prints a <HI> line and checks the runtime runs it.
+• Checks `consensus tcp` and `runtime tcp` given an address that does not
+  parse exit with status 1 and say the address is invalid.
+• Runs `consensus tcp 0.0.0.0:0` and checks its HTTP status server still
+  listens on 127.0.0.1, and on 0.0.0.0 only with REPLICODE_HTTP_ADDR=0.0.0.0.
+Usage (after `cargo build`, from the repository root):
+    python3 test/listen_address.py
+"""
+import glob, os, re, subprocess, sys, tempfile, threading
+
+GUEST = b"""(module
+  (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 0) "\\10\\00\\00\\00\\05\\00\\00\\00")
+  (data (i32.const 16) "<HI>\\n")
+  (func (export "_start")
+    (drop (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 32)))))
+"""
+TIMEOUT = 10
+
+
+def watch_log(consensus):
+    """Drains the consensus log in the background. Returns an event set once
+    it logs the runtime port, the port, and an event set once it accepts a
+    runtime."""
+    listening, accepted, port = threading.Event(), threading.Event(), []
+
+    def read_log():
+        for line in consensus.stderr:
+            match = re.search(rb"Listening for runtimes on 127\.0\.0\.1:(\d+)", line)
+            if match and not port:
+                port.append(int(match.group(1)))
+                listening.set()
+            if b"Accepted runtime 0" in line:
+                accepted.set()
+
+    threading.Thread(target=read_log, daemon=True).start()
+    return listening, port, accepted
+
+
+def printed(runtime, text):
+    """Returns an event set once the runtime prints `text`."""
+    seen = threading.Event()
+
+    def read_output():
+        output = b""
+        while not seen.is_set():
+            chunk = runtime.stdout.read1(4096)
+            if not chunk:
+                return
+            output += chunk
+            if text in output:
+                seen.set()
+
+    threading.Thread(target=read_output, daemon=True).start()
+    return seen
+
+
+def rejects_bad_address(binary):
+    result = subprocess.run([binary, "tcp", "not an address"], stdin=subprocess.DEVNULL,
+                            capture_output=True, timeout=TIMEOUT, env={"RUST_LOG": "error"})
+    return result.returncode == 1 and b"invalid address 'not an address'" in result.stderr
+
+
+def http_interface(env):
+    """Runs `consensus tcp 0.0.0.0:0` with `env` and returns the interface its
+    HTTP status server listens on, or None if it does not log one."""
+    before = set(glob.glob("sessions/session-*.bin"))
+    consensus = subprocess.Popen(["target/debug/consensus", "tcp", "0.0.0.0:0"], stdin=subprocess.PIPE,
+                                 stdout=subprocess.DEVNULL, stderr=subprocess.PIPE,
+                                 env={"REPLICODE_HTTP_PORT": "0", "RUST_LOG": "info", **env})
+    interface = []
+    found = threading.Event()
+
+    def read_log():
+        for line in consensus.stderr:
+            match = re.search(rb"HTTP server listening on ([\d.]+):\d+", line)
+            if match:
+                interface.append(match.group(1).decode())
+                found.set()
+
+    threading.Thread(target=read_log, daemon=True).start()
+    try:
+        found.wait(TIMEOUT)
+    finally:
+        consensus.kill()
+        consensus.wait()
+        for session in set(glob.glob("sessions/session-*.bin")) - before:
+            os.unlink(session)
+    return interface[0] if interface else None
+
+
+def run():
+    for env, expected in [({}, "127.0.0.1"), ({"REPLICODE_HTTP_ADDR": "0.0.0.0"}, "0.0.0.0")]:
+        interface = http_interface(env)
+        if interface != expected:
+            print(f"FAIL: with {env or 'no REPLICODE_HTTP_ADDR'}, the HTTP server listened on {interface}, not {expected}")
+            return 1
+    print("PASS: the HTTP server stayed on loopback for runtimes on 0.0.0.0, unless REPLICODE_HTTP_ADDR moved it")
+
+    for binary in ["target/debug/consensus", "target/debug/runtime"]:
+        if not rejects_bad_address(binary):
+            print(f"FAIL: {binary} tcp did not reject an invalid address")
+            return 1
+
+    before = set(glob.glob("sessions/session-*.bin"))
+    consensus = subprocess.Popen(["target/debug/consensus", "tcp", "127.0.0.1:0"], stdin=subprocess.PIPE,
+                                 stdout=subprocess.DEVNULL, stderr=subprocess.PIPE,
+                                 env={"REPLICODE_HTTP_PORT": "0", "RUST_LOG": "info"})
+    runtime = None
+    with tempfile.NamedTemporaryFile(suffix=".wat", delete=False) as f:
+        f.write(GUEST)
+    try:
+        listening, port, accepted = watch_log(consensus)
+        if not listening.wait(TIMEOUT):
+            print("FAIL: consensus did not log the port it listens for runtimes on")
+            return 1
+        runtime = subprocess.Popen(["target/debug/runtime", "tcp", f"127.0.0.1:{port[0]}"],
+                                   stdout=subprocess.PIPE, stderr=subprocess.DEVNULL)
+        if not accepted.wait(TIMEOUT):
+            print(f"FAIL: consensus on port {port[0]} did not accept the runtime")
+            return 1
+        consensus.stdin.write(f"init {f.name}\n".encode())
+        consensus.stdin.flush()
+        if not printed(runtime, b"<HI>").wait(TIMEOUT):
+            print("FAIL: the runtime did not run the guest")
+            return 1
+    finally:
+        os.unlink(f.name)
+        for process in [runtime, consensus]:
+            if process is not None:
+                process.kill()
+                process.wait()
+        for session in set(glob.glob("sessions/session-*.bin")) - before:
+            os.unlink(session)
+    print(f"PASS: consensus listened on port {port[0]} and the runtime pointed at it connected and ran a guest")
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(run())
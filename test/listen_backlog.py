@@ -0,0 +1,127 @@
+#!/usr/bin/env python3
+"""
+listen_backlog.py
+---------------------------------
+• Runs `consensus tcp` and a runtime, and starts a guest that binds a
+  loopback port, listens with a backlog of 1, and then, each time consensus
+  sends it a message, accepts one connection and sends "hi" on it.
+• Opens two connections before the guest accepts any, and checks a third
+  does not connect, since the host only queues as many as the backlog allows.
+• Sends one message and checks only the first connection was accepted, then
+  sends another and checks the second one now is.
+Usage (after `cargo build`, from the repository root; uses ports 9000 and 8080):
+    python3 test/listen_backlog.py
+"""
+import glob, os, socket, subprocess, sys, tempfile, time
+
+GUEST = """(module
+  (import "wasi_snapshot_preview1" "sock_open" (func $sock_open (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "sock_bind" (func $sock_bind (param i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "sock_listen" (func $sock_listen (param i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "sock_accept" (func $sock_accept (param i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "sock_send" (func $sock_send (param i32 i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_read" (func $fd_read (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  ;; sockaddr_in for 127.0.0.1:PORT
+  (data (i32.const 32) "\\02\\00PORTBYTES\\7f\\00\\00\\01\\00\\00\\00\\00\\00\\00\\00\\00")
+  (data (i32.const 96) "hi")
+  ;; waits for a message from consensus on stdin
+  (func $wait
+    (i32.store (i32.const 16) (i32.const 128))
+    (i32.store (i32.const 20) (i32.const 64))
+    (drop (call $fd_read (i32.const 0) (i32.const 16) (i32.const 1) (i32.const 24))))
+  (func $accept_one
+    (call $wait)
+    (loop $retry
+      (br_if $retry (call $sock_accept (i32.load (i32.const 0)) (i32.const 0) (i32.const 4))))
+    (drop (call $sock_send (i32.load (i32.const 4)) (i32.const 96) (i32.const 2) (i32.const 0) (i32.const 8))))
+  (func (export "_start")
+    (drop (call $sock_open (i32.const 1) (i32.const 1) (i32.const 0) (i32.const 0)))
+    (drop (call $sock_bind (i32.load (i32.const 0)) (i32.const 32) (i32.const 16)))
+    (drop (call $sock_listen (i32.load (i32.const 0)) (i32.const 1)))
+    (call $accept_one)
+    (call $accept_one)
+    (call $wait)))
+"""
+TIMEOUT = 10
+
+
+def free_tcp_port():
+    with socket.socket() as s:
+        s.bind(("127.0.0.1", 0))
+        return s.getsockname()[1]
+
+
+def greeted(client):
+    """Returns whether the guest has sent "hi" on `client`."""
+    client.settimeout(0.5)
+    try:
+        return client.recv(2) == b"hi"
+    except socket.timeout:
+        return False
+
+
+def send(consensus, line):
+    consensus.stdin.write(line.encode() + b"\n")
+    consensus.stdin.flush()
+
+
+def run():
+    workdir = tempfile.mkdtemp()
+    port = free_tcp_port()
+    path = os.path.join(workdir, "backlog_server.wat")
+    with open(path, "w") as f:
+        f.write(GUEST.replace("PORTBYTES", "".join(f"\\{b:02x}" for b in port.to_bytes(2, "big"))))
+    before = set(glob.glob("sessions/session-*.bin"))
+    consensus = subprocess.Popen(["target/debug/consensus", "tcp"], stdin=subprocess.PIPE,
+                                 stdout=subprocess.DEVNULL, stderr=subprocess.DEVNULL)
+    time.sleep(0.5)
+    runtime = subprocess.Popen(["target/debug/runtime", "tcp"], stdout=subprocess.DEVNULL,
+                               stderr=subprocess.DEVNULL)
+    clients, failure = [], None
+    try:
+        time.sleep(0.5)
+        send(consensus, f"init {path}")
+        time.sleep(1.5)
+        # With a backlog of 1 the kernel completes two handshakes and drops the third
+        for _ in range(2):
+            clients.append(socket.create_connection(("127.0.0.1", port), timeout=1))
+        try:
+            clients.append(socket.create_connection(("127.0.0.1", port), timeout=1))
+            failure = "a third connection got through a backlog of 1"
+        except OSError:
+            pass
+        if failure is None:
+            send(consensus, "msg 1 fd:0,body:go")
+            time.sleep(1)
+            if [greeted(c) for c in clients] != [True, False]:
+                failure = "the first message should accept the first connection and only that one"
+        if failure is None:
+            send(consensus, "msg 1 fd:0,body:go")
+            time.sleep(1)
+            if not greeted(clients[1]):
+                failure = "the second message should accept the second connection"
+        send(consensus, "exit")
+        consensus.stdin.close()
+        runtime.wait(timeout=TIMEOUT)
+        consensus.wait(timeout=TIMEOUT)
+    except subprocess.TimeoutExpired:
+        runtime.kill()
+        consensus.kill()
+        failure = "consensus or the runtime did not exit"
+    except OSError as e:
+        failure = f"could not connect to the guest's listener: {e}"
+    finally:
+        for client in clients:
+            client.close()
+        for session in set(glob.glob("sessions/session-*.bin")) - before:
+            os.unlink(session)
+    if failure:
+        print(f"FAIL: {failure}")
+        return 1
+    print("PASS: the listener queued only its backlog and the guest accepted one connection per accept")
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(run())
@@ -0,0 +1,80 @@
+#!/usr/bin/env python3
+"""
+max_batches.py
+---------------------------------
+• Writes a consensus file of five batches, each ending in a clock record;
+  the first also starts a guest that prints "started" and then reads stdin,
+  so it stays blocked.
+• Replays it with `runtime follow`, which would otherwise wait for more
+  input forever, once with --max-batches=3 and once with --max-records=2,
+  with RUST_LOG=applied_record=info.
+• Checks each run exits on its own with status 0, that the first applied
+  the records of exactly three batches and the second exactly two records,
+  and that the guest ran and the summary reports it was left blocked.
+Usage (after `cargo build --bin runtime`, from the repository root):
+    python3 test/max_batches.py
+"""
+import os, re, shutil, subprocess, sys, tempfile
+from support import record
+
+GUEST = b"""(module
+  (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_read" (func $fd_read (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 16) "started\\n")
+  (func (export "_start")
+    (i32.store (i32.const 0) (i32.const 16))
+    (i32.store (i32.const 4) (i32.const 8))
+    (drop (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 8)))
+    (i32.store (i32.const 4) (i32.const 64))
+    (drop (call $fd_read (i32.const 0) (i32.const 0) (i32.const 1) (i32.const 8)))))
+"""
+BATCHES = 5
+TIMEOUT = 20
+LINE = re.compile(rb"applied_record\] cursor=\d+ batch=(\d+) record=\d+ type=(\d+)")
+
+
+def run_limited(path, limit):
+    """Returns the exit status, stdout and the (batch, type) of each record applied."""
+    try:
+        result = subprocess.run(["target/debug/runtime", "follow", path, limit], capture_output=True,
+                                timeout=TIMEOUT, env={"RUST_LOG": "applied_record=info,info"})
+    except subprocess.TimeoutExpired:
+        return None, b"", []
+    applied = [(int(b), int(t)) for b, t in LINE.findall(result.stderr)]
+    return result.returncode, result.stdout, applied
+
+
+def run():
+    workdir = tempfile.mkdtemp()
+    path = os.path.join(workdir, "consensus_input.bin")
+    with open(path, "wb") as f:
+        f.write(record(2, 0, GUEST) + record(0, 0, b"clock:1") * BATCHES)
+    try:
+        runs = {
+            "--max-batches=3": (run_limited(path, "--max-batches=3"), [(1, 2), (1, 0), (2, 0), (3, 0)]),
+            "--max-records=2": (run_limited(path, "--max-records=2"), [(1, 2), (1, 0)]),
+        }
+    finally:
+        shutil.rmtree(workdir)
+    for limit, ((status, output, applied), expected) in runs.items():
+        if status is None:
+            print(f"FAIL: the runtime with {limit} was still running after {TIMEOUT}s")
+            return 1
+        if status != 0:
+            print(f"FAIL: the runtime with {limit} exited with {status}")
+            return 1
+        if applied != expected:
+            print(f"FAIL: expected {limit} to apply (batch, type) {expected}, got {applied}")
+            return 1
+        batches = len({b for b, _ in expected})
+        summary = f"Stopped after {batches} batches and {len(expected)} records: 0 processes finished, 1 left blocked.\n"
+        if output != b"started\n" + summary.encode():
+            print(f"FAIL: expected the guest to run once and then the summary with {limit}, got {output}")
+            return 1
+    print("PASS: the runtime stopped after 3 batches and after 2 records, leaving the guest blocked")
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(run())
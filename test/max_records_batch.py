@@ -0,0 +1,65 @@
+#!/usr/bin/env python3
+"""
+max_records_batch.py
+---------------------------------
+• Stands in for `consensus tcp` and sends a runtime started with
+  --max-records=3 a batch with the Init of a guest that echoes what it reads
+  from stdin, then a batch of a stdin line for it and two clock records.
+• Checks the guest echoes the line, and that the summary counts the first
+  batch only: the limit stopped the second before its last clock record, so
+  it must not be counted or acknowledged.
+Usage (after `cargo build --bin runtime`, from the repository root):
+    python3 test/max_records_batch.py
+"""
+import socket, subprocess, sys
+from support import record, batch
+
+GUEST = b"""(module
+  (import "wasi_snapshot_preview1" "fd_read" (func $fd_read (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (func (export "_start")
+    (loop $echo
+      (i32.store (i32.const 0) (i32.const 64))
+      (i32.store (i32.const 4) (i32.const 64))
+      (drop (call $fd_read (i32.const 0) (i32.const 0) (i32.const 1) (i32.const 8)))
+      (i32.store (i32.const 4) (i32.load (i32.const 8)))
+      (drop (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 8)))
+      (br $echo))))
+"""
+TIMEOUT = 10
+SUMMARY = b"Stopped after 1 batches and 3 records: 0 processes finished, 1 left blocked.\n"
+
+
+def run():
+    server = socket.socket()
+    server.bind(("127.0.0.1", 0))
+    server.listen(1)
+    server.settimeout(TIMEOUT)
+    port = server.getsockname()[1]
+    runtime = subprocess.Popen(["target/debug/runtime", "tcp", f"127.0.0.1:{port}", "--max-records=3"],
+                               stdout=subprocess.PIPE, stderr=subprocess.DEVNULL)
+    try:
+        conn, _ = server.accept()
+        conn.sendall(batch(1, record(2, 0, GUEST)))
+        conn.sendall(batch(2, record(1, 1, b"fd:0,body:a\n") + record(0, 0, b"clock:1")
+                           + record(0, 0, b"clock:1")))
+        output, _ = runtime.communicate(timeout=TIMEOUT)
+        conn.close()
+    except (socket.timeout, subprocess.TimeoutExpired):
+        print(f"FAIL: the runtime did not connect and stop within {TIMEOUT}s")
+        return 1
+    finally:
+        runtime.kill()
+        server.close()
+
+    if runtime.returncode != 0 or output != b"a\n" + SUMMARY:
+        print(f"FAIL: expected the guest to echo the line and a summary counting one batch, "
+              f"got status {runtime.returncode} and {output}")
+        return 1
+    print("PASS: the runtime stopped inside the second batch and did not count it as applied")
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(run())
@@ -0,0 +1,90 @@
+#!/usr/bin/env python3
+"""
+memory_limit.py
+---------------------------------
+• Replays, with `runtime benchmark`, the Init of a guest capped at two pages
+  with `mem:131072`, the WAT counterpart of wasm_programs/test_memory_limit.c.
+  It grows its memory by a page, which fits, and by another, which does not,
+  prints whether each grow succeeded, then reads a line from stdin and
+  echoes it.
+• Checks the first grow succeeded and the second returned -1 instead of
+  trapping, that the guest went on to echo its line, and that it exited
+  normally.
+• Replays a guest that traps after its grow was refused, and checks it is
+  reported as killed by the memory limit rather than as an ordinary trap.
+Usage (after `cargo build --bin runtime`, from the repository root):
+    python3 test/memory_limit.py
+"""
+import os, subprocess, sys, tempfile
+from support import record
+
+GROWER = b"""(module
+  (import "wasi_snapshot_preview1" "fd_read" (func $fd_read (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 100) "grew\\nrefused\\n")
+  (func $print (param $at i32) (param $len i32)
+    (i32.store (i32.const 0) (local.get $at))
+    (i32.store (i32.const 4) (local.get $len))
+    (drop (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 8))))
+  (func $grow
+    (if (i32.eq (memory.grow (i32.const 1)) (i32.const -1))
+      (then (call $print (i32.const 105) (i32.const 8)))
+      (else (call $print (i32.const 100) (i32.const 5)))))
+  (func (export "_start")
+    (call $grow)
+    (call $grow)
+    (i32.store (i32.const 0) (i32.const 200))
+    (i32.store (i32.const 4) (i32.const 64))
+    (drop (call $fd_read (i32.const 0) (i32.const 0) (i32.const 1) (i32.const 8)))
+    (call $print (i32.const 200) (i32.load (i32.const 8)))))
+"""
+# Aborts on a failed grow, as a C guest does when malloc returns NULL
+ABORTER = b"""(module
+  (memory (export "memory") 1)
+  (func (export "_start")
+    (if (i32.eq (memory.grow (i32.const 2)) (i32.const -1))
+      (then unreachable))))
+"""
+CAP = b"mem:131072\0"
+TIMEOUT = 10
+
+
+def replay(records):
+    """Returns the stdout and log of a replay of `records`, or None on a timeout."""
+    with tempfile.NamedTemporaryFile(suffix=".bin", delete=False) as f:
+        f.write(records)
+    try:
+        result = subprocess.run(["target/debug/runtime", "benchmark", f.name], capture_output=True,
+                                timeout=TIMEOUT, env=dict(os.environ, RUST_LOG="info"))
+    except subprocess.TimeoutExpired:
+        return None, None
+    finally:
+        os.unlink(f.name)
+    return result.stdout, result.stderr.decode(errors="replace")
+
+
+def run():
+    output, log = replay(record(2, 0, CAP + GROWER) + record(0, 0, b"clock:1")
+                         + record(1, 1, b"fd:0,body:still running\n") + record(0, 0, b"clock:1"))
+    if output is None:
+        print(f"FAIL: the replay did not end within {TIMEOUT}s")
+        return 1
+    if output != b"grew\nrefused\nstill running\n" or "Process 1 finished (exited)" not in log:
+        print(log)
+        print(f"FAIL: expected one grow to succeed, the next to fail and the guest to echo its "
+              f"line and exit, got {output}")
+        return 1
+    print("PASS: memory.grow failed at the cap and the guest kept running")
+
+    output, log = replay(record(2, 0, CAP + ABORTER) + record(0, 0, b"clock:1"))
+    if output is None or "Process 1 finished (killed by memory limit)" not in log:
+        print(log)
+        print("FAIL: expected the guest that trapped after a refused grow to be killed by the memory limit")
+        return 1
+    print("PASS: a trap after a refused grow is reported as a memory limit kill")
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(run())
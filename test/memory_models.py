@@ -0,0 +1,81 @@
+#!/usr/bin/env python3
+"""
+memory_models.py
+---------------------------------
+• Stands in for `consensus tcp` and sends a batch with the Inits of
+  wasm_programs/test_multi_memory.wat, which defines two memories, and
+  wasm_programs/test_shared_memory.wat, which exports a shared one, then of
+  a guest with a single memory that prints "started".
+• Checks the runtime reports the first two back to consensus as failed
+  Inits with the error the README documents for each, and that only the
+  last one starts.
+Usage (after `cargo build --bin runtime`, from the repository root):
+    python3 test/memory_models.py
+"""
+import socket, subprocess, sys, time
+from support import batch, read_outgoing_records, record
+
+GUEST = b"""(module
+  (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 16) "started\\n")
+  (func (export "_start")
+    (i32.store (i32.const 0) (i32.const 16))
+    (i32.store (i32.const 4) (i32.const 8))
+    (drop (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 8)))))
+"""
+REJECTED = [
+    ("wasm_programs/test_multi_memory.wat", b"multi-memory is not supported"),
+    ("wasm_programs/test_shared_memory.wat", b"threads proposal is not supported"),
+]
+TIMEOUT = 10
+
+
+def run():
+    inits = b""
+    for path, _ in REJECTED:
+        with open(path, "rb") as f:
+            inits += record(2, 0, f.read())
+    server = socket.socket()
+    server.bind(("127.0.0.1", 0))
+    server.listen(1)
+    server.settimeout(TIMEOUT)
+    runtime = subprocess.Popen(["target/debug/runtime", "tcp", f"127.0.0.1:{server.getsockname()[1]}"],
+                               stdout=subprocess.PIPE, stderr=subprocess.DEVNULL)
+    failures = {}
+    try:
+        conn, _ = server.accept()
+        conn.settimeout(TIMEOUT)
+        conn.sendall(batch(1, inits + record(2, 0, GUEST) + record(0, 0, b"clock:1")))
+        number, deadline = 2, time.time() + TIMEOUT
+        while len(failures) < len(REJECTED) and time.time() < deadline:
+            # The runtime only sends its batch before reading the next one
+            conn.sendall(batch(number, record(0, 0, b"clock:1")))
+            number += 1
+            for msg_type, pid, payload in read_outgoing_records(conn):
+                if msg_type == 2:
+                    failures[pid] = payload
+        conn.close()
+        output, _ = runtime.communicate(timeout=TIMEOUT)
+    except (socket.timeout, subprocess.TimeoutExpired, EOFError) as e:
+        print(f"FAIL: the runtime stopped answering ({e})")
+        return 1
+    finally:
+        runtime.kill()
+        server.close()
+
+    for pid, (path, error) in enumerate(REJECTED, start=1):
+        failure = failures.get(pid, b"")
+        if not failure.startswith(b"error:") or error not in failure:
+            print(f"FAIL: expected the Init of {path} to fail with {error}, got {failure}")
+            return 1
+        print(f"PASS: {path} was rejected at Init: {failure.decode(errors='replace')}")
+    if len(REJECTED) + 1 in failures or output != b"started\n":
+        print(f"FAIL: expected only the single-memory guest to start, got {output}")
+        return 1
+    print("PASS: the single-memory guest started")
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(run())
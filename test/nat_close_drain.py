@@ -0,0 +1,128 @@
+#!/usr/bin/env python3
+"""
+nat_close_drain.py
+---------------------------------
+• Runs `consensus tcp` and a runtime, and starts a guest that connects to a
+  server here, sends it 8 MiB, more than the loopback socket buffers hold,
+  and closes the socket at once, so consensus still has bytes to write on
+  close. The server does not read until the guest has closed.
+• Checks `/status` keeps answering while consensus waits for the server to
+  drain, so the close does not block on the NAT table's lock.
+• Checks the server then receives all 8 MiB before the connection ends.
+Usage (after `cargo build`, from the repository root; uses port 9000):
+    python3 test/nat_close_drain.py
+"""
+import glob, os, socket, subprocess, sys, tempfile, time, urllib.request
+
+CHUNK = 1 << 20
+CHUNKS = 8
+GUEST = """(module
+  (import "wasi_snapshot_preview1" "sock_open" (func $sock_open (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "sock_connect" (func $sock_connect (param i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "sock_send" (func $sock_send (param i32 i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "sock_close" (func $sock_close (param i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 17)
+  ;; sockaddr_in for 127.0.0.1:PORT
+  (data (i32.const 32) "\\01\\00PORTBYTES\\7f\\00\\00\\01\\00\\00\\00\\00\\00\\00\\00\\00")
+  (data (i32.const 64) "closed\\0a")
+  (func (export "_start")
+    (local $i i32)
+    (drop (call $sock_open (i32.const 1) (i32.const 1) (i32.const 0) (i32.const 0)))
+    (drop (call $sock_connect (i32.load (i32.const 0)) (i32.const 32) (i32.const 16)))
+    (loop $send
+      ;; the runtime's sock_send takes the data itself, not an iovec array
+      (drop (call $sock_send (i32.load (i32.const 0)) (i32.const 65536) (i32.const CHUNK) (i32.const 0) (i32.const 4)))
+      (local.set $i (i32.add (local.get $i) (i32.const 1)))
+      (br_if $send (i32.lt_u (local.get $i) (i32.const CHUNKS))))
+    (drop (call $sock_close (i32.load (i32.const 0))))
+    (i32.store (i32.const 8) (i32.const 64))
+    (i32.store (i32.const 12) (i32.const 7))
+    (drop (call $fd_write (i32.const 1) (i32.const 8) (i32.const 1) (i32.const 16)))))
+"""
+TIMEOUT = 20
+# Consensus's blocking drain gives up after a second without progress, so
+# the server waits less than that before reading
+STALL = 0.6
+MAX_STATUS_WAIT = 0.3
+
+
+def free_port():
+    with socket.socket() as s:
+        s.bind(("127.0.0.1", 0))
+        return s.getsockname()[1]
+
+
+def status_latency(http_port):
+    start = time.time()
+    with urllib.request.urlopen(f"http://127.0.0.1:{http_port}/status", timeout=TIMEOUT) as response:
+        response.read()
+    return time.time() - start
+
+
+def run():
+    server = socket.socket()
+    server.bind(("127.0.0.1", 0))
+    server.listen(1)
+    server.settimeout(TIMEOUT)
+    port = server.getsockname()[1]
+    http_port = free_port()
+    workdir = tempfile.mkdtemp()
+    path = os.path.join(workdir, "close_drain.wat")
+    with open(path, "w") as f:
+        f.write(GUEST.replace("PORTBYTES", "".join(f"\\{b:02x}" for b in port.to_bytes(2, "big")))
+                .replace("CHUNKS", str(CHUNKS)).replace("CHUNK", str(CHUNK)))
+    before = set(glob.glob("sessions/session-*.bin"))
+
+    consensus = subprocess.Popen(["target/debug/consensus", "tcp"], stdin=subprocess.PIPE,
+                                 stdout=subprocess.DEVNULL, stderr=subprocess.DEVNULL,
+                                 env={**os.environ, "REPLICODE_HTTP_PORT": str(http_port)})
+    time.sleep(0.5)
+    runtime = subprocess.Popen(["target/debug/runtime", "tcp"], stdout=subprocess.PIPE,
+                               stderr=subprocess.DEVNULL)
+    received = 0
+    slowest = 0.0
+    try:
+        time.sleep(0.5)
+        consensus.stdin.write(f"init {path}\n".encode())
+        consensus.stdin.flush()
+        conn, _ = server.accept()
+        if runtime.stdout.readline() != b"closed\n":
+            print("FAIL: the guest did not close its socket")
+            return 1
+        stalled_until = time.time() + STALL
+        while time.time() < stalled_until:
+            slowest = max(slowest, status_latency(http_port))
+        conn.settimeout(TIMEOUT)
+        for chunk in iter(lambda: conn.recv(1 << 16), b""):
+            received += len(chunk)
+        conn.close()
+        consensus.stdin.write(b"exit\n")
+        consensus.stdin.close()
+        runtime.wait(timeout=TIMEOUT)
+        consensus.wait(timeout=TIMEOUT)
+    except (socket.timeout, subprocess.TimeoutExpired) as e:
+        print(f"FAIL: timed out ({e})")
+        return 1
+    finally:
+        runtime.kill()
+        consensus.kill()
+        server.close()
+        os.unlink(path)
+        os.rmdir(workdir)
+        for session in set(glob.glob("sessions/session-*.bin")) - before:
+            os.unlink(session)
+
+    if slowest > MAX_STATUS_WAIT:
+        print(f"FAIL: /status took {slowest:.2f}s while consensus drained the closed connection")
+        return 1
+    print(f"PASS: /status answered within {slowest:.2f}s while the close drained")
+    if received != CHUNK * CHUNKS:
+        print(f"FAIL: the server received {received} of {CHUNK * CHUNKS} bytes")
+        return 1
+    print(f"PASS: the server received all {received} bytes sent before the close")
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(run())
@@ -0,0 +1,117 @@
+#!/usr/bin/env python3
+"""
+nat_pool.py
+---------------------------------
+• Runs `consensus tcp` and a runtime, and starts a guest that three times
+  connects to a local server, sends a line and closes the socket.
+• Without a pool, checks the server sees three connections; with
+  REPLICODE_NAT_POOL_SIZE=1, checks it sees one connection carrying all three
+  lines, because consensus kept it open and reused it for the later connects.
+Usage (after `cargo build`, from the repository root; uses ports 9000 and 8080):
+    python3 test/nat_pool.py
+"""
+import glob, os, socket, subprocess, sys, tempfile, threading, time
+
+GUEST = """(module
+  (import "wasi_snapshot_preview1" "sock_open" (func $sock_open (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "sock_connect" (func $sock_connect (param i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "sock_send" (func $sock_send (param i32 i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "sock_close" (func $sock_close (param i32) (result i32)))
+  (memory (export "memory") 1)
+  ;; sockaddr_in for 127.0.0.1:PORT
+  (data (i32.const 32) "\\02\\00PORTBYTES\\7f\\00\\00\\01\\00\\00\\00\\00\\00\\00\\00\\00")
+  (data (i32.const 64) "request 0\\n")
+  (func (export "_start")
+    (local $i i32) (local $fd i32)
+    (loop $next
+      (drop (call $sock_open (i32.const 1) (i32.const 1) (i32.const 0) (i32.const 0)))
+      (local.set $fd (i32.load (i32.const 0)))
+      (drop (call $sock_connect (local.get $fd) (i32.const 32) (i32.const 16)))
+      (i32.store8 (i32.const 72) (i32.add (i32.const 48) (local.get $i)))
+      ;; the runtime's sock_send takes the data itself, not an iovec array
+      (drop (call $sock_send (local.get $fd) (i32.const 64) (i32.const 10) (i32.const 0) (i32.const 24)))
+      (drop (call $sock_close (local.get $fd)))
+      (local.set $i (i32.add (local.get $i) (i32.const 1)))
+      (br_if $next (i32.lt_u (local.get $i) (i32.const 3))))))
+"""
+TIMEOUT = 10
+
+
+def collect(conn, received):
+    for chunk in iter(lambda: conn.recv(1024), b""):
+        received.append(chunk)
+
+
+def serve(server, connections):
+    """Accepts connections and collects what arrives on each."""
+    while True:
+        try:
+            conn, _ = server.accept()
+        except OSError:
+            return
+        received = []
+        connections.append(received)
+        threading.Thread(target=collect, args=(conn, received), daemon=True).start()
+
+
+def run_once(workdir, pool_size):
+    server = socket.socket()
+    server.bind(("127.0.0.1", 0))
+    server.listen(8)
+    port = server.getsockname()[1]
+    connections = []
+    threading.Thread(target=serve, args=(server, connections), daemon=True).start()
+    guest = os.path.join(workdir, "connect_three_times.wat")
+    port_bytes = "".join(f"\\{b:02x}" for b in port.to_bytes(2, "big"))
+    with open(guest, "w") as f:
+        f.write(GUEST.replace("PORTBYTES", port_bytes))
+
+    env = {"REPLICODE_NAT_POOL_SIZE": str(pool_size)} if pool_size else {}
+    consensus = subprocess.Popen(["target/debug/consensus", "tcp"], stdin=subprocess.PIPE,
+                                 stdout=subprocess.DEVNULL, stderr=subprocess.DEVNULL, env=env)
+    time.sleep(0.5)
+    runtime = subprocess.Popen(["target/debug/runtime", "tcp"], stdout=subprocess.DEVNULL,
+                               stderr=subprocess.DEVNULL)
+    time.sleep(0.5)
+    consensus.stdin.write(f"init {guest}\n".encode())
+    consensus.stdin.flush()
+    time.sleep(2)
+    consensus.stdin.write(b"exit\n")
+    consensus.stdin.close()
+    try:
+        runtime.wait(timeout=TIMEOUT)
+        consensus.wait(timeout=TIMEOUT)
+    except subprocess.TimeoutExpired:
+        runtime.kill()
+        consensus.kill()
+        return None
+    finally:
+        server.close()
+    return [b"".join(received) for received in connections]
+
+
+def run():
+    workdir = tempfile.mkdtemp()
+    before = set(glob.glob("sessions/session-*.bin"))
+    try:
+        unpooled = run_once(workdir, 0)
+        pooled = run_once(workdir, 1)
+    finally:
+        for path in set(glob.glob("sessions/session-*.bin")) - before:
+            os.unlink(path)
+    if unpooled is None or pooled is None:
+        print("FAIL: consensus or the runtime did not exit")
+        return 1
+    lines = b"request 0\nrequest 1\nrequest 2\n"
+    if len(unpooled) != 3 or b"".join(unpooled) != lines:
+        print(f"FAIL: without a pool, expected three connections with one line each, got {unpooled}")
+        return 1
+    if pooled != [lines]:
+        print(f"FAIL: with a pool, expected one connection carrying all three lines, got {pooled}")
+        return 1
+    print("PASS: with a pool, the three connects reused one connection")
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(run())
@@ -0,0 +1,84 @@
+#!/usr/bin/env python3
+"""
+netcat_client.py
+---------------------------------
+• Starts an echo server here that sends back each line it reads and, once
+  the client has closed its sending half, answers "bye" and closes.
+• Runs `consensus netcat-client`, with --host/--port and with the
+  positional form, and types two lines into it, then ends its stdin.
+• Checks the client printed both echoes, then "bye", which the server only
+  sends after seeing the client's half-close, and exited with status 0 when
+  the server closed.
+• Checks the first echo arrives while the client's stdin is still open, so
+  the socket is read while stdin is still being forwarded.
+Usage (after `cargo build --bin consensus`, from the repository root):
+    python3 test/netcat_client.py
+"""
+import socket, subprocess, sys, threading
+from support import wait_for, watch
+
+TIMEOUT = 10
+
+
+def echo_server(server):
+    """Echoes lines to each connection until it half-closes, then says bye."""
+    while True:
+        try:
+            conn, _ = server.accept()
+        except OSError:
+            return
+        with conn:
+            for line in conn.makefile("rb"):
+                conn.sendall(line)
+            conn.sendall(b"bye\n")
+
+
+def run_client(args):
+    client = subprocess.Popen(["target/debug/consensus", "netcat-client"] + args, stdin=subprocess.PIPE,
+                              stdout=subprocess.PIPE, stderr=subprocess.DEVNULL)
+    output = watch(client.stdout)
+    try:
+        client.stdin.write(b"hello\n")
+        client.stdin.flush()
+        # Still connected and typing: the echo must come back before stdin ends
+        interactive = wait_for(lambda: output == b"hello\n", TIMEOUT)
+        client.stdin.write(b"again\n")
+        client.stdin.close()
+        status = client.wait(timeout=TIMEOUT)
+    except subprocess.TimeoutExpired:
+        client.kill()
+        client.wait()
+        status = None
+    wait_for(lambda: output.endswith(b"bye\n"), 1)  # the reader thread may lag the exit
+    return status, interactive, bytes(output)
+
+
+def run():
+    server = socket.socket()
+    server.bind(("127.0.0.1", 0))
+    server.listen(4)
+    port = str(server.getsockname()[1])
+    threading.Thread(target=echo_server, args=(server,), daemon=True).start()
+    try:
+        runs = {
+            "--host/--port": run_client(["--host", "127.0.0.1", "--port", port]),
+            "positional": run_client(["127.0.0.1", port]),
+        }
+    finally:
+        server.close()
+    for form, (status, interactive, output) in runs.items():
+        if status is None:
+            print(f"FAIL: netcat-client ({form}) was still running after the server closed")
+            return 1
+        if not interactive:
+            print(f"FAIL: netcat-client ({form}) did not print the echo while stdin was open, got {output}")
+            return 1
+        if status != 0 or output != b"hello\nagain\nbye\n":
+            print(f"FAIL: netcat-client ({form}) exited with {status} after printing {output}")
+            return 1
+    print("PASS: netcat-client forwarded both ways, half-closed on stdin EOF and exited when the server closed")
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(run())
@@ -0,0 +1,98 @@
+#!/usr/bin/env python3
+"""
+node_socket_limit.py
+---------------------------------
+• Runs `consensus tcp` with REPLICODE_MAX_SOCKETS_TOTAL=3 and a runtime, and
+  starts three guests that each connect twice to a local listener, then stay
+  alive holding their connections.
+• Checks through /status that the NAT gave the first guest both connections,
+  the second only the last free one and the third none, although none of them
+  is near its per-process limit, and that the node is at 3 of 3 sockets.
+Usage (after `cargo build`, from the repository root; uses ports 9000 and 8080):
+    python3 test/node_socket_limit.py
+"""
+import glob, json, os, socket, subprocess, sys, tempfile, time, urllib.request
+
+GUEST = """(module
+  (import "wasi_snapshot_preview1" "sock_open" (func $sock_open (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "sock_connect" (func $sock_connect (param i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_read" (func $fd_read (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  ;; sockaddr_in for 127.0.0.1:PORT
+  (data (i32.const 32) "\\02\\00PORTBYTES\\7f\\00\\00\\01\\00\\00\\00\\00\\00\\00\\00\\00")
+  (func $connect
+    (drop (call $sock_open (i32.const 1) (i32.const 1) (i32.const 0) (i32.const 0)))
+    (drop (call $sock_connect (i32.load (i32.const 0)) (i32.const 32) (i32.const 16))))
+  (func (export "_start")
+    (call $connect)
+    (call $connect)
+    ;; keep the connections open until stdin closes
+    (i32.store (i32.const 16) (i32.const 128))
+    (i32.store (i32.const 20) (i32.const 64))
+    (drop (call $fd_read (i32.const 0) (i32.const 16) (i32.const 1) (i32.const 24)))))
+"""
+TIMEOUT = 10
+
+
+def guest(workdir, port):
+    path = os.path.join(workdir, "connect_twice.wat")
+    port_bytes = "".join(f"\\{b:02x}" for b in port.to_bytes(2, "big"))
+    with open(path, "w") as f:
+        f.write(GUEST.replace("PORTBYTES", port_bytes))
+    return path
+
+
+def run():
+    server = socket.socket()
+    server.bind(("127.0.0.1", 0))
+    server.listen(8)
+    port = server.getsockname()[1]
+    workdir = tempfile.mkdtemp()
+    before = set(glob.glob("sessions/session-*.bin"))
+
+    consensus = subprocess.Popen(["target/debug/consensus", "tcp"], stdin=subprocess.PIPE,
+                                 stdout=subprocess.DEVNULL, stderr=subprocess.DEVNULL,
+                                 env={"REPLICODE_MAX_SOCKETS_TOTAL": "3"})
+    time.sleep(0.5)
+    runtime = subprocess.Popen(["target/debug/runtime", "tcp"], stdout=subprocess.DEVNULL,
+                               stderr=subprocess.DEVNULL)
+    time.sleep(0.5)
+    path = guest(workdir, port)
+    for _ in range(3):
+        consensus.stdin.write(f"init {path}\n".encode())
+        consensus.stdin.flush()
+        time.sleep(1)
+    try:
+        with urllib.request.urlopen("http://127.0.0.1:8080/status", timeout=TIMEOUT) as response:
+            status = json.load(response)
+    except OSError as e:
+        status = {"error": str(e)}
+    consensus.stdin.write(b"exit\n")
+    consensus.stdin.close()
+    try:
+        runtime.wait(timeout=TIMEOUT)
+        consensus.wait(timeout=TIMEOUT)
+    except subprocess.TimeoutExpired:
+        runtime.kill()
+        consensus.kill()
+        print("FAIL: consensus or the runtime did not exit")
+        return 1
+    finally:
+        server.close()
+    for path in set(glob.glob("sessions/session-*.bin")) - before:
+        os.unlink(path)
+
+    held = {pid: info["open_sockets"] for pid, info in status.get("processes", {}).items()}
+    if held != {"1": 2, "2": 1}:
+        print(f"FAIL: expected process 1 to hold 2 connections and process 2 one, got {held}")
+        return 1
+    sockets = status.get("sockets", {})
+    if (sockets.get("open"), sockets.get("limit")) != (3, 3):
+        print(f"FAIL: expected /status to report 3 of 3 sockets, got {sockets}")
+        return 1
+    print("PASS: connects beyond the node-wide limit were refused for processes 2 and 3")
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(run())
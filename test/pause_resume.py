@@ -0,0 +1,74 @@
+#!/usr/bin/env python3
+"""
+pause_resume.py
+---------------------------------
+• Replays a consensus file with `runtime benchmark` that starts a guest
+  which counts from 1 to 9, sleeping 1000 ns in poll_oneoff after each
+  number, while each batch advances the logical clock by 1000 ns.
+• Pauses it with a Pause (7) record after it has printed a few numbers,
+  lets five more batches go by, and resumes it with a Resume (8) record.
+• Checks the guest printed nothing between the pause and the resume,
+  although the clock kept advancing, and that it then went on from where it
+  stopped, without skipping or repeating a number.
+Usage (after `cargo build --bin runtime`, from the repository root):
+    python3 test/pause_resume.py
+"""
+import os, re, subprocess, sys, tempfile
+from support import record
+
+GUEST = b"""(module
+  (import "wasi_snapshot_preview1" "poll_oneoff" (func $poll_oneoff (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 64) "count 0\\n")
+  (func (export "_start")
+    (local $count i32)
+    (loop $counting
+      (local.set $count (i32.add (local.get $count) (i32.const 1)))
+      (i32.store8 (i32.const 70) (i32.add (i32.const 48) (local.get $count)))
+      (i32.store (i32.const 0) (i32.const 64))
+      (i32.store (i32.const 4) (i32.const 8))
+      (drop (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 8)))
+      ;; one relative clock subscription of 1000 ns
+      (i64.store (i32.const 152) (i64.const 1000))
+      (drop (call $poll_oneoff (i32.const 128) (i32.const 192) (i32.const 1) (i32.const 24)))
+      (br_if $counting (i32.lt_u (local.get $count) (i32.const 9))))))
+"""
+TIMEOUT = 30
+
+
+def run():
+    tick = record(0, 0, b"clock:1000")
+    records = (record(2, 0, GUEST) + tick * 3 + record(7, 1, b"") + tick * 5
+               + record(8, 1, b"") + tick * 12)
+    with tempfile.NamedTemporaryFile(suffix=".bin", delete=False) as f:
+        f.write(records)
+    try:
+        # One stream, so the guest's lines fall between the log lines of the records
+        result = subprocess.run(["target/debug/runtime", "benchmark", f.name], env={"RUST_LOG": "info"},
+                                stdout=subprocess.PIPE, stderr=subprocess.STDOUT, timeout=TIMEOUT)
+    except subprocess.TimeoutExpired:
+        print("FAIL: the runtime did not finish the file")
+        return 1
+    finally:
+        os.unlink(f.name)
+    output = result.stdout.decode(errors="replace")
+    paused, resumed = output.find("Paused process 1"), output.find("Resumed process 1")
+    if paused < 0 or resumed < paused:
+        print("FAIL: the runtime never logged pausing and then resuming process 1")
+        return 1
+    before = re.findall(r"count (\d)", output[:paused])
+    during = re.findall(r"count (\d)", output[paused:resumed])
+    after = re.findall(r"count (\d)", output[resumed:])
+    if not before or during:
+        print(f"FAIL: expected counting before the pause and none while paused, got {before} then {during}")
+        return 1
+    if before + after != [str(n) for n in range(1, 10)]:
+        print(f"FAIL: expected the count to go on from where it stopped, got {before} then {after}")
+        return 1
+    print(f"PASS: the guest stopped at count {before[-1]} while paused and counted on to 9 once resumed")
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(run())
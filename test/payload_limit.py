@@ -0,0 +1,145 @@
+#!/usr/bin/env python3
+"""
+payload_limit.py
+---------------------------------
+• Replays a consensus file with `runtime benchmark` that starts a guest,
+  then holds a record claiming a 4 GB payload (a sparse file, so the
+  bytes are there without taking up disk), then starts a second guest.
+• Checks the runtime drops the oversized record without allocating it,
+  and reads on to start the second guest.
+• Stands in for `consensus tcp` with REPLICODE_MAX_PAYLOAD_BYTES=1024, and
+  sends a runtime an Init batch, a 2000-byte batch, and a clock batch.
+• Checks the runtime rejects the 2000-byte batch (type 12) for being over
+  the limit, and still reads and acknowledges the batch after it.
+• Sends a runtime a 32 KB zstd-compressed batch that expands to 1 GiB, and
+  checks it is rejected for decompressing past the default limit.
+Usage (after `cargo build`, from the repository root; uses port 9000):
+    python3 test/payload_limit.py
+"""
+import os, re, socket, struct, subprocess, sys, tempfile, zlib
+from support import batch, read_outgoing_records, record
+
+GUEST = """(module
+  (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 64) "NAME")
+  (func (export "_start")
+    (i32.store (i32.const 0) (i32.const 64))
+    (i32.store (i32.const 4) (i32.const 7))
+    (drop (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 8)))))
+"""
+HUGE = 0xFFFFFFFF
+WIRE_ZSTD = 0x08
+TIMEOUT = 30
+
+
+def guest(name):
+    return GUEST.replace("NAME", name).encode()
+
+
+def from_file():
+    """Returns an error message, or None if the oversized record was dropped."""
+    with tempfile.NamedTemporaryFile(suffix=".bin", delete=False) as f:
+        f.write(record(2, 0, guest("<first>")) + record(0, 0, b"clock:1"))
+        f.write(struct.pack("<BQI", 2, 0, HUGE))
+        f.seek(HUGE, os.SEEK_CUR)
+        f.write(record(2, 0, guest("<after>")) + record(0, 0, b"clock:1"))
+    try:
+        result = subprocess.run(["target/debug/runtime", "benchmark", f.name], env={"RUST_LOG": "info"},
+                                capture_output=True, timeout=TIMEOUT)
+    except subprocess.TimeoutExpired:
+        return "the runtime did not finish the file"
+    finally:
+        os.unlink(f.name)
+    output, log = result.stdout.decode(errors="replace"), result.stderr.decode(errors="replace")
+    if not re.search(rf"Dropped a record of {HUGE} bytes for process 0: over the \d+-byte limit", log):
+        return "the runtime never reported dropping the 4 GB record"
+    if "<first>" not in output or "<after>" not in output:
+        return f"expected both guests to run around the dropped record, got {output!r}"
+    return None
+
+
+def stand_in(batches, last, env):
+    """Sends a runtime `batches` as consensus would, and returns the records it
+    sends back until it acknowledges batch `last`, or an error message."""
+    server = socket.socket()
+    server.setsockopt(socket.SOL_SOCKET, socket.SO_REUSEADDR, 1)
+    server.bind(("127.0.0.1", 9000))
+    server.listen(1)
+    runtime = subprocess.Popen(["target/debug/runtime", "tcp"], stdout=subprocess.DEVNULL, stderr=subprocess.DEVNULL,
+                               env=env)
+    records = []
+    try:
+        conn, _ = server.accept()
+        conn.settimeout(TIMEOUT)
+        for data in batches:
+            conn.sendall(data)
+        while not any(msg_type == 15 and pid == last for msg_type, pid, _ in records):
+            records += read_outgoing_records(conn)
+        conn.close()
+        runtime.wait(timeout=TIMEOUT)
+    except (socket.timeout, EOFError, subprocess.TimeoutExpired) as e:
+        return f"the runtime stopped answering ({e})"
+    finally:
+        runtime.kill()
+        server.close()
+    return records
+
+
+def over_the_wire():
+    """Returns an error message, or None if the oversized batch was rejected and reading went on."""
+    records = stand_in([batch(1, record(2, 2**64 - 1, guest("<wired>")) + record(0, 0, b"clock:1")),
+                        batch(2, record(1, 1, b"x" * 1987)),
+                        batch(3, record(0, 0, b"clock:1"))], 3, {"REPLICODE_MAX_PAYLOAD_BYTES": "1024"})
+    if isinstance(records, str):
+        return records
+    rejected = [(pid, payload) for msg_type, pid, payload in records if msg_type == 12]
+    if len(rejected) != 1 or rejected[0][0] != 2 or b"over the 1024-byte limit" not in rejected[0][1]:
+        return f"expected batch 2 to be rejected for its size, got {rejected}"
+    acks = [pid for msg_type, pid, _ in records if msg_type == 15]
+    if acks != [1, 3]:
+        return f"expected acks for batches 1 and 3 around the rejected one, got {acks}"
+    return None
+
+
+def zstd_bomb(size):
+    """A zstd frame of `size` zero bytes in 128 KiB RLE blocks, 4 bytes each."""
+    frame = struct.pack("<IBB", 0xFD2FB528, 0x00, 0x38)  # magic, no content size, 128 KiB window
+    block = 128 << 10
+    count = size // block
+    for i in range(count):
+        header = (i == count - 1) | 1 << 1 | block << 3  # last block flag, RLE type, repeat count
+        frame += header.to_bytes(3, "little") + b"\0"
+    return frame
+
+
+def compressed_batch(number, data):
+    """A batch in wire format 3 whose data is zstd-compressed."""
+    return (struct.pack("<QBQQ", number, 0x30 | WIRE_ZSTD, 2**64 - 1, len(data)) + data
+            + struct.pack("<I", zlib.crc32(data)))
+
+
+def decompression_bomb():
+    """Returns an error message, or None if a batch expanding to 1 GiB was rejected."""
+    bomb = zstd_bomb(1 << 30)
+    records = stand_in([compressed_batch(1, bomb), batch(2, record(0, 0, b"clock:1"))], 2, {})
+    if isinstance(records, str):
+        return records
+    rejected = [(pid, payload) for msg_type, pid, payload in records if msg_type == 12]
+    if len(rejected) != 1 or rejected[0][0] != 1 or b"byte limit" not in rejected[0][1]:
+        return f"expected the {len(bomb)}-byte batch that expands to 1 GiB to be rejected, got {rejected}"
+    return None
+
+
+def run():
+    for check in (from_file, over_the_wire, decompression_bomb):
+        error = check()
+        if error:
+            print(f"FAIL: {error}")
+            return 1
+    print("PASS: the 4 GB record, the batch over the limit and the zstd bomb were dropped, and reading went on after them")
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(run())
@@ -0,0 +1,114 @@
+#!/usr/bin/env python3
+"""
+port_assignment.py
+---------------------------------
+• Replays the same consensus file twice with `runtime benchmark`, as two
+  replicas fed the same batches. It starts a guest with the Init option
+  `ports:20000` that opens eight sockets a round for four rounds, closing
+  every other one after each round so later rounds reuse some FDs, and
+  prints each socket's FD and its guest-visible port from sock_getsockname
+  (the steps of wasm_programs/test_port_assignment.c).
+• Checks both replays printed the same FDs and ports, that the ports run
+  from 20000 up without gaps, and that each FD is the lowest free one.
+Usage (after `cargo build --bin runtime`, from the repository root):
+    python3 test/port_assignment.py
+"""
+import os, re, subprocess, sys, tempfile
+from support import record
+
+GUEST = b"""(module
+  (import "wasi_snapshot_preview1" "sock_open" (func $sock_open (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "sock_getsockname" (func $sock_getsockname (param i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "sock_close" (func $sock_close (param i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  ;; the line printed for each socket: <FD:PORT>
+  (data (i32.const 64) "<00:00000>")
+  ;; writes $value as $width decimal digits ending just before $end
+  (func $digits (param $value i32) (param $end i32) (param $width i32)
+    (loop $digit
+      (local.set $end (i32.sub (local.get $end) (i32.const 1)))
+      (i32.store8 (local.get $end) (i32.add (i32.const 48) (i32.rem_u (local.get $value) (i32.const 10))))
+      (local.set $value (i32.div_u (local.get $value) (i32.const 10)))
+      (local.set $width (i32.sub (local.get $width) (i32.const 1)))
+      (br_if $digit (local.get $width))))
+  (func $open (param $slot i32)
+    (local $fd i32)
+    (if (call $sock_open (i32.const 1) (i32.const 1) (i32.const 0) (local.get $slot)) (then (unreachable)))
+    (local.set $fd (i32.load (local.get $slot)))
+    (i32.store (i32.const 8) (i32.const 16))
+    (if (call $sock_getsockname (local.get $fd) (i32.const 32) (i32.const 8)) (then (unreachable)))
+    (call $digits (local.get $fd) (i32.const 67) (i32.const 2))
+    ;; the port is big-endian at offset 2 of the sockaddr
+    (call $digits (i32.or (i32.shl (i32.load8_u (i32.const 34)) (i32.const 8)) (i32.load8_u (i32.const 35)))
+      (i32.const 73) (i32.const 5))
+    (i32.store (i32.const 16) (i32.const 64))
+    (i32.store (i32.const 20) (i32.const 10))
+    (drop (call $fd_write (i32.const 1) (i32.const 16) (i32.const 1) (i32.const 24))))
+  (func (export "_start")
+    (local $round i32)
+    (local $i i32)
+    (loop $rounds
+      ;; FDs of this round's sockets go in 128..160
+      (local.set $i (i32.const 0))
+      (loop $sockets
+        (call $open (i32.add (i32.const 128) (i32.shl (local.get $i) (i32.const 2))))
+        (local.set $i (i32.add (local.get $i) (i32.const 1)))
+        (br_if $sockets (i32.lt_u (local.get $i) (i32.const 8))))
+      ;; close every other socket so the next round reuses some FDs
+      (local.set $i (i32.const 0))
+      (loop $close
+        (drop (call $sock_close (i32.load (i32.add (i32.const 128) (i32.shl (local.get $i) (i32.const 2))))))
+        (local.set $i (i32.add (local.get $i) (i32.const 2)))
+        (br_if $close (i32.lt_u (local.get $i) (i32.const 8))))
+      (local.set $round (i32.add (local.get $round) (i32.const 1)))
+      (br_if $rounds (i32.lt_u (local.get $round) (i32.const 4))))))
+"""
+FIRST_PORT = 20000
+TIMEOUT = 30
+
+
+def expected():
+    """The FD and port of each socket, if FDs are the lowest free ones and
+    ports count up from FIRST_PORT."""
+    open_fds, sockets = {0, 1, 2, 3}, []
+    for _ in range(4):
+        fds = []
+        for _ in range(8):
+            fd = min(set(range(len(open_fds) + 1)) - open_fds)
+            open_fds.add(fd)
+            fds.append(fd)
+            sockets.append((fd, FIRST_PORT + len(sockets)))
+        open_fds -= set(fds[::2])
+    return sockets
+
+
+def replay(path):
+    result = subprocess.run(["target/debug/runtime", "benchmark", path], capture_output=True, timeout=TIMEOUT)
+    return [(int(fd), int(port))
+            for fd, port in re.findall(r"<(\d\d):(\d{5})>", result.stdout.decode(errors="replace"))]
+
+
+def run():
+    with tempfile.NamedTemporaryFile(suffix=".bin", delete=False) as f:
+        f.write(record(2, 0, f"ports:{FIRST_PORT}\0".encode() + GUEST) + record(0, 0, b"clock:1000"))
+    try:
+        first, second = replay(f.name), replay(f.name)
+    except subprocess.TimeoutExpired:
+        print("FAIL: the runtime did not finish the guest")
+        return 1
+    finally:
+        os.unlink(f.name)
+    if first != second:
+        print(f"FAIL: the two replays assigned different FDs or ports:\n  {first}\n  {second}")
+        return 1
+    if first != expected():
+        print(f"FAIL: expected the sockets {expected()}, got {first}")
+        return 1
+    print(f"PASS: both replays gave {len(first)} sockets the same FDs and ports {FIRST_PORT} "
+          f"to {first[-1][1]}")
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(run())
@@ -0,0 +1,52 @@
+#!/usr/bin/env python3
+"""
+port_exhaustion.py
+---------------------------------
+• Replays a consensus file with `runtime benchmark` that starts a guest
+  with the Init option `ports:65535`, so its first socket gets the last
+  guest-visible port, and has it open two sockets.
+• Checks the first sock_open succeeds and the second fails with
+  EADDRNOTAVAIL, instead of the port counter wrapping around to 0 or
+  panicking in a debug build.
+Usage (after `cargo build --bin runtime`, from the repository root):
+    python3 test/port_exhaustion.py
+"""
+import os, subprocess, sys, tempfile
+from support import record
+
+GUEST = b"""(module
+  (import "wasi_snapshot_preview1" "sock_open" (func $sock_open (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 32) "ok\\n")
+  (func (export "_start")
+    (if (call $sock_open (i32.const 1) (i32.const 1) (i32.const 0) (i32.const 0)) (then (unreachable)))
+    ;; EADDRNOTAVAIL
+    (if (i32.ne (call $sock_open (i32.const 1) (i32.const 1) (i32.const 0) (i32.const 4)) (i32.const 4))
+      (then (unreachable)))
+    (i32.store (i32.const 16) (i32.const 32))
+    (i32.store (i32.const 20) (i32.const 3))
+    (drop (call $fd_write (i32.const 1) (i32.const 16) (i32.const 1) (i32.const 24)))))
+"""
+TIMEOUT = 30
+
+
+def run():
+    with tempfile.NamedTemporaryFile(suffix=".bin", delete=False) as f:
+        f.write(record(2, 0, b"ports:65535\0" + GUEST) + record(0, 0, b"clock:1"))
+    try:
+        result = subprocess.run(["target/debug/runtime", "benchmark", f.name], capture_output=True, timeout=TIMEOUT)
+    except subprocess.TimeoutExpired:
+        print("FAIL: the runtime did not finish the guest")
+        return 1
+    finally:
+        os.unlink(f.name)
+    if result.stdout != b"ok\n":
+        print(f"FAIL: expected the second sock_open to fail with EADDRNOTAVAIL, got {result.stdout!r}")
+        return 1
+    print("PASS: a process out of guest-visible ports got EADDRNOTAVAIL from sock_open")
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(run())
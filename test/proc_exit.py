@@ -0,0 +1,67 @@
+#!/usr/bin/env python3
+"""
+proc_exit.py
+---------------------------------
+• Replays a consensus file with `runtime benchmark` that starts a guest that
+  calls proc_exit(3) at once, and a second guest that prints <B1>, reads its
+  stdin and prints <B2> once consensus has sent it input.
+• Checks the first guest ends cleanly (joined as exited, no panic anywhere
+  in the log) and the second keeps running and prints both lines.
+Usage (after `cargo build --bin runtime`, from the repository root):
+    python3 test/proc_exit.py
+"""
+import os, re, subprocess, sys, tempfile
+from support import record
+
+EXITS = b"""(module
+  (import "wasi_snapshot_preview1" "proc_exit" (func $proc_exit (param i32)))
+  (memory (export "memory") 1)
+  (func (export "_start")
+    (call $proc_exit (i32.const 3))
+    (unreachable)))
+"""
+KEEPS_RUNNING = b"""(module
+  (import "wasi_snapshot_preview1" "fd_read" (func $fd_read (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 64) "<B1><B2>")
+  (func $print (param $at i32)
+    (i32.store (i32.const 0) (local.get $at))
+    (i32.store (i32.const 4) (i32.const 4))
+    (drop (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 8))))
+  (func (export "_start")
+    (call $print (i32.const 64))
+    (i32.store (i32.const 16) (i32.const 128))
+    (i32.store (i32.const 20) (i32.const 16))
+    (drop (call $fd_read (i32.const 0) (i32.const 16) (i32.const 1) (i32.const 24)))
+    (call $print (i32.const 68))))
+"""
+TIMEOUT = 30
+
+
+def run():
+    records = (record(2, 0, EXITS) + record(2, 0, KEEPS_RUNNING) + record(0, 0, b"clock:1") * 2
+               + record(1, 2, b"fd:0,body:go") + record(0, 0, b"clock:1"))
+    with tempfile.NamedTemporaryFile(suffix=".bin", delete=False) as f:
+        f.write(records)
+    try:
+        result = subprocess.run(["target/debug/runtime", "benchmark", f.name], env={"RUST_LOG": "info"},
+                                capture_output=True, timeout=TIMEOUT)
+    finally:
+        os.unlink(f.name)
+    output, log = result.stdout.decode(errors="replace"), result.stderr.decode(errors="replace")
+    if "panicked" in log:
+        print("FAIL: proc_exit panicked the guest thread")
+        return 1
+    if "Process 1 finished (exited) and joined." not in log:
+        print("FAIL: the process that called proc_exit was not joined as exited")
+        return 1
+    if re.findall(r"<B\d>", output) != ["<B1>", "<B2>"]:
+        print(f"FAIL: expected the other process to print <B1> and <B2>, got {output!r}")
+        return 1
+    print("PASS: proc_exit ended its process cleanly and the other one kept running")
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(run())
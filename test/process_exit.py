@@ -0,0 +1,86 @@
+#!/usr/bin/env python3
+"""
+process_exit.py
+---------------------------------
+• Stands in for `consensus tcp` and sends an Init batch that starts three
+  guests: one calls proc_exit(42), one returns from _start, and one traps
+  in a function named $boom. Then it sends clock batches until the runtime
+  reports how all three ended.
+• Checks the runtime sends a ProcessExit (type 14) record for each process
+  once it has joined it: "code:42" for the first, "code:0" for the second,
+  and an error for the third, followed by a backtrace that names $boom.
+Usage (after `cargo build --bin runtime`, from the repository root; uses port 9000):
+    python3 test/process_exit.py
+"""
+import socket, struct, subprocess, sys, time, zlib
+from support import read_outgoing_records, record
+
+EXIT_42 = b"""(module
+  (import "wasi_snapshot_preview1" "proc_exit" (func $proc_exit (param i32)))
+  (memory (export "memory") 1)
+  (func (export "_start")
+    (call $proc_exit (i32.const 42))))
+"""
+RETURN = b"""(module
+  (memory (export "memory") 1)
+  (func (export "_start")))
+"""
+TRAP = b"""(module
+  (memory (export "memory") 1)
+  (func $boom unreachable)
+  (func (export "_start")
+    (call $boom)))
+"""
+TIMEOUT = 10
+
+
+def batch(number, records):
+    return struct.pack("<QBQQ", number, 0x20, 0, len(records)) + records + struct.pack("<I", zlib.crc32(records))
+
+
+def run():
+    server = socket.socket()
+    server.setsockopt(socket.SOL_SOCKET, socket.SO_REUSEADDR, 1)
+    server.bind(("127.0.0.1", 9000))
+    server.listen(1)
+    runtime = subprocess.Popen(["target/debug/runtime", "tcp"], stdout=subprocess.DEVNULL,
+                               stderr=subprocess.DEVNULL)
+    exits = {}
+    try:
+        conn, _ = server.accept()
+        conn.settimeout(0.2)
+        conn.sendall(batch(1, record(2, 0, EXIT_42) + record(2, 0, RETURN) + record(2, 0, TRAP)
+                                + record(0, 0, b"clock:1")))
+        number, deadline = 2, time.time() + TIMEOUT
+        while len(exits) < 3 and time.time() < deadline:
+            # The runtime only sends its batch before reading the next one
+            conn.sendall(batch(number, record(0, 0, b"clock:1")))
+            number += 1
+            try:
+                records = read_outgoing_records(conn)
+            except socket.timeout:
+                continue
+            exits.update((pid, payload) for msg_type, pid, payload in records if msg_type == 14)
+        conn.close()
+        runtime.wait(timeout=TIMEOUT)
+    except subprocess.TimeoutExpired:
+        runtime.kill()
+        print("FAIL: the runtime did not exit")
+        return 1
+    finally:
+        server.close()
+    trap = exits.pop(3, b"")
+    if exits != {1: b"code:42", 2: b"code:0"}:
+        print(f"FAIL: expected ProcessExit records with code 42 for process 1 and 0 for process 2, got {exits}")
+        return 1
+    print("PASS: consensus was sent the exit code of each process")
+    reason, _, backtrace = trap.partition(b"\n")
+    if not reason.startswith(b"error:") or b"boom" not in backtrace:
+        print(f"FAIL: expected an error and a backtrace naming $boom for process 3, got {trap!r}")
+        return 1
+    print(f"PASS: consensus was sent the backtrace of the process that trapped ({reason.decode()})")
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(run())
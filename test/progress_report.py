@@ -0,0 +1,85 @@
+#!/usr/bin/env python3
+"""
+progress_report.py
+---------------------------------
+• Runs `consensus tcp` and a runtime, and starts a guest that reports its
+  progress with env.__builtin_rt_report in 10% steps, as "processed 000%"
+  to "processed 100%", yielding after each report as
+  wasm_programs/test_progress_report.c does. Then it stays alive waiting on
+  stdin.
+• Checks /status lists only the guest's last report under "progress" for
+  its pid.
+Usage (after `cargo build`, from the repository root; uses ports 9000 and 8080):
+    python3 test/progress_report.py
+"""
+import glob, json, os, subprocess, sys, tempfile, time, urllib.request
+
+GUEST = """(module
+  (import "env" "__builtin_rt_report" (func $report (param i32 i32) (result i32)))
+  (import "env" "__builtin_rt_yield" (func $yield))
+  (import "wasi_snapshot_preview1" "fd_read" (func $fd_read (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 64) "processed 000%")
+  (func (export "_start")
+    (local $pct i32)
+    (loop $step
+      (i32.store8 (i32.const 74) (i32.add (i32.const 48) (i32.div_u (local.get $pct) (i32.const 100))))
+      (i32.store8 (i32.const 75) (i32.add (i32.const 48) (i32.rem_u (i32.div_u (local.get $pct) (i32.const 10)) (i32.const 10))))
+      (if (call $report (i32.const 64) (i32.const 14)) (then (unreachable)))
+      (call $yield)
+      (local.set $pct (i32.add (local.get $pct) (i32.const 10)))
+      (br_if $step (i32.le_u (local.get $pct) (i32.const 100))))
+    ;; stay alive until stdin closes
+    (i32.store (i32.const 0) (i32.const 128))
+    (i32.store (i32.const 4) (i32.const 64))
+    (drop (call $fd_read (i32.const 0) (i32.const 0) (i32.const 1) (i32.const 8)))))
+"""
+TIMEOUT = 10
+
+
+def run():
+    workdir = tempfile.mkdtemp()
+    path = os.path.join(workdir, "progress.wat")
+    with open(path, "w") as f:
+        f.write(GUEST)
+    before = set(glob.glob("sessions/session-*.bin"))
+    consensus = subprocess.Popen(["target/debug/consensus", "tcp"], stdin=subprocess.PIPE,
+                                 stdout=subprocess.DEVNULL, stderr=subprocess.DEVNULL)
+    time.sleep(0.5)
+    runtime = subprocess.Popen(["target/debug/runtime", "tcp"], stdout=subprocess.DEVNULL,
+                               stderr=subprocess.DEVNULL)
+    time.sleep(0.5)
+    consensus.stdin.write(f"init {path}\n".encode())
+    consensus.stdin.flush()
+    time.sleep(2)
+    try:
+        with urllib.request.urlopen("http://127.0.0.1:8080/status", timeout=TIMEOUT) as response:
+            status = json.load(response)
+    except OSError as e:
+        status = {"error": str(e)}
+    consensus.stdin.write(b"exit\n")
+    consensus.stdin.close()
+    try:
+        runtime.wait(timeout=TIMEOUT)
+        consensus.wait(timeout=TIMEOUT)
+    except subprocess.TimeoutExpired:
+        runtime.kill()
+        consensus.kill()
+        print("FAIL: consensus or the runtime did not exit")
+        return 1
+    finally:
+        os.unlink(path)
+        os.rmdir(workdir)
+        for session in set(glob.glob("sessions/session-*.bin")) - before:
+            os.unlink(session)
+
+    progress = status.get("progress")
+    if progress != {"1": "processed 100%"}:
+        print(f"FAIL: expected /status to show process 1's last report, processed 100%, got {progress}")
+        return 1
+    print("PASS: /status showed the guest's latest progress report")
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(run())
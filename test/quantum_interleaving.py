@@ -0,0 +1,69 @@
+#!/usr/bin/env python3
+"""
+quantum_interleaving.py
+---------------------------------
+• Writes a consensus file that starts two processes. Each one prints its
+  letter ten times, computing for a while before each line and never
+  yielding on its own.
+• Replays it three times with `runtime benchmark --quantum=20000` and checks
+  the two processes are interleaved, and interleaved the same way every run.
+Usage (after `cargo build --bin runtime`, from the repository root):
+    python3 test/quantum_interleaving.py
+"""
+import os, subprocess, sys, tempfile
+from support import record
+
+GUEST = """(module
+  (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 0) "\\10\\00\\00\\00\\02\\00\\00\\00")
+  (data (i32.const 16) "LETTER\\n")
+  (func (export "_start")
+    (local $line i32) (local $i i32)
+    (loop $lines
+      (local.set $i (i32.const 0))
+      (loop $busy
+        (local.set $i (i32.add (local.get $i) (i32.const 1)))
+        (br_if $busy (i32.lt_u (local.get $i) (i32.const STEPS))))
+      (drop (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 32)))
+      (local.set $line (i32.add (local.get $line) (i32.const 1)))
+      (br_if $lines (i32.lt_u (local.get $line) (i32.const 10))))))
+"""
+RUNS = 3
+TIMEOUT = 30
+
+
+def guest(letter, steps):
+    return GUEST.replace("LETTER", letter).replace("STEPS", str(steps)).encode()
+
+
+def run():
+    # Different amounts of work per line, so the processes drift apart
+    records = (record(2, 0, guest("A", 1000)) + record(2, 0, guest("B", 1700))
+               + record(0, 0, b"clock:1"))
+    with tempfile.NamedTemporaryFile(suffix=".bin", delete=False) as f:
+        f.write(records)
+    outputs = []
+    try:
+        for _ in range(RUNS):
+            result = subprocess.run(["target/debug/runtime", "benchmark", f.name, "--quantum=20000"],
+                                    capture_output=True, timeout=TIMEOUT)
+            outputs.append(result.stdout.decode().replace("\n", ""))
+    finally:
+        os.unlink(f.name)
+    order = outputs[0]
+    if sorted(order) != sorted("A" * 10 + "B" * 10):
+        print(f"FAIL: expected ten lines from each process, got {order!r}")
+        return 1
+    if "AB" not in order or "BA" not in order[order.index("AB"):]:
+        print(f"FAIL: the processes were not interleaved: {order}")
+        return 1
+    if any(output != order for output in outputs):
+        print(f"FAIL: the interleaving changed between runs: {outputs}")
+        return 1
+    print(f"PASS: {RUNS} runs interleaved the processes the same way: {order}")
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(run())
@@ -0,0 +1,62 @@
+#!/usr/bin/env python3
+"""
+random_seed.py
+---------------------------------
+• Replays consensus files with `runtime benchmark` whose Init record starts a
+  guest that prints 32 bytes from random_get, with the `seed:` option that
+  `init` sends.
+• Checks two replays with seed 42 print identical bytes, a replay with seed
+  43 prints different ones, and a second process started from the same seed
+  gets a stream of its own.
+Usage (after `cargo build --bin runtime`, from the repository root):
+    python3 test/random_seed.py
+"""
+import os, re, subprocess, sys, tempfile
+from support import record
+
+GUEST = b"""(module
+  (import "wasi_snapshot_preview1" "random_get" (func $random_get (param i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 64) "<random:")
+  (data (i32.const 104) ">")
+  (func (export "_start")
+    (drop (call $random_get (i32.const 72) (i32.const 32)))
+    (i32.store (i32.const 0) (i32.const 64))
+    (i32.store (i32.const 4) (i32.const 41))
+    (drop (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 8)))))
+"""
+TIMEOUT = 30
+
+
+def random_bytes(seed, processes=1):
+    """Returns what each process printed, in pid order."""
+    records = record(2, 0, f"seed:{seed}\0".encode() + GUEST) * processes + record(0, 0, b"clock:1")
+    with tempfile.NamedTemporaryFile(suffix=".bin", delete=False) as f:
+        f.write(records)
+    try:
+        result = subprocess.run(["target/debug/runtime", "benchmark", f.name],
+                                capture_output=True, timeout=TIMEOUT)
+    finally:
+        os.unlink(f.name)
+    return re.findall(rb"<random:(.{32})>", result.stdout, re.DOTALL)
+
+
+def run():
+    first, second, other = random_bytes(42), random_bytes(42), random_bytes(43)
+    if len(first) != 1 or first != second:
+        print(f"FAIL: two replays with seed 42 printed different bytes: {first} and {second}")
+        return 1
+    if len(other) != 1 or other == first:
+        print(f"FAIL: seed 43 should give other bytes than seed 42, got {other}")
+        return 1
+    both = random_bytes(42, processes=2)
+    if len(both) != 2 or both[0] == both[1]:
+        print(f"FAIL: two processes with seed 42 should get different bytes, got {both}")
+        return 1
+    print("PASS: random_get is the same on every replay of a seed, and differs between seeds and processes")
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(run())
@@ -0,0 +1,115 @@
+#!/usr/bin/env python3
+"""
+read_only_root.py
+---------------------------------
+• Replays, with `runtime benchmark`, the Init of a guest with a read-only
+  root (`readonly:1`) and a preloaded directory holding in.txt. The guest
+  opens in.txt for reading, then tries every syscall that modifies the
+  sandbox: path_open with O_CREAT and with O_TRUNC, path_create_directory,
+  path_remove_directory, path_unlink_file, path_rename, path_symlink,
+  path_link, path_filestat_set_times, fd_write, fd_pwrite, fd_allocate,
+  fd_filestat_set_size and fd_filestat_set_times on in.txt, file_create and
+  __builtin_rt_atomic_replace. It prints the errno of each on stdout.
+• Checks every one of them failed with EACCES (13), while stdout still
+  worked, and that in.txt in the preloaded directory is unchanged.
+Usage (after `cargo build --bin runtime`, from the repository root):
+    python3 test/read_only_root.py
+"""
+import os, shutil, subprocess, sys, tempfile
+from support import record
+
+GUEST = b"""(module
+  (import "wasi_snapshot_preview1" "path_open" (func $path_open (param i32 i32 i32 i32 i32 i64 i64 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "path_create_directory" (func $path_create_directory (param i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "path_remove_directory" (func $path_remove_directory (param i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "path_unlink_file" (func $path_unlink_file (param i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "path_rename" (func $path_rename (param i32 i32 i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "path_symlink" (func $path_symlink (param i32 i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "path_link" (func $path_link (param i32 i32 i32 i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "path_filestat_set_times" (func $path_filestat_set_times (param i32 i32 i32 i32 i64 i64 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_pwrite" (func $fd_pwrite (param i32 i32 i32 i64 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_allocate" (func $fd_allocate (param i32 i64 i64) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_filestat_set_size" (func $fd_filestat_set_size (param i32 i64) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_filestat_set_times" (func $fd_filestat_set_times (param i32 i64 i64 i32) (result i32)))
+  (import "env" "file_create" (func $file_create (param i32 i32 i32) (result i32)))
+  (import "env" "__builtin_rt_atomic_replace" (func $atomic_replace (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 100) "in.txt")
+  (data (i32.const 110) "out.txt")
+  (data (i32.const 120) "newdir")
+  (data (i32.const 130) "moved.txt")
+  ;; iovec { buf = 110, len = 3 } at 16
+  (data (i32.const 16) "\\6e\\00\\00\\00\\03\\00\\00\\00")
+  (global $out (mut i32) (i32.const 200))
+  ;; Appends the errno to the line at 200 as two digits and a space
+  (func $note (param $errno i32)
+    (i32.store8 (global.get $out) (i32.add (i32.const 48) (i32.div_u (local.get $errno) (i32.const 10))))
+    (i32.store8 (i32.add (global.get $out) (i32.const 1)) (i32.add (i32.const 48) (i32.rem_u (local.get $errno) (i32.const 10))))
+    (i32.store8 (i32.add (global.get $out) (i32.const 2)) (i32.const 32))
+    (global.set $out (i32.add (global.get $out) (i32.const 3))))
+  (func (export "_start")
+    (local $fd i32)
+    ;; Only the FD_READ right, since asking for FD_WRITE is itself refused
+    (drop (call $path_open (i32.const 3) (i32.const 0) (i32.const 100) (i32.const 6) (i32.const 0)
+      (i64.const 2) (i64.const 2) (i32.const 0) (i32.const 8)))
+    (local.set $fd (i32.load (i32.const 8)))
+    (call $note (call $path_open (i32.const 3) (i32.const 0) (i32.const 110) (i32.const 7) (i32.const 1)
+      (i64.const -1) (i64.const -1) (i32.const 0) (i32.const 8)))
+    (call $note (call $path_open (i32.const 3) (i32.const 0) (i32.const 100) (i32.const 6) (i32.const 8)
+      (i64.const -1) (i64.const -1) (i32.const 0) (i32.const 8)))
+    (call $note (call $path_create_directory (i32.const 3) (i32.const 120) (i32.const 6)))
+    (call $note (call $path_remove_directory (i32.const 3) (i32.const 120) (i32.const 6)))
+    (call $note (call $path_unlink_file (i32.const 3) (i32.const 100) (i32.const 6)))
+    (call $note (call $path_rename (i32.const 3) (i32.const 100) (i32.const 6) (i32.const 3) (i32.const 130) (i32.const 9)))
+    (call $note (call $path_symlink (i32.const 100) (i32.const 6) (i32.const 3) (i32.const 130) (i32.const 9)))
+    (call $note (call $path_link (i32.const 3) (i32.const 0) (i32.const 100) (i32.const 6) (i32.const 3) (i32.const 130) (i32.const 9)))
+    (call $note (call $path_filestat_set_times (i32.const 3) (i32.const 0) (i32.const 100) (i32.const 6)
+      (i64.const 1) (i64.const 1) (i32.const 5)))
+    (call $note (call $fd_write (local.get $fd) (i32.const 16) (i32.const 1) (i32.const 8)))
+    (call $note (call $fd_pwrite (local.get $fd) (i32.const 16) (i32.const 1) (i64.const 0) (i32.const 8)))
+    (call $note (call $fd_allocate (local.get $fd) (i64.const 0) (i64.const 4096)))
+    (call $note (call $fd_filestat_set_size (local.get $fd) (i64.const 0)))
+    (call $note (call $fd_filestat_set_times (local.get $fd) (i64.const 1) (i64.const 1) (i32.const 5)))
+    (call $note (call $file_create (i32.const 110) (i32.const 7) (i32.const 8)))
+    (call $note (call $atomic_replace (i32.const 110) (i32.const 7) (i32.const 100) (i32.const 6)))
+    (i32.store8 (global.get $out) (i32.const 10))
+    (i32.store (i32.const 0) (i32.const 200))
+    (i32.store (i32.const 4) (i32.sub (i32.add (global.get $out) (i32.const 1)) (i32.const 200)))
+    (drop (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 8)))))
+"""
+WRITES = 16
+CONTENT = b"original\n"
+TIMEOUT = 10
+
+
+def run():
+    preload = tempfile.mkdtemp()
+    with open(os.path.join(preload, "in.txt"), "wb") as f:
+        f.write(CONTENT)
+    with tempfile.NamedTemporaryFile(suffix=".bin", delete=False) as f:
+        f.write(record(2, 0, f"dir:{preload}\0readonly:1\0".encode() + GUEST) + record(0, 0, b"clock:1"))
+    try:
+        result = subprocess.run(["target/debug/runtime", "benchmark", f.name], capture_output=True, timeout=TIMEOUT)
+    except subprocess.TimeoutExpired:
+        print(f"FAIL: the replay did not end within {TIMEOUT}s")
+        return 1
+    finally:
+        os.unlink(f.name)
+        with open(os.path.join(preload, "in.txt"), "rb") as f:
+            content = f.read()
+        shutil.rmtree(preload)
+    # The runtime traces some syscalls on stdout too; the guest's line is all errnos
+    lines = [line for line in result.stdout.splitlines() if line[:2].isdigit()]
+    if lines != [b"13 " * WRITES]:
+        print(f"FAIL: expected all {WRITES} writes to fail with EACCES (13), got {lines}")
+        return 1
+    if content != CONTENT:
+        print(f"FAIL: the preloaded in.txt changed to {content}")
+        return 1
+    print(f"PASS: all {WRITES} syscalls that modify the sandbox failed with EACCES, and stdout still worked")
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(run())
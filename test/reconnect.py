@@ -0,0 +1,106 @@
+#!/usr/bin/env python3
+"""
+reconnect.py
+---------------------------------
+• Starts `runtime tcp` before anything listens on port 9000, waits, then
+  stands in for `consensus tcp` and checks the runtime connects once it is up.
+• Sends an Init batch for a guest that echoes its stdin, and a batch with
+  "one". Then drops the connection in the middle of a batch header.
+• Checks the runtime connects again. As consensus does on every accept, the
+  stand-in sends its whole history again, then a batch with "two". The guest
+  must echo "one" and "two" once each, so no batch was applied twice.
+• Starts another runtime with `--connect-timeout=1000` and nothing on the
+  port it connects to, and checks it exits with an error within a few
+  seconds instead of retrying forever.
+Usage (after `cargo build --bin runtime`, from the repository root; uses port 9000):
+    python3 test/reconnect.py
+"""
+import socket, struct, subprocess, sys, time
+from support import batch, hang_up, record
+
+ECHO = b"""(module
+  (import "wasi_snapshot_preview1" "fd_read" (func $fd_read (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (func (export "_start")
+    (loop $echo
+      (i32.store (i32.const 0) (i32.const 64))
+      (i32.store (i32.const 4) (i32.const 64))
+      (drop (call $fd_read (i32.const 0) (i32.const 0) (i32.const 1) (i32.const 8)))
+      (i32.store (i32.const 4) (i32.load (i32.const 8)))
+      (drop (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 12)))
+      (br $echo))))
+"""
+TIMEOUT = 10
+
+
+def accept(server, what):
+    try:
+        conn, _ = server.accept()
+        return conn
+    except socket.timeout:
+        raise SystemExit(f"FAIL: the runtime did not {what} within {TIMEOUT}s")
+
+
+def run():
+    history = [batch(1, record(2, 0, ECHO) + record(0, 0, b"clock:1")),
+               batch(2, record(1, 1, b"fd:0,body:one\n") + record(0, 0, b"clock:1"))]
+    runtime = subprocess.Popen(["target/debug/runtime", "tcp"], stdout=subprocess.PIPE,
+                               stderr=subprocess.DEVNULL)
+    server = socket.socket()
+    try:
+        time.sleep(1.5)  # the runtime retries while nothing listens
+        if runtime.poll() is not None:
+            print(f"FAIL: the runtime exited with {runtime.returncode} before consensus was up")
+            return 1
+        server.setsockopt(socket.SOL_SOCKET, socket.SO_REUSEADDR, 1)
+        server.bind(("127.0.0.1", 9000))
+        server.listen(1)
+        server.settimeout(TIMEOUT)
+        conn = accept(server, "connect once consensus was up")
+        conn.sendall(b"".join(history))
+        time.sleep(1)
+        conn.sendall(struct.pack("<QB", 3, 0) + b"\x10\x00")  # cut off mid-header
+        conn.close()
+        conn = accept(server, "reconnect after losing the connection")
+        conn.sendall(b"".join(history) + batch(3, record(1, 1, b"fd:0,body:two\n") + record(0, 0, b"clock:1")))
+        time.sleep(1)
+        hang_up(conn)
+        output, _ = runtime.communicate(timeout=TIMEOUT)
+    except subprocess.TimeoutExpired:
+        runtime.kill()
+        print("FAIL: the runtime did not exit once the connection was closed")
+        return 1
+    finally:
+        if runtime.poll() is None:
+            runtime.kill()
+        server.close()
+    output = output.decode(errors="replace")
+    if output != "one\ntwo\n":
+        print(f"FAIL: expected the guest to echo one and two once each, got {output!r}")
+        return 1
+    print("PASS: the runtime connected late, reconnected, and skipped the batches sent again")
+    return give_up()
+
+
+def give_up():
+    with socket.socket() as s:
+        s.bind(("127.0.0.1", 0))
+        port = s.getsockname()[1]  # closed again, so nothing listens on it
+    runtime = subprocess.Popen(["target/debug/runtime", "tcp", f"127.0.0.1:{port}", "--connect-timeout=1000"],
+                               stdout=subprocess.DEVNULL, stderr=subprocess.DEVNULL)
+    try:
+        runtime.wait(timeout=TIMEOUT)
+    except subprocess.TimeoutExpired:
+        runtime.kill()
+        print(f"FAIL: the runtime still retried {TIMEOUT}s after a 1s connect timeout")
+        return 1
+    if runtime.returncode == 0:
+        print("FAIL: the runtime that never reached consensus exited successfully")
+        return 1
+    print(f"PASS: the runtime gave up connecting and exited with {runtime.returncode}")
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(run())
@@ -0,0 +1,72 @@
+#!/usr/bin/env python3
+"""
+replay_breakpoint.py
+---------------------------------
+• Writes a consensus file (Init, clock, a stdin line, clock) for a guest that
+  reads stdin twice, and replays it with `runtime benchmark --break=batch:2`.
+• Checks the replay stops after the second batch and dumps the clock, the
+  blocked process and the line waiting in its FD 0, then continues to the
+  end when the operator types `c`.
+• Replays it again with --break=pid:1=blocked, and checks the replay stops
+  after the first batch, once the guest has blocked reading stdin.
+• Replays an Init with `deadline:0` with --break=pid:1=finished, and checks
+  the replay stops on the process, which was ended before its first turn.
+Usage (after `cargo build --bin runtime`, from the repository root):
+    python3 test/replay_breakpoint.py
+"""
+import os, subprocess, sys, tempfile
+from support import record
+
+GUEST = b"""(module
+  (import "wasi_snapshot_preview1" "fd_read" (func $fd_read (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (func (export "_start")
+    (i32.store (i32.const 0) (i32.const 100))
+    (i32.store (i32.const 4) (i32.const 64))
+    (drop (call $fd_read (i32.const 0) (i32.const 0) (i32.const 1) (i32.const 8)))
+    (drop (call $fd_read (i32.const 0) (i32.const 0) (i32.const 1) (i32.const 8)))))
+"""
+TIMEOUT = 10
+
+
+def replay(records, spec):
+    """Returns the exit status and stderr of a replay of `records` with `spec`."""
+    with tempfile.NamedTemporaryFile(suffix=".bin", delete=False) as f:
+        f.write(records)
+    try:
+        result = subprocess.run(["target/debug/runtime", "benchmark", f.name, f"--break={spec}"],
+                                input=b"c\n", capture_output=True, timeout=TIMEOUT)
+    except subprocess.TimeoutExpired:
+        return None, ""
+    finally:
+        os.unlink(f.name)
+    return result.returncode, result.stderr.decode(errors="replace")
+
+
+def run():
+    records = (record(2, 0, GUEST) + record(0, 0, b"clock:5")
+               + record(1, 1, b"fd:0,body:hello") + record(0, 0, b"clock:5"))
+    cases = [
+        ("batch:2", records, ["=== Breakpoint: Batch(2) ===", "clock: 10  batches applied: 2",
+                              "process 1: Blocked (StdinRead)", "fd 0: FDEntry(buffer: \"hello\n\""]),
+        ("pid:1=blocked", records, ["=== Breakpoint: State(1, Blocked) ===", "clock: 5  batches applied: 1",
+                                    "process 1: Blocked (StdinRead)"]),
+        ("pid:1=finished", record(2, 0, b"deadline:0\0" + GUEST) + record(0, 0, b"clock:5"),
+         ["=== Breakpoint: State(1, Finished) ===", "clock: 5  batches applied: 1", "process 1: Finished"]),
+    ]
+    for spec, replayed, expected in cases:
+        status, output = replay(replayed, spec)
+        if status is None:
+            print(f"FAIL: replay with --break={spec} did not finish")
+            return 1
+        missing = [line for line in expected if line not in output]
+        if missing or output.count("=== Breakpoint") != 1 or status != 0:
+            print(output)
+            print(f"FAIL: expected one stop for {spec} and a clean exit; missing {missing}")
+            return 1
+        print(f"PASS: the replay stopped at {spec} and dumped the runtime's state")
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(run())
@@ -0,0 +1,89 @@
+#!/usr/bin/env python3
+"""
+replay_hash.py
+---------------------------------
+• Records a session with `consensus tcp` and a runtime, in which a guest
+  writes the line consensus sends it on stdin to a file in its sandbox.
+• Replays the session file twice with `runtime replay` and checks both
+  replays print the same SHA-256 of the guest's final sandbox.
+Usage (after `cargo build`, from the repository root; uses ports 9000 and 8080):
+    python3 test/replay_hash.py
+"""
+import glob, os, re, subprocess, sys, tempfile, time
+
+GUEST = """(module
+  (import "wasi_snapshot_preview1" "fd_read" (func $fd_read (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "path_open" (func $path_open (param i32 i32 i32 i32 i32 i64 i64 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_close" (func $fd_close (param i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 256) "out")
+  (func (export "_start")
+    ;; read one line from stdin into 512
+    (i32.store (i32.const 16) (i32.const 512))
+    (i32.store (i32.const 20) (i32.const 64))
+    (drop (call $fd_read (i32.const 0) (i32.const 16) (i32.const 1) (i32.const 24)))
+    ;; write it to a new file "out" in the sandbox
+    (drop (call $path_open (i32.const 3) (i32.const 0) (i32.const 256) (i32.const 3) (i32.const 1)
+      (i64.const -1) (i64.const -1) (i32.const 0) (i32.const 64)))
+    (i32.store (i32.const 20) (i32.load (i32.const 24)))
+    (drop (call $fd_write (i32.load (i32.const 64)) (i32.const 16) (i32.const 1) (i32.const 28)))
+    (drop (call $fd_close (i32.load (i32.const 64))))))
+"""
+TIMEOUT = 30
+
+
+def record_session(workdir):
+    """Runs consensus and a runtime, and returns the session file consensus wrote."""
+    path = os.path.join(workdir, "write_stdin.wat")
+    with open(path, "w") as f:
+        f.write(GUEST)
+    before = set(glob.glob("sessions/session-*.bin"))
+    consensus = subprocess.Popen(["target/debug/consensus", "tcp"], stdin=subprocess.PIPE,
+                                 stdout=subprocess.DEVNULL, stderr=subprocess.DEVNULL)
+    time.sleep(0.5)
+    runtime = subprocess.Popen(["target/debug/runtime", "tcp"], stdout=subprocess.DEVNULL,
+                               stderr=subprocess.DEVNULL)
+    try:
+        time.sleep(0.5)
+        for command in (f"init {path}", "msg 1 fd:0,body:replayed", "exit"):
+            consensus.stdin.write(command.encode() + b"\n")
+            consensus.stdin.flush()
+            time.sleep(1.5)
+        consensus.stdin.close()
+        runtime.wait(timeout=TIMEOUT)
+        consensus.wait(timeout=TIMEOUT)
+    except subprocess.TimeoutExpired:
+        runtime.kill()
+        consensus.kill()
+    sessions = set(glob.glob("sessions/session-*.bin")) - before
+    return sessions.pop() if len(sessions) == 1 else None
+
+
+def replay(session):
+    """Returns the sandbox digest `runtime replay` prints for each process."""
+    result = subprocess.run(["target/debug/runtime", "replay", session], capture_output=True, timeout=TIMEOUT)
+    return dict(re.findall(r"process (\d+) sandbox sha256 ([0-9a-f]{64})", result.stdout.decode(errors="replace")))
+
+
+def run():
+    session = record_session(tempfile.mkdtemp())
+    if session is None:
+        print("FAIL: consensus did not record a session")
+        return 1
+    try:
+        first, second = replay(session), replay(session)
+    finally:
+        os.unlink(session)
+    if "1" not in first:
+        print(f"FAIL: the replay printed no sandbox digest for process 1, got {first}")
+        return 1
+    if first != second:
+        print(f"FAIL: two replays of the same session printed {first} and {second}")
+        return 1
+    print(f"PASS: both replays printed sandbox digest {first['1'][:16]}... for process 1")
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(run())
@@ -0,0 +1,117 @@
+#!/usr/bin/env python3
+"""
+runtime_metrics.py
+---------------------------------
+• Starts `runtime follow --metrics-port` on a consensus file that starts a
+  guest which reads stdin, so it stays blocked, followed by three clock
+  records.
+• Scrapes /metrics once the runtime has applied them, and checks every
+  metric the runtime documents is there with its TYPE line, and that the
+  process counts, batches and records applied, the guest's disk quota and
+  its fd_read calls have the values the input leads to.
+• Checks any other path gets a 404.
+Usage (after `cargo build --bin runtime`, from the repository root):
+    python3 test/runtime_metrics.py
+"""
+import os, re, shutil, socket, subprocess, sys, tempfile, urllib.error, urllib.request
+from support import record, wait_for
+
+GUEST = b"""(module
+  (import "wasi_snapshot_preview1" "fd_read" (func $fd_read (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 0) "\\40\\00\\00\\00\\10\\00\\00\\00")
+  (func (export "_start")
+    (drop (call $fd_read (i32.const 0) (i32.const 0) (i32.const 1) (i32.const 8)))))
+"""
+METRICS = {
+    "replicode_ready_processes": "gauge",
+    "replicode_blocked_processes": "gauge",
+    "replicode_finished_processes_total": "counter",
+    "replicode_batches_applied_total": "counter",
+    "replicode_records_applied_total": "counter",
+    "replicode_process_disk_usage_bytes": "gauge",
+    "replicode_process_disk_quota_bytes": "gauge",
+    "replicode_process_fuel_consumed_total": "counter",
+    "replicode_process_syscalls_total": "counter",
+}
+EXPECTED = {
+    "replicode_ready_processes": 0,
+    "replicode_blocked_processes": 1,
+    "replicode_finished_processes_total": 0,
+    "replicode_batches_applied_total": 3,
+    "replicode_records_applied_total": 4,
+    'replicode_process_disk_quota_bytes{pid="1"}': 10 * 1024 * 1024,
+    'replicode_process_syscalls_total{pid="1",syscall="fd_read"}': 1,
+}
+TIMEOUT = 20
+
+
+def free_port():
+    with socket.socket() as s:
+        s.bind(("127.0.0.1", 0))
+        return s.getsockname()[1]
+
+
+def scrape(port, path="/metrics"):
+    with urllib.request.urlopen(f"http://127.0.0.1:{port}{path}", timeout=TIMEOUT) as response:
+        return response.read().decode()
+
+
+def samples(text):
+    """Maps each sample's name and labels to its value."""
+    return {name: int(value) for name, value in re.findall(r"^(replicode_\S+) (\d+)$", text, re.M)}
+
+
+def run():
+    workdir = tempfile.mkdtemp()
+    path = os.path.join(workdir, "consensus_input.bin")
+    with open(path, "wb") as f:
+        f.write(record(2, 0, GUEST) + record(0, 0, b"clock:1") * 3)
+    port = free_port()
+    runtime = subprocess.Popen(["target/debug/runtime", "follow", path, f"--metrics-port={port}"],
+                               stdout=subprocess.DEVNULL, stderr=subprocess.DEVNULL)
+    text = ""
+    try:
+        def applied():
+            nonlocal text
+            try:
+                text = scrape(port)
+            except OSError:
+                return False
+            return samples(text).get("replicode_batches_applied_total") == 3
+        if not wait_for(applied, TIMEOUT):
+            print(f"FAIL: the runtime did not report the three batches applied, got:\n{text}")
+            return 1
+        try:
+            scrape(port, "/status")
+            missing_path = None
+        except urllib.error.HTTPError as e:
+            missing_path = e.code
+    finally:
+        runtime.kill()
+        runtime.wait()
+        shutil.rmtree(workdir)
+
+    for name, kind in METRICS.items():
+        if f"# TYPE {name} {kind}\n" not in text:
+            print(f"FAIL: /metrics has no {kind} {name}:\n{text}")
+            return 1
+    print(f"PASS: /metrics lists all {len(METRICS)} metrics")
+    values = samples(text)
+    for name, value in EXPECTED.items():
+        if values.get(name) != value:
+            print(f"FAIL: expected {name} to be {value}, got {values.get(name)}")
+            return 1
+    if not values.get('replicode_process_fuel_consumed_total{pid="1"}'):
+        print("FAIL: the guest's fuel was not reported")
+        return 1
+    print("PASS: the metrics match the blocked guest and the input applied")
+    if missing_path != 404:
+        print(f"FAIL: expected a 404 for /status, got {missing_path}")
+        return 1
+    print("PASS: other paths get a 404")
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(run())
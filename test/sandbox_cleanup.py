@@ -0,0 +1,93 @@
+#!/usr/bin/env python3
+"""
+sandbox_cleanup.py
+---------------------------------
+• Stands in for `consensus tcp` and starts a guest on a runtime that sleeps
+  in poll_oneoff until a clock batch wakes it.
+• Checks the guest's sandbox is `pid_1` under the runtime's own
+  `wasi_sandbox_<N>` root while it runs, and that once the runtime reports
+  it exited (a ProcessExit record) that directory is gone while the root
+  is still there.
+• Checks the runtime removes its root when consensus closes the connection,
+  and also when `runtime benchmark` fails on a missing consensus file.
+Usage (after `cargo build`, from the repository root; uses port 9000):
+    python3 test/sandbox_cleanup.py
+"""
+import glob, os, socket, subprocess, sys
+from support import batch, read_outgoing_records, record, wait_for
+
+GUEST = b"""(module
+  (import "wasi_snapshot_preview1" "poll_oneoff" (func $poll_oneoff (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (func (export "_start")
+    ;; one relative clock subscription of 1 ms
+    (i64.store (i32.const 152) (i64.const 1000000))
+    (drop (call $poll_oneoff (i32.const 128) (i32.const 192) (i32.const 1) (i32.const 24)))))
+"""
+TIMEOUT = 10
+
+
+def while_connected():
+    """Returns an error message, or None if the process sandbox came and went under the runtime's root."""
+    before = set(glob.glob("wasi_sandbox_*"))
+    server = socket.socket()
+    server.setsockopt(socket.SOL_SOCKET, socket.SO_REUSEADDR, 1)
+    server.bind(("127.0.0.1", 9000))
+    server.listen(1)
+    runtime = subprocess.Popen(["target/debug/runtime", "tcp"], stdout=subprocess.DEVNULL, stderr=subprocess.DEVNULL)
+    try:
+        conn, _ = server.accept()
+        conn.settimeout(TIMEOUT)
+        roots = set(glob.glob("wasi_sandbox_*")) - before
+        if len(roots) != 1:
+            return f"expected the runtime to create one sandbox root, found {sorted(roots)}"
+        root = roots.pop()
+        sandbox = os.path.join(root, "pid_1")
+        conn.sendall(batch(1, record(2, 2**64 - 1, GUEST) + record(0, 0, b"clock:1")))
+        if not wait_for(lambda: os.path.isdir(sandbox), TIMEOUT):
+            return f"the running guest never got a sandbox at {sandbox}"
+        conn.sendall(batch(2, record(0, 0, b"clock:1000000")))
+        records = []
+        while not any(msg_type == 14 and pid == 1 for msg_type, pid, _ in records):
+            records += read_outgoing_records(conn)
+        if not wait_for(lambda: not os.path.exists(sandbox), TIMEOUT):
+            return f"{sandbox} was left behind after process 1 exited"
+        if not os.path.isdir(root):
+            return f"the sandbox root {root} was removed while the runtime was still running"
+        conn.close()
+        runtime.wait(timeout=TIMEOUT)
+    except (socket.timeout, EOFError, subprocess.TimeoutExpired) as e:
+        return f"the runtime stopped answering ({e})"
+    finally:
+        runtime.kill()
+        server.close()
+    if os.path.exists(root):
+        return f"the runtime exited without removing its sandbox root {root}"
+    return None
+
+
+def on_error():
+    """Returns an error message, or None if a runtime that failed still removed its root."""
+    before = set(glob.glob("wasi_sandbox_*"))
+    result = subprocess.run(["target/debug/runtime", "benchmark", "no/such/consensus_input.bin"],
+                            capture_output=True, timeout=TIMEOUT)
+    if result.returncode == 0:
+        return "the runtime succeeded on a missing consensus file"
+    left = set(glob.glob("wasi_sandbox_*")) - before
+    if left:
+        return f"the failed runtime left its sandbox root behind: {sorted(left)}"
+    return None
+
+
+def run():
+    for check in (while_connected, on_error):
+        error = check()
+        if error:
+            print(f"FAIL: {error}")
+            return 1
+    print("PASS: the process sandbox was removed under the runtime's root, and the root on exit and on error")
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(run())
@@ -0,0 +1,89 @@
+#!/usr/bin/env python3
+"""
+secrets_env.py
+---------------------------------
+• Runs `consensus tcp` and a runtime started with `--secrets=<file>`, and
+  starts a guest that prints its environment.
+• Checks the secret reaches the guest's environ, but is not in the session
+  file consensus records, nor in the runtime's logs (even at debug level
+  with --no-redact).
+Usage (after `cargo build`, from the repository root; uses ports 9000 and 8080):
+    python3 test/secrets_env.py
+"""
+import glob, os, subprocess, sys, tempfile, time
+
+SECRET = b"s3cr3t-token-0b1e"
+GUEST = b"""(module
+  (import "wasi_snapshot_preview1" "environ_sizes_get" (func $sizes (param i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "environ_get" (func $environ (param i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (func (export "_start")
+    (local $i i32)
+    (drop (call $sizes (i32.const 0) (i32.const 4)))
+    (drop (call $environ (i32.const 64) (i32.const 1024)))
+    ;; print the strings, with each NUL turned into a newline
+    (loop $chars
+      (if (i32.eqz (i32.load8_u (i32.add (i32.const 1024) (local.get $i))))
+        (then (i32.store8 (i32.add (i32.const 1024) (local.get $i)) (i32.const 10))))
+      (local.set $i (i32.add (local.get $i) (i32.const 1)))
+      (br_if $chars (i32.lt_u (local.get $i) (i32.load (i32.const 4)))))
+    (i32.store (i32.const 16) (i32.const 1024))
+    (i32.store (i32.const 20) (i32.load (i32.const 4)))
+    (drop (call $fd_write (i32.const 1) (i32.const 16) (i32.const 1) (i32.const 24)))))
+"""
+TIMEOUT = 10
+
+
+def run():
+    workdir = tempfile.mkdtemp()
+    secrets = os.path.join(workdir, "secrets.env")
+    with open(secrets, "wb") as f:
+        f.write(b"# provisioned out of band\nAPI_KEY=" + SECRET + b"\n")
+    guest = os.path.join(workdir, "print_env.wat")
+    with open(guest, "wb") as f:
+        f.write(GUEST)
+    before = set(glob.glob("sessions/session-*.bin"))
+
+    consensus = subprocess.Popen(["target/debug/consensus", "tcp"], stdin=subprocess.PIPE,
+                                 stdout=subprocess.DEVNULL, stderr=subprocess.DEVNULL)
+    time.sleep(0.5)
+    runtime = subprocess.Popen(["target/debug/runtime", "tcp", "--secrets=" + secrets, "--no-redact"],
+                               env={"RUST_LOG": "debug"}, stdout=subprocess.PIPE, stderr=subprocess.PIPE)
+    time.sleep(0.5)
+    consensus.stdin.write(f"init {guest}\n".encode())
+    consensus.stdin.flush()
+    time.sleep(2)
+    consensus.stdin.write(b"exit\n")
+    consensus.stdin.close()
+    try:
+        output, logs = runtime.communicate(timeout=TIMEOUT)
+        consensus.wait(timeout=TIMEOUT)
+    except subprocess.TimeoutExpired:
+        runtime.kill()
+        consensus.kill()
+        print("FAIL: consensus or the runtime did not exit")
+        return 1
+
+    sessions = set(glob.glob("sessions/session-*.bin")) - before
+    recorded = b"".join(open(path, "rb").read() for path in sessions)
+    for path in sessions:
+        os.unlink(path)
+    if b"API_KEY=" + SECRET not in output:
+        print(f"FAIL: the guest did not see the secret; it printed {output!r}")
+        return 1
+    if b"environ_get" not in recorded:
+        print(f"FAIL: expected a session file with the Init, got {len(recorded)} bytes")
+        return 1
+    if SECRET in recorded:
+        print("FAIL: the secret is in the session file")
+        return 1
+    if SECRET in logs:
+        print("FAIL: the secret is in the runtime's logs")
+        return 1
+    print("PASS: the guest got the secret, which is in neither the session file nor the logs")
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(run())
@@ -0,0 +1,59 @@
+#!/usr/bin/env python3
+"""
+seq_counter.py
+---------------------------------
+• Replays a consensus file with `runtime benchmark`, twice, that starts two
+  processes from a guest that calls `env.__builtin_rt_seq` five times and
+  prints the values on one line.
+• Checks each process printed "seq 1 2 3 4 5", so each has a counter of its
+  own that starts at 1, and that both runs printed the same.
+Usage (after `cargo build --bin runtime`, from the repository root):
+    python3 test/seq_counter.py
+"""
+import os, subprocess, sys, tempfile
+from support import record
+
+GUEST = b"""(module
+  (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (import "env" "__builtin_rt_seq" (func $seq (result i64)))
+  (memory (export "memory") 1)
+  (data (i32.const 16) "seq ? ? ? ? ?\\n")
+  (func (export "_start")
+    (local $i i32)
+    (loop $next
+      (i32.store8 (i32.add (i32.const 20) (i32.mul (local.get $i) (i32.const 2)))
+        (i32.add (i32.const 48) (i32.wrap_i64 (call $seq))))
+      (local.set $i (i32.add (local.get $i) (i32.const 1)))
+      (br_if $next (i32.lt_u (local.get $i) (i32.const 5))))
+    (i32.store (i32.const 0) (i32.const 16))
+    (i32.store (i32.const 4) (i32.const 14))
+    (drop (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 8)))))
+"""
+TIMEOUT = 60
+
+
+def replay(path):
+    result = subprocess.run(["target/debug/runtime", "benchmark", path], capture_output=True, timeout=TIMEOUT)
+    return [line for line in result.stdout.decode(errors="replace").splitlines() if line.startswith("seq")]
+
+
+def run():
+    with tempfile.NamedTemporaryFile(suffix=".bin", delete=False) as f:
+        f.write(record(2, 0, GUEST) + record(2, 0, GUEST) + record(0, 0, b"clock:1"))
+    try:
+        first, second = replay(f.name), replay(f.name)
+    finally:
+        os.unlink(f.name)
+    if first != ["seq 1 2 3 4 5"] * 2:
+        print(f"FAIL: expected both processes to print seq 1 2 3 4 5, got {first}")
+        return 1
+    print("PASS: each process counted 1 to 5 on its own")
+    if first != second:
+        print(f"FAIL: the second run printed {second}, the first {first}")
+        return 1
+    print("PASS: both runs printed the same sequences")
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(run())
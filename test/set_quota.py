@@ -0,0 +1,83 @@
+#!/usr/bin/env python3
+"""
+set_quota.py
+---------------------------------
+• Replays a consensus file with `runtime benchmark` that starts a guest with
+  the Init option `quota:6000` (what `init <wasm_file> -q 6000` sends). It
+  creates a file, writes 4 KiB to it, sleeps a second and writes the 4 KiB
+  again (the steps of wasm_programs/test_set_quota.c). The next batch holds
+  SetQuota records that query the usage, try to lower the quota to 100 bytes
+  and raise it to 1000000, then a clock record that ends the sleep.
+• Checks the first write failed with ENOSPC and the one after raising the
+  quota succeeded, and that the runtime logged the usage, refused the
+  quota below it and applied the raise.
+Usage (after `cargo build --bin runtime`, from the repository root):
+    python3 test/set_quota.py
+"""
+import os, re, struct, subprocess, sys, tempfile
+from support import record
+
+GUEST = b"""(module
+  (import "wasi_snapshot_preview1" "path_open" (func $path_open (param i32 i32 i32 i32 i32 i64 i64 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "poll_oneoff" (func $poll_oneoff (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 256) "out.bin")
+  (data (i32.const 288) "<??>")
+  ;; prints fd_write's errno as two digits
+  (func $print (param $errno i32)
+    (i32.store8 (i32.const 289) (i32.add (i32.const 48) (i32.div_u (local.get $errno) (i32.const 10))))
+    (i32.store8 (i32.const 290) (i32.add (i32.const 48) (i32.rem_u (local.get $errno) (i32.const 10))))
+    (i32.store (i32.const 16) (i32.const 288))
+    (i32.store (i32.const 20) (i32.const 4))
+    (drop (call $fd_write (i32.const 1) (i32.const 16) (i32.const 1) (i32.const 24))))
+  (func $write (param $fd i32) (result i32)
+    (i32.store (i32.const 0) (i32.const 4096))
+    (i32.store (i32.const 4) (i32.const 4096))
+    (call $fd_write (local.get $fd) (i32.const 0) (i32.const 1) (i32.const 8)))
+  (func (export "_start")
+    (local $fd i32)
+    (drop (call $path_open (i32.const 3) (i32.const 0) (i32.const 256) (i32.const 7) (i32.const 1)
+      (i64.const -1) (i64.const -1) (i32.const 0) (i32.const 64)))
+    (local.set $fd (i32.load (i32.const 64)))
+    (call $print (call $write (local.get $fd)))
+    ;; sleep for one second: a relative clock subscription
+    (i64.store (i32.const 152) (i64.const 1000000000))
+    (drop (call $poll_oneoff (i32.const 128) (i32.const 192) (i32.const 1) (i32.const 24)))
+    (call $print (call $write (local.get $fd)))))
+"""
+TIMEOUT = 30
+
+
+def set_quota(quota=None):
+    return record(10, 1, b"" if quota is None else struct.pack("<Q", quota))
+
+
+def run():
+    records = (record(2, 0, b"quota:6000\0" + GUEST) + record(0, 0, b"clock:1")
+               + set_quota() + set_quota(100) + set_quota(1000000) + record(0, 0, b"clock:2000000000"))
+    with tempfile.NamedTemporaryFile(suffix=".bin", delete=False) as f:
+        f.write(records)
+    try:
+        result = subprocess.run(["target/debug/runtime", "benchmark", f.name], env={"RUST_LOG": "info"},
+                                capture_output=True, timeout=TIMEOUT)
+    finally:
+        os.unlink(f.name)
+    errnos = re.findall(r"<(\d\d)>", result.stdout.decode(errors="replace"))
+    # ENOSPC, then success
+    if errnos != ["28", "00"]:
+        print(f"FAIL: expected the first write to fail with ENOSPC (28) and the second to succeed, got {errnos}")
+        return 1
+    log = result.stderr.decode(errors="replace")
+    for line in [r"Process 1 uses \d+ of 6000 bytes of disk quota",
+                 r"Refusing to set disk quota of process 1 to 100 bytes",
+                 r"Set disk quota of process 1 to 1000000 bytes"]:
+        if not re.search(line, log):
+            print(f"FAIL: expected the runtime to log {line!r}")
+            return 1
+    print("PASS: a write refused under the quota succeeded once a SetQuota record raised it")
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(run())
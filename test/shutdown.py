@@ -0,0 +1,74 @@
+#!/usr/bin/env python3
+"""
+shutdown.py
+---------------------------------
+• Replays consensus files with `runtime benchmark` that start a guest which
+  prints <A>, blocks reading stdin, and prints <B> once it has read, then
+  send a Shutdown (type 13) record while it waits.
+• Checks a Shutdown for the guest's pid ends it before it prints <B>, and the
+  scheduler joins it and removes its sandbox; then that a Shutdown for pid 0
+  ends both of two guests, and input sent afterwards finds no process.
+Usage (after `cargo build --bin runtime`, from the repository root):
+    python3 test/shutdown.py
+"""
+import glob, os, re, subprocess, sys, tempfile
+from support import record
+
+GUEST = b"""(module
+  (import "wasi_snapshot_preview1" "fd_read" (func $fd_read (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 64) "<A><B>")
+  (func $print (param $at i32)
+    (i32.store (i32.const 0) (local.get $at))
+    (i32.store (i32.const 4) (i32.const 3))
+    (drop (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 8))))
+  (func (export "_start")
+    (call $print (i32.const 64))
+    (i32.store (i32.const 16) (i32.const 128))
+    (i32.store (i32.const 20) (i32.const 16))
+    (drop (call $fd_read (i32.const 0) (i32.const 16) (i32.const 1) (i32.const 24)))
+    (call $print (i32.const 67))))
+"""
+TIMEOUT = 30
+
+
+def replay(records):
+    """Returns the guest output and the runtime log."""
+    with tempfile.NamedTemporaryFile(suffix=".bin", delete=False) as f:
+        f.write(records)
+    try:
+        result = subprocess.run(["target/debug/runtime", "benchmark", f.name], env={"RUST_LOG": "info"},
+                                capture_output=True, timeout=TIMEOUT)
+    finally:
+        os.unlink(f.name)
+    return result.stdout.decode(errors="replace"), result.stderr.decode(errors="replace")
+
+
+def run():
+    sandboxes = set(glob.glob("wasi_sandbox_*"))
+    output, log = replay(record(2, 0, GUEST) + record(0, 0, b"clock:1") * 2
+                         + record(13, 1, b"") + record(0, 0, b"clock:1"))
+    if re.findall(r"<([AB])>", output) != ["A"]:
+        print(f"FAIL: expected the guest to print only <A> before it was shut down, got {output!r}")
+        return 1
+    if "Process 1 finished (shut down by consensus) and joined." not in log:
+        print("FAIL: the scheduler never joined process 1 after its Shutdown")
+        return 1
+    if set(glob.glob("wasi_sandbox_*")) - sandboxes:
+        print("FAIL: the sandbox of the process that was shut down was left behind")
+        return 1
+
+    output, log = replay(record(2, 0, GUEST) * 2 + record(0, 0, b"clock:1") * 2
+                         + record(13, 0, b"") + record(0, 0, b"clock:1")
+                         + record(1, 1, b"fd:0,body:late") + record(0, 0, b"clock:1"))
+    joined = sorted(re.findall(r"Process (\d+) finished \(shut down by consensus\) and joined", log))
+    if joined != ["1", "2"] or "<B>" in output:
+        print(f"FAIL: expected a Shutdown for pid 0 to end processes 1 and 2, ended {joined}, output {output!r}")
+        return 1
+    print("PASS: Shutdown ended the guests while they waited, and they were joined and cleaned up")
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(run())
@@ -0,0 +1,142 @@
+#!/usr/bin/env python3
+"""
+sock_bind.py
+---------------------------------
+• Runs `consensus tcp` with the default bind allow-list, `127.0.0.1:*`, and
+  a runtime, and starts a guest that binds a stream socket to 0.0.0.0 on a
+  free port, as `INADDR_ANY` servers do, listens, and sends "bound\\n" to the
+  first client it accepts.
+• Checks a client connecting to that port on 127.0.0.1 is accepted and
+  gets the guest's line, so the wildcard bind was given loopback instead of
+  being refused for not being on the allow-list.
+• Runs it again binding 127.0.0.1 on a port named in REPLICODE_BIND_ALLOW,
+  and checks an outside client reaches the guest on the port it chose.
+• Starts consensus with REPLICODE_HTTP_PORT set to a free port and has the
+  guest bind 127.0.0.1 on it, and checks consensus refuses the listen under
+  the default allow-list: `127.0.0.1:*` must not grant consensus's own ports.
+Usage (after `cargo build`, from the repository root; uses port 9000):
+    python3 test/sock_bind.py
+"""
+import glob, os, socket, subprocess, sys, tempfile, time
+
+GUEST = """(module
+  (import "wasi_snapshot_preview1" "sock_open" (func $sock_open (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "sock_bind" (func $sock_bind (param i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "sock_listen" (func $sock_listen (param i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "sock_accept" (func $sock_accept (param i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "sock_send" (func $sock_send (param i32 i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  ;; sockaddr_in for IP:PORT
+  (data (i32.const 32) "\\02\\00PORTBYTESIPBYTES\\00\\00\\00\\00\\00\\00\\00\\00")
+  (data (i32.const 64) "bound\\0a")
+  (func (export "_start")
+    (drop (call $sock_open (i32.const 1) (i32.const 1) (i32.const 0) (i32.const 0)))
+    (if (call $sock_bind (i32.load (i32.const 0)) (i32.const 32) (i32.const 16)) (then (unreachable)))
+    (if (call $sock_listen (i32.load (i32.const 0)) (i32.const 5)) (then (unreachable)))
+    (if (call $sock_accept (i32.load (i32.const 0)) (i32.const 0) (i32.const 4)) (then (unreachable)))
+    (drop (call $sock_send (i32.load (i32.const 4)) (i32.const 64) (i32.const 6) (i32.const 0) (i32.const 8)))))
+"""
+TIMEOUT = 10
+
+
+def free_port():
+    with socket.socket() as s:
+        s.bind(("127.0.0.1", 0))
+        return s.getsockname()[1]
+
+
+def connect(port):
+    """Returns what the guest sends a client on `port`, or None if it never listened."""
+    deadline = time.time() + TIMEOUT
+    while time.time() < deadline:
+        try:
+            with socket.create_connection(("127.0.0.1", port), timeout=TIMEOUT) as conn:
+                return conn.recv(64)
+        except ConnectionRefusedError:
+            time.sleep(0.1)
+    return None
+
+
+def escape(data):
+    return "".join(f"\\{b:02x}" for b in data)
+
+
+def serve(ip, port, env, client):
+    """Starts a guest bound to ip:port; returns what a client got (if `client`) and consensus's log."""
+    workdir = tempfile.mkdtemp()
+    path = os.path.join(workdir, "bind.wat")
+    log_path = os.path.join(workdir, "consensus.log")
+    with open(path, "w") as f:
+        f.write(GUEST.replace("PORTBYTES", escape(port.to_bytes(2, "big")))
+                     .replace("IPBYTES", escape(socket.inet_aton(ip))))
+    before = set(glob.glob("sessions/session-*.bin"))
+
+    log = open(log_path, "w")
+    consensus = subprocess.Popen(["target/debug/consensus", "tcp"], stdin=subprocess.PIPE,
+                                 stdout=subprocess.DEVNULL, stderr=log, env={**os.environ, **env})
+    time.sleep(0.5)
+    runtime = subprocess.Popen(["target/debug/runtime", "tcp"], stdout=subprocess.DEVNULL,
+                               stderr=subprocess.DEVNULL)
+    received = None
+    try:
+        time.sleep(0.5)
+        consensus.stdin.write(f"init {path}\n".encode())
+        consensus.stdin.flush()
+        if client:
+            received = connect(port)
+        time.sleep(0.5)
+        consensus.stdin.write(b"exit\n")
+        consensus.stdin.close()
+        runtime.wait(timeout=TIMEOUT)
+        consensus.wait(timeout=TIMEOUT)
+    except subprocess.TimeoutExpired:
+        received = "timeout"
+    finally:
+        runtime.kill()
+        consensus.kill()
+        log.close()
+        with open(log_path, errors="replace") as f:
+            output = f.read()
+        os.unlink(path)
+        os.unlink(log_path)
+        os.rmdir(workdir)
+        for session in set(glob.glob("sessions/session-*.bin")) - before:
+            os.unlink(session)
+    return received, output
+
+
+def run():
+    port = free_port()
+    received, _ = serve("0.0.0.0", port, {}, client=True)
+    if received == "timeout":
+        print("FAIL: consensus or the runtime did not exit")
+        return 1
+    if received is None:
+        print(f"FAIL: nothing listened on 127.0.0.1:{port} after the guest bound 0.0.0.0:{port}")
+        return 1
+    if received != b"bound\n":
+        print(f"FAIL: expected the guest's line, got {received!r}")
+        return 1
+    print(f"PASS: a guest that bound 0.0.0.0:{port} was given loopback and accepted a client")
+
+    port = free_port()
+    received, _ = serve("127.0.0.1", port, {"REPLICODE_BIND_ALLOW": f"127.0.0.1:{port}"}, client=True)
+    if received != b"bound\n":
+        print(f"FAIL: a client of the allowed 127.0.0.1:{port} got {received!r}, not the guest's line")
+        return 1
+    print(f"PASS: an outside client reached the guest on 127.0.0.1:{port}, which it chose")
+
+    http_port = free_port()
+    received, output = serve("127.0.0.1", http_port, {"REPLICODE_HTTP_PORT": str(http_port)}, client=False)
+    if received == "timeout":
+        print("FAIL: consensus or the runtime did not exit")
+        return 1
+    if f"may not bind 127.0.0.1:{http_port}" not in output:
+        print(f"FAIL: a guest was not refused consensus's HTTP port {http_port} under `127.0.0.1:*`")
+        return 1
+    print(f"PASS: `127.0.0.1:*` did not let a guest bind consensus's HTTP port {http_port}")
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(run())
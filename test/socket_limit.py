@@ -0,0 +1,117 @@
+#!/usr/bin/env python3
+"""
+socket_limit.py
+---------------------------------
+• Runs `consensus tcp` with REPLICODE_MAX_SOCKETS_PER_PROCESS=4 and a
+  runtime, and starts a guest that connects six times to a local listener,
+  printing whether each connect succeeded, then stays alive holding its
+  connections (the steps of wasm_programs/test_socket_limit.c).
+• Checks the first four connects succeeded and the two past the limit
+  failed, that the listener only saw four connections, and that /status
+  reports the process holding four sockets.
+Usage (after `cargo build`, from the repository root; uses ports 9000 and 8080):
+    python3 test/socket_limit.py
+"""
+import glob, json, os, re, socket, subprocess, sys, tempfile, threading, time, urllib.request
+
+GUEST = """(module
+  (import "wasi_snapshot_preview1" "sock_open" (func $sock_open (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "sock_connect" (func $sock_connect (param i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_read" (func $fd_read (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  ;; sockaddr_in for 127.0.0.1:PORT
+  (data (i32.const 32) "\\02\\00PORTBYTES\\7f\\00\\00\\01\\00\\00\\00\\00\\00\\00\\00\\00")
+  (data (i32.const 64) "<?>")
+  ;; prints <0> for a connect that succeeded and <x> for one that failed
+  (func $print (param $ok i32)
+    (i32.store8 (i32.const 65) (select (i32.const 48) (i32.const 120) (local.get $ok)))
+    (i32.store (i32.const 16) (i32.const 64))
+    (i32.store (i32.const 20) (i32.const 3))
+    (drop (call $fd_write (i32.const 1) (i32.const 16) (i32.const 1) (i32.const 24))))
+  (func (export "_start")
+    (local $i i32)
+    (loop $connect
+      (drop (call $sock_open (i32.const 1) (i32.const 1) (i32.const 0) (i32.const 0)))
+      (call $print (i32.eqz (call $sock_connect (i32.load (i32.const 0)) (i32.const 32) (i32.const 16))))
+      (local.set $i (i32.add (local.get $i) (i32.const 1)))
+      (br_if $connect (i32.lt_u (local.get $i) (i32.const 6))))
+    ;; keep the connections open until stdin closes
+    (i32.store (i32.const 16) (i32.const 128))
+    (i32.store (i32.const 20) (i32.const 64))
+    (drop (call $fd_read (i32.const 0) (i32.const 16) (i32.const 1) (i32.const 24)))))
+"""
+TIMEOUT = 10
+
+
+def count_accepts(server, accepted):
+    while True:
+        try:
+            conn, _ = server.accept()
+        except OSError:
+            return
+        accepted.append(conn)
+
+
+def run():
+    server = socket.socket()
+    server.bind(("127.0.0.1", 0))
+    server.listen(8)
+    accepted = []
+    threading.Thread(target=count_accepts, args=(server, accepted), daemon=True).start()
+    port_bytes = "".join(f"\\{b:02x}" for b in server.getsockname()[1].to_bytes(2, "big"))
+    workdir = tempfile.mkdtemp()
+    path = os.path.join(workdir, "connect_six.wat")
+    with open(path, "w") as f:
+        f.write(GUEST.replace("PORTBYTES", port_bytes))
+    before = set(glob.glob("sessions/session-*.bin"))
+
+    consensus = subprocess.Popen(["target/debug/consensus", "tcp"], stdin=subprocess.PIPE,
+                                 stdout=subprocess.DEVNULL, stderr=subprocess.DEVNULL,
+                                 env={"REPLICODE_MAX_SOCKETS_PER_PROCESS": "4"})
+    time.sleep(0.5)
+    runtime = subprocess.Popen(["target/debug/runtime", "tcp"], stdout=subprocess.PIPE,
+                               stderr=subprocess.DEVNULL)
+    time.sleep(0.5)
+    consensus.stdin.write(f"init {path}\n".encode())
+    consensus.stdin.flush()
+    time.sleep(2)
+    try:
+        with urllib.request.urlopen("http://127.0.0.1:8080/status", timeout=TIMEOUT) as response:
+            status = json.load(response)
+    except OSError as e:
+        status = {"error": str(e)}
+    consensus.stdin.write(b"exit\n")
+    consensus.stdin.close()
+    try:
+        output, _ = runtime.communicate(timeout=TIMEOUT)
+        consensus.wait(timeout=TIMEOUT)
+    except subprocess.TimeoutExpired:
+        runtime.kill()
+        consensus.kill()
+        print("FAIL: consensus or the runtime did not exit")
+        return 1
+    finally:
+        server.close()
+        os.unlink(path)
+        os.rmdir(workdir)
+        for session in set(glob.glob("sessions/session-*.bin")) - before:
+            os.unlink(session)
+
+    results = "".join(re.findall(r"<([0x])>", output.decode(errors="replace")))
+    if results != "0000xx":
+        print(f"FAIL: expected four connects to succeed and two to fail (0000xx), got {results!r}")
+        return 1
+    if len(accepted) != 4:
+        print(f"FAIL: expected the listener to see 4 connections, got {len(accepted)}")
+        return 1
+    held = status.get("processes", {}).get("1", {}).get("open_sockets")
+    if held != 4:
+        print(f"FAIL: expected /status to report process 1 holding 4 sockets, got {held}")
+        return 1
+    print("PASS: connects beyond the per-process limit failed and the NAT held four connections")
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(run())
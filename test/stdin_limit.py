@@ -0,0 +1,118 @@
+#!/usr/bin/env python3
+"""
+stdin_limit.py
+---------------------------------
+• Replays, with `runtime benchmark`, the steps of
+  wasm_programs/test_stdin_limit.c: a guest started with the Init option
+  `limits:stdin=16` sleeps for a second while three lines arrive on its
+  stdin, then reads what was buffered and prints it.
+• Checks the guest read the two lines that fit in 16 bytes, and that the
+  runtime logged refusing the third.
+• Replays a flood of 1024 chunks of 64 KiB for the stdin of a guest with a
+  64 KiB limit that never reads it, 64 MiB in all.
+• Checks all but the first chunk were refused, and that the runtime's peak
+  memory stayed within 32 MiB of a replay with a single chunk.
+Usage (after `cargo build --bin runtime`, from the repository root):
+    python3 test/stdin_limit.py
+"""
+import os, resource, subprocess, sys, tempfile
+from support import record
+
+READER = b"""(module
+  (import "wasi_snapshot_preview1" "poll_oneoff" (func $poll_oneoff (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_read" (func $fd_read (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (func (export "_start")
+    ;; sleep for one second: a relative clock subscription
+    (i64.store (i32.const 152) (i64.const 1000000000))
+    (drop (call $poll_oneoff (i32.const 128) (i32.const 192) (i32.const 1) (i32.const 24)))
+    (i32.store (i32.const 0) (i32.const 256))
+    (i32.store (i32.const 4) (i32.const 255))
+    (drop (call $fd_read (i32.const 0) (i32.const 0) (i32.const 1) (i32.const 8)))
+    (i32.store (i32.const 4) (i32.load (i32.const 8)))
+    (drop (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 8)))))
+"""
+# Sleeps far longer than the replay lasts, so it never reads stdin
+SLEEPER = b"""(module
+  (import "wasi_snapshot_preview1" "poll_oneoff" (func $poll_oneoff (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (func (export "_start")
+    (i64.store (i32.const 152) (i64.const 1000000000000))
+    (drop (call $poll_oneoff (i32.const 128) (i32.const 192) (i32.const 1) (i32.const 24)))))
+"""
+CHUNKS = 1024
+CHUNK = 64 * 1024
+HEADROOM = 32 * 1024 * 1024
+TIMEOUT = 60
+
+
+def replay(path):
+    """Returns the stdout and log of a replay of the file at `path`."""
+    result = subprocess.run(["target/debug/runtime", "benchmark", path], capture_output=True, timeout=TIMEOUT)
+    return result.stdout, result.stderr.decode(errors="replace")
+
+
+def flood(chunks):
+    """Replays `chunks` stdin chunks, each in a batch of its own, for a
+    guest that never reads them. Returns the log."""
+    # input_line adds the newline back to each body
+    chunk = record(1, 1, b"fd:0,body:" + b"x" * (CHUNK - 1)) + record(0, 0, b"clock:1")
+    with tempfile.NamedTemporaryFile(suffix=".bin", delete=False) as f:
+        f.write(record(2, 0, f"limits:stdin={CHUNK}\0".encode() + SLEEPER) + record(0, 0, b"clock:1"))
+        for _ in range(chunks):
+            f.write(chunk)
+    try:
+        return replay(f.name)[1]
+    finally:
+        os.unlink(f.name)
+
+
+def peak_rss():
+    """The largest peak memory of any replay so far, in bytes."""
+    return resource.getrusage(resource.RUSAGE_CHILDREN).ru_maxrss * 1024
+
+
+def run():
+    records = (record(2, 0, b"limits:stdin=16\0" + READER) + record(0, 0, b"clock:1")
+               + record(1, 1, b"fd:0,body:hello") + record(1, 1, b"fd:0,body:world")
+               + record(1, 1, b"fd:0,body:refused") + record(0, 0, b"clock:2000000000"))
+    with tempfile.NamedTemporaryFile(suffix=".bin", delete=False) as f:
+        f.write(records)
+    try:
+        output, log = replay(f.name)
+    except subprocess.TimeoutExpired:
+        print(f"FAIL: the replay did not end within {TIMEOUT}s")
+        return 1
+    finally:
+        os.unlink(f.name)
+    if not output.endswith(b"hello\nworld\n"):
+        print(f"FAIL: expected the guest to read the two lines that fit, got {output!r}")
+        return 1
+    if "Refused input for process 1 (7 bytes, via file): FD 0 is full: 12 unread bytes already buffered, limit 16" not in log:
+        print("FAIL: expected the runtime to log refusing the third line")
+        return 1
+    print("PASS: input past the 16-byte limit was refused and the rest reached the guest")
+
+    try:
+        flood(1)
+        baseline = peak_rss()
+        log = flood(CHUNKS)
+    except subprocess.TimeoutExpired:
+        print(f"FAIL: the flood replay did not end within {TIMEOUT}s")
+        return 1
+    refused = log.count("Refused input for process 1")
+    if refused != CHUNKS - 1:
+        print(f"FAIL: expected {CHUNKS - 1} chunks to be refused, got {refused}")
+        return 1
+    if peak_rss() > baseline + HEADROOM:
+        print(f"FAIL: the runtime grew from {baseline >> 20} MiB to {peak_rss() >> 20} MiB "
+              f"while {CHUNKS * CHUNK >> 20} MiB of input were refused")
+        return 1
+    print(f"PASS: {CHUNKS * CHUNK >> 20} MiB of unread input were refused, and the runtime "
+          f"peaked at {peak_rss() >> 20} MiB")
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(run())
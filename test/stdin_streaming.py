@@ -0,0 +1,75 @@
+#!/usr/bin/env python3
+"""
+stdin_streaming.py
+---------------------------------
+• Stands in for `consensus tcp`: starts a process that echoes each stdin
+  read followed by "|", then sends three stdin chunks in a single batch.
+• Checks the guest read them one chunk per fd_read ("one\\n|two\\n|end\\n|")
+  rather than all three in one read.
+Usage (after `cargo build --bin runtime`, from the repository root):
+    python3 test/stdin_streaming.py
+"""
+import socket, subprocess, sys, time
+from support import batch, record
+
+GUEST = b"""(module
+  (import "wasi_snapshot_preview1" "fd_read" (func $fd_read (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 16) "\\40\\00\\00\\00\\01\\00\\00\\00")
+  (data (i32.const 64) "|")
+  (func (export "_start")
+    (loop $next
+      ;; iovec { buf = 100, len = 64 }
+      (i32.store (i32.const 0) (i32.const 100))
+      (i32.store (i32.const 4) (i32.const 64))
+      (drop (call $fd_read (i32.const 0) (i32.const 0) (i32.const 1) (i32.const 8)))
+      (if (i32.eqz (i32.load (i32.const 8))) (then (return)))
+      ;; echo what was read, then the separator
+      (i32.store (i32.const 4) (i32.load (i32.const 8)))
+      (drop (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 12)))
+      (drop (call $fd_write (i32.const 1) (i32.const 16) (i32.const 1) (i32.const 12)))
+      (br_if $next (i32.ne (i32.load8_u (i32.const 100)) (i32.const 101))))))
+"""
+TIMEOUT = 10
+
+
+def run():
+    init = record(2, 2**64 - 1, GUEST)
+    chunks = b"".join(record(1, 1, b"fd:0,body:" + text) for text in (b"one", b"two", b"end"))
+    server = socket.socket()
+    server.setsockopt(socket.SOL_SOCKET, socket.SO_REUSEADDR, 1)
+    server.bind(("127.0.0.1", 9000))
+    server.listen(1)
+    runtime = subprocess.Popen(["target/debug/runtime", "tcp"],
+                               stdout=subprocess.PIPE, stderr=subprocess.STDOUT)
+    conn, _ = server.accept()
+    conn.sendall(batch(1, init))
+    time.sleep(1)  # let the guest block on stdin
+    conn.sendall(batch(2, chunks))
+    time.sleep(1)
+    # Take what the runtime sent back (the guest's ProcessExit record), so
+    # closing does not reset the connection under it
+    conn.shutdown(socket.SHUT_WR)
+    conn.settimeout(TIMEOUT)
+    while conn.recv(4096):
+        pass
+    conn.close()
+    server.close()
+
+    try:
+        output, _ = runtime.communicate(timeout=TIMEOUT)
+    except subprocess.TimeoutExpired:
+        runtime.kill()
+        print(f"FAIL: runtime still running {TIMEOUT}s after the last batch")
+        return 1
+    if b"one\n|two\n|end\n|" not in output:
+        print(output.decode(errors="replace"))
+        print("FAIL: expected one fd_read per chunk")
+        return 1
+    print("PASS: each chunk of the batch was read on its own")
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(run())
@@ -0,0 +1,105 @@
+"""
+support
+---------------------------------
+Helpers shared by the test scripts in test/: building consensus records and
+batches, reading the batches a runtime sends back, and waiting on processes
+started in the background. The scripts import it as `from support import ...`,
+since Python puts test/ on the path when running one of them.
+"""
+import socket, struct, threading, time
+
+
+def record(msg_type, pid, payload):
+    """A record as consensus writes it: type, pid, payload length, payload."""
+    return struct.pack("<BQI", msg_type, pid, len(payload)) + payload
+
+
+def batch(number, records):
+    """An incoming batch in wire format 0, which has no clock or checksum."""
+    return struct.pack("<QBQ", number, 0, len(records)) + records
+
+
+def read_exact(conn, n, peer="runtime"):
+    """Reads exactly `n` bytes from `conn`, or raises EOFError naming `peer`."""
+    data = b""
+    while len(data) < n:
+        chunk = conn.recv(n - len(data))
+        if not chunk:
+            raise EOFError(f"{peer} closed the connection")
+        data += chunk
+    return data
+
+
+def read_outgoing_records(conn):
+    """Reads the next batch a runtime sends, in any wire format, and returns
+    its records as (type, pid, payload) tuples. The empty batch 0 a runtime
+    opens a connection with, its hello, is skipped."""
+    number = 0
+    while number == 0:
+        number, direction = struct.unpack("<QB", read_exact(conn, 9))
+        if direction >> 4 >= 2:
+            read_exact(conn, 8)  # the runtime's clock
+        length, = struct.unpack("<Q", read_exact(conn, 8))
+        data = read_exact(conn, length)
+        if direction >> 4:
+            read_exact(conn, 4)  # CRC32 of the data
+    records = []
+    while data:
+        msg_type, pid, size = struct.unpack("<BQI", data[:13])
+        records.append((msg_type, pid, data[13:13 + size]))
+        data = data[13 + size:]
+    return records
+
+
+def read_hello(conn):
+    """Reads the batch a runtime opens a connection with. Returns its number
+    and records."""
+    number, direction = struct.unpack("<QB", read_exact(conn, 9))
+    read_exact(conn, 8)  # the runtime's clock
+    length, = struct.unpack("<Q", read_exact(conn, 8))
+    data = read_exact(conn, length)
+    read_exact(conn, 4)  # CRC32 of the data
+    records = []
+    while data:
+        msg_type, pid, size = struct.unpack("<BQI", data[:13])
+        records.append((msg_type, pid, data[13:13 + size]))
+        data = data[13 + size:]
+    return number, records
+
+
+def hang_up(conn, timeout=10):
+    """Closes a stand-in consensus's end of a connection between batches, as
+    consensus does on exit: shuts it down for writing, then reads what the
+    runtime still sends until it closes its end too. Closing with those
+    records unread would reset the connection, which the runtime takes for a
+    lost one."""
+    conn.shutdown(socket.SHUT_WR)
+    conn.settimeout(timeout)
+    try:
+        while conn.recv(65536):
+            pass
+    except OSError:
+        pass
+    conn.close()
+
+
+def wait_for(condition, timeout):
+    """Polls `condition` until it holds or `timeout` seconds pass. Returns whether it held."""
+    deadline = time.time() + timeout
+    while time.time() < deadline:
+        if condition():
+            return True
+        time.sleep(0.05)
+    return False
+
+
+def watch(stream):
+    """Collects `stream` in the background. Returns the bytes so far."""
+    collected = bytearray()
+
+    def read():
+        for chunk in iter(lambda: stream.read1(65536), b""):
+            collected.extend(chunk)
+
+    threading.Thread(target=read, daemon=True).start()
+    return collected
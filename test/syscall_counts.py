@@ -0,0 +1,42 @@
+#!/usr/bin/env python3
+"""
+syscall_counts.py
+---------------------------------
+• Replays, with `runtime benchmark`, two Inits of
+  wasm_programs/test_syscall_counts.wat, which calls args_sizes_get once,
+  clock_time_get twice and fd_write three times.
+• Checks the runtime logged exactly those counts for each process when it
+  exited, so each process counts its own syscalls.
+Usage (after `cargo build --bin runtime`, from the repository root):
+    python3 test/syscall_counts.py
+"""
+import os, re, subprocess, sys, tempfile
+from support import record
+
+COUNTS = "args_sizes_get=1, clock_time_get=2, fd_write=3"
+TIMEOUT = 30
+
+
+def run():
+    with open("wasm_programs/test_syscall_counts.wat", "rb") as f:
+        guest = f.read()
+    with tempfile.NamedTemporaryFile(suffix=".bin", delete=False) as f:
+        f.write(record(2, 0, guest) + record(2, 0, guest) + record(0, 0, b"clock:1"))
+    try:
+        result = subprocess.run(["target/debug/runtime", "benchmark", f.name], capture_output=True,
+                                timeout=TIMEOUT, env=dict(os.environ, RUST_LOG="info"))
+    except subprocess.TimeoutExpired:
+        print(f"FAIL: the replay did not end within {TIMEOUT}s")
+        return 1
+    finally:
+        os.unlink(f.name)
+    counts = dict(re.findall(r"Process (\d+) syscalls: (.*)", result.stderr.decode(errors="replace")))
+    if counts != {"1": COUNTS, "2": COUNTS}:
+        print(f"FAIL: expected both processes to log {COUNTS}, got {counts}")
+        return 1
+    print(f"PASS: each process counted its own syscalls: {COUNTS}")
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(run())
@@ -0,0 +1,81 @@
+#!/usr/bin/env python3
+"""
+table_limit.py
+---------------------------------
+• Replays, with `runtime benchmark`, the Init of
+  wasm_programs/test_table_limit.wat, which grows its table one element at
+  a time, once with the Init option `limits:table=100` and once with the
+  default limit of 10000 elements.
+• Checks both are killed by the table limit when they ask for one element
+  more than it allows.
+• Replays the Inits of a module declaring a table of 200 elements with
+  `limits:table=100`, and of one defining two tables with `limits:tables=1`.
+• Checks neither process is started, and the runtime logs which limit the
+  module is over.
+Usage (after `cargo build --bin runtime`, from the repository root):
+    python3 test/table_limit.py
+"""
+import os, subprocess, sys, tempfile
+from support import record
+
+LARGE_TABLE = b"""(module
+  (memory (export "memory") 1)
+  (table 200 funcref)
+  (func (export "_start")))
+"""
+TWO_TABLES = b"""(module
+  (memory (export "memory") 1)
+  (table 1 funcref)
+  (table 1 funcref)
+  (func (export "_start")))
+"""
+TIMEOUT = 30
+
+
+def replay(records):
+    """Returns the log of a replay of `records`, or None on a timeout."""
+    with tempfile.NamedTemporaryFile(suffix=".bin", delete=False) as f:
+        f.write(records)
+    try:
+        result = subprocess.run(["target/debug/runtime", "benchmark", f.name], capture_output=True,
+                                timeout=TIMEOUT, env=dict(os.environ, RUST_LOG="info"))
+    except subprocess.TimeoutExpired:
+        return None
+    finally:
+        os.unlink(f.name)
+    return result.stderr.decode(errors="replace")
+
+
+def run():
+    with open("wasm_programs/test_table_limit.wat", "rb") as f:
+        grower = f.read()
+    for options, limit in [(b"limits:table=100\0", 100), (b"", 10000)]:
+        log = replay(record(2, 0, options + grower) + record(0, 0, b"clock:1"))
+        if log is None:
+            print(f"FAIL: the replay with a limit of {limit} did not end within {TIMEOUT}s")
+            return 1
+        if (f"requested {limit + 1} with a limit of {limit}" not in log
+                or "Process 1 finished (killed by table limit)" not in log):
+            print(f"FAIL: expected the guest to be killed by the table limit at {limit + 1} elements")
+            return 1
+    print("PASS: growing a table past its limit killed the guest, with the default and an Init limit")
+
+    log = replay(record(2, 0, b"limits:table=100\0" + LARGE_TABLE) + record(2, 0, b"limits:tables=1\0" + TWO_TABLES)
+                 + record(0, 0, b"clock:1"))
+    if log is None:
+        print(f"FAIL: the replay of the oversized modules did not end within {TIMEOUT}s")
+        return 1
+    for pid, reason in [(1, "module declares a table of 200 elements; the limit is 100"),
+                        (2, "module defines 2 tables; the limit is 1")]:
+        if f"Failed to create new process {pid}: {reason}" not in log:
+            print(f"FAIL: expected the Init of process {pid} to be rejected with {reason!r}")
+            return 1
+    if "Started process" in log:
+        print("FAIL: a module over a table limit was started")
+        return 1
+    print("PASS: Inits of modules over the table size and count limits were rejected")
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(run())
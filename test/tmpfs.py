@@ -0,0 +1,96 @@
+#!/usr/bin/env python3
+"""
+tmpfs.py
+---------------------------------
+• Stands in for `consensus tcp` and sends a runtime an Init record carrying
+  `tmpfs:1` (what `init <wasm_file> -t` sends). The guest creates a file,
+  writes a line to it, opens it again, reads the line back and prints it,
+  then waits on stdin.
+• Checks the line comes back intact and that, while the guest is still
+  running, nothing is under the process's sandbox directory.
+• Starts the guest of test/init_quota.py with `tmpfs:1` and `quota:8192`, and
+  checks the in-memory file is held to the quota as a file on disk would be.
+Usage (after `cargo build --bin runtime`, from the repository root):
+    python3 test/tmpfs.py
+"""
+import os, re, shutil, socket, subprocess, sys
+from support import batch, record, wait_for, watch
+
+GUEST = b"""(module
+  (import "wasi_snapshot_preview1" "path_open" (func $path_open (param i32 i32 i32 i32 i32 i64 i64 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_read" (func $fd_read (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 256) "note")
+  (data (i32.const 300) "kept in memory\\n")
+  (func (export "_start")
+    (drop (call $path_open (i32.const 3) (i32.const 0) (i32.const 256) (i32.const 4) (i32.const 1)
+      (i64.const -1) (i64.const -1) (i32.const 0) (i32.const 64)))
+    (i32.store (i32.const 16) (i32.const 300))
+    (i32.store (i32.const 20) (i32.const 15))
+    (drop (call $fd_write (i32.load (i32.const 64)) (i32.const 16) (i32.const 1) (i32.const 32)))
+    (drop (call $path_open (i32.const 3) (i32.const 0) (i32.const 256) (i32.const 4) (i32.const 0)
+      (i64.const -1) (i64.const -1) (i32.const 0) (i32.const 68)))
+    (i32.store (i32.const 16) (i32.const 400))
+    (i32.store (i32.const 20) (i32.const 64))
+    (drop (call $fd_read (i32.load (i32.const 68)) (i32.const 16) (i32.const 1) (i32.const 32)))
+    (i32.store (i32.const 20) (i32.load (i32.const 32)))
+    (drop (call $fd_write (i32.const 1) (i32.const 16) (i32.const 1) (i32.const 32)))
+    (drop (call $fd_read (i32.const 0) (i32.const 16) (i32.const 1) (i32.const 32)))))
+"""
+TIMEOUT = 20
+
+
+def quota_guest():
+    with open(os.path.join(os.path.dirname(__file__), "init_quota.py")) as f:
+        source = f.read()
+    return re.search(r'GUEST = b"""(.*?)"""', source, re.DOTALL).group(1).replace("\\\\", "\\").encode()
+
+
+def run():
+    server = socket.socket()
+    server.bind(("127.0.0.1", 0))
+    server.listen(1)
+    server.settimeout(TIMEOUT)
+    runtime = subprocess.Popen(["target/debug/runtime", "tcp", f"127.0.0.1:{server.getsockname()[1]}"],
+                               stdout=subprocess.PIPE, stderr=subprocess.PIPE, env={"RUST_LOG": "info"})
+    output, log = watch(runtime.stdout), watch(runtime.stderr)
+    root = None
+    try:
+        conn, _ = server.accept()
+        conn.sendall(batch(1, record(2, 0, b"tmpfs:1\0" + GUEST)
+                           + record(2, 0, b"tmpfs:1\0quota:8192\0" + quota_guest()) + record(0, 0, b"clock:1")))
+        wait_for(lambda: b"kept in memory" in output and b"writes " in output, TIMEOUT)
+        found = re.search(rb"Using sandbox root: (\S+)", log)
+        root = found and found.group(1).decode()
+        on_disk = root and [os.path.join(dir, name) for dir, dirs, files in os.walk(os.path.join(root, "pid_1"))
+                            for name in dirs + files]
+        conn.close()
+    except socket.timeout:
+        print(f"FAIL: the runtime did not connect within {TIMEOUT}s")
+        return 1
+    finally:
+        runtime.kill()
+        runtime.wait()
+        server.close()
+        if root:
+            shutil.rmtree(root, ignore_errors=True)
+    lines = output.decode().splitlines()
+    if not root:
+        print("FAIL: the runtime did not log its sandbox root")
+        return 1
+    if "kept in memory" not in lines:
+        print(f"FAIL: the guest did not read back the line it wrote, got {lines}")
+        return 1
+    if on_disk:
+        print(f"FAIL: the tmpfs process left files in its sandbox directory: {on_disk}")
+        return 1
+    if "writes 04" not in lines:
+        print(f"FAIL: expected 4 writes under the 8 KiB quota in tmpfs mode, got {lines}")
+        return 1
+    print("PASS: the file was written and read back in memory, nothing reached the disk, and the quota held")
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(run())
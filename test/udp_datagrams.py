@@ -0,0 +1,147 @@
+#!/usr/bin/env python3
+"""
+udp_datagrams.py
+---------------------------------
+• Runs `consensus tcp` and a runtime, and starts two guests with SOCK_DGRAM
+  sockets: a server that binds a loopback port and waits in sock_recv_from,
+  then a client that sends it "ping" with sock_send_to and waits for a reply.
+  The server answers "pong" to the address sock_recv_from reported.
+• Checks both guests printed the datagram they received, so the datagram
+  went through the consensus NAT table's UDP sockets both ways.
+• Starts a third guest that binds an address outside the allow-list and
+  sends to the broadcast address, which the host refuses, and checks both
+  calls fail (EADDRINUSE and EIO) instead of reporting success.
+Usage (after `cargo build`, from the repository root; uses ports 9000 and 8080):
+    python3 test/udp_datagrams.py
+"""
+import glob, os, re, socket, subprocess, sys, tempfile, time
+
+GUEST = """(module
+  (import "wasi_snapshot_preview1" "sock_open" (func $sock_open (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "sock_bind" (func $sock_bind (param i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "sock_send_to" (func $sock_send_to (param i32 i32 i32 i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "sock_recv_from" (func $sock_recv_from (param i32 i32 i32 i32 i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  ;; sockaddr_in for 127.0.0.1:PORT
+  (data (i32.const 32) "\\02\\00PORTBYTES\\7f\\00\\00\\01\\00\\00\\00\\00\\00\\00\\00\\00")
+  (data (i32.const 96) "SEND")
+  (data (i32.const 128) "<ROLE got:")
+  ;; receives one datagram after the 12-byte prefix at 128, and prints it as a line
+  (func $receive
+    (i32.store (i32.const 60) (i32.const 16))
+    (drop (call $sock_recv_from (i32.load (i32.const 0)) (i32.const 140) (i32.const 16) (i32.const 0)
+      (i32.const 64) (i32.const 60) (i32.const 4) (i32.const 8)))
+    (i32.store16 (i32.add (i32.const 140) (i32.load (i32.const 4))) (i32.const 0x0a3e))
+    (i32.store (i32.const 16) (i32.const 128))
+    (i32.store (i32.const 20) (i32.add (i32.const 14) (i32.load (i32.const 4))))
+    (drop (call $fd_write (i32.const 1) (i32.const 16) (i32.const 1) (i32.const 24))))
+  (func (export "_start")
+    (drop (call $sock_open (i32.const 1) (i32.const 2) (i32.const 0) (i32.const 0)))
+    BODY))
+"""
+SERVER_BODY = """(drop (call $sock_bind (i32.load (i32.const 0)) (i32.const 32) (i32.const 16)))
+    (call $receive)
+    ;; reply to whoever sent the datagram
+    (drop (call $sock_send_to (i32.load (i32.const 0)) (i32.const 96) (i32.const 4) (i32.const 0)
+      (i32.const 64) (i32.const 16) (i32.const 12)))"""
+CLIENT_BODY = """(drop (call $sock_send_to (i32.load (i32.const 0)) (i32.const 96) (i32.const 4) (i32.const 0)
+      (i32.const 32) (i32.const 16) (i32.const 12)))
+    (call $receive)"""
+FAILING = """(module
+  (import "wasi_snapshot_preview1" "sock_open" (func $sock_open (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "sock_bind" (func $sock_bind (param i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "sock_send_to" (func $sock_send_to (param i32 i32 i32 i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  ;; sockaddr_in for 10.255.255.1:PORT, not on the allow-list
+  (data (i32.const 32) "\\02\\00PORTBYTES\\0a\\ff\\ff\\01\\00\\00\\00\\00\\00\\00\\00\\00")
+  ;; sockaddr_in for 255.255.255.255:9; sending there needs SO_BROADCAST
+  (data (i32.const 64) "\\02\\00\\00\\09\\ff\\ff\\ff\\ff\\00\\00\\00\\00\\00\\00\\00\\00")
+  (data (i32.const 96) "ping")
+  (data (i32.const 128) "<bind refused>\\n")
+  (data (i32.const 160) "<send refused>\\n")
+  (func $print (param $at i32)
+    (i32.store (i32.const 16) (local.get $at))
+    (i32.store (i32.const 20) (i32.const 15))
+    (drop (call $fd_write (i32.const 1) (i32.const 16) (i32.const 1) (i32.const 24))))
+  (func (export "_start")
+    (drop (call $sock_open (i32.const 1) (i32.const 2) (i32.const 0) (i32.const 0)))
+    (if (i32.eq (call $sock_bind (i32.load (i32.const 0)) (i32.const 32) (i32.const 16)) (i32.const 3))
+      (then (call $print (i32.const 128))))
+    (drop (call $sock_open (i32.const 1) (i32.const 2) (i32.const 0) (i32.const 0)))
+    (if (i32.eq (call $sock_send_to (i32.load (i32.const 0)) (i32.const 96) (i32.const 4) (i32.const 0)
+          (i32.const 64) (i32.const 16) (i32.const 12)) (i32.const 29))
+      (then (call $print (i32.const 160))))))
+"""
+TIMEOUT = 10
+
+
+def guest(workdir, name, port, role, send, body):
+    path = os.path.join(workdir, name)
+    port_bytes = "".join(f"\\{b:02x}" for b in port.to_bytes(2, "big"))
+    with open(path, "w") as f:
+        f.write(GUEST.replace("PORTBYTES", port_bytes).replace("ROLE", role)
+                .replace("SEND", send).replace("BODY", body))
+    return path
+
+
+def free_udp_port():
+    with socket.socket(socket.AF_INET, socket.SOCK_DGRAM) as s:
+        s.bind(("127.0.0.1", 0))
+        return s.getsockname()[1]
+
+
+def run():
+    workdir = tempfile.mkdtemp()
+    port = free_udp_port()
+    server = guest(workdir, "udp_server.wat", port, "server", "pong", SERVER_BODY)
+    client = guest(workdir, "udp_client.wat", port, "client", "ping", CLIENT_BODY)
+    failing = os.path.join(workdir, "udp_failing.wat")
+    with open(failing, "w") as f:
+        f.write(FAILING.replace("PORTBYTES", "".join(f"\\{b:02x}" for b in port.to_bytes(2, "big"))))
+    before = set(glob.glob("sessions/session-*.bin"))
+    consensus = subprocess.Popen(["target/debug/consensus", "tcp"], stdin=subprocess.PIPE,
+                                 stdout=subprocess.DEVNULL, stderr=subprocess.DEVNULL)
+    time.sleep(0.5)
+    runtime = subprocess.Popen(["target/debug/runtime", "tcp"], stdout=subprocess.PIPE,
+                               stderr=subprocess.DEVNULL)
+    try:
+        time.sleep(0.5)
+        consensus.stdin.write(f"init {server}\n".encode())
+        consensus.stdin.flush()
+        time.sleep(1.5)
+        consensus.stdin.write(f"init {client}\n".encode())
+        consensus.stdin.flush()
+        time.sleep(2)
+        consensus.stdin.write(f"init {failing}\n".encode())
+        consensus.stdin.flush()
+        time.sleep(1.5)
+        consensus.stdin.write(b"exit\n")
+        consensus.stdin.close()
+        output, _ = runtime.communicate(timeout=TIMEOUT)
+        consensus.wait(timeout=TIMEOUT)
+    except subprocess.TimeoutExpired:
+        runtime.kill()
+        consensus.kill()
+        print("FAIL: consensus or the runtime did not exit")
+        return 1
+    finally:
+        for path in set(glob.glob("sessions/session-*.bin")) - before:
+            os.unlink(path)
+    output = output.decode(errors="replace")
+    received = re.findall(r"<(server|client) got:(\w*)>", output)
+    if sorted(received) != [("client", "pong"), ("server", "ping")]:
+        print(f"FAIL: expected the server to get ping and the client pong, got {received}")
+        return 1
+    print("PASS: the two guests exchanged datagrams through the NAT table")
+    refused = re.findall(r"<(bind|send) refused>", output)
+    if refused != ["bind", "send"]:
+        print(f"FAIL: expected a refused bind and send to fail with EADDRINUSE and EIO, got {refused}")
+        return 1
+    print("PASS: a bind and a send consensus could not carry out failed in the guest")
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(run())
@@ -0,0 +1,107 @@
+#!/usr/bin/env python3
+"""
+udp_two_peers.py
+---------------------------------
+• Runs `consensus tcp` and a runtime, and starts a guest that binds one
+  SOCK_DGRAM socket to a loopback port, prints "bound", then twice takes a
+  datagram with sock_recv_from and sends it back with sock_send_to to the
+  address sock_recv_from reported.
+• Sends "one" and "two" to the guest's port from two UDP sockets here, each
+  on a port of its own, before the guest receives either.
+• Checks each socket gets back its own datagram, so one guest socket talks
+  to two peers and each datagram is reported with its own sender.
+Usage (after `cargo build`, from the repository root; uses ports 9000 and 8080):
+    python3 test/udp_two_peers.py
+"""
+import glob, os, socket, subprocess, sys, tempfile, time
+
+GUEST = """(module
+  (import "wasi_snapshot_preview1" "sock_open" (func $sock_open (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "sock_bind" (func $sock_bind (param i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "sock_send_to" (func $sock_send_to (param i32 i32 i32 i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "sock_recv_from" (func $sock_recv_from (param i32 i32 i32 i32 i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  ;; sockaddr_in for 127.0.0.1:PORT
+  (data (i32.const 32) "\\02\\00PORTBYTES\\7f\\00\\00\\01\\00\\00\\00\\00\\00\\00\\00\\00")
+  (data (i32.const 128) "bound\\n")
+  (func (export "_start")
+    (local $i i32)
+    (drop (call $sock_open (i32.const 1) (i32.const 2) (i32.const 0) (i32.const 0)))
+    (drop (call $sock_bind (i32.load (i32.const 0)) (i32.const 32) (i32.const 16)))
+    (i32.store (i32.const 16) (i32.const 128))
+    (i32.store (i32.const 20) (i32.const 6))
+    (drop (call $fd_write (i32.const 1) (i32.const 16) (i32.const 1) (i32.const 24)))
+    (loop $echo
+      (i32.store (i32.const 60) (i32.const 16))
+      (drop (call $sock_recv_from (i32.load (i32.const 0)) (i32.const 160) (i32.const 16) (i32.const 0)
+        (i32.const 64) (i32.const 60) (i32.const 4) (i32.const 8)))
+      (drop (call $sock_send_to (i32.load (i32.const 0)) (i32.const 160) (i32.load (i32.const 4)) (i32.const 0)
+        (i32.const 64) (i32.const 16) (i32.const 12)))
+      (local.set $i (i32.add (local.get $i) (i32.const 1)))
+      (br_if $echo (i32.lt_u (local.get $i) (i32.const 2))))))
+"""
+TIMEOUT = 10
+
+
+def free_udp_port():
+    with socket.socket(socket.AF_INET, socket.SOCK_DGRAM) as s:
+        s.bind(("127.0.0.1", 0))
+        return s.getsockname()[1]
+
+
+def run():
+    workdir = tempfile.mkdtemp()
+    port = free_udp_port()
+    path = os.path.join(workdir, "udp_echo.wat")
+    with open(path, "w") as f:
+        f.write(GUEST.replace("PORTBYTES", "".join(f"\\{b:02x}" for b in port.to_bytes(2, "big"))))
+    peers = [socket.socket(socket.AF_INET, socket.SOCK_DGRAM) for _ in range(2)]
+    for peer in peers:
+        peer.bind(("127.0.0.1", 0))
+        peer.settimeout(TIMEOUT)
+    before = set(glob.glob("sessions/session-*.bin"))
+    consensus = subprocess.Popen(["target/debug/consensus", "tcp"], stdin=subprocess.PIPE,
+                                 stdout=subprocess.DEVNULL, stderr=subprocess.DEVNULL)
+    time.sleep(0.5)
+    runtime = subprocess.Popen(["target/debug/runtime", "tcp"], stdout=subprocess.PIPE,
+                               stderr=subprocess.DEVNULL)
+    replies = []
+    try:
+        time.sleep(0.5)
+        consensus.stdin.write(f"init {path}\n".encode())
+        consensus.stdin.flush()
+        if runtime.stdout.readline() != b"bound\n":
+            print("FAIL: the guest did not bind its socket")
+            return 1
+        for peer, message in zip(peers, [b"one", b"two"]):
+            peer.sendto(message, ("127.0.0.1", port))
+        for peer in peers:
+            data, source = peer.recvfrom(16)
+            replies.append((data, source[1]))
+        consensus.stdin.write(b"exit\n")
+        consensus.stdin.close()
+        runtime.wait(timeout=TIMEOUT)
+        consensus.wait(timeout=TIMEOUT)
+    except (socket.timeout, subprocess.TimeoutExpired) as e:
+        print(f"FAIL: timed out ({e}), got {replies}")
+        return 1
+    finally:
+        runtime.kill()
+        consensus.kill()
+        for peer in peers:
+            peer.close()
+        os.unlink(path)
+        os.rmdir(workdir)
+        for session in set(glob.glob("sessions/session-*.bin")) - before:
+            os.unlink(session)
+
+    if replies != [(b"one", port), (b"two", port)]:
+        print(f"FAIL: expected each peer to get its own datagram back from port {port}, got {replies}")
+        return 1
+    print("PASS: one guest socket answered two peers, each with its own datagram")
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(run())
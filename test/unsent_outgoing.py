@@ -0,0 +1,97 @@
+#!/usr/bin/env python3
+"""
+unsent_outgoing.py
+---------------------------------
+• Stands in for `consensus tcp` and starts a guest that, once it reads a line
+  from stdin, computes for a while, sends a progress report and reads again.
+• Sends the line and the first bytes of another batch, then resets the
+  connection while the guest computes, so the runtime's write of the
+  outgoing batch with the report fails with the connection lost mid-batch.
+• Checks the runtime connects again and sends that batch, report and all,
+  right after its hello, under the number it had when the write failed.
+Usage (after `cargo build --bin runtime`, from the repository root):
+    python3 test/unsent_outgoing.py
+"""
+import socket, struct, subprocess, sys, time
+from support import batch, read_exact, read_hello, record
+
+GUEST = b"""(module
+  (import "wasi_snapshot_preview1" "fd_read" (func $fd_read (param i32 i32 i32 i32) (result i32)))
+  (import "env" "__builtin_rt_report" (func $report (param i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 128) "done")
+  (func (export "_start")
+    (local $i i32)
+    (i32.store (i32.const 0) (i32.const 64))
+    (i32.store (i32.const 4) (i32.const 16))
+    (drop (call $fd_read (i32.const 0) (i32.const 0) (i32.const 1) (i32.const 8)))
+    (loop $busy
+      (local.set $i (i32.add (local.get $i) (i32.const 1)))
+      (br_if $busy (i32.lt_u (local.get $i) (i32.const 400000000))))
+    (drop (call $report (i32.const 128) (i32.const 4)))
+    (drop (call $fd_read (i32.const 0) (i32.const 0) (i32.const 1) (i32.const 8)))))
+"""
+TIMEOUT = 30
+
+
+def read_outgoing(conn):
+    """Like read_outgoing_records, but also returns the batch's number."""
+    number, direction = struct.unpack("<QB", read_exact(conn, 9))
+    if direction >> 4 >= 2:
+        read_exact(conn, 8)  # the runtime's clock
+    length, = struct.unpack("<Q", read_exact(conn, 8))
+    data = read_exact(conn, length)
+    if direction >> 4:
+        read_exact(conn, 4)  # CRC32 of the data
+    records = []
+    while data:
+        msg_type, pid, size = struct.unpack("<BQI", data[:13])
+        records.append((msg_type, pid, data[13:13 + size]))
+        data = data[13 + size:]
+    return number, records
+
+
+def run():
+    server = socket.socket()
+    server.bind(("127.0.0.1", 0))
+    server.listen(1)
+    server.settimeout(TIMEOUT)
+    port = server.getsockname()[1]
+    runtime = subprocess.Popen(["target/debug/runtime", "tcp", f"127.0.0.1:{port}"],
+                               stdout=subprocess.DEVNULL, stderr=subprocess.DEVNULL)
+    try:
+        conn, _ = server.accept()
+        conn.settimeout(TIMEOUT)
+        read_hello(conn)
+        conn.sendall(batch(1, record(2, 0, GUEST) + record(0, 0, b"clock:1")))
+        first, _ = read_outgoing(conn)  # the ack of batch 1, once the guest blocks
+        # The start of a third batch, so the reset below is a lost connection
+        # and not consensus closing between batches
+        conn.sendall(batch(2, record(1, 1, b"fd:0,body:go") + record(0, 0, b"clock:1")) + b"\x03\x00")
+        time.sleep(0.3)
+        # Reset rather than close, so the runtime's next write fails
+        conn.setsockopt(socket.SOL_SOCKET, socket.SO_LINGER, struct.pack("ii", 1, 0))
+        conn.close()
+        conn, _ = server.accept()
+        conn.settimeout(TIMEOUT)
+        read_hello(conn)
+        number, records = read_outgoing(conn)
+        conn.close()
+    except (socket.timeout, EOFError) as e:
+        print(f"FAIL: the runtime did not reconnect and send its outgoing batch ({e})")
+        return 1
+    finally:
+        runtime.kill()
+        runtime.wait()
+        server.close()
+
+    if (6, 1, b"done") not in records or number != first + 1:
+        print(f"FAIL: expected outgoing batch {first + 1} with the report first on the new connection, "
+              f"got batch {number} with {records}")
+        return 1
+    print(f"PASS: outgoing batch {number}, whose write failed, was sent first after reconnecting")
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(run())
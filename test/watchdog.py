@@ -0,0 +1,62 @@
+#!/usr/bin/env python3
+"""
+watchdog.py
+---------------------------------
+• Writes a consensus file that starts wasm_programs/test_watchdog.wat, which
+  spins forever without a syscall, and a guest that echoes what it reads from
+  stdin, then sends the echo guest a line after the spinner has started.
+• Replays it with `runtime benchmark --fuel=0 --watchdog=100 --watchdog-kill`,
+  and checks the spinner is reported as killed by the watchdog, the echo
+  guest still gets and echoes its line, and the replay ends. --fuel=0 is
+  needed, since a fuel limit would make the spinner yield every turn.
+Usage (after `cargo build --bin runtime`, from the repository root):
+    python3 test/watchdog.py
+"""
+import os, subprocess, sys, tempfile
+from support import record
+
+ECHO = b"""(module
+  (import "wasi_snapshot_preview1" "fd_read" (func $fd_read (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (func (export "_start")
+    (i32.store (i32.const 0) (i32.const 64))
+    (i32.store (i32.const 4) (i32.const 64))
+    (drop (call $fd_read (i32.const 0) (i32.const 0) (i32.const 1) (i32.const 8)))
+    (i32.store (i32.const 4) (i32.load (i32.const 8)))
+    (drop (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 8)))))
+"""
+TIMEOUT = 10
+
+
+def run():
+    with open("wasm_programs/test_watchdog.wat", "rb") as f:
+        spinner = f.read()
+    records = (record(2, 0, spinner) + record(2, 0, ECHO) + record(0, 0, b"clock:1")
+               + record(1, 2, b"fd:0,body:still here\n") + record(0, 0, b"clock:1"))
+    with tempfile.NamedTemporaryFile(suffix=".bin", delete=False) as f:
+        f.write(records)
+    try:
+        result = subprocess.run(["target/debug/runtime", "benchmark", f.name, "--fuel=0",
+                                 "--watchdog=100", "--watchdog-kill"],
+                                capture_output=True, timeout=TIMEOUT, env=dict(os.environ, RUST_LOG="info"))
+    except subprocess.TimeoutExpired:
+        print(f"FAIL: the replay did not end within {TIMEOUT}s, so the spinner was not killed")
+        return 1
+    finally:
+        os.unlink(f.name)
+    log = result.stderr.decode(errors="replace")
+    if "Process 1 finished (killed by the watchdog)" not in log:
+        print(log)
+        print("FAIL: expected process 1 to be reported as killed by the watchdog")
+        return 1
+    if b"still here\n" not in result.stdout or "Process 2 finished" not in log:
+        print(log)
+        print(f"FAIL: expected process 2 to echo its line and finish, got {result.stdout}")
+        return 1
+    print("PASS: the watchdog killed the spinning process and the other process kept running")
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(run())
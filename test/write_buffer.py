@@ -0,0 +1,68 @@
+#!/usr/bin/env python3
+"""
+write_buffer.py
+---------------------------------
+• Replays a consensus file with `runtime benchmark` that starts a guest
+  writing 1 MiB to a file in a single fd_write, once with the default write
+  buffer (64 KiB) and once started with `limits:write=1024`.
+• Checks both writes report all 1 MiB written, and that the default buffer
+  blocked to flush about 16 times against about 1024 with 1 KiB, each block
+  costing the guest a batch.
+Usage (after `cargo build --bin runtime`, from the repository root):
+    python3 test/write_buffer.py
+"""
+import os, re, subprocess, sys, tempfile
+from support import record
+
+GUEST = b"""(module
+  (import "wasi_snapshot_preview1" "path_open" (func $path_open (param i32 i32 i32 i32 i32 i64 i64 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 17)
+  (data (i32.const 32) "<ok>")
+  (data (i32.const 256) "big.bin")
+  (func (export "_start")
+    (drop (call $path_open (i32.const 3) (i32.const 0) (i32.const 256) (i32.const 7) (i32.const 1)
+      (i64.const -1) (i64.const -1) (i32.const 0) (i32.const 64)))
+    ;; the 1 MiB from 64 KiB on, in one call
+    (i32.store (i32.const 80) (i32.const 65536))
+    (i32.store (i32.const 84) (i32.const 1048576))
+    (drop (call $fd_write (i32.load (i32.const 64)) (i32.const 80) (i32.const 1) (i32.const 88)))
+    (if (i32.eq (i32.load (i32.const 88)) (i32.const 1048576))
+      (then
+        (i32.store (i32.const 16) (i32.const 32))
+        (i32.store (i32.const 20) (i32.const 4))
+        (drop (call $fd_write (i32.const 1) (i32.const 16) (i32.const 1) (i32.const 24)))))))
+"""
+BATCHES = 1100
+TIMEOUT = 120
+
+
+def replay(options):
+    """Returns whether the guest wrote all 1 MiB, and how many times it blocked."""
+    records = record(2, 0, options + GUEST) + record(0, 0, b"clock:1") * BATCHES
+    with tempfile.NamedTemporaryFile(suffix=".bin", delete=False) as f:
+        f.write(records)
+    try:
+        result = subprocess.run(["target/debug/runtime", "benchmark", f.name], env={"RUST_LOG": "info"},
+                                capture_output=True, timeout=TIMEOUT)
+    finally:
+        os.unlink(f.name)
+    blocks = len(re.findall(r"Process 1 blocked; moving it to Blocked queue", result.stderr.decode(errors="replace")))
+    return b"<ok>" in result.stdout, blocks
+
+
+def run():
+    wrote_large, large_blocks = replay(b"")
+    wrote_small, small_blocks = replay(b"limits:write=1024\0")
+    if not (wrote_large and wrote_small):
+        print(f"FAIL: a write did not report all 1 MiB (64 KiB buffer: {wrote_large}, 1 KiB buffer: {wrote_small})")
+        return 1
+    if large_blocks > 16 or small_blocks < 1000:
+        print(f"FAIL: expected at most 16 blocks with 64 KiB and about 1024 with 1 KiB, got {large_blocks} and {small_blocks}")
+        return 1
+    print(f"PASS: the 1 MiB write blocked {large_blocks} times with a 64 KiB buffer and {small_blocks} with 1 KiB")
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(run())
@@ -0,0 +1,327 @@
+;; WASI conformance guest run by `runtime selftest`.
+;;
+;; Each check exercises one registered syscall through its raw import and
+;; stores its outcome at results[index] (1 = pass, 2 = fail; 0 = not run).
+;; After every check the whole array is sent with __builtin_rt_report, so if
+;; a syscall hangs or traps the runtime still knows how far the guest got.
+;; Indices must match SELFTEST_CHECKS in runtime/src/selftest.rs.
+;;
+;; Memory layout:
+;;   0..30      results
+;;   64..71     iovec,  72 out u32 (nwritten, nread, bufused, fd), 76 second fd
+;;   80..87     u64 out (seek offset, tell, clock resolution)
+;;   128..191   filestat
+;;   256..511   read / readdir buffer
+;;   512..559   subscription, 576..607 event, 608 nevents, 612 sentinel
+;;   640..655   sockaddr in, 660 addr_len, 680..695 sockaddr out
+;;   704..735   random bytes, 740 / 748 clock samples
+;;   1024..     strings
+(module
+  (import "wasi_snapshot_preview1" "fd_prestat_get" (func $fd_prestat_get (param i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_prestat_dir_name" (func $fd_prestat_dir_name (param i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_fdstat_get" (func $fd_fdstat_get (param i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "args_sizes_get" (func $args_sizes_get (param i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_read" (func $fd_read (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_seek" (func $fd_seek (param i32 i64 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_tell" (func $fd_tell (param i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_close" (func $fd_close (param i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_filestat_get" (func $fd_filestat_get (param i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_readdir" (func $fd_readdir (param i32 i32 i32 i64 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "path_open" (func $path_open (param i32 i32 i32 i32 i32 i64 i64 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "path_filestat_get" (func $path_filestat_get (param i32 i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "path_create_directory" (func $path_create_directory (param i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "path_remove_directory" (func $path_remove_directory (param i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "path_unlink_file" (func $path_unlink_file (param i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "path_rename" (func $path_rename (param i32 i32 i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "clock_res_get" (func $clock_res_get (param i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "clock_time_get" (func $clock_time_get (param i32 i64 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "poll_oneoff" (func $poll_oneoff (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "random_get" (func $random_get (param i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "sock_open" (func $sock_open (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "sock_bind" (func $sock_bind (param i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "sock_getsockname" (func $sock_getsockname (param i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "sock_getpeername" (func $sock_getpeername (param i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "sock_close" (func $sock_close (param i32) (result i32)))
+  (import "env" "__builtin_rt_report" (func $report (param i32 i32) (result i32)))
+
+  (memory (export "memory") 1)
+
+  (data (i32.const 1024) "selftest: fd_write ok\n")
+  (data (i32.const 1056) "st_file")
+  (data (i32.const 1064) "hello world")
+  (data (i32.const 1080) "st_dir")
+  (data (i32.const 1088) "st_dir/a")
+  (data (i32.const 1104) "st_renamed")
+
+  (global $fd (mut i32) (i32.const -1))
+  (global $sock (mut i32) (i32.const -1))
+
+  (func $record (param $index i32) (param $ok i32)
+    (i32.store8 (local.get $index) (select (i32.const 1) (i32.const 2) (local.get $ok)))
+    (drop (call $report (i32.const 0) (i32.const 30))))
+
+  ;; fd_write of one buffer; nwritten lands at 72
+  (func $write (param $fd i32) (param $ptr i32) (param $len i32) (result i32)
+    (i32.store (i32.const 64) (local.get $ptr))
+    (i32.store (i32.const 68) (local.get $len))
+    (call $fd_write (local.get $fd) (i32.const 64) (i32.const 1) (i32.const 72)))
+
+  ;; path_open relative to the preopened root (fd 3); the new fd lands at 72
+  (func $open (param $path i32) (param $len i32) (param $oflags i32) (result i32)
+    (call $path_open (i32.const 3) (i32.const 0) (local.get $path) (local.get $len) (local.get $oflags)
+      (i64.const -1) (i64.const -1) (i32.const 0) (i32.const 72)))
+
+  (func $stat (param $path i32) (param $len i32) (result i32)
+    (call $path_filestat_get (i32.const 3) (i32.const 0) (local.get $path) (local.get $len) (i32.const 128)))
+
+  (func (export "_start")
+    ;; 0: the root directory is preopened as a directory named "."
+    (call $record (i32.const 0)
+      (i32.and
+        (i32.eqz (call $fd_prestat_get (i32.const 3) (i32.const 80)))
+        (i32.and
+          (i32.eqz (i32.load8_u (i32.const 80)))
+          (i32.eq (i32.load (i32.const 84)) (i32.const 1)))))
+
+    ;; 1
+    (call $record (i32.const 1)
+      (i32.and
+        (i32.eqz (call $fd_prestat_dir_name (i32.const 3) (i32.const 256) (i32.const 16)))
+        (i32.eq (i32.load8_u (i32.const 256)) (i32.const 46))))
+
+    ;; 2: fd 3 is a directory
+    (call $record (i32.const 2)
+      (i32.and
+        (i32.eqz (call $fd_fdstat_get (i32.const 3) (i32.const 128)))
+        (i32.eq (i32.load8_u (i32.const 128)) (i32.const 3))))
+
+    ;; 3: no arguments were passed
+    (i32.store (i32.const 72) (i32.const -1))
+    (call $record (i32.const 3)
+      (i32.and
+        (i32.eqz (call $args_sizes_get (i32.const 72) (i32.const 80)))
+        (i32.eqz (i32.load (i32.const 72)))))
+
+    ;; 4
+    (call $record (i32.const 4)
+      (i32.and
+        (i32.eqz (call $write (i32.const 1) (i32.const 1024) (i32.const 22)))
+        (i32.eq (i32.load (i32.const 72)) (i32.const 22))))
+
+    ;; 5: O_CREAT
+    (call $record (i32.const 5)
+      (i32.and
+        (i32.eqz (call $open (i32.const 1056) (i32.const 7) (i32.const 1)))
+        (i32.ge_s (i32.load (i32.const 72)) (i32.const 4))))
+    (global.set $fd (i32.load (i32.const 72)))
+
+    ;; 6
+    (call $record (i32.const 6)
+      (i32.and
+        (i32.eqz (call $write (global.get $fd) (i32.const 1064) (i32.const 11)))
+        (i32.eq (i32.load (i32.const 72)) (i32.const 11))))
+
+    ;; 7: a closed fd can no longer be written
+    (call $record (i32.const 7)
+      (i32.and
+        (i32.eqz (call $fd_close (global.get $fd)))
+        (i32.ne (call $write (global.get $fd) (i32.const 1064) (i32.const 11)) (i32.const 0))))
+
+    ;; 8: reopen for reading
+    (call $record (i32.const 8)
+      (i32.eqz (call $open (i32.const 1056) (i32.const 7) (i32.const 0))))
+    (global.set $fd (i32.load (i32.const 72)))
+
+    ;; 9: the first five bytes are "hello"
+    (i32.store (i32.const 64) (i32.const 256))
+    (i32.store (i32.const 68) (i32.const 5))
+    (call $record (i32.const 9)
+      (i32.and
+        (i32.eqz (call $fd_read (global.get $fd) (i32.const 64) (i32.const 1) (i32.const 72)))
+        (i32.and
+          (i32.eq (i32.load (i32.const 72)) (i32.const 5))
+          (i32.and
+            (i32.eq (i32.load (i32.const 256)) (i32.const 0x6c6c6568))
+            (i32.eq (i32.load8_u (i32.const 260)) (i32.const 0x6f))))))
+
+    ;; 10: SEEK_CUR, SEEK_SET past the end (allowed, as with lseek), SEEK_END
+    (call $record (i32.const 10)
+      (i32.and
+        (i32.and
+          (i32.eqz (call $fd_seek (global.get $fd) (i64.const 0) (i32.const 1) (i32.const 80)))
+          (i64.eq (i64.load (i32.const 80)) (i64.const 5)))
+        (i32.and
+          (i32.and
+            (i32.eqz (call $fd_seek (global.get $fd) (i64.const 100) (i32.const 0) (i32.const 80)))
+            (i64.eq (i64.load (i32.const 80)) (i64.const 100)))
+          (i32.and
+            (i32.eqz (call $fd_seek (global.get $fd) (i64.const -5) (i32.const 2) (i32.const 80)))
+            (i64.eq (i64.load (i32.const 80)) (i64.const 6))))))
+
+    ;; 11
+    (i64.store (i32.const 80) (i64.const -1))
+    (call $record (i32.const 11)
+      (i32.and
+        (i32.eqz (call $fd_tell (global.get $fd) (i32.const 80)))
+        (i64.eq (i64.load (i32.const 80)) (i64.const 6))))
+
+    ;; 12: regular file of 11 bytes (filetype at 16, size at 32)
+    (call $record (i32.const 12)
+      (i32.and
+        (i32.eqz (call $fd_filestat_get (global.get $fd) (i32.const 128)))
+        (i32.and
+          (i32.eq (i32.load8_u (i32.const 144)) (i32.const 4))
+          (i64.eq (i64.load (i32.const 160)) (i64.const 11)))))
+    (drop (call $fd_close (global.get $fd)))
+
+    ;; 13: same layout as fd_filestat_get
+    (call $record (i32.const 13)
+      (i32.and
+        (i32.eqz (call $stat (i32.const 1056) (i32.const 7)))
+        (i32.and
+          (i32.eq (i32.load8_u (i32.const 144)) (i32.const 4))
+          (i64.eq (i64.load (i32.const 160)) (i64.const 11)))))
+
+    ;; 14: creating it twice is EEXIST
+    (call $record (i32.const 14)
+      (i32.and
+        (i32.eqz (call $path_create_directory (i32.const 3) (i32.const 1080) (i32.const 6)))
+        (i32.eq (call $path_create_directory (i32.const 3) (i32.const 1080) (i32.const 6)) (i32.const 20))))
+
+    ;; 15: st_dir holds only "a"; the first dirent has d_namlen 1 and the
+    ;; name follows its 24-byte header
+    (drop (call $open (i32.const 1088) (i32.const 8) (i32.const 1)))
+    (drop (call $fd_close (i32.load (i32.const 72))))
+    (drop (call $open (i32.const 1080) (i32.const 6) (i32.const 2)))
+    (global.set $fd (i32.load (i32.const 72)))
+    (call $record (i32.const 15)
+      (i32.and
+        (i32.eqz (call $fd_readdir (global.get $fd) (i32.const 256) (i32.const 256) (i64.const 0) (i32.const 72)))
+        (i32.and
+          (i32.ge_u (i32.load (i32.const 72)) (i32.const 25))
+          (i32.and
+            (i32.eq (i32.load (i32.const 272)) (i32.const 1))
+            (i32.eq (i32.load8_u (i32.const 280)) (i32.const 0x61))))))
+    (drop (call $fd_close (global.get $fd)))
+
+    ;; 16: unlinking it twice is ENOENT
+    (call $record (i32.const 16)
+      (i32.and
+        (i32.eqz (call $path_unlink_file (i32.const 3) (i32.const 1088) (i32.const 8)))
+        (i32.eq (call $path_unlink_file (i32.const 3) (i32.const 1088) (i32.const 8)) (i32.const 2))))
+
+    ;; 17
+    (call $record (i32.const 17)
+      (i32.and
+        (i32.eqz (call $path_remove_directory (i32.const 3) (i32.const 1080) (i32.const 6)))
+        (i32.eq (call $stat (i32.const 1080) (i32.const 6)) (i32.const 2))))
+
+    ;; 18: the new name exists and the old one does not
+    (call $record (i32.const 18)
+      (i32.and
+        (i32.eqz (call $path_rename (i32.const 3) (i32.const 1056) (i32.const 7)
+                                    (i32.const 3) (i32.const 1104) (i32.const 10)))
+        (i32.and
+          (i32.eqz (call $stat (i32.const 1104) (i32.const 10)))
+          (i32.eq (call $stat (i32.const 1056) (i32.const 7)) (i32.const 2)))))
+
+    ;; 19
+    (call $record (i32.const 19)
+      (i32.and
+        (i32.eqz (call $clock_res_get (i32.const 1) (i32.const 80)))
+        (i64.gt_u (i64.load (i32.const 80)) (i64.const 0))))
+
+    ;; 20: the monotonic clock does not go backwards
+    (call $record (i32.const 20)
+      (i32.and
+        (i32.and
+          (i32.eqz (call $clock_time_get (i32.const 1) (i64.const 1) (i32.const 740)))
+          (i32.eqz (call $clock_time_get (i32.const 1) (i64.const 1) (i32.const 748))))
+        (i64.ge_u (i64.load (i32.const 748)) (i64.load (i32.const 740)))))
+
+    ;; 21: a 1ms clock subscription fires once, the clock has advanced by at
+    ;; least 1ms, and nevents is a u32 (the word after it is left alone)
+    (i64.store (i32.const 512) (i64.const 0x5e1f))   ;; userdata
+    (i32.store8 (i32.const 520) (i32.const 0))       ;; eventtype clock
+    (i32.store (i32.const 528) (i32.const 1))        ;; monotonic
+    (i64.store (i32.const 536) (i64.const 1000000))  ;; timeout
+    (i32.store (i32.const 612) (i32.const 0xa5a5a5a5))
+    (call $record (i32.const 21)
+      (i32.and
+        (i32.and
+          (i32.eqz (call $poll_oneoff (i32.const 512) (i32.const 576) (i32.const 1) (i32.const 608)))
+          (i32.eq (i32.load (i32.const 608)) (i32.const 1)))
+        (i32.and
+          (i32.and
+            (i64.eq (i64.load (i32.const 576)) (i64.const 0x5e1f))
+            (i32.eqz (i32.load16_u (i32.const 584))))
+          (i32.and
+            (i32.eq (i32.load (i32.const 612)) (i32.const 0xa5a5a5a5))
+            (i32.and
+              (i32.eqz (call $clock_time_get (i32.const 1) (i64.const 1) (i32.const 748)))
+              (i64.ge_u (i64.load (i32.const 748))
+                        (i64.add (i64.load (i32.const 740)) (i64.const 1000000))))))))
+
+    ;; 22: 32 zeroed bytes come back with something in them
+    (call $record (i32.const 22)
+      (i32.and
+        (i32.eqz (call $random_get (i32.const 704) (i32.const 32)))
+        (i64.ne
+          (i64.or
+            (i64.or (i64.load (i32.const 704)) (i64.load (i32.const 712)))
+            (i64.or (i64.load (i32.const 720)) (i64.load (i32.const 728))))
+          (i64.const 0))))
+
+    ;; 23: AF_INET stream socket
+    (call $record (i32.const 23)
+      (i32.eqz (call $sock_open (i32.const 1) (i32.const 1) (i32.const 0) (i32.const 72))))
+    (global.set $sock (i32.load (i32.const 72)))
+
+    ;; 24: 127.0.0.1:8080
+    (i32.store16 (i32.const 640) (i32.const 1))
+    (i32.store16 (i32.const 642) (i32.const 0x901f))
+    (i32.store (i32.const 644) (i32.const 0x0100007f))
+    (call $record (i32.const 24)
+      (i32.eqz (call $sock_bind (global.get $sock) (i32.const 640) (i32.const 16))))
+
+    ;; 25: reports the bound address
+    (i32.store (i32.const 660) (i32.const 16))
+    (call $record (i32.const 25)
+      (i32.and
+        (i32.eqz (call $sock_getsockname (global.get $sock) (i32.const 680) (i32.const 660)))
+        (i32.and
+          (i32.eq (i32.load (i32.const 660)) (i32.const 16))
+          (i32.and
+            (i32.eq (i32.load16_u (i32.const 682)) (i32.const 0x901f))
+            (i32.eq (i32.load (i32.const 684)) (i32.const 0x0100007f))))))
+
+    ;; 26: never connected, so ENOTCONN
+    (i32.store (i32.const 660) (i32.const 16))
+    (call $record (i32.const 26)
+      (i32.eq (call $sock_getpeername (global.get $sock) (i32.const 680) (i32.const 660)) (i32.const 53)))
+
+    ;; 27
+    (call $record (i32.const 27)
+      (i32.eqz (call $sock_close (global.get $sock))))
+
+    ;; 28: EBADF
+    (call $record (i32.const 28)
+      (i32.eq (call $fd_close (i32.const 1000)) (i32.const 8)))
+
+    ;; 29: two sockets get consecutive fds; once the first is closed, the
+    ;; next socket reuses its fd rather than a higher one
+    (drop (call $sock_open (i32.const 1) (i32.const 1) (i32.const 0) (i32.const 72)))
+    (global.set $sock (i32.load (i32.const 72)))
+    (drop (call $sock_open (i32.const 1) (i32.const 1) (i32.const 0) (i32.const 76)))
+    (drop (call $sock_close (global.get $sock)))
+    (i32.store (i32.const 72) (i32.const -1))
+    (call $record (i32.const 29)
+      (i32.and
+        (i32.eqz (call $sock_open (i32.const 1) (i32.const 1) (i32.const 0) (i32.const 72)))
+        (i32.and
+          (i32.eq (i32.load (i32.const 76)) (i32.add (global.get $sock) (i32.const 1)))
+          (i32.eq (i32.load (i32.const 72)) (global.get $sock)))))
+    (drop (call $sock_close (i32.load (i32.const 72))))
+    (drop (call $sock_close (i32.load (i32.const 76)))))
+)
@@ -35,14 +35,22 @@ int sock_shutdown(int sock_fd, int how);
 
 #define BUF_SIZE 4096
 #define MAX_PATH 256
+// Most files a SYNC manifest may list (MAX_MANIFEST_ENTRIES in dircopy_protocol.rs)
+#define MAX_ENTRIES 1024
+// A manifest line: size, CRC-32 and a path of up to MAX_PATH - 1 bytes
+#define MAX_LINE (MAX_PATH + 32)
 
 int copy_file(const char* src, const char* dst);
 int copy_dir(const char* src, const char* dst);
 void handle_client(int client_fd);
+void handle_sync(int client_fd, const char* args);
+
+// Paths of the manifest's files the server already has, answered with HAVE
+static char have_paths[MAX_ENTRIES][MAX_PATH];
 
 int main() {
     int server_fd, client_fd, ret;
-    ret = sock_open(2, 1, 0, &server_fd); // AF_INET=2, SOCK_STREAM=1
+    ret = sock_open(1, 1, 0, &server_fd); // AF_INET=1, SOCK_STREAM=1
     if (ret != 0) {
         printf("Failed to open socket\n");
         return 1;
@@ -78,7 +86,10 @@ void handle_client(int client_fd) {
         }
         received++;
     }
-    if (strncmp(cmd_buf, "COPY ", 5) == 0) {
+    cmd_buf[received] = 0;
+    if (strncmp(cmd_buf, "SYNC ", 5) == 0) {
+        handle_sync(client_fd, cmd_buf + 5);
+    } else if (strncmp(cmd_buf, "COPY ", 5) == 0) {
         char src[MAX_PATH], dst[MAX_PATH];
         if (sscanf(cmd_buf+5, "%255s %255s", src, dst) != 2) {
             char err[] = "ERR Invalid arguments\n";
@@ -146,4 +157,182 @@ int copy_file(const char* src, const char* dst) {
     close(src_fd);
     close(dst_fd);
     return 0;
-} 
\ No newline at end of file
+} 
+
+// Sends all `len` bytes of `buf`, logging a failure
+void send_all(int client_fd, const char* buf, int len) {
+    int bytes_sent;
+    int ret = sock_send(client_fd, buf, len, 0, &bytes_sent);
+    if (ret != 0 || bytes_sent != len) {
+        printf("[SERVER] Failed to send response (ret=%d, bytes=%d)\n", ret, bytes_sent);
+        fflush(stdout);
+    }
+}
+
+void send_str(int client_fd, const char* str) {
+    send_all(client_fd, str, strlen(str));
+}
+
+// Receives exactly `len` bytes into `buf`. Returns 0, or -1 if the client
+// disconnected first.
+int recv_exact(int client_fd, char* buf, int len) {
+    int total_received = 0;
+    while (total_received < len) {
+        int bytes_received;
+        int ret = sock_recv(client_fd, buf + total_received, len - total_received, 0, &bytes_received, NULL);
+        if (ret != 0 || bytes_received == 0) {
+            printf("[SERVER] Client disconnected mid-copy (ret=%d)\n", ret);
+            fflush(stdout);
+            return -1;
+        }
+        total_received += bytes_received;
+    }
+    return 0;
+}
+
+// Receives one line into `buf`, without its '\n'. Returns 0, or -1 if the
+// client disconnected or the line does not fit in `max` bytes.
+int recv_line(int client_fd, char* buf, int max) {
+    for (int len = 0; len < max; len++) {
+        if (recv_exact(client_fd, buf + len, 1) != 0) return -1;
+        if (buf[len] == '\n') {
+            buf[len] = 0;
+            return 0;
+        }
+    }
+    printf("[SERVER] Line longer than %d bytes\n", max - 1);
+    fflush(stdout);
+    return -1;
+}
+
+// Whether `path` is relative and stays below the directory it is joined to
+int safe_path(const char* path) {
+    if (path[0] == 0 || path[0] == '/' || strlen(path) >= MAX_PATH) return 0;
+    const char* part = path;
+    while (1) {
+        const char* end = strchr(part, '/');
+        int len = end ? end - part : (int)strlen(part);
+        if (len == 2 && strncmp(part, "..", 2) == 0) return 0;
+        if (!end) return 1;
+        part = end + 1;
+    }
+}
+
+// Creates the directories above `path` that do not exist yet
+void make_parents(char* path) {
+    for (char* slash = strchr(path + 1, '/'); slash; slash = strchr(slash + 1, '/')) {
+        *slash = 0;
+        mkdir(path, 0777);
+        *slash = '/';
+    }
+}
+
+// The size and CRC-32 (IEEE, as crc32fast computes it) of the file at
+// `path`. Returns 0, or -1 if it cannot be read.
+int file_crc32(const char* path, uint64_t* size_out, uint32_t* crc_out) {
+    int fd = open(path, O_RDONLY);
+    if (fd < 0) return -1;
+    unsigned char buf[BUF_SIZE];
+    uint32_t crc = 0xFFFFFFFF;
+    uint64_t size = 0;
+    ssize_t nread;
+    while ((nread = read(fd, buf, BUF_SIZE)) > 0) {
+        for (ssize_t i = 0; i < nread; i++) {
+            crc ^= buf[i];
+            for (int bit = 0; bit < 8; bit++) {
+                crc = (crc >> 1) ^ (0xEDB88320 & -(crc & 1));
+            }
+        }
+        size += nread;
+    }
+    close(fd);
+    if (nread < 0) return -1;
+    *size_out = size;
+    *crc_out = ~crc;
+    return 0;
+}
+
+// Receives `size` bytes from the client into a new file at `path`
+int receive_file(int client_fd, const char* path, uint32_t size) {
+    int fd = open(path, O_WRONLY | O_CREAT | O_TRUNC, 0666);
+    if (fd < 0) return -1;
+    char buf[BUF_SIZE];
+    while (size > 0) {
+        int chunk = size > BUF_SIZE ? BUF_SIZE : size;
+        if (recv_exact(client_fd, buf, chunk) != 0 || write(fd, buf, chunk) != chunk) {
+            close(fd);
+            return -1;
+        }
+        size -= chunk;
+    }
+    close(fd);
+    return 0;
+}
+
+// Copies a tree from the client into the directory named in `args`, as
+// consensus/src/clients/dircopy_protocol.rs describes: answers its manifest
+// with the files already here, then receives the others.
+void handle_sync(int client_fd, const char* args) {
+    char dst[MAX_PATH], line[MAX_LINE], full[2 * MAX_PATH + 2];
+    int count;
+    if (sscanf(args, "%255s %d", dst, &count) != 2 || count < 0 || count > MAX_ENTRIES) {
+        send_str(client_fd, "ERR Invalid SYNC header\n");
+        return;
+    }
+    if (!safe_path(dst)) {
+        send_str(client_fd, "ERR Invalid destination\n");
+        return;
+    }
+    mkdir(dst, 0777);
+    int have = 0;
+    for (int i = 0; i < count; i++) {
+        unsigned long long size;
+        unsigned int crc;
+        int offset = 0;
+        if (recv_line(client_fd, line, sizeof(line)) != 0) return;
+        if (sscanf(line, "%llu %8x %n", &size, &crc, &offset) != 2 || offset == 0 || !safe_path(line + offset)) {
+            send_str(client_fd, "ERR Invalid manifest line\n");
+            return;
+        }
+        snprintf(full, sizeof(full), "%s/%s", dst, line + offset);
+        uint64_t have_size;
+        uint32_t have_crc;
+        if (file_crc32(full, &have_size, &have_crc) == 0 && have_size == size && have_crc == crc) {
+            strcpy(have_paths[have++], line + offset);
+        }
+    }
+    printf("[SERVER] Already have %d of %d files in %s\n", have, count, dst);
+    fflush(stdout);
+    snprintf(line, sizeof(line), "HAVE %d\n", have);
+    send_str(client_fd, line);
+    for (int i = 0; i < have; i++) {
+        snprintf(line, sizeof(line), "%s\n", have_paths[i]);
+        send_str(client_fd, line);
+    }
+
+    int received = 0;
+    while (1) {
+        if (recv_line(client_fd, line, sizeof(line)) != 0) return;
+        if (strcmp(line, "DONE") == 0) break;
+        if (strncmp(line, "SEND ", 5) != 0 || !safe_path(line + 5)) {
+            send_str(client_fd, "ERR Expected SEND or DONE\n");
+            return;
+        }
+        unsigned char size_buf[4];
+        if (recv_exact(client_fd, (char*)size_buf, 4) != 0) return;
+        uint32_t size = (uint32_t)size_buf[0] << 24 | size_buf[1] << 16 | size_buf[2] << 8 | size_buf[3];
+        snprintf(full, sizeof(full), "%s/%s", dst, line + 5);
+        make_parents(full);
+        if (receive_file(client_fd, full, size) != 0) {
+            char response[MAX_LINE + 32];
+            snprintf(response, sizeof(response), "ERR Could not write %s\n", line + 5);
+            send_str(client_fd, response);
+            return;
+        }
+        printf("[SERVER] Received %s (%u bytes)\n", full, size);
+        fflush(stdout);
+        received++;
+    }
+    snprintf(line, sizeof(line), "OK %d\n", received);
+    send_str(client_fd, line);
+}
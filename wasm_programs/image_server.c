@@ -370,7 +370,7 @@ int main() {
     start_time = get_timestamp_ms();
     
     // Open a socket
-    ret = sock_open(2, 1, 0, &server_fd); // AF_INET=2, SOCK_STREAM=1
+    ret = sock_open(1, 1, 0, &server_fd); // AF_INET=1, SOCK_STREAM=1
     if (ret != 0) {
         printf("[SERVER] Failed to open socket (ret=%d)\n", ret);
         return 1;
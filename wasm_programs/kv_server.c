@@ -42,10 +42,13 @@ int sock_close(int sock_fd);
 #define MAX_CMD_SIZE 1024
 #define KV_DIR "/tmp/"
 
-// Simple in-memory key-value store (fixed size)
+// Simple in-memory key-value store (fixed size). Values are byte strings of
+// value_lens[i] bytes, as the protocol carries them (see
+// consensus/src/clients/kv_protocol.rs): they may hold spaces and newlines.
 #define MAX_ENTRIES 100
 char keys[MAX_ENTRIES][MAX_KEY];
 char values[MAX_ENTRIES][MAX_VAL];
+int value_lens[MAX_ENTRIES];
 int num_entries = 0;
 
 // Helper function to trim whitespace and newlines from the end of a string
@@ -58,16 +61,16 @@ void trim_end(char *str) {
 }
 
 // In-memory implementation
-int set_key(const char* key, const char* value) {
-    printf("[SERVER] Setting key '%s' to value '%s'\n", key, value);
+int set_key(const char* key, const char* value, int len) {
+    printf("[SERVER] Setting key '%s' to value '%.*s'\n", key, len, value);
     fflush(stdout);
     
     // Check if key already exists
     for (int i = 0; i < num_entries; i++) {
         if (strcmp(keys[i], key) == 0) {
             // Update existing value
-            strncpy(values[i], value, MAX_VAL-1);
-            values[i][MAX_VAL-1] = '\0';
+            memcpy(values[i], value, len);
+            value_lens[i] = len;
             printf("[SERVER] Updated existing key '%s'\n", key);
             fflush(stdout);
             return 0;
@@ -79,8 +82,8 @@ int set_key(const char* key, const char* value) {
         strncpy(keys[num_entries], key, MAX_KEY-1);
         keys[num_entries][MAX_KEY-1] = '\0';
         
-        strncpy(values[num_entries], value, MAX_VAL-1);
-        values[num_entries][MAX_VAL-1] = '\0';
+        memcpy(values[num_entries], value, len);
+        value_lens[num_entries] = len;
         
         num_entries++;
         printf("[SERVER] Added new key '%s', total entries: %d\n", key, num_entries);
@@ -93,17 +96,17 @@ int set_key(const char* key, const char* value) {
     return -1;
 }
 
-int get_key(const char* key, char* value_out, int maxlen) {
+// Returns the length of the value of `key`, or -1 if it is not set
+int get_key(const char* key, char* value_out) {
     printf("[SERVER] Getting value for key '%s'\n", key);
     fflush(stdout);
     
     for (int i = 0; i < num_entries; i++) {
         if (strcmp(keys[i], key) == 0) {
-            strncpy(value_out, values[i], maxlen-1);
-            value_out[maxlen-1] = '\0';
-            printf("[SERVER] Found key '%s', value: '%s'\n", key, value_out);
+            memcpy(value_out, values[i], value_lens[i]);
+            printf("[SERVER] Found key '%s', value: '%.*s'\n", key, value_lens[i], value_out);
             fflush(stdout);
-            return 0;
+            return value_lens[i];
         }
     }
     
@@ -121,7 +124,8 @@ int del_key(const char* key) {
             // Move last entry to this position (if not the last one)
             if (i < num_entries - 1) {
                 strcpy(keys[i], keys[num_entries-1]);
-                strcpy(values[i], values[num_entries-1]);
+                memcpy(values[i], values[num_entries-1], value_lens[num_entries-1]);
+                value_lens[i] = value_lens[num_entries-1];
             }
             num_entries--;
             printf("[SERVER] Deleted key '%s', remaining entries: %d\n", key, num_entries);
@@ -135,16 +139,43 @@ int del_key(const char* key) {
     return -1;
 }
 
+// Sends all `len` bytes of `buf`, logging a failure
+void send_all(int client_fd, const char* buf, int len) {
+    int bytes_sent;
+    int ret = sock_send(client_fd, buf, len, 0, &bytes_sent);
+    if (ret != 0 || bytes_sent != len) {
+        printf("[SERVER] Failed to send response (ret=%d, bytes=%d)\n", ret, bytes_sent);
+        fflush(stdout);
+    }
+}
+
+// Receives exactly `len` bytes into `buf`. Returns 0, or -1 if the client
+// disconnected first.
+int recv_exact(int client_fd, char* buf, int len) {
+    int total_received = 0;
+    while (total_received < len) {
+        int bytes_received;
+        int ret = sock_recv(client_fd, buf + total_received, len - total_received, 0, &bytes_received, NULL);
+        if (ret != 0 || bytes_received == 0) {
+            printf("[SERVER] Client disconnected before sending the value (ret=%d)\n", ret);
+            fflush(stdout);
+            return -1;
+        }
+        total_received += bytes_received;
+    }
+    return 0;
+}
+
 void handle_client(int client_fd) {
     char cmd_buf[MAX_CMD_SIZE];
     int bytes_received;
-    int bytes_sent;
     int ret;
     
     printf("[SERVER] New client connection on fd %d\n", client_fd);
     fflush(stdout);
     
-    // Receive command - character by character until we hit a newline
+    // Receive the header line - character by character until we hit a
+    // newline, so a SET value after it stays unread
     int total_received = 0;
     while (total_received < MAX_CMD_SIZE - 1) {
         ret = sock_recv(client_fd, cmd_buf + total_received, 1, 0, &bytes_received, NULL);
@@ -167,6 +198,7 @@ void handle_client(int client_fd) {
     cmd_buf[total_received] = '\0';
     printf("[SERVER] Received command (%d bytes): %s", total_received, cmd_buf);
     fflush(stdout);
+    trim_end(cmd_buf);
 
     // Process the command using strtok like image_server
     char* cmd = strtok(cmd_buf, " ");
@@ -176,142 +208,71 @@ void handle_client(int client_fd) {
         sock_close(client_fd);
         return;
     }
+    char* key = strtok(NULL, " ");
 
     if (strcasecmp(cmd, "SET") == 0) {
-        char* key = strtok(NULL, " ");
-        if (key == NULL) {
-            printf("[SERVER] Missing key for SET command\n");
-            fflush(stdout);
-            const char* response = "ERR: Missing key\n";
-            sock_send(client_fd, response, strlen(response), 0, &bytes_sent);
-            sock_shutdown(client_fd, 1);
-            sock_close(client_fd);
-            return;
-        }
-        
-        char* value = strtok(NULL, "\n");
-        if (value == NULL) {
-            printf("[SERVER] Missing value for SET command\n");
+        char* len_str = strtok(NULL, " ");
+        if (key == NULL || len_str == NULL) {
+            printf("[SERVER] Missing key or value length for SET command\n");
             fflush(stdout);
-            const char* response = "ERR: Missing value\n";
-            sock_send(client_fd, response, strlen(response), 0, &bytes_sent);
-            sock_shutdown(client_fd, 1);
-            sock_close(client_fd);
-            return;
-        }
-        
-        // Trim whitespace
-        trim_end(key);
-        // Skip leading whitespace in value
-        while (*value == ' ') value++;
-        trim_end(value);
-        
-        printf("[SERVER] Processing SET request for key: '%s' value: '%s'\n", key, value);
-        fflush(stdout);
-        
-        int result = set_key(key, value);
-        
-        if (result == 0) {
-            const char* response = "OK\n";
-            ret = sock_send(client_fd, response, strlen(response), 0, &bytes_sent);
-            if (ret != 0 || bytes_sent != strlen(response)) {
-                printf("[SERVER] Failed to send response (ret=%d, bytes=%d)\n", ret, bytes_sent);
-                fflush(stdout);
-            }
+            const char* response = "ERR missing key or value length\n";
+            send_all(client_fd, response, strlen(response));
         } else {
-            const char* response = "ERR 1\n";
-            ret = sock_send(client_fd, response, strlen(response), 0, &bytes_sent);
-            if (ret != 0 || bytes_sent != strlen(response)) {
-                printf("[SERVER] Failed to send response (ret=%d, bytes=%d)\n", ret, bytes_sent);
+            int len = atoi(len_str);
+            char value[MAX_VAL];
+            if (len < 0 || len > MAX_VAL) {
+                printf("[SERVER] Value of %d bytes for key '%s' is too long\n", len, key);
+                fflush(stdout);
+                const char* response = "ERR value too long\n";
+                send_all(client_fd, response, strlen(response));
+            } else if (recv_exact(client_fd, value, len) == 0) {
+                printf("[SERVER] Processing SET request for key: '%s' (%d bytes)\n", key, len);
                 fflush(stdout);
+                const char* response = set_key(key, value, len) == 0 ? "OK\n" : "ERR store full\n";
+                send_all(client_fd, response, strlen(response));
             }
         }
     } 
     else if (strcasecmp(cmd, "GET") == 0) {
-        char* key = strtok(NULL, "\n");
+        char value[MAX_VAL];
+        int len = key == NULL ? -1 : get_key(key, value);
         if (key == NULL) {
             printf("[SERVER] Missing key for GET command\n");
             fflush(stdout);
-            const char* response = "ERR: Missing key\n";
-            sock_send(client_fd, response, strlen(response), 0, &bytes_sent);
-            sock_shutdown(client_fd, 1);
-            sock_close(client_fd);
-            return;
-        }
-        
-        trim_end(key);
-        char value[MAX_VAL];
-        
-        printf("[SERVER] Processing GET request for key: '%s'\n", key);
-        fflush(stdout);
-        
-        if (get_key(key, value, MAX_VAL) == 0) {
-            char response[MAX_VAL + 8];
-            snprintf(response, sizeof(response), "VALUE %s\n", value);
-            ret = sock_send(client_fd, response, strlen(response), 0, &bytes_sent);
-            if (ret != 0 || bytes_sent != strlen(response)) {
-                printf("[SERVER] Failed to send response (ret=%d, bytes=%d)\n", ret, bytes_sent);
-                fflush(stdout);
-            }
+            const char* response = "ERR missing key\n";
+            send_all(client_fd, response, strlen(response));
+        } else if (len >= 0) {
+            char header[32];
+            snprintf(header, sizeof(header), "VALUE %d\n", len);
+            send_all(client_fd, header, strlen(header));
+            send_all(client_fd, value, len);
         } else {
-            const char* response = "ERR: Key not found\n";
-            ret = sock_send(client_fd, response, strlen(response), 0, &bytes_sent);
-            if (ret != 0 || bytes_sent != strlen(response)) {
-                printf("[SERVER] Failed to send response (ret=%d, bytes=%d)\n", ret, bytes_sent);
-                fflush(stdout);
-            }
+            const char* response = "ERR key not found\n";
+            send_all(client_fd, response, strlen(response));
         }
     } 
     else if (strcasecmp(cmd, "DEL") == 0) {
-        char* key = strtok(NULL, "\n");
         if (key == NULL) {
             printf("[SERVER] Missing key for DEL command\n");
             fflush(stdout);
-            const char* response = "ERR: Missing key\n";
-            sock_send(client_fd, response, strlen(response), 0, &bytes_sent);
-            sock_shutdown(client_fd, 1);
-            sock_close(client_fd);
-            return;
-        }
-        
-        trim_end(key);
-        
-        printf("[SERVER] Processing DEL request for key: '%s'\n", key);
-        fflush(stdout);
-        
-        if (del_key(key) == 0) {
-            const char* response = "OK\n";
-            ret = sock_send(client_fd, response, strlen(response), 0, &bytes_sent);
-            if (ret != 0 || bytes_sent != strlen(response)) {
-                printf("[SERVER] Failed to send response (ret=%d, bytes=%d)\n", ret, bytes_sent);
-                fflush(stdout);
-            }
+            const char* response = "ERR missing key\n";
+            send_all(client_fd, response, strlen(response));
         } else {
-            const char* response = "ERR: Failed to delete\n";
-            ret = sock_send(client_fd, response, strlen(response), 0, &bytes_sent);
-            if (ret != 0 || bytes_sent != strlen(response)) {
-                printf("[SERVER] Failed to send response (ret=%d, bytes=%d)\n", ret, bytes_sent);
-                fflush(stdout);
-            }
+            printf("[SERVER] Processing DEL request for key: '%s'\n", key);
+            fflush(stdout);
+            const char* response = del_key(key) == 0 ? "OK\n" : "ERR key not found\n";
+            send_all(client_fd, response, strlen(response));
         }
     } 
     else if (strcasecmp(cmd, "QUIT") == 0) {
         const char* response = "BYE\n";
-        ret = sock_send(client_fd, response, strlen(response), 0, &bytes_sent);
-        if (ret != 0 || bytes_sent != strlen(response)) {
-            printf("[SERVER] Failed to send response (ret=%d, bytes=%d)\n", ret, bytes_sent);
-            fflush(stdout);
-        }
+        send_all(client_fd, response, strlen(response));
     } 
     else {
         printf("[SERVER] Unknown command: %s\n", cmd);
         fflush(stdout);
-        const char* response = "ERR: Unknown command\n";
-        ret = sock_send(client_fd, response, strlen(response), 0, &bytes_sent);
-        if (ret != 0 || bytes_sent != strlen(response)) {
-            printf("[SERVER] Failed to send response (ret=%d, bytes=%d)\n", ret, bytes_sent);
-            fflush(stdout);
-        }
+        const char* response = "ERR unknown command\n";
+        send_all(client_fd, response, strlen(response));
     }
     
     // Always properly shutdown and close client connection after sending response
@@ -338,8 +299,8 @@ int main() {
     fflush(stdout);
     
     // Initialize with some test data
-    set_key("test1", "value1");
-    set_key("test2", "value2");
+    set_key("test1", "value1", 6);
+    set_key("test2", "value2", 6);
     printf("[SERVER] Initialized with %d test entries\n", num_entries);
     fflush(stdout);
     
@@ -358,7 +319,7 @@ int main() {
         fflush(stdout);
     }
     
-    ret = sock_open(2, 1, 0, &server_fd); // AF_INET=2, SOCK_STREAM=1
+    ret = sock_open(1, 1, 0, &server_fd); // AF_INET=1, SOCK_STREAM=1
     if (ret != 0) {
         printf("[SERVER] Failed to open socket (ret=%d)\n", ret);
         return 1;
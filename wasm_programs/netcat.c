@@ -94,8 +94,8 @@ int main(int argc, char *argv[]) {
     int done = 0;
     FILE *output_file = NULL;
 
-    // Open a socket (AF_INET=2, SOCK_STREAM=1)
-    ret = sock_open(2, 1, 0, &sockfd);
+    // Open a socket (AF_INET=1, SOCK_STREAM=1)
+    ret = sock_open(1, 1, 0, &sockfd);
     if (ret != 0) {
         printf("Failed to open socket\n");
         return 1;
@@ -29,7 +29,7 @@ int main() {
     int bytes_sent;
     
     // Open a socket
-    ret = sock_open(2, 1, 0, &sock_fd); // AF_INET=2, SOCK_STREAM=1
+    ret = sock_open(1, 1, 0, &sock_fd); // AF_INET=1, SOCK_STREAM=1
     if (ret != 0) {
         printf("Failed to open socket\n");
         return 1;
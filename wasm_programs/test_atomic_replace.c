@@ -0,0 +1,53 @@
+#include <stdio.h>
+#include <string.h>
+
+extern int __builtin_rt_atomic_replace(const char* tmp_path, int tmp_len,
+                                       const char* final_path, int final_len);
+
+static int write_file(const char* path, const char* content) {
+    FILE* f = fopen(path, "w");
+    if (!f) {
+        return -1;
+    }
+    fputs(content, f);
+    return fclose(f);
+}
+
+// Writes the new version of a file to a temp file, then atomically replaces
+// the old version with it. The final file must hold the new content, and
+// the temp file must be gone. wasi_suite/atomic_replace.wat runs the same
+// steps in the conformance suite.
+int main(void) {
+    if (write_file("config.txt", "version 1\n") != 0 || write_file("config.txt.tmp", "version 2\n") != 0) {
+        printf("FAIL could not write the files\n");
+        return 1;
+    }
+
+    int ret = __builtin_rt_atomic_replace("config.txt.tmp", 14, "config.txt", 10);
+    if (ret != 0) {
+        printf("FAIL atomic replace returned %d\n", ret);
+        return 1;
+    }
+
+    char line[32] = {0};
+    FILE* f = fopen("config.txt", "r");
+    if (!f || !fgets(line, sizeof line, f) || strcmp(line, "version 2\n") != 0) {
+        printf("FAIL config.txt does not hold the new content: %s", line);
+        return 1;
+    }
+    fclose(f);
+    if (fopen("config.txt.tmp", "r") != NULL) {
+        printf("FAIL the temp file still exists\n");
+        return 1;
+    }
+
+    // Replacing again fails: the temp file has been consumed
+    ret = __builtin_rt_atomic_replace("config.txt.tmp", 14, "config.txt", 10);
+    if (ret != 2) {
+        printf("FAIL second replace returned %d, expected ENOENT\n", ret);
+        return 1;
+    }
+
+    printf("Atomic replace works\n");
+    return 0;
+}
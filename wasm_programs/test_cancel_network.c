@@ -0,0 +1,46 @@
+#include <stdio.h>
+#include <string.h>
+#include <netinet/in.h>
+#include <arpa/inet.h>
+
+// WASI socket functions
+__attribute__((import_module("wasi_snapshot_preview1")))
+__attribute__((import_name("sock_open")))
+int sock_open(int domain, int socktype, int protocol, int* sock_fd_out);
+
+__attribute__((import_module("wasi_snapshot_preview1")))
+__attribute__((import_name("sock_connect")))
+int sock_connect(int sock_fd, const struct sockaddr* addr, int addr_len);
+
+#define ECONNABORTED 13
+
+// Connects to an unroutable address and reports how the connect ended. Write
+//   init build/test_cancel_network.wasm
+//   clock 1000
+//   cancelnetwork 1
+// with `consensus benchmark` and replay it with `runtime benchmark`: the
+// connect is still pending when the CancelNetwork record arrives, so it must
+// fail with ECONNABORTED. A replay has no NAT table to answer the connect, so
+// with another `clock` instead of `cancelnetwork` it stays blocked.
+int main() {
+    int fd;
+    struct sockaddr_in addr;
+
+    if (sock_open(1, 1, 0, &fd) != 0) {
+        printf("Failed to open socket\n");
+        return 1;
+    }
+
+    memset(&addr, 0, sizeof(addr));
+    addr.sin_family = AF_INET;
+    addr.sin_port = htons(80);
+    inet_pton(AF_INET, "10.255.255.1", &addr.sin_addr);
+
+    int ret = sock_connect(fd, (struct sockaddr*)&addr, sizeof(addr));
+    if (ret == ECONNABORTED) {
+        printf("PASS: connect was cancelled (ECONNABORTED)\n");
+        return 0;
+    }
+    printf("FAIL: connect returned %d, expected %d (ECONNABORTED)\n", ret, ECONNABORTED);
+    return 1;
+}
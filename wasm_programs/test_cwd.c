@@ -0,0 +1,50 @@
+#include <stdio.h>
+#include <errno.h>
+#include <string.h>
+
+static int failures = 0;
+
+static void check(const char* what, int ok) {
+    if (ok) {
+        printf("PASS: %s\n", what);
+    } else {
+        printf("FAIL: %s (errno %d)\n", what, errno);
+        failures++;
+    }
+}
+
+// Must be started with a working directory:
+//   init build/test_cwd.wasm -w data
+// Relative paths resolve under data/, and leaving the sandbox from there is
+// still refused. test/cwd.py runs the same checks.
+int main() {
+    FILE* f = fopen("note.txt", "w");
+    check("create note.txt", f != NULL);
+    if (f) {
+        fputs("hello from data/\n", f);
+        fclose(f);
+    }
+
+    char line[64] = {0};
+    f = fopen("../data/note.txt", "r");
+    check("note.txt is data/note.txt", f != NULL && fgets(line, sizeof line, f) != NULL
+          && strcmp(line, "hello from data/\n") == 0);
+    if (f) {
+        fclose(f);
+    }
+
+    errno = 0;
+    f = fopen("../note.txt", "r");
+    check("note.txt is not in the sandbox root", f == NULL);
+
+    errno = 0;
+    f = fopen("../../outside.txt", "w");
+    check("escaping the sandbox from data/ is refused", f == NULL);
+
+    if (failures == 0) {
+        printf("All paths resolved under the working directory\n");
+        return 0;
+    }
+    printf("%d checks failed\n", failures);
+    return 1;
+}
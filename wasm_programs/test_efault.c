@@ -0,0 +1,58 @@
+#include <stdio.h>
+#include <stdint.h>
+
+// Raw WASI imports, so the out-of-bounds pointers reach the runtime unchanged
+__attribute__((import_module("wasi_snapshot_preview1")))
+__attribute__((import_name("fd_write")))
+int raw_fd_write(int fd, const void* iovs, int iovs_len, int* nwritten);
+
+__attribute__((import_module("wasi_snapshot_preview1")))
+__attribute__((import_name("fd_read")))
+int raw_fd_read(int fd, const void* iovs, int iovs_len, int* nread);
+
+__attribute__((import_module("wasi_snapshot_preview1")))
+__attribute__((import_name("clock_time_get")))
+int raw_clock_time_get(int clock_id, long long precision, void* time);
+
+__attribute__((import_module("wasi_snapshot_preview1")))
+__attribute__((import_name("args_sizes_get")))
+int raw_args_sizes_get(void* argc, void* argv_buf_size);
+
+#define WASI_EFAULT 21
+#define BAD_PTR ((void*)0xFFFFFFF0u)
+
+static int failures = 0;
+
+static void expect_efault(const char* name, int ret) {
+    if (ret == WASI_EFAULT) {
+        printf("PASS %s -> EFAULT\n", name);
+    } else {
+        printf("FAIL %s -> %d (expected %d)\n", name, ret, WASI_EFAULT);
+        failures++;
+    }
+}
+
+// Each syscall gets a pointer past the end of linear memory and must return
+// EFAULT instead of trapping the whole process. wasi_suite/efault.wat makes
+// the same calls, for the conformance run.
+int main() {
+    int n;
+    uint32_t iov[2];
+
+    expect_efault("fd_write iovs", raw_fd_write(1, BAD_PTR, 1, &n));
+
+    iov[0] = 0xFFFFFFF0u; // buf
+    iov[1] = 64;          // buf_len runs off the end of memory
+    expect_efault("fd_write buffer", raw_fd_write(1, iov, 1, &n));
+    expect_efault("fd_read buffer", raw_fd_read(0, iov, 1, &n));
+
+    iov[0] = 0;
+    iov[1] = 4;
+    expect_efault("fd_write nwritten", raw_fd_write(1, iov, 1, BAD_PTR));
+
+    expect_efault("clock_time_get", raw_clock_time_get(0, 1, BAD_PTR));
+    expect_efault("args_sizes_get", raw_args_sizes_get(BAD_PTR, &n));
+
+    printf("%s\n", failures == 0 ? "All EFAULT checks passed" : "Some EFAULT checks failed");
+    return failures == 0 ? 0 : 1;
+}
@@ -0,0 +1,52 @@
+#include <stdio.h>
+#include <fcntl.h>
+#include <unistd.h>
+#include <string.h>
+
+// Run with the file cache enabled: init build/test_file_cache.wasm -c 4096
+// Reopens the same file several times, then appends to it and checks that the
+// next open sees the new content instead of a stale cached copy.
+static int read_all(const char *path, char *buf, size_t len) {
+    int fd = open(path, O_RDONLY);
+    if (fd < 0) {
+        return -1;
+    }
+    ssize_t n = read(fd, buf, len - 1);
+    close(fd);
+    if (n < 0) {
+        return -1;
+    }
+    buf[n] = '\0';
+    return (int)n;
+}
+
+int main(void) {
+    char buf[256];
+    int fd = open("cache_test.txt", O_WRONLY | O_CREAT, 0666);
+    if (fd < 0 || write(fd, "v1", 2) != 2) {
+        printf("Failed to create cache_test.txt\n");
+        return 1;
+    }
+    close(fd);
+
+    for (int i = 0; i < 5; i++) {
+        if (read_all("cache_test.txt", buf, sizeof(buf)) < 0 || strcmp(buf, "v1") != 0) {
+            printf("FAIL: read %d returned \"%s\"\n", i, buf);
+            return 1;
+        }
+    }
+
+    fd = open("cache_test.txt", O_WRONLY | O_APPEND);
+    if (fd < 0 || write(fd, "v2", 2) != 2) {
+        printf("Failed to append to cache_test.txt\n");
+        return 1;
+    }
+    close(fd);
+
+    if (read_all("cache_test.txt", buf, sizeof(buf)) < 0 || strcmp(buf, "v1v2") != 0) {
+        printf("FAIL: stale read after write: \"%s\"\n", buf);
+        return 1;
+    }
+    printf("PASS: file cache invalidated after write\n");
+    return 0;
+}
@@ -0,0 +1,25 @@
+;; Yields once per command-line argument, then exits, so processes started
+;; together finish in a different order than their pids. Write both Inits
+;; into the same batch, e.g. with `consensus benchmark`:
+;;   init wasm_programs/test_finish_order.wat -a a b c d
+;;   init wasm_programs/test_finish_order.wat
+;;   init wasm_programs/test_finish_order.wat -a a b
+;; Process 2 finishes first and process 1 last, but the runtime still joins
+;; and cleans them up in pid order: it logs "Process 1 finished", then 2,
+;; then 3. test/finish_order.py checks its ProcessExit records come in that
+;; order too.
+(module
+  (import "wasi_snapshot_preview1" "args_sizes_get" (func $args_sizes_get (param i32 i32) (result i32)))
+  (import "env" "__builtin_rt_yield" (func $yield))
+  (memory (export "memory") 1)
+
+  (func (export "_start")
+    (local $n i32)
+    (drop (call $args_sizes_get (i32.const 0) (i32.const 4)))
+    (local.set $n (i32.load (i32.const 0)))
+    (block $done
+      (loop $again
+        (br_if $done (i32.eqz (local.get $n)))
+        (call $yield)
+        (local.set $n (i32.sub (local.get $n) (i32.const 1)))
+        (br $again)))))
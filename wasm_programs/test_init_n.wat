@@ -0,0 +1,18 @@
+;; A worker that prints one line and exits, to be launched as a pool from a
+;; single InitN record:
+;;   initn 10 wasm_programs/test_init_n.wat
+;; Expected: the runtime logs "Starting processes 1..=10 for InitN"
+;; (pids continue after any process started earlier), prints "worker" ten
+;; times, and logs "Process <pid> finished" once for each of the ten pids.
+;; In tcp mode consensus also logs the pid range every runtime assigned.
+;; test/init_n.py checks this.
+(module
+  (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+
+  ;; iovec { buf = 64, len = 7 }
+  (data (i32.const 0) "\40\00\00\00\07\00\00\00")
+  (data (i32.const 64) "worker\n")
+
+  (func (export "_start")
+    (drop (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 32)))))
@@ -0,0 +1,35 @@
+;; Opens a socket (FD 4) and a directory (FD 5), then waits for a line on
+;; stdin. FD updates aimed at anything but a readable file are refused with
+;; a reason. Start `runtime follow`, type
+;;   init wasm_programs/test_inject_mismatch.wat
+;; into `consensus benchmark`, and once the guest waits on stdin:
+;;   msg 1 fd:4,body:to-socket
+;;   msg 1 fd:5,body:to-directory
+;;   msg 1 fd:9,body:to-nothing
+;;   msg 1 fd:0,body:done
+;; Expected: the runtime logs that process 1's FD 4 is a socket (whose input
+;; comes from NetworkIn records instead), FD 5 is a directory and FD 9 is
+;; not open, and the guest prints "done". In tcp mode each refusal is also
+;; reported back to consensus, which logs it. test/inject_mismatch.py checks
+;; those reports.
+(module
+  (import "wasi_snapshot_preview1" "sock_open" (func $sock_open (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "path_open" (func $path_open (param i32 i32 i32 i32 i32 i64 i64 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_read" (func $fd_read (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+
+  (data (i32.const 100) ".")
+
+  (func (export "_start")
+    ;; IPv4 stream socket
+    (drop (call $sock_open (i32.const 1) (i32.const 1) (i32.const 0) (i32.const 200)))
+    ;; the working directory, O_DIRECTORY
+    (drop (call $path_open (i32.const 3) (i32.const 0) (i32.const 100) (i32.const 1)
+      (i32.const 2) (i64.const 0) (i64.const 0) (i32.const 0) (i32.const 204)))
+    ;; iovec { buf = 300, len = 64 }, read stdin and echo it
+    (i32.store (i32.const 0) (i32.const 300))
+    (i32.store (i32.const 4) (i32.const 64))
+    (drop (call $fd_read (i32.const 0) (i32.const 0) (i32.const 1) (i32.const 8)))
+    (i32.store (i32.const 4) (i32.load (i32.const 8)))
+    (drop (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 8)))))
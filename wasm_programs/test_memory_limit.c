@@ -0,0 +1,25 @@
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+// Allocates 1MB chunks until malloc fails. Start it with a small cap,
+//   init build/test_memory_limit.wasm -m 8388608
+// and the runtime should refuse the memory.grow past the cap, so malloc
+// returns NULL and the program reports how far it got and exits normally.
+// test/memory_limit.py runs the same steps as WAT, since it cannot build this.
+int main() {
+    size_t chunk = 1024 * 1024;
+    size_t total = 0;
+
+    for (;;) {
+        char* p = malloc(chunk);
+        if (p == NULL) {
+            printf("malloc returned NULL after %zu bytes\n", total);
+            return 0;
+        }
+        memset(p, 0xAB, chunk); // touch the pages so the memory is really used
+        total += chunk;
+        printf("Allocated %zu bytes\n", total);
+        fflush(stdout);
+    }
+}
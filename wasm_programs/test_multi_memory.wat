@@ -0,0 +1,23 @@
+;; Multi-memory guest: another memory is declared (and exported) before the
+;; one exported as "memory", which is the only one syscalls see. Pointers into
+;; the other could not be read, so Init must reject this module:
+;;   init wasm_programs/test_multi_memory.wat
+;; Expected: the Init fails with "module defines 2 memories; multi-memory is
+;; not supported" and nothing is printed.
+(module
+  (import "wasi_snapshot_preview1" "fd_write"
+    (func $fd_write (param i32 i32 i32 i32) (result i32)))
+
+  (memory $scratch 1)
+  (memory $main 1)
+  (export "scratch" (memory $scratch))
+  (export "memory" (memory $main))
+
+  ;; iovec { buf = 64, len = 48 } at offset 0 of the exported memory
+  (data (memory $main) (i32.const 0) "\40\00\00\00\30\00\00\00")
+  (data (memory $main) (i32.const 64) "multi-memory: syscalls used the exported memory\n")
+  ;; Garbage at the same offsets in the other memory, so reading the wrong one shows
+  (data (memory $scratch) (i32.const 0) "\ff\ff\ff\ff\ff\ff\ff\ff")
+
+  (func (export "_start")
+    (drop (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 32)))))
@@ -0,0 +1,70 @@
+#include <stdio.h>
+#include <stdint.h>
+
+// Raw WASI imports, so the pointer/length pairs reach the runtime unchanged
+__attribute__((import_module("wasi_snapshot_preview1")))
+__attribute__((import_name("fd_write")))
+int raw_fd_write(int fd, const void* iovs, int iovs_len, int* nwritten);
+
+__attribute__((import_module("wasi_snapshot_preview1")))
+__attribute__((import_name("fd_read")))
+int raw_fd_read(int fd, const void* iovs, int iovs_len, int* nread);
+
+__attribute__((import_module("wasi_snapshot_preview1")))
+__attribute__((import_name("path_filestat_get")))
+int raw_path_filestat_get(int fd, int flags, const void* path, int path_len, void* buf);
+
+__attribute__((import_module("wasi_snapshot_preview1")))
+__attribute__((import_name("sock_open")))
+int raw_sock_open(int domain, int socktype, int protocol, int* sock_fd_out);
+
+__attribute__((import_module("wasi_snapshot_preview1")))
+__attribute__((import_name("sock_send")))
+int raw_sock_send(int sock_fd, const void* si_data, int si_data_len, int si_flags, int* ret_data_len);
+
+#define WASI_EFAULT 21
+
+static int failures = 0;
+
+static void expect(const char* name, int ret, int want) {
+    if (ret == want) {
+        printf("PASS %s -> %d\n", name, ret);
+    } else {
+        printf("FAIL %s -> %d (expected %d)\n", name, ret, want);
+        failures++;
+    }
+}
+
+// Pointer/length pairs whose sum lies beyond (or wraps around) the 32-bit
+// address space must fail with EFAULT rather than trap or read out of bounds.
+// wasi_suite/overflow_ptrs.wat makes the same calls, for the conformance run.
+int main() {
+    int n;
+    int sock;
+    char stat[64];
+    uint32_t iov[4];
+
+    iov[0] = 0xFFFFFF00u; // buf
+    iov[1] = 0xFFFFFFFFu; // buf_len
+    expect("fd_write max len", raw_fd_write(1, iov, 1, &n), WASI_EFAULT);
+    expect("fd_read max len", raw_fd_read(0, iov, 1, &n), WASI_EFAULT);
+
+    // A valid iovec followed by one that wraps: nothing may be written
+    iov[0] = (uint32_t)(uintptr_t)"x";
+    iov[1] = 1;
+    iov[2] = 0xFFFFFFFFu;
+    iov[3] = 2;
+    expect("fd_write wrapping iovec", raw_fd_write(1, iov, 2, &n), WASI_EFAULT);
+
+    // iovs_len large enough that iovs_len * 8 overflows 32 bits
+    expect("fd_write iovs_len", raw_fd_write(1, iov, 0x20000001, &n), WASI_EFAULT);
+
+    expect("path_filestat_get path", raw_path_filestat_get(3, 0, (void*)0xFFFFFFFFu, -1, stat), WASI_EFAULT);
+
+    if (raw_sock_open(1, 1, 0, &sock) == 0) {
+        expect("sock_send data", raw_sock_send(sock, (void*)0xFFFFFFF0u, 0x20, 0, &n), WASI_EFAULT);
+    }
+
+    printf("%s\n", failures == 0 ? "All overflow checks passed" : "Some overflow checks failed");
+    return failures == 0 ? 0 : 1;
+}
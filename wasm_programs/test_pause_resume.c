@@ -0,0 +1,16 @@
+#include <stdio.h>
+#include <unistd.h>
+
+// Counts once per (deterministic) second. Run it under `consensus tcp`, then
+// type `pause <pid>` at the consensus prompt: the count must stop advancing
+// on every runtime even as the clock keeps ticking. After `resume <pid>` it
+// continues from where it stopped, without skipping or repeating a number.
+int main() {
+    for (int count = 1; count <= 120; count++) {
+        printf("count %d\n", count);
+        fflush(stdout);
+        sleep(1);
+    }
+    printf("Done counting\n");
+    return 0;
+}
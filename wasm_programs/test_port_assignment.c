@@ -0,0 +1,49 @@
+#include <stdio.h>
+#include <netinet/in.h>
+#include <arpa/inet.h>
+
+// WASI socket functions
+__attribute__((import_module("wasi_snapshot_preview1")))
+__attribute__((import_name("sock_open")))
+int sock_open(int domain, int socktype, int protocol, int* sock_fd_out);
+
+__attribute__((import_module("wasi_snapshot_preview1")))
+__attribute__((import_name("sock_getsockname")))
+int sock_getsockname(int sock_fd, struct sockaddr* addr, socklen_t* addr_len);
+
+__attribute__((import_module("wasi_snapshot_preview1")))
+__attribute__((import_name("sock_close")))
+int sock_close(int sock_fd);
+
+#define ROUNDS 4
+#define SOCKETS 8
+
+// Opens and closes sockets in a fixed pattern and prints the guest-visible
+// port of each one. Write
+//   init build/test_port_assignment.wasm -p 20000
+//   clock 1000
+// with `consensus benchmark`, replay it with `runtime benchmark` twice, and
+// diff the two outputs: every replica must assign the same ports, starting
+// at 20000 (or at 1 without -p). test/port_assignment.py does this.
+int main() {
+    int fds[SOCKETS];
+
+    for (int round = 0; round < ROUNDS; round++) {
+        for (int i = 0; i < SOCKETS; i++) {
+            struct sockaddr_in addr;
+            socklen_t len = sizeof(addr);
+
+            if (sock_open(1, 1, 0, &fds[i]) != 0 ||
+                sock_getsockname(fds[i], (struct sockaddr*)&addr, &len) != 0) {
+                printf("FAIL: socket %d of round %d\n", i, round);
+                return 1;
+            }
+            printf("round %d: fd %d has port %d\n", round, fds[i], ntohs(addr.sin_port));
+        }
+        // Close every other socket so the next round reuses some FDs
+        for (int i = 0; i < SOCKETS; i += 2) {
+            sock_close(fds[i]);
+        }
+    }
+    return 0;
+}
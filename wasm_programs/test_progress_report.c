@@ -0,0 +1,25 @@
+#include <stdio.h>
+#include <string.h>
+#include <unistd.h>
+
+extern int __builtin_rt_report(const char *ptr, int len);
+extern void __builtin_rt_yield(void);
+
+// Reports progress in 10% steps. While running in tcp mode the latest
+// report shows up under "progress" in the consensus /status page, which
+// test/progress_report.py checks.
+int main(void) {
+    char report[32];
+    for (int pct = 0; pct <= 100; pct += 10) {
+        int len = snprintf(report, sizeof(report), "processed %d%%", pct);
+        if (__builtin_rt_report(report, len) != 0) {
+            printf("Failed to report progress at %d%%\n", pct);
+            return 1;
+        }
+        printf("Reported: %s\n", report);
+        fflush(stdout);
+        sleep(1);
+        __builtin_rt_yield();
+    }
+    return 0;
+}
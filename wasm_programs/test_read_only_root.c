@@ -0,0 +1,58 @@
+#include <stdio.h>
+#include <errno.h>
+#include <fcntl.h>
+#include <unistd.h>
+#include <sys/stat.h>
+
+// The runtime reports EACCES with the same value as for sandbox escapes.
+#define RT_EACCES 13
+
+// Functions provided by the runtime
+int file_create(const char* path, int path_len, int* fd_out);
+
+static int failures = 0;
+
+static void expect_denied(const char* what, int ret) {
+    if (ret == -1 && errno == RT_EACCES) {
+        printf("PASS: %s denied\n", what);
+    } else {
+        printf("FAIL: %s returned %d (errno %d), expected EACCES\n", what, ret, errno);
+        failures++;
+    }
+}
+
+// Must be started with a read-only root:
+//   init build/test_read_only_root.wasm -r
+// Every operation that would modify the sandbox fails with EACCES, while
+// stdout (these lines) keeps working.
+int main() {
+    int fd;
+
+    errno = 0;
+    expect_denied("open(O_WRONLY | O_CREAT)", open("out.txt", O_WRONLY | O_CREAT, 0644));
+    errno = 0;
+    expect_denied("open(O_RDWR | O_TRUNC)", open("out.txt", O_RDWR | O_TRUNC));
+    errno = 0;
+    expect_denied("mkdir", mkdir("newdir", 0755));
+    errno = 0;
+    expect_denied("rmdir", rmdir("newdir"));
+    errno = 0;
+    expect_denied("unlink", unlink("out.txt"));
+    errno = 0;
+    expect_denied("rename", rename("out.txt", "moved.txt"));
+
+    int ret = file_create("created.txt", 11, &fd);
+    if (ret == RT_EACCES) {
+        printf("PASS: file_create denied\n");
+    } else {
+        printf("FAIL: file_create returned %d, expected EACCES\n", ret);
+        failures++;
+    }
+
+    if (failures == 0) {
+        printf("All write operations were denied\n");
+        return 0;
+    }
+    printf("%d write operations were not denied\n", failures);
+    return 1;
+}
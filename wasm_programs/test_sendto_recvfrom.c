@@ -0,0 +1,78 @@
+#include <stdio.h>
+#include <string.h>
+#include <netinet/in.h>
+#include <arpa/inet.h>
+
+// WASI socket functions
+__attribute__((import_module("wasi_snapshot_preview1")))
+__attribute__((import_name("sock_open")))
+int sock_open(int domain, int socktype, int protocol, int* sock_fd_out);
+
+__attribute__((import_module("wasi_snapshot_preview1")))
+__attribute__((import_name("sock_listen")))
+int sock_listen(int sock_fd, int backlog);
+
+__attribute__((import_module("wasi_snapshot_preview1")))
+__attribute__((import_name("sock_accept")))
+int sock_accept(int sock_fd, int flags, int* sock_fd_out);
+
+__attribute__((import_module("wasi_snapshot_preview1")))
+__attribute__((import_name("sock_send_to")))
+int sock_send_to(int sock_fd, const void* data, int data_len, int flags,
+                 const struct sockaddr* addr, int addr_len, int* sent_out);
+
+__attribute__((import_module("wasi_snapshot_preview1")))
+__attribute__((import_name("sock_recv_from")))
+int sock_recv_from(int sock_fd, void* buf, int buf_len, int flags,
+                   struct sockaddr* addr, socklen_t* addr_len,
+                   int* received_out, int* flags_out);
+
+// Accepts one connection and echoes each message back to the address
+// sock_recv_from reports it came from, e.g. after
+//   consensus netcat-client --host 127.0.0.1 --port <host port>
+// Every echoed line is prefixed with that address. Datagram sockets are not
+// emulated yet, so this exercises the connection-mode behaviour.
+int main() {
+    int server_fd;
+    int client_fd;
+
+    if (sock_open(1, 1, 0, &server_fd) != 0 || sock_listen(server_fd, 5) != 0) {
+        printf("Failed to set up listener\n");
+        return 1;
+    }
+    if (sock_accept(server_fd, 0, &client_fd) != 0) {
+        printf("Failed to accept connection\n");
+        return 1;
+    }
+
+    for (;;) {
+        char buf[256];
+        char reply[320];
+        char ip[INET_ADDRSTRLEN];
+        struct sockaddr_in from;
+        socklen_t from_len = sizeof(from);
+        int received;
+        int flags;
+        int sent;
+
+        if (sock_recv_from(client_fd, buf, sizeof(buf) - 1, 0, (struct sockaddr*)&from,
+                           &from_len, &received, &flags) != 0 || received == 0) {
+            break;
+        }
+        buf[received] = '\0';
+        if (from_len != sizeof(from)) {
+            printf("FAIL: no source address for %d bytes\n", received);
+            return 1;
+        }
+
+        inet_ntop(AF_INET, &from.sin_addr, ip, sizeof(ip));
+        int len = snprintf(reply, sizeof(reply), "%s:%d says %s", ip, ntohs(from.sin_port), buf);
+        if (sock_send_to(client_fd, reply, len, 0, (struct sockaddr*)&from, from_len, &sent) != 0) {
+            printf("FAIL: sock_send_to failed\n");
+            return 1;
+        }
+        printf("Echoed %d bytes to %s:%d\n", sent, ip, ntohs(from.sin_port));
+        fflush(stdout);
+    }
+    return 0;
+}
@@ -0,0 +1,20 @@
+#include <stdio.h>
+#include <stdint.h>
+
+extern uint64_t __builtin_rt_seq(void);
+
+// Prints ids built from the per-process sequence. Run it twice (or on two
+// replicas): both runs must print exactly the same lines, starting at 1.
+int main(void) {
+    for (int i = 0; i < 5; i++) {
+        uint64_t seq = __builtin_rt_seq();
+        printf("id req-%llu\n", (unsigned long long)seq);
+    }
+    uint64_t last = __builtin_rt_seq();
+    if (last != 6) {
+        printf("FAIL expected sequence value 6, got %llu\n", (unsigned long long)last);
+        return 1;
+    }
+    printf("Sequence is deterministic\n");
+    return 0;
+}
@@ -0,0 +1,44 @@
+#include <stdio.h>
+#include <string.h>
+#include <errno.h>
+#include <fcntl.h>
+#include <unistd.h>
+
+static char block[4096];
+
+// Writes a block that does not fit the disk quota, waits a (deterministic)
+// second, and writes it again. Write
+//   init build/test_set_quota.wasm -q 6000
+//   clock 1
+//   quota 1
+//   quota 1 1000000
+//   clock 2000000000
+// with `consensus benchmark` and replay it with `runtime benchmark`. The first
+// write fails with ENOSPC; the second batch reports the usage and raises the
+// quota, so the retry succeeds. The quota is set by `init`, since the guest
+// runs its first write before a `quota` record in the same batch is applied.
+// test/set_quota.py replays the same records.
+int main() {
+    memset(block, 'x', sizeof(block));
+
+    int fd = open("out.bin", O_WRONLY | O_CREAT, 0644);
+    if (fd < 0) {
+        printf("FAIL: open failed (errno %d)\n", errno);
+        return 1;
+    }
+
+    if (write(fd, block, sizeof(block)) >= 0) {
+        printf("FAIL: first write fit in the quota\n");
+        return 1;
+    }
+    printf("First write refused (errno %d)\n", errno);
+
+    sleep(1);
+    if (write(fd, block, sizeof(block)) != (ssize_t)sizeof(block)) {
+        printf("FAIL: write after raising the quota failed (errno %d)\n", errno);
+        return 1;
+    }
+    printf("PASS: write succeeded after the quota was raised\n");
+    close(fd);
+    return 0;
+}
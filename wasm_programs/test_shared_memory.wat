@@ -0,0 +1,9 @@
+;; Shared-memory guest (WebAssembly threads proposal). The runtime does not
+;; support threads, so Init must reject this module with a clear error instead
+;; of running it:
+;;   init wasm_programs/test_shared_memory.wat
+(module
+  (import "wasi_snapshot_preview1" "proc_exit" (func $proc_exit (param i32)))
+  (memory (export "memory") 1 1 shared)
+  (func (export "_start")
+    (call $proc_exit (i32.const 1))))
@@ -0,0 +1,65 @@
+#include <stdio.h>
+#include <string.h>
+#include <netinet/in.h>
+
+// WASI socket functions
+__attribute__((import_module("wasi_snapshot_preview1")))
+__attribute__((import_name("sock_open")))
+int sock_open(int domain, int socktype, int protocol, int* sock_fd_out);
+
+__attribute__((import_module("wasi_snapshot_preview1")))
+__attribute__((import_name("sock_bind")))
+int sock_bind(int sock_fd, const struct sockaddr* addr, int addr_len);
+
+__attribute__((import_module("wasi_snapshot_preview1")))
+__attribute__((import_name("sock_listen")))
+int sock_listen(int sock_fd, int backlog);
+
+__attribute__((import_module("wasi_snapshot_preview1")))
+__attribute__((import_name("sock_accept")))
+int sock_accept(int sock_fd, int flags, int* sock_fd_out);
+
+__attribute__((import_module("wasi_snapshot_preview1")))
+__attribute__((import_name("sock_send")))
+int sock_send(int sock_fd, const void* si_data, int si_data_len, int si_flags, int* ret_data_len);
+
+// Binds to a fixed port so an outside client can connect to it, e.g.
+//   consensus netcat-client --host 127.0.0.1 --port 8000
+// Start consensus with REPLICODE_BIND_ALLOW=127.0.0.1:8000 (loopback is allowed by default).
+int main() {
+    int server_fd;
+    int client_fd;
+    int sent;
+    struct sockaddr_in addr;
+
+    if (sock_open(1, 1, 0, &server_fd) != 0) {
+        printf("Failed to open socket\n");
+        return 1;
+    }
+
+    memset(&addr, 0, sizeof(addr));
+    addr.sin_family = AF_INET;
+    addr.sin_port = htons(8000);
+    addr.sin_addr.s_addr = htonl(INADDR_LOOPBACK);
+    if (sock_bind(server_fd, (struct sockaddr*)&addr, sizeof(addr)) != 0) {
+        printf("Failed to bind 127.0.0.1:8000\n");
+        return 1;
+    }
+
+    if (sock_listen(server_fd, 5) != 0) {
+        printf("Failed to listen (is 127.0.0.1:8000 in the allow-list?)\n");
+        return 1;
+    }
+    printf("Listening on 127.0.0.1:8000\n");
+    fflush(stdout);
+
+    if (sock_accept(server_fd, 0, &client_fd) != 0) {
+        printf("Failed to accept connection\n");
+        return 1;
+    }
+
+    const char* msg = "hello from a bound guest socket\n";
+    sock_send(client_fd, msg, strlen(msg), 0, &sent);
+    printf("Sent %d bytes to client\n", sent);
+    return 0;
+}
@@ -0,0 +1,80 @@
+#include <stdio.h>
+#include <string.h>
+#include <netinet/in.h>
+#include <arpa/inet.h>
+
+// WASI socket functions
+__attribute__((import_module("wasi_snapshot_preview1")))
+__attribute__((import_name("sock_open")))
+int sock_open(int domain, int socktype, int protocol, int* sock_fd_out);
+
+__attribute__((import_module("wasi_snapshot_preview1")))
+__attribute__((import_name("sock_listen")))
+int sock_listen(int sock_fd, int backlog);
+
+__attribute__((import_module("wasi_snapshot_preview1")))
+__attribute__((import_name("sock_accept")))
+int sock_accept(int sock_fd, int flags, int* sock_fd_out);
+
+__attribute__((import_module("wasi_snapshot_preview1")))
+__attribute__((import_name("sock_getsockname")))
+int sock_getsockname(int sock_fd, struct sockaddr* addr, socklen_t* addr_len);
+
+__attribute__((import_module("wasi_snapshot_preview1")))
+__attribute__((import_name("sock_getpeername")))
+int sock_getpeername(int sock_fd, struct sockaddr* addr, socklen_t* addr_len);
+
+static void print_addr(const char* label, struct sockaddr_in* addr) {
+    char ip[INET_ADDRSTRLEN];
+    inet_ntop(AF_INET, &addr->sin_addr, ip, sizeof(ip));
+    printf("%s %s:%d\n", label, ip, ntohs(addr->sin_port));
+}
+
+// Listens, accepts one connection and prints both ends of it. The peer is the
+// real client address as seen by consensus, e.g. after
+//   consensus netcat-client --host 127.0.0.1 --port <host port>
+int main() {
+    int server_fd;
+    int client_fd;
+    struct sockaddr_in addr;
+    socklen_t len;
+
+    if (sock_open(1, 1, 0, &server_fd) != 0 || sock_listen(server_fd, 5) != 0) {
+        printf("Failed to set up listener\n");
+        return 1;
+    }
+
+    len = sizeof(addr);
+    if (sock_getsockname(server_fd, (struct sockaddr*)&addr, &len) != 0) {
+        printf("getsockname failed on listener\n");
+        return 1;
+    }
+    print_addr("Listening on", &addr);
+
+    len = sizeof(addr);
+    if (sock_getpeername(server_fd, (struct sockaddr*)&addr, &len) == 0) {
+        printf("FAIL: listener unexpectedly has a peer\n");
+        return 1;
+    }
+    fflush(stdout);
+
+    if (sock_accept(server_fd, 0, &client_fd) != 0) {
+        printf("Failed to accept connection\n");
+        return 1;
+    }
+
+    len = sizeof(addr);
+    if (sock_getsockname(client_fd, (struct sockaddr*)&addr, &len) != 0) {
+        printf("getsockname failed on accepted socket\n");
+        return 1;
+    }
+    print_addr("Accepted on", &addr);
+
+    len = sizeof(addr);
+    if (sock_getpeername(client_fd, (struct sockaddr*)&addr, &len) != 0) {
+        printf("FAIL: getpeername failed on accepted socket\n");
+        return 1;
+    }
+    print_addr("Peer is", &addr);
+    return 0;
+}
@@ -0,0 +1,48 @@
+#include <stdio.h>
+#include <string.h>
+#include <netinet/in.h>
+
+// WASI socket functions
+__attribute__((import_module("wasi_snapshot_preview1")))
+__attribute__((import_name("sock_open")))
+int sock_open(int domain, int socktype, int protocol, int* sock_fd_out);
+
+__attribute__((import_module("wasi_snapshot_preview1")))
+__attribute__((import_name("sock_connect")))
+int sock_connect(int sock_fd, const struct sockaddr* addr, int addr_len);
+
+#define LIMIT 4
+#define ATTEMPTS (LIMIT + 2)
+
+// Start consensus with REPLICODE_MAX_SOCKETS_PER_PROCESS=4 and something
+// listening on 127.0.0.1:8000 (e.g. test-server). The first LIMIT connects
+// succeed and every one after that is refused by the NAT. test/socket_limit.py
+// runs the same steps.
+int main() {
+    struct sockaddr_in addr;
+    int fds[ATTEMPTS];
+
+    memset(&addr, 0, sizeof(addr));
+    addr.sin_family = AF_INET;
+    addr.sin_port = htons(8000);
+    addr.sin_addr.s_addr = htonl(INADDR_LOOPBACK);
+
+    for (int i = 0; i < ATTEMPTS; i++) {
+        if (sock_open(1, 1, 0, &fds[i]) != 0) {
+            printf("Failed to open socket %d\n", i);
+            return 1;
+        }
+        int ret = sock_connect(fds[i], (struct sockaddr*)&addr, sizeof(addr));
+        if (i < LIMIT && ret != 0) {
+            printf("FAIL: connect %d within the limit failed (%d)\n", i, ret);
+            return 1;
+        }
+        if (i >= LIMIT && ret == 0) {
+            printf("FAIL: connect %d beyond the limit succeeded\n", i);
+            return 1;
+        }
+        printf("connect %d -> %d\n", i, ret);
+    }
+    printf("PASS: connects beyond the limit were refused\n");
+    return 0;
+}
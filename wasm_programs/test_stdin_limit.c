@@ -0,0 +1,34 @@
+#include <stdio.h>
+#include <string.h>
+#include <unistd.h>
+
+// Leaves stdin unread for a (deterministic) second, then checks what was
+// buffered. Write
+//   init build/test_stdin_limit.wasm -l stdin=16
+//   clock 1
+//   msg 1 fd:0,body:hello
+//   msg 1 fd:0,body:world
+//   msg 1 fd:0,body:refused
+//   clock 2000000000
+// with `consensus benchmark` and replay it with `runtime benchmark`. The
+// first two lines fit in the 16-byte limit; the third would not, so the
+// runtime refuses it instead of buffering it. test/stdin_limit.py replays the
+// same records.
+int main() {
+    char buf[256];
+
+    sleep(1);
+    ssize_t n = read(0, buf, sizeof(buf) - 1);
+    if (n < 0) {
+        printf("FAIL: read from stdin failed\n");
+        return 1;
+    }
+    buf[n] = '\0';
+
+    if (strcmp(buf, "hello\nworld\n") == 0) {
+        printf("PASS: excess input was refused\n");
+        return 0;
+    }
+    printf("FAIL: stdin held %zd bytes: %s\n", n, buf);
+    return 1;
+}
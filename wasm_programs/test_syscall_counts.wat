@@ -0,0 +1,25 @@
+;; Makes a known sequence of syscalls: fd_write three times, clock_time_get
+;; twice and args_sizes_get once.
+;; Module::new accepts the text format, so Init this file directly:
+;;   init wasm_programs/test_syscall_counts.wat
+;; Expected: when the process exits, the runtime logs
+;;   Process 1 syscalls: args_sizes_get=1, clock_time_get=2, fd_write=3
+;; and, with --metrics-port, reports the same counts while it runs as
+;; replicode_process_syscalls_total. test/syscall_counts.py checks the log.
+(module
+  (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "clock_time_get" (func $clock_time_get (param i32 i64 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "args_sizes_get" (func $args_sizes_get (param i32 i32) (result i32)))
+  (memory (export "memory") 1)
+
+  ;; iovec { buf = 64, len = 6 }
+  (data (i32.const 0) "\40\00\00\00\06\00\00\00")
+  (data (i32.const 64) "count\n")
+
+  (func (export "_start")
+    (drop (call $args_sizes_get (i32.const 16) (i32.const 20)))
+    (drop (call $clock_time_get (i32.const 1) (i64.const 1) (i32.const 24)))
+    (drop (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 32)))
+    (drop (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 32)))
+    (drop (call $clock_time_get (i32.const 1) (i64.const 1) (i32.const 24)))
+    (drop (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 32)))))
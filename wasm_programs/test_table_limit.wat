@@ -0,0 +1,14 @@
+;; Grows its function table one element at a time until the runtime stops it.
+;; With the default limit of 10000 elements (or e.g. `-l table=100` on init)
+;; the runtime should terminate it as "killed by table limit". If table.grow
+;; instead returns -1 the guest exits normally, which means the limit did not
+;; trap. test/table_limit.py runs it with both limits.
+(module
+  (memory (export "memory") 1)
+  (table $t 1 funcref)
+  (func (export "_start")
+    (loop $grow
+      (if (i32.eq (table.grow $t (ref.null func) (i32.const 1)) (i32.const -1))
+        (then (return)))
+      (br $grow)))
+)
@@ -0,0 +1,17 @@
+;; Traps three calls deep, so the runtime has a backtrace to report.
+;; Module::new accepts the text format, so Init this file directly:
+;;   init wasm_programs/test_unreachable.wat
+;; Expected: the runtime logs "Process 1 trapped in:" followed by the guest
+;; frames crash, middle, outer and start (the names come from the name
+;; section the text format emits), then reports the process as finished
+;; (trapped).
+(module
+  (memory (export "memory") 1)
+  (func $crash
+    unreachable)
+  (func $middle
+    call $crash)
+  (func $outer
+    call $middle)
+  (func $start (export "_start")
+    call $outer))
@@ -0,0 +1,14 @@
+;; Spins forever without making a syscall, so it never yields or blocks.
+;; Module::new accepts the text format, so Init this file directly:
+;;   init wasm_programs/test_watchdog.wat
+;; and replay with a short watchdog timeout:
+;;   runtime benchmark --fuel=0 --watchdog=100 --watchdog-kill
+;; (with a fuel limit the process yields every turn, and the watchdog never fires)
+;; Expected: the runtime logs that the process has been running for 100ms
+;; without yielding, then reports it as finished (killed by the watchdog).
+;; Without --watchdog-kill the process is only flagged and keeps spinning.
+(module
+  (memory (export "memory") 1)
+  (func (export "_start")
+    (loop $spin
+      (br $spin))))
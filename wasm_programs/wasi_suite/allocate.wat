@@ -0,0 +1,34 @@
+;; fd_allocate grows a file to cover the range it is given, and fd_advise
+;; checks its arguments.
+;; Reports: errno of allocating 100 bytes in a new file and the st_size
+;; fd_filestat_get then reads, the st_size after allocating bytes 10..30
+;; (unchanged) and bytes 90..140, errno of allocating 0 bytes (EINVAL), then
+;; errnos of fd_advise with ADVICE_SEQUENTIAL, with advice 9 (EINVAL) and
+;; on a closed FD (EBADF).
+(module
+  (import "wasi_snapshot_preview1" "path_open" (func $path_open (param i32 i32 i32 i32 i32 i64 i64 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_allocate" (func $fd_allocate (param i32 i64 i64) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_advise" (func $fd_advise (param i32 i64 i64 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_filestat_get" (func $fd_filestat_get (param i32 i32) (result i32)))
+  (import "env" "__builtin_rt_report" (func $report (param i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 256) "preallocated")
+  (func $size (param $fd i32) (result i32)
+    (drop (call $fd_filestat_get (local.get $fd) (i32.const 128)))
+    (i32.wrap_i64 (i64.load (i32.const 160))))
+  (func (export "_start")
+    (local $fd i32)
+    (drop (call $path_open (i32.const 3) (i32.const 0) (i32.const 256) (i32.const 12) (i32.const 1)
+      (i64.const -1) (i64.const -1) (i32.const 0) (i32.const 64)))
+    (local.set $fd (i32.load (i32.const 64)))
+    (i32.store (i32.const 0) (call $fd_allocate (local.get $fd) (i64.const 0) (i64.const 100)))
+    (i32.store (i32.const 4) (call $size (local.get $fd)))
+    (drop (call $fd_allocate (local.get $fd) (i64.const 10) (i64.const 20)))
+    (i32.store (i32.const 8) (call $size (local.get $fd)))
+    (drop (call $fd_allocate (local.get $fd) (i64.const 90) (i64.const 50)))
+    (i32.store (i32.const 12) (call $size (local.get $fd)))
+    (i32.store (i32.const 16) (call $fd_allocate (local.get $fd) (i64.const 0) (i64.const 0)))
+    (i32.store (i32.const 20) (call $fd_advise (local.get $fd) (i64.const 0) (i64.const 0) (i32.const 1)))
+    (i32.store (i32.const 24) (call $fd_advise (local.get $fd) (i64.const 0) (i64.const 0) (i32.const 9)))
+    (i32.store (i32.const 28) (call $fd_advise (i32.const 99) (i64.const 0) (i64.const 0) (i32.const 0)))
+    (drop (call $report (i32.const 0) (i32.const 32)))))
@@ -0,0 +1,44 @@
+;; __builtin_rt_atomic_replace puts the temp file's content under the final
+;; name and removes the temp file. Writes "version 1\n" to config.txt and
+;; "version 2\n" to config.txt.tmp, replaces config.txt with the temp file,
+;; reads config.txt back, then looks for the temp file and replaces again.
+;; Reports: errno of the replace, bytes read back from config.txt, the first
+;; and the ninth of them ('v', '2'), errno of opening the temp file and of
+;; the second replace, both ENOENT (2).
+(module
+  (import "wasi_snapshot_preview1" "path_open" (func $path_open (param i32 i32 i32 i32 i32 i64 i64 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_read" (func $fd_read (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_close" (func $fd_close (param i32) (result i32)))
+  (import "env" "__builtin_rt_atomic_replace" (func $replace (param i32 i32 i32 i32) (result i32)))
+  (import "env" "__builtin_rt_report" (func $report (param i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 256) "config.txt")
+  (data (i32.const 272) "config.txt.tmp")
+  (data (i32.const 288) "version 1\n")
+  (data (i32.const 304) "version 2\n")
+  ;; returns the errno of path_open; the fd is left at offset 64
+  (func $open (param $path i32) (param $len i32) (param $oflags i32) (result i32)
+    (call $path_open (i32.const 3) (i32.const 0) (local.get $path) (local.get $len) (local.get $oflags)
+      (i64.const -1) (i64.const -1) (i32.const 0) (i32.const 64)))
+  (func $write_file (param $path i32) (param $len i32) (param $content i32)
+    (local $fd i32)
+    (drop (call $open (local.get $path) (local.get $len) (i32.const 1)))
+    (local.set $fd (i32.load (i32.const 64)))
+    (i32.store (i32.const 80) (local.get $content))
+    (i32.store (i32.const 84) (i32.const 10))
+    (drop (call $fd_write (local.get $fd) (i32.const 80) (i32.const 1) (i32.const 88)))
+    (drop (call $fd_close (local.get $fd))))
+  (func (export "_start")
+    (call $write_file (i32.const 256) (i32.const 10) (i32.const 288))
+    (call $write_file (i32.const 272) (i32.const 14) (i32.const 304))
+    (i32.store (i32.const 0) (call $replace (i32.const 272) (i32.const 14) (i32.const 256) (i32.const 10)))
+    (drop (call $open (i32.const 256) (i32.const 10) (i32.const 0)))
+    (i32.store (i32.const 80) (i32.const 512))
+    (i32.store (i32.const 84) (i32.const 64))
+    (drop (call $fd_read (i32.load (i32.const 64)) (i32.const 80) (i32.const 1) (i32.const 4)))
+    (i32.store (i32.const 8) (i32.load8_u (i32.const 512)))
+    (i32.store (i32.const 12) (i32.load8_u (i32.const 520)))
+    (i32.store (i32.const 16) (call $open (i32.const 272) (i32.const 14) (i32.const 0)))
+    (i32.store (i32.const 20) (call $replace (i32.const 272) (i32.const 14) (i32.const 256) (i32.const 10)))
+    (drop (call $report (i32.const 0) (i32.const 24)))))
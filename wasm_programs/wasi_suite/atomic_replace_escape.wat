@@ -0,0 +1,25 @@
+;; __builtin_rt_atomic_replace confines both paths to the sandbox before it
+;; opens either to sync it. Replaces "final" with "tmp", then tries temp
+;; paths that climb out of the sandbox, to a host file that exists and to one
+;; that does not.
+;; Reports: errno of the replace inside the sandbox, then of the two outside
+;; it, which must both be EACCES (13), so the guest cannot tell whether a host
+;; file exists.
+(module
+  (import "wasi_snapshot_preview1" "path_open" (func $path_open (param i32 i32 i32 i32 i32 i64 i64 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_close" (func $fd_close (param i32) (result i32)))
+  (import "env" "__builtin_rt_atomic_replace" (func $replace (param i32 i32 i32 i32) (result i32)))
+  (import "env" "__builtin_rt_report" (func $report (param i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 256) "tmp")
+  (data (i32.const 264) "final")
+  (data (i32.const 288) "../../../../../../../../../../etc/passwd")
+  (data (i32.const 352) "../../../../../../../../../../no_such_file")
+  (func (export "_start")
+    (drop (call $path_open (i32.const 3) (i32.const 0) (i32.const 256) (i32.const 3) (i32.const 1)
+      (i64.const -1) (i64.const -1) (i32.const 0) (i32.const 64)))
+    (drop (call $fd_close (i32.load (i32.const 64))))
+    (i32.store (i32.const 0) (call $replace (i32.const 256) (i32.const 3) (i32.const 264) (i32.const 5)))
+    (i32.store (i32.const 4) (call $replace (i32.const 288) (i32.const 40) (i32.const 264) (i32.const 5)))
+    (i32.store (i32.const 8) (call $replace (i32.const 352) (i32.const 42) (i32.const 264) (i32.const 5)))
+    (drop (call $report (i32.const 0) (i32.const 12)))))
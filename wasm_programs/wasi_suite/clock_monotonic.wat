@@ -0,0 +1,11 @@
+;; Two reads of the monotonic clock: both succeed, and time does not go back.
+;; Reports: errno of each read, then 1 if the second is >= the first.
+(module
+  (import "wasi_snapshot_preview1" "clock_time_get" (func $clock_time_get (param i32 i64 i32) (result i32)))
+  (import "env" "__builtin_rt_report" (func $report (param i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (func (export "_start")
+    (i32.store (i32.const 0) (call $clock_time_get (i32.const 1) (i64.const 1) (i32.const 64)))
+    (i32.store (i32.const 4) (call $clock_time_get (i32.const 1) (i64.const 1) (i32.const 72)))
+    (i32.store (i32.const 8) (i64.ge_u (i64.load (i32.const 72)) (i64.load (i32.const 64))))
+    (drop (call $report (i32.const 0) (i32.const 12)))))
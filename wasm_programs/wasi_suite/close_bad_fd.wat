@@ -0,0 +1,9 @@
+;; Closing a descriptor that was never opened.
+;; Reports: errno (EBADF is 8).
+(module
+  (import "wasi_snapshot_preview1" "fd_close" (func $fd_close (param i32) (result i32)))
+  (import "env" "__builtin_rt_report" (func $report (param i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (func (export "_start")
+    (i32.store (i32.const 0) (call $fd_close (i32.const 99)))
+    (drop (call $report (i32.const 0) (i32.const 4)))))
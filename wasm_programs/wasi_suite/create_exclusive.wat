@@ -0,0 +1,21 @@
+;; O_CREAT | O_EXCL fails with EEXIST on a file that already exists, and an
+;; FD opened with FDFLAGS_APPEND reports that flag in fd_fdstat_get.
+;; Reports: errno of creating the file, errno of creating it again
+;; exclusively, errno of opening it with APPEND, and the fs_flags of that FD.
+(module
+  (import "wasi_snapshot_preview1" "path_open" (func $path_open (param i32 i32 i32 i32 i32 i64 i64 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_fdstat_get" (func $fd_fdstat_get (param i32 i32) (result i32)))
+  (import "env" "__builtin_rt_report" (func $report (param i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 256) "efile")
+  (func $open (param $oflags i32) (param $fdflags i32) (result i32)
+    (call $path_open (i32.const 3) (i32.const 0) (i32.const 256) (i32.const 5) (local.get $oflags)
+      (i64.const -1) (i64.const -1) (local.get $fdflags) (i32.const 64)))
+  (func (export "_start")
+    (i32.store (i32.const 0) (call $open (i32.const 1) (i32.const 0)))
+    ;; O_CREAT | O_EXCL
+    (i32.store (i32.const 4) (call $open (i32.const 5) (i32.const 0)))
+    (i32.store (i32.const 8) (call $open (i32.const 0) (i32.const 1)))
+    (drop (call $fd_fdstat_get (i32.load (i32.const 64)) (i32.const 128)))
+    (i32.store (i32.const 12) (i32.load16_u (i32.const 130)))
+    (drop (call $report (i32.const 0) (i32.const 16)))))
@@ -0,0 +1,23 @@
+;; The checks of wasm_programs/test_efault.c: each syscall gets a pointer past
+;; the end of linear memory and must return EFAULT instead of trapping.
+;; Reports: the errno of fd_write with bad iovs, of fd_write and fd_read with
+;; an iovec whose buffer runs off the end, of fd_write with a bad nwritten,
+;; of clock_time_get and of args_sizes_get (EFAULT is 21). Reaching the
+;; report at all shows the process was not trapped.
+(module
+  (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_read" (func $fd_read (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "clock_time_get" (func $clock_time_get (param i32 i64 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "args_sizes_get" (func $args_sizes_get (param i32 i32) (result i32)))
+  (import "env" "__builtin_rt_report" (func $report (param i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  ;; iovec { buf = 0xfffffff0, len = 64 } at 64, and { buf = 96, len = 4 } at 72
+  (data (i32.const 64) "\f0\ff\ff\ff\40\00\00\00\60\00\00\00\04\00\00\00")
+  (func (export "_start")
+    (i32.store (i32.const 0) (call $fd_write (i32.const 1) (i32.const 0xfffffff0) (i32.const 1) (i32.const 128)))
+    (i32.store (i32.const 4) (call $fd_write (i32.const 1) (i32.const 64) (i32.const 1) (i32.const 128)))
+    (i32.store (i32.const 8) (call $fd_read (i32.const 0) (i32.const 64) (i32.const 1) (i32.const 128)))
+    (i32.store (i32.const 12) (call $fd_write (i32.const 1) (i32.const 72) (i32.const 1) (i32.const 0xfffffff0)))
+    (i32.store (i32.const 16) (call $clock_time_get (i32.const 0) (i64.const 1) (i32.const 0xfffffff0)))
+    (i32.store (i32.const 20) (call $args_sizes_get (i32.const 0xfffffff0) (i32.const 128)))
+    (drop (call $report (i32.const 0) (i32.const 24)))))
@@ -0,0 +1,42 @@
+;; A file's mtime is the logical clock of its last write: set when it is
+;; created, moved on by a write once time has passed, and kept after close.
+;; Reports: errno of path_open, 1 if the new file's mtime is the clock at
+;; creation, 1 if a write 1ms later moves it on, 1 if that is the clock at
+;; the write, then errno of path_filestat_get after a close and another 1ms,
+;; and 1 if it reports the same mtime.
+(module
+  (import "wasi_snapshot_preview1" "path_open" (func $path_open (param i32 i32 i32 i32 i32 i64 i64 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_close" (func $fd_close (param i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_filestat_get" (func $fd_filestat_get (param i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "path_filestat_get" (func $path_filestat_get (param i32 i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "clock_time_get" (func $clock_time_get (param i32 i64 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "poll_oneoff" (func $poll_oneoff (param i32 i32 i32 i32) (result i32)))
+  (import "env" "__builtin_rt_report" (func $report (param i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 1024) "mtime_file")
+  (data (i32.const 1040) "hi")
+  (func $sleep_1ms
+    (i32.store8 (i32.const 520) (i32.const 0))       ;; eventtype clock
+    (i32.store (i32.const 528) (i32.const 1))        ;; monotonic
+    (i64.store (i32.const 536) (i64.const 1000000))  ;; timeout
+    (drop (call $poll_oneoff (i32.const 512) (i32.const 576) (i32.const 1) (i32.const 608))))
+  (func (export "_start")
+    (drop (call $clock_time_get (i32.const 0) (i64.const 1) (i32.const 640)))
+    (i32.store (i32.const 0) (call $path_open (i32.const 3) (i32.const 0) (i32.const 1024) (i32.const 10) (i32.const 1)
+      (i64.const -1) (i64.const -1) (i32.const 0) (i32.const 64)))
+    (drop (call $fd_filestat_get (i32.load (i32.const 64)) (i32.const 128)))
+    (i32.store (i32.const 4) (i64.eq (i64.load (i32.const 176)) (i64.load (i32.const 640))))
+    (call $sleep_1ms)
+    (drop (call $clock_time_get (i32.const 0) (i64.const 1) (i32.const 648)))
+    (i32.store (i32.const 80) (i32.const 1040))
+    (i32.store (i32.const 84) (i32.const 2))
+    (drop (call $fd_write (i32.load (i32.const 64)) (i32.const 80) (i32.const 1) (i32.const 88)))
+    (drop (call $fd_filestat_get (i32.load (i32.const 64)) (i32.const 192)))
+    (i32.store (i32.const 8) (i64.gt_u (i64.load (i32.const 240)) (i64.load (i32.const 176))))
+    (i32.store (i32.const 12) (i64.eq (i64.load (i32.const 240)) (i64.load (i32.const 648))))
+    (drop (call $fd_close (i32.load (i32.const 64))))
+    (call $sleep_1ms)
+    (i32.store (i32.const 16) (call $path_filestat_get (i32.const 3) (i32.const 0) (i32.const 1024) (i32.const 10) (i32.const 256)))
+    (i32.store (i32.const 20) (i64.eq (i64.load (i32.const 304)) (i64.load (i32.const 240))))
+    (drop (call $report (i32.const 0) (i32.const 24)))))
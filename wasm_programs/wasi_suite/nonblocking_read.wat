@@ -0,0 +1,19 @@
+;; fd_read on stdin set to FDFLAGS_NONBLOCK returns EAGAIN (6) with nread 0
+;; when nothing is buffered, instead of blocking until consensus sends input.
+;; Reports: errno of fd_fdstat_set_flags, errno of fd_read, nread (set to 99
+;; beforehand), then fs_flags from fd_fdstat_get.
+(module
+  (import "wasi_snapshot_preview1" "fd_fdstat_set_flags" (func $fd_fdstat_set_flags (param i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_fdstat_get" (func $fd_fdstat_get (param i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_read" (func $fd_read (param i32 i32 i32 i32) (result i32)))
+  (import "env" "__builtin_rt_report" (func $report (param i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (func (export "_start")
+    (i32.store (i32.const 0) (call $fd_fdstat_set_flags (i32.const 0) (i32.const 4)))
+    (i32.store (i32.const 80) (i32.const 256))
+    (i32.store (i32.const 84) (i32.const 64))
+    (i32.store (i32.const 8) (i32.const 99))
+    (i32.store (i32.const 4) (call $fd_read (i32.const 0) (i32.const 80) (i32.const 1) (i32.const 8)))
+    (drop (call $fd_fdstat_get (i32.const 0) (i32.const 96)))
+    (i32.store (i32.const 12) (i32.load16_u (i32.const 98)))
+    (drop (call $report (i32.const 0) (i32.const 16)))))
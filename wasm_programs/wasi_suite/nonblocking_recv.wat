@@ -0,0 +1,17 @@
+;; sock_recv on a socket set to FDFLAGS_NONBLOCK returns EAGAIN (6) when no
+;; data is waiting, instead of blocking.
+;; Reports: errno of fd_fdstat_set_flags, then of sock_recv.
+(module
+  (import "wasi_snapshot_preview1" "sock_open" (func $sock_open (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_fdstat_set_flags" (func $fd_fdstat_set_flags (param i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "sock_recv" (func $sock_recv (param i32 i32 i32 i32 i32 i32) (result i32)))
+  (import "env" "__builtin_rt_report" (func $report (param i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (func (export "_start")
+    (drop (call $sock_open (i32.const 1) (i32.const 1) (i32.const 0) (i32.const 64)))
+    (i32.store (i32.const 0) (call $fd_fdstat_set_flags (i32.load (i32.const 64)) (i32.const 4)))
+    (i32.store (i32.const 80) (i32.const 256))
+    (i32.store (i32.const 84) (i32.const 64))
+    (i32.store (i32.const 4) (call $sock_recv (i32.load (i32.const 64)) (i32.const 80) (i32.const 1) (i32.const 0)
+      (i32.const 88) (i32.const 92)))
+    (drop (call $report (i32.const 0) (i32.const 8)))))
@@ -0,0 +1,33 @@
+;; The checks of wasm_programs/test_overflow_ptrs.c: pointer/length pairs whose
+;; sum lies beyond, or wraps around, the 32-bit address space.
+;; Reports: the errno of fd_write and fd_read with an iovec of length
+;; 0xffffffff, of fd_write with a valid iovec followed by one that wraps, of
+;; fd_write with an iovs_len whose array overflows, of path_filestat_get with
+;; a wrapping path and of sock_send with data running off the end (EFAULT is
+;; 21), then the bytes the wrapping write got out (none).
+(module
+  (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_read" (func $fd_read (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "path_filestat_get" (func $path_filestat_get (param i32 i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "sock_open" (func $sock_open (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "sock_send" (func $sock_send (param i32 i32 i32 i32 i32) (result i32)))
+  (import "env" "__builtin_rt_report" (func $report (param i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  ;; iovec { buf = 0xffffff00, len = 0xffffffff } at 64
+  (data (i32.const 64) "\00\ff\ff\ff\ff\ff\ff\ff")
+  ;; iovecs { buf = 112, len = 1 } and { buf = 0xffffffff, len = 2 } at 96
+  (data (i32.const 96) "\70\00\00\00\01\00\00\00\ff\ff\ff\ff\02\00\00\00")
+  (data (i32.const 112) "x")
+  (func (export "_start")
+    (i32.store (i32.const 0) (call $fd_write (i32.const 1) (i32.const 64) (i32.const 1) (i32.const 128)))
+    (i32.store (i32.const 4) (call $fd_read (i32.const 0) (i32.const 64) (i32.const 1) (i32.const 128)))
+    (i32.store (i32.const 132) (i32.const 0))
+    (i32.store (i32.const 8) (call $fd_write (i32.const 1) (i32.const 96) (i32.const 2) (i32.const 132)))
+    (i32.store (i32.const 12) (call $fd_write (i32.const 1) (i32.const 96) (i32.const 0x20000001) (i32.const 128)))
+    (i32.store (i32.const 16)
+      (call $path_filestat_get (i32.const 3) (i32.const 0) (i32.const 0xffffffff) (i32.const -1) (i32.const 192)))
+    (drop (call $sock_open (i32.const 1) (i32.const 1) (i32.const 0) (i32.const 136)))
+    (i32.store (i32.const 20)
+      (call $sock_send (i32.load (i32.const 136)) (i32.const 0xfffffff0) (i32.const 0x20) (i32.const 0) (i32.const 128)))
+    (i32.store (i32.const 24) (i32.load (i32.const 132)))
+    (drop (call $report (i32.const 0) (i32.const 28)))))
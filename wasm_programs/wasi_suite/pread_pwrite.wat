@@ -0,0 +1,58 @@
+;; fd_pwrite writes at an offset and fd_pread reads at one, neither moving
+;; the read pointer. Writes "hello " with fd_write, "world" with fd_pwrite at
+;; offset 6, then reopens the file and preads it back.
+;; Reports: errno and bytes written of fd_pwrite, errno and bytes read of
+;; fd_pread at offset 0, 1 if they read "hello world", the first byte of a
+;; pread at offset 6 ('w') and of a following fd_read ('h'), and what a pread
+;; at offset 6 reads after a pwrite of "W" there on the same FD ('W').
+(module
+  (import "wasi_snapshot_preview1" "path_open" (func $path_open (param i32 i32 i32 i32 i32 i64 i64 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_read" (func $fd_read (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_pwrite" (func $fd_pwrite (param i32 i32 i32 i64 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_pread" (func $fd_pread (param i32 i32 i32 i64 i32) (result i32)))
+  (import "env" "__builtin_rt_report" (func $report (param i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 256) "pfile")
+  (data (i32.const 272) "hello world")
+  (data (i32.const 288) "W")
+  (func $open (param $oflags i32) (result i32)
+    (drop (call $path_open (i32.const 3) (i32.const 0) (i32.const 256) (i32.const 5) (local.get $oflags)
+      (i64.const -1) (i64.const -1) (i32.const 0) (i32.const 64)))
+    (i32.load (i32.const 64)))
+  ;; points the iovec at 80 to len bytes at ptr
+  (func $iovec (param $ptr i32) (param $len i32)
+    (i32.store (i32.const 80) (local.get $ptr))
+    (i32.store (i32.const 84) (local.get $len)))
+  (func (export "_start")
+    (local $fd i32) (local $i i32) (local $same i32)
+    (local.set $fd (call $open (i32.const 1)))
+    (call $iovec (i32.const 272) (i32.const 6))
+    (drop (call $fd_write (local.get $fd) (i32.const 80) (i32.const 1) (i32.const 88)))
+    (call $iovec (i32.const 278) (i32.const 5))
+    (i32.store (i32.const 0) (call $fd_pwrite (local.get $fd) (i32.const 80) (i32.const 1) (i64.const 6) (i32.const 4)))
+
+    (local.set $fd (call $open (i32.const 0)))
+    (call $iovec (i32.const 512) (i32.const 64))
+    (i32.store (i32.const 8) (call $fd_pread (local.get $fd) (i32.const 80) (i32.const 1) (i64.const 0) (i32.const 12)))
+    (local.set $same (i32.const 1))
+    (loop $compare
+      (if (i32.ne (i32.load8_u (i32.add (i32.const 512) (local.get $i)))
+                  (i32.load8_u (i32.add (i32.const 272) (local.get $i))))
+        (then (local.set $same (i32.const 0))))
+      (local.set $i (i32.add (local.get $i) (i32.const 1)))
+      (br_if $compare (i32.lt_u (local.get $i) (i32.const 11))))
+    (i32.store (i32.const 16) (local.get $same))
+
+    (call $iovec (i32.const 600) (i32.const 1))
+    (drop (call $fd_pread (local.get $fd) (i32.const 80) (i32.const 1) (i64.const 6) (i32.const 88)))
+    (i32.store (i32.const 20) (i32.load8_u (i32.const 600)))
+    (drop (call $fd_read (local.get $fd) (i32.const 80) (i32.const 1) (i32.const 88)))
+    (i32.store (i32.const 24) (i32.load8_u (i32.const 600)))
+
+    (call $iovec (i32.const 288) (i32.const 1))
+    (drop (call $fd_pwrite (local.get $fd) (i32.const 80) (i32.const 1) (i64.const 6) (i32.const 88)))
+    (call $iovec (i32.const 600) (i32.const 1))
+    (drop (call $fd_pread (local.get $fd) (i32.const 80) (i32.const 1) (i64.const 6) (i32.const 88)))
+    (i32.store (i32.const 28) (i32.load8_u (i32.const 600)))
+    (drop (call $report (i32.const 0) (i32.const 32)))))
@@ -0,0 +1,10 @@
+;; random_get fills the buffer (16 zero bytes are all but impossible).
+;; Reports: errno, and 1 if any byte of the buffer is non-zero.
+(module
+  (import "wasi_snapshot_preview1" "random_get" (func $random_get (param i32 i32) (result i32)))
+  (import "env" "__builtin_rt_report" (func $report (param i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (func (export "_start")
+    (i32.store (i32.const 0) (call $random_get (i32.const 64) (i32.const 16)))
+    (i32.store (i32.const 4) (i64.ne (i64.or (i64.load (i32.const 64)) (i64.load (i32.const 72))) (i64.const 0)))
+    (drop (call $report (i32.const 0) (i32.const 8)))))
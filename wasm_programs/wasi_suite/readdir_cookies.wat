@@ -0,0 +1,48 @@
+;; Resuming fd_readdir from the d_next cookie of the first entry skips
+;; exactly that entry.
+;; Reports: errno of the read from cookie 0, errno of the resumed read, and
+;; how many fewer entries the resumed read returned.
+(module
+  (import "wasi_snapshot_preview1" "path_create_directory" (func $path_create_directory (param i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "path_open" (func $path_open (param i32 i32 i32 i32 i32 i64 i64 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_close" (func $fd_close (param i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_readdir" (func $fd_readdir (param i32 i32 i32 i64 i32) (result i32)))
+  (import "env" "__builtin_rt_report" (func $report (param i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 256) "cookie_dir/a")
+  (data (i32.const 272) "cookie_dir/b")
+  (data (i32.const 288) "cookie_dir/c")
+
+  (func $create (param $path i32)
+    (drop (call $path_open (i32.const 3) (i32.const 0) (local.get $path) (i32.const 12) (i32.const 1)
+      (i64.const -1) (i64.const -1) (i32.const 0) (i32.const 64)))
+    (drop (call $fd_close (i32.load (i32.const 64)))))
+
+  ;; Counts the dirents in buf[0..used]: a 24-byte header, then d_namlen bytes
+  (func $count (param $buf i32) (param $used i32) (result i32)
+    (local $pos i32) (local $n i32)
+    (block $done
+      (loop $next
+        (br_if $done (i32.gt_u (i32.add (local.get $pos) (i32.const 24)) (local.get $used)))
+        (local.set $n (i32.add (local.get $n) (i32.const 1)))
+        (local.set $pos (i32.add (local.get $pos)
+          (i32.add (i32.const 24) (i32.load (i32.add (local.get $buf) (i32.add (local.get $pos) (i32.const 16)))))))
+        (br $next)))
+    (local.get $n))
+
+  (func (export "_start")
+    (local $fd i32)
+    (drop (call $path_create_directory (i32.const 3) (i32.const 256) (i32.const 10)))
+    (call $create (i32.const 256))
+    (call $create (i32.const 272))
+    (call $create (i32.const 288))
+    (drop (call $path_open (i32.const 3) (i32.const 0) (i32.const 256) (i32.const 10) (i32.const 2)
+      (i64.const -1) (i64.const -1) (i32.const 0) (i32.const 64)))
+    (local.set $fd (i32.load (i32.const 64)))
+    (i32.store (i32.const 0) (call $fd_readdir (local.get $fd) (i32.const 1024) (i32.const 1024) (i64.const 0) (i32.const 68)))
+    (i32.store (i32.const 4) (call $fd_readdir (local.get $fd) (i32.const 4096) (i32.const 1024)
+      (i64.load (i32.const 1024)) (i32.const 72)))
+    (i32.store (i32.const 8) (i32.sub
+      (call $count (i32.const 1024) (i32.load (i32.const 68)))
+      (call $count (i32.const 4096) (i32.load (i32.const 72)))))
+    (drop (call $report (i32.const 0) (i32.const 12)))))
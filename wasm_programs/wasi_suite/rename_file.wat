@@ -0,0 +1,20 @@
+;; path_rename moves a file: the new name exists and the old one does not.
+;; Reports: errno of path_rename, then 1 if stat of the new name succeeds,
+;; then 1 if stat of the old name fails.
+(module
+  (import "wasi_snapshot_preview1" "path_open" (func $path_open (param i32 i32 i32 i32 i32 i64 i64 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_close" (func $fd_close (param i32) (result i32)))
+  (import "wasi_snapshot_preview1" "path_rename" (func $path_rename (param i32 i32 i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "path_filestat_get" (func $path_filestat_get (param i32 i32 i32 i32 i32) (result i32)))
+  (import "env" "__builtin_rt_report" (func $report (param i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 256) "old_name")
+  (data (i32.const 272) "new_name")
+  (func (export "_start")
+    (drop (call $path_open (i32.const 3) (i32.const 0) (i32.const 256) (i32.const 8) (i32.const 1)
+      (i64.const -1) (i64.const -1) (i32.const 0) (i32.const 64)))
+    (drop (call $fd_close (i32.load (i32.const 64))))
+    (i32.store (i32.const 0) (call $path_rename (i32.const 3) (i32.const 256) (i32.const 8) (i32.const 3) (i32.const 272) (i32.const 8)))
+    (i32.store (i32.const 4) (i32.eqz (call $path_filestat_get (i32.const 3) (i32.const 0) (i32.const 272) (i32.const 8) (i32.const 128))))
+    (i32.store (i32.const 8) (i32.ne (call $path_filestat_get (i32.const 3) (i32.const 0) (i32.const 256) (i32.const 8) (i32.const 128)) (i32.const 0)))
+    (drop (call $report (i32.const 0) (i32.const 12)))))
@@ -0,0 +1,37 @@
+;; A renamed file keeps its content under the new name and is gone under the
+;; old one. Writes "moved" to old_name, renames it to new_name, then opens
+;; both names.
+;; Reports: errno of path_rename, errno of opening the old name, bytes read
+;; back from the new name, and the first two of them ('m', 'o').
+(module
+  (import "wasi_snapshot_preview1" "path_open" (func $path_open (param i32 i32 i32 i32 i32 i64 i64 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "path_rename" (func $path_rename (param i32 i32 i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_read" (func $fd_read (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_close" (func $fd_close (param i32) (result i32)))
+  (import "env" "__builtin_rt_report" (func $report (param i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 256) "old_name")
+  (data (i32.const 272) "new_name")
+  (data (i32.const 288) "moved")
+  ;; returns the errno of path_open; the fd is left at offset 64
+  (func $open (param $path i32) (param $oflags i32) (result i32)
+    (call $path_open (i32.const 3) (i32.const 0) (local.get $path) (i32.const 8) (local.get $oflags)
+      (i64.const -1) (i64.const -1) (i32.const 0) (i32.const 64)))
+  (func (export "_start")
+    (local $fd i32)
+    (drop (call $open (i32.const 256) (i32.const 1)))
+    (local.set $fd (i32.load (i32.const 64)))
+    (i32.store (i32.const 80) (i32.const 288))
+    (i32.store (i32.const 84) (i32.const 5))
+    (drop (call $fd_write (local.get $fd) (i32.const 80) (i32.const 1) (i32.const 88)))
+    (drop (call $fd_close (local.get $fd)))
+    (i32.store (i32.const 0) (call $path_rename (i32.const 3) (i32.const 256) (i32.const 8) (i32.const 3) (i32.const 272) (i32.const 8)))
+    (i32.store (i32.const 4) (call $open (i32.const 256) (i32.const 0)))
+    (drop (call $open (i32.const 272) (i32.const 0)))
+    (i32.store (i32.const 80) (i32.const 512))
+    (i32.store (i32.const 84) (i32.const 64))
+    (drop (call $fd_read (i32.load (i32.const 64)) (i32.const 80) (i32.const 1) (i32.const 8)))
+    (i32.store (i32.const 12) (i32.load8_u (i32.const 512)))
+    (i32.store (i32.const 16) (i32.load8_u (i32.const 513)))
+    (drop (call $report (i32.const 0) (i32.const 20)))))
@@ -0,0 +1,37 @@
+;; fd_renumber moves an fd onto another one, closing what that one held.
+;; Opens "rfile" and "other", renumbers the first onto the second, writes
+;; "hi" through it, then reopens "rfile" to read it back.
+;; Reports: errno of fd_renumber, errno of fd_write through the new fd, of
+;; fd_seek on the old fd (EBADF, it is closed) and of renumbering it again
+;; (EBADF), then the bytes read back from "rfile" and the first of them ('h').
+(module
+  (import "wasi_snapshot_preview1" "path_open" (func $path_open (param i32 i32 i32 i32 i32 i64 i64 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_read" (func $fd_read (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_seek" (func $fd_seek (param i32 i64 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_renumber" (func $fd_renumber (param i32 i32) (result i32)))
+  (import "env" "__builtin_rt_report" (func $report (param i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 256) "rfile")
+  (data (i32.const 264) "other")
+  (data (i32.const 272) "hi")
+  (func $open (param $path i32) (result i32)
+    (drop (call $path_open (i32.const 3) (i32.const 0) (local.get $path) (i32.const 5) (i32.const 1)
+      (i64.const -1) (i64.const -1) (i32.const 0) (i32.const 64)))
+    (i32.load (i32.const 64)))
+  (func (export "_start")
+    (local $from i32) (local $to i32) (local $fd i32)
+    (local.set $from (call $open (i32.const 256)))
+    (local.set $to (call $open (i32.const 264)))
+    (i32.store (i32.const 0) (call $fd_renumber (local.get $from) (local.get $to)))
+    (i32.store (i32.const 80) (i32.const 272))
+    (i32.store (i32.const 84) (i32.const 2))
+    (i32.store (i32.const 4) (call $fd_write (local.get $to) (i32.const 80) (i32.const 1) (i32.const 88)))
+    (i32.store (i32.const 8) (call $fd_seek (local.get $from) (i64.const 0) (i32.const 1) (i32.const 96)))
+    (i32.store (i32.const 12) (call $fd_renumber (local.get $from) (local.get $to)))
+    (local.set $fd (call $open (i32.const 256)))
+    (i32.store (i32.const 80) (i32.const 512))
+    (i32.store (i32.const 84) (i32.const 16))
+    (drop (call $fd_read (local.get $fd) (i32.const 80) (i32.const 1) (i32.const 16)))
+    (i32.store (i32.const 20) (i32.load8_u (i32.const 512)))
+    (drop (call $report (i32.const 0) (i32.const 24)))))
@@ -0,0 +1,21 @@
+;; Seeking past the end of a 5-byte file is allowed, as with lseek.
+;; Reports: errno of fd_seek(fd, 10, SEEK_SET), and the new offset.
+(module
+  (import "wasi_snapshot_preview1" "path_open" (func $path_open (param i32 i32 i32 i32 i32 i64 i64 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_seek" (func $fd_seek (param i32 i64 i32 i32) (result i32)))
+  (import "env" "__builtin_rt_report" (func $report (param i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 256) "seek_file")
+  (data (i32.const 272) "hello")
+  (func (export "_start")
+    (local $fd i32)
+    (drop (call $path_open (i32.const 3) (i32.const 0) (i32.const 256) (i32.const 9) (i32.const 1)
+      (i64.const -1) (i64.const -1) (i32.const 0) (i32.const 64)))
+    (local.set $fd (i32.load (i32.const 64)))
+    (i32.store (i32.const 80) (i32.const 272))
+    (i32.store (i32.const 84) (i32.const 5))
+    (drop (call $fd_write (local.get $fd) (i32.const 80) (i32.const 1) (i32.const 88)))
+    (i32.store (i32.const 0) (call $fd_seek (local.get $fd) (i64.const 10) (i32.const 0) (i32.const 96)))
+    (i32.store (i32.const 4) (i32.wrap_i64 (i64.load (i32.const 96))))
+    (drop (call $report (i32.const 0) (i32.const 8)))))
@@ -0,0 +1,66 @@
+;; fd_seek moves the write cursor of a file on disk with each whence. Writes
+;; "hello world", reopens the file with only the write right (so nothing of it
+;; is buffered), seeks to the end, back 6 and writes ",", then seeks to 20 and
+;; writes "!", growing the file with a hole. Reopens it to read it back.
+;; Reports: the sum of the fd_seek errnos, the offsets after SEEK_END 0,
+;; SEEK_CUR -6 and SEEK_SET 20, the fd_tell and SEEK_END offsets after the
+;; last write, then bytes 5 (','), 15 (0) and 20 ('!') read back, and the
+;; SEEK_END offset after writing "!" at the end through that fd, whose read
+;; buffer was filled when it was opened.
+(module
+  (import "wasi_snapshot_preview1" "path_open" (func $path_open (param i32 i32 i32 i32 i32 i64 i64 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_read" (func $fd_read (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_seek" (func $fd_seek (param i32 i64 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_tell" (func $fd_tell (param i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_close" (func $fd_close (param i32) (result i32)))
+  (import "env" "__builtin_rt_report" (func $report (param i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (global $errnos (mut i32) (i32.const 0))
+  (data (i32.const 256) "wfile")
+  (data (i32.const 272) "hello world,!")
+  (func $open (param $oflags i32) (param $rights i64) (result i32)
+    (drop (call $path_open (i32.const 3) (i32.const 0) (i32.const 256) (i32.const 5) (local.get $oflags)
+      (local.get $rights) (local.get $rights) (i32.const 0) (i32.const 64)))
+    (i32.load (i32.const 64)))
+  (func $write (param $fd i32) (param $ptr i32) (param $len i32)
+    (i32.store (i32.const 80) (local.get $ptr))
+    (i32.store (i32.const 84) (local.get $len))
+    (drop (call $fd_write (local.get $fd) (i32.const 80) (i32.const 1) (i32.const 88))))
+  ;; seeks and returns the new offset, adding the errno to $errnos
+  (func $seek (param $fd i32) (param $offset i64) (param $whence i32) (result i32)
+    (global.set $errnos (i32.add (global.get $errnos)
+      (call $fd_seek (local.get $fd) (local.get $offset) (local.get $whence) (i32.const 96))))
+    (i32.wrap_i64 (i64.load (i32.const 96))))
+  ;; returns the byte at offset $at of the file open as $fd
+  (func $byte_at (param $fd i32) (param $at i64) (result i32)
+    (drop (call $seek (local.get $fd) (local.get $at) (i32.const 0)))
+    (i32.store (i32.const 80) (i32.const 512))
+    (i32.store (i32.const 84) (i32.const 1))
+    (drop (call $fd_read (local.get $fd) (i32.const 80) (i32.const 1) (i32.const 88)))
+    (i32.load8_u (i32.const 512)))
+  (func (export "_start")
+    (local $fd i32)
+    (local.set $fd (call $open (i32.const 1) (i64.const -1)))
+    (call $write (local.get $fd) (i32.const 272) (i32.const 11))
+    (drop (call $fd_close (local.get $fd)))
+    ;; FD_WRITE only
+    (local.set $fd (call $open (i32.const 0) (i64.const 64)))
+    (i32.store (i32.const 4) (call $seek (local.get $fd) (i64.const 0) (i32.const 2)))
+    (i32.store (i32.const 8) (call $seek (local.get $fd) (i64.const -6) (i32.const 1)))
+    (call $write (local.get $fd) (i32.const 283) (i32.const 1))
+    (i32.store (i32.const 12) (call $seek (local.get $fd) (i64.const 20) (i32.const 0)))
+    (call $write (local.get $fd) (i32.const 284) (i32.const 1))
+    (drop (call $fd_tell (local.get $fd) (i32.const 96)))
+    (i32.store (i32.const 16) (i32.wrap_i64 (i64.load (i32.const 96))))
+    (i32.store (i32.const 20) (call $seek (local.get $fd) (i64.const 0) (i32.const 2)))
+    (drop (call $fd_close (local.get $fd)))
+    (local.set $fd (call $open (i32.const 0) (i64.const -1)))
+    (i32.store (i32.const 24) (call $byte_at (local.get $fd) (i64.const 5)))
+    (i32.store (i32.const 28) (call $byte_at (local.get $fd) (i64.const 15)))
+    (i32.store (i32.const 32) (call $byte_at (local.get $fd) (i64.const 20)))
+    (drop (call $seek (local.get $fd) (i64.const 0) (i32.const 2)))
+    (call $write (local.get $fd) (i32.const 284) (i32.const 1))
+    (i32.store (i32.const 36) (call $seek (local.get $fd) (i64.const 0) (i32.const 2)))
+    (i32.store (i32.const 0) (global.get $errnos))
+    (drop (call $report (i32.const 0) (i32.const 40)))))
@@ -0,0 +1,32 @@
+;; fd_filestat_set_size truncates a file, both for the FD and on disk.
+;; Writes 100 bytes, truncates the file to 10, then reopens it and reads it.
+;; Reports: errno of fd_filestat_set_size, st_size from fd_filestat_get,
+;; bytes read back after reopening, and errno of resizing the preopened
+;; directory (EBADF).
+(module
+  (import "wasi_snapshot_preview1" "path_open" (func $path_open (param i32 i32 i32 i32 i32 i64 i64 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_read" (func $fd_read (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_filestat_get" (func $fd_filestat_get (param i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_filestat_set_size" (func $fd_filestat_set_size (param i32 i64) (result i32)))
+  (import "env" "__builtin_rt_report" (func $report (param i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 256) "sized")
+  (func $open (param $oflags i32) (result i32)
+    (drop (call $path_open (i32.const 3) (i32.const 0) (i32.const 256) (i32.const 5) (local.get $oflags)
+      (i64.const -1) (i64.const -1) (i32.const 0) (i32.const 64)))
+    (i32.load (i32.const 64)))
+  (func (export "_start")
+    (local $fd i32)
+    (local.set $fd (call $open (i32.const 1)))
+    (i32.store (i32.const 80) (i32.const 1024))
+    (i32.store (i32.const 84) (i32.const 100))
+    (drop (call $fd_write (local.get $fd) (i32.const 80) (i32.const 1) (i32.const 88)))
+    (i32.store (i32.const 0) (call $fd_filestat_set_size (local.get $fd) (i64.const 10)))
+    (drop (call $fd_filestat_get (local.get $fd) (i32.const 128)))
+    (i32.store (i32.const 4) (i32.load (i32.const 160)))
+    (i32.store (i32.const 80) (i32.const 2048))
+    (i32.store (i32.const 84) (i32.const 200))
+    (drop (call $fd_read (call $open (i32.const 0)) (i32.const 80) (i32.const 1) (i32.const 8)))
+    (i32.store (i32.const 12) (call $fd_filestat_set_size (i32.const 3) (i64.const 0)))
+    (drop (call $report (i32.const 0) (i32.const 16)))))
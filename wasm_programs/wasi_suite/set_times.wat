@@ -0,0 +1,36 @@
+;; path_filestat_set_times sets a file's logical times, which both filestat
+;; calls then report.
+;; Reports: errno of setting atime 42 and mtime 1234567, the atime and mtime
+;; path_filestat_get reads back, the mtime fd_filestat_get reads on an FD
+;; already open, 1 if MTIM_NOW sets the mtime to the clock, then the errnos
+;; for ATIM with ATIM_NOW (EINVAL) and for a missing path (ENOENT).
+(module
+  (import "wasi_snapshot_preview1" "path_open" (func $path_open (param i32 i32 i32 i32 i32 i64 i64 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_filestat_get" (func $fd_filestat_get (param i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "path_filestat_get" (func $path_filestat_get (param i32 i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "path_filestat_set_times" (func $set_times (param i32 i32 i32 i32 i64 i64 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "clock_time_get" (func $clock_time_get (param i32 i64 i32) (result i32)))
+  (import "env" "__builtin_rt_report" (func $report (param i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 1024) "times_file")
+  (data (i32.const 1040) "missing")
+  (func (export "_start")
+    (drop (call $path_open (i32.const 3) (i32.const 0) (i32.const 1024) (i32.const 10) (i32.const 1)
+      (i64.const -1) (i64.const -1) (i32.const 0) (i32.const 64)))
+    (i32.store (i32.const 0) (call $set_times (i32.const 3) (i32.const 0) (i32.const 1024) (i32.const 10)
+      (i64.const 42) (i64.const 1234567) (i32.const 5)))
+    (drop (call $path_filestat_get (i32.const 3) (i32.const 0) (i32.const 1024) (i32.const 10) (i32.const 128)))
+    (i32.store (i32.const 4) (i32.wrap_i64 (i64.load (i32.const 168))))
+    (i32.store (i32.const 8) (i32.wrap_i64 (i64.load (i32.const 176))))
+    (drop (call $fd_filestat_get (i32.load (i32.const 64)) (i32.const 192)))
+    (i32.store (i32.const 12) (i32.wrap_i64 (i64.load (i32.const 240))))
+    (drop (call $set_times (i32.const 3) (i32.const 0) (i32.const 1024) (i32.const 10)
+      (i64.const 0) (i64.const 0) (i32.const 8)))
+    (drop (call $clock_time_get (i32.const 0) (i64.const 1) (i32.const 640)))
+    (drop (call $path_filestat_get (i32.const 3) (i32.const 0) (i32.const 1024) (i32.const 10) (i32.const 256)))
+    (i32.store (i32.const 16) (i64.eq (i64.load (i32.const 304)) (i64.load (i32.const 640))))
+    (i32.store (i32.const 20) (call $set_times (i32.const 3) (i32.const 0) (i32.const 1024) (i32.const 10)
+      (i64.const 0) (i64.const 0) (i32.const 3)))
+    (i32.store (i32.const 24) (call $set_times (i32.const 3) (i32.const 0) (i32.const 1040) (i32.const 7)
+      (i64.const 0) (i64.const 0) (i32.const 4)))
+    (drop (call $report (i32.const 0) (i32.const 28)))))
@@ -0,0 +1,15 @@
+;; The preopened root is a directory, by path and by descriptor.
+;; Reports: errno and filetype of path_filestat_get("."), then of
+;; fd_filestat_get(3) (a directory is filetype 3).
+(module
+  (import "wasi_snapshot_preview1" "path_filestat_get" (func $path_filestat_get (param i32 i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_filestat_get" (func $fd_filestat_get (param i32 i32) (result i32)))
+  (import "env" "__builtin_rt_report" (func $report (param i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 256) ".")
+  (func (export "_start")
+    (i32.store (i32.const 0) (call $path_filestat_get (i32.const 3) (i32.const 0) (i32.const 256) (i32.const 1) (i32.const 64)))
+    (i32.store (i32.const 4) (i32.load8_u (i32.const 80)))
+    (i32.store (i32.const 8) (call $fd_filestat_get (i32.const 3) (i32.const 128)))
+    (i32.store (i32.const 12) (i32.load8_u (i32.const 144)))
+    (drop (call $report (i32.const 0) (i32.const 16)))))
@@ -0,0 +1,29 @@
+;; path_filestat_get reports the size of an 11-byte file in st_size, and
+;; fails for a missing path and for one outside the sandbox.
+;; Reports: errno, filetype (offset 16, 4 = regular file) and st_size
+;; (offset 32) for the file, then the errno for "missing" (ENOENT) and for
+;; ".." (EACCES).
+(module
+  (import "wasi_snapshot_preview1" "path_open" (func $path_open (param i32 i32 i32 i32 i32 i64 i64 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_close" (func $fd_close (param i32) (result i32)))
+  (import "wasi_snapshot_preview1" "path_filestat_get" (func $path_filestat_get (param i32 i32 i32 i32 i32) (result i32)))
+  (import "env" "__builtin_rt_report" (func $report (param i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 256) "size_file")
+  (data (i32.const 272) "hello world")
+  (data (i32.const 288) "missing")
+  (data (i32.const 296) "..")
+  (func (export "_start")
+    (drop (call $path_open (i32.const 3) (i32.const 0) (i32.const 256) (i32.const 9) (i32.const 1)
+      (i64.const -1) (i64.const -1) (i32.const 0) (i32.const 64)))
+    (i32.store (i32.const 80) (i32.const 272))
+    (i32.store (i32.const 84) (i32.const 11))
+    (drop (call $fd_write (i32.load (i32.const 64)) (i32.const 80) (i32.const 1) (i32.const 88)))
+    (drop (call $fd_close (i32.load (i32.const 64))))
+    (i32.store (i32.const 0) (call $path_filestat_get (i32.const 3) (i32.const 0) (i32.const 256) (i32.const 9) (i32.const 128)))
+    (i32.store (i32.const 4) (i32.load8_u (i32.const 144)))
+    (i32.store (i32.const 8) (i32.wrap_i64 (i64.load (i32.const 160))))
+    (i32.store (i32.const 12) (call $path_filestat_get (i32.const 3) (i32.const 0) (i32.const 288) (i32.const 7) (i32.const 128)))
+    (i32.store (i32.const 16) (call $path_filestat_get (i32.const 3) (i32.const 0) (i32.const 296) (i32.const 2) (i32.const 128)))
+    (drop (call $report (i32.const 0) (i32.const 20)))))
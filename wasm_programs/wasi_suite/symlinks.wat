@@ -0,0 +1,59 @@
+;; Symlinks are emulated inside the sandbox: path_symlink makes one,
+;; path_readlink reads its target back, and path_open follows it with
+;; SYMLINK_FOLLOW. Writes "hi" to target.txt and links "link" to it, then
+;; links loop_a and loop_b to each other, and links dlink to the directory dir.
+;; Reports: errno of path_symlink, errno of linking "link" again (EEXIST),
+;; errno of path_readlink, its nread and first byte ('t'), errno of opening
+;; "link" with SYMLINK_FOLLOW and the first byte read through it ('h'),
+;; errno of opening "link" without it (ELOOP), and errno of opening loop_a
+;; with it (ELOOP), errno of path_remove_directory on dlink (ENOTDIR) and
+;; errno of path_readlink on dlink afterwards, since the link is kept.
+(module
+  (import "wasi_snapshot_preview1" "path_open" (func $path_open (param i32 i32 i32 i32 i32 i64 i64 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "path_symlink" (func $path_symlink (param i32 i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "path_readlink" (func $path_readlink (param i32 i32 i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "path_create_directory" (func $path_create_directory (param i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "path_remove_directory" (func $path_remove_directory (param i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_read" (func $fd_read (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_close" (func $fd_close (param i32) (result i32)))
+  (import "env" "__builtin_rt_report" (func $report (param i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 256) "target.txt")
+  (data (i32.const 272) "link")
+  (data (i32.const 288) "loop_a")
+  (data (i32.const 304) "loop_b")
+  (data (i32.const 320) "hi")
+  (data (i32.const 336) "dir")
+  (data (i32.const 352) "dlink")
+  ;; returns the errno of path_open; the fd is left at offset 128
+  (func $open (param $dirflags i32) (param $path i32) (param $len i32) (param $oflags i32) (result i32)
+    (call $path_open (i32.const 3) (local.get $dirflags) (local.get $path) (local.get $len) (local.get $oflags)
+      (i64.const -1) (i64.const -1) (i32.const 0) (i32.const 128)))
+  (func (export "_start")
+    (local $fd i32)
+    (drop (call $open (i32.const 0) (i32.const 256) (i32.const 10) (i32.const 1)))
+    (local.set $fd (i32.load (i32.const 128)))
+    (i32.store (i32.const 160) (i32.const 320))
+    (i32.store (i32.const 164) (i32.const 2))
+    (drop (call $fd_write (local.get $fd) (i32.const 160) (i32.const 1) (i32.const 168)))
+    (drop (call $fd_close (local.get $fd)))
+    (i32.store (i32.const 0) (call $path_symlink (i32.const 256) (i32.const 10) (i32.const 3) (i32.const 272) (i32.const 4)))
+    (i32.store (i32.const 4) (call $path_symlink (i32.const 256) (i32.const 10) (i32.const 3) (i32.const 272) (i32.const 4)))
+    (i32.store (i32.const 8) (call $path_readlink (i32.const 3) (i32.const 272) (i32.const 4) (i32.const 512) (i32.const 64) (i32.const 12)))
+    (i32.store (i32.const 16) (i32.load8_u (i32.const 512)))
+    ;; SYMLINK_FOLLOW
+    (i32.store (i32.const 20) (call $open (i32.const 1) (i32.const 272) (i32.const 4) (i32.const 0)))
+    (i32.store (i32.const 160) (i32.const 600))
+    (i32.store (i32.const 164) (i32.const 64))
+    (drop (call $fd_read (i32.load (i32.const 128)) (i32.const 160) (i32.const 1) (i32.const 168)))
+    (i32.store (i32.const 24) (i32.load8_u (i32.const 600)))
+    (i32.store (i32.const 28) (call $open (i32.const 0) (i32.const 272) (i32.const 4) (i32.const 0)))
+    (drop (call $path_symlink (i32.const 304) (i32.const 6) (i32.const 3) (i32.const 288) (i32.const 6)))
+    (drop (call $path_symlink (i32.const 288) (i32.const 6) (i32.const 3) (i32.const 304) (i32.const 6)))
+    (i32.store (i32.const 32) (call $open (i32.const 1) (i32.const 288) (i32.const 6) (i32.const 0)))
+    (drop (call $path_create_directory (i32.const 3) (i32.const 336) (i32.const 3)))
+    (drop (call $path_symlink (i32.const 336) (i32.const 3) (i32.const 3) (i32.const 352) (i32.const 5)))
+    (i32.store (i32.const 36) (call $path_remove_directory (i32.const 3) (i32.const 352) (i32.const 5)))
+    (i32.store (i32.const 40) (call $path_readlink (i32.const 3) (i32.const 352) (i32.const 5) (i32.const 512) (i32.const 64) (i32.const 48)))
+    (drop (call $report (i32.const 0) (i32.const 44)))))
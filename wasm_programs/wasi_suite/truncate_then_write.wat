@@ -0,0 +1,36 @@
+;; Opening an existing file with O_TRUNC empties it before the next write.
+;; Writes "hello world", reopens the file with O_TRUNC, writes "hi", then
+;; reads it back.
+;; Reports: errno of the O_TRUNC path_open, bytes read back, and the first
+;; two of them ('h', 'i').
+(module
+  (import "wasi_snapshot_preview1" "path_open" (func $path_open (param i32 i32 i32 i32 i32 i64 i64 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_read" (func $fd_read (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_close" (func $fd_close (param i32) (result i32)))
+  (import "env" "__builtin_rt_report" (func $report (param i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 256) "tfile")
+  (data (i32.const 272) "hello world")
+  (data (i32.const 288) "hi")
+  (func $open (param $oflags i32) (result i32)
+    (i32.store (i32.const 0) (call $path_open (i32.const 3) (i32.const 0) (i32.const 256) (i32.const 5) (local.get $oflags)
+      (i64.const -1) (i64.const -1) (i32.const 0) (i32.const 64)))
+    (i32.load (i32.const 64)))
+  (func $write (param $fd i32) (param $ptr i32) (param $len i32)
+    (i32.store (i32.const 80) (local.get $ptr))
+    (i32.store (i32.const 84) (local.get $len))
+    (drop (call $fd_write (local.get $fd) (i32.const 80) (i32.const 1) (i32.const 88)))
+    (drop (call $fd_close (local.get $fd))))
+  (func (export "_start")
+    (local $fd i32)
+    (call $write (call $open (i32.const 1)) (i32.const 272) (i32.const 11))
+    ;; O_CREAT | O_TRUNC
+    (call $write (call $open (i32.const 9)) (i32.const 288) (i32.const 2))
+    (local.set $fd (call $open (i32.const 0)))
+    (i32.store (i32.const 80) (i32.const 512))
+    (i32.store (i32.const 84) (i32.const 64))
+    (drop (call $fd_read (local.get $fd) (i32.const 80) (i32.const 1) (i32.const 4)))
+    (i32.store (i32.const 8) (i32.load8_u (i32.const 512)))
+    (i32.store (i32.const 12) (i32.load8_u (i32.const 513)))
+    (drop (call $report (i32.const 0) (i32.const 16)))))